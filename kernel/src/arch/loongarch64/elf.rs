@@ -1,4 +1,11 @@
-use crate::{arch::MMArch, libs::elf::ElfArch, mm::MemoryManagementArch};
+use alloc::sync::Arc;
+
+use crate::{
+    arch::{interrupt::TrapFrame, MMArch},
+    libs::elf::ElfArch,
+    mm::MemoryManagementArch,
+    process::ProcessControlBlock,
+};
 
 #[derive(Debug, Clone, Copy, Hash)]
 pub struct LoongArch64ElfArch;
@@ -7,4 +14,54 @@ impl ElfArch for LoongArch64ElfArch {
     const ELF_ET_DYN_BASE: usize = MMArch::USER_END_VADDR.data() / 3 * 2;
 
     const ELF_PAGE_SIZE: usize = MMArch::PAGE_SIZE;
+
+    /// EM_LOONGARCH
+    const ELF_MACHINE: u16 = 0x102;
+
+    const ELF_NGREG: usize = 45;
+
+    /// 顺序与Linux的`struct user_pt_regs`一致：r0-r31, orig_a0, era, badv, 保留字段
+    fn core_regs(_pcb: &Arc<ProcessControlBlock>, frame: &TrapFrame, regs: &mut [u64]) {
+        let values = [
+            frame.r0,
+            frame.ra,
+            frame.tp,
+            frame.usp,
+            frame.a0,
+            frame.a1,
+            frame.a2,
+            frame.a3,
+            frame.a4,
+            frame.a5,
+            frame.a6,
+            frame.a7,
+            frame.t0,
+            frame.t1,
+            frame.t2,
+            frame.t3,
+            frame.t4,
+            frame.t5,
+            frame.t6,
+            frame.t7,
+            frame.t8,
+            frame.r21,
+            frame.fp,
+            frame.s0,
+            frame.s1,
+            frame.s2,
+            frame.s3,
+            frame.s4,
+            frame.s5,
+            frame.s6,
+            frame.s7,
+            frame.s8,
+            frame.orig_a0,
+            frame.csr_era,
+            frame.csr_badvaddr,
+        ];
+        regs.fill(0);
+        for (reg, value) in regs.iter_mut().zip(values) {
+            *reg = value as u64;
+        }
+    }
 }
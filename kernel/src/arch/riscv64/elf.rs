@@ -1,4 +1,11 @@
-use crate::{arch::MMArch, libs::elf::ElfArch, mm::MemoryManagementArch};
+use alloc::sync::Arc;
+
+use crate::{
+    arch::{interrupt::TrapFrame, MMArch},
+    libs::elf::ElfArch,
+    mm::MemoryManagementArch,
+    process::ProcessControlBlock,
+};
 
 #[derive(Debug, Clone, Copy, Hash)]
 pub struct RiscV64ElfArch;
@@ -7,4 +14,22 @@ impl ElfArch for RiscV64ElfArch {
     const ELF_ET_DYN_BASE: usize = MMArch::USER_END_VADDR.data() / 3 * 2;
 
     const ELF_PAGE_SIZE: usize = MMArch::PAGE_SIZE;
+
+    /// EM_RISCV
+    const ELF_MACHINE: u16 = 0xf3;
+
+    const ELF_NGREG: usize = 32;
+
+    /// 顺序与Linux的`struct user_regs_struct`一致：pc, x1-x31
+    fn core_regs(_pcb: &Arc<ProcessControlBlock>, frame: &TrapFrame, regs: &mut [u64]) {
+        let values = [
+            frame.epc, frame.ra, frame.sp, frame.gp, frame.tp, frame.t0, frame.t1, frame.t2,
+            frame.s0, frame.s1, frame.a0, frame.a1, frame.a2, frame.a3, frame.a4, frame.a5,
+            frame.a6, frame.a7, frame.s2, frame.s3, frame.s4, frame.s5, frame.s6, frame.s7,
+            frame.s8, frame.s9, frame.s10, frame.s11, frame.t3, frame.t4, frame.t5, frame.t6,
+        ];
+        for (reg, value) in regs.iter_mut().zip(values) {
+            *reg = value as u64;
+        }
+    }
 }
@@ -1,10 +1,13 @@
 use log::error;
 
 use crate::{
-    arch::{interrupt::TrapFrame, sched::sched, CurrentIrqArch},
-    exception::InterruptArch,
+    arch::interrupt::TrapFrame,
     ipc::signal_types::SignalArch,
-    process::ProcessManager,
+    process::{
+        coredump::{do_coredump, WCOREFLAG},
+        job_control::do_signal_stop,
+        ProcessManager,
+    },
 };
 
 /// 信号最大值
@@ -127,36 +130,41 @@ impl Signal {
     }
 
     /// 调用信号的默认处理函数
-    pub fn handle_default(&self) {
+    ///
+    /// ## 参数
+    ///
+    /// - `frame`: 当前线程进入内核时保存的用户态寄存器，生成 core dump 时使用
+    pub fn handle_default(&self, frame: &TrapFrame) {
         match self {
             Signal::INVALID => {
                 error!("attempting to handler an Invalid");
             }
             Signal::SIGHUP => sig_terminate(self.clone()),
             Signal::SIGINT => sig_terminate(self.clone()),
-            Signal::SIGQUIT => sig_terminate_dump(self.clone()),
-            Signal::SIGILL => sig_terminate_dump(self.clone()),
-            Signal::SIGTRAP => sig_terminate_dump(self.clone()),
-            Signal::SIGABRT_OR_IOT => sig_terminate_dump(self.clone()),
-            Signal::SIGBUS => sig_terminate_dump(self.clone()),
-            Signal::SIGFPE => sig_terminate_dump(self.clone()),
+            Signal::SIGQUIT => sig_terminate_dump(self.clone(), frame),
+            Signal::SIGILL => sig_terminate_dump(self.clone(), frame),
+            Signal::SIGTRAP => sig_terminate_dump(self.clone(), frame),
+            Signal::SIGABRT_OR_IOT => sig_terminate_dump(self.clone(), frame),
+            Signal::SIGBUS => sig_terminate_dump(self.clone(), frame),
+            Signal::SIGFPE => sig_terminate_dump(self.clone(), frame),
             Signal::SIGKILL => sig_terminate(self.clone()),
             Signal::SIGUSR1 => sig_terminate(self.clone()),
-            Signal::SIGSEGV => sig_terminate_dump(self.clone()),
+            Signal::SIGSEGV => sig_terminate_dump(self.clone(), frame),
             Signal::SIGUSR2 => sig_terminate(self.clone()),
             Signal::SIGPIPE => sig_terminate(self.clone()),
             Signal::SIGALRM => sig_terminate(self.clone()),
             Signal::SIGTERM => sig_terminate(self.clone()),
             Signal::SIGSTKFLT => sig_terminate(self.clone()),
             Signal::SIGCHLD => sig_ignore(self.clone()),
-            Signal::SIGCONT => sig_continue(self.clone()),
+            // 发送SIGCONT时已经让进程继续运行了
+            Signal::SIGCONT => sig_ignore(self.clone()),
             Signal::SIGSTOP => sig_stop(self.clone()),
             Signal::SIGTSTP => sig_stop(self.clone()),
             Signal::SIGTTIN => sig_stop(self.clone()),
             Signal::SIGTTOU => sig_stop(self.clone()),
             Signal::SIGURG => sig_ignore(self.clone()),
-            Signal::SIGXCPU => sig_terminate_dump(self.clone()),
-            Signal::SIGXFSZ => sig_terminate_dump(self.clone()),
+            Signal::SIGXCPU => sig_terminate_dump(self.clone(), frame),
+            Signal::SIGXFSZ => sig_terminate_dump(self.clone(), frame),
             Signal::SIGVTALRM => sig_terminate(self.clone()),
             Signal::SIGPROF => sig_terminate(self.clone()),
             Signal::SIGWINCH => sig_ignore(self.clone()),
@@ -306,37 +314,19 @@ fn sig_terminate(sig: Signal) {
 }
 
 /// 信号默认处理函数——终止进程并生成 core dump
-fn sig_terminate_dump(sig: Signal) {
-    ProcessManager::exit(sig as usize);
-    // TODO 生成 coredump 文件
+fn sig_terminate_dump(sig: Signal, frame: &TrapFrame) {
+    let mut exit_code = sig as usize;
+    if do_coredump(sig, frame) {
+        exit_code |= WCOREFLAG;
+    }
+    ProcessManager::exit(exit_code);
 }
 
 /// 信号默认处理函数——暂停进程
 fn sig_stop(sig: Signal) {
-    let guard = unsafe { CurrentIrqArch::save_and_disable_irq() };
-    ProcessManager::mark_stop().unwrap_or_else(|e| {
-        error!(
-            "sleep error :{:?},failed to sleep process :{:?}, with signal :{:?}",
-            e,
-            ProcessManager::current_pcb(),
-            sig
-        );
-    });
-    drop(guard);
-    sched();
-    // TODO 暂停进程
+    do_signal_stop(sig);
 }
 
-/// 信号默认处理函数——继续进程
-fn sig_continue(sig: Signal) {
-    ProcessManager::wakeup_stop(&ProcessManager::current_pcb()).unwrap_or_else(|_| {
-        error!(
-            "Failed to wake up process pid = {:?} with signal :{:?}",
-            ProcessManager::current_pcb().pid(),
-            sig
-        );
-    });
-}
 /// 信号默认处理函数——忽略
 fn sig_ignore(_sig: Signal) {
     return;
@@ -1,4 +1,4 @@
-use core::sync::atomic::{compiler_fence, AtomicBool, Ordering};
+use core::sync::atomic::{compiler_fence, Ordering};
 
 use log::{debug, info};
 use system_error::SystemError;
@@ -6,17 +6,18 @@ use system_error::SystemError;
 use crate::{
     arch::{
         mm::{
-            kernel_page_flags, INNER_ALLOCATOR, KERNEL_BEGIN_PA, KERNEL_BEGIN_VA, KERNEL_END_PA,
-            KERNEL_END_VA,
+            kernel_page_flags, LockedFrameAllocator, KERNEL_BEGIN_PA, KERNEL_BEGIN_VA,
+            KERNEL_END_PA, KERNEL_END_VA,
         },
         MMArch,
     },
     driver::firmware::efi::efi_manager,
     libs::lib_ui::screen_manager::scm_disable_put_to_window,
     mm::{
-        allocator::{buddy::BuddyAllocator, bump::BumpAllocator, page_frame::FrameAllocator},
+        allocator::{bump::BumpAllocator, page_frame::FrameAllocator},
         kernel_mapper::KernelMapper,
         memblock::mem_block_manager,
+        mmzone::zones_init,
         page::PageEntry,
         MemoryManagementArch, PageTableKind, PhysAddr, VirtAddr,
     },
@@ -130,24 +131,20 @@ pub(super) unsafe fn riscv_mm_init() -> Result<(), SystemError> {
         bump_allocator.usage().used().bytes() / 1024
     );
 
-    // 初始化buddy_allocator
-    let buddy_allocator = unsafe { BuddyAllocator::<MMArch>::new(bump_allocator).unwrap() };
-    // 设置全局的页帧分配器
-    unsafe { set_inner_allocator(buddy_allocator) };
+    // 初始化各个内存区域的伙伴分配器
+    unsafe { zones_init(bump_allocator) };
     info!("Successfully initialized buddy allocator");
     // 关闭显示输出
     scm_disable_put_to_window();
 
     // make the new page table current
     {
-        let mut binding = INNER_ALLOCATOR.lock();
-        let mut allocator_guard = binding.as_mut().unwrap();
         debug!("To enable new page table.");
         compiler_fence(Ordering::SeqCst);
         let mapper = crate::mm::page::PageMapper::<MMArch, _>::new(
             PageTableKind::Kernel,
             new_page_table,
-            &mut allocator_guard,
+            LockedFrameAllocator,
         );
         compiler_fence(Ordering::SeqCst);
         mapper.make_current();
@@ -160,17 +157,6 @@ pub(super) unsafe fn riscv_mm_init() -> Result<(), SystemError> {
     return Ok(());
 }
 
-unsafe fn set_inner_allocator(allocator: BuddyAllocator<MMArch>) {
-    static FLAG: AtomicBool = AtomicBool::new(false);
-    if FLAG
-        .compare_exchange(false, true, Ordering::SeqCst, Ordering::SeqCst)
-        .is_err()
-    {
-        panic!("Cannot set inner allocator twice!");
-    }
-    *INNER_ALLOCATOR.lock() = Some(allocator);
-}
-
 /// 低地址重映射的管理器
 ///
 /// 低地址重映射的管理器，在smp初始化完成之前，需要使用低地址的映射，因此需要在smp初始化完成之后，取消这一段映射
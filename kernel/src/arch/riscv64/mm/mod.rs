@@ -5,13 +5,11 @@ use system_error::SystemError;
 use crate::{
     arch::MMArch,
     driver::open_firmware::fdt::open_firmware_fdt_driver,
-    libs::spinlock::SpinLock,
     mm::{
-        allocator::{
-            buddy::BuddyAllocator,
-            page_frame::{FrameAllocator, PageFrameCount, PageFrameUsage, PhysPageFrame},
-        },
+        allocator::page_frame::{FrameAllocator, PageFrameCount, PageFrameUsage, PhysPageFrame},
+        gfp::GfpFlags,
         kernel_mapper::KernelMapper,
+        mmzone::{alloc_pages, free_pages, zones_usage},
         page::{EntryFlags, PageEntry, PAGE_1G_SHIFT},
         ucontext::UserMapper,
         MemoryManagementArch, PageTableKind, PhysAddr, VirtAddr, VmFlags,
@@ -35,8 +33,6 @@ pub(self) static mut KERNEL_BEGIN_VA: VirtAddr = VirtAddr::new(0);
 /// 内核结束虚拟地址
 pub(self) static mut KERNEL_END_VA: VirtAddr = VirtAddr::new(0);
 
-pub(self) static INNER_ALLOCATOR: SpinLock<Option<BuddyAllocator<MMArch>>> = SpinLock::new(None);
-
 /// RiscV64的内存管理架构结构体(sv39)
 #[derive(Debug, Clone, Copy, Hash)]
 pub struct RiscV64MMArch;
@@ -348,25 +344,15 @@ pub struct LockedFrameAllocator;
 
 impl FrameAllocator for LockedFrameAllocator {
     unsafe fn allocate(&mut self, count: PageFrameCount) -> Option<(PhysAddr, PageFrameCount)> {
-        if let Some(ref mut allocator) = *INNER_ALLOCATOR.lock_irqsave() {
-            return allocator.allocate(count);
-        } else {
-            return None;
-        }
+        return alloc_pages(GfpFlags::GFP_KERNEL, count);
     }
 
     unsafe fn free(&mut self, address: crate::mm::PhysAddr, count: PageFrameCount) {
         assert!(count.data().is_power_of_two());
-        if let Some(ref mut allocator) = *INNER_ALLOCATOR.lock_irqsave() {
-            return allocator.free(address, count);
-        }
+        free_pages(address, count);
     }
 
     unsafe fn usage(&self) -> PageFrameUsage {
-        if let Some(ref mut allocator) = *INNER_ALLOCATOR.lock_irqsave() {
-            return allocator.usage();
-        } else {
-            panic!("usage error");
-        }
+        return zones_usage();
     }
 }
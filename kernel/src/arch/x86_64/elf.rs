@@ -1,4 +1,11 @@
-use crate::{arch::MMArch, libs::elf::ElfArch, mm::MemoryManagementArch};
+use alloc::sync::Arc;
+
+use crate::{
+    arch::{interrupt::TrapFrame, MMArch},
+    libs::elf::ElfArch,
+    mm::MemoryManagementArch,
+    process::ProcessControlBlock,
+};
 
 #[derive(Debug, Clone, Copy, Hash)]
 pub struct X86_64ElfArch;
@@ -7,4 +14,46 @@ impl ElfArch for X86_64ElfArch {
     const ELF_ET_DYN_BASE: usize = MMArch::USER_END_VADDR.data() / 3 * 2;
 
     const ELF_PAGE_SIZE: usize = MMArch::PAGE_SIZE;
+
+    /// EM_X86_64
+    const ELF_MACHINE: u16 = 0x3e;
+
+    const ELF_NGREG: usize = 27;
+
+    /// 顺序与Linux的`struct user_regs_struct`一致
+    fn core_regs(pcb: &Arc<ProcessControlBlock>, frame: &TrapFrame, regs: &mut [u64]) {
+        let arch_info = pcb.arch_info_irqsave();
+        let orig_rax = unsafe { frame.syscall_nr() }
+            .map(|nr| nr as u64)
+            .unwrap_or(u64::MAX);
+        regs.copy_from_slice(&[
+            frame.r15,
+            frame.r14,
+            frame.r13,
+            frame.r12,
+            frame.rbp,
+            frame.rbx,
+            frame.r11,
+            frame.r10,
+            frame.r9,
+            frame.r8,
+            frame.rax,
+            frame.rcx,
+            frame.rdx,
+            frame.rsi,
+            frame.rdi,
+            orig_rax,
+            frame.rip,
+            frame.cs,
+            frame.rflags,
+            frame.rsp,
+            frame.ss,
+            arch_info.fsbase() as u64,
+            arch_info.gsbase() as u64,
+            frame.ds,
+            frame.es,
+            0,
+            0,
+        ]);
+    }
 }
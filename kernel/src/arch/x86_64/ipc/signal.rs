@@ -18,8 +18,11 @@ use crate::{
         signal_types::{SaHandlerType, SigInfo, Sigaction, SigactionType, SignalArch},
     },
     mm::MemoryManagementArch,
-    process::ProcessManager,
-    sched::{schedule, SchedMode},
+    process::{
+        coredump::{do_coredump, WCOREFLAG},
+        job_control::do_signal_stop,
+        ProcessManager,
+    },
     syscall::user_access::UserBufferWriter,
 };
 
@@ -144,36 +147,41 @@ impl Signal {
     }
 
     /// 调用信号的默认处理函数
-    pub fn handle_default(&self) {
+    ///
+    /// ## 参数
+    ///
+    /// - `frame`: 当前线程进入内核时保存的用户态寄存器，生成 core dump 时使用
+    pub fn handle_default(&self, frame: &TrapFrame) {
         match self {
             Signal::INVALID => {
                 error!("attempting to handler an Invalid");
             }
             Signal::SIGHUP => sig_terminate(*self),
             Signal::SIGINT => sig_terminate(*self),
-            Signal::SIGQUIT => sig_terminate_dump(*self),
-            Signal::SIGILL => sig_terminate_dump(*self),
-            Signal::SIGTRAP => sig_terminate_dump(*self),
-            Signal::SIGABRT_OR_IOT => sig_terminate_dump(*self),
-            Signal::SIGBUS => sig_terminate_dump(*self),
-            Signal::SIGFPE => sig_terminate_dump(*self),
+            Signal::SIGQUIT => sig_terminate_dump(*self, frame),
+            Signal::SIGILL => sig_terminate_dump(*self, frame),
+            Signal::SIGTRAP => sig_terminate_dump(*self, frame),
+            Signal::SIGABRT_OR_IOT => sig_terminate_dump(*self, frame),
+            Signal::SIGBUS => sig_terminate_dump(*self, frame),
+            Signal::SIGFPE => sig_terminate_dump(*self, frame),
             Signal::SIGKILL => sig_terminate(*self),
             Signal::SIGUSR1 => sig_terminate(*self),
-            Signal::SIGSEGV => sig_terminate_dump(*self),
+            Signal::SIGSEGV => sig_terminate_dump(*self, frame),
             Signal::SIGUSR2 => sig_terminate(*self),
             Signal::SIGPIPE => sig_terminate(*self),
             Signal::SIGALRM => sig_terminate(*self),
             Signal::SIGTERM => sig_terminate(*self),
             Signal::SIGSTKFLT => sig_terminate(*self),
             Signal::SIGCHLD => sig_ignore(*self),
-            Signal::SIGCONT => sig_continue(*self),
+            // 发送SIGCONT时已经让进程继续运行了
+            Signal::SIGCONT => sig_ignore(*self),
             Signal::SIGSTOP => sig_stop(*self),
             Signal::SIGTSTP => sig_stop(*self),
             Signal::SIGTTIN => sig_stop(*self),
             Signal::SIGTTOU => sig_stop(*self),
             Signal::SIGURG => sig_ignore(*self),
-            Signal::SIGXCPU => sig_terminate_dump(*self),
-            Signal::SIGXFSZ => sig_terminate_dump(*self),
+            Signal::SIGXCPU => sig_terminate_dump(*self, frame),
+            Signal::SIGXFSZ => sig_terminate_dump(*self, frame),
             Signal::SIGVTALRM => sig_terminate(*self),
             Signal::SIGPROF => sig_terminate(*self),
             Signal::SIGWINCH => sig_ignore(*self),
@@ -639,7 +647,7 @@ fn setup_frame(
     match sigaction.action() {
         SigactionType::SaHandler(handler_type) => match handler_type {
             SaHandlerType::Default => {
-                sig.handle_default();
+                sig.handle_default(trap_frame);
                 return Ok(0);
             }
             SaHandlerType::Customized(handler) => {
@@ -647,11 +655,11 @@ fn setup_frame(
                 if handler >= MMArch::USER_END_VADDR {
                     // 如果当前是SIGSEGV,则采用默认函数处理
                     if sig == Signal::SIGSEGV {
-                        sig.handle_default();
+                        sig.handle_default(trap_frame);
                         return Ok(0);
                     } else {
                         error!("attempting  to execute a signal handler from kernel");
-                        sig.handle_default();
+                        sig.handle_default(trap_frame);
                         return Err(SystemError::EINVAL);
                     }
                 } else {
@@ -786,36 +794,19 @@ fn sig_terminate(sig: Signal) {
 }
 
 /// 信号默认处理函数——终止进程并生成 core dump
-fn sig_terminate_dump(sig: Signal) {
-    ProcessManager::exit(sig as usize);
-    // TODO 生成 coredump 文件
+fn sig_terminate_dump(sig: Signal, frame: &TrapFrame) {
+    let mut exit_code = sig as usize;
+    if do_coredump(sig, frame) {
+        exit_code |= WCOREFLAG;
+    }
+    ProcessManager::exit(exit_code);
 }
 
 /// 信号默认处理函数——暂停进程
 fn sig_stop(sig: Signal) {
-    let guard = unsafe { CurrentIrqArch::save_and_disable_irq() };
-    ProcessManager::mark_stop().unwrap_or_else(|e| {
-        error!(
-            "sleep error :{:?},failed to sleep process :{:?}, with signal :{:?}",
-            e,
-            ProcessManager::current_pcb(),
-            sig
-        );
-    });
-    drop(guard);
-    schedule(SchedMode::SM_NONE);
-    // TODO 暂停进程
-}
-/// 信号默认处理函数——继续进程
-fn sig_continue(sig: Signal) {
-    ProcessManager::wakeup_stop(&ProcessManager::current_pcb()).unwrap_or_else(|_| {
-        error!(
-            "Failed to wake up process pid = {:?} with signal :{:?}",
-            ProcessManager::current_pcb().pid(),
-            sig
-        );
-    });
+    do_signal_stop(sig);
 }
+
 /// 信号默认处理函数——忽略
 fn sig_ignore(_sig: Signal) {
     return;
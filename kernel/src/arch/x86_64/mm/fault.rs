@@ -17,6 +17,7 @@ use crate::{
     ipc::signal_types::{SigInfo, SigType},
     mm::{
         fault::{FaultFlags, PageFaultHandler, PageFaultMessage},
+        oom_kill::pagefault_out_of_memory,
         ucontext::{AddressSpace, LockedVMA},
         VirtAddr, VmFaultReason, VmFlags,
     },
@@ -300,6 +301,7 @@ impl X86_64MMArch {
                     address.data(),
                 );
                 send_segv();
+                return;
             }
             let mapper = &mut space_guard.user_mapper.utable;
             let message = PageFaultMessage::new(vma.clone(), address, flags, mapper);
@@ -324,8 +326,21 @@ impl X86_64MMArch {
             | VmFaultReason::VM_FAULT_HWPOISON_LARGE
             | VmFaultReason::VM_FAULT_FALLBACK;
 
-        if likely(!fault.contains(vm_fault_error)) {
+        if likely(!fault.intersects(vm_fault_error)) {
             panic!("fault error: {:?}", fault)
         }
+
+        if fault.contains(VmFaultReason::VM_FAULT_OOM) {
+            drop(space_guard);
+            pagefault_out_of_memory();
+        } else if fault.contains(VmFaultReason::VM_FAULT_SIGBUS) {
+            let pid = ProcessManager::current_pid();
+            let mut info = SigInfo::new(Signal::SIGBUS, 0, SigCode::User, SigType::Kill(pid));
+            Signal::SIGBUS
+                .send_signal_info(Some(&mut info), pid)
+                .expect("failed to send SIGBUS to process");
+        } else if fault.contains(VmFaultReason::VM_FAULT_SIGSEGV) {
+            send_segv();
+        }
     }
 }
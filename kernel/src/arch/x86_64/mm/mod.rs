@@ -13,15 +13,13 @@ use crate::driver::serial::serial8250::send_to_default_serial8250_port;
 use crate::init::boot::boot_callbacks;
 use crate::libs::align::page_align_up;
 use crate::libs::lib_ui::screen_manager::scm_disable_put_to_window;
-use crate::libs::spinlock::SpinLock;
 
 use crate::mm::allocator::page_frame::{FrameAllocator, PageFrameCount, PageFrameUsage};
+use crate::mm::gfp::GfpFlags;
 use crate::mm::memblock::mem_block_manager;
+use crate::mm::mmzone::{alloc_pages, free_pages, zones_init, zones_usage};
 use crate::mm::ucontext::LockedVMA;
-use crate::{
-    arch::MMArch,
-    mm::allocator::{buddy::BuddyAllocator, bump::BumpAllocator},
-};
+use crate::{arch::MMArch, mm::allocator::bump::BumpAllocator};
 
 use crate::mm::kernel_mapper::KernelMapper;
 use crate::mm::page::{EntryFlags, PageEntry, PAGE_1G_SHIFT};
@@ -40,8 +38,6 @@ pub type PageMapper =
 /// 初始的CR3寄存器的值，用于内存管理初始化时，创建的第一个内核页表的位置
 static mut INITIAL_CR3_VALUE: PhysAddr = PhysAddr::new(0);
 
-static INNER_ALLOCATOR: SpinLock<Option<BuddyAllocator<MMArch>>> = SpinLock::new(None);
-
 #[derive(Clone, Copy, Debug)]
 pub struct X86_64MMBootstrapInfo {
     kernel_load_base_paddr: usize,
@@ -524,24 +520,20 @@ unsafe fn allocator_init() {
         bump_allocator.offset() / 1024
     );
 
-    // 初始化buddy_allocator
-    let buddy_allocator = unsafe { BuddyAllocator::<X86_64MMArch>::new(bump_allocator).unwrap() };
-    // 设置全局的页帧分配器
-    unsafe { set_inner_allocator(buddy_allocator) };
+    // 初始化各个内存区域的伙伴分配器
+    unsafe { zones_init(bump_allocator) };
     info!("Successfully initialized buddy allocator");
     // 关闭显示输出
     scm_disable_put_to_window();
 
     // make the new page table current
     {
-        let mut binding = INNER_ALLOCATOR.lock();
-        let mut allocator_guard = binding.as_mut().unwrap();
         debug!("To enable new page table.");
         compiler_fence(Ordering::SeqCst);
         let mapper = crate::mm::page::PageMapper::<MMArch, _>::new(
             PageTableKind::Kernel,
             new_page_table,
-            &mut allocator_guard,
+            LockedFrameAllocator,
         );
         compiler_fence(Ordering::SeqCst);
         mapper.make_current();
@@ -649,28 +641,17 @@ pub fn test_buddy() {
 pub struct LockedFrameAllocator;
 
 impl FrameAllocator for LockedFrameAllocator {
-    unsafe fn allocate(&mut self, mut count: PageFrameCount) -> Option<(PhysAddr, PageFrameCount)> {
-        count = count.next_power_of_two();
-        if let Some(ref mut allocator) = *INNER_ALLOCATOR.lock_irqsave() {
-            return allocator.allocate(count);
-        } else {
-            return None;
-        }
+    unsafe fn allocate(&mut self, count: PageFrameCount) -> Option<(PhysAddr, PageFrameCount)> {
+        return alloc_pages(GfpFlags::GFP_KERNEL, count);
     }
 
     unsafe fn free(&mut self, address: crate::mm::PhysAddr, count: PageFrameCount) {
         assert!(count.data().is_power_of_two());
-        if let Some(ref mut allocator) = *INNER_ALLOCATOR.lock_irqsave() {
-            return allocator.free(address, count);
-        }
+        free_pages(address, count);
     }
 
     unsafe fn usage(&self) -> PageFrameUsage {
-        if let Some(ref mut allocator) = *INNER_ALLOCATOR.lock_irqsave() {
-            return allocator.usage();
-        } else {
-            panic!("usage error");
-        }
+        return zones_usage();
     }
 }
 
@@ -689,17 +670,6 @@ pub unsafe fn kernel_page_flags<A: MemoryManagementArch>(virt: VirtAddr) -> Entr
     }
 }
 
-unsafe fn set_inner_allocator(allocator: BuddyAllocator<MMArch>) {
-    static FLAG: AtomicBool = AtomicBool::new(false);
-    if FLAG
-        .compare_exchange(false, true, Ordering::SeqCst, Ordering::SeqCst)
-        .is_err()
-    {
-        panic!("Cannot set inner allocator twice!");
-    }
-    *INNER_ALLOCATOR.lock() = Some(allocator);
-}
-
 /// 低地址重映射的管理器
 ///
 /// 低地址重映射的管理器，在smp初始化完成之前，需要使用低地址的映射，因此需要在smp初始化完成之后，取消这一段映射
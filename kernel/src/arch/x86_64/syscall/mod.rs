@@ -8,7 +8,7 @@ use crate::{
     ipc::signal_types::SignalArch,
     libs::align::SafeForZero,
     mm::VirtAddr,
-    process::ProcessManager,
+    process::{seccomp::secure_computing, ProcessManager},
     syscall::{Syscall, SYS_SCHED},
 };
 use log::debug;
@@ -100,6 +100,17 @@ pub extern "sysv64" fn syscall_handler(frame: &mut TrapFrame) {
         debug!("syscall: pid: {:?}, num={:?}\n", pid, syscall_num);
     }
 
+    // 架构相关的系统调用不经过Syscall::handle，需要在这里进行seccomp检查
+    if matches!(syscall_num, SYS_RT_SIGRETURN | SYS_ARCH_PRCTL) {
+        if let Some(r) = secure_computing(syscall_num, &args, frame) {
+            syscall_return!(
+                r.unwrap_or_else(|e| e.to_posix_errno() as usize),
+                frame,
+                show
+            );
+        }
+    }
+
     // Arch specific syscall
     match syscall_num {
         SYS_RT_SIGRETURN => {
@@ -22,6 +22,7 @@ extern crate acpi;
 
 pub mod bus;
 pub mod glue;
+mod numa;
 pub mod pmtmr;
 mod sysfs;
 
@@ -72,6 +73,7 @@ impl AcpiManager {
         }
 
         self.map_tables(acpi_args)?;
+        numa::acpi_numa_init();
         self.bus_init()?;
         info!("Acpi Manager initialized.");
         return Ok(());
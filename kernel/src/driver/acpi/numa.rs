@@ -0,0 +1,110 @@
+//! 解析ACPI SRAT（System Resource Affinity Table）表，获取NUMA拓扑
+
+use acpi::sdt::SdtHeader;
+use log::{info, warn};
+
+use crate::{
+    mm::{
+        numa::{numa_init, NumaMeminfo},
+        PhysAddr,
+    },
+    smp::cpu::ProcessorId,
+};
+
+use super::acpi_manager;
+
+#[repr(transparent)]
+struct Srat {
+    header: SdtHeader,
+}
+
+unsafe impl acpi::AcpiTable for Srat {
+    const SIGNATURE: acpi::sdt::Signature = acpi::sdt::Signature::SRAT;
+    fn header(&self) -> &SdtHeader {
+        return &self.header;
+    }
+}
+
+/// SRAT表头（包括保留字段）的长度，之后是各个亲和性结构
+const SRAT_HEADER_LEN: usize = 48;
+
+/// 处理器本地APIC亲和性结构
+const SRAT_TYPE_CPU_AFFINITY: u8 = 0;
+/// 内存亲和性结构
+const SRAT_TYPE_MEMORY_AFFINITY: u8 = 1;
+/// 处理器本地x2APIC亲和性结构
+const SRAT_TYPE_X2APIC_CPU_AFFINITY: u8 = 2;
+
+/// 亲和性结构的flags中表示该结构有效的位
+const SRAT_ENABLED: u32 = 1;
+
+fn read_u32(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap())
+}
+
+fn read_u64(data: &[u8], offset: usize) -> u64 {
+    u64::from_le_bytes(data[offset..offset + 8].try_into().unwrap())
+}
+
+/// 解析SRAT表并初始化NUMA节点，没有SRAT表时所有内存和CPU都属于节点0
+pub(super) fn acpi_numa_init() {
+    let tables = match acpi_manager().tables() {
+        Some(tables) => tables,
+        None => return,
+    };
+    let srat = match tables.find_entire_table::<Srat>() {
+        Ok(srat) => srat,
+        Err(_) => {
+            info!("ACPI: no SRAT found, NUMA disabled");
+            return;
+        }
+    };
+    let data = unsafe {
+        core::slice::from_raw_parts(
+            srat.virtual_start().as_ptr() as *const u8,
+            srat.region_length(),
+        )
+    };
+
+    let mut info = NumaMeminfo::new();
+    let mut offset = SRAT_HEADER_LEN;
+    while offset + 2 <= data.len() {
+        let entry_type = data[offset];
+        let len = data[offset + 1] as usize;
+        if len < 2 || offset + len > data.len() {
+            warn!("ACPI: invalid SRAT entry at offset {offset}");
+            break;
+        }
+        let entry = &data[offset..offset + len];
+        match entry_type {
+            SRAT_TYPE_CPU_AFFINITY if len >= 16 => {
+                if read_u32(entry, 4) & SRAT_ENABLED != 0 {
+                    // 邻近域的低8位位于偏移2，高24位位于偏移9
+                    let domain = entry[2] as u32
+                        | (entry[9] as u32) << 8
+                        | (entry[10] as u32) << 16
+                        | (entry[11] as u32) << 24;
+                    info.add_cpu(ProcessorId::new(entry[3] as u32), domain);
+                }
+            }
+            SRAT_TYPE_MEMORY_AFFINITY if len >= 40 => {
+                if read_u32(entry, 28) & SRAT_ENABLED != 0 {
+                    let domain = read_u32(entry, 2);
+                    let base = read_u64(entry, 8) as usize;
+                    let size = read_u64(entry, 16) as usize;
+                    info.add_memblk(domain, PhysAddr::new(base), PhysAddr::new(base + size));
+                }
+            }
+            SRAT_TYPE_X2APIC_CPU_AFFINITY if len >= 24 => {
+                if read_u32(entry, 12) & SRAT_ENABLED != 0 {
+                    let domain = read_u32(entry, 4);
+                    info.add_cpu(ProcessorId::new(read_u32(entry, 8)), domain);
+                }
+            }
+            _ => {}
+        }
+        offset += len;
+    }
+
+    numa_init(info);
+}
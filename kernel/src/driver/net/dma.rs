@@ -2,17 +2,17 @@ use crate::arch::mm::kernel_page_flags;
 
 use crate::arch::MMArch;
 
+use crate::mm::gfp::GfpFlags;
 use crate::mm::kernel_mapper::KernelMapper;
+use crate::mm::mmzone::alloc_pages;
 use crate::mm::page::EntryFlags;
 use crate::mm::{
-    allocator::page_frame::{
-        allocate_page_frames, deallocate_page_frames, PageFrameCount, PhysPageFrame,
-    },
+    allocator::page_frame::{deallocate_page_frames, PageFrameCount, PhysPageFrame},
     MemoryManagementArch, PhysAddr, VirtAddr,
 };
 use core::ptr::NonNull;
 const PAGE_SIZE: usize = 4096;
-/// @brief 申请用于DMA的内存页
+/// @brief 申请用于DMA的内存页，内存页位于4G以下，以便只支持32位地址的设备访问
 /// @param pages 页数（4k一页）
 /// @return PhysAddr 获得的内存页的初始物理地址
 pub fn dma_alloc(pages: usize) -> (usize, NonNull<u8>) {
@@ -22,10 +22,10 @@ pub fn dma_alloc(pages: usize) -> (usize, NonNull<u8>) {
             .next_power_of_two(),
     );
     unsafe {
-        let (paddr, count) = allocate_page_frames(page_num).expect("e1000e: alloc page failed");
+        // 分配时清空这块区域，防止出现脏数据
+        let (paddr, _) = alloc_pages(GfpFlags::GFP_DMA32 | GfpFlags::__GFP_ZERO, page_num)
+            .expect("e1000e: alloc page failed");
         let virt = MMArch::phys_2_virt(paddr).unwrap();
-        // 清空这块区域，防止出现脏数据
-        core::ptr::write_bytes(virt.data() as *mut u8, 0, count.data() * MMArch::PAGE_SIZE);
 
         let dma_flags: EntryFlags<MMArch> = EntryFlags::mmio_flags();
 
@@ -1,7 +1,7 @@
 use crate::{
     arch::{interrupt::TrapFrame, CurrentSignalArch},
     ipc::signal_types::SignalArch,
-    process::{ProcessFlags, ProcessManager},
+    process::{resource::rlimit_resume_work, ProcessFlags, ProcessManager},
 };
 
 #[no_mangle]
@@ -38,6 +38,12 @@ unsafe fn exit_to_user_mode_prepare(frame: &mut TrapFrame) {
 /// 必须保证所有的栈上的Arc/Box指针等，都已经被释放。否则，可能会导致内存泄漏。
 unsafe fn exit_to_user_mode_loop(frame: &mut TrapFrame, mut process_flags_work: ProcessFlags) {
     while !process_flags_work.exit_to_user_mode_work().is_empty() {
+        if process_flags_work.contains(ProcessFlags::NOTIFY_RESUME) {
+            ProcessManager::current_pcb()
+                .flags()
+                .remove(ProcessFlags::NOTIFY_RESUME);
+            rlimit_resume_work();
+        }
         if process_flags_work.contains(ProcessFlags::HAS_PENDING_SIGNAL) {
             unsafe { CurrentSignalArch::do_signal_or_restart(frame) };
         }
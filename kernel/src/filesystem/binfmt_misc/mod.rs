@@ -0,0 +1,655 @@
+//! binfmt_misc
+//!
+//! 允许用户在运行时根据文件头部的魔数或者文件扩展名注册解释器，例如使用qemu-user运行其他架构的程序。
+//! 通过向`register`文件写入`:name:type:offset:magic:mask:interpreter:flags`注册解释器，
+//! 每个注册项在文件系统中对应一个同名文件，读取可以得到注册项的信息，写入`1`/`0`/`-1`分别启用、禁用、删除注册项。
+//! 向`status`文件写入`1`/`0`/`-1`则对整个binfmt_misc生效。
+//!
+//! 参考 https://code.dragonos.org.cn/xref/linux-6.1.9/fs/binfmt_misc.c
+
+use core::any::Any;
+use core::fmt::Write;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use alloc::{
+    ffi::CString,
+    string::{String, ToString},
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+use linkme::distributed_slice;
+use system_error::SystemError;
+
+use crate::{
+    libs::{rwlock::RwLock, spinlock::SpinLockGuard},
+    process::exec::{load_binary_file, BinaryLoader, BinaryLoaderResult, ExecError, ExecParam},
+};
+
+use super::vfs::{
+    file::{FileMode, FilePrivateData},
+    syscall::ModeType,
+    utils::DName,
+    vcore::generate_inode_id,
+    FileSystem, FileSystemMaker, FileSystemMakerData, FileType, FsInfo, IndexNode, InodeId, Magic,
+    Metadata, SuperBlock, FSMAKER,
+};
+
+/// 注册字符串的最大长度
+const MAX_REGISTER_LENGTH: usize = 1920;
+/// 魔数匹配时可以检查的文件头部长度
+const BINPRM_BUF_SIZE: usize = 256;
+/// 注册项名称的最大长度
+const BINFMT_MISC_MAX_NAMELEN: usize = 255;
+
+bitflags! {
+    /// 注册项的标志
+    struct BinfmtFlags: u32 {
+        /// `P`: 保留原来的argv[0]
+        const PRESERVE_ARGV0 = 1 << 0;
+        /// `F`: 注册时就打开解释器，之后即使在其他挂载命名空间或chroot中也能使用
+        const FIX_BINARY = 1 << 1;
+    }
+}
+
+/// 注册项的匹配方式
+#[derive(Debug)]
+enum BinfmtMatch {
+    /// 文件头部偏移`offset`处的内容与掩码按位与之后等于`magic`
+    Magic {
+        offset: usize,
+        magic: Vec<u8>,
+        mask: Option<Vec<u8>>,
+    },
+    /// 文件扩展名（不包括`.`）
+    Extension(String),
+}
+
+/// binfmt_misc的注册项
+#[derive(Debug)]
+struct BinfmtEntry {
+    name: String,
+    matcher: BinfmtMatch,
+    interpreter: String,
+    flags: BinfmtFlags,
+    /// 设置了`F`标志时，注册时打开的解释器
+    interp_inode: Option<Arc<dyn IndexNode>>,
+    enabled: AtomicBool,
+    inode_id: InodeId,
+}
+
+impl BinfmtEntry {
+    /// 解析注册字符串`:name:type:offset:magic:mask:interpreter:flags`，第一个字符是分隔符
+    fn parse(buf: &[u8]) -> Result<Self, SystemError> {
+        if buf.len() < 11 || buf.len() > MAX_REGISTER_LENGTH {
+            return Err(SystemError::EINVAL);
+        }
+        let s = core::str::from_utf8(buf).map_err(|_| SystemError::EINVAL)?;
+        let s = s.strip_suffix('\n').unwrap_or(s);
+        let del = s.chars().next().ok_or(SystemError::EINVAL)?;
+        let fields: Vec<&str> = s[del.len_utf8()..].split(del).collect();
+        if fields.len() < 6 || fields.len() > 7 {
+            return Err(SystemError::EINVAL);
+        }
+        let (name, kind, offset, magic, mask, interpreter) = (
+            fields[0], fields[1], fields[2], fields[3], fields[4], fields[5],
+        );
+        let flags_str = fields.get(6).copied().unwrap_or("");
+
+        if name.is_empty()
+            || name == "."
+            || name == ".."
+            || name.contains('/')
+            || name.len() > BINFMT_MISC_MAX_NAMELEN
+        {
+            return Err(SystemError::EINVAL);
+        }
+
+        let matcher = match kind {
+            "M" => {
+                let offset = if offset.is_empty() {
+                    0
+                } else {
+                    offset.parse::<usize>().map_err(|_| SystemError::EINVAL)?
+                };
+                let mut magic = unescape_hex(magic);
+                if magic.is_empty() || offset + magic.len() > BINPRM_BUF_SIZE {
+                    return Err(SystemError::EINVAL);
+                }
+                let mask = if mask.is_empty() {
+                    None
+                } else {
+                    let mask = unescape_hex(mask);
+                    if mask.len() != magic.len() {
+                        return Err(SystemError::EINVAL);
+                    }
+                    magic.iter_mut().zip(mask.iter()).for_each(|(m, k)| *m &= k);
+                    Some(mask)
+                };
+                BinfmtMatch::Magic {
+                    offset,
+                    magic,
+                    mask,
+                }
+            }
+            "E" => {
+                if !offset.is_empty() || !mask.is_empty() || magic.is_empty() || magic.contains('/')
+                {
+                    return Err(SystemError::EINVAL);
+                }
+                BinfmtMatch::Extension(magic.to_string())
+            }
+            _ => return Err(SystemError::EINVAL),
+        };
+
+        // 解释器路径会作为argv[0]传给解释器，不能包含空字符
+        if interpreter.is_empty() || interpreter.contains('\0') {
+            return Err(SystemError::EINVAL);
+        }
+
+        let mut flags = BinfmtFlags::empty();
+        for c in flags_str.chars() {
+            match c {
+                'P' => flags.insert(BinfmtFlags::PRESERVE_ARGV0),
+                'F' => flags.insert(BinfmtFlags::FIX_BINARY),
+                // 不支持通过AT_EXECFD把文件交给解释器（`O`、`C`标志）
+                _ => return Err(SystemError::EINVAL),
+            }
+        }
+
+        let interp_inode = if flags.contains(BinfmtFlags::FIX_BINARY) {
+            Some(ExecParam::lookup_exec(interpreter)?)
+        } else {
+            None
+        };
+
+        return Ok(Self {
+            name: name.to_string(),
+            matcher,
+            interpreter: interpreter.to_string(),
+            flags,
+            interp_inode,
+            enabled: AtomicBool::new(true),
+            inode_id: generate_inode_id(),
+        });
+    }
+
+    /// 判断文件是否与注册项匹配
+    ///
+    /// ## 参数
+    ///
+    /// - `filename`: 文件路径
+    /// - `buf`: 文件头部的内容
+    fn matches(&self, filename: &str, buf: &[u8]) -> bool {
+        if !self.enabled.load(Ordering::SeqCst) {
+            return false;
+        }
+        match &self.matcher {
+            BinfmtMatch::Magic {
+                offset,
+                magic,
+                mask,
+            } => {
+                let Some(head) = buf.get(*offset..*offset + magic.len()) else {
+                    return false;
+                };
+                match mask {
+                    Some(mask) => head
+                        .iter()
+                        .zip(mask.iter())
+                        .map(|(b, k)| b & k)
+                        .eq(magic.iter().copied()),
+                    None => head == magic.as_slice(),
+                }
+            }
+            BinfmtMatch::Extension(ext) => {
+                let basename = filename.rsplit('/').next().unwrap_or(filename);
+                basename
+                    .rsplit_once('.')
+                    .is_some_and(|(_, e)| e == ext.as_str())
+            }
+        }
+    }
+
+    /// 读取注册项文件时的内容
+    fn status(&self) -> String {
+        let mut s = String::new();
+        if self.enabled.load(Ordering::SeqCst) {
+            s.push_str("enabled\n");
+        } else {
+            s.push_str("disabled\n");
+        }
+        writeln!(s, "interpreter {}", self.interpreter).ok();
+        s.push_str("flags: ");
+        if self.flags.contains(BinfmtFlags::PRESERVE_ARGV0) {
+            s.push('P');
+        }
+        if self.flags.contains(BinfmtFlags::FIX_BINARY) {
+            s.push('F');
+        }
+        s.push('\n');
+        match &self.matcher {
+            BinfmtMatch::Magic {
+                offset,
+                magic,
+                mask,
+            } => {
+                writeln!(s, "offset {}", offset).ok();
+                writeln!(s, "magic {}", hex_string(magic)).ok();
+                if let Some(mask) = mask {
+                    writeln!(s, "mask {}", hex_string(mask)).ok();
+                }
+            }
+            BinfmtMatch::Extension(ext) => {
+                writeln!(s, "extension .{}", ext).ok();
+            }
+        }
+        return s;
+    }
+}
+
+/// 把`\xHH`形式的转义序列转换为对应的字节
+fn unescape_hex(s: &str) -> Vec<u8> {
+    let bytes = s.as_bytes();
+    let mut result = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'\\' && i + 4 <= bytes.len() && (bytes[i + 1] | 0x20) == b'x' {
+            let hex = core::str::from_utf8(&bytes[i + 2..i + 4]).unwrap_or("");
+            if let Ok(b) = u8::from_str_radix(hex, 16) {
+                result.push(b);
+                i += 4;
+                continue;
+            }
+        }
+        result.push(bytes[i]);
+        i += 1;
+    }
+    return result;
+}
+
+fn hex_string(bytes: &[u8]) -> String {
+    let mut s = String::with_capacity(bytes.len() * 2);
+    for b in bytes {
+        write!(s, "{:02x}", b).ok();
+    }
+    return s;
+}
+
+/// 写入`status`和注册项文件的命令
+enum BinfmtCommand {
+    Disable,
+    Enable,
+    Remove,
+}
+
+impl BinfmtCommand {
+    fn parse(buf: &[u8]) -> Result<Self, SystemError> {
+        let buf = buf.strip_suffix(b"\n").unwrap_or(buf);
+        match buf {
+            b"0" => Ok(Self::Disable),
+            b"1" => Ok(Self::Enable),
+            b"-1" => Ok(Self::Remove),
+            _ => Err(SystemError::EINVAL),
+        }
+    }
+}
+
+/// 所有binfmt_misc挂载点共享的注册信息
+#[derive(Debug)]
+struct BinfmtMisc {
+    enabled: AtomicBool,
+    /// 注册项，后注册的在前面，匹配时优先
+    entries: RwLock<Vec<Arc<BinfmtEntry>>>,
+}
+
+static BINFMT_MISC: BinfmtMisc = BinfmtMisc {
+    enabled: AtomicBool::new(true),
+    entries: RwLock::new(Vec::new()),
+};
+
+impl BinfmtMisc {
+    fn register(&self, entry: BinfmtEntry) -> Result<(), SystemError> {
+        let mut entries = self.entries.write();
+        if entries.iter().any(|e| e.name == entry.name) {
+            return Err(SystemError::EEXIST);
+        }
+        entries.insert(0, Arc::new(entry));
+        return Ok(());
+    }
+
+    fn unregister(&self, entry: &Arc<BinfmtEntry>) {
+        self.entries.write().retain(|e| !Arc::ptr_eq(e, entry));
+    }
+
+    fn find(&self, name: &str) -> Option<Arc<BinfmtEntry>> {
+        self.entries.read().iter().find(|e| e.name == name).cloned()
+    }
+
+    /// 查找与文件匹配的注册项
+    fn search(&self, filename: &str, buf: &[u8]) -> Option<Arc<BinfmtEntry>> {
+        if !self.enabled.load(Ordering::SeqCst) {
+            return None;
+        }
+        self.entries
+            .read()
+            .iter()
+            .find(|e| e.matches(filename, buf))
+            .cloned()
+    }
+}
+
+#[derive(Debug)]
+pub struct BinfmtMiscLoader;
+
+pub const BINFMT_MISC_LOADER: BinfmtMiscLoader = BinfmtMiscLoader::new();
+
+impl BinfmtMiscLoader {
+    pub const fn new() -> Self {
+        Self
+    }
+}
+
+impl BinaryLoader for BinfmtMiscLoader {
+    fn probe(&'static self, param: &ExecParam, buf: &[u8]) -> Result<(), ExecError> {
+        BINFMT_MISC
+            .search(param.interp(), buf)
+            .map(|_| ())
+            .ok_or(ExecError::NotExecutable)
+    }
+
+    fn load(
+        &'static self,
+        param: &mut ExecParam,
+        head_buf: &[u8],
+    ) -> Result<BinaryLoaderResult, ExecError> {
+        let entry = BINFMT_MISC
+            .search(param.interp(), head_buf)
+            .ok_or(ExecError::NotExecutable)?;
+
+        let inode = match &entry.interp_inode {
+            Some(inode) => inode.clone(),
+            None => ExecParam::lookup_exec(&entry.interpreter).map_err(ExecError::Errno)?,
+        };
+        let binary = CString::new(param.interp()).map_err(|_| ExecError::InvalidParemeter)?;
+        let interpreter =
+            CString::new(entry.interpreter.as_str()).map_err(|_| ExecError::InvalidParemeter)?;
+        param
+            .set_interpreter(&entry.interpreter, inode)
+            .map_err(ExecError::Errno)?;
+
+        // 参数列表改写为: 解释器 文件路径 原参数[1..]，设置了P标志时保留原来的argv[0]
+        let args = &mut param.init_info_mut().args;
+        if !entry.flags.contains(BinfmtFlags::PRESERVE_ARGV0) && !args.is_empty() {
+            args.remove(0);
+        }
+        args.splice(0..0, [interpreter, binary]);
+
+        return load_binary_file(param).map_err(ExecError::Errno);
+    }
+}
+
+/// binfmt_misc文件系统
+#[derive(Debug)]
+pub struct BinfmtMiscFs {
+    root_inode: Arc<BinfmtMiscInode>,
+    register_inode: Arc<BinfmtMiscInode>,
+    status_inode: Arc<BinfmtMiscInode>,
+    super_block: SuperBlock,
+}
+
+#[derive(Debug)]
+enum BinfmtMiscInodeType {
+    Root,
+    Register,
+    Status,
+    Entry(Arc<BinfmtEntry>),
+}
+
+#[derive(Debug)]
+pub struct BinfmtMiscInode {
+    kind: BinfmtMiscInodeType,
+    metadata: Metadata,
+    fs: Weak<BinfmtMiscFs>,
+}
+
+impl BinfmtMiscInode {
+    fn new(kind: BinfmtMiscInodeType, fs: Weak<BinfmtMiscFs>) -> Self {
+        let (file_type, mode) = match kind {
+            BinfmtMiscInodeType::Root => (FileType::Dir, 0o755),
+            BinfmtMiscInodeType::Register => (FileType::File, 0o200),
+            BinfmtMiscInodeType::Status | BinfmtMiscInodeType::Entry(_) => (FileType::File, 0o644),
+        };
+        let mut metadata = Metadata::new(file_type, ModeType::from_bits_truncate(mode));
+        if let BinfmtMiscInodeType::Entry(entry) = &kind {
+            metadata.inode_id = entry.inode_id;
+        }
+        if file_type == FileType::Dir {
+            metadata.nlinks = 2;
+        }
+        Self { kind, metadata, fs }
+    }
+
+    /// 读取文件时的内容
+    fn content(&self) -> Result<String, SystemError> {
+        match &self.kind {
+            BinfmtMiscInodeType::Root => Err(SystemError::EISDIR),
+            BinfmtMiscInodeType::Register => Err(SystemError::EINVAL),
+            BinfmtMiscInodeType::Status => {
+                if BINFMT_MISC.enabled.load(Ordering::SeqCst) {
+                    Ok("enabled\n".to_string())
+                } else {
+                    Ok("disabled\n".to_string())
+                }
+            }
+            BinfmtMiscInodeType::Entry(entry) => Ok(entry.status()),
+        }
+    }
+}
+
+impl IndexNode for BinfmtMiscInode {
+    fn open(
+        &self,
+        _data: SpinLockGuard<FilePrivateData>,
+        _mode: &FileMode,
+    ) -> Result<(), SystemError> {
+        return Ok(());
+    }
+
+    fn close(&self, _data: SpinLockGuard<FilePrivateData>) -> Result<(), SystemError> {
+        return Ok(());
+    }
+
+    fn truncate(&self, _len: usize) -> Result<(), SystemError> {
+        if self.metadata.file_type == FileType::Dir {
+            return Err(SystemError::EISDIR);
+        }
+        return Ok(());
+    }
+
+    fn read_at(
+        &self,
+        offset: usize,
+        len: usize,
+        buf: &mut [u8],
+        _data: SpinLockGuard<FilePrivateData>,
+    ) -> Result<usize, SystemError> {
+        let content = self.content()?;
+        let content = content.as_bytes();
+        if offset >= content.len() {
+            return Ok(0);
+        }
+        let len = core::cmp::min(len.min(buf.len()), content.len() - offset);
+        buf[..len].copy_from_slice(&content[offset..offset + len]);
+        return Ok(len);
+    }
+
+    fn write_at(
+        &self,
+        _offset: usize,
+        len: usize,
+        buf: &[u8],
+        _data: SpinLockGuard<FilePrivateData>,
+    ) -> Result<usize, SystemError> {
+        let buf = &buf[..len.min(buf.len())];
+        match &self.kind {
+            BinfmtMiscInodeType::Root => return Err(SystemError::EISDIR),
+            BinfmtMiscInodeType::Register => {
+                BINFMT_MISC.register(BinfmtEntry::parse(buf)?)?;
+            }
+            BinfmtMiscInodeType::Status => match BinfmtCommand::parse(buf)? {
+                BinfmtCommand::Disable => BINFMT_MISC.enabled.store(false, Ordering::SeqCst),
+                BinfmtCommand::Enable => BINFMT_MISC.enabled.store(true, Ordering::SeqCst),
+                BinfmtCommand::Remove => BINFMT_MISC.entries.write().clear(),
+            },
+            BinfmtMiscInodeType::Entry(entry) => match BinfmtCommand::parse(buf)? {
+                BinfmtCommand::Disable => entry.enabled.store(false, Ordering::SeqCst),
+                BinfmtCommand::Enable => entry.enabled.store(true, Ordering::SeqCst),
+                BinfmtCommand::Remove => BINFMT_MISC.unregister(entry),
+            },
+        }
+        return Ok(buf.len());
+    }
+
+    fn fs(&self) -> Arc<dyn FileSystem> {
+        return self.fs.upgrade().unwrap();
+    }
+
+    fn as_any_ref(&self) -> &dyn Any {
+        self
+    }
+
+    fn metadata(&self) -> Result<Metadata, SystemError> {
+        return Ok(self.metadata.clone());
+    }
+
+    fn find(&self, name: &str) -> Result<Arc<dyn IndexNode>, SystemError> {
+        if self.metadata.file_type != FileType::Dir {
+            return Err(SystemError::ENOTDIR);
+        }
+        let fs = self.fs.upgrade().ok_or(SystemError::ENOENT)?;
+        match name {
+            "" | "." | ".." => Ok(fs.root_inode.clone()),
+            "register" => Ok(fs.register_inode.clone()),
+            "status" => Ok(fs.status_inode.clone()),
+            name => {
+                let entry = BINFMT_MISC.find(name).ok_or(SystemError::ENOENT)?;
+                Ok(Arc::new(BinfmtMiscInode::new(
+                    BinfmtMiscInodeType::Entry(entry),
+                    self.fs.clone(),
+                )))
+            }
+        }
+    }
+
+    fn get_entry_name(&self, ino: InodeId) -> Result<String, SystemError> {
+        if self.metadata.file_type != FileType::Dir {
+            return Err(SystemError::ENOTDIR);
+        }
+        let fs = self.fs.upgrade().ok_or(SystemError::ENOENT)?;
+        if ino == fs.register_inode.metadata.inode_id {
+            return Ok(String::from("register"));
+        }
+        if ino == fs.status_inode.metadata.inode_id {
+            return Ok(String::from("status"));
+        }
+        match ino.into() {
+            0 => Ok(String::from(".")),
+            1 => Ok(String::from("..")),
+            ino => BINFMT_MISC
+                .entries
+                .read()
+                .iter()
+                .find(|e| e.inode_id.into() == ino)
+                .map(|e| e.name.clone())
+                .ok_or(SystemError::ENOENT),
+        }
+    }
+
+    fn list(&self) -> Result<Vec<String>, SystemError> {
+        if self.metadata.file_type != FileType::Dir {
+            return Err(SystemError::ENOTDIR);
+        }
+        let mut keys: Vec<String> = Vec::new();
+        keys.push(String::from("."));
+        keys.push(String::from(".."));
+        keys.push(String::from("register"));
+        keys.push(String::from("status"));
+        keys.extend(BINFMT_MISC.entries.read().iter().map(|e| e.name.clone()));
+        return Ok(keys);
+    }
+
+    fn dname(&self) -> Result<DName, SystemError> {
+        match &self.kind {
+            BinfmtMiscInodeType::Root => Ok(DName::default()),
+            BinfmtMiscInodeType::Register => Ok(DName::from("register")),
+            BinfmtMiscInodeType::Status => Ok(DName::from("status")),
+            BinfmtMiscInodeType::Entry(entry) => Ok(DName::from(entry.name.as_str())),
+        }
+    }
+
+    fn parent(&self) -> Result<Arc<dyn IndexNode>, SystemError> {
+        let fs = self.fs.upgrade().ok_or(SystemError::EINVAL)?;
+        return Ok(fs.root_inode.clone());
+    }
+}
+
+impl FileSystem for BinfmtMiscFs {
+    fn root_inode(&self) -> Arc<dyn IndexNode> {
+        return self.root_inode.clone();
+    }
+
+    fn info(&self) -> FsInfo {
+        return FsInfo {
+            blk_dev_id: 0,
+            max_name_len: BINFMT_MISC_MAX_NAMELEN,
+        };
+    }
+
+    fn as_any_ref(&self) -> &dyn Any {
+        self
+    }
+
+    fn name(&self) -> &str {
+        "binfmt_misc"
+    }
+
+    fn super_block(&self) -> SuperBlock {
+        self.super_block.clone()
+    }
+}
+
+impl BinfmtMiscFs {
+    fn new() -> Arc<Self> {
+        Arc::new_cyclic(|fs| BinfmtMiscFs {
+            root_inode: Arc::new(BinfmtMiscInode::new(BinfmtMiscInodeType::Root, fs.clone())),
+            register_inode: Arc::new(BinfmtMiscInode::new(
+                BinfmtMiscInodeType::Register,
+                fs.clone(),
+            )),
+            status_inode: Arc::new(BinfmtMiscInode::new(
+                BinfmtMiscInodeType::Status,
+                fs.clone(),
+            )),
+            super_block: SuperBlock::new(
+                Magic::BINFMTFS_MAGIC,
+                4096,
+                BINFMT_MISC_MAX_NAMELEN as u64,
+            ),
+        })
+    }
+
+    pub fn make_binfmt_misc(
+        _data: Option<&dyn FileSystemMakerData>,
+    ) -> Result<Arc<dyn FileSystem + 'static>, SystemError> {
+        return Ok(BinfmtMiscFs::new());
+    }
+}
+
+#[distributed_slice(FSMAKER)]
+static BINFMTMISCMAKER: FileSystemMaker = FileSystemMaker::new(
+    "binfmt_misc",
+    &(BinfmtMiscFs::make_binfmt_misc
+        as fn(
+            Option<&dyn FileSystemMakerData>,
+        ) -> Result<Arc<dyn FileSystem + 'static>, SystemError>),
+);
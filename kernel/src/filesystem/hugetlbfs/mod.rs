@@ -0,0 +1,720 @@
+//! hugetlbfs
+//!
+//! 文件数据保存在从大页池中分配的大页里，只能通过mmap访问，不支持write。
+//! 文件的大小必须是大页大小的整数倍，大页在第一次缺页时才从大页池中分配。
+
+use core::any::Any;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use alloc::{
+    collections::BTreeMap,
+    string::{String, ToString},
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+use linkme::distributed_slice;
+use system_error::SystemError;
+
+use crate::{
+    arch::MMArch,
+    driver::base::device::device_number::DeviceNumber,
+    libs::{
+        casting::DowncastArc,
+        rwlock::RwLock,
+        spinlock::{SpinLock, SpinLockGuard},
+    },
+    mm::{
+        fault::PageFaultMessage,
+        hugetlb::{default_hstate, size_to_hstate, Hstate},
+        MemoryManagementArch, PhysAddr, VmFaultReason,
+    },
+    process::ProcessManager,
+    time::PosixTimeSpec,
+};
+
+use super::{
+    tmpfs::TmpfsMountData,
+    vfs::{
+        file::{File, FileMode, FilePrivateData},
+        syscall::ModeType,
+        utils::DName,
+        vcore::generate_inode_id,
+        FileSystem, FileSystemMaker, FileSystemMakerData, FileType, FsInfo, IndexNode, InodeId,
+        Magic, Metadata, SuperBlock, FSMAKER,
+    },
+};
+
+/// hugetlbfs的inode名称的最大长度
+const HUGETLBFS_MAX_NAMELEN: usize = 255;
+
+/// MAP_HUGETLB匿名映射使用的内部hugetlbfs，每种大页各一个
+static HUGETLBFS_INTERNAL: SpinLock<Vec<Arc<Hugetlbfs>>> = SpinLock::new(Vec::new());
+
+/// hugetlbfs挂载参数
+///
+/// 支持的参数: `pagesize=`, `size=`, `mode=`, `uid=`, `gid=`
+#[derive(Debug, Clone, Default)]
+pub struct HugetlbfsMountData {
+    /// 大页的字节数
+    pagesize: Option<usize>,
+    /// 容量上限(字节)
+    size: Option<usize>,
+    /// 根目录权限
+    mode: Option<ModeType>,
+    /// 根目录所有者
+    uid: Option<usize>,
+    /// 根目录所属组
+    gid: Option<usize>,
+}
+
+impl HugetlbfsMountData {
+    pub fn from_row(raw_data: *const u8) -> Result<Self, SystemError> {
+        let mut data = HugetlbfsMountData::default();
+        if raw_data.is_null() {
+            return Ok(data);
+        }
+        let len = (0..)
+            .find(|&i| unsafe { raw_data.add(i).read() } == 0)
+            .ok_or(SystemError::EINVAL)?;
+        let slice = unsafe { core::slice::from_raw_parts(raw_data, len) };
+        let raw_str = core::str::from_utf8(slice).map_err(|_| SystemError::EINVAL)?;
+
+        for pair in raw_str.split(',').filter(|s| !s.is_empty()) {
+            let mut parts = pair.splitn(2, '=');
+            let key = parts.next().ok_or(SystemError::EINVAL)?;
+            let value = parts.next().ok_or(SystemError::EINVAL)?;
+
+            match key {
+                "pagesize" => data.pagesize = Some(TmpfsMountData::parse_number(value)?),
+                "size" => data.size = Some(TmpfsMountData::parse_number(value)?),
+                "mode" => {
+                    let mode = u32::from_str_radix(value, 8).map_err(|_| SystemError::EINVAL)?;
+                    data.mode = Some(ModeType::from_bits_truncate(mode) & ModeType::S_IALLUGO);
+                }
+                "uid" => data.uid = Some(value.parse().map_err(|_| SystemError::EINVAL)?),
+                "gid" => data.gid = Some(value.parse().map_err(|_| SystemError::EINVAL)?),
+                _ => return Err(SystemError::EINVAL),
+            }
+        }
+        Ok(data)
+    }
+}
+
+impl FileSystemMakerData for HugetlbfsMountData {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+/// hugetlbfs文件系统结构体
+#[derive(Debug)]
+pub struct Hugetlbfs {
+    root_inode: Arc<LockedHugetlbfsInode>,
+    super_block: RwLock<SuperBlock>,
+    /// 文件使用的大页池
+    hstate: &'static Hstate,
+    /// 容量上限(大页)，0表示不限制
+    max_pages: usize,
+    /// 已分配的大页数
+    used_pages: AtomicUsize,
+}
+
+#[derive(Debug)]
+pub struct LockedHugetlbfsInode(SpinLock<HugetlbfsInode>);
+
+/// hugetlbfs的Inode结构体(不包含锁)
+#[derive(Debug)]
+pub struct HugetlbfsInode {
+    /// 指向父Inode的弱引用
+    parent: Weak<LockedHugetlbfsInode>,
+    /// 指向自身的弱引用
+    self_ref: Weak<LockedHugetlbfsInode>,
+    /// 子Inode的B树
+    children: BTreeMap<DName, Arc<LockedHugetlbfsInode>>,
+    /// 文件中已分配的大页，键为大页在文件中的序号。文件对每个大页持有一个引用
+    pages: BTreeMap<usize, PhysAddr>,
+    /// 当前inode的元数据
+    metadata: Metadata,
+    /// 指向inode所在的文件系统对象的指针
+    fs: Weak<Hugetlbfs>,
+    /// 文件使用的大页池
+    hstate: &'static Hstate,
+
+    name: DName,
+}
+
+impl HugetlbfsInode {
+    fn new(
+        parent: Weak<LockedHugetlbfsInode>,
+        fs: Weak<Hugetlbfs>,
+        hstate: &'static Hstate,
+        name: DName,
+        file_type: FileType,
+        mode: ModeType,
+    ) -> Self {
+        let cred = ProcessManager::current_pcb().cred();
+        let now = PosixTimeSpec::now();
+        Self {
+            parent,
+            self_ref: Weak::default(),
+            children: BTreeMap::new(),
+            pages: BTreeMap::new(),
+            metadata: Metadata {
+                dev_id: 0,
+                inode_id: generate_inode_id(),
+                size: 0,
+                blk_size: hstate.size(),
+                blocks: 0,
+                atime: now,
+                mtime: now,
+                ctime: now,
+                btime: now,
+                file_type,
+                mode,
+                nlinks: if file_type == FileType::Dir { 2 } else { 1 },
+                uid: cred.fsuid.data(),
+                gid: cred.fsgid.data(),
+                raw_dev: DeviceNumber::default(),
+            },
+            fs,
+            hstate,
+            name,
+        }
+    }
+
+    /// 释放序号不小于`first`的所有大页
+    fn remove_pages_from(&mut self, first: usize) {
+        let removed = self.pages.split_off(&first);
+        if removed.is_empty() {
+            return;
+        }
+        if let Some(fs) = self.fs.upgrade() {
+            fs.used_pages.fetch_sub(removed.len(), Ordering::SeqCst);
+        }
+        for paddr in removed.into_values() {
+            self.hstate.put_page(paddr);
+        }
+    }
+}
+
+impl Drop for HugetlbfsInode {
+    fn drop(&mut self) {
+        self.remove_pages_from(0);
+    }
+}
+
+impl FileSystem for Hugetlbfs {
+    fn root_inode(&self) -> Arc<dyn IndexNode> {
+        return self.root_inode.clone();
+    }
+
+    fn info(&self) -> FsInfo {
+        return FsInfo {
+            blk_dev_id: 0,
+            max_name_len: HUGETLBFS_MAX_NAMELEN,
+        };
+    }
+
+    fn as_any_ref(&self) -> &dyn Any {
+        self
+    }
+
+    fn name(&self) -> &str {
+        "hugetlbfs"
+    }
+
+    fn super_block(&self) -> SuperBlock {
+        let mut sb = self.super_block.read().clone();
+        if self.max_pages != 0 {
+            sb.blocks = self.max_pages as u64;
+            sb.bfree = sb
+                .blocks
+                .saturating_sub(self.used_pages.load(Ordering::Relaxed) as u64);
+            sb.bavail = sb.bfree;
+        }
+        sb
+    }
+
+    unsafe fn fault(&self, _pfm: &mut PageFaultMessage) -> VmFaultReason {
+        // 大页映射的缺页由handle_hugetlb_fault处理，不会走到这里
+        VmFaultReason::VM_FAULT_SIGBUS
+    }
+}
+
+impl Hugetlbfs {
+    fn new(hstate: &'static Hstate, mount_data: &HugetlbfsMountData) -> Arc<Self> {
+        let super_block = SuperBlock::new(
+            Magic::HUGETLBFS_MAGIC,
+            hstate.size() as u64,
+            HUGETLBFS_MAX_NAMELEN as u64,
+        );
+
+        let result: Arc<Hugetlbfs> = Arc::new_cyclic(|fs| {
+            let mut root = HugetlbfsInode::new(
+                Weak::default(),
+                fs.clone(),
+                hstate,
+                DName::default(),
+                FileType::Dir,
+                mount_data
+                    .mode
+                    .unwrap_or(ModeType::from_bits_truncate(0o755)),
+            );
+            if let Some(uid) = mount_data.uid {
+                root.metadata.uid = uid;
+            }
+            if let Some(gid) = mount_data.gid {
+                root.metadata.gid = gid;
+            }
+
+            Hugetlbfs {
+                root_inode: Arc::new(LockedHugetlbfsInode(SpinLock::new(root))),
+                super_block: RwLock::new(super_block),
+                hstate,
+                max_pages: mount_data.size.unwrap_or(0) / hstate.size(),
+                used_pages: AtomicUsize::new(0),
+            }
+        });
+
+        let mut root_guard = result.root_inode.0.lock();
+        root_guard.parent = Arc::downgrade(&result.root_inode);
+        root_guard.self_ref = Arc::downgrade(&result.root_inode);
+        drop(root_guard);
+
+        return result;
+    }
+
+    pub fn make_hugetlbfs(
+        data: Option<&dyn FileSystemMakerData>,
+    ) -> Result<Arc<dyn FileSystem + 'static>, SystemError> {
+        let mount_data = data
+            .and_then(|d| d.as_any().downcast_ref::<HugetlbfsMountData>())
+            .cloned()
+            .unwrap_or_default();
+        let hstate = match mount_data.pagesize {
+            Some(size) => size_to_hstate(size),
+            None => default_hstate(),
+        }
+        .ok_or(SystemError::EINVAL)?;
+        return Ok(Hugetlbfs::new(hstate, &mount_data));
+    }
+
+    /// 申请一个大页的配额
+    ///
+    /// ## 返回值
+    ///
+    /// - `Err(SystemError::ENOSPC)` 超出了`size=`指定的容量上限
+    fn alloc_quota(&self) -> Result<(), SystemError> {
+        self.used_pages
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |used| {
+                if self.max_pages != 0 && used >= self.max_pages {
+                    None
+                } else {
+                    Some(used + 1)
+                }
+            })
+            .map(|_| ())
+            .map_err(|_| SystemError::ENOSPC)
+    }
+
+    /// 创建一个不在任何目录中的文件，用于MAP_HUGETLB匿名映射
+    fn new_unlinked_file(self: &Arc<Self>, size: usize) -> Arc<LockedHugetlbfsInode> {
+        let mut inode = HugetlbfsInode::new(
+            Arc::downgrade(&self.root_inode),
+            Arc::downgrade(self),
+            self.hstate,
+            DName::default(),
+            FileType::File,
+            ModeType::from_bits_truncate(0o600),
+        );
+        inode.metadata.size = size as i64;
+        inode.metadata.nlinks = 0;
+
+        let result = Arc::new(LockedHugetlbfsInode(SpinLock::new(inode)));
+        result.0.lock().self_ref = Arc::downgrade(&result);
+        return result;
+    }
+}
+
+#[distributed_slice(FSMAKER)]
+static HUGETLBFSMAKER: FileSystemMaker = FileSystemMaker::new(
+    "hugetlbfs",
+    &(Hugetlbfs::make_hugetlbfs
+        as fn(
+            Option<&dyn FileSystemMakerData>,
+        ) -> Result<Arc<dyn FileSystem + 'static>, SystemError>),
+);
+
+/// 获取文件对应的hugetlbfs inode
+///
+/// ## 返回值
+///
+/// 文件不在hugetlbfs中时返回None
+pub fn hugetlbfs_inode(file: &File) -> Option<Arc<LockedHugetlbfsInode>> {
+    file.inode().downcast_arc::<LockedHugetlbfsInode>()
+}
+
+/// 为MAP_HUGETLB匿名映射创建一个大小为`size`字节的hugetlbfs文件
+pub fn hugetlb_file_setup(hstate: &'static Hstate, size: usize) -> Result<Arc<File>, SystemError> {
+    let fs = {
+        let mut internal = HUGETLBFS_INTERNAL.lock();
+        match internal.iter().find(|fs| core::ptr::eq(fs.hstate, hstate)) {
+            Some(fs) => fs.clone(),
+            None => {
+                let fs = Hugetlbfs::new(hstate, &HugetlbfsMountData::default());
+                internal.push(fs.clone());
+                fs
+            }
+        }
+    };
+    let inode = fs.new_unlinked_file(size);
+    return Ok(Arc::new(File::new(inode, FileMode::O_RDWR)?));
+}
+
+impl LockedHugetlbfsInode {
+    /// 文件使用的大页池
+    pub fn hstate(&self) -> &'static Hstate {
+        self.0.lock().hstate
+    }
+
+    /// 文件大小对应的大页数
+    pub fn size_in_pages(&self) -> usize {
+        let inode = self.0.lock();
+        (inode.metadata.size as usize).div_ceil(inode.hstate.size())
+    }
+
+    /// 获取文件中第`index`个大页，并为调用者增加一个引用
+    ///
+    /// ## 返回值
+    ///
+    /// 大页还没有被分配时返回None
+    pub fn get_page(&self, index: usize) -> Option<PhysAddr> {
+        let inode = self.0.lock();
+        let paddr = *inode.pages.get(&index)?;
+        inode.hstate.get_page(paddr);
+        return Some(paddr);
+    }
+
+    /// 获取文件中第`index`个大页，不存在时从大页池中分配，并为调用者增加一个引用
+    ///
+    /// ## 返回值
+    ///
+    /// - `Err(SystemError::ENOSPC)` 超出了挂载时指定的容量上限
+    /// - `Err(SystemError::ENOMEM)` 大页池中没有空闲的大页
+    pub fn get_or_alloc_page(&self, index: usize) -> Result<PhysAddr, SystemError> {
+        let mut inode = self.0.lock();
+        let hstate = inode.hstate;
+        if let Some(paddr) = inode.pages.get(&index).copied() {
+            hstate.get_page(paddr);
+            return Ok(paddr);
+        }
+
+        let fs = inode.fs.upgrade().ok_or(SystemError::ENOENT)?;
+        fs.alloc_quota()?;
+        let paddr = match hstate.alloc_page() {
+            Some(paddr) => paddr,
+            None => {
+                fs.used_pages.fetch_sub(1, Ordering::SeqCst);
+                return Err(SystemError::ENOMEM);
+            }
+        };
+        inode.pages.insert(index, paddr);
+        hstate.get_page(paddr);
+        return Ok(paddr);
+    }
+
+    /// 统计`[first, first + count)`范围内还没有被分配的大页数量
+    pub fn count_missing(&self, first: usize, count: usize) -> usize {
+        let inode = self.0.lock();
+        count - inode.pages.range(first..first + count).count()
+    }
+
+    /// 若文件小于`size`字节，则将其扩大到`size`字节
+    pub fn extend_size(&self, size: usize) {
+        let mut inode = self.0.lock();
+        if (inode.metadata.size as usize) < size {
+            inode.metadata.size = size as i64;
+        }
+    }
+
+    /// 将文件大小设置为`len`，并释放超出文件末尾的大页
+    fn set_size(&self, len: usize) -> Result<(), SystemError> {
+        let mut inode = self.0.lock();
+        match inode.metadata.file_type {
+            FileType::File => {}
+            FileType::Dir => return Err(SystemError::EISDIR),
+            _ => return Err(SystemError::EINVAL),
+        }
+        let size = inode.hstate.size();
+        if len % size != 0 {
+            return Err(SystemError::EINVAL);
+        }
+        inode.remove_pages_from(len / size);
+        inode.metadata.size = len as i64;
+        let now = PosixTimeSpec::now();
+        inode.metadata.mtime = now;
+        inode.metadata.ctime = now;
+        return Ok(());
+    }
+
+    /// 在当前目录下新建一个inode
+    fn new_child(
+        &self,
+        name: &str,
+        file_type: FileType,
+        mode: ModeType,
+    ) -> Result<Arc<LockedHugetlbfsInode>, SystemError> {
+        let name = DName::from(name);
+        let mut inode = self.0.lock();
+        if inode.metadata.file_type != FileType::Dir {
+            return Err(SystemError::ENOTDIR);
+        }
+        if inode.children.contains_key(&name) {
+            return Err(SystemError::EEXIST);
+        }
+
+        let result = Arc::new(LockedHugetlbfsInode(SpinLock::new(HugetlbfsInode::new(
+            inode.self_ref.clone(),
+            inode.fs.clone(),
+            inode.hstate,
+            name.clone(),
+            file_type,
+            mode,
+        ))));
+        result.0.lock().self_ref = Arc::downgrade(&result);
+
+        inode.children.insert(name, result.clone());
+        if file_type == FileType::Dir {
+            inode.metadata.nlinks += 1;
+        }
+        let now = PosixTimeSpec::now();
+        inode.metadata.mtime = now;
+        inode.metadata.ctime = now;
+
+        return Ok(result);
+    }
+}
+
+impl IndexNode for LockedHugetlbfsInode {
+    fn truncate(&self, len: usize) -> Result<(), SystemError> {
+        let size = self.0.lock().metadata.size as usize;
+        if size > len {
+            return self.set_size(len);
+        }
+        return Ok(());
+    }
+
+    fn open(
+        &self,
+        _data: SpinLockGuard<FilePrivateData>,
+        _mode: &FileMode,
+    ) -> Result<(), SystemError> {
+        return Ok(());
+    }
+
+    fn close(&self, _data: SpinLockGuard<FilePrivateData>) -> Result<(), SystemError> {
+        return Ok(());
+    }
+
+    fn read_at(
+        &self,
+        offset: usize,
+        len: usize,
+        buf: &mut [u8],
+        _data: SpinLockGuard<FilePrivateData>,
+    ) -> Result<usize, SystemError> {
+        let inode = self.0.lock();
+        if inode.metadata.file_type == FileType::Dir {
+            return Err(SystemError::EISDIR);
+        }
+        let file_size = inode.metadata.size as usize;
+        let hstate = inode.hstate;
+        drop(inode);
+
+        let end = core::cmp::min(file_size, offset.saturating_add(len.min(buf.len())));
+        let mut pos = offset;
+        while pos < end {
+            let index = pos / hstate.size();
+            let page_offset = pos % hstate.size();
+            let count = core::cmp::min(end - pos, hstate.size() - page_offset);
+            let dst = &mut buf[pos - offset..pos - offset + count];
+            // 文件空洞读出来全为0
+            match self.get_page(index) {
+                Some(paddr) => {
+                    let vaddr = unsafe { MMArch::phys_2_virt(paddr + page_offset) }.unwrap();
+                    dst.copy_from_slice(unsafe {
+                        core::slice::from_raw_parts(vaddr.data() as *const u8, count)
+                    });
+                    hstate.put_page(paddr);
+                }
+                None => dst.fill(0),
+            }
+            pos += count;
+        }
+
+        return Ok(end.saturating_sub(offset));
+    }
+
+    fn write_at(
+        &self,
+        _offset: usize,
+        _len: usize,
+        _buf: &[u8],
+        _data: SpinLockGuard<FilePrivateData>,
+    ) -> Result<usize, SystemError> {
+        // 与Linux一致，hugetlbfs的文件只能通过mmap写入
+        return Err(SystemError::EINVAL);
+    }
+
+    fn fs(&self) -> Arc<dyn FileSystem> {
+        return self.0.lock().fs.upgrade().unwrap();
+    }
+
+    fn as_any_ref(&self) -> &dyn Any {
+        self
+    }
+
+    fn metadata(&self) -> Result<Metadata, SystemError> {
+        let inode = self.0.lock();
+        let mut metadata = inode.metadata.clone();
+        metadata.blocks = inode.pages.len() * (inode.hstate.size() / 512);
+        return Ok(metadata);
+    }
+
+    fn set_metadata(&self, metadata: &Metadata) -> Result<(), SystemError> {
+        let mut inode = self.0.lock();
+        inode.metadata.atime = metadata.atime;
+        inode.metadata.mtime = metadata.mtime;
+        inode.metadata.ctime = metadata.ctime;
+        inode.metadata.btime = metadata.btime;
+        inode.metadata.mode = metadata.mode;
+        inode.metadata.uid = metadata.uid;
+        inode.metadata.gid = metadata.gid;
+        return Ok(());
+    }
+
+    fn resize(&self, len: usize) -> Result<(), SystemError> {
+        return self.set_size(len);
+    }
+
+    fn create_with_data(
+        &self,
+        name: &str,
+        file_type: FileType,
+        mode: ModeType,
+        _data: usize,
+    ) -> Result<Arc<dyn IndexNode>, SystemError> {
+        if file_type != FileType::File && file_type != FileType::Dir {
+            return Err(SystemError::EINVAL);
+        }
+        return Ok(self.new_child(name, file_type, mode)?);
+    }
+
+    fn unlink(&self, name: &str) -> Result<(), SystemError> {
+        let mut inode = self.0.lock();
+        if inode.metadata.file_type != FileType::Dir {
+            return Err(SystemError::ENOTDIR);
+        }
+        if name == "." || name == ".." {
+            return Err(SystemError::ENOTEMPTY);
+        }
+
+        let name = DName::from(name);
+        let to_delete = inode.children.get(&name).ok_or(SystemError::ENOENT)?;
+        if to_delete.0.lock().metadata.file_type == FileType::Dir {
+            return Err(SystemError::EISDIR);
+        }
+        to_delete.0.lock().metadata.nlinks -= 1;
+        inode.children.remove(&name);
+        let now = PosixTimeSpec::now();
+        inode.metadata.mtime = now;
+        inode.metadata.ctime = now;
+        return Ok(());
+    }
+
+    fn rmdir(&self, name: &str) -> Result<(), SystemError> {
+        let name = DName::from(name);
+        let mut inode = self.0.lock();
+        if inode.metadata.file_type != FileType::Dir {
+            return Err(SystemError::ENOTDIR);
+        }
+        let to_delete = inode.children.get(&name).ok_or(SystemError::ENOENT)?;
+        let mut to_delete_guard = to_delete.0.lock();
+        if to_delete_guard.metadata.file_type != FileType::Dir {
+            return Err(SystemError::ENOTDIR);
+        }
+        if !to_delete_guard.children.is_empty() {
+            return Err(SystemError::ENOTEMPTY);
+        }
+
+        to_delete_guard.metadata.nlinks = 0;
+        drop(to_delete_guard);
+        inode.children.remove(&name);
+        inode.metadata.nlinks -= 1;
+        let now = PosixTimeSpec::now();
+        inode.metadata.mtime = now;
+        inode.metadata.ctime = now;
+        return Ok(());
+    }
+
+    fn find(&self, name: &str) -> Result<Arc<dyn IndexNode>, SystemError> {
+        let inode = self.0.lock();
+        if inode.metadata.file_type != FileType::Dir {
+            return Err(SystemError::ENOTDIR);
+        }
+
+        match name {
+            "" | "." => Ok(inode.self_ref.upgrade().ok_or(SystemError::ENOENT)?),
+            ".." => Ok(inode.parent.upgrade().ok_or(SystemError::ENOENT)?),
+            name => Ok(inode
+                .children
+                .get(&DName::from(name))
+                .ok_or(SystemError::ENOENT)?
+                .clone()),
+        }
+    }
+
+    fn get_entry_name(&self, ino: InodeId) -> Result<String, SystemError> {
+        let inode = self.0.lock();
+        if inode.metadata.file_type != FileType::Dir {
+            return Err(SystemError::ENOTDIR);
+        }
+
+        match ino.into() {
+            0 => Ok(String::from(".")),
+            1 => Ok(String::from("..")),
+            ino => inode
+                .children
+                .iter()
+                .find(|(_, v)| v.0.lock().metadata.inode_id.into() == ino)
+                .map(|(k, _)| k.to_string())
+                .ok_or(SystemError::ENOENT),
+        }
+    }
+
+    fn list(&self) -> Result<Vec<String>, SystemError> {
+        let inode = self.0.lock();
+        if inode.metadata.file_type != FileType::Dir {
+            return Err(SystemError::ENOTDIR);
+        }
+
+        let mut keys: Vec<String> = Vec::new();
+        keys.push(String::from("."));
+        keys.push(String::from(".."));
+        keys.extend(inode.children.keys().map(|k| k.to_string()));
+        return Ok(keys);
+    }
+
+    fn dname(&self) -> Result<DName, SystemError> {
+        Ok(self.0.lock().name.clone())
+    }
+
+    fn parent(&self) -> Result<Arc<dyn IndexNode>, SystemError> {
+        self.0
+            .lock()
+            .parent
+            .upgrade()
+            .map(|item| item as Arc<dyn IndexNode>)
+            .ok_or(SystemError::EINVAL)
+    }
+}
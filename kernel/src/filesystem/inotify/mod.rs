@@ -0,0 +1,653 @@
+//! inotify文件变化通知
+//!
+//! 每个inotify实例对应一个[`InotifyInode`]，其上的监视(watch)登记在全局的监视表中。
+//! VFS在文件发生变化时通过[`crate::filesystem::vfs::fsnotify`]中的钩子调用[`inotify_handle_event`]，
+//! 把事件放入所有关心它的实例的事件队列。
+
+use core::{
+    any::Any,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use alloc::{
+    collections::{BTreeMap, LinkedList, VecDeque},
+    string::String,
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+use system_error::SystemError;
+
+use crate::{
+    driver::tty::tty_core::TtyIoctlCmd,
+    filesystem::{
+        epoll::{event_poll::EventPoll, EPollEventType, EPollItem},
+        vfs::{
+            file::{File, FileMode},
+            syscall::ModeType,
+            FilePrivateData, FileSystem, FileType, IndexNode, InodeId, Metadata, PollableInode,
+        },
+    },
+    libs::{
+        casting::DowncastArc,
+        spinlock::{SpinLock, SpinLockGuard},
+        wait_queue::WaitQueue,
+    },
+    process::{ProcessFlags, ProcessManager},
+    sched::SchedMode,
+    syscall::user_access::UserBufferWriter,
+};
+
+pub mod syscall;
+
+/// 每个实例的事件队列的最大长度
+pub const INOTIFY_MAX_QUEUED_EVENTS: usize = 16384;
+/// 每个用户在所有实例中的最大监视数量
+pub const INOTIFY_MAX_USER_WATCHES: usize = 8192;
+
+bitflags! {
+    /// inotify事件及inotify_add_watch的mask
+    pub struct InotifyMask: u32 {
+        /// 文件被读取
+        const IN_ACCESS = 0x00000001;
+        /// 文件被修改
+        const IN_MODIFY = 0x00000002;
+        /// 元数据被修改
+        const IN_ATTRIB = 0x00000004;
+        /// 以可写方式打开的文件被关闭
+        const IN_CLOSE_WRITE = 0x00000008;
+        /// 以只读方式打开的文件被关闭
+        const IN_CLOSE_NOWRITE = 0x00000010;
+        /// 文件被打开
+        const IN_OPEN = 0x00000020;
+        /// 文件被移出被监视的目录
+        const IN_MOVED_FROM = 0x00000040;
+        /// 文件被移入被监视的目录
+        const IN_MOVED_TO = 0x00000080;
+        /// 在被监视的目录中创建了文件
+        const IN_CREATE = 0x00000100;
+        /// 被监视的目录中的文件被删除
+        const IN_DELETE = 0x00000200;
+        /// 被监视的文件自身被删除
+        const IN_DELETE_SELF = 0x00000400;
+        /// 被监视的文件自身被移动
+        const IN_MOVE_SELF = 0x00000800;
+
+        /// 文件系统被卸载
+        const IN_UNMOUNT = 0x00002000;
+        /// 事件队列溢出
+        const IN_Q_OVERFLOW = 0x00004000;
+        /// 监视被移除
+        const IN_IGNORED = 0x00008000;
+
+        const IN_CLOSE = Self::IN_CLOSE_WRITE.bits | Self::IN_CLOSE_NOWRITE.bits;
+        const IN_MOVE = Self::IN_MOVED_FROM.bits | Self::IN_MOVED_TO.bits;
+        const IN_ALL_EVENTS = 0x00000fff;
+
+        /// 只监视目录
+        const IN_ONLYDIR = 0x01000000;
+        /// 不跟随符号链接
+        const IN_DONT_FOLLOW = 0x02000000;
+        /// 文件被unlink后不再产生事件
+        const IN_EXCL_UNLINK = 0x04000000;
+        /// 只创建新的监视，已存在时返回EEXIST
+        const IN_MASK_CREATE = 0x10000000;
+        /// 将mask合并到已存在的监视中
+        const IN_MASK_ADD = 0x20000000;
+        /// 事件的主体是目录
+        const IN_ISDIR = 0x40000000;
+        /// 只产生一次事件
+        const IN_ONESHOT = 0x80000000;
+    }
+}
+
+bitflags! {
+    /// inotify_init1的flags
+    pub struct InotifyInitFlags: u32 {
+        const IN_NONBLOCK = FileMode::O_NONBLOCK.bits();
+        const IN_CLOEXEC = FileMode::O_CLOEXEC.bits();
+    }
+}
+
+/// inotify文件的私有信息
+///
+/// 非阻塞标志以文件的当前模式为准，这样F_SETFL修改O_NONBLOCK后，read能立即生效
+#[derive(Debug, Clone)]
+pub struct InotifyPrivateData {
+    mode: FileMode,
+}
+
+impl InotifyPrivateData {
+    pub fn new(mode: FileMode) -> Self {
+        Self { mode }
+    }
+
+    pub fn set_mode(&mut self, mode: FileMode) {
+        self.mode = mode;
+    }
+}
+
+/// 用户态的`struct inotify_event`（不含变长的name）
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+struct InotifyEventHeader {
+    wd: i32,
+    mask: u32,
+    cookie: u32,
+    len: u32,
+}
+
+const INOTIFY_EVENT_HEADER_SIZE: usize = core::mem::size_of::<InotifyEventHeader>();
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct InotifyEvent {
+    wd: i32,
+    mask: InotifyMask,
+    cookie: u32,
+    name: Option<String>,
+}
+
+impl InotifyEvent {
+    /// name补齐'\0'后的长度，是事件头长度的整数倍
+    fn name_len(&self) -> usize {
+        match &self.name {
+            Some(name) => (name.len() + 1).next_multiple_of(INOTIFY_EVENT_HEADER_SIZE),
+            None => 0,
+        }
+    }
+
+    fn size(&self) -> usize {
+        INOTIFY_EVENT_HEADER_SIZE + self.name_len()
+    }
+
+    fn write_to(&self, buf: &mut [u8]) {
+        let header = InotifyEventHeader {
+            wd: self.wd,
+            mask: self.mask.bits(),
+            cookie: self.cookie,
+            len: self.name_len() as u32,
+        };
+        let header_bytes = unsafe {
+            core::slice::from_raw_parts(
+                &header as *const InotifyEventHeader as *const u8,
+                INOTIFY_EVENT_HEADER_SIZE,
+            )
+        };
+        buf[..INOTIFY_EVENT_HEADER_SIZE].copy_from_slice(header_bytes);
+        let name_buf = &mut buf[INOTIFY_EVENT_HEADER_SIZE..self.size()];
+        name_buf.fill(0);
+        if let Some(name) = &self.name {
+            name_buf[..name.len()].copy_from_slice(name.as_bytes());
+        }
+    }
+}
+
+/// 监视表的键，(dev_id, inode_id)
+pub type InotifyInodeKey = (usize, InodeId);
+
+pub fn inotify_inode_key(inode: &Arc<dyn IndexNode>) -> Result<InotifyInodeKey, SystemError> {
+    let metadata = inode.metadata()?;
+    Ok((metadata.dev_id, metadata.inode_id))
+}
+
+/// 全局监视表：被监视的inode -> (实例, wd)
+static INOTIFY_WATCHES: SpinLock<BTreeMap<InotifyInodeKey, Vec<(Weak<InotifyInode>, i32)>>> =
+    SpinLock::new(BTreeMap::new());
+/// 全局监视数量，为0时VFS的钩子可以直接返回
+static INOTIFY_WATCH_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+/// 当前是否存在任何监视
+#[inline]
+pub fn inotify_has_watches() -> bool {
+    INOTIFY_WATCH_COUNT.load(Ordering::Relaxed) != 0
+}
+
+/// 每个用户持有的监视数量：uid -> 数量
+static INOTIFY_USER_WATCHES: SpinLock<BTreeMap<usize, usize>> = SpinLock::new(BTreeMap::new());
+
+/// 为用户记入一个监视
+///
+/// ## 返回值
+///
+/// - `Err(SystemError::ENOSPC)` 该用户的监视数量已达到上限
+fn user_watches_charge(uid: usize) -> Result<(), SystemError> {
+    let mut counts = INOTIFY_USER_WATCHES.lock();
+    let count = counts.entry(uid).or_insert(0);
+    if *count >= INOTIFY_MAX_USER_WATCHES {
+        return Err(SystemError::ENOSPC);
+    }
+    *count += 1;
+    Ok(())
+}
+
+fn user_watches_uncharge(uid: usize, nr: usize) {
+    let mut counts = INOTIFY_USER_WATCHES.lock();
+    if let Some(count) = counts.get_mut(&uid) {
+        *count = count.saturating_sub(nr);
+        if *count == 0 {
+            counts.remove(&uid);
+        }
+    }
+}
+
+fn registry_add(key: InotifyInodeKey, instance: Weak<InotifyInode>, wd: i32) {
+    INOTIFY_WATCHES
+        .lock()
+        .entry(key)
+        .or_default()
+        .push((instance, wd));
+    INOTIFY_WATCH_COUNT.fetch_add(1, Ordering::Relaxed);
+}
+
+fn registry_remove(key: InotifyInodeKey, instance: *const InotifyInode, wd: i32) {
+    let mut watches = INOTIFY_WATCHES.lock();
+    if let Some(list) = watches.get_mut(&key) {
+        let len = list.len();
+        list.retain(|(inst, w)| !(inst.as_ptr() == instance && *w == wd));
+        INOTIFY_WATCH_COUNT.fetch_sub(len - list.len(), Ordering::Relaxed);
+        if list.is_empty() {
+            watches.remove(&key);
+        }
+    }
+}
+
+/// 将事件分发给所有监视了该inode的inotify实例
+///
+/// ## 参数
+///
+/// - `key` 产生事件的inode
+/// - `mask` 事件类型，可以带有IN_ISDIR
+/// - `cookie` 用于关联IN_MOVED_FROM和IN_MOVED_TO，其余事件为0
+/// - `name` 事件发生在被监视目录中的子项上时，为子项的名字
+pub fn inotify_handle_event(
+    key: InotifyInodeKey,
+    mask: InotifyMask,
+    cookie: u32,
+    name: Option<&str>,
+) {
+    let targets = match INOTIFY_WATCHES.lock().get(&key) {
+        Some(list) => list.clone(),
+        None => return,
+    };
+    for (instance, wd) in targets {
+        if let Some(instance) = instance.upgrade() {
+            instance.handle_event(wd, mask, cookie, name);
+        }
+    }
+}
+
+/// 被监视的inode被删除时，移除其上的所有监视，并向实例发送IN_IGNORED
+pub fn inotify_inode_removed(key: InotifyInodeKey) {
+    let targets = match INOTIFY_WATCHES.lock().get(&key) {
+        Some(list) => list.clone(),
+        None => return,
+    };
+    for (instance, wd) in targets {
+        if let Some(instance) = instance.upgrade() {
+            instance.remove_watch(wd).ok();
+        }
+    }
+}
+
+#[derive(Debug)]
+struct InotifyWatch {
+    key: InotifyInodeKey,
+    mask: InotifyMask,
+}
+
+#[derive(Debug)]
+struct InnerInotify {
+    /// wd -> 监视
+    watches: BTreeMap<i32, InotifyWatch>,
+    /// 下一个分配的wd
+    next_wd: i32,
+    events: VecDeque<InotifyEvent>,
+    /// 队列中所有事件的总长度
+    events_size: usize,
+}
+
+/// inotify实例
+#[derive(Debug)]
+pub struct InotifyInode {
+    inner: SpinLock<InnerInotify>,
+    /// 创建该实例的用户，监视数量记在该用户名下
+    uid: usize,
+    wait_queue: WaitQueue,
+    epitems: SpinLock<LinkedList<Arc<EPollItem>>>,
+    self_ref: Weak<InotifyInode>,
+}
+
+impl InotifyInode {
+    pub fn new() -> Arc<Self> {
+        let uid = ProcessManager::current_pcb().cred().uid.data();
+        Arc::new_cyclic(|self_ref| Self {
+            inner: SpinLock::new(InnerInotify {
+                watches: BTreeMap::new(),
+                next_wd: 1,
+                events: VecDeque::new(),
+                events_size: 0,
+            }),
+            uid,
+            wait_queue: WaitQueue::default(),
+            epitems: SpinLock::new(LinkedList::new()),
+            self_ref: self_ref.clone(),
+        })
+    }
+
+    /// 添加或修改对inode的监视
+    ///
+    /// ## 返回值
+    ///
+    /// - `Ok(i32)` 监视描述符wd
+    /// - `Err(SystemError::EEXIST)` 设置了IN_MASK_CREATE且监视已经存在
+    /// - `Err(SystemError::ENOSPC)` 用户的监视数量超过上限
+    pub fn add_watch(&self, key: InotifyInodeKey, mask: InotifyMask) -> Result<i32, SystemError> {
+        let mut inner = self.inner.lock();
+        let events = mask
+            & (InotifyMask::IN_ALL_EVENTS
+                | InotifyMask::IN_EXCL_UNLINK
+                | InotifyMask::IN_ONESHOT);
+        if let Some((wd, watch)) = inner.watches.iter_mut().find(|(_, w)| w.key == key) {
+            if mask.contains(InotifyMask::IN_MASK_CREATE) {
+                return Err(SystemError::EEXIST);
+            }
+            if mask.contains(InotifyMask::IN_MASK_ADD) {
+                watch.mask |= events;
+            } else {
+                watch.mask = events;
+            }
+            return Ok(*wd);
+        }
+
+        let wd = inner.next_wd;
+        let next_wd = wd.checked_add(1).ok_or(SystemError::ENOSPC)?;
+        user_watches_charge(self.uid)?;
+        inner.next_wd = next_wd;
+        inner.watches.insert(wd, InotifyWatch { key, mask: events });
+        drop(inner);
+
+        registry_add(key, self.self_ref.clone(), wd);
+        Ok(wd)
+    }
+
+    /// 移除监视，并在事件队列中放入IN_IGNORED事件
+    pub fn remove_watch(&self, wd: i32) -> Result<(), SystemError> {
+        let mut inner = self.inner.lock();
+        let watch = inner.watches.remove(&wd).ok_or(SystemError::EINVAL)?;
+        self.queue_event(
+            &mut inner,
+            InotifyEvent {
+                wd,
+                mask: InotifyMask::IN_IGNORED,
+                cookie: 0,
+                name: None,
+            },
+        );
+        drop(inner);
+
+        registry_remove(watch.key, self as *const Self, wd);
+        user_watches_uncharge(self.uid, 1);
+        self.notify_readable();
+        Ok(())
+    }
+
+    fn handle_event(&self, wd: i32, mask: InotifyMask, cookie: u32, name: Option<&str>) {
+        let mut inner = self.inner.lock();
+        let Some(watch) = inner.watches.get(&wd) else {
+            return;
+        };
+        if !watch.mask.intersects(mask & InotifyMask::IN_ALL_EVENTS) {
+            return;
+        }
+        let oneshot = watch.mask.contains(InotifyMask::IN_ONESHOT);
+        self.queue_event(
+            &mut inner,
+            InotifyEvent {
+                wd,
+                mask,
+                cookie,
+                name: name.map(String::from),
+            },
+        );
+        drop(inner);
+
+        if oneshot {
+            self.remove_watch(wd).ok();
+        } else {
+            self.notify_readable();
+        }
+    }
+
+    /// 将事件放入队列，与队尾相同的事件会被合并，队列满时放入一个IN_Q_OVERFLOW事件
+    fn queue_event(&self, inner: &mut SpinLockGuard<InnerInotify>, event: InotifyEvent) {
+        if inner.events.back() == Some(&event) {
+            return;
+        }
+        let event = if inner.events.len() >= INOTIFY_MAX_QUEUED_EVENTS {
+            if inner
+                .events
+                .back()
+                .is_some_and(|e| e.mask == InotifyMask::IN_Q_OVERFLOW)
+            {
+                return;
+            }
+            InotifyEvent {
+                wd: -1,
+                mask: InotifyMask::IN_Q_OVERFLOW,
+                cookie: 0,
+                name: None,
+            }
+        } else {
+            event
+        };
+        inner.events_size += event.size();
+        inner.events.push_back(event);
+    }
+
+    fn notify_readable(&self) {
+        self.wait_queue.wakeup_all(None);
+        let _ = EventPoll::wakeup_epoll(
+            &self.epitems,
+            EPollEventType::EPOLLIN | EPollEventType::EPOLLRDNORM,
+        );
+    }
+
+    fn readable(&self) -> bool {
+        !self.inner.lock().events.is_empty()
+    }
+}
+
+impl Drop for InotifyInode {
+    fn drop(&mut self) {
+        let watches = core::mem::take(&mut self.inner.lock().watches);
+        user_watches_uncharge(self.uid, watches.len());
+        for (wd, watch) in watches {
+            registry_remove(watch.key, self as *const Self, wd);
+        }
+    }
+}
+
+impl PollableInode for InotifyInode {
+    fn poll(&self, _private_data: &FilePrivateData) -> Result<usize, SystemError> {
+        let mut events = EPollEventType::empty();
+        if self.readable() {
+            events |= EPollEventType::EPOLLIN | EPollEventType::EPOLLRDNORM;
+        }
+        Ok(events.bits() as usize)
+    }
+
+    fn add_epitem(
+        &self,
+        epitem: Arc<EPollItem>,
+        _private_data: &FilePrivateData,
+    ) -> Result<(), SystemError> {
+        self.epitems.lock().push_back(epitem);
+        Ok(())
+    }
+
+    fn remove_epitem(
+        &self,
+        epitem: &Arc<EPollItem>,
+        _private_data: &FilePrivateData,
+    ) -> Result<(), SystemError> {
+        let mut guard = self.epitems.lock();
+        let len = guard.len();
+        guard.retain(|x| !Arc::ptr_eq(x, epitem));
+        if len != guard.len() {
+            return Ok(());
+        }
+        Err(SystemError::ENOENT)
+    }
+}
+
+impl IndexNode for InotifyInode {
+    fn open(
+        &self,
+        mut data: SpinLockGuard<FilePrivateData>,
+        mode: &FileMode,
+    ) -> Result<(), SystemError> {
+        *data = FilePrivateData::Inotify(InotifyPrivateData::new(*mode));
+        Ok(())
+    }
+
+    fn close(&self, _data: SpinLockGuard<FilePrivateData>) -> Result<(), SystemError> {
+        Ok(())
+    }
+
+    /// 读取事件，每次读取整数个事件
+    ///
+    /// 缓冲区连一个事件都放不下时返回EINVAL；队列为空时，文件处于非阻塞模式则返回EAGAIN，否则等待事件到来。
+    fn read_at(
+        &self,
+        _offset: usize,
+        len: usize,
+        buf: &mut [u8],
+        data: SpinLockGuard<FilePrivateData>,
+    ) -> Result<usize, SystemError> {
+        let nonblock = match &*data {
+            FilePrivateData::Inotify(pdata) => pdata.mode.contains(FileMode::O_NONBLOCK),
+            _ => false,
+        };
+        drop(data);
+        let len = len.min(buf.len());
+        let mut inner = self.inner.lock();
+        while inner.events.is_empty() {
+            if nonblock {
+                return Err(SystemError::EAGAIN_OR_EWOULDBLOCK);
+            }
+            drop(inner);
+
+            if ProcessManager::current_pcb().has_pending_signal_fast() {
+                return Err(SystemError::ERESTARTSYS);
+            }
+            let r = wq_wait_event_interruptible!(self.wait_queue, self.readable(), {});
+            if r.is_err() {
+                ProcessManager::current_pcb()
+                    .flags()
+                    .insert(ProcessFlags::HAS_PENDING_SIGNAL);
+                return Err(SystemError::ERESTARTSYS);
+            }
+            inner = self.inner.lock();
+        }
+
+        let mut copied = 0;
+        while let Some(event) = inner.events.front() {
+            let size = event.size();
+            if copied + size > len {
+                break;
+            }
+            event.write_to(&mut buf[copied..copied + size]);
+            copied += size;
+            inner.events_size -= size;
+            inner.events.pop_front();
+        }
+        if copied == 0 {
+            return Err(SystemError::EINVAL);
+        }
+        Ok(copied)
+    }
+
+    fn write_at(
+        &self,
+        _offset: usize,
+        _len: usize,
+        _buf: &[u8],
+        _data: SpinLockGuard<FilePrivateData>,
+    ) -> Result<usize, SystemError> {
+        Err(SystemError::EINVAL)
+    }
+
+    fn ioctl(
+        &self,
+        cmd: u32,
+        data: usize,
+        _private_data: &FilePrivateData,
+    ) -> Result<usize, SystemError> {
+        match cmd {
+            TtyIoctlCmd::FIONREAD => {
+                let size = self.inner.lock().events_size;
+                let mut writer =
+                    UserBufferWriter::new(data as *mut i32, core::mem::size_of::<i32>(), true)?;
+                writer.copy_one_to_user::<i32>(&(size as i32), 0)?;
+                Ok(0)
+            }
+            _ => Err(SystemError::ENOTTY),
+        }
+    }
+
+    fn metadata(&self) -> Result<Metadata, SystemError> {
+        let meta = Metadata {
+            mode: ModeType::from_bits_truncate(0o600),
+            file_type: FileType::File,
+            ..Default::default()
+        };
+        Ok(meta)
+    }
+
+    fn resize(&self, _len: usize) -> Result<(), SystemError> {
+        Ok(())
+    }
+
+    fn fs(&self) -> Arc<dyn FileSystem> {
+        panic!("Inotify does not have a filesystem")
+    }
+
+    fn as_any_ref(&self) -> &dyn Any {
+        self
+    }
+
+    fn list(&self) -> Result<Vec<String>, SystemError> {
+        Err(SystemError::ENOTDIR)
+    }
+
+    fn as_pollable_inode(&self) -> Result<&dyn PollableInode, SystemError> {
+        Ok(self)
+    }
+}
+
+/// 创建inotify实例，并返回其文件描述符
+pub fn do_inotify_init(flags: InotifyInitFlags) -> Result<usize, SystemError> {
+    let inode = InotifyInode::new();
+    let mut mode = FileMode::O_RDONLY;
+    if flags.contains(InotifyInitFlags::IN_NONBLOCK) {
+        mode |= FileMode::O_NONBLOCK;
+    }
+    if flags.contains(InotifyInitFlags::IN_CLOEXEC) {
+        mode |= FileMode::O_CLOEXEC;
+    }
+    let file = File::new(inode, mode)?;
+    let binding = ProcessManager::current_pcb().fd_table();
+    let mut fd_table_guard = binding.write();
+    fd_table_guard.alloc_fd(file, None).map(|fd| fd as usize)
+}
+
+/// 根据文件描述符获取inotify实例
+pub fn inotify_from_fd(fd: i32) -> Result<Arc<InotifyInode>, SystemError> {
+    let binding = ProcessManager::current_pcb().fd_table();
+    let file = binding
+        .read()
+        .get_file_by_fd(fd)
+        .ok_or(SystemError::EBADF)?;
+    file.inode()
+        .downcast_arc::<InotifyInode>()
+        .ok_or(SystemError::EINVAL)
+}
@@ -0,0 +1,5 @@
+#[cfg(target_arch = "x86_64")]
+mod sys_inotify_init;
+mod sys_inotify_add_watch;
+mod sys_inotify_init1;
+mod sys_inotify_rm_watch;
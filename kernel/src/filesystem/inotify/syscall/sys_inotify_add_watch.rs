@@ -0,0 +1,86 @@
+//! System call handler for inotify_add_watch.
+
+use alloc::string::ToString;
+
+use crate::arch::syscall::nr::SYS_INOTIFY_ADD_WATCH;
+use crate::filesystem::inotify::{inotify_from_fd, inotify_inode_key, InotifyMask};
+use crate::filesystem::vfs::permission::{inode_permission, PermissionMask};
+use crate::filesystem::vfs::{
+    fcntl::AtFlags, utils::user_path_at, FileType, MAX_PATHLEN, VFS_MAX_FOLLOW_SYMLINK_TIMES,
+};
+use crate::process::ProcessManager;
+use crate::syscall::table::FormattedSyscallParam;
+use crate::syscall::table::Syscall;
+use crate::syscall::user_access::check_and_clone_cstr;
+use alloc::vec::Vec;
+use system_error::SystemError;
+
+pub struct SysInotifyAddWatchHandle;
+
+impl Syscall for SysInotifyAddWatchHandle {
+    fn num_args(&self) -> usize {
+        3
+    }
+
+    /// 为`pathname`指向的文件添加监视，若已经存在则修改其mask
+    ///
+    /// ## 返回值
+    ///
+    /// - `Ok(usize)` 监视描述符wd
+    fn handle(&self, args: &[usize], _from_user: bool) -> Result<usize, SystemError> {
+        let mask = InotifyMask::from_bits_truncate(Self::mask(args));
+        if !mask.intersects(InotifyMask::IN_ALL_EVENTS) {
+            return Err(SystemError::EINVAL);
+        }
+        if mask.contains(InotifyMask::IN_MASK_ADD | InotifyMask::IN_MASK_CREATE) {
+            return Err(SystemError::EINVAL);
+        }
+
+        let inotify = inotify_from_fd(Self::fd(args))?;
+
+        let path = check_and_clone_cstr(Self::pathname(args), Some(MAX_PATHLEN))?
+            .into_string()
+            .map_err(|_| SystemError::EINVAL)?;
+        let pcb = ProcessManager::current_pcb();
+        let (begin_inode, remain_path) = user_path_at(&pcb, AtFlags::AT_FDCWD.bits(), &path)?;
+        let inode = begin_inode.lookup_follow_symlink2(
+            &remain_path,
+            VFS_MAX_FOLLOW_SYMLINK_TIMES,
+            !mask.contains(InotifyMask::IN_DONT_FOLLOW),
+        )?;
+
+        if mask.contains(InotifyMask::IN_ONLYDIR) && inode.metadata()?.file_type != FileType::Dir
+        {
+            return Err(SystemError::ENOTDIR);
+        }
+        // 需要有文件的读权限
+        inode_permission(&inode, PermissionMask::MAY_READ)?;
+
+        let wd = inotify.add_watch(inotify_inode_key(&inode)?, mask)?;
+        Ok(wd as usize)
+    }
+
+    fn entry_format(&self, args: &[usize]) -> Vec<FormattedSyscallParam> {
+        vec![
+            FormattedSyscallParam::new("fd", Self::fd(args).to_string()),
+            FormattedSyscallParam::new("pathname", format!("{:#x}", Self::pathname(args) as usize)),
+            FormattedSyscallParam::new("mask", format!("{:#x}", Self::mask(args))),
+        ]
+    }
+}
+
+impl SysInotifyAddWatchHandle {
+    fn fd(args: &[usize]) -> i32 {
+        args[0] as i32
+    }
+
+    fn pathname(args: &[usize]) -> *const u8 {
+        args[1] as *const u8
+    }
+
+    fn mask(args: &[usize]) -> u32 {
+        args[2] as u32
+    }
+}
+
+syscall_table_macros::declare_syscall!(SYS_INOTIFY_ADD_WATCH, SysInotifyAddWatchHandle);
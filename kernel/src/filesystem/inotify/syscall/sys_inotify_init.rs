@@ -0,0 +1,26 @@
+//! System call handler for inotify_init.
+
+use crate::arch::syscall::nr::SYS_INOTIFY_INIT;
+use crate::filesystem::inotify::{do_inotify_init, InotifyInitFlags};
+use crate::syscall::table::FormattedSyscallParam;
+use crate::syscall::table::Syscall;
+use alloc::vec::Vec;
+use system_error::SystemError;
+
+pub struct SysInotifyInitHandle;
+
+impl Syscall for SysInotifyInitHandle {
+    fn num_args(&self) -> usize {
+        0
+    }
+
+    fn handle(&self, _args: &[usize], _from_user: bool) -> Result<usize, SystemError> {
+        do_inotify_init(InotifyInitFlags::empty())
+    }
+
+    fn entry_format(&self, _args: &[usize]) -> Vec<FormattedSyscallParam> {
+        vec![]
+    }
+}
+
+syscall_table_macros::declare_syscall!(SYS_INOTIFY_INIT, SysInotifyInitHandle);
@@ -0,0 +1,36 @@
+//! System call handler for inotify_init1.
+
+use crate::arch::syscall::nr::SYS_INOTIFY_INIT1;
+use crate::filesystem::inotify::{do_inotify_init, InotifyInitFlags};
+use crate::syscall::table::FormattedSyscallParam;
+use crate::syscall::table::Syscall;
+use alloc::vec::Vec;
+use system_error::SystemError;
+
+pub struct SysInotifyInit1Handle;
+
+impl Syscall for SysInotifyInit1Handle {
+    fn num_args(&self) -> usize {
+        1
+    }
+
+    fn handle(&self, args: &[usize], _from_user: bool) -> Result<usize, SystemError> {
+        let flags = InotifyInitFlags::from_bits(Self::flags(args)).ok_or(SystemError::EINVAL)?;
+        do_inotify_init(flags)
+    }
+
+    fn entry_format(&self, args: &[usize]) -> Vec<FormattedSyscallParam> {
+        vec![FormattedSyscallParam::new(
+            "flags",
+            format!("{:#x}", Self::flags(args)),
+        )]
+    }
+}
+
+impl SysInotifyInit1Handle {
+    fn flags(args: &[usize]) -> u32 {
+        args[0] as u32
+    }
+}
+
+syscall_table_macros::declare_syscall!(SYS_INOTIFY_INIT1, SysInotifyInit1Handle);
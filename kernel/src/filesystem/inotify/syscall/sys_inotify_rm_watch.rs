@@ -0,0 +1,44 @@
+//! System call handler for inotify_rm_watch.
+
+use alloc::string::ToString;
+
+use crate::arch::syscall::nr::SYS_INOTIFY_RM_WATCH;
+use crate::filesystem::inotify::inotify_from_fd;
+use crate::syscall::table::FormattedSyscallParam;
+use crate::syscall::table::Syscall;
+use alloc::vec::Vec;
+use system_error::SystemError;
+
+pub struct SysInotifyRmWatchHandle;
+
+impl Syscall for SysInotifyRmWatchHandle {
+    fn num_args(&self) -> usize {
+        2
+    }
+
+    /// 移除inotify实例上的监视，实例会收到一个IN_IGNORED事件
+    fn handle(&self, args: &[usize], _from_user: bool) -> Result<usize, SystemError> {
+        let inotify = inotify_from_fd(Self::fd(args))?;
+        inotify.remove_watch(Self::wd(args))?;
+        Ok(0)
+    }
+
+    fn entry_format(&self, args: &[usize]) -> Vec<FormattedSyscallParam> {
+        vec![
+            FormattedSyscallParam::new("fd", Self::fd(args).to_string()),
+            FormattedSyscallParam::new("wd", Self::wd(args).to_string()),
+        ]
+    }
+}
+
+impl SysInotifyRmWatchHandle {
+    fn fd(args: &[usize]) -> i32 {
+        args[0] as i32
+    }
+
+    fn wd(args: &[usize]) -> i32 {
+        args[1] as i32
+    }
+}
+
+syscall_table_macros::declare_syscall!(SYS_INOTIFY_RM_WATCH, SysInotifyRmWatchHandle);
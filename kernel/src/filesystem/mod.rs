@@ -1,8 +1,11 @@
+pub mod binfmt_misc;
 pub mod devfs;
 pub mod devpts;
 pub mod epoll;
 pub mod eventfd;
 pub mod fat;
+pub mod hugetlbfs;
+pub mod inotify;
 pub mod kernfs;
 pub mod mbr;
 pub mod overlayfs;
@@ -11,4 +14,5 @@ pub mod poll;
 pub mod procfs;
 pub mod ramfs;
 pub mod sysfs;
+pub mod tmpfs;
 pub mod vfs;
@@ -1,6 +1,6 @@
-use super::OvlInode;
+use super::{OvlInode, OVL_XATTR_PREFIX};
 use crate::{
-    filesystem::vfs::{IndexNode, Metadata},
+    filesystem::vfs::{xattr::XattrFlags, FileType, IndexNode, Metadata},
     libs::spinlock::SpinLock,
 };
 use alloc::sync::Arc;
@@ -18,11 +18,15 @@ impl OvlInode {
         let metadata = lower_inode.metadata()?;
         let new_upper_inode = self.create_upper_inode(metadata.clone())?;
 
-        let mut buffer = vec![0u8; metadata.size as usize];
-        let lock = SpinLock::new(crate::filesystem::vfs::FilePrivateData::Unused);
-        lower_inode.read_at(0, metadata.size as usize, &mut buffer, lock.lock())?;
+        if metadata.file_type == FileType::File {
+            let mut buffer = vec![0u8; metadata.size as usize];
+            let lock = SpinLock::new(crate::filesystem::vfs::FilePrivateData::Unused);
+            lower_inode.read_at(0, metadata.size as usize, &mut buffer, lock.lock())?;
 
-        new_upper_inode.write_at(0, metadata.size as usize, &buffer, lock.lock())?;
+            new_upper_inode.write_at(0, metadata.size as usize, &buffer, lock.lock())?;
+        }
+
+        Self::copy_up_xattrs(lower_inode, &new_upper_inode)?;
 
         *upper_inode = Some(new_upper_inode);
 
@@ -30,12 +34,34 @@ impl OvlInode {
     }
 
     fn create_upper_inode(&self, metadata: Metadata) -> Result<Arc<dyn IndexNode>, SystemError> {
-        let upper_inode = self.upper_inode.lock();
-        let upper_root_inode = upper_inode
-            .as_ref()
-            .ok_or(SystemError::ENOSYS)?
-            .fs()
-            .root_inode();
+        let upper_root_inode = self.fs.upgrade().ok_or(SystemError::EROFS)?.ovl_upper_mnt();
         upper_root_inode.create_with_data(&self.dname()?.0, metadata.file_type, metadata.mode, 0)
     }
+
+    /// 将下层inode的扩展属性拷贝到上层，overlayfs内部使用的`trusted.overlay.*`属性除外
+    fn copy_up_xattrs(
+        lower_inode: &Arc<dyn IndexNode>,
+        upper_inode: &Arc<dyn IndexNode>,
+    ) -> Result<(), SystemError> {
+        let size = match lower_inode.listxattr(&mut []) {
+            Ok(size) => size,
+            // 下层文件系统不支持扩展属性
+            Err(SystemError::EOPNOTSUPP_OR_ENOTSUP) => return Ok(()),
+            Err(e) => return Err(e),
+        };
+        let mut names = vec![0u8; size];
+        let size = lower_inode.listxattr(&mut names)?;
+        names.truncate(size);
+
+        for name in names.split(|c| *c == 0).filter(|n| !n.is_empty()) {
+            let name = core::str::from_utf8(name).map_err(|_| SystemError::EINVAL)?;
+            if name.starts_with(OVL_XATTR_PREFIX) {
+                continue;
+            }
+            let mut value = vec![0u8; lower_inode.getxattr(name, &mut [])?];
+            let len = lower_inode.getxattr(name, &mut value)?;
+            upper_inode.setxattr(name, &value[..len], XattrFlags::empty())?;
+        }
+        Ok(())
+    }
 }
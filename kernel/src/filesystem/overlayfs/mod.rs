@@ -7,6 +7,7 @@ use super::vfs::{self, FileSystem, FileType, FsInfo, IndexNode, Metadata, SuperB
 use super::vfs::{FSMAKER, ROOT_INODE};
 use crate::driver::base::device::device_number::DeviceNumber;
 use crate::driver::base::device::device_number::Major;
+use crate::filesystem::vfs::xattr::XattrFlags;
 use crate::filesystem::vfs::{FileSystemMaker, FileSystemMakerData};
 use crate::libs::spinlock::SpinLock;
 use alloc::string::String;
@@ -20,6 +21,12 @@ use system_error::SystemError;
 const WHITEOUT_MODE: u64 = 0o020000 | 0o600; // whiteout字符设备文件模式与权限
 const WHITEOUT_DEV: DeviceNumber = DeviceNumber::new(Major::UNNAMED_MAJOR, 0); // Whiteout 文件设备号
 const WHITEOUT_FLAG: u64 = 0x1;
+/// overlayfs内部使用的扩展属性前缀，这些属性对用户不可见
+const OVL_XATTR_PREFIX: &str = "trusted.overlay.";
+/// 标记上层目录为不透明目录，查找时不再访问下层的同名目录
+const OVL_XATTR_OPAQUE: &str = "trusted.overlay.opaque";
+/// 标记上层文件为whiteout，用于上层文件系统不支持创建字符设备的情况
+const OVL_XATTR_WHITEOUT: &str = "trusted.overlay.whiteout";
 
 #[distributed_slice(FSMAKER)]
 static OVERLAYFSMAKER: FileSystemMaker = FileSystemMaker::new(
@@ -219,7 +226,18 @@ impl OvlInode {
         let whiteout_mode = vfs::syscall::ModeType::S_IFCHR;
         let mut upper_inode = self.upper_inode.lock();
         if let Some(ref upper_inode) = *upper_inode {
-            upper_inode.mknod(name, whiteout_mode, WHITEOUT_DEV)?;
+            if upper_inode
+                .mknod(name, whiteout_mode, WHITEOUT_DEV)
+                .is_err()
+            {
+                // 上层文件系统不支持字符设备时，使用带有扩展属性标记的空文件作为whiteout
+                let whiteout = upper_inode.create(
+                    name,
+                    FileType::File,
+                    vfs::syscall::ModeType::from_bits_truncate(0o600),
+                )?;
+                whiteout.setxattr(OVL_XATTR_WHITEOUT, b"y", XattrFlags::empty())?;
+            }
         } else {
             let new_inode = self
                 .fs
@@ -246,10 +264,71 @@ impl OvlInode {
                 if let Some(ovl_inode) = inode.as_any_ref().downcast_ref::<OvlInode>() {
                     return ovl_inode.is_whiteout();
                 }
+                return ovl_is_whiteout(&inode);
             }
         }
         false
     }
+
+    /// 当前目录的上层目录是否为不透明目录
+    fn is_opaque(&self) -> bool {
+        match *self.upper_inode.lock() {
+            Some(ref upper_inode) => ovl_is_opaque_dir(upper_inode),
+            None => false,
+        }
+    }
+
+    /// 删除目录项，下层存在同名目录项时在上层创建whiteout将其隐藏
+    fn remove_entry(&self, name: &str, is_dir: bool) -> Result<(), SystemError> {
+        if self.has_whiteout(name) {
+            return Err(SystemError::ENOENT);
+        }
+        let upper_inode = self.upper_inode.lock().clone();
+        let in_upper = upper_inode
+            .as_ref()
+            .is_some_and(|upper| upper.find(name).is_ok());
+        let in_lower = self.lower_exists(name) && !self.is_opaque();
+        if !in_upper && !in_lower {
+            return Err(SystemError::ENOENT);
+        }
+
+        if let Some(upper_inode) = upper_inode.filter(|_| in_upper) {
+            if is_dir {
+                upper_inode.rmdir(name)?;
+            } else {
+                upper_inode.unlink(name)?;
+            }
+        }
+        if in_lower {
+            self.create_whiteout(name)?;
+        }
+        Ok(())
+    }
+
+    /// 下层中是否存在名为`name`的目录项
+    fn lower_exists(&self, name: &str) -> bool {
+        self.lower_inode
+            .as_ref()
+            .is_some_and(|lower| lower.find(name).is_ok())
+    }
+}
+
+/// 判断上层的inode是否为whiteout
+///
+/// whiteout为设备号是0/0的字符设备，或者带有`trusted.overlay.whiteout`属性的文件
+fn ovl_is_whiteout(inode: &Arc<dyn IndexNode>) -> bool {
+    if let Ok(metadata) = inode.metadata() {
+        if metadata.file_type == FileType::CharDevice && metadata.raw_dev == WHITEOUT_DEV {
+            return true;
+        }
+    }
+    inode.getxattr(OVL_XATTR_WHITEOUT, &mut []).is_ok()
+}
+
+/// 判断上层的目录是否带有`trusted.overlay.opaque=y`属性
+fn ovl_is_opaque_dir(inode: &Arc<dyn IndexNode>) -> bool {
+    let mut buf = [0u8; 1];
+    matches!(inode.getxattr(OVL_XATTR_OPAQUE, &mut buf), Ok(1)) && buf[0] == b'y'
 }
 
 impl IndexNode for OvlInode {
@@ -309,10 +388,21 @@ impl IndexNode for OvlInode {
 
     fn list(&self) -> Result<Vec<String>, system_error::SystemError> {
         let mut entries: Vec<String> = Vec::new();
-        let upper_inode = self.upper_inode.lock();
-        if let Some(ref upper_inode) = *upper_inode {
-            let upper_entries = upper_inode.list()?;
-            entries.extend(upper_entries);
+        let upper_inode = self.upper_inode.lock().clone();
+        let mut opaque = false;
+        if let Some(ref upper_inode) = upper_inode {
+            for entry in upper_inode.list()? {
+                let is_whiteout = upper_inode
+                    .find(&entry)
+                    .is_ok_and(|inode| ovl_is_whiteout(&inode));
+                if !is_whiteout {
+                    entries.push(entry);
+                }
+            }
+            opaque = ovl_is_opaque_dir(upper_inode);
+        }
+        if opaque {
+            return Ok(entries);
         }
         if let Some(lower_inode) = &self.lower_inode {
             let lower_entries = lower_inode.list()?;
@@ -331,45 +421,31 @@ impl IndexNode for OvlInode {
         name: &str,
         mode: vfs::syscall::ModeType,
     ) -> Result<Arc<dyn IndexNode>, system_error::SystemError> {
-        if let Some(ref upper_inode) = *self.upper_inode.lock() {
-            upper_inode.mkdir(name, mode)
+        let upper_inode = self.upper_inode.lock().clone();
+        if let Some(ref upper_inode) = upper_inode {
+            // 覆盖whiteout或者下层同名目录时，新目录需要标记为不透明，以隐藏下层的内容
+            let whiteout = upper_inode
+                .find(name)
+                .is_ok_and(|inode| ovl_is_whiteout(&inode));
+            if whiteout {
+                upper_inode.unlink(name)?;
+            }
+            let dir = upper_inode.mkdir(name, mode)?;
+            if whiteout || self.lower_exists(name) {
+                dir.setxattr(OVL_XATTR_OPAQUE, b"y", XattrFlags::empty())?;
+            }
+            Ok(dir)
         } else {
             Err(SystemError::EROFS)
         }
     }
 
     fn rmdir(&self, name: &str) -> Result<(), SystemError> {
-        let upper_inode = self.upper_inode.lock();
-        if let Some(ref upper_inode) = *upper_inode {
-            upper_inode.rmdir(name)?;
-        } else if let Some(lower_inode) = &self.lower_inode {
-            if lower_inode.find(name).is_ok() {
-                self.create_whiteout(name)?;
-            } else {
-                return Err(SystemError::ENOENT);
-            }
-        } else {
-            return Err(SystemError::ENOENT);
-        }
-
-        Ok(())
+        self.remove_entry(name, true)
     }
 
     fn unlink(&self, name: &str) -> Result<(), SystemError> {
-        let upper_inode = self.upper_inode.lock();
-        if let Some(ref upper_inode) = *upper_inode {
-            upper_inode.unlink(name)?;
-        } else if let Some(lower_inode) = &self.lower_inode {
-            if lower_inode.find(name).is_ok() {
-                self.create_whiteout(name)?;
-            } else {
-                return Err(SystemError::ENOENT);
-            }
-        } else {
-            return Err(SystemError::ENOENT);
-        }
-
-        Ok(())
+        self.remove_entry(name, false)
     }
 
     fn link(
@@ -398,14 +474,17 @@ impl IndexNode for OvlInode {
     }
 
     fn find(&self, name: &str) -> Result<Arc<dyn IndexNode>, system_error::SystemError> {
-        let upper_inode = self.upper_inode.lock();
-        if let Some(ref upper) = *upper_inode {
+        let upper_inode = self.upper_inode.lock().clone();
+        if let Some(ref upper) = upper_inode {
             if let Ok(inode) = upper.find(name) {
+                if ovl_is_whiteout(&inode) {
+                    return Err(SystemError::ENOENT);
+                }
                 return Ok(inode);
             }
-        }
-        if self.has_whiteout(name) {
-            return Err(SystemError::ENOENT);
+            if ovl_is_opaque_dir(upper) {
+                return Err(SystemError::ENOENT);
+            }
         }
 
         if let Some(lower) = &self.lower_inode {
@@ -430,4 +509,61 @@ impl IndexNode for OvlInode {
             Err(SystemError::EROFS)
         }
     }
+
+    fn getxattr(&self, name: &str, buf: &mut [u8]) -> Result<usize, SystemError> {
+        if name.starts_with(OVL_XATTR_PREFIX) {
+            return Err(SystemError::EOPNOTSUPP_OR_ENOTSUP);
+        }
+        if let Some(ref upper_inode) = *self.upper_inode.lock() {
+            return upper_inode.getxattr(name, buf);
+        }
+        if let Some(ref lower_inode) = self.lower_inode {
+            return lower_inode.getxattr(name, buf);
+        }
+        Err(SystemError::ENODATA)
+    }
+
+    fn setxattr(&self, name: &str, value: &[u8], flags: XattrFlags) -> Result<(), SystemError> {
+        if name.starts_with(OVL_XATTR_PREFIX) {
+            return Err(SystemError::EOPNOTSUPP_OR_ENOTSUP);
+        }
+        self.copy_up()?;
+        if let Some(ref upper_inode) = *self.upper_inode.lock() {
+            return upper_inode.setxattr(name, value, flags);
+        }
+        Err(SystemError::EROFS)
+    }
+
+    fn listxattr(&self, buf: &mut [u8]) -> Result<usize, SystemError> {
+        let inode = match *self.upper_inode.lock() {
+            Some(ref upper_inode) => upper_inode.clone(),
+            None => self.lower_inode.clone().ok_or(SystemError::ENODATA)?,
+        };
+        let size = inode.listxattr(&mut [])?;
+        let mut names = vec![0u8; size];
+        let size = inode.listxattr(&mut names)?;
+        names.truncate(size);
+
+        // 隐藏overlayfs内部使用的属性
+        let mut result = Vec::with_capacity(names.len());
+        for name in names.split(|c| *c == 0).filter(|n| !n.is_empty()) {
+            if name.starts_with(OVL_XATTR_PREFIX.as_bytes()) {
+                continue;
+            }
+            result.extend_from_slice(name);
+            result.push(0);
+        }
+        vfs::xattr::copy_to_buf(&result, buf)
+    }
+
+    fn removexattr(&self, name: &str) -> Result<(), SystemError> {
+        if name.starts_with(OVL_XATTR_PREFIX) {
+            return Err(SystemError::EOPNOTSUPP_OR_ENOTSUP);
+        }
+        self.copy_up()?;
+        if let Some(ref upper_inode) = *self.upper_inode.lock() {
+            return upper_inode.removexattr(name);
+        }
+        Err(SystemError::EROFS)
+    }
 }
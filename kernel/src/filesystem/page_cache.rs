@@ -25,12 +25,79 @@ use crate::{
 use crate::{libs::align::page_align_up, mm::page::PageType};
 
 static PAGE_CACHE_ID: AtomicUsize = AtomicUsize::new(0);
+/// 共享内存(tmpfs等)页缓存当前占用的页面数
+static NR_SHMEM_PAGES: AtomicUsize = AtomicUsize::new(0);
+/// 预读时单次从磁盘读取的最大页面数
+const READAHEAD_MAX_PAGES: usize = 32;
+
+/// 获取共享内存页缓存当前占用的页面数，用于/proc/meminfo中的Shmem统计
+pub fn shmem_pages() -> usize {
+    NR_SHMEM_PAGES.load(Ordering::Relaxed)
+}
+
+/// 共享内存页缓存的容量配额
+///
+/// 页缓存中每新建一个页面申请一页配额，移除页面时归还，因此文件空洞不占用配额
+#[derive(Debug)]
+pub struct ShmemQuota {
+    /// 容量上限(页)，0表示不限制
+    max_pages: usize,
+    /// 已使用的页数
+    used_pages: AtomicUsize,
+}
+
+impl ShmemQuota {
+    pub fn new(max_pages: usize) -> Arc<Self> {
+        Arc::new(Self {
+            max_pages,
+            used_pages: AtomicUsize::new(0),
+        })
+    }
+
+    #[inline]
+    pub fn max_pages(&self) -> usize {
+        self.max_pages
+    }
+
+    #[inline]
+    pub fn used_pages(&self) -> usize {
+        self.used_pages.load(Ordering::Relaxed)
+    }
+
+    /// 申请`count`页配额
+    ///
+    /// ## 返回值
+    ///
+    /// - `Err(SystemError::ENOSPC)` 超出了容量上限
+    fn charge(&self, count: usize) -> Result<(), SystemError> {
+        self.used_pages
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |used| {
+                let new_used = used.checked_add(count)?;
+                if self.max_pages != 0 && new_used > self.max_pages {
+                    None
+                } else {
+                    Some(new_used)
+                }
+            })
+            .map(|_| ())
+            .map_err(|_| SystemError::ENOSPC)
+    }
+
+    fn uncharge(&self, count: usize) {
+        if count != 0 {
+            self.used_pages.fetch_sub(count, Ordering::SeqCst);
+        }
+    }
+}
+
 /// 页面缓存
 #[derive(Debug)]
 pub struct PageCache {
     id: usize,
     inner: SpinLock<InnerPageCache>,
     inode: Lazy<Weak<dyn IndexNode>>,
+    /// 页缓存中的页面数量，可以在不持有页缓存锁的情况下读取
+    nr_pages: Arc<AtomicUsize>,
 }
 
 #[derive(Debug)]
@@ -39,19 +106,45 @@ pub struct InnerPageCache {
     id: usize,
     pages: HashMap<usize, Arc<Page>>,
     page_cache_ref: Weak<PageCache>,
+    /// 新建页面时使用的页面标志
+    page_flags: PageFlags,
+    /// 共享内存页缓存的容量配额
+    quota: Option<Arc<ShmemQuota>>,
+    /// 与PageCache共享的页面计数
+    nr_pages: Arc<AtomicUsize>,
 }
 
 impl InnerPageCache {
-    pub fn new(page_cache_ref: Weak<PageCache>, id: usize) -> InnerPageCache {
+    pub fn new(
+        page_cache_ref: Weak<PageCache>,
+        id: usize,
+        page_flags: PageFlags,
+        quota: Option<Arc<ShmemQuota>>,
+        nr_pages: Arc<AtomicUsize>,
+    ) -> InnerPageCache {
         Self {
             id,
             pages: HashMap::new(),
             page_cache_ref,
+            page_flags,
+            quota,
+            nr_pages,
         }
     }
 
+    /// 当前页缓存是否为共享内存页缓存
+    #[inline]
+    pub fn is_shmem(&self) -> bool {
+        self.page_flags.contains(PageFlags::PG_SWAPBACKED)
+    }
+
     pub fn add_page(&mut self, offset: usize, page: &Arc<Page>) {
-        self.pages.insert(offset, page.clone());
+        if self.pages.insert(offset, page.clone()).is_none() {
+            self.nr_pages.fetch_add(1, Ordering::Relaxed);
+            if self.is_shmem() {
+                NR_SHMEM_PAGES.fetch_add(1, Ordering::Relaxed);
+            }
+        }
     }
 
     pub fn get_page(&self, offset: usize) -> Option<Arc<Page>> {
@@ -59,7 +152,21 @@ impl InnerPageCache {
     }
 
     pub fn remove_page(&mut self, offset: usize) -> Option<Arc<Page>> {
-        self.pages.remove(&offset)
+        let page = self.pages.remove(&offset);
+        if page.is_some() {
+            self.nr_pages.fetch_sub(1, Ordering::Relaxed);
+            if self.is_shmem() {
+                NR_SHMEM_PAGES.fetch_sub(1, Ordering::Relaxed);
+                self.uncharge(1);
+            }
+        }
+        page
+    }
+
+    fn uncharge(&self, count: usize) {
+        if let Some(quota) = self.quota.as_ref() {
+            quota.uncharge(count);
+        }
     }
 
     fn create_pages(&mut self, start_page_index: usize, buf: &[u8]) -> Result<(), SystemError> {
@@ -78,17 +185,22 @@ impl InnerPageCache {
             let buf_offset = i * MMArch::PAGE_SIZE;
             let page_index = start_page_index + i;
 
-            let page = page_manager_guard.create_one_page(
-                PageType::File(FileMapInfo {
-                    page_cache: self
-                        .page_cache_ref
-                        .upgrade()
-                        .expect("failed to get self_arc of pagecache"),
-                    index: page_index,
-                }),
-                PageFlags::PG_LRU,
-                &mut LockedFrameAllocator,
-            )?;
+            if let Some(quota) = self.quota.as_ref() {
+                quota.charge(1)?;
+            }
+            let page = page_manager_guard
+                .create_one_page(
+                    PageType::File(FileMapInfo {
+                        page_cache: self
+                            .page_cache_ref
+                            .upgrade()
+                            .expect("failed to get self_arc of pagecache"),
+                        index: page_index,
+                    }),
+                    self.page_flags,
+                    &mut LockedFrameAllocator,
+                )
+                .inspect_err(|_| self.uncharge(1))?;
 
             let mut page_guard = page.write_irqsave();
             unsafe {
@@ -180,14 +292,8 @@ impl InnerPageCache {
             buf_offset += sub_len;
         }
 
+        let shmem = self.is_shmem();
         for (page_index, count) in not_exist {
-            // TODO 这里使用buffer避免多次读取磁盘，将来引入异步IO直接写入页面，减少内存开销和拷贝
-            let mut page_buf = vec![0u8; MMArch::PAGE_SIZE * count];
-
-            inode.read_sync(page_index * MMArch::PAGE_SIZE, page_buf.as_mut())?;
-
-            self.create_pages(page_index, page_buf.as_mut())?;
-
             // 实际要拷贝的内容在文件中的偏移量
             let copy_offset = core::cmp::max(page_index * MMArch::PAGE_SIZE, offset);
             // 实际要拷贝的内容的长度
@@ -202,6 +308,20 @@ impl InnerPageCache {
 
             let buf_offset = copy_offset.saturating_sub(offset);
 
+            // 共享内存中不存在的页面是文件空洞，直接填充0，不分配页面也不占用配额
+            if shmem {
+                buf[buf_offset..buf_offset + copy_len].fill(0);
+                ret += copy_len;
+                continue;
+            }
+
+            // TODO 这里使用buffer避免多次读取磁盘，将来引入异步IO直接写入页面，减少内存开销和拷贝
+            let mut page_buf = vec![0u8; MMArch::PAGE_SIZE * count];
+
+            inode.read_sync(page_index * MMArch::PAGE_SIZE, page_buf.as_mut())?;
+
+            self.create_pages(page_index, page_buf.as_mut())?;
+
             buf[buf_offset..buf_offset + copy_len]
                 .copy_from_slice(&page_buf[page_buf_offset..page_buf_offset + copy_len]);
 
@@ -263,7 +383,10 @@ impl InnerPageCache {
 
             if page.is_none() {
                 let page_buf = vec![0u8; MMArch::PAGE_SIZE];
-                self.create_pages(page_index, &page_buf)?;
+                if let Err(e) = self.create_pages(page_index, &page_buf) {
+                    // 已经写入了部分数据时返回写入的长度
+                    return if ret > 0 { Ok(ret) } else { Err(e) };
+                }
                 page = self.get_page(page_index);
             }
 
@@ -290,13 +413,92 @@ impl InnerPageCache {
         Ok(ret)
     }
 
-    pub fn resize(&mut self, len: usize) -> Result<(), SystemError> {
-        let page_num = page_align_up(len) / MMArch::PAGE_SIZE;
+    /// 预读文件中`[start_page_index, start_page_index + page_num)`范围内尚未缓存的页面
+    ///
+    /// 超出文件末尾的部分会被忽略，共享内存没有后备存储，不需要预读
+    pub fn readahead(
+        &mut self,
+        start_page_index: usize,
+        page_num: usize,
+    ) -> Result<(), SystemError> {
+        if self.is_shmem() {
+            return Ok(());
+        }
+
+        let inode: Arc<dyn IndexNode> = self
+            .page_cache_ref
+            .upgrade()
+            .unwrap()
+            .inode
+            .upgrade()
+            .unwrap();
+        let file_size = inode.metadata()?.size as usize;
+        let end = min(
+            start_page_index.saturating_add(page_num),
+            page_align_up(file_size) >> MMArch::PAGE_SHIFT,
+        );
+
+        let mut page_index = start_page_index;
+        while page_index < end {
+            if self.pages.contains_key(&page_index) {
+                page_index += 1;
+                continue;
+            }
+
+            // 合并连续的缺失页面，每次最多读取READAHEAD_MAX_PAGES个页面
+            let mut count = 1;
+            while count < READAHEAD_MAX_PAGES
+                && page_index + count < end
+                && !self.pages.contains_key(&(page_index + count))
+            {
+                count += 1;
+            }
+
+            let mut page_buf = vec![0u8; MMArch::PAGE_SIZE * count];
+            inode.read_sync(page_index * MMArch::PAGE_SIZE, page_buf.as_mut())?;
+            self.create_pages(page_index, page_buf.as_mut())?;
+
+            page_index += count;
+        }
+
+        Ok(())
+    }
+
+    /// 从页缓存中移除`[start_page_index, end_page_index)`范围内的页面
+    ///
+    /// 共享内存页没有后备存储，未被映射时直接释放
+    pub fn remove_range(&mut self, start_page_index: usize, end_page_index: usize) {
+        let shmem = self.is_shmem();
+        let removed: Vec<Arc<Page>> = self
+            .pages
+            .drain_filter(|index, _page| *index >= start_page_index && *index < end_page_index)
+            .map(|(_i, page)| page)
+            .collect();
+        self.nr_pages.fetch_sub(removed.len(), Ordering::Relaxed);
 
         let mut reclaimer = page_reclaimer_lock_irqsave();
-        for (_i, page) in self.pages.drain_filter(|index, _page| *index >= page_num) {
+        for page in removed.iter() {
             let _ = reclaimer.remove_page(&page.phys_address());
         }
+        drop(reclaimer);
+
+        if shmem {
+            NR_SHMEM_PAGES.fetch_sub(removed.len(), Ordering::Relaxed);
+            self.uncharge(removed.len());
+            let mut page_manager = page_manager_lock_irqsave();
+            for page in removed.iter() {
+                if page.read_irqsave().map_count() == 0 {
+                    page_manager.remove_page(&page.phys_address());
+                }
+            }
+        }
+    }
+
+    pub fn resize(&mut self, len: usize) -> Result<(), SystemError> {
+        let page_num = page_align_up(len) / MMArch::PAGE_SIZE;
+
+        let shmem = self.is_shmem();
+        self.remove_range(page_num, usize::MAX);
 
         if page_num > 0 {
             let last_page_index = page_num - 1;
@@ -305,7 +507,7 @@ impl InnerPageCache {
                 unsafe {
                     page.write_irqsave().truncate(last_len);
                 };
-            } else {
+            } else if !shmem {
                 return Err(SystemError::EIO);
             }
         }
@@ -321,15 +523,53 @@ impl Drop for InnerPageCache {
         for page in self.pages.values() {
             page_manager.remove_page(&page.phys_address());
         }
+        if self.is_shmem() {
+            NR_SHMEM_PAGES.fetch_sub(self.pages.len(), Ordering::Relaxed);
+            self.uncharge(self.pages.len());
+        }
     }
 }
 
 impl PageCache {
     pub fn new(inode: Option<Weak<dyn IndexNode>>) -> Arc<PageCache> {
+        Self::new_with_flags(inode, PageFlags::PG_LRU, None)
+    }
+
+    /// 创建共享内存(tmpfs)使用的页缓存
+    ///
+    /// 共享内存页没有可回写的后备存储，因此不加入LRU链表，也不会被页面回收线程回收
+    ///
+    /// ## 参数
+    ///
+    /// - `inode`: 页缓存所属的inode
+    /// - `quota`: 新建页面时需要申请的容量配额，None表示不限制
+    pub fn new_shmem(
+        inode: Option<Weak<dyn IndexNode>>,
+        quota: Option<Arc<ShmemQuota>>,
+    ) -> Arc<PageCache> {
+        Self::new_with_flags(
+            inode,
+            PageFlags::PG_SWAPBACKED | PageFlags::PG_UNEVICTABLE | PageFlags::PG_UPTODATE,
+            quota,
+        )
+    }
+
+    fn new_with_flags(
+        inode: Option<Weak<dyn IndexNode>>,
+        page_flags: PageFlags,
+        quota: Option<Arc<ShmemQuota>>,
+    ) -> Arc<PageCache> {
         let id = PAGE_CACHE_ID.fetch_add(1, Ordering::SeqCst);
+        let nr_pages = Arc::new(AtomicUsize::new(0));
         Arc::new_cyclic(|weak| Self {
             id,
-            inner: SpinLock::new(InnerPageCache::new(weak.clone(), id)),
+            inner: SpinLock::new(InnerPageCache::new(
+                weak.clone(),
+                id,
+                page_flags,
+                quota,
+                nr_pages.clone(),
+            )),
             inode: {
                 let v: Lazy<Weak<dyn IndexNode>> = Lazy::new();
                 if let Some(inode) = inode {
@@ -337,6 +577,7 @@ impl PageCache {
                 }
                 v
             },
+            nr_pages,
         })
     }
 
@@ -347,6 +588,14 @@ impl PageCache {
         self.id
     }
 
+    /// 页缓存中的页面数量
+    ///
+    /// 不需要获取页缓存的锁，因此可以在持有页缓存锁时通过inode的metadata调用
+    #[inline]
+    pub fn nr_pages(&self) -> usize {
+        self.nr_pages.load(Ordering::Relaxed)
+    }
+
     pub fn inode(&self) -> Option<Weak<dyn IndexNode>> {
         self.inode.try_get().cloned()
     }
@@ -12,8 +12,9 @@ use alloc::{
 use system_error::SystemError;
 
 use crate::{
-    arch::mm::LockedFrameAllocator,
+    arch::{mm::LockedFrameAllocator, MMArch},
     driver::base::device::device_number::DeviceNumber,
+    filesystem::page_cache::shmem_pages,
     filesystem::vfs::{
         vcore::{generate_inode_id, ROOT_INODE},
         FileType,
@@ -23,8 +24,24 @@ use crate::{
         rwlock::RwLock,
         spinlock::{SpinLock, SpinLockGuard},
     },
-    mm::allocator::page_frame::FrameAllocator,
-    process::{Pid, ProcessManager},
+    mm::{
+        allocator::{
+            page_frame::FrameAllocator,
+            pcp::{PCP_BATCH, PCP_HIGH},
+        },
+        huge_memory::{nr_anon_thps, HPAGE_PMD_SIZE},
+        hugetlb::{default_hstate, hstates},
+        mmzone::populated_zones,
+        oom_kill::{oom_score, OOM_SCORE_ADJ_MAX, OOM_SCORE_ADJ_MIN},
+        swap::{swap_free_pages, swap_total_pages},
+        MemoryManagementArch,
+    },
+    process::{
+        capability::capable,
+        coredump::{core_pattern, set_core_pattern},
+        cred::CAPFlags,
+        Pid, ProcessManager,
+    },
     time::PosixTimeSpec,
 };
 
@@ -52,6 +69,14 @@ pub enum ProcFileType {
     ProcKmsg = 2,
     /// 可执行路径
     ProcExe = 3,
+    /// OOM killer的分数调整值
+    ProcOomScoreAdj = 4,
+    /// OOM killer的分数
+    ProcOomScore = 5,
+    /// zoneinfo
+    ProcZoneinfo = 6,
+    /// core dump文件名的模板
+    ProcCorePattern = 7,
     //todo: 其他文件类型
     ///默认文件类型
     Default,
@@ -64,6 +89,10 @@ impl From<u8> for ProcFileType {
             1 => ProcFileType::ProcMeminfo,
             2 => ProcFileType::ProcKmsg,
             3 => ProcFileType::ProcExe,
+            4 => ProcFileType::ProcOomScoreAdj,
+            5 => ProcFileType::ProcOomScore,
+            6 => ProcFileType::ProcZoneinfo,
+            7 => ProcFileType::ProcCorePattern,
             _ => ProcFileType::Default,
         }
     }
@@ -203,6 +232,9 @@ impl ProcFSInode {
         // kthread
         pdata.append(&mut format!("\nKthread:\t{}", pcb.is_kthread() as usize).into());
 
+        // seccomp
+        pdata.append(&mut format!("\nSeccomp:\t{}", pcb.seccomp_mode()).into());
+
         pdata.append(&mut format!("\ncpu_id:\t{}", cpu_id).as_bytes().to_owned());
         pdata.append(&mut format!("\npriority:\t{:?}", priority).as_bytes().to_owned());
         pdata.append(
@@ -263,12 +295,163 @@ impl ProcFSInode {
                 .to_owned(),
         );
 
+        data.append(
+            &mut format!("Shmem:\t{} kB\n", (shmem_pages() * MMArch::PAGE_SIZE) >> 10)
+                .as_bytes()
+                .to_owned(),
+        );
+
+        data.append(
+            &mut format!(
+                "SwapTotal:\t{} kB\n",
+                (swap_total_pages() * MMArch::PAGE_SIZE) >> 10
+            )
+            .as_bytes()
+            .to_owned(),
+        );
+
+        data.append(
+            &mut format!(
+                "SwapFree:\t{} kB\n",
+                (swap_free_pages() * MMArch::PAGE_SIZE) >> 10
+            )
+            .as_bytes()
+            .to_owned(),
+        );
+
+        data.append(
+            &mut format!(
+                "AnonHugePages:\t{} kB\n",
+                (nr_anon_thps() * HPAGE_PMD_SIZE) >> 10
+            )
+            .as_bytes()
+            .to_owned(),
+        );
+
+        // 默认大页池的统计信息，以及所有大页池占用的内存总量
+        if let Some(hstate) = default_hstate() {
+            data.append(
+                &mut format!(
+                    "HugePages_Total:\t{}\nHugePages_Free:\t{}\nHugepagesize:\t{} kB\n",
+                    hstate.nr_hugepages(),
+                    hstate.free_hugepages(),
+                    hstate.size() >> 10
+                )
+                .as_bytes()
+                .to_owned(),
+            );
+        }
+        let hugetlb_kb: usize = hstates()
+            .iter()
+            .map(|h| (h.nr_hugepages() * h.size()) >> 10)
+            .sum();
+        data.append(
+            &mut format!("Hugetlb:\t{} kB\n", hugetlb_kb)
+                .as_bytes()
+                .to_owned(),
+        );
+
         // 去除多余的\0
         self.trim_string(data);
 
         return Ok((data.len() * size_of::<u8>()) as i64);
     }
 
+    /// 打开 zoneinfo 文件
+    fn open_zoneinfo(&self, pdata: &mut ProcfsFilePrivateData) -> Result<i64, SystemError> {
+        let data: &mut Vec<u8> = &mut pdata.data;
+
+        for (nid, zone_type, zone) in populated_zones() {
+            let usage = zone.usage();
+            data.append(
+                &mut format!(
+                    "Node {}, zone {:>8}\n  pages free     {}\n        managed  {}\n        present  {}\n  pagesets\n",
+                    nid,
+                    zone_type.name(),
+                    usage.free().data(),
+                    usage.total().data(),
+                    zone.present_pages()
+                )
+                .as_bytes()
+                .to_owned(),
+            );
+
+            // 每CPU页帧缓存
+            for (cpu, count, stats) in zone.pcp().stats() {
+                data.append(
+                    &mut format!(
+                        "    cpu: {}\n              count: {}\n              high:  {}\n              batch: {}\n              alloc_hit: {}\n              refill: {}\n              free:  {}\n              drained: {}\n",
+                        cpu.data(),
+                        count,
+                        PCP_HIGH,
+                        PCP_BATCH,
+                        stats.alloc_hit,
+                        stats.refill,
+                        stats.free,
+                        stats.drained
+                    )
+                    .as_bytes()
+                    .to_owned(),
+                );
+            }
+        }
+
+        self.trim_string(data);
+
+        return Ok((data.len() * size_of::<u8>()) as i64);
+    }
+
+    /// 打开oom_score_adj文件
+    fn open_oom_score_adj(&self, pdata: &mut ProcfsFilePrivateData) -> Result<i64, SystemError> {
+        let pcb = ProcessManager::find(self.fdata.pid).ok_or(SystemError::ESRCH)?;
+        pdata.data = format!("{}\n", pcb.oom_score_adj()).into_bytes();
+        return Ok(pdata.data.len() as i64);
+    }
+
+    /// 打开oom_score文件
+    fn open_oom_score(&self, pdata: &mut ProcfsFilePrivateData) -> Result<i64, SystemError> {
+        let pcb = ProcessManager::find(self.fdata.pid).ok_or(SystemError::ESRCH)?;
+        pdata.data = format!("{}\n", oom_score(&pcb)).into_bytes();
+        return Ok(pdata.data.len() as i64);
+    }
+
+    /// 写入oom_score_adj文件
+    fn write_oom_score_adj(&self, buf: &[u8]) -> Result<usize, SystemError> {
+        let pcb = ProcessManager::find(self.fdata.pid).ok_or(SystemError::ESRCH)?;
+        let adj = core::str::from_utf8(buf)
+            .map_err(|_| SystemError::EINVAL)?
+            .trim_matches(|c: char| c.is_whitespace() || c == '\0')
+            .parse::<i32>()
+            .map_err(|_| SystemError::EINVAL)?;
+        if !(OOM_SCORE_ADJ_MIN..=OOM_SCORE_ADJ_MAX).contains(&adj) {
+            return Err(SystemError::EINVAL);
+        }
+        // 只有拥有CAP_SYS_RESOURCE的进程可以降低进程的oom_score_adj
+        if adj < pcb.oom_score_adj() && !capable(CAPFlags::CAP_SYS_RESOURCE) {
+            return Err(SystemError::EACCES);
+        }
+        pcb.set_oom_score_adj(adj);
+        return Ok(buf.len());
+    }
+
+    /// 打开core_pattern文件
+    fn open_core_pattern(&self, pdata: &mut ProcfsFilePrivateData) -> Result<i64, SystemError> {
+        pdata.data = format!("{}\n", core_pattern()).into_bytes();
+        return Ok(pdata.data.len() as i64);
+    }
+
+    /// 写入core_pattern文件
+    fn write_core_pattern(&self, buf: &[u8]) -> Result<usize, SystemError> {
+        if !capable(CAPFlags::CAP_SYS_ADMIN) {
+            return Err(SystemError::EACCES);
+        }
+        let pattern = core::str::from_utf8(buf)
+            .map_err(|_| SystemError::EINVAL)?
+            .trim_end_matches(|c: char| c == '\n' || c == '\0');
+        set_core_pattern(pattern)?;
+        return Ok(buf.len());
+    }
+
     // 打开 exe 文件
     fn open_exe(&self, _pdata: &mut ProcfsFilePrivateData) -> Result<i64, SystemError> {
         // 这个文件是一个软链接，直接返回0即可
@@ -408,6 +591,23 @@ impl ProcFS {
             panic!("create meminfo error");
         }
 
+        // 创建zoneinfo文件
+        let binding = inode.create(
+            "zoneinfo",
+            FileType::File,
+            ModeType::from_bits_truncate(0o444),
+        );
+        if let Ok(zoneinfo) = binding {
+            let zoneinfo_file = zoneinfo
+                .as_any_ref()
+                .downcast_ref::<LockedProcFSInode>()
+                .unwrap();
+            zoneinfo_file.0.lock().fdata.pid = Pid::new(0);
+            zoneinfo_file.0.lock().fdata.ftype = ProcFileType::ProcZoneinfo;
+        } else {
+            panic!("create zoneinfo error");
+        }
+
         // 创建kmsg文件
         let binding = inode.create("kmsg", FileType::File, ModeType::from_bits_truncate(0o444));
         if let Ok(kmsg) = binding {
@@ -462,6 +662,41 @@ impl ProcFS {
             panic!("create exe error");
         }
 
+        let sys_dir = inode
+            .create("sys", FileType::Dir, ModeType::from_bits_truncate(0o555))
+            .expect("create /proc/sys error");
+
+        // 创建binfmt_misc文件系统的挂载点
+        sys_dir
+            .create("fs", FileType::Dir, ModeType::from_bits_truncate(0o555))
+            .and_then(|fs| {
+                fs.create(
+                    "binfmt_misc",
+                    FileType::Dir,
+                    ModeType::from_bits_truncate(0o755),
+                )
+            })
+            .expect("create /proc/sys/fs/binfmt_misc error");
+
+        let core_pattern = sys_dir
+            .create("kernel", FileType::Dir, ModeType::from_bits_truncate(0o555))
+            .and_then(|kernel| {
+                kernel.create(
+                    "core_pattern",
+                    FileType::File,
+                    ModeType::from_bits_truncate(0o644),
+                )
+            })
+            .expect("create /proc/sys/kernel/core_pattern error");
+        core_pattern
+            .as_any_ref()
+            .downcast_ref::<LockedProcFSInode>()
+            .unwrap()
+            .0
+            .lock()
+            .fdata
+            .ftype = ProcFileType::ProcCorePattern;
+
         return result;
     }
 
@@ -504,6 +739,32 @@ impl ProcFS {
         exe_file.0.lock().fdata.pid = pid;
         exe_file.0.lock().fdata.ftype = ProcFileType::ProcExe;
 
+        // oom_score_adj文件
+        let oom_score_adj_binding: Arc<dyn IndexNode> = pid_dir.create(
+            "oom_score_adj",
+            FileType::File,
+            ModeType::from_bits_truncate(0o644),
+        )?;
+        let oom_score_adj_file = oom_score_adj_binding
+            .as_any_ref()
+            .downcast_ref::<LockedProcFSInode>()
+            .unwrap();
+        oom_score_adj_file.0.lock().fdata.pid = pid;
+        oom_score_adj_file.0.lock().fdata.ftype = ProcFileType::ProcOomScoreAdj;
+
+        // oom_score文件
+        let oom_score_binding: Arc<dyn IndexNode> = pid_dir.create(
+            "oom_score",
+            FileType::File,
+            ModeType::from_bits_truncate(0o444),
+        )?;
+        let oom_score_file = oom_score_binding
+            .as_any_ref()
+            .downcast_ref::<LockedProcFSInode>()
+            .unwrap();
+        oom_score_file.0.lock().fdata.pid = pid;
+        oom_score_file.0.lock().fdata.ftype = ProcFileType::ProcOomScore;
+
         //todo: 创建其他文件
 
         return Ok(());
@@ -519,6 +780,8 @@ impl ProcFS {
         // 删除进程文件夹下文件
         pid_dir.unlink("status")?;
         pid_dir.unlink("exe")?;
+        pid_dir.unlink("oom_score_adj")?;
+        pid_dir.unlink("oom_score")?;
 
         // 查看进程文件是否还存在
         // let pf= pid_dir.find("status").expect("Cannot find status");
@@ -548,7 +811,11 @@ impl IndexNode for LockedProcFSInode {
         let file_size = match inode.fdata.ftype {
             ProcFileType::ProcStatus => inode.open_status(&mut private_data)?,
             ProcFileType::ProcMeminfo => inode.open_meminfo(&mut private_data)?,
+            ProcFileType::ProcZoneinfo => inode.open_zoneinfo(&mut private_data)?,
             ProcFileType::ProcExe => inode.open_exe(&mut private_data)?,
+            ProcFileType::ProcOomScoreAdj => inode.open_oom_score_adj(&mut private_data)?,
+            ProcFileType::ProcOomScore => inode.open_oom_score(&mut private_data)?,
+            ProcFileType::ProcCorePattern => inode.open_core_pattern(&mut private_data)?,
             ProcFileType::Default => inode.data.len() as i64,
             _ => {
                 todo!()
@@ -604,10 +871,16 @@ impl IndexNode for LockedProcFSInode {
             ProcFileType::ProcStatus => {
                 return inode.proc_read(offset, len, buf, &mut private_data)
             }
-            ProcFileType::ProcMeminfo => {
+            ProcFileType::ProcMeminfo | ProcFileType::ProcZoneinfo => {
                 return inode.proc_read(offset, len, buf, &mut private_data)
             }
             ProcFileType::ProcExe => return inode.read_link(buf),
+            ProcFileType::ProcOomScoreAdj | ProcFileType::ProcOomScore => {
+                return inode.proc_read(offset, len, buf, &mut private_data)
+            }
+            ProcFileType::ProcCorePattern => {
+                return inode.proc_read(offset, len, buf, &mut private_data)
+            }
             ProcFileType::ProcKmsg => (),
             ProcFileType::Default => (),
         };
@@ -630,11 +903,19 @@ impl IndexNode for LockedProcFSInode {
     fn write_at(
         &self,
         _offset: usize,
-        _len: usize,
-        _buf: &[u8],
+        len: usize,
+        buf: &[u8],
         _data: SpinLockGuard<FilePrivateData>,
     ) -> Result<usize, SystemError> {
-        return Err(SystemError::ENOSYS);
+        if buf.len() < len {
+            return Err(SystemError::EINVAL);
+        }
+        let inode: SpinLockGuard<ProcFSInode> = self.0.lock();
+        match inode.fdata.ftype {
+            ProcFileType::ProcOomScoreAdj => inode.write_oom_score_adj(&buf[..len]),
+            ProcFileType::ProcCorePattern => inode.write_core_pattern(&buf[..len]),
+            _ => Err(SystemError::ENOSYS),
+        }
     }
 
     fn fs(&self) -> Arc<dyn FileSystem> {
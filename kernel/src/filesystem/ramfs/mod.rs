@@ -22,8 +22,12 @@ use alloc::{
 use system_error::SystemError;
 
 use super::vfs::{
-    file::FilePrivateData, syscall::ModeType, utils::DName, FileSystem, FileSystemMaker, FsInfo,
-    IndexNode, InodeId, Metadata, SpecialNodeData,
+    file::FilePrivateData,
+    posix_acl::{posix_acl_create, XATTR_NAME_POSIX_ACL_DEFAULT},
+    syscall::ModeType,
+    utils::DName,
+    xattr::{SimpleXattr, XattrFlags},
+    FileSystem, FileSystemMaker, FsInfo, IndexNode, InodeId, Metadata, SpecialNodeData,
 };
 
 use linkme::distributed_slice;
@@ -66,6 +70,8 @@ pub struct RamFSInode {
     fs: Weak<RamFS>,
     /// 指向特殊节点
     special_node: Option<SpecialNodeData>,
+    /// 扩展属性
+    xattr: SimpleXattr,
 
     name: DName,
 }
@@ -96,6 +102,7 @@ impl RamFSInode {
             },
             fs: Weak::default(),
             special_node: None,
+            xattr: SimpleXattr::new(),
             name: Default::default(),
         }
     }
@@ -318,6 +325,12 @@ impl IndexNode for LockedRamFSInode {
         if inode.children.contains_key(&name) {
             return Err(SystemError::EEXIST);
         }
+        // 继承父目录的默认ACL
+        let (mode, xattr) = posix_acl_create(
+            inode.xattr.get_value(XATTR_NAME_POSIX_ACL_DEFAULT).as_deref(),
+            mode,
+            file_type == FileType::Dir,
+        )?;
 
         // 创建inode
         let result: Arc<LockedRamFSInode> = Arc::new(LockedRamFSInode(SpinLock::new(RamFSInode {
@@ -344,6 +357,7 @@ impl IndexNode for LockedRamFSInode {
             },
             fs: inode.fs.clone(),
             special_node: None,
+            xattr,
             name: name.clone(),
         })));
 
@@ -568,7 +582,7 @@ impl IndexNode for LockedRamFSInode {
         &self,
         filename: &str,
         mode: ModeType,
-        _dev_t: DeviceNumber,
+        dev_t: DeviceNumber,
     ) -> Result<Arc<dyn IndexNode>, SystemError> {
         let mut inode = self.0.lock();
         if inode.metadata.file_type != FileType::Dir {
@@ -582,6 +596,11 @@ impl IndexNode for LockedRamFSInode {
         }
 
         let filename = DName::from(filename);
+        let (mode, xattr) = posix_acl_create(
+            inode.xattr.get_value(XATTR_NAME_POSIX_ACL_DEFAULT).as_deref(),
+            mode,
+            false,
+        )?;
 
         let nod = Arc::new(LockedRamFSInode(SpinLock::new(RamFSInode {
             parent: inode.self_ref.clone(),
@@ -607,6 +626,7 @@ impl IndexNode for LockedRamFSInode {
             },
             fs: inode.fs.clone(),
             special_node: None,
+            xattr,
             name: filename.clone(),
         })));
 
@@ -620,10 +640,10 @@ impl IndexNode for LockedRamFSInode {
             nod.0.lock().special_node = Some(SpecialNodeData::Pipe(pipe_inode));
         } else if mode.contains(ModeType::S_IFBLK) {
             nod.0.lock().metadata.file_type = FileType::BlockDevice;
-            unimplemented!()
+            nod.0.lock().metadata.raw_dev = dev_t;
         } else if mode.contains(ModeType::S_IFCHR) {
             nod.0.lock().metadata.file_type = FileType::CharDevice;
-            unimplemented!()
+            nod.0.lock().metadata.raw_dev = dev_t;
         }
 
         inode.children.insert(filename, nod.clone());
@@ -634,6 +654,28 @@ impl IndexNode for LockedRamFSInode {
         return self.0.lock().special_node.clone();
     }
 
+    fn getxattr(&self, name: &str, buf: &mut [u8]) -> Result<usize, SystemError> {
+        return self.0.lock().xattr.get(name, buf);
+    }
+
+    fn setxattr(&self, name: &str, value: &[u8], flags: XattrFlags) -> Result<(), SystemError> {
+        let mut inode = self.0.lock();
+        inode.xattr.set(name, value, flags)?;
+        inode.metadata.ctime = PosixTimeSpec::now();
+        return Ok(());
+    }
+
+    fn listxattr(&self, buf: &mut [u8]) -> Result<usize, SystemError> {
+        return self.0.lock().xattr.list(buf);
+    }
+
+    fn removexattr(&self, name: &str) -> Result<(), SystemError> {
+        let mut inode = self.0.lock();
+        inode.xattr.remove(name)?;
+        inode.metadata.ctime = PosixTimeSpec::now();
+        return Ok(());
+    }
+
     fn dname(&self) -> Result<DName, SystemError> {
         Ok(self.0.lock().name.clone())
     }
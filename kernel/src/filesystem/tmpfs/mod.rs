@@ -0,0 +1,1074 @@
+use core::any::Any;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::arch::mm::LockedFrameAllocator;
+use crate::arch::MMArch;
+use crate::filesystem::page_cache::{PageCache, ShmemQuota};
+use crate::filesystem::vfs::{FileSystemMakerData, FSMAKER};
+use crate::libs::align::page_align_up;
+use crate::libs::rwlock::RwLock;
+use crate::mm::allocator::page_frame::FrameAllocator;
+use crate::mm::fault::{PageFaultHandler, PageFaultMessage};
+use crate::mm::{MemoryManagementArch, VmFaultReason};
+use crate::process::ProcessManager;
+use crate::{
+    driver::base::device::device_number::DeviceNumber,
+    filesystem::vfs::{vcore::generate_inode_id, FileType},
+    ipc::pipe::LockedPipeInode,
+    libs::casting::DowncastArc,
+    libs::spinlock::{SpinLock, SpinLockGuard},
+    time::PosixTimeSpec,
+};
+
+use alloc::string::ToString;
+use alloc::{
+    collections::BTreeMap,
+    string::String,
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+use system_error::SystemError;
+
+use super::vfs::{
+    fcntl::SealFlags,
+    file::{File, FileMode, FilePrivateData},
+    posix_acl::{posix_acl_create, XATTR_NAME_POSIX_ACL_DEFAULT},
+    syscall::ModeType,
+    utils::DName,
+    xattr::{SimpleXattr, XattrFlags},
+    FileSystem, FileSystemMaker, FsInfo, IndexNode, InodeId, Metadata, SpecialNodeData,
+};
+
+use linkme::distributed_slice;
+
+use super::vfs::{Magic, SuperBlock};
+
+/// tmpfs的inode名称的最大长度
+const TMPFS_MAX_NAMELEN: usize = 255;
+
+/// memfd_create使用的内部tmpfs
+static SHMEM_INTERNAL: SpinLock<Option<Arc<Tmpfs>>> = SpinLock::new(None);
+
+/// 计算`size`字节需要占用的页面数
+#[inline]
+fn size_to_pages(size: usize) -> usize {
+    page_align_up(size) >> MMArch::PAGE_SHIFT
+}
+
+/// tmpfs挂载参数
+///
+/// 支持的参数: `size=`, `nr_inodes=`, `mode=`, `uid=`, `gid=`
+#[derive(Debug, Clone, Default)]
+pub struct TmpfsMountData {
+    /// 容量上限(页)，0表示不限制
+    max_pages: Option<usize>,
+    /// inode数量上限，0表示不限制
+    max_inodes: Option<usize>,
+    /// 根目录权限
+    mode: Option<ModeType>,
+    /// 根目录所有者
+    uid: Option<usize>,
+    /// 根目录所属组
+    gid: Option<usize>,
+}
+
+impl TmpfsMountData {
+    pub fn from_row(raw_data: *const u8) -> Result<Self, SystemError> {
+        let mut data = TmpfsMountData::default();
+        if raw_data.is_null() {
+            return Ok(data);
+        }
+        let len = (0..)
+            .find(|&i| unsafe { raw_data.add(i).read() } == 0)
+            .ok_or(SystemError::EINVAL)?;
+        let slice = unsafe { core::slice::from_raw_parts(raw_data, len) };
+        let raw_str = core::str::from_utf8(slice).map_err(|_| SystemError::EINVAL)?;
+
+        for pair in raw_str.split(',').filter(|s| !s.is_empty()) {
+            let mut parts = pair.splitn(2, '=');
+            let key = parts.next().ok_or(SystemError::EINVAL)?;
+            let value = parts.next().ok_or(SystemError::EINVAL)?;
+
+            match key {
+                "size" => data.max_pages = Some(Self::parse_size(value)?),
+                "nr_inodes" => data.max_inodes = Some(Self::parse_number(value)?),
+                "mode" => {
+                    let mode = u32::from_str_radix(value, 8).map_err(|_| SystemError::EINVAL)?;
+                    data.mode = Some(ModeType::from_bits_truncate(mode) & ModeType::S_IALLUGO);
+                }
+                "uid" => data.uid = Some(value.parse().map_err(|_| SystemError::EINVAL)?),
+                "gid" => data.gid = Some(value.parse().map_err(|_| SystemError::EINVAL)?),
+                _ => return Err(SystemError::EINVAL),
+            }
+        }
+        Ok(data)
+    }
+
+    /// 解析带有k/m/g/t后缀的数值
+    pub(super) fn parse_number(value: &str) -> Result<usize, SystemError> {
+        let (num, shift) = match value.as_bytes().last() {
+            Some(b'k') | Some(b'K') => (&value[..value.len() - 1], 10),
+            Some(b'm') | Some(b'M') => (&value[..value.len() - 1], 20),
+            Some(b'g') | Some(b'G') => (&value[..value.len() - 1], 30),
+            Some(b't') | Some(b'T') => (&value[..value.len() - 1], 40),
+            _ => (value, 0),
+        };
+        let num: usize = num.parse().map_err(|_| SystemError::EINVAL)?;
+        num.checked_shl(shift)
+            .filter(|v| (v >> shift) == num)
+            .ok_or(SystemError::EINVAL)
+    }
+
+    /// 解析`size=`参数，返回页数。支持按物理内存百分比指定
+    fn parse_size(value: &str) -> Result<usize, SystemError> {
+        if let Some(percent) = value.strip_suffix('%') {
+            let percent: usize = percent.parse().map_err(|_| SystemError::EINVAL)?;
+            let total = unsafe { LockedFrameAllocator.usage() }.total().data();
+            return Ok(total * percent / 100);
+        }
+        Ok(size_to_pages(Self::parse_number(value)?))
+    }
+}
+
+impl FileSystemMakerData for TmpfsMountData {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+/// @brief tmpfs的Inode结构体
+#[derive(Debug)]
+pub struct LockedTmpfsInode(pub SpinLock<TmpfsInode>);
+
+/// @brief tmpfs文件系统结构体
+///
+/// 文件数据保存在页缓存中，页面不会被回写，也不会被页面回收线程回收
+#[derive(Debug)]
+pub struct Tmpfs {
+    /// tmpfs的root inode
+    root_inode: Arc<LockedTmpfsInode>,
+    super_block: RwLock<SuperBlock>,
+    /// 容量配额，按照页缓存中实际存在的页面计算
+    quota: Arc<ShmemQuota>,
+    /// inode数量上限，0表示不限制
+    max_inodes: usize,
+    /// 已使用的inode数量
+    used_inodes: AtomicUsize,
+}
+
+/// @brief tmpfs的Inode结构体(不包含锁)
+#[derive(Debug)]
+pub struct TmpfsInode {
+    /// 指向父Inode的弱引用
+    parent: Weak<LockedTmpfsInode>,
+    /// 指向自身的弱引用
+    self_ref: Weak<LockedTmpfsInode>,
+    /// 子Inode的B树
+    children: BTreeMap<DName, Arc<LockedTmpfsInode>>,
+    /// 文件数据所在的页缓存(仅普通文件和符号链接有)
+    page_cache: Option<Arc<PageCache>>,
+    /// 当前inode的元数据
+    metadata: Metadata,
+    /// 指向inode所在的文件系统对象的指针
+    fs: Weak<Tmpfs>,
+    /// 指向特殊节点
+    special_node: Option<SpecialNodeData>,
+    /// 扩展属性
+    xattr: SimpleXattr,
+    /// 文件的seal，只有memfd_create(MFD_ALLOW_SEALING)创建的文件可以添加seal
+    seals: SealFlags,
+    /// 可写的共享映射（VM_SHARED | VM_MAYWRITE）的数量，存在时不能添加F_SEAL_WRITE
+    mmap_writable: usize,
+
+    name: DName,
+}
+
+impl TmpfsInode {
+    fn new(
+        parent: Weak<LockedTmpfsInode>,
+        fs: Weak<Tmpfs>,
+        name: DName,
+        file_type: FileType,
+        mode: ModeType,
+        raw_dev: DeviceNumber,
+    ) -> Self {
+        let cred = ProcessManager::current_pcb().cred();
+        let now = PosixTimeSpec::now();
+        Self {
+            parent,
+            self_ref: Weak::default(),
+            children: BTreeMap::new(),
+            page_cache: None,
+            metadata: Metadata {
+                dev_id: 0,
+                inode_id: generate_inode_id(),
+                size: 0,
+                blk_size: MMArch::PAGE_SIZE,
+                blocks: 0,
+                atime: now,
+                mtime: now,
+                ctime: now,
+                btime: now,
+                file_type,
+                mode,
+                nlinks: if file_type == FileType::Dir { 2 } else { 1 },
+                uid: cred.fsuid.data(),
+                gid: cred.fsgid.data(),
+                raw_dev,
+            },
+            fs,
+            special_node: None,
+            xattr: SimpleXattr::new(),
+            seals: SealFlags::F_SEAL_SEAL,
+            mmap_writable: 0,
+            name,
+        }
+    }
+}
+
+impl Drop for TmpfsInode {
+    fn drop(&mut self) {
+        // 页面持有页缓存的引用，需要主动释放页面，同时归还容量配额
+        if let Some(page_cache) = self.page_cache.take() {
+            page_cache.lock_irqsave().remove_range(0, usize::MAX);
+        }
+        if let Some(fs) = self.fs.upgrade() {
+            fs.release_inode();
+        }
+    }
+}
+
+impl FileSystem for Tmpfs {
+    fn root_inode(&self) -> Arc<dyn super::vfs::IndexNode> {
+        return self.root_inode.clone();
+    }
+
+    fn info(&self) -> FsInfo {
+        return FsInfo {
+            blk_dev_id: 0,
+            max_name_len: TMPFS_MAX_NAMELEN,
+        };
+    }
+
+    /// @brief 本函数用于实现动态转换。
+    /// 具体的文件系统在实现本函数时，最简单的方式就是：直接返回self
+    fn as_any_ref(&self) -> &dyn Any {
+        self
+    }
+
+    fn name(&self) -> &str {
+        "tmpfs"
+    }
+
+    fn super_block(&self) -> SuperBlock {
+        let mut sb = self.super_block.read().clone();
+        let used_pages = self.quota.used_pages() as u64;
+        let used_inodes = self.used_inodes.load(Ordering::Relaxed) as u64;
+        if self.quota.max_pages() != 0 {
+            sb.blocks = self.quota.max_pages() as u64;
+            sb.bfree = sb.blocks.saturating_sub(used_pages);
+            sb.bavail = sb.bfree;
+        }
+        if self.max_inodes != 0 {
+            sb.files = self.max_inodes as u64;
+            sb.ffree = sb.files.saturating_sub(used_inodes);
+        }
+        sb
+    }
+
+    unsafe fn fault(&self, pfm: &mut PageFaultMessage) -> VmFaultReason {
+        PageFaultHandler::filemap_fault(pfm)
+    }
+
+    unsafe fn map_pages(
+        &self,
+        pfm: &mut PageFaultMessage,
+        start_pgoff: usize,
+        end_pgoff: usize,
+    ) -> VmFaultReason {
+        PageFaultHandler::filemap_map_pages(pfm, start_pgoff, end_pgoff)
+    }
+}
+
+impl Tmpfs {
+    pub fn new(mount_data: &TmpfsMountData) -> Arc<Self> {
+        let total_pages = unsafe { LockedFrameAllocator.usage() }.total().data();
+        let mut super_block = SuperBlock::new(
+            Magic::TMPFS_MAGIC,
+            MMArch::PAGE_SIZE as u64,
+            TMPFS_MAX_NAMELEN as u64,
+        );
+        super_block.frsize = MMArch::PAGE_SIZE as u64;
+
+        // 与Linux一致，默认容量与inode数量上限均为物理内存页数的一半
+        let result: Arc<Tmpfs> = Arc::new_cyclic(|fs| {
+            let mut root = TmpfsInode::new(
+                Weak::default(),
+                fs.clone(),
+                DName::default(),
+                FileType::Dir,
+                mount_data
+                    .mode
+                    .unwrap_or(ModeType::from_bits_truncate(0o1777)),
+                DeviceNumber::default(),
+            );
+            if let Some(uid) = mount_data.uid {
+                root.metadata.uid = uid;
+            }
+            if let Some(gid) = mount_data.gid {
+                root.metadata.gid = gid;
+            }
+
+            Tmpfs {
+                root_inode: Arc::new(LockedTmpfsInode(SpinLock::new(root))),
+                super_block: RwLock::new(super_block),
+                quota: ShmemQuota::new(mount_data.max_pages.unwrap_or(total_pages / 2)),
+                max_inodes: mount_data.max_inodes.unwrap_or(total_pages / 2),
+                used_inodes: AtomicUsize::new(1),
+            }
+        });
+
+        // 对root inode加锁，并继续完成初始化工作
+        let mut root_guard: SpinLockGuard<TmpfsInode> = result.root_inode.0.lock();
+        root_guard.parent = Arc::downgrade(&result.root_inode);
+        root_guard.self_ref = Arc::downgrade(&result.root_inode);
+        // 释放锁
+        drop(root_guard);
+
+        return result;
+    }
+
+    pub fn make_tmpfs(
+        data: Option<&dyn FileSystemMakerData>,
+    ) -> Result<Arc<dyn FileSystem + 'static>, SystemError> {
+        let mount_data = data
+            .and_then(|d| d.as_any().downcast_ref::<TmpfsMountData>())
+            .cloned()
+            .unwrap_or_default();
+        let fs = Tmpfs::new(&mount_data);
+        return Ok(fs);
+    }
+
+    /// 申请一个inode配额
+    ///
+    /// ## 返回值
+    ///
+    /// - `Err(SystemError::ENOSPC)` 超出了`nr_inodes=`指定的inode数量上限
+    fn alloc_inode(&self) -> Result<(), SystemError> {
+        self.used_inodes
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |used| {
+                if self.max_inodes != 0 && used >= self.max_inodes {
+                    None
+                } else {
+                    Some(used + 1)
+                }
+            })
+            .map(|_| ())
+            .map_err(|_| SystemError::ENOSPC)
+    }
+
+    fn release_inode(&self) {
+        self.used_inodes.fetch_sub(1, Ordering::SeqCst);
+    }
+
+    /// 创建一个不在任何目录中的文件，用于memfd_create
+    fn new_unlinked_file(
+        self: &Arc<Self>,
+        name: &str,
+        seals: SealFlags,
+    ) -> Result<Arc<LockedTmpfsInode>, SystemError> {
+        self.alloc_inode()?;
+        let mut inode = TmpfsInode::new(
+            Arc::downgrade(&self.root_inode),
+            Arc::downgrade(self),
+            DName::from(name),
+            FileType::File,
+            ModeType::from_bits_truncate(0o777),
+            DeviceNumber::default(),
+        );
+        inode.metadata.nlinks = 0;
+        inode.seals = seals;
+
+        let result = Arc::new(LockedTmpfsInode(SpinLock::new(inode)));
+        result.0.lock().self_ref = Arc::downgrade(&result);
+        result.0.lock().page_cache = Some(PageCache::new_shmem(
+            Some(Arc::downgrade(&result) as Weak<dyn IndexNode>),
+            Some(self.quota.clone()),
+        ));
+        return Ok(result);
+    }
+}
+
+/// 为memfd_create创建一个由页缓存保存数据的匿名文件
+///
+/// ## 参数
+///
+/// - `name`: 文件名，只用于在/proc/<pid>/fd等位置显示
+/// - `seals`: 文件初始的seal
+/// - `mode`: 文件的打开模式
+pub fn shmem_file_setup(name: &str, seals: SealFlags, mode: FileMode) -> Result<File, SystemError> {
+    let fs = SHMEM_INTERNAL
+        .lock()
+        .get_or_insert_with(|| {
+            // 内部tmpfs不限制容量与inode数量
+            Tmpfs::new(&TmpfsMountData {
+                max_pages: Some(0),
+                max_inodes: Some(0),
+                ..Default::default()
+            })
+        })
+        .clone();
+    let inode = fs.new_unlinked_file(name, seals)?;
+    return File::new(inode, mode);
+}
+
+#[distributed_slice(FSMAKER)]
+static TMPFSMAKER: FileSystemMaker = FileSystemMaker::new(
+    "tmpfs",
+    &(Tmpfs::make_tmpfs
+        as fn(
+            Option<&dyn FileSystemMakerData>,
+        ) -> Result<Arc<dyn FileSystem + 'static>, SystemError>),
+);
+
+impl LockedTmpfsInode {
+    /// 在当前目录下新建一个inode
+    fn new_child(
+        &self,
+        name: &str,
+        file_type: FileType,
+        mode: ModeType,
+        raw_dev: DeviceNumber,
+    ) -> Result<Arc<LockedTmpfsInode>, SystemError> {
+        let name = DName::from(name);
+        // 获取当前inode
+        let mut inode = self.0.lock();
+        // 如果当前inode不是文件夹，则返回
+        if inode.metadata.file_type != FileType::Dir {
+            return Err(SystemError::ENOTDIR);
+        }
+        // 如果有重名的，则返回
+        if inode.children.contains_key(&name) {
+            return Err(SystemError::EEXIST);
+        }
+        // 继承父目录的默认ACL
+        let (mode, xattr) = posix_acl_create(
+            inode
+                .xattr
+                .get_value(XATTR_NAME_POSIX_ACL_DEFAULT)
+                .as_deref(),
+            mode,
+            file_type == FileType::Dir,
+        )?;
+        let fs = inode.fs.upgrade().ok_or(SystemError::ENOENT)?;
+        fs.alloc_inode()?;
+
+        // 创建inode
+        let result: Arc<LockedTmpfsInode> =
+            Arc::new(LockedTmpfsInode(SpinLock::new(TmpfsInode::new(
+                inode.self_ref.clone(),
+                inode.fs.clone(),
+                name.clone(),
+                file_type,
+                mode,
+                raw_dev,
+            ))));
+
+        // 初始化inode的自引用的weak指针
+        result.0.lock().self_ref = Arc::downgrade(&result);
+        result.0.lock().xattr = xattr;
+        if file_type == FileType::File || file_type == FileType::SymLink {
+            result.0.lock().page_cache = Some(PageCache::new_shmem(
+                Some(Arc::downgrade(&result) as Weak<dyn IndexNode>),
+                Some(fs.quota.clone()),
+            ));
+        }
+
+        // 将子inode插入父inode的B树中
+        inode.children.insert(name, result.clone());
+        if file_type == FileType::Dir {
+            inode.metadata.nlinks += 1;
+        }
+        let now = PosixTimeSpec::now();
+        inode.metadata.mtime = now;
+        inode.metadata.ctime = now;
+
+        return Ok(result);
+    }
+
+    /// 重命名时检查将被覆盖的目标`old`能否被替换，可以替换时减少它的硬链接计数
+    ///
+    /// ## 返回值
+    ///
+    /// - `Ok(true)` 被覆盖的是目录，其父目录的硬链接计数需要减一
+    /// - `Err(SystemError::ENOTEMPTY)` 被覆盖的目录不为空
+    fn replace_target(old: &Arc<LockedTmpfsInode>, is_dir: bool) -> Result<bool, SystemError> {
+        let mut old_inode = old.0.lock();
+        let old_is_dir = old_inode.metadata.file_type == FileType::Dir;
+        match (is_dir, old_is_dir) {
+            (false, true) => return Err(SystemError::EISDIR),
+            (true, false) => return Err(SystemError::ENOTDIR),
+            (true, true) if !old_inode.children.is_empty() => return Err(SystemError::ENOTEMPTY),
+            _ => {}
+        }
+        if old_is_dir {
+            old_inode.metadata.nlinks = 0;
+        } else {
+            old_inode.metadata.nlinks -= 1;
+        }
+        old_inode.metadata.ctime = PosixTimeSpec::now();
+        Ok(old_is_dir)
+    }
+
+    /// 将文件大小设置为`len`，缩小时释放超出文件末尾的页面
+    fn set_size(&self, len: usize) -> Result<(), SystemError> {
+        let mut inode = self.0.lock();
+        match inode.metadata.file_type {
+            FileType::File | FileType::SymLink => {}
+            FileType::Dir => return Err(SystemError::EISDIR),
+            _ => return Err(SystemError::EINVAL),
+        }
+        let old_size = inode.metadata.size as usize;
+        if (len < old_size && inode.seals.contains(SealFlags::F_SEAL_SHRINK))
+            || (len > old_size && inode.seals.contains(SealFlags::F_SEAL_GROW))
+        {
+            return Err(SystemError::EPERM);
+        }
+        inode.metadata.size = len as i64;
+        let now = PosixTimeSpec::now();
+        inode.metadata.mtime = now;
+        inode.metadata.ctime = now;
+        let page_cache = inode.page_cache.clone();
+        drop(inode);
+
+        if let Some(page_cache) = page_cache {
+            page_cache.lock_irqsave().resize(len)?;
+        }
+        return Ok(());
+    }
+}
+
+impl IndexNode for LockedTmpfsInode {
+    fn read_sync(&self, _offset: usize, buf: &mut [u8]) -> Result<usize, SystemError> {
+        // tmpfs没有后备存储，页缓存中不存在的页面(文件空洞)读出来全为0
+        buf.fill(0);
+        return Ok(buf.len());
+    }
+
+    fn write_sync(&self, _offset: usize, buf: &[u8]) -> Result<usize, SystemError> {
+        // 数据只保存在页缓存中，无需回写
+        return Ok(buf.len());
+    }
+
+    fn truncate(&self, len: usize) -> Result<(), SystemError> {
+        let size = self.0.lock().metadata.size as usize;
+        //当前文件长度大于_len才进行截断，否则不操作
+        if size > len {
+            return self.set_size(len);
+        }
+        return Ok(());
+    }
+
+    fn close(&self, _data: SpinLockGuard<FilePrivateData>) -> Result<(), SystemError> {
+        return Ok(());
+    }
+
+    fn open(
+        &self,
+        _data: SpinLockGuard<FilePrivateData>,
+        _mode: &super::vfs::file::FileMode,
+    ) -> Result<(), SystemError> {
+        return Ok(());
+    }
+
+    fn read_at(
+        &self,
+        offset: usize,
+        len: usize,
+        buf: &mut [u8],
+        _data: SpinLockGuard<FilePrivateData>,
+    ) -> Result<usize, SystemError> {
+        let len = core::cmp::min(len, buf.len());
+
+        let inode: SpinLockGuard<TmpfsInode> = self.0.lock();
+        // 检查当前inode是否为一个文件夹，如果是的话，就返回错误
+        if inode.metadata.file_type == FileType::Dir {
+            return Err(SystemError::EISDIR);
+        }
+        let page_cache = inode.page_cache.clone().ok_or(SystemError::EINVAL)?;
+        drop(inode);
+
+        return page_cache.lock_irqsave().read(offset, &mut buf[0..len]);
+    }
+
+    fn write_at(
+        &self,
+        offset: usize,
+        len: usize,
+        buf: &[u8],
+        _data: SpinLockGuard<FilePrivateData>,
+    ) -> Result<usize, SystemError> {
+        let len = core::cmp::min(len, buf.len());
+
+        let mut inode: SpinLockGuard<TmpfsInode> = self.0.lock();
+        // 检查当前inode是否为一个文件夹，如果是的话，就返回错误
+        if inode.metadata.file_type == FileType::Dir {
+            return Err(SystemError::EISDIR);
+        }
+        let page_cache = inode.page_cache.clone().ok_or(SystemError::EINVAL)?;
+        if inode
+            .seals
+            .intersects(SealFlags::F_SEAL_WRITE | SealFlags::F_SEAL_FUTURE_WRITE)
+        {
+            return Err(SystemError::EPERM);
+        }
+
+        // 先扩展文件大小，使并发的读取能够看到新写入的数据
+        let old_size = inode.metadata.size as usize;
+        let end = offset.checked_add(len).ok_or(SystemError::EFBIG)?;
+        if end > old_size && inode.seals.contains(SealFlags::F_SEAL_GROW) {
+            return Err(SystemError::EPERM);
+        }
+        if end > old_size {
+            inode.metadata.size = end as i64;
+        }
+        let now = PosixTimeSpec::now();
+        inode.metadata.mtime = now;
+        inode.metadata.ctime = now;
+        drop(inode);
+
+        // 容量不足时只写入部分数据，此时将文件大小回退到实际写入的位置
+        let r = page_cache.lock_irqsave().write(offset, &buf[0..len]);
+        let written_end = offset + *r.as_ref().unwrap_or(&0);
+        if end > old_size && written_end < end {
+            let mut inode = self.0.lock();
+            if inode.metadata.size as usize == end {
+                inode.metadata.size = old_size.max(written_end) as i64;
+            }
+        }
+        return r;
+    }
+
+    fn fs(&self) -> Arc<dyn FileSystem> {
+        return self.0.lock().fs.upgrade().unwrap();
+    }
+
+    fn as_any_ref(&self) -> &dyn core::any::Any {
+        self
+    }
+
+    fn metadata(&self) -> Result<Metadata, SystemError> {
+        let inode = self.0.lock();
+        let mut metadata = inode.metadata.clone();
+        let page_cache = inode.page_cache.clone();
+        drop(inode);
+        // 只统计实际分配了的页面，文件空洞不占用空间
+        let nr_pages = page_cache.map_or(0, |pc| pc.nr_pages());
+        metadata.blocks = nr_pages * (MMArch::PAGE_SIZE / 512);
+
+        return Ok(metadata);
+    }
+
+    fn set_metadata(&self, metadata: &Metadata) -> Result<(), SystemError> {
+        let mut inode = self.0.lock();
+        inode.metadata.atime = metadata.atime;
+        inode.metadata.mtime = metadata.mtime;
+        inode.metadata.ctime = metadata.ctime;
+        inode.metadata.btime = metadata.btime;
+        inode.metadata.mode = metadata.mode;
+        inode.metadata.uid = metadata.uid;
+        inode.metadata.gid = metadata.gid;
+
+        return Ok(());
+    }
+
+    fn resize(&self, len: usize) -> Result<(), SystemError> {
+        return self.set_size(len);
+    }
+
+    fn page_cache(&self) -> Option<Arc<PageCache>> {
+        self.0.lock().page_cache.clone()
+    }
+
+    fn create_with_data(
+        &self,
+        name: &str,
+        file_type: FileType,
+        mode: ModeType,
+        data: usize,
+    ) -> Result<Arc<dyn IndexNode>, SystemError> {
+        let result = self.new_child(name, file_type, mode, DeviceNumber::from(data as u32))?;
+        return Ok(result);
+    }
+
+    fn link(&self, name: &str, other: &Arc<dyn IndexNode>) -> Result<(), SystemError> {
+        let other: &LockedTmpfsInode = other
+            .downcast_ref::<LockedTmpfsInode>()
+            .ok_or(SystemError::EXDEV)?;
+        let name = DName::from(name);
+        let mut inode: SpinLockGuard<TmpfsInode> = self.0.lock();
+        let mut other_locked: SpinLockGuard<TmpfsInode> = other.0.lock();
+
+        // 如果当前inode不是文件夹，那么报错
+        if inode.metadata.file_type != FileType::Dir {
+            return Err(SystemError::ENOTDIR);
+        }
+
+        // 如果另一个inode是文件夹，那么也报错
+        if other_locked.metadata.file_type == FileType::Dir {
+            return Err(SystemError::EISDIR);
+        }
+
+        // 如果当前文件夹下已经有同名文件，也报错。
+        if inode.children.contains_key(&name) {
+            return Err(SystemError::EEXIST);
+        }
+
+        inode
+            .children
+            .insert(name, other_locked.self_ref.upgrade().unwrap());
+
+        // 增加硬链接计数
+        other_locked.metadata.nlinks += 1;
+        other_locked.metadata.ctime = PosixTimeSpec::now();
+        return Ok(());
+    }
+
+    fn unlink(&self, name: &str) -> Result<(), SystemError> {
+        let mut inode: SpinLockGuard<TmpfsInode> = self.0.lock();
+        // 如果当前inode不是目录，那么也没有子目录/文件的概念了，因此要求当前inode的类型是目录
+        if inode.metadata.file_type != FileType::Dir {
+            return Err(SystemError::ENOTDIR);
+        }
+        // 不允许删除当前文件夹，也不允许删除上一个目录
+        if name == "." || name == ".." {
+            return Err(SystemError::ENOTEMPTY);
+        }
+
+        let name = DName::from(name);
+        // 获得要删除的文件的inode
+        let to_delete = inode.children.get(&name).ok_or(SystemError::ENOENT)?;
+        if to_delete.0.lock().metadata.file_type == FileType::Dir {
+            return Err(SystemError::EISDIR);
+        }
+        // 减少硬链接计数
+        to_delete.0.lock().metadata.nlinks -= 1;
+        // 在当前目录中删除这个子目录项
+        inode.children.remove(&name);
+        let now = PosixTimeSpec::now();
+        inode.metadata.mtime = now;
+        inode.metadata.ctime = now;
+        return Ok(());
+    }
+
+    fn rmdir(&self, name: &str) -> Result<(), SystemError> {
+        let name = DName::from(name);
+        let mut inode: SpinLockGuard<TmpfsInode> = self.0.lock();
+        // 如果当前inode不是目录，那么也没有子目录/文件的概念了，因此要求当前inode的类型是目录
+        if inode.metadata.file_type != FileType::Dir {
+            return Err(SystemError::ENOTDIR);
+        }
+        // 获得要删除的文件夹的inode
+        let to_delete = inode.children.get(&name).ok_or(SystemError::ENOENT)?;
+        let mut to_delete_guard = to_delete.0.lock();
+        if to_delete_guard.metadata.file_type != FileType::Dir {
+            return Err(SystemError::ENOTDIR);
+        }
+        if !to_delete_guard.children.is_empty() {
+            return Err(SystemError::ENOTEMPTY);
+        }
+
+        to_delete_guard.metadata.nlinks = 0;
+        drop(to_delete_guard);
+        // 在当前目录中删除这个子目录项
+        inode.children.remove(&name);
+        inode.metadata.nlinks -= 1;
+        let now = PosixTimeSpec::now();
+        inode.metadata.mtime = now;
+        inode.metadata.ctime = now;
+        return Ok(());
+    }
+
+    fn move_to(
+        &self,
+        old_name: &str,
+        target: &Arc<dyn IndexNode>,
+        new_name: &str,
+    ) -> Result<(), SystemError> {
+        let inode_to_move = self
+            .find(old_name)?
+            .downcast_arc::<LockedTmpfsInode>()
+            .ok_or(SystemError::EINVAL)?;
+        let target = target
+            .clone()
+            .downcast_arc::<LockedTmpfsInode>()
+            .ok_or(SystemError::EXDEV)?;
+
+        let new_name = DName::from(new_name);
+        let old_name = DName::from(old_name);
+        let is_dir = inode_to_move.0.lock().metadata.file_type == FileType::Dir;
+
+        // 判断是否在同一目录下, 是则进行重命名
+        if Arc::ptr_eq(&target, &self.0.lock().self_ref.upgrade().unwrap()) {
+            let mut self_inode = self.0.lock();
+            if let Some(old) = self_inode.children.get(&new_name) {
+                if Arc::ptr_eq(old, &inode_to_move) {
+                    return Ok(());
+                }
+                if Self::replace_target(old, is_dir)? {
+                    self_inode.metadata.nlinks -= 1;
+                }
+            }
+            self_inode.children.remove(&old_name);
+            self_inode
+                .children
+                .insert(new_name.clone(), inode_to_move.clone());
+            drop(self_inode);
+            inode_to_move.0.lock().name = new_name;
+            return Ok(());
+        }
+
+        let mut target_inode = target.0.lock();
+        if target_inode.metadata.file_type != FileType::Dir {
+            return Err(SystemError::ENOTDIR);
+        }
+        if let Some(old) = target_inode.children.get(&new_name) {
+            if Arc::ptr_eq(old, &inode_to_move) {
+                return Ok(());
+            }
+            if Self::replace_target(old, is_dir)? {
+                target_inode.metadata.nlinks -= 1;
+            }
+        }
+        target_inode
+            .children
+            .insert(new_name.clone(), inode_to_move.clone());
+        if is_dir {
+            target_inode.metadata.nlinks += 1;
+        }
+        drop(target_inode);
+
+        let mut self_inode = self.0.lock();
+        self_inode.children.remove(&old_name);
+        if is_dir {
+            self_inode.metadata.nlinks -= 1;
+        }
+        drop(self_inode);
+
+        // 修改其对父节点的引用
+        let mut guard = inode_to_move.0.lock();
+        guard.parent = Arc::downgrade(&target);
+        guard.name = new_name;
+
+        return Ok(());
+    }
+
+    fn find(&self, name: &str) -> Result<Arc<dyn IndexNode>, SystemError> {
+        let inode = self.0.lock();
+
+        if inode.metadata.file_type != FileType::Dir {
+            return Err(SystemError::ENOTDIR);
+        }
+
+        match name {
+            "" | "." => {
+                return Ok(inode.self_ref.upgrade().ok_or(SystemError::ENOENT)?);
+            }
+
+            ".." => {
+                return Ok(inode.parent.upgrade().ok_or(SystemError::ENOENT)?);
+            }
+            name => {
+                // 在子目录项中查找
+                let name = DName::from(name);
+                return Ok(inode
+                    .children
+                    .get(&name)
+                    .ok_or(SystemError::ENOENT)?
+                    .clone());
+            }
+        }
+    }
+
+    fn get_entry_name(&self, ino: InodeId) -> Result<String, SystemError> {
+        let inode: SpinLockGuard<TmpfsInode> = self.0.lock();
+        if inode.metadata.file_type != FileType::Dir {
+            return Err(SystemError::ENOTDIR);
+        }
+
+        match ino.into() {
+            0 => {
+                return Ok(String::from("."));
+            }
+            1 => {
+                return Ok(String::from(".."));
+            }
+            ino => {
+                let mut key: Vec<String> = inode
+                    .children
+                    .iter()
+                    .filter_map(|(k, v)| {
+                        if v.0.lock().metadata.inode_id.into() == ino {
+                            Some(k.to_string())
+                        } else {
+                            None
+                        }
+                    })
+                    .collect();
+
+                match key.len() {
+                    0 => {
+                        return Err(SystemError::ENOENT);
+                    }
+                    // 同一个inode可能在同一目录下有多个硬链接，任取一个即可
+                    _ => {
+                        return Ok(key.remove(0));
+                    }
+                }
+            }
+        }
+    }
+
+    fn list(&self) -> Result<Vec<String>, SystemError> {
+        let info = self.metadata()?;
+        if info.file_type != FileType::Dir {
+            return Err(SystemError::ENOTDIR);
+        }
+
+        let mut keys: Vec<String> = Vec::new();
+        keys.push(String::from("."));
+        keys.push(String::from(".."));
+        keys.append(
+            &mut self
+                .0
+                .lock()
+                .children
+                .keys()
+                .map(|k| k.to_string())
+                .collect(),
+        );
+
+        return Ok(keys);
+    }
+
+    fn mknod(
+        &self,
+        filename: &str,
+        mode: ModeType,
+        dev_t: DeviceNumber,
+    ) -> Result<Arc<dyn IndexNode>, SystemError> {
+        if self.0.lock().metadata.file_type != FileType::Dir {
+            return Err(SystemError::ENOTDIR);
+        }
+
+        // 判断需要创建的类型
+        let file_type = match mode & ModeType::S_IFMT {
+            ModeType::S_IFREG => {
+                // 普通文件
+                return self.create(filename, FileType::File, mode);
+            }
+            ModeType::S_IFIFO => FileType::Pipe,
+            ModeType::S_IFBLK => FileType::BlockDevice,
+            ModeType::S_IFCHR => FileType::CharDevice,
+            ModeType::S_IFSOCK => FileType::Socket,
+            _ => return Err(SystemError::EINVAL),
+        };
+
+        let nod = self.new_child(filename, file_type, mode, dev_t)?;
+        if file_type == FileType::Pipe {
+            // 创建pipe文件
+            let pipe_inode = LockedPipeInode::new();
+            // 设置special_node
+            nod.0.lock().special_node = Some(SpecialNodeData::Pipe(pipe_inode));
+        }
+
+        Ok(nod)
+    }
+
+    fn special_node(&self) -> Option<super::vfs::SpecialNodeData> {
+        return self.0.lock().special_node.clone();
+    }
+
+    fn getxattr(&self, name: &str, buf: &mut [u8]) -> Result<usize, SystemError> {
+        return self.0.lock().xattr.get(name, buf);
+    }
+
+    fn setxattr(&self, name: &str, value: &[u8], flags: XattrFlags) -> Result<(), SystemError> {
+        let mut inode = self.0.lock();
+        inode.xattr.set(name, value, flags)?;
+        inode.metadata.ctime = PosixTimeSpec::now();
+        return Ok(());
+    }
+
+    fn listxattr(&self, buf: &mut [u8]) -> Result<usize, SystemError> {
+        return self.0.lock().xattr.list(buf);
+    }
+
+    fn removexattr(&self, name: &str) -> Result<(), SystemError> {
+        let mut inode = self.0.lock();
+        inode.xattr.remove(name)?;
+        inode.metadata.ctime = PosixTimeSpec::now();
+        return Ok(());
+    }
+
+    fn get_seals(&self) -> Result<SealFlags, SystemError> {
+        let inode = self.0.lock();
+        if inode.metadata.file_type != FileType::File {
+            return Err(SystemError::EINVAL);
+        }
+        return Ok(inode.seals);
+    }
+
+    fn add_seals(&self, seals: SealFlags) -> Result<(), SystemError> {
+        let mut inode = self.0.lock();
+        if inode.metadata.file_type != FileType::File {
+            return Err(SystemError::EINVAL);
+        }
+        if inode.seals.contains(SealFlags::F_SEAL_SEAL) {
+            return Err(SystemError::EPERM);
+        }
+        // 可写共享映射的计数与seal在同一把锁下修改，检查之后不会再出现新的可写映射
+        if seals.contains(SealFlags::F_SEAL_WRITE)
+            && !inode.seals.contains(SealFlags::F_SEAL_WRITE)
+            && inode.mmap_writable > 0
+        {
+            return Err(SystemError::EBUSY);
+        }
+        inode.seals |= seals;
+        return Ok(());
+    }
+
+    fn mapping_map_writable(&self) -> Result<(), SystemError> {
+        let mut inode = self.0.lock();
+        if inode
+            .seals
+            .intersects(SealFlags::F_SEAL_WRITE | SealFlags::F_SEAL_FUTURE_WRITE)
+        {
+            return Err(SystemError::EPERM);
+        }
+        inode.mmap_writable += 1;
+        return Ok(());
+    }
+
+    fn mapping_allow_writable(&self) {
+        self.0.lock().mmap_writable += 1;
+    }
+
+    fn mapping_unmap_writable(&self) {
+        let mut inode = self.0.lock();
+        inode.mmap_writable = inode.mmap_writable.saturating_sub(1);
+    }
+
+    fn dname(&self) -> Result<DName, SystemError> {
+        Ok(self.0.lock().name.clone())
+    }
+
+    fn parent(&self) -> Result<Arc<dyn IndexNode>, SystemError> {
+        self.0
+            .lock()
+            .parent
+            .upgrade()
+            .map(|item| item as Arc<dyn IndexNode>)
+            .ok_or(SystemError::EINVAL)
+    }
+}
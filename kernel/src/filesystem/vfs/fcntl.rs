@@ -24,6 +24,13 @@ pub enum FcntlCommand {
     /// set record locking info (blocking)
     SetLockWait = 7,
 
+    /// get open file description locking info
+    GetOfdLock = 36,
+    /// set open file description locking info (non-blocking)
+    SetOfdLock = 37,
+    /// set open file description locking info (blocking)
+    SetOfdLockWait = 38,
+
     SetLease = F_LINUX_SPECIFIC_BASE,
     GetLease = F_LINUX_SPECIFIC_BASE + 1,
 
@@ -108,3 +115,19 @@ bitflags! {
 
 /// for F_[GET|SET]FL
 pub const FD_CLOEXEC: u32 = 1;
+
+bitflags! {
+    /// for F_ADD_SEALS/F_GET_SEALS
+    pub struct SealFlags: u32 {
+        /// 禁止再添加新的seal
+        const F_SEAL_SEAL = 0x0001;
+        /// 禁止缩小文件
+        const F_SEAL_SHRINK = 0x0002;
+        /// 禁止扩大文件
+        const F_SEAL_GROW = 0x0004;
+        /// 禁止写入文件
+        const F_SEAL_WRITE = 0x0008;
+        /// 禁止之后再建立可写的映射或者写入文件，已有的可写映射不受影响
+        const F_SEAL_FUTURE_WRITE = 0x0010;
+    }
+}
@@ -4,19 +4,29 @@ use alloc::{string::String, sync::Arc, vec::Vec};
 use log::error;
 use system_error::SystemError;
 
-use super::{Dirent, FileType, IndexNode, InodeId, Metadata, SpecialNodeData};
+use super::{
+    file_lock::{locks_remove_posix, FileLockHolder, FileLockOwner},
+    fsnotify::{fsnotify_access, fsnotify_close, fsnotify_modify},
+    Dirent, FileType, IndexNode, InodeId, Metadata, SpecialNodeData,
+};
 use crate::{
+    arch::ipc::signal::Signal,
     driver::{
         base::{block::SeekFrom, device::DevicePrivateData},
         tty::tty_device::TtyFilePrivateData,
     },
     filesystem::{
         epoll::{event_poll::EPollPrivateData, EPollItem},
+        inotify::InotifyPrivateData,
         procfs::ProcfsFilePrivateData,
     },
     ipc::pipe::PipeFsPrivateData,
     libs::{rwlock::RwLock, spinlock::SpinLock},
-    process::{cred::Cred, ProcessManager},
+    process::{
+        cred::Cred,
+        resource::{rlimit, send_rlimit_signal, RLimitID, RLIM_INFINITY},
+        ProcessManager,
+    },
 };
 
 /// 文件私有信息的枚举类型
@@ -33,6 +43,8 @@ pub enum FilePrivateData {
     Tty(TtyFilePrivateData),
     /// epoll私有信息
     EPoll(EPollPrivateData),
+    /// inotify私有信息
+    Inotify(InotifyPrivateData),
     /// 不需要文件私有信息
     Unused,
 }
@@ -45,8 +57,10 @@ impl Default for FilePrivateData {
 
 impl FilePrivateData {
     pub fn update_mode(&mut self, mode: FileMode) {
-        if let FilePrivateData::Pipefs(pdata) = self {
-            pdata.set_mode(mode);
+        match self {
+            FilePrivateData::Pipefs(pdata) => pdata.set_mode(mode),
+            FilePrivateData::Inotify(pdata) => pdata.set_mode(mode),
+            _ => {}
         }
     }
 }
@@ -128,6 +142,8 @@ pub struct File {
     pub private_data: SpinLock<FilePrivateData>,
     /// 文件的凭证
     cred: Cred,
+    /// 打开文件描述持有的flock锁和OFD锁
+    lock_holder: Arc<FileLockHolder>,
 }
 
 impl File {
@@ -145,6 +161,7 @@ impl File {
         }
 
         let f = File {
+            lock_holder: Arc::new(FileLockHolder::new(inode.clone())),
             inode,
             offset: AtomicUsize::new(0),
             mode: RwLock::new(mode),
@@ -247,6 +264,9 @@ impl File {
             self.offset
                 .fetch_add(len, core::sync::atomic::Ordering::SeqCst);
         }
+        if len > 0 {
+            fsnotify_access(&self.inode);
+        }
 
         Ok(len)
     }
@@ -263,6 +283,7 @@ impl File {
         if buf.len() < len {
             return Err(SystemError::ENOBUFS);
         }
+        let len = self.check_fsize_limit(offset, len)?;
 
         // 如果文件指针已经超过了文件大小，则需要扩展文件大小
         if offset > self.inode.metadata()?.size as usize {
@@ -276,10 +297,34 @@ impl File {
             self.offset
                 .fetch_add(len, core::sync::atomic::Ordering::SeqCst);
         }
+        if len > 0 {
+            fsnotify_modify(&self.inode);
+        }
 
         Ok(len)
     }
 
+    /// 检查写入是否超出RLIMIT_FSIZE
+    ///
+    /// ## 返回值
+    ///
+    /// - `Ok(usize)` 截断到资源限制以内的写入长度
+    /// - `Err(SystemError::EFBIG)` 写入的起始位置已经超出限制，此时会向当前进程发送SIGXFSZ
+    fn check_fsize_limit(&self, offset: usize, len: usize) -> Result<usize, SystemError> {
+        if self.file_type != FileType::File || len == 0 {
+            return Ok(len);
+        }
+        let limit = rlimit(RLimitID::Fsize);
+        if limit == RLIM_INFINITY {
+            return Ok(len);
+        }
+        if offset as u64 >= limit {
+            send_rlimit_signal(Signal::SIGXFSZ);
+            return Err(SystemError::EFBIG);
+        }
+        return Ok(len.min((limit - offset as u64) as usize));
+    }
+
     /// @brief 获取文件的元数据
     pub fn metadata(&self) -> Result<Metadata, SystemError> {
         return self.inode.metadata();
@@ -405,6 +450,18 @@ impl File {
         return self.inode.clone();
     }
 
+    /// 获取文件当前的偏移量
+    #[inline]
+    pub fn pos(&self) -> usize {
+        return self.offset.load(Ordering::SeqCst);
+    }
+
+    /// 获取打开文件描述在文件锁中的身份
+    #[inline]
+    pub fn lock_holder(&self) -> &Arc<FileLockHolder> {
+        return &self.lock_holder;
+    }
+
     /// @brief 尝试克隆一个文件
     ///
     /// @return Option<File> 克隆后的文件结构体。如果克隆失败，返回None
@@ -417,6 +474,7 @@ impl File {
             readdir_subdirs_name: SpinLock::new(self.readdir_subdirs_name.lock().clone()),
             private_data: SpinLock::new(self.private_data.lock().clone()),
             cred: self.cred.clone(),
+            lock_holder: self.lock_holder.clone(),
         };
         // 调用inode的open方法，让inode知道有新的文件打开了这个inode
         if self
@@ -479,9 +537,17 @@ impl File {
     pub fn ftruncate(&self, len: usize) -> Result<(), SystemError> {
         // 如果文件不可写，返回错误
         self.writeable()?;
+        if self.file_type == FileType::File {
+            let limit = rlimit(RLimitID::Fsize);
+            if limit != RLIM_INFINITY && len as u64 > limit {
+                send_rlimit_signal(Signal::SIGXFSZ);
+                return Err(SystemError::EFBIG);
+            }
+        }
 
         // 调用inode的truncate方法
         self.inode.resize(len)?;
+        fsnotify_modify(&self.inode);
         return Ok(());
     }
 
@@ -520,6 +586,12 @@ impl Drop for File {
                 r.as_ref().unwrap_err()
             );
         }
+
+        // 只有共享同一打开文件描述的最后一个File关闭时才产生关闭事件
+        if Arc::strong_count(&self.lock_holder) == 1 {
+            let writable = self.mode().accmode() != FileMode::O_RDONLY.bits();
+            fsnotify_close(&self.inode, writable);
+        }
     }
 }
 
@@ -579,7 +651,13 @@ impl FileDescriptorVec {
     /// @return false 不合法
     #[inline]
     pub fn validate_fd(fd: i32) -> bool {
-        return !(fd < 0 || fd as usize > FileDescriptorVec::PROCESS_MAX_FD);
+        return !(fd < 0 || fd as usize >= FileDescriptorVec::PROCESS_MAX_FD);
+    }
+
+    /// 当前进程可以使用的文件描述符数量，受RLIMIT_NOFILE限制
+    pub fn max_fds() -> usize {
+        let limit = rlimit(RLimitID::Nofile);
+        return limit.min(FileDescriptorVec::PROCESS_MAX_FD as u64) as usize;
     }
 
     /// 申请文件描述符，并把文件对象存入其中。
@@ -587,14 +665,19 @@ impl FileDescriptorVec {
     /// ## 参数
     ///
     /// - `file` 要存放的文件对象
-    /// - `fd` 如果为Some(i32)，表示指定要申请这个文件描述符，如果这个文件描述符已经被使用，那么返回EBADF
+    /// - `fd` 如果为Some(i32)，表示指定要申请这个文件描述符，如果这个文件描述符已经被使用，
+    ///   或者超出了RLIMIT_NOFILE的限制，那么返回EBADF
     ///
     /// ## 返回值
     ///
     /// - `Ok(i32)` 申请成功，返回申请到的文件描述符
     /// - `Err(SystemError)` 申请失败，返回错误码，并且，file对象将被drop掉
     pub fn alloc_fd(&mut self, file: File, fd: Option<i32>) -> Result<i32, SystemError> {
+        let max_fds = FileDescriptorVec::max_fds();
         if let Some(new_fd) = fd {
+            if new_fd < 0 || new_fd as usize >= max_fds {
+                return Err(SystemError::EBADF);
+            }
             let x = &mut self.fds[new_fd as usize];
             if x.is_none() {
                 *x = Some(Arc::new(file));
@@ -604,7 +687,7 @@ impl FileDescriptorVec {
             }
         } else {
             // 没有指定要申请的文件描述符编号
-            for i in 0..FileDescriptorVec::PROCESS_MAX_FD {
+            for i in 0..max_fds {
                 if self.fds[i].is_none() {
                     self.fds[i] = Some(Arc::new(file));
                     return Ok(i as i32);
@@ -636,9 +719,16 @@ impl FileDescriptorVec {
 
         // 把文件描述符数组对应位置设置为空
         let file = self.fds[fd as usize].take().unwrap();
+        // 关闭任意一个文件描述符都会释放本进程在该inode上的POSIX记录锁
+        locks_remove_posix(&file.inode(), self.lock_owner());
         return Ok(file);
     }
 
+    /// 获取本文件描述符表作为POSIX记录锁持有者的身份
+    pub fn lock_owner(&self) -> FileLockOwner {
+        return FileLockOwner::Posix(self as *const Self as usize);
+    }
+
     #[allow(dead_code)]
     pub fn iter(&self) -> FileDescriptorIterator {
         return FileDescriptorIterator::new(self);
@@ -663,6 +753,16 @@ impl FileDescriptorVec {
     }
 }
 
+impl Drop for FileDescriptorVec {
+    fn drop(&mut self) {
+        // 文件描述符表被释放时，释放其持有的所有POSIX记录锁
+        let owner = self.lock_owner();
+        for file in self.fds.iter().flatten() {
+            locks_remove_posix(&file.inode(), owner);
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct FileDescriptorIterator<'a> {
     fds: &'a FileDescriptorVec,
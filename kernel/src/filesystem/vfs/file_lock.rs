@@ -0,0 +1,553 @@
+//! 建议性文件锁
+//!
+//! 包括flock(2)的整文件锁，以及fcntl(2)的POSIX记录锁和OFD(open file description)锁。
+//!
+//! - flock锁和OFD锁属于打开文件描述，dup和fork得到的文件共享同一把锁，在最后一个引用关闭时释放。
+//! - POSIX记录锁属于进程的文件描述符表，关闭该inode的任意一个文件描述符，或者进程退出时释放。
+//!
+//! flock锁与记录锁互不影响；POSIX锁与OFD锁之间会相互冲突。
+
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use alloc::{collections::BTreeMap, sync::Arc, vec::Vec};
+use system_error::SystemError;
+
+use crate::{
+    libs::{
+        spinlock::{SpinLock, SpinLockGuard},
+        wait_queue::WaitQueue,
+    },
+    process::ProcessManager,
+};
+
+use super::{
+    file::{File, FileMode},
+    IndexNode, InodeId,
+};
+
+pub const F_RDLCK: i16 = 0;
+pub const F_WRLCK: i16 = 1;
+pub const F_UNLCK: i16 = 2;
+
+const SEEK_SET: i16 = 0;
+const SEEK_CUR: i16 = 1;
+const SEEK_END: i16 = 2;
+
+/// 记录锁能表示的最大偏移量
+const OFFSET_MAX: i64 = i64::MAX;
+/// 死锁检测时，沿等待关系查找的最大步数
+const MAX_DEADLK_ITERATIONS: usize = 10;
+
+bitflags! {
+    /// flock(2)的操作
+    pub struct FlockOperation: u32 {
+        /// 共享锁
+        const LOCK_SH = 1;
+        /// 排他锁
+        const LOCK_EX = 2;
+        /// 非阻塞
+        const LOCK_NB = 4;
+        /// 解锁
+        const LOCK_UN = 8;
+    }
+}
+
+/// 用户态的`struct flock`
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PosixFlock {
+    pub l_type: i16,
+    pub l_whence: i16,
+    pub l_start: i64,
+    pub l_len: i64,
+    pub l_pid: i32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FileLockType {
+    Read,
+    Write,
+}
+
+impl FileLockType {
+    fn conflicts_with(&self, other: FileLockType) -> bool {
+        *self == FileLockType::Write || other == FileLockType::Write
+    }
+
+    fn to_flock_type(self) -> i16 {
+        match self {
+            FileLockType::Read => F_RDLCK,
+            FileLockType::Write => F_WRLCK,
+        }
+    }
+}
+
+/// 文件锁的持有者
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum FileLockOwner {
+    /// POSIX记录锁，持有者为进程的文件描述符表
+    Posix(usize),
+    /// flock锁和OFD锁，持有者为打开文件描述
+    File(usize),
+}
+
+#[derive(Debug, Clone)]
+struct FileLock {
+    owner: FileLockOwner,
+    lock_type: FileLockType,
+    /// 锁定区间的起始偏移（包含）
+    start: i64,
+    /// 锁定区间的结束偏移（包含），`OFFSET_MAX`表示一直到文件末尾
+    end: i64,
+    /// 加锁进程的pid，F_GETLK时返回给用户
+    pid: i32,
+}
+
+impl FileLock {
+    fn overlaps(&self, start: i64, end: i64) -> bool {
+        self.start <= end && start <= self.end
+    }
+
+    fn conflicts_with(&self, other: &FileLock) -> bool {
+        self.owner != other.owner
+            && self.overlaps(other.start, other.end)
+            && self.lock_type.conflicts_with(other.lock_type)
+    }
+}
+
+#[derive(Debug, Default)]
+struct InnerFileLockContext {
+    flock: Vec<FileLock>,
+    posix: Vec<FileLock>,
+}
+
+impl InnerFileLockContext {
+    fn is_empty(&self) -> bool {
+        self.flock.is_empty() && self.posix.is_empty()
+    }
+}
+
+/// 一个inode上的所有文件锁
+#[derive(Debug)]
+struct FileLockContext {
+    inner: SpinLock<InnerFileLockContext>,
+    /// 等待锁被释放的进程
+    wait_queue: WaitQueue,
+}
+
+type FileLockKey = (usize, InodeId);
+
+/// 所有持有文件锁的inode，以(dev_id, inode_id)为键
+static FILE_LOCK_CONTEXTS: SpinLock<BTreeMap<FileLockKey, Arc<FileLockContext>>> =
+    SpinLock::new(BTreeMap::new());
+
+/// POSIX锁的等待关系：等待者 -> 阻塞它的锁的持有者，用于死锁检测
+static BLOCKED_POSIX_OWNERS: SpinLock<BTreeMap<FileLockOwner, FileLockOwner>> =
+    SpinLock::new(BTreeMap::new());
+
+fn lock_key(inode: &Arc<dyn IndexNode>) -> Result<FileLockKey, SystemError> {
+    let metadata = inode.metadata()?;
+    Ok((metadata.dev_id, metadata.inode_id))
+}
+
+/// 获取inode的文件锁上下文，`create`为true时若不存在则创建
+fn get_context(key: FileLockKey, create: bool) -> Option<Arc<FileLockContext>> {
+    let mut contexts = FILE_LOCK_CONTEXTS.lock();
+    if let Some(ctx) = contexts.get(&key) {
+        return Some(ctx.clone());
+    }
+    if !create {
+        return None;
+    }
+    let ctx = Arc::new(FileLockContext {
+        inner: SpinLock::new(InnerFileLockContext::default()),
+        wait_queue: WaitQueue::default(),
+    });
+    contexts.insert(key, ctx.clone());
+    Some(ctx)
+}
+
+/// 如果inode上已经没有锁，也没有等待者，则释放其上下文
+fn put_context(key: FileLockKey, ctx: Arc<FileLockContext>) {
+    let mut contexts = FILE_LOCK_CONTEXTS.lock();
+    // 全局表和调用者各持有一个引用
+    if Arc::strong_count(&ctx) == 2 && ctx.inner.lock().is_empty() {
+        contexts.remove(&key);
+    }
+}
+
+/// 打开文件描述在文件锁中的身份
+///
+/// dup和fork得到的文件共享同一个`FileLockHolder`，最后一个引用被释放时，
+/// 该打开文件描述持有的flock锁和OFD锁随之释放。
+#[derive(Debug)]
+pub struct FileLockHolder {
+    inode: Arc<dyn IndexNode>,
+    /// 是否曾经加过锁
+    used: AtomicBool,
+}
+
+impl FileLockHolder {
+    pub fn new(inode: Arc<dyn IndexNode>) -> Self {
+        Self {
+            inode,
+            used: AtomicBool::new(false),
+        }
+    }
+
+    pub fn owner(&self) -> FileLockOwner {
+        FileLockOwner::File(self as *const Self as usize)
+    }
+}
+
+impl Drop for FileLockHolder {
+    fn drop(&mut self) {
+        if self.used.load(Ordering::SeqCst) {
+            locks_remove_owner(&self.inode, self.owner());
+        }
+    }
+}
+
+/// 释放`owner`在inode上持有的所有锁
+fn locks_remove_owner(inode: &Arc<dyn IndexNode>, owner: FileLockOwner) {
+    if FILE_LOCK_CONTEXTS.lock().is_empty() {
+        return;
+    }
+    let Ok(key) = lock_key(inode) else {
+        return;
+    };
+    let Some(ctx) = get_context(key, false) else {
+        return;
+    };
+
+    let mut inner = ctx.inner.lock();
+    let len = inner.flock.len() + inner.posix.len();
+    inner.flock.retain(|l| l.owner != owner);
+    inner.posix.retain(|l| l.owner != owner);
+    let removed = len != inner.flock.len() + inner.posix.len();
+    drop(inner);
+
+    if removed {
+        ctx.wait_queue.wakeup_all(None);
+    }
+    put_context(key, ctx);
+}
+
+/// 关闭文件描述符时，释放文件描述符表在该inode上持有的POSIX记录锁
+pub fn locks_remove_posix(inode: &Arc<dyn IndexNode>, owner: FileLockOwner) {
+    locks_remove_owner(inode, owner);
+}
+
+/// 在锁上下文的等待队列上睡眠，直到被唤醒或者收到信号
+fn wait_for_unlock(
+    ctx: &FileLockContext,
+    inner: SpinLockGuard<InnerFileLockContext>,
+) -> Result<(), SystemError> {
+    ctx.wait_queue.sleep_unlock_spinlock(inner)?;
+    if ProcessManager::current_pcb().has_pending_signal_fast() {
+        return Err(SystemError::ERESTARTSYS);
+    }
+    Ok(())
+}
+
+/// flock(2)：对整个文件加锁或解锁
+///
+/// ## 参数
+///
+/// - `file` 要加锁的文件
+/// - `operation` LOCK_SH、LOCK_EX或LOCK_UN，可以与LOCK_NB组合
+pub fn flock(file: &File, operation: FlockOperation) -> Result<(), SystemError> {
+    let lock_type = match operation & !FlockOperation::LOCK_NB {
+        FlockOperation::LOCK_SH => Some(FileLockType::Read),
+        FlockOperation::LOCK_EX => Some(FileLockType::Write),
+        FlockOperation::LOCK_UN => None,
+        _ => return Err(SystemError::EINVAL),
+    };
+
+    let holder = file.lock_holder();
+    let owner = holder.owner();
+    let inode = file.inode();
+    let Some(lock_type) = lock_type else {
+        locks_remove_flock(&inode, owner)?;
+        return Ok(());
+    };
+
+    let key = lock_key(&inode)?;
+    let ctx = get_context(key, true).unwrap();
+    holder.used.store(true, Ordering::SeqCst);
+    let new_lock = FileLock {
+        owner,
+        lock_type,
+        start: 0,
+        end: OFFSET_MAX,
+        pid: ProcessManager::current_pcb().tgid().data() as i32,
+    };
+
+    let r = loop {
+        let mut inner = ctx.inner.lock();
+        // 已经持有的锁先释放，再尝试获取新锁（锁的升级和降级不是原子的）
+        let len = inner.flock.len();
+        inner.flock.retain(|l| l.owner != owner);
+        if inner.flock.len() != len {
+            ctx.wait_queue.wakeup_all(None);
+        }
+
+        if !inner.flock.iter().any(|l| l.conflicts_with(&new_lock)) {
+            inner.flock.push(new_lock.clone());
+            break Ok(());
+        }
+        if operation.contains(FlockOperation::LOCK_NB) {
+            break Err(SystemError::EAGAIN_OR_EWOULDBLOCK);
+        }
+        if let Err(e) = wait_for_unlock(&ctx, inner) {
+            break Err(e);
+        }
+    };
+    put_context(key, ctx);
+    r
+}
+
+fn locks_remove_flock(inode: &Arc<dyn IndexNode>, owner: FileLockOwner) -> Result<(), SystemError> {
+    let key = lock_key(inode)?;
+    let Some(ctx) = get_context(key, false) else {
+        return Ok(());
+    };
+    let mut inner = ctx.inner.lock();
+    let len = inner.flock.len();
+    inner.flock.retain(|l| l.owner != owner);
+    let removed = inner.flock.len() != len;
+    drop(inner);
+    if removed {
+        ctx.wait_queue.wakeup_all(None);
+    }
+    put_context(key, ctx);
+    Ok(())
+}
+
+/// 将用户传入的`struct flock`转换为锁定区间
+fn flock_to_range(file: &File, fl: &PosixFlock) -> Result<(i64, i64), SystemError> {
+    let base = match fl.l_whence {
+        SEEK_SET => 0,
+        SEEK_CUR => file.pos() as i64,
+        SEEK_END => file.metadata()?.size,
+        _ => return Err(SystemError::EINVAL),
+    };
+    let mut start = base.checked_add(fl.l_start).ok_or(SystemError::EOVERFLOW)?;
+    if start < 0 {
+        return Err(SystemError::EINVAL);
+    }
+
+    let end = if fl.l_len > 0 {
+        start
+            .checked_add(fl.l_len - 1)
+            .ok_or(SystemError::EOVERFLOW)?
+    } else if fl.l_len < 0 {
+        // 负数长度表示锁定[start + len, start - 1]
+        let end = start - 1;
+        start += fl.l_len;
+        if start < 0 {
+            return Err(SystemError::EINVAL);
+        }
+        end
+    } else {
+        OFFSET_MAX
+    };
+    Ok((start, end))
+}
+
+/// 检查`waiter`等待`blocker`是否会造成死锁
+fn posix_locks_deadlock(waiter: FileLockOwner, blocker: FileLockOwner) -> bool {
+    // OFD锁不参与死锁检测
+    if !matches!(waiter, FileLockOwner::Posix(_)) {
+        return false;
+    }
+    let blocked = BLOCKED_POSIX_OWNERS.lock();
+    let mut owner = blocker;
+    for _ in 0..MAX_DEADLK_ITERATIONS {
+        if owner == waiter {
+            return true;
+        }
+        match blocked.get(&owner) {
+            Some(next) => owner = *next,
+            None => return false,
+        }
+    }
+    false
+}
+
+/// F_GETLK / F_OFD_GETLK：查询与请求的锁冲突的第一把锁
+///
+/// ## 参数
+///
+/// - `file` 目标文件
+/// - `owner` 请求者
+/// - `fl` 请求的锁，返回时被填写为冲突的锁；没有冲突时`l_type`被设置为F_UNLCK
+pub fn posix_getlk(
+    file: &File,
+    owner: FileLockOwner,
+    fl: &mut PosixFlock,
+) -> Result<(), SystemError> {
+    let lock_type = match fl.l_type {
+        F_RDLCK => FileLockType::Read,
+        F_WRLCK => FileLockType::Write,
+        _ => return Err(SystemError::EINVAL),
+    };
+    let (start, end) = flock_to_range(file, fl)?;
+    let request = FileLock {
+        owner,
+        lock_type,
+        start,
+        end,
+        pid: 0,
+    };
+
+    fl.l_type = F_UNLCK;
+    let key = lock_key(&file.inode())?;
+    let Some(ctx) = get_context(key, false) else {
+        return Ok(());
+    };
+    let inner = ctx.inner.lock();
+    if let Some(l) = inner.posix.iter().find(|l| l.conflicts_with(&request)) {
+        fl.l_type = l.lock_type.to_flock_type();
+        fl.l_whence = SEEK_SET;
+        fl.l_start = l.start;
+        fl.l_len = if l.end == OFFSET_MAX {
+            0
+        } else {
+            l.end - l.start + 1
+        };
+        fl.l_pid = match l.owner {
+            FileLockOwner::Posix(_) => l.pid,
+            FileLockOwner::File(_) => -1,
+        };
+    }
+    drop(inner);
+    put_context(key, ctx);
+    Ok(())
+}
+
+/// F_SETLK / F_SETLKW / F_OFD_SETLK / F_OFD_SETLKW：加锁或解锁一段区间
+///
+/// ## 参数
+///
+/// - `file` 目标文件
+/// - `owner` 锁的持有者
+/// - `fl` 用户传入的`struct flock`
+/// - `wait` 存在冲突时是否等待
+///
+/// ## 返回值
+///
+/// - `Err(SystemError::EAGAIN_OR_EWOULDBLOCK)` 存在冲突且不等待
+/// - `Err(SystemError::EDEADLK)` 等待会造成死锁
+/// - `Err(SystemError::EBADF)` 文件的打开模式不允许加该类型的锁
+pub fn posix_setlk(
+    file: &File,
+    owner: FileLockOwner,
+    fl: &PosixFlock,
+    wait: bool,
+) -> Result<(), SystemError> {
+    let accmode = file.mode().accmode();
+    let lock_type = match fl.l_type {
+        F_RDLCK => {
+            if accmode == FileMode::O_WRONLY.bits() {
+                return Err(SystemError::EBADF);
+            }
+            Some(FileLockType::Read)
+        }
+        F_WRLCK => {
+            if accmode == FileMode::O_RDONLY.bits() {
+                return Err(SystemError::EBADF);
+            }
+            Some(FileLockType::Write)
+        }
+        F_UNLCK => None,
+        _ => return Err(SystemError::EINVAL),
+    };
+    let (start, end) = flock_to_range(file, fl)?;
+
+    let key = lock_key(&file.inode())?;
+    let Some(ctx) = get_context(key, lock_type.is_some()) else {
+        // 没有任何锁，解锁操作直接返回
+        return Ok(());
+    };
+    if matches!(owner, FileLockOwner::File(_)) {
+        file.lock_holder().used.store(true, Ordering::SeqCst);
+    }
+
+    let request = FileLock {
+        owner,
+        lock_type: lock_type.unwrap_or(FileLockType::Read),
+        start,
+        end,
+        pid: ProcessManager::current_pcb().tgid().data() as i32,
+    };
+
+    let r = loop {
+        let mut inner = ctx.inner.lock();
+        if lock_type.is_some() {
+            if let Some(blocker) = inner.posix.iter().find(|l| l.conflicts_with(&request)) {
+                if !wait {
+                    break Err(SystemError::EAGAIN_OR_EWOULDBLOCK);
+                }
+                let blocker = blocker.owner;
+                if posix_locks_deadlock(owner, blocker) {
+                    break Err(SystemError::EDEADLK);
+                }
+
+                BLOCKED_POSIX_OWNERS.lock().insert(owner, blocker);
+                let r = wait_for_unlock(&ctx, inner);
+                BLOCKED_POSIX_OWNERS.lock().remove(&owner);
+                if let Err(e) = r {
+                    break Err(e);
+                }
+                continue;
+            }
+        }
+
+        posix_lock_apply(&mut inner.posix, &request, lock_type.is_none());
+        drop(inner);
+        // 锁的范围缩小或类型降级后，可能有等待者可以继续
+        ctx.wait_queue.wakeup_all(None);
+        break Ok(());
+    };
+    put_context(key, ctx);
+    r
+}
+
+/// 将请求应用到锁列表上：切分或删除持有者已有的、与请求区间重叠的锁，
+/// 合并相邻的同类型锁，然后（对于加锁请求）插入新锁
+fn posix_lock_apply(locks: &mut Vec<FileLock>, request: &FileLock, unlock: bool) {
+    let mut new_lock = request.clone();
+    let mut result = Vec::with_capacity(locks.len() + 2);
+    for l in locks.drain(..) {
+        if l.owner != request.owner {
+            result.push(l);
+            continue;
+        }
+
+        let adjacent = l.end.checked_add(1) == Some(request.start)
+            || request.end.checked_add(1) == Some(l.start);
+        let overlaps = l.overlaps(request.start, request.end);
+        if !unlock && l.lock_type == request.lock_type && (overlaps || adjacent) {
+            new_lock.start = new_lock.start.min(l.start);
+            new_lock.end = new_lock.end.max(l.end);
+        } else if overlaps {
+            if l.start < request.start {
+                let mut left = l.clone();
+                left.end = request.start - 1;
+                result.push(left);
+            }
+            if l.end > request.end {
+                let mut right = l;
+                right.start = request.end + 1;
+                result.push(right);
+            }
+        } else {
+            result.push(l);
+        }
+    }
+    if !unlock {
+        result.push(new_lock);
+    }
+    *locks = result;
+}
@@ -0,0 +1,175 @@
+//! VFS的文件变化通知钩子
+//!
+//! VFS在完成各类操作后调用这里的函数，由它们把事件转发给inotify。
+//! 当系统中不存在任何监视时，所有钩子都会直接返回。
+
+use core::sync::atomic::{AtomicU32, Ordering};
+
+use alloc::sync::Arc;
+
+use crate::filesystem::inotify::{
+    inotify_handle_event, inotify_has_watches, inotify_inode_key, inotify_inode_removed,
+    InotifyMask,
+};
+
+use super::{FileType, IndexNode};
+
+/// 用于关联IN_MOVED_FROM和IN_MOVED_TO的cookie
+static MOVE_COOKIE: AtomicU32 = AtomicU32::new(1);
+
+fn is_dir(inode: &Arc<dyn IndexNode>) -> bool {
+    inode
+        .metadata()
+        .is_ok_and(|metadata| metadata.file_type == FileType::Dir)
+}
+
+fn dir_flag(is_dir: bool) -> InotifyMask {
+    if is_dir {
+        InotifyMask::IN_ISDIR
+    } else {
+        InotifyMask::empty()
+    }
+}
+
+/// 向目录的监视者发送发生在其子项`name`上的事件
+fn notify_dir(dir: &Arc<dyn IndexNode>, mask: InotifyMask, cookie: u32, name: &str) {
+    if let Ok(key) = inotify_inode_key(dir) {
+        inotify_handle_event(key, mask, cookie, Some(name));
+    }
+}
+
+/// 向inode自身以及其父目录的监视者发送事件
+///
+/// 父目录通过`parent()`和`dname()`获取，文件系统不支持时只通知inode自身
+fn notify_inode(inode: &Arc<dyn IndexNode>, mask: InotifyMask) {
+    let mask = mask | dir_flag(is_dir(inode));
+    if let Ok(key) = inotify_inode_key(inode) {
+        inotify_handle_event(key, mask, 0, None);
+    }
+
+    if let (Ok(parent), Ok(name)) = (inode.parent(), inode.dname()) {
+        // 文件系统的根目录的父目录是其自身
+        if inotify_inode_key(&parent).ok() != inotify_inode_key(inode).ok() {
+            notify_dir(&parent, mask, 0, name.as_ref());
+        }
+    }
+}
+
+/// 在目录`dir`中创建了`name`
+pub fn fsnotify_create(dir: &Arc<dyn IndexNode>, name: &str, is_dir: bool) {
+    if !inotify_has_watches() {
+        return;
+    }
+    notify_dir(dir, InotifyMask::IN_CREATE | dir_flag(is_dir), 0, name);
+}
+
+/// 在目录`dir`中创建了指向`inode`的硬链接`name`
+pub fn fsnotify_link(dir: &Arc<dyn IndexNode>, name: &str, inode: &Arc<dyn IndexNode>) {
+    if !inotify_has_watches() {
+        return;
+    }
+    notify_dir(dir, InotifyMask::IN_CREATE, 0, name);
+    if let Ok(key) = inotify_inode_key(inode) {
+        inotify_handle_event(key, InotifyMask::IN_ATTRIB, 0, None);
+    }
+}
+
+/// 从目录`dir`中删除了`name`
+///
+/// ## 参数
+///
+/// - `dir` 父目录
+/// - `name` 被删除的目录项名
+/// - `inode` 被删除的inode，需要在删除之前获取
+/// - `last_link` 删除后inode是否已经不存在任何链接（目录总是为true）
+pub fn fsnotify_delete(
+    dir: &Arc<dyn IndexNode>,
+    name: &str,
+    inode: &Arc<dyn IndexNode>,
+    is_dir: bool,
+    last_link: bool,
+) {
+    if !inotify_has_watches() {
+        return;
+    }
+    let key = inotify_inode_key(inode).ok();
+    if !last_link {
+        // 链接数减少
+        if let Some(key) = key {
+            inotify_handle_event(key, InotifyMask::IN_ATTRIB, 0, None);
+        }
+    }
+    notify_dir(dir, InotifyMask::IN_DELETE | dir_flag(is_dir), 0, name);
+    if last_link {
+        if let Some(key) = key {
+            inotify_handle_event(key, InotifyMask::IN_DELETE_SELF, 0, None);
+            inotify_inode_removed(key);
+        }
+    }
+}
+
+/// `inode`从`old_dir/old_name`移动到了`new_dir/new_name`
+pub fn fsnotify_move(
+    old_dir: &Arc<dyn IndexNode>,
+    old_name: &str,
+    new_dir: &Arc<dyn IndexNode>,
+    new_name: &str,
+    inode: &Arc<dyn IndexNode>,
+    is_dir: bool,
+) {
+    if !inotify_has_watches() {
+        return;
+    }
+    let cookie = MOVE_COOKIE.fetch_add(1, Ordering::Relaxed);
+    let flag = dir_flag(is_dir);
+    notify_dir(old_dir, InotifyMask::IN_MOVED_FROM | flag, cookie, old_name);
+    notify_dir(new_dir, InotifyMask::IN_MOVED_TO | flag, cookie, new_name);
+    if let Ok(key) = inotify_inode_key(inode) {
+        inotify_handle_event(key, InotifyMask::IN_MOVE_SELF, 0, None);
+    }
+}
+
+/// 文件内容被修改
+pub fn fsnotify_modify(inode: &Arc<dyn IndexNode>) {
+    if !inotify_has_watches() {
+        return;
+    }
+    notify_inode(inode, InotifyMask::IN_MODIFY);
+}
+
+/// 文件被读取
+pub fn fsnotify_access(inode: &Arc<dyn IndexNode>) {
+    if !inotify_has_watches() {
+        return;
+    }
+    notify_inode(inode, InotifyMask::IN_ACCESS);
+}
+
+/// 文件被打开
+pub fn fsnotify_open(inode: &Arc<dyn IndexNode>) {
+    if !inotify_has_watches() {
+        return;
+    }
+    notify_inode(inode, InotifyMask::IN_OPEN);
+}
+
+/// 文件被关闭，`writable`表示文件是否以可写方式打开
+pub fn fsnotify_close(inode: &Arc<dyn IndexNode>, writable: bool) {
+    if !inotify_has_watches() {
+        return;
+    }
+    let mask = if writable {
+        InotifyMask::IN_CLOSE_WRITE
+    } else {
+        InotifyMask::IN_CLOSE_NOWRITE
+    };
+    notify_inode(inode, mask);
+}
+
+/// 文件的元数据（权限、所有者、时间戳、扩展属性等）被修改
+pub fn fsnotify_attrib(inode: &Arc<dyn IndexNode>) {
+    if !inotify_has_watches() {
+        return;
+    }
+    notify_inode(inode, InotifyMask::IN_ATTRIB);
+}
@@ -1,12 +1,17 @@
 pub mod fcntl;
 pub mod file;
+pub mod file_lock;
+pub mod fsnotify;
 pub mod iov;
 pub mod mount;
 pub mod open;
+pub mod permission;
+pub mod posix_acl;
 pub mod stat;
 pub mod syscall;
 pub mod utils;
 pub mod vcore;
+pub mod xattr;
 
 use ::core::{any::Any, fmt::Debug, sync::atomic::AtomicUsize};
 use alloc::{string::String, sync::Arc, vec::Vec};
@@ -28,7 +33,15 @@ use crate::{
     time::PosixTimeSpec,
 };
 
-use self::{file::FileMode, syscall::ModeType, utils::DName, vcore::generate_inode_id};
+use self::{
+    fcntl::SealFlags,
+    file::FileMode,
+    permission::{generic_permission, PermissionMask},
+    syscall::ModeType,
+    utils::DName,
+    vcore::generate_inode_id,
+    xattr::XattrFlags,
+};
 pub use self::{file::FilePrivateData, mount::MountFS, vcore::ROOT_INODE};
 
 use super::page_cache::PageCache;
@@ -648,6 +661,86 @@ pub trait IndexNode: Any + Sync + Send + Debug + CastFromSync {
         None
     }
 
+    /// # 获取扩展属性
+    ///
+    /// ## 参数
+    ///
+    /// - `name` 带有命名空间前缀的属性名
+    /// - `buf` 存放属性值的缓冲区，长度为0时只返回属性值的长度
+    ///
+    /// ## 返回值
+    ///
+    /// - `Ok(usize)` 属性值的长度
+    /// - `Err(SystemError)` 错误码，属性不存在时返回ENODATA
+    fn getxattr(&self, _name: &str, _buf: &mut [u8]) -> Result<usize, SystemError> {
+        return Err(SystemError::EOPNOTSUPP_OR_ENOTSUP);
+    }
+
+    /// # 设置扩展属性
+    ///
+    /// ## 参数
+    ///
+    /// - `name` 带有命名空间前缀的属性名
+    /// - `value` 属性值
+    /// - `flags` XATTR_CREATE/XATTR_REPLACE
+    fn setxattr(&self, _name: &str, _value: &[u8], _flags: XattrFlags) -> Result<(), SystemError> {
+        return Err(SystemError::EOPNOTSUPP_OR_ENOTSUP);
+    }
+
+    /// # 列出所有扩展属性名
+    ///
+    /// 属性名之间以'\0'分隔，`buf`长度为0时只返回所需的长度
+    fn listxattr(&self, _buf: &mut [u8]) -> Result<usize, SystemError> {
+        return Err(SystemError::EOPNOTSUPP_OR_ENOTSUP);
+    }
+
+    /// # 删除扩展属性
+    fn removexattr(&self, _name: &str) -> Result<(), SystemError> {
+        return Err(SystemError::EOPNOTSUPP_OR_ENOTSUP);
+    }
+
+    /// # 获取文件的seal
+    ///
+    /// 不支持seal的文件返回EINVAL
+    fn get_seals(&self) -> Result<SealFlags, SystemError> {
+        return Err(SystemError::EINVAL);
+    }
+
+    /// # 为文件添加seal
+    ///
+    /// ## 参数
+    ///
+    /// - `seals` 需要添加的seal
+    ///
+    /// ## 返回值
+    ///
+    /// - `Err(SystemError::EPERM)` 文件已经带有F_SEAL_SEAL
+    /// - `Err(SystemError::EBUSY)` 添加F_SEAL_WRITE时文件仍存在可写的共享映射
+    /// - `Err(SystemError::EINVAL)` 文件不支持seal
+    fn add_seals(&self, _seals: SealFlags) -> Result<(), SystemError> {
+        return Err(SystemError::EINVAL);
+    }
+
+    /// # 准备建立可写的共享映射
+    ///
+    /// mmap在创建VMA之前调用，与[`IndexNode::add_seals`]在同一把锁下检查seal并增加可写共享映射的计数，
+    /// VMA创建完成后调用[`IndexNode::mapping_unmap_writable`]释放这次计数
+    ///
+    /// ## 返回值
+    ///
+    /// - `Err(SystemError::EPERM)` 文件被F_SEAL_WRITE或F_SEAL_FUTURE_WRITE封印
+    fn mapping_map_writable(&self) -> Result<(), SystemError> {
+        return Ok(());
+    }
+
+    /// # 增加可写共享映射的计数
+    ///
+    /// 带有VM_SHARED和VM_MAYWRITE的VMA被创建时调用（mmap、fork以及VMA的切分）
+    fn mapping_allow_writable(&self) {}
+
+    /// # 减少可写共享映射的计数
+    fn mapping_unmap_writable(&self) {}
+
     /// Transform the inode to a pollable inode
     ///
     /// If the inode is not pollable, return an error
@@ -734,9 +827,12 @@ impl dyn IndexNode {
         // 逐级查找文件
         while !rest_path.is_empty() {
             // 当前这一级不是文件夹
-            if result.metadata()?.file_type != FileType::Dir {
+            let metadata = result.metadata()?;
+            if metadata.file_type != FileType::Dir {
                 return Err(SystemError::ENOTDIR);
             }
+            // 需要有目录的搜索权限
+            generic_permission(&result, &metadata, PermissionMask::MAY_EXEC)?;
 
             let name;
             // 寻找“/”
@@ -924,7 +1020,10 @@ bitflags! {
         const KER_MAGIC = 0x3153464b;
         const PROC_MAGIC = 0x9fa0;
         const RAMFS_MAGIC = 0x858458f6;
+        const TMPFS_MAGIC = 0x01021994;
+        const HUGETLBFS_MAGIC = 0x958458f6;
         const MOUNT_MAGIC = 61267;
+        const BINFMTFS_MAGIC = 0x42494e4d;
     }
 }
 
@@ -1055,12 +1154,16 @@ macro_rules! producefs {
     ($initializer_slice:ident,$filesystem:ident,$raw_data : ident) => {
         match $initializer_slice.iter().find(|&m| m.name == $filesystem) {
             Some(maker) => {
-                let mount_data = match $filesystem {
-                    "overlay" => OverlayMountData::from_row($raw_data).ok(),
+                use alloc::boxed::Box;
+                let mount_data: Option<Box<dyn FileSystemMakerData>> = match $filesystem {
+                    "overlay" => OverlayMountData::from_row($raw_data)
+                        .ok()
+                        .map(|d| Box::new(d) as Box<dyn FileSystemMakerData>),
+                    "tmpfs" => Some(Box::new(TmpfsMountData::from_row($raw_data)?)),
+                    "hugetlbfs" => Some(Box::new(HugetlbfsMountData::from_row($raw_data)?)),
                     _ => None,
                 };
-                let data: Option<&dyn FileSystemMakerData> =
-                    mount_data.as_ref().map(|d| d as &dyn FileSystemMakerData);
+                let data: Option<&dyn FileSystemMakerData> = mount_data.as_deref();
 
                 maker.call(data)
             }
@@ -24,8 +24,8 @@ use crate::{
 };
 
 use super::{
-    file::FileMode, syscall::ModeType, utils::DName, FilePrivateData, FileSystem, FileType,
-    IndexNode, InodeId, Magic, PollableInode, SuperBlock,
+    fcntl::SealFlags, file::FileMode, syscall::ModeType, utils::DName, xattr::XattrFlags,
+    FilePrivateData, FileSystem, FileType, IndexNode, InodeId, Magic, PollableInode, SuperBlock,
 };
 
 const MOUNTFS_BLOCK_SIZE: u64 = 512;
@@ -540,6 +540,30 @@ impl IndexNode for MountFSInode {
         self.inner_inode.page_cache()
     }
 
+    fn getxattr(&self, name: &str, buf: &mut [u8]) -> Result<usize, SystemError> {
+        self.inner_inode.getxattr(name, buf)
+    }
+
+    fn setxattr(&self, name: &str, value: &[u8], flags: XattrFlags) -> Result<(), SystemError> {
+        self.inner_inode.setxattr(name, value, flags)
+    }
+
+    fn listxattr(&self, buf: &mut [u8]) -> Result<usize, SystemError> {
+        self.inner_inode.listxattr(buf)
+    }
+
+    fn removexattr(&self, name: &str) -> Result<(), SystemError> {
+        self.inner_inode.removexattr(name)
+    }
+
+    fn get_seals(&self) -> Result<SealFlags, SystemError> {
+        self.inner_inode.get_seals()
+    }
+
+    fn add_seals(&self, seals: SealFlags) -> Result<(), SystemError> {
+        self.inner_inode.add_seals(seals)
+    }
+
     fn as_pollable_inode(&self) -> Result<&dyn PollableInode, SystemError> {
         self.inner_inode.as_pollable_inode()
     }
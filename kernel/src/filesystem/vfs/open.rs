@@ -5,18 +5,20 @@ use system_error::SystemError;
 use super::{
     fcntl::AtFlags,
     file::{File, FileMode},
+    fsnotify::{fsnotify_attrib, fsnotify_create, fsnotify_open},
+    permission::{inode_permission, PermissionMask},
     syscall::{ModeType, OpenHow, OpenHowResolve},
     utils::{rsplit_path, user_path_at},
     FileType, IndexNode, MAX_PATHLEN, ROOT_INODE, VFS_MAX_FOLLOW_SYMLINK_TIMES,
 };
+use crate::time::{syscall::PosixTimeval, PosixTimeSpec};
 use crate::{
     driver::base::block::SeekFrom, process::ProcessManager,
     syscall::user_access::check_and_clone_cstr,
 };
-use crate::{filesystem::vfs::syscall::UtimensFlags, process::cred::Kgid};
 use crate::{
-    process::cred::GroupInfo,
-    time::{syscall::PosixTimeval, PosixTimeSpec},
+    filesystem::vfs::syscall::UtimensFlags,
+    process::cred::{CAPFlags, Kgid},
 };
 use alloc::string::String;
 
@@ -106,33 +108,26 @@ pub fn do_fchownat(
 fn chown_common(inode: Arc<dyn IndexNode>, uid: usize, gid: usize) -> Result<usize, SystemError> {
     let mut meta = inode.metadata()?;
     let cred = ProcessManager::current_pcb().cred();
-    let current_uid = cred.uid.data();
-    let current_gid = cred.gid.data();
-    let mut group_info = GroupInfo::default();
-    if let Some(info) = cred.group_info.as_ref() {
-        group_info = info.clone();
-    }
 
     // 检查权限
-    match current_uid {
-        0 => {
-            meta.uid = uid;
-            meta.gid = gid;
+    if cred.has_capability(CAPFlags::CAP_CHOWN) {
+        meta.uid = uid;
+        meta.gid = gid;
+    } else {
+        // 非文件所有者不能更改信息，且不能更改uid
+        if cred.fsuid.data() != meta.uid || uid != meta.uid {
+            return Err(SystemError::EPERM);
         }
-        _ => {
-            // 非文件所有者不能更改信息，且不能更改uid
-            if current_uid != meta.uid || uid != meta.uid {
-                return Err(SystemError::EPERM);
-            }
-            if gid != current_gid && !group_info.gids.contains(&Kgid::from(gid)) {
-                return Err(SystemError::EPERM);
-            }
-            meta.gid = gid;
+        // 只能把文件的组改为自己所属的组（fsgid或附加组）
+        if gid != meta.gid && !cred.in_group_p(Kgid::new(gid)) {
+            return Err(SystemError::EPERM);
         }
+        meta.gid = gid;
     }
 
     meta.mode.remove(ModeType::S_ISUID | ModeType::S_ISGID);
     inode.set_metadata(&meta)?;
+    fsnotify_attrib(&inode);
 
     return Ok(0);
 }
@@ -181,7 +176,10 @@ fn do_sys_openat2(
     );
 
     let inode: Arc<dyn IndexNode> = match inode {
-        Ok(inode) => inode,
+        Ok(inode) => {
+            may_open(&inode, how.o_flags)?;
+            inode
+        }
         Err(errno) => {
             // 文件不存在，且需要创建
             if how.o_flags.contains(FileMode::O_CREAT)
@@ -192,12 +190,18 @@ fn do_sys_openat2(
                 // 查找父目录
                 let parent_inode: Arc<dyn IndexNode> =
                     ROOT_INODE().lookup(parent_path.unwrap_or("/"))?;
+                // 需要有父目录的写和搜索权限
+                inode_permission(
+                    &parent_inode,
+                    PermissionMask::MAY_WRITE | PermissionMask::MAY_EXEC,
+                )?;
                 // 创建文件
                 let inode: Arc<dyn IndexNode> = parent_inode.create(
                     filename,
                     FileType::File,
                     ModeType::from_bits_truncate(0o755),
                 )?;
+                fsnotify_create(&parent_inode, filename, false);
                 inode
             } else {
                 // 不需要创建文件，因此返回错误码
@@ -214,7 +218,8 @@ fn do_sys_openat2(
 
     // 创建文件对象
 
-    let file: File = File::new(inode, how.o_flags)?;
+    let file: File = File::new(inode.clone(), how.o_flags)?;
+    fsnotify_open(&inode);
 
     // 打开模式为“追加”
     if how.o_flags.contains(FileMode::O_APPEND) {
@@ -238,6 +243,24 @@ fn do_sys_openat2(
     return r;
 }
 
+/// 检查当前进程是否有权限以`o_flags`指定的方式打开已存在的inode
+fn may_open(inode: &Arc<dyn IndexNode>, o_flags: FileMode) -> Result<(), SystemError> {
+    if o_flags.contains(FileMode::O_PATH) {
+        return Ok(());
+    }
+
+    let mut mask = match o_flags.accmode() {
+        x if x == FileMode::O_WRONLY.bits() => PermissionMask::MAY_WRITE,
+        x if x == FileMode::O_RDWR.bits() => PermissionMask::MAY_READ | PermissionMask::MAY_WRITE,
+        _ => PermissionMask::MAY_READ,
+    };
+    if o_flags.contains(FileMode::O_TRUNC) {
+        mask |= PermissionMask::MAY_WRITE;
+    }
+
+    inode_permission(inode, mask)
+}
+
 /// On Linux, futimens() is a library function implemented on top of
 /// the utimensat() system call.  To support this, the Linux
 /// utimensat() system call implements a nonstandard feature: if
@@ -293,6 +316,7 @@ pub fn do_utimensat(
         meta.mtime = now;
         inode.set_metadata(&meta).unwrap();
     }
+    fsnotify_attrib(&inode);
     return Ok(0);
 }
 
@@ -316,5 +340,6 @@ pub fn do_utimes(path: &str, times: Option<[PosixTimeval; 2]>) -> Result<usize,
         meta.mtime = now;
         inode.set_metadata(&meta)?;
     }
+    fsnotify_attrib(&inode);
     return Ok(0);
 }
@@ -0,0 +1,424 @@
+//! POSIX访问控制列表(ACL)
+//!
+//! ACL以扩展属性的形式保存在inode上，属性名为`system.posix_acl_access`（访问ACL）
+//! 和`system.posix_acl_default`（目录的默认ACL），属性值的格式与Linux保持一致：
+//! 一个4字节的版本号，后面跟着若干个8字节的表项（tag: u16, perm: u16, id: u32），均为小端序。
+
+use alloc::{sync::Arc, vec::Vec};
+use system_error::SystemError;
+
+use crate::{
+    process::cred::{Cred, Kgid},
+    time::PosixTimeSpec,
+};
+
+use super::{
+    permission::PermissionMask,
+    syscall::ModeType,
+    xattr::{SimpleXattr, XattrFlags},
+    FileType, IndexNode, Metadata,
+};
+
+pub const XATTR_NAME_POSIX_ACL_ACCESS: &str = "system.posix_acl_access";
+pub const XATTR_NAME_POSIX_ACL_DEFAULT: &str = "system.posix_acl_default";
+
+/// ACL扩展属性格式的版本号
+const POSIX_ACL_XATTR_VERSION: u32 = 0x0002;
+/// 属性头的长度
+const POSIX_ACL_HEADER_SIZE: usize = 4;
+/// 每个表项的长度
+const POSIX_ACL_ENTRY_SIZE: usize = 8;
+/// 不需要id的表项使用的id
+const ACL_UNDEFINED_ID: u32 = u32::MAX;
+
+pub const ACL_USER_OBJ: u16 = 0x01;
+pub const ACL_USER: u16 = 0x02;
+pub const ACL_GROUP_OBJ: u16 = 0x04;
+pub const ACL_GROUP: u16 = 0x08;
+pub const ACL_MASK: u16 = 0x10;
+pub const ACL_OTHER: u16 = 0x20;
+
+const ACL_PERM_MASK: u16 = 0o7;
+
+/// ACL的类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PosixAclType {
+    Access,
+    Default,
+}
+
+impl PosixAclType {
+    pub fn from_xattr_name(name: &str) -> Option<Self> {
+        match name {
+            XATTR_NAME_POSIX_ACL_ACCESS => Some(Self::Access),
+            XATTR_NAME_POSIX_ACL_DEFAULT => Some(Self::Default),
+            _ => None,
+        }
+    }
+
+    pub fn xattr_name(&self) -> &'static str {
+        match self {
+            Self::Access => XATTR_NAME_POSIX_ACL_ACCESS,
+            Self::Default => XATTR_NAME_POSIX_ACL_DEFAULT,
+        }
+    }
+}
+
+/// ACL表项
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PosixAclEntry {
+    pub tag: u16,
+    pub perm: u16,
+    /// 对于ACL_USER和ACL_GROUP，表示uid或gid，其余表项无意义
+    pub id: u32,
+}
+
+/// POSIX ACL，表项按照(tag, id)有序排列
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PosixAcl {
+    entries: Vec<PosixAclEntry>,
+}
+
+impl PosixAcl {
+    /// 从扩展属性的值解析ACL
+    ///
+    /// ## 返回值
+    ///
+    /// - `Ok(None)` 属性值中没有任何表项
+    /// - `Err(SystemError::EOPNOTSUPP_OR_ENOTSUP)` 版本号不支持
+    /// - `Err(SystemError::EINVAL)` 属性值格式错误或ACL不合法
+    pub fn from_xattr(value: &[u8]) -> Result<Option<Self>, SystemError> {
+        if value.len() < POSIX_ACL_HEADER_SIZE
+            || (value.len() - POSIX_ACL_HEADER_SIZE) % POSIX_ACL_ENTRY_SIZE != 0
+        {
+            return Err(SystemError::EINVAL);
+        }
+        let version = u32::from_le_bytes(value[0..4].try_into().unwrap());
+        if version != POSIX_ACL_XATTR_VERSION {
+            return Err(SystemError::EOPNOTSUPP_OR_ENOTSUP);
+        }
+
+        let entries = value[POSIX_ACL_HEADER_SIZE..]
+            .chunks_exact(POSIX_ACL_ENTRY_SIZE)
+            .map(|e| {
+                let tag = u16::from_le_bytes([e[0], e[1]]);
+                let perm = u16::from_le_bytes([e[2], e[3]]);
+                let id = match tag {
+                    ACL_USER | ACL_GROUP => u32::from_le_bytes([e[4], e[5], e[6], e[7]]),
+                    _ => ACL_UNDEFINED_ID,
+                };
+                PosixAclEntry { tag, perm, id }
+            })
+            .collect::<Vec<_>>();
+        if entries.is_empty() {
+            return Ok(None);
+        }
+
+        let acl = Self { entries };
+        acl.validate()?;
+        Ok(Some(acl))
+    }
+
+    /// 将ACL转换为扩展属性的值
+    pub fn to_xattr(&self) -> Vec<u8> {
+        let mut buf =
+            Vec::with_capacity(POSIX_ACL_HEADER_SIZE + self.entries.len() * POSIX_ACL_ENTRY_SIZE);
+        buf.extend_from_slice(&POSIX_ACL_XATTR_VERSION.to_le_bytes());
+        for e in self.entries.iter() {
+            buf.extend_from_slice(&e.tag.to_le_bytes());
+            buf.extend_from_slice(&e.perm.to_le_bytes());
+            buf.extend_from_slice(&e.id.to_le_bytes());
+        }
+        buf
+    }
+
+    /// 检查ACL是否合法：
+    /// - ACL_USER_OBJ、ACL_GROUP_OBJ、ACL_OTHER各有且只有一个
+    /// - 存在ACL_USER或ACL_GROUP表项时必须有ACL_MASK
+    /// - 表项按(tag, id)严格递增排列
+    fn validate(&self) -> Result<(), SystemError> {
+        let mut prev: Option<(u16, u32)> = None;
+        let (mut user_obj, mut group_obj, mut other, mut mask, mut named) = (0, 0, 0, 0, 0);
+        for e in self.entries.iter() {
+            if e.perm & !ACL_PERM_MASK != 0 {
+                return Err(SystemError::EINVAL);
+            }
+            match e.tag {
+                ACL_USER_OBJ => user_obj += 1,
+                ACL_GROUP_OBJ => group_obj += 1,
+                ACL_OTHER => other += 1,
+                ACL_MASK => mask += 1,
+                ACL_USER | ACL_GROUP => named += 1,
+                _ => return Err(SystemError::EINVAL),
+            }
+            let key = (e.tag, e.id);
+            if prev.is_some_and(|p| p >= key) {
+                return Err(SystemError::EINVAL);
+            }
+            prev = Some(key);
+        }
+
+        if user_obj != 1 || group_obj != 1 || other != 1 || mask > 1 || (named > 0 && mask == 0)
+        {
+            return Err(SystemError::EINVAL);
+        }
+        Ok(())
+    }
+
+    fn find_mut(&mut self, tag: u16) -> Option<&mut PosixAclEntry> {
+        self.entries.iter_mut().find(|e| e.tag == tag)
+    }
+
+    fn find(&self, tag: u16) -> Option<&PosixAclEntry> {
+        self.entries.iter().find(|e| e.tag == tag)
+    }
+
+    /// 计算与ACL对应的文件权限位
+    ///
+    /// ## 返回值
+    ///
+    /// - `(ModeType, bool)` 对应的权限位，以及ACL是否能被权限位完全表示
+    pub fn equiv_mode(&self) -> (ModeType, bool) {
+        let mut mode = 0u32;
+        let mut equiv = true;
+        for e in self.entries.iter() {
+            let perm = e.perm as u32;
+            match e.tag {
+                ACL_USER_OBJ => mode |= perm << 6,
+                ACL_GROUP_OBJ => mode |= perm << 3,
+                ACL_OTHER => mode |= perm,
+                ACL_MASK => {
+                    mode = (mode & !0o070) | (perm << 3);
+                    equiv = false;
+                }
+                _ => equiv = false,
+            }
+        }
+        (ModeType::from_bits_truncate(mode), equiv)
+    }
+
+    /// 新建inode时，用请求的权限位与继承而来的ACL相互约束
+    ///
+    /// ## 参数
+    ///
+    /// - `mode` 请求的权限位，返回时被更新为最终的权限位
+    ///
+    /// ## 返回值
+    ///
+    /// - `bool` ACL是否能被权限位完全表示
+    fn create_masq(&mut self, mode: &mut u32) -> bool {
+        let mut equiv = true;
+        let mut has_mask = false;
+        for e in self.entries.iter_mut() {
+            match e.tag {
+                ACL_USER_OBJ => {
+                    e.perm &= ((*mode >> 6) & 7) as u16;
+                    *mode &= ((e.perm as u32) << 6) | !0o700;
+                }
+                ACL_OTHER => {
+                    e.perm &= (*mode & 7) as u16;
+                    *mode &= (e.perm as u32) | !0o007;
+                }
+                ACL_MASK => {
+                    has_mask = true;
+                    equiv = false;
+                }
+                ACL_USER | ACL_GROUP => equiv = false,
+                _ => {}
+            }
+        }
+
+        // 有ACL_MASK时，组权限位对应的是ACL_MASK而不是ACL_GROUP_OBJ
+        let group = self
+            .find_mut(if has_mask { ACL_MASK } else { ACL_GROUP_OBJ })
+            .unwrap();
+        group.perm &= ((*mode >> 3) & 7) as u16;
+        *mode &= ((group.perm as u32) << 3) | !0o070;
+        equiv
+    }
+
+    /// 根据ACL检查当前进程是否有权限访问inode
+    ///
+    /// ## 参数
+    ///
+    /// - `metadata` inode的元数据
+    /// - `cred` 当前进程的凭证
+    /// - `mask` 请求的权限
+    pub fn permission(
+        &self,
+        metadata: &Metadata,
+        cred: &Cred,
+        mask: PermissionMask,
+    ) -> Result<(), SystemError> {
+        let want =
+            (mask & (PermissionMask::MAY_READ | PermissionMask::MAY_WRITE | PermissionMask::MAY_EXEC))
+                .bits() as u16;
+        let acl_mask = self.find(ACL_MASK).map(|e| e.perm).unwrap_or(ACL_PERM_MASK);
+        let check = |perm: u16| {
+            if perm & want == want {
+                Ok(())
+            } else {
+                Err(SystemError::EACCES)
+            }
+        };
+
+        let mut found = false;
+        for e in self.entries.iter() {
+            match e.tag {
+                ACL_USER_OBJ => {
+                    if cred.fsuid.data() == metadata.uid {
+                        return check(e.perm);
+                    }
+                }
+                ACL_USER => {
+                    if cred.fsuid.data() == e.id as usize {
+                        return check(e.perm & acl_mask);
+                    }
+                }
+                ACL_GROUP_OBJ | ACL_GROUP => {
+                    let gid = if e.tag == ACL_GROUP_OBJ {
+                        metadata.gid
+                    } else {
+                        e.id as usize
+                    };
+                    if cred.in_group_p(Kgid::new(gid)) {
+                        found = true;
+                        if e.perm & acl_mask & want == want {
+                            return Ok(());
+                        }
+                    }
+                }
+                ACL_MASK => {}
+                ACL_OTHER => {
+                    if found {
+                        return Err(SystemError::EACCES);
+                    }
+                    return check(e.perm);
+                }
+                _ => break,
+            }
+        }
+        Err(SystemError::EIO)
+    }
+}
+
+/// 读取inode上指定类型的ACL
+///
+/// ## 返回值
+///
+/// - `Ok(None)` inode上没有该类型的ACL，或者文件系统不支持ACL
+pub fn get_acl(
+    inode: &Arc<dyn IndexNode>,
+    acl_type: PosixAclType,
+) -> Result<Option<PosixAcl>, SystemError> {
+    let name = acl_type.xattr_name();
+    let size = match inode.getxattr(name, &mut []) {
+        Ok(size) => size,
+        Err(SystemError::ENODATA) | Err(SystemError::EOPNOTSUPP_OR_ENOTSUP) => return Ok(None),
+        Err(e) => return Err(e),
+    };
+    let mut value = vec![0u8; size];
+    let size = inode.getxattr(name, &mut value)?;
+    PosixAcl::from_xattr(&value[..size])
+}
+
+/// 设置`system.posix_acl_*`扩展属性
+///
+/// 设置访问ACL时会同步更新inode的权限位；若ACL能被权限位完全表示，则不再单独保存ACL。
+/// 只有目录可以设置默认ACL。
+///
+/// ## 参数
+///
+/// - `inode` 目标inode
+/// - `acl_type` ACL的类型
+/// - `value` 扩展属性的值，为空时删除ACL
+/// - `flags` setxattr的flags
+pub fn posix_acl_xattr_set(
+    inode: &Arc<dyn IndexNode>,
+    acl_type: PosixAclType,
+    value: &[u8],
+    flags: XattrFlags,
+) -> Result<(), SystemError> {
+    let mut acl = if value.is_empty() {
+        None
+    } else {
+        PosixAcl::from_xattr(value)?
+    };
+    let name = acl_type.xattr_name();
+
+    let mut metadata = inode.metadata()?;
+    if acl_type == PosixAclType::Default && metadata.file_type != FileType::Dir {
+        return if acl.is_some() {
+            Err(SystemError::EACCES)
+        } else {
+            Ok(())
+        };
+    }
+
+    if acl_type == PosixAclType::Access {
+        if let Some(a) = acl.as_ref() {
+            let (mode, equiv) = a.equiv_mode();
+            metadata.mode = (metadata.mode & !ModeType::S_IRWXUGO) | mode;
+            metadata.ctime = PosixTimeSpec::now();
+            inode.set_metadata(&metadata)?;
+            if equiv {
+                acl = None;
+            }
+        }
+    }
+
+    match acl {
+        Some(acl) => inode.setxattr(name, &acl.to_xattr(), flags),
+        None => match inode.removexattr(name) {
+            Ok(()) | Err(SystemError::ENODATA) => Ok(()),
+            Err(e) => Err(e),
+        },
+    }
+}
+
+/// 在目录下新建inode时，根据目录的默认ACL计算新inode的权限位和ACL
+///
+/// ## 参数
+///
+/// - `dir_default` 父目录的默认ACL（扩展属性的值）
+/// - `mode` 请求的权限位
+/// - `is_dir` 新建的是否是目录
+///
+/// ## 返回值
+///
+/// - `Ok((ModeType, SimpleXattr))` 新inode的权限位，以及保存了继承而来的ACL的扩展属性集合
+pub fn posix_acl_create(
+    dir_default: Option<&[u8]>,
+    mode: ModeType,
+    is_dir: bool,
+) -> Result<(ModeType, SimpleXattr), SystemError> {
+    let mut xattr = SimpleXattr::new();
+    let default = match dir_default {
+        Some(value) => PosixAcl::from_xattr(value)?,
+        None => None,
+    };
+    let Some(default) = default else {
+        return Ok((mode, xattr));
+    };
+
+    if is_dir {
+        xattr.set(
+            XATTR_NAME_POSIX_ACL_DEFAULT,
+            &default.to_xattr(),
+            XattrFlags::empty(),
+        )?;
+    }
+
+    let mut access = default;
+    let mut perm = (mode & ModeType::S_IRWXUGO).bits();
+    let equiv = access.create_masq(&mut perm);
+    if !equiv {
+        xattr.set(
+            XATTR_NAME_POSIX_ACL_ACCESS,
+            &access.to_xattr(),
+            XattrFlags::empty(),
+        )?;
+    }
+    let mode = (mode & !ModeType::S_IRWXUGO) | ModeType::from_bits_truncate(perm);
+    Ok((mode, xattr))
+}
@@ -1,4 +1,6 @@
+use crate::filesystem::hugetlbfs::HugetlbfsMountData;
 use crate::filesystem::overlayfs::OverlayMountData;
+use crate::filesystem::tmpfs::TmpfsMountData;
 use crate::filesystem::vfs::FileSystemMakerData;
 use core::mem::size_of;
 
@@ -14,7 +16,7 @@ use crate::{
     filesystem::vfs::{file::FileDescriptorVec, vcore as Vcore},
     libs::rwlock::RwLockWriteGuard,
     mm::VirtAddr,
-    process::ProcessManager,
+    process::{capability::capable, cred::CAPFlags, ProcessManager},
     syscall::{
         user_access::{self, check_and_clone_cstr, UserBufferWriter},
         Syscall,
@@ -25,8 +27,10 @@ use crate::{
 use super::stat::{do_newfstatat, do_statx, vfs_fstat};
 use super::vcore::do_symlinkat;
 use super::{
-    fcntl::{AtFlags, FcntlCommand, FD_CLOEXEC},
+    fcntl::{AtFlags, FcntlCommand, SealFlags, FD_CLOEXEC},
     file::{File, FileMode},
+    file_lock::{posix_getlk, posix_setlk, FileLockOwner, PosixFlock},
+    fsnotify::{fsnotify_create, fsnotify_link, fsnotify_move},
     open::{
         do_faccessat, do_fchmodat, do_fchownat, do_sys_open, do_utimensat, do_utimes, ksys_fchown,
     },
@@ -38,6 +42,7 @@ use super::{
 
 mod open_utils;
 mod sys_close;
+mod sys_flock;
 #[cfg(any(target_arch = "x86_64", target_arch = "riscv64"))]
 mod sys_fstat;
 mod sys_ioctl;
@@ -52,6 +57,12 @@ mod sys_stat;
 mod sys_write;
 mod sys_writev;
 
+mod sys_getxattr;
+mod sys_listxattr;
+mod sys_removexattr;
+mod sys_setxattr;
+mod xattr_utils;
+
 mod epoll_utils;
 #[cfg(target_arch = "x86_64")]
 mod sys_epoll_create;
@@ -766,7 +777,9 @@ impl Syscall {
             new_begin_inode.lookup_follow_symlink(new_parent_path.unwrap_or("/"), symlink_times)?;
 
         // 被调用者利用downcast_ref判断两inode是否为同一文件系统
-        return new_parent.link(new_name, &old_inode).map(|_| 0);
+        new_parent.link(new_name, &old_inode)?;
+        fsnotify_link(&new_parent, new_name, &old_inode);
+        return Ok(0);
     }
 
     pub fn link(old: *const u8, new: *const u8) -> Result<usize, SystemError> {
@@ -929,7 +942,17 @@ impl Syscall {
         let (new_filename, new_parent_path) = rsplit_path(&new_remain_path);
         let new_parent_inode = ROOT_INODE()
             .lookup_follow_symlink(new_parent_path.unwrap_or("/"), VFS_MAX_FOLLOW_SYMLINK_TIMES)?;
+        let moved_inode = old_parent_inode.find(old_filename)?;
+        let is_dir = moved_inode.metadata()?.file_type == FileType::Dir;
         old_parent_inode.move_to(old_filename, &new_parent_inode, new_filename)?;
+        fsnotify_move(
+            &old_parent_inode,
+            old_filename,
+            &new_parent_inode,
+            new_filename,
+            &moved_inode,
+            is_dir,
+        );
         return Ok(0);
     }
 
@@ -1002,6 +1025,9 @@ impl Syscall {
         if !(FileDescriptorVec::validate_fd(oldfd) && FileDescriptorVec::validate_fd(newfd)) {
             return Err(SystemError::EBADF);
         }
+        if newfd as usize >= FileDescriptorVec::max_fds() {
+            return Err(SystemError::EBADF);
+        }
 
         if oldfd == newfd {
             // 若oldfd与newfd相等
@@ -1040,15 +1066,17 @@ impl Syscall {
     /// - `fd`：文件描述符
     /// - `cmd`：命令
     /// - `arg`：参数
-    pub fn fcntl(fd: i32, cmd: FcntlCommand, arg: i32) -> Result<usize, SystemError> {
+    pub fn fcntl(fd: i32, cmd: FcntlCommand, arg: usize) -> Result<usize, SystemError> {
         // debug!("fcntl ({cmd:?}) fd: {fd}, arg={arg}");
         match cmd {
             FcntlCommand::DupFd | FcntlCommand::DupFdCloexec => {
-                if arg < 0 || arg as usize >= FileDescriptorVec::PROCESS_MAX_FD {
-                    return Err(SystemError::EBADF);
+                let arg = arg as i32;
+                let max_fds = FileDescriptorVec::max_fds();
+                if arg < 0 || arg as usize >= max_fds {
+                    return Err(SystemError::EINVAL);
                 }
                 let arg = arg as usize;
-                for i in arg..FileDescriptorVec::PROCESS_MAX_FD {
+                for i in arg..max_fds {
                     let binding = ProcessManager::current_pcb().fd_table();
                     let mut fd_table_guard = binding.write();
                     if fd_table_guard.get_file_by_fd(i as i32).is_none() {
@@ -1131,6 +1159,40 @@ impl Syscall {
 
                 return Err(SystemError::EBADF);
             }
+            FcntlCommand::GetLock
+            | FcntlCommand::SetLock
+            | FcntlCommand::SetLockWait
+            | FcntlCommand::GetOfdLock
+            | FcntlCommand::SetOfdLock
+            | FcntlCommand::SetOfdLockWait => {
+                return Self::fcntl_lock(fd, cmd, arg as *mut PosixFlock);
+            }
+            FcntlCommand::AddSeals => {
+                let binding = ProcessManager::current_pcb().fd_table();
+                let fd_table_guard = binding.read();
+                let file = fd_table_guard
+                    .get_file_by_fd(fd)
+                    .ok_or(SystemError::EBADF)?;
+                // drop guard 以避免无法调度的问题
+                drop(fd_table_guard);
+
+                // 只有以可写方式打开的文件才能添加seal
+                file.writeable()?;
+                let seals = SealFlags::from_bits(arg as u32).ok_or(SystemError::EINVAL)?;
+                file.inode().add_seals(seals)?;
+                return Ok(0);
+            }
+            FcntlCommand::GetSeals => {
+                let binding = ProcessManager::current_pcb().fd_table();
+                let fd_table_guard = binding.read();
+                let file = fd_table_guard
+                    .get_file_by_fd(fd)
+                    .ok_or(SystemError::EBADF)?;
+                // drop guard 以避免无法调度的问题
+                drop(fd_table_guard);
+
+                return Ok(file.inode().get_seals()?.bits() as usize);
+            }
             _ => {
                 // TODO: unimplemented
                 // 未实现的命令，返回0，不报错。
@@ -1141,6 +1203,60 @@ impl Syscall {
         }
     }
 
+    /// 处理fcntl的记录锁命令
+    ///
+    /// ## 参数
+    ///
+    /// - `fd` 文件描述符
+    /// - `cmd` F_GETLK、F_SETLK、F_SETLKW，或者对应的OFD锁命令
+    /// - `user_flock` 用户态的`struct flock`指针
+    fn fcntl_lock(
+        fd: i32,
+        cmd: FcntlCommand,
+        user_flock: *mut PosixFlock,
+    ) -> Result<usize, SystemError> {
+        let binding = ProcessManager::current_pcb().fd_table();
+        let fd_table_guard = binding.read();
+        let file = fd_table_guard
+            .get_file_by_fd(fd)
+            .ok_or(SystemError::EBADF)?;
+        let posix_owner = fd_table_guard.lock_owner();
+        // drop guard 以避免无法调度的问题
+        drop(fd_table_guard);
+
+        let mut flock = *UserBufferReader::new(user_flock, size_of::<PosixFlock>(), true)?
+            .read_one_from_user::<PosixFlock>(0)?;
+
+        let ofd = matches!(
+            cmd,
+            FcntlCommand::GetOfdLock | FcntlCommand::SetOfdLock | FcntlCommand::SetOfdLockWait
+        );
+        let owner = if ofd {
+            // OFD锁要求l_pid为0
+            if flock.l_pid != 0 {
+                return Err(SystemError::EINVAL);
+            }
+            file.lock_holder().owner()
+        } else {
+            posix_owner
+        };
+
+        match cmd {
+            FcntlCommand::GetLock | FcntlCommand::GetOfdLock => {
+                posix_getlk(&file, owner, &mut flock)?;
+                UserBufferWriter::new(user_flock, size_of::<PosixFlock>(), true)?
+                    .copy_one_to_user(&flock, 0)?;
+            }
+            FcntlCommand::SetLock | FcntlCommand::SetOfdLock => {
+                posix_setlk(&file, owner, &flock, false)?;
+            }
+            _ => {
+                posix_setlk(&file, owner, &flock, true)?;
+            }
+        }
+        return Ok(0);
+    }
+
     /// # ftruncate
     ///
     /// ## 描述
@@ -1273,6 +1389,7 @@ impl Syscall {
             .lookup_follow_symlink(parent_path.unwrap_or("/"), VFS_MAX_FOLLOW_SYMLINK_TIMES)?;
         // 创建nod
         parent_inode.mknod(filename, mode, dev_t)?;
+        fsnotify_create(&parent_inode, filename, false);
 
         return Ok(0);
     }
@@ -1433,6 +1550,9 @@ impl Syscall {
         _mountflags: usize,
         data: *const u8,
     ) -> Result<usize, SystemError> {
+        if !capable(CAPFlags::CAP_SYS_ADMIN) {
+            return Err(SystemError::EPERM);
+        }
         let target = user_access::check_and_clone_cstr(target, Some(MAX_PATHLEN))?
             .into_string()
             .map_err(|_| SystemError::EINVAL)?;
@@ -1456,6 +1576,9 @@ impl Syscall {
     ///
     /// [umount(2) — Linux manual page](https://www.man7.org/linux/man-pages/man2/umount.2.html)
     pub fn umount2(target: *const u8, flags: i32) -> Result<(), SystemError> {
+        if !capable(CAPFlags::CAP_SYS_ADMIN) {
+            return Err(SystemError::EPERM);
+        }
         let target = user_access::check_and_clone_cstr(target, Some(MAX_PATHLEN))?
             .into_string()
             .map_err(|_| SystemError::EINVAL)?;
@@ -0,0 +1,71 @@
+//! System call handler for flock.
+
+use crate::arch::syscall::nr::SYS_FLOCK;
+use crate::filesystem::vfs::file_lock::{flock, FlockOperation};
+use crate::process::ProcessManager;
+use crate::syscall::table::FormattedSyscallParam;
+use crate::syscall::table::Syscall;
+use system_error::SystemError;
+
+use alloc::string::ToString;
+use alloc::vec::Vec;
+
+/// Handler for the `flock` system call.
+pub struct SysFlockHandle;
+
+impl Syscall for SysFlockHandle {
+    /// Returns the number of arguments this syscall takes (2).
+    fn num_args(&self) -> usize {
+        2
+    }
+
+    /// Applies or removes an advisory lock on the whole file.
+    ///
+    /// # Arguments
+    ///
+    /// * `fd` - File descriptor number
+    /// * `operation` - LOCK_SH, LOCK_EX or LOCK_UN, optionally ORed with LOCK_NB
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(usize)` - On success, returns 0
+    /// * `Err(SystemError)` - On failure, returns a POSIX error code
+    fn handle(&self, args: &[usize], _from_user: bool) -> Result<usize, SystemError> {
+        let fd = Self::fd(args);
+        let operation =
+            FlockOperation::from_bits(Self::operation(args)).ok_or(SystemError::EINVAL)?;
+
+        let binding = ProcessManager::current_pcb().fd_table();
+        let fd_table_guard = binding.read();
+        let file = fd_table_guard
+            .get_file_by_fd(fd)
+            .ok_or(SystemError::EBADF)?;
+        // drop guard 以避免无法调度的问题
+        drop(fd_table_guard);
+
+        flock(&file, operation)?;
+        return Ok(0);
+    }
+
+    /// Formats the syscall arguments for display/debugging purposes.
+    fn entry_format(&self, args: &[usize]) -> Vec<FormattedSyscallParam> {
+        vec![
+            FormattedSyscallParam::new("fd", Self::fd(args).to_string()),
+            FormattedSyscallParam::new("operation", format!("{:#x}", Self::operation(args))),
+        ]
+    }
+}
+
+impl SysFlockHandle {
+    /// Extracts the file descriptor (fd) argument from syscall parameters.
+    fn fd(args: &[usize]) -> i32 {
+        args[0] as i32
+    }
+
+    /// Extracts the operation argument from syscall parameters.
+    fn operation(args: &[usize]) -> u32 {
+        args[1] as u32
+    }
+}
+
+syscall_table_macros::declare_syscall!(SYS_FLOCK, SysFlockHandle);
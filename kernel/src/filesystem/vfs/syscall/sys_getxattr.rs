@@ -0,0 +1,183 @@
+//! System call handlers for `getxattr`, `lgetxattr` and `fgetxattr`.
+
+use alloc::string::ToString;
+use alloc::vec::Vec;
+use system_error::SystemError;
+
+use crate::arch::syscall::nr::{SYS_FGETXATTR, SYS_GETXATTR, SYS_LGETXATTR};
+use crate::syscall::table::FormattedSyscallParam;
+use crate::syscall::table::Syscall;
+
+use super::xattr_utils::{do_getxattr, xattr_fd_inode, xattr_path_inode};
+
+/// Handler for the `getxattr` system call.
+///
+/// Retrieve an extended attribute value of the file at the given path, following symbolic links.
+pub struct SysGetxattrHandle;
+
+impl Syscall for SysGetxattrHandle {
+    /// Returns the number of arguments this syscall takes (4).
+    fn num_args(&self) -> usize {
+        4
+    }
+
+    fn handle(&self, args: &[usize], from_user: bool) -> Result<usize, SystemError> {
+        let inode = xattr_path_inode(Self::path(args), true)?;
+        do_getxattr(
+            &inode,
+            Self::name(args),
+            Self::value(args),
+            Self::size(args),
+            from_user,
+        )
+    }
+
+    /// Formats the syscall arguments for display/debugging purposes.
+    fn entry_format(&self, args: &[usize]) -> Vec<FormattedSyscallParam> {
+        vec![
+            FormattedSyscallParam::new("path", format!("{:#x}", Self::path(args) as usize)),
+            FormattedSyscallParam::new("name", format!("{:#x}", Self::name(args) as usize)),
+            FormattedSyscallParam::new("value", format!("{:#x}", Self::value(args) as usize)),
+            FormattedSyscallParam::new("size", Self::size(args).to_string()),
+        ]
+    }
+}
+
+impl SysGetxattrHandle {
+    /// Extracts the path argument from syscall parameters.
+    fn path(args: &[usize]) -> *const u8 {
+        args[0] as *const u8
+    }
+
+    /// Extracts the attribute name argument from syscall parameters.
+    fn name(args: &[usize]) -> *const u8 {
+        args[1] as *const u8
+    }
+
+    /// Extracts the attribute value buffer argument from syscall parameters.
+    fn value(args: &[usize]) -> *mut u8 {
+        args[2] as *mut u8
+    }
+
+    /// Extracts the size of the buffer argument from syscall parameters.
+    fn size(args: &[usize]) -> usize {
+        args[3]
+    }
+}
+
+syscall_table_macros::declare_syscall!(SYS_GETXATTR, SysGetxattrHandle);
+
+/// Handler for the `lgetxattr` system call.
+///
+/// Retrieve an extended attribute value of the file at the given path,
+/// without following a final symbolic link.
+pub struct SysLgetxattrHandle;
+
+impl Syscall for SysLgetxattrHandle {
+    /// Returns the number of arguments this syscall takes (4).
+    fn num_args(&self) -> usize {
+        4
+    }
+
+    fn handle(&self, args: &[usize], from_user: bool) -> Result<usize, SystemError> {
+        let inode = xattr_path_inode(Self::path(args), false)?;
+        do_getxattr(
+            &inode,
+            Self::name(args),
+            Self::value(args),
+            Self::size(args),
+            from_user,
+        )
+    }
+
+    /// Formats the syscall arguments for display/debugging purposes.
+    fn entry_format(&self, args: &[usize]) -> Vec<FormattedSyscallParam> {
+        vec![
+            FormattedSyscallParam::new("path", format!("{:#x}", Self::path(args) as usize)),
+            FormattedSyscallParam::new("name", format!("{:#x}", Self::name(args) as usize)),
+            FormattedSyscallParam::new("value", format!("{:#x}", Self::value(args) as usize)),
+            FormattedSyscallParam::new("size", Self::size(args).to_string()),
+        ]
+    }
+}
+
+impl SysLgetxattrHandle {
+    /// Extracts the path argument from syscall parameters.
+    fn path(args: &[usize]) -> *const u8 {
+        args[0] as *const u8
+    }
+
+    /// Extracts the attribute name argument from syscall parameters.
+    fn name(args: &[usize]) -> *const u8 {
+        args[1] as *const u8
+    }
+
+    /// Extracts the attribute value buffer argument from syscall parameters.
+    fn value(args: &[usize]) -> *mut u8 {
+        args[2] as *mut u8
+    }
+
+    /// Extracts the size of the buffer argument from syscall parameters.
+    fn size(args: &[usize]) -> usize {
+        args[3]
+    }
+}
+
+syscall_table_macros::declare_syscall!(SYS_LGETXATTR, SysLgetxattrHandle);
+
+/// Handler for the `fgetxattr` system call.
+///
+/// Retrieve an extended attribute value of an open file.
+pub struct SysFgetxattrHandle;
+
+impl Syscall for SysFgetxattrHandle {
+    /// Returns the number of arguments this syscall takes (4).
+    fn num_args(&self) -> usize {
+        4
+    }
+
+    fn handle(&self, args: &[usize], from_user: bool) -> Result<usize, SystemError> {
+        let inode = xattr_fd_inode(Self::fd(args))?;
+        do_getxattr(
+            &inode,
+            Self::name(args),
+            Self::value(args),
+            Self::size(args),
+            from_user,
+        )
+    }
+
+    /// Formats the syscall arguments for display/debugging purposes.
+    fn entry_format(&self, args: &[usize]) -> Vec<FormattedSyscallParam> {
+        vec![
+            FormattedSyscallParam::new("fd", Self::fd(args).to_string()),
+            FormattedSyscallParam::new("name", format!("{:#x}", Self::name(args) as usize)),
+            FormattedSyscallParam::new("value", format!("{:#x}", Self::value(args) as usize)),
+            FormattedSyscallParam::new("size", Self::size(args).to_string()),
+        ]
+    }
+}
+
+impl SysFgetxattrHandle {
+    /// Extracts the file descriptor argument from syscall parameters.
+    fn fd(args: &[usize]) -> i32 {
+        args[0] as i32
+    }
+
+    /// Extracts the attribute name argument from syscall parameters.
+    fn name(args: &[usize]) -> *const u8 {
+        args[1] as *const u8
+    }
+
+    /// Extracts the attribute value buffer argument from syscall parameters.
+    fn value(args: &[usize]) -> *mut u8 {
+        args[2] as *mut u8
+    }
+
+    /// Extracts the size of the buffer argument from syscall parameters.
+    fn size(args: &[usize]) -> usize {
+        args[3]
+    }
+}
+
+syscall_table_macros::declare_syscall!(SYS_FGETXATTR, SysFgetxattrHandle);
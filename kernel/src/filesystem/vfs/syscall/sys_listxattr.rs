@@ -0,0 +1,147 @@
+//! System call handlers for `listxattr`, `llistxattr` and `flistxattr`.
+
+use alloc::string::ToString;
+use alloc::vec::Vec;
+use system_error::SystemError;
+
+use crate::arch::syscall::nr::{SYS_FLISTXATTR, SYS_LISTXATTR, SYS_LLISTXATTR};
+use crate::syscall::table::FormattedSyscallParam;
+use crate::syscall::table::Syscall;
+
+use super::xattr_utils::{do_listxattr, xattr_fd_inode, xattr_path_inode};
+
+/// Handler for the `listxattr` system call.
+///
+/// List extended attribute names of the file at the given path, following symbolic links.
+pub struct SysListxattrHandle;
+
+impl Syscall for SysListxattrHandle {
+    /// Returns the number of arguments this syscall takes (3).
+    fn num_args(&self) -> usize {
+        3
+    }
+
+    fn handle(&self, args: &[usize], from_user: bool) -> Result<usize, SystemError> {
+        let inode = xattr_path_inode(Self::path(args), true)?;
+        do_listxattr(&inode, Self::list(args), Self::size(args), from_user)
+    }
+
+    /// Formats the syscall arguments for display/debugging purposes.
+    fn entry_format(&self, args: &[usize]) -> Vec<FormattedSyscallParam> {
+        vec![
+            FormattedSyscallParam::new("path", format!("{:#x}", Self::path(args) as usize)),
+            FormattedSyscallParam::new("list", format!("{:#x}", Self::list(args) as usize)),
+            FormattedSyscallParam::new("size", Self::size(args).to_string()),
+        ]
+    }
+}
+
+impl SysListxattrHandle {
+    /// Extracts the path argument from syscall parameters.
+    fn path(args: &[usize]) -> *const u8 {
+        args[0] as *const u8
+    }
+
+    /// Extracts the buffer receiving the attribute names argument from syscall parameters.
+    fn list(args: &[usize]) -> *mut u8 {
+        args[1] as *mut u8
+    }
+
+    /// Extracts the size of the buffer argument from syscall parameters.
+    fn size(args: &[usize]) -> usize {
+        args[2]
+    }
+}
+
+syscall_table_macros::declare_syscall!(SYS_LISTXATTR, SysListxattrHandle);
+
+/// Handler for the `llistxattr` system call.
+///
+/// List extended attribute names of the file at the given path,
+/// without following a final symbolic link.
+pub struct SysLlistxattrHandle;
+
+impl Syscall for SysLlistxattrHandle {
+    /// Returns the number of arguments this syscall takes (3).
+    fn num_args(&self) -> usize {
+        3
+    }
+
+    fn handle(&self, args: &[usize], from_user: bool) -> Result<usize, SystemError> {
+        let inode = xattr_path_inode(Self::path(args), false)?;
+        do_listxattr(&inode, Self::list(args), Self::size(args), from_user)
+    }
+
+    /// Formats the syscall arguments for display/debugging purposes.
+    fn entry_format(&self, args: &[usize]) -> Vec<FormattedSyscallParam> {
+        vec![
+            FormattedSyscallParam::new("path", format!("{:#x}", Self::path(args) as usize)),
+            FormattedSyscallParam::new("list", format!("{:#x}", Self::list(args) as usize)),
+            FormattedSyscallParam::new("size", Self::size(args).to_string()),
+        ]
+    }
+}
+
+impl SysLlistxattrHandle {
+    /// Extracts the path argument from syscall parameters.
+    fn path(args: &[usize]) -> *const u8 {
+        args[0] as *const u8
+    }
+
+    /// Extracts the buffer receiving the attribute names argument from syscall parameters.
+    fn list(args: &[usize]) -> *mut u8 {
+        args[1] as *mut u8
+    }
+
+    /// Extracts the size of the buffer argument from syscall parameters.
+    fn size(args: &[usize]) -> usize {
+        args[2]
+    }
+}
+
+syscall_table_macros::declare_syscall!(SYS_LLISTXATTR, SysLlistxattrHandle);
+
+/// Handler for the `flistxattr` system call.
+///
+/// List extended attribute names of an open file.
+pub struct SysFlistxattrHandle;
+
+impl Syscall for SysFlistxattrHandle {
+    /// Returns the number of arguments this syscall takes (3).
+    fn num_args(&self) -> usize {
+        3
+    }
+
+    fn handle(&self, args: &[usize], from_user: bool) -> Result<usize, SystemError> {
+        let inode = xattr_fd_inode(Self::fd(args))?;
+        do_listxattr(&inode, Self::list(args), Self::size(args), from_user)
+    }
+
+    /// Formats the syscall arguments for display/debugging purposes.
+    fn entry_format(&self, args: &[usize]) -> Vec<FormattedSyscallParam> {
+        vec![
+            FormattedSyscallParam::new("fd", Self::fd(args).to_string()),
+            FormattedSyscallParam::new("list", format!("{:#x}", Self::list(args) as usize)),
+            FormattedSyscallParam::new("size", Self::size(args).to_string()),
+        ]
+    }
+}
+
+impl SysFlistxattrHandle {
+    /// Extracts the file descriptor argument from syscall parameters.
+    fn fd(args: &[usize]) -> i32 {
+        args[0] as i32
+    }
+
+    /// Extracts the buffer receiving the attribute names argument from syscall parameters.
+    fn list(args: &[usize]) -> *mut u8 {
+        args[1] as *mut u8
+    }
+
+    /// Extracts the size of the buffer argument from syscall parameters.
+    fn size(args: &[usize]) -> usize {
+        args[2]
+    }
+}
+
+syscall_table_macros::declare_syscall!(SYS_FLISTXATTR, SysFlistxattrHandle);
@@ -0,0 +1,129 @@
+//! System call handlers for `removexattr`, `lremovexattr` and `fremovexattr`.
+
+use alloc::string::ToString;
+use alloc::vec::Vec;
+use system_error::SystemError;
+
+use crate::arch::syscall::nr::{SYS_FREMOVEXATTR, SYS_LREMOVEXATTR, SYS_REMOVEXATTR};
+use crate::syscall::table::FormattedSyscallParam;
+use crate::syscall::table::Syscall;
+
+use super::xattr_utils::{do_removexattr, xattr_fd_inode, xattr_path_inode};
+
+/// Handler for the `removexattr` system call.
+///
+/// Remove an extended attribute of the file at the given path, following symbolic links.
+pub struct SysRemovexattrHandle;
+
+impl Syscall for SysRemovexattrHandle {
+    /// Returns the number of arguments this syscall takes (2).
+    fn num_args(&self) -> usize {
+        2
+    }
+
+    fn handle(&self, args: &[usize], _from_user: bool) -> Result<usize, SystemError> {
+        let inode = xattr_path_inode(Self::path(args), true)?;
+        do_removexattr(&inode, Self::name(args))
+    }
+
+    /// Formats the syscall arguments for display/debugging purposes.
+    fn entry_format(&self, args: &[usize]) -> Vec<FormattedSyscallParam> {
+        vec![
+            FormattedSyscallParam::new("path", format!("{:#x}", Self::path(args) as usize)),
+            FormattedSyscallParam::new("name", format!("{:#x}", Self::name(args) as usize)),
+        ]
+    }
+}
+
+impl SysRemovexattrHandle {
+    /// Extracts the path argument from syscall parameters.
+    fn path(args: &[usize]) -> *const u8 {
+        args[0] as *const u8
+    }
+
+    /// Extracts the attribute name argument from syscall parameters.
+    fn name(args: &[usize]) -> *const u8 {
+        args[1] as *const u8
+    }
+}
+
+syscall_table_macros::declare_syscall!(SYS_REMOVEXATTR, SysRemovexattrHandle);
+
+/// Handler for the `lremovexattr` system call.
+///
+/// Remove an extended attribute of the file at the given path,
+/// without following a final symbolic link.
+pub struct SysLremovexattrHandle;
+
+impl Syscall for SysLremovexattrHandle {
+    /// Returns the number of arguments this syscall takes (2).
+    fn num_args(&self) -> usize {
+        2
+    }
+
+    fn handle(&self, args: &[usize], _from_user: bool) -> Result<usize, SystemError> {
+        let inode = xattr_path_inode(Self::path(args), false)?;
+        do_removexattr(&inode, Self::name(args))
+    }
+
+    /// Formats the syscall arguments for display/debugging purposes.
+    fn entry_format(&self, args: &[usize]) -> Vec<FormattedSyscallParam> {
+        vec![
+            FormattedSyscallParam::new("path", format!("{:#x}", Self::path(args) as usize)),
+            FormattedSyscallParam::new("name", format!("{:#x}", Self::name(args) as usize)),
+        ]
+    }
+}
+
+impl SysLremovexattrHandle {
+    /// Extracts the path argument from syscall parameters.
+    fn path(args: &[usize]) -> *const u8 {
+        args[0] as *const u8
+    }
+
+    /// Extracts the attribute name argument from syscall parameters.
+    fn name(args: &[usize]) -> *const u8 {
+        args[1] as *const u8
+    }
+}
+
+syscall_table_macros::declare_syscall!(SYS_LREMOVEXATTR, SysLremovexattrHandle);
+
+/// Handler for the `fremovexattr` system call.
+///
+/// Remove an extended attribute of an open file.
+pub struct SysFremovexattrHandle;
+
+impl Syscall for SysFremovexattrHandle {
+    /// Returns the number of arguments this syscall takes (2).
+    fn num_args(&self) -> usize {
+        2
+    }
+
+    fn handle(&self, args: &[usize], _from_user: bool) -> Result<usize, SystemError> {
+        let inode = xattr_fd_inode(Self::fd(args))?;
+        do_removexattr(&inode, Self::name(args))
+    }
+
+    /// Formats the syscall arguments for display/debugging purposes.
+    fn entry_format(&self, args: &[usize]) -> Vec<FormattedSyscallParam> {
+        vec![
+            FormattedSyscallParam::new("fd", Self::fd(args).to_string()),
+            FormattedSyscallParam::new("name", format!("{:#x}", Self::name(args) as usize)),
+        ]
+    }
+}
+
+impl SysFremovexattrHandle {
+    /// Extracts the file descriptor argument from syscall parameters.
+    fn fd(args: &[usize]) -> i32 {
+        args[0] as i32
+    }
+
+    /// Extracts the attribute name argument from syscall parameters.
+    fn name(args: &[usize]) -> *const u8 {
+        args[1] as *const u8
+    }
+}
+
+syscall_table_macros::declare_syscall!(SYS_FREMOVEXATTR, SysFremovexattrHandle);
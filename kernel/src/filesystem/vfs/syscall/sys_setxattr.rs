@@ -0,0 +1,204 @@
+//! System call handlers for `setxattr`, `lsetxattr` and `fsetxattr`.
+
+use alloc::string::ToString;
+use alloc::vec::Vec;
+use system_error::SystemError;
+
+use crate::arch::syscall::nr::{SYS_FSETXATTR, SYS_LSETXATTR, SYS_SETXATTR};
+use crate::syscall::table::FormattedSyscallParam;
+use crate::syscall::table::Syscall;
+
+use super::xattr_utils::{do_setxattr, xattr_fd_inode, xattr_path_inode};
+
+/// Handler for the `setxattr` system call.
+///
+/// Set an extended attribute value of the file at the given path, following symbolic links.
+pub struct SysSetxattrHandle;
+
+impl Syscall for SysSetxattrHandle {
+    /// Returns the number of arguments this syscall takes (5).
+    fn num_args(&self) -> usize {
+        5
+    }
+
+    fn handle(&self, args: &[usize], from_user: bool) -> Result<usize, SystemError> {
+        let inode = xattr_path_inode(Self::path(args), true)?;
+        do_setxattr(
+            &inode,
+            Self::name(args),
+            Self::value(args),
+            Self::size(args),
+            Self::flags(args),
+            from_user,
+        )
+    }
+
+    /// Formats the syscall arguments for display/debugging purposes.
+    fn entry_format(&self, args: &[usize]) -> Vec<FormattedSyscallParam> {
+        vec![
+            FormattedSyscallParam::new("path", format!("{:#x}", Self::path(args) as usize)),
+            FormattedSyscallParam::new("name", format!("{:#x}", Self::name(args) as usize)),
+            FormattedSyscallParam::new("value", format!("{:#x}", Self::value(args) as usize)),
+            FormattedSyscallParam::new("size", Self::size(args).to_string()),
+            FormattedSyscallParam::new("flags", Self::flags(args).to_string()),
+        ]
+    }
+}
+
+impl SysSetxattrHandle {
+    /// Extracts the path argument from syscall parameters.
+    fn path(args: &[usize]) -> *const u8 {
+        args[0] as *const u8
+    }
+
+    /// Extracts the attribute name argument from syscall parameters.
+    fn name(args: &[usize]) -> *const u8 {
+        args[1] as *const u8
+    }
+
+    /// Extracts the attribute value buffer argument from syscall parameters.
+    fn value(args: &[usize]) -> *const u8 {
+        args[2] as *const u8
+    }
+
+    /// Extracts the size of the buffer argument from syscall parameters.
+    fn size(args: &[usize]) -> usize {
+        args[3]
+    }
+
+    /// Extracts the XATTR_CREATE/XATTR_REPLACE flags argument from syscall parameters.
+    fn flags(args: &[usize]) -> u32 {
+        args[4] as u32
+    }
+}
+
+syscall_table_macros::declare_syscall!(SYS_SETXATTR, SysSetxattrHandle);
+
+/// Handler for the `lsetxattr` system call.
+///
+/// Set an extended attribute value of the file at the given path,
+/// without following a final symbolic link.
+pub struct SysLsetxattrHandle;
+
+impl Syscall for SysLsetxattrHandle {
+    /// Returns the number of arguments this syscall takes (5).
+    fn num_args(&self) -> usize {
+        5
+    }
+
+    fn handle(&self, args: &[usize], from_user: bool) -> Result<usize, SystemError> {
+        let inode = xattr_path_inode(Self::path(args), false)?;
+        do_setxattr(
+            &inode,
+            Self::name(args),
+            Self::value(args),
+            Self::size(args),
+            Self::flags(args),
+            from_user,
+        )
+    }
+
+    /// Formats the syscall arguments for display/debugging purposes.
+    fn entry_format(&self, args: &[usize]) -> Vec<FormattedSyscallParam> {
+        vec![
+            FormattedSyscallParam::new("path", format!("{:#x}", Self::path(args) as usize)),
+            FormattedSyscallParam::new("name", format!("{:#x}", Self::name(args) as usize)),
+            FormattedSyscallParam::new("value", format!("{:#x}", Self::value(args) as usize)),
+            FormattedSyscallParam::new("size", Self::size(args).to_string()),
+            FormattedSyscallParam::new("flags", Self::flags(args).to_string()),
+        ]
+    }
+}
+
+impl SysLsetxattrHandle {
+    /// Extracts the path argument from syscall parameters.
+    fn path(args: &[usize]) -> *const u8 {
+        args[0] as *const u8
+    }
+
+    /// Extracts the attribute name argument from syscall parameters.
+    fn name(args: &[usize]) -> *const u8 {
+        args[1] as *const u8
+    }
+
+    /// Extracts the attribute value buffer argument from syscall parameters.
+    fn value(args: &[usize]) -> *const u8 {
+        args[2] as *const u8
+    }
+
+    /// Extracts the size of the buffer argument from syscall parameters.
+    fn size(args: &[usize]) -> usize {
+        args[3]
+    }
+
+    /// Extracts the XATTR_CREATE/XATTR_REPLACE flags argument from syscall parameters.
+    fn flags(args: &[usize]) -> u32 {
+        args[4] as u32
+    }
+}
+
+syscall_table_macros::declare_syscall!(SYS_LSETXATTR, SysLsetxattrHandle);
+
+/// Handler for the `fsetxattr` system call.
+///
+/// Set an extended attribute value of an open file.
+pub struct SysFsetxattrHandle;
+
+impl Syscall for SysFsetxattrHandle {
+    /// Returns the number of arguments this syscall takes (5).
+    fn num_args(&self) -> usize {
+        5
+    }
+
+    fn handle(&self, args: &[usize], from_user: bool) -> Result<usize, SystemError> {
+        let inode = xattr_fd_inode(Self::fd(args))?;
+        do_setxattr(
+            &inode,
+            Self::name(args),
+            Self::value(args),
+            Self::size(args),
+            Self::flags(args),
+            from_user,
+        )
+    }
+
+    /// Formats the syscall arguments for display/debugging purposes.
+    fn entry_format(&self, args: &[usize]) -> Vec<FormattedSyscallParam> {
+        vec![
+            FormattedSyscallParam::new("fd", Self::fd(args).to_string()),
+            FormattedSyscallParam::new("name", format!("{:#x}", Self::name(args) as usize)),
+            FormattedSyscallParam::new("value", format!("{:#x}", Self::value(args) as usize)),
+            FormattedSyscallParam::new("size", Self::size(args).to_string()),
+            FormattedSyscallParam::new("flags", Self::flags(args).to_string()),
+        ]
+    }
+}
+
+impl SysFsetxattrHandle {
+    /// Extracts the file descriptor argument from syscall parameters.
+    fn fd(args: &[usize]) -> i32 {
+        args[0] as i32
+    }
+
+    /// Extracts the attribute name argument from syscall parameters.
+    fn name(args: &[usize]) -> *const u8 {
+        args[1] as *const u8
+    }
+
+    /// Extracts the attribute value buffer argument from syscall parameters.
+    fn value(args: &[usize]) -> *const u8 {
+        args[2] as *const u8
+    }
+
+    /// Extracts the size of the buffer argument from syscall parameters.
+    fn size(args: &[usize]) -> usize {
+        args[3]
+    }
+
+    /// Extracts the XATTR_CREATE/XATTR_REPLACE flags argument from syscall parameters.
+    fn flags(args: &[usize]) -> u32 {
+        args[4] as u32
+    }
+}
+
+syscall_table_macros::declare_syscall!(SYS_FSETXATTR, SysFsetxattrHandle);
@@ -0,0 +1,144 @@
+//! Common helpers shared by the extended attribute system calls.
+
+use alloc::{string::String, sync::Arc};
+use system_error::SystemError;
+
+use crate::{
+    filesystem::vfs::{
+        fcntl::AtFlags,
+        utils::user_path_at,
+        xattr::{
+            vfs_getxattr, vfs_listxattr, vfs_removexattr, vfs_setxattr, XattrFlags, XATTR_LIST_MAX,
+            XATTR_NAME_MAX, XATTR_SIZE_MAX,
+        },
+        IndexNode, MAX_PATHLEN, VFS_MAX_FOLLOW_SYMLINK_TIMES,
+    },
+    process::ProcessManager,
+    syscall::user_access::{check_and_clone_cstr, UserBufferReader, UserBufferWriter},
+};
+
+/// Resolves a user supplied path to an inode.
+///
+/// # Arguments
+/// * `path` - Pointer to the path string
+/// * `follow_symlink` - Whether to follow a symbolic link in the final component
+pub(super) fn xattr_path_inode(
+    path: *const u8,
+    follow_symlink: bool,
+) -> Result<Arc<dyn IndexNode>, SystemError> {
+    let path = check_and_clone_cstr(path, Some(MAX_PATHLEN))?
+        .into_string()
+        .map_err(|_| SystemError::EINVAL)?;
+    if path.is_empty() {
+        return Err(SystemError::ENOENT);
+    }
+    let (inode, path) = user_path_at(
+        &ProcessManager::current_pcb(),
+        AtFlags::AT_FDCWD.bits(),
+        &path,
+    )?;
+    inode.lookup_follow_symlink2(&path, VFS_MAX_FOLLOW_SYMLINK_TIMES, follow_symlink)
+}
+
+/// Resolves a file descriptor to an inode.
+pub(super) fn xattr_fd_inode(fd: i32) -> Result<Arc<dyn IndexNode>, SystemError> {
+    let binding = ProcessManager::current_pcb().fd_table();
+    let fd_table_guard = binding.read();
+    let file = fd_table_guard
+        .get_file_by_fd(fd)
+        .ok_or(SystemError::EBADF)?;
+    // drop guard 以避免无法调度的问题
+    drop(fd_table_guard);
+    Ok(file.inode())
+}
+
+/// Copies an attribute name from user space.
+///
+/// Names that are empty or longer than `XATTR_NAME_MAX` are rejected with `ERANGE`.
+fn xattr_name(name: *const u8) -> Result<String, SystemError> {
+    let name = check_and_clone_cstr(name, Some(XATTR_NAME_MAX + 1))?
+        .into_string()
+        .map_err(|_| SystemError::EINVAL)?;
+    if name.is_empty() || name.len() > XATTR_NAME_MAX {
+        return Err(SystemError::ERANGE);
+    }
+    Ok(name)
+}
+
+/// Sets an extended attribute on `inode` from user supplied arguments.
+pub(super) fn do_setxattr(
+    inode: &Arc<dyn IndexNode>,
+    name: *const u8,
+    value: *const u8,
+    size: usize,
+    flags: u32,
+    from_user: bool,
+) -> Result<usize, SystemError> {
+    let flags = XattrFlags::from_bits(flags).ok_or(SystemError::EINVAL)?;
+    let name = xattr_name(name)?;
+    if size > XATTR_SIZE_MAX {
+        return Err(SystemError::E2BIG);
+    }
+
+    let value = if size == 0 {
+        &[][..]
+    } else {
+        UserBufferReader::new(value, size, from_user)?.read_from_user::<u8>(0)?
+    };
+    vfs_setxattr(inode, &name, value, flags)?;
+    Ok(0)
+}
+
+/// Reads an extended attribute of `inode` into a user buffer.
+///
+/// When `size` is zero only the length of the value is returned.
+pub(super) fn do_getxattr(
+    inode: &Arc<dyn IndexNode>,
+    name: *const u8,
+    value: *mut u8,
+    size: usize,
+    from_user: bool,
+) -> Result<usize, SystemError> {
+    let name = xattr_name(name)?;
+    if size == 0 {
+        return vfs_getxattr(inode, &name, &mut []);
+    }
+
+    let size = size.min(XATTR_SIZE_MAX);
+    let mut kbuf = vec![0u8; size];
+    let len = vfs_getxattr(inode, &name, &mut kbuf)?;
+    let mut writer = UserBufferWriter::new(value, len, from_user)?;
+    writer.copy_to_user(&kbuf[..len], 0)?;
+    Ok(len)
+}
+
+/// Lists the extended attribute names of `inode` into a user buffer.
+///
+/// When `size` is zero only the length of the list is returned.
+pub(super) fn do_listxattr(
+    inode: &Arc<dyn IndexNode>,
+    list: *mut u8,
+    size: usize,
+    from_user: bool,
+) -> Result<usize, SystemError> {
+    if size == 0 {
+        return vfs_listxattr(inode, &mut []);
+    }
+
+    let size = size.min(XATTR_LIST_MAX);
+    let mut kbuf = vec![0u8; size];
+    let len = vfs_listxattr(inode, &mut kbuf)?;
+    let mut writer = UserBufferWriter::new(list, len, from_user)?;
+    writer.copy_to_user(&kbuf[..len], 0)?;
+    Ok(len)
+}
+
+/// Removes an extended attribute from `inode`.
+pub(super) fn do_removexattr(
+    inode: &Arc<dyn IndexNode>,
+    name: *const u8,
+) -> Result<usize, SystemError> {
+    let name = xattr_name(name)?;
+    vfs_removexattr(inode, &name)?;
+    Ok(0)
+}
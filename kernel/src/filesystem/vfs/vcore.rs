@@ -25,6 +25,7 @@ use crate::{
 use super::{
     fcntl::AtFlags,
     file::FileMode,
+    fsnotify::{fsnotify_create, fsnotify_delete},
     mount::{init_mountlist, MOUNT_LIST},
     stat::LookUpFlags,
     syscall::UmountFlag,
@@ -185,7 +186,9 @@ pub fn do_mkdir_at(
             current_inode.lookup_follow_symlink(parent, VFS_MAX_FOLLOW_SYMLINK_TIMES)?;
     }
     // debug!("mkdir at {:?}", current_inode.metadata()?.inode_id);
-    return current_inode.mkdir(name, ModeType::from_bits_truncate(mode.bits()));
+    let inode = current_inode.mkdir(name, ModeType::from_bits_truncate(mode.bits()))?;
+    fsnotify_create(&current_inode, name, true);
+    return Ok(inode);
 }
 
 /// @brief 删除文件夹
@@ -217,6 +220,7 @@ pub fn do_remove_dir(dirfd: i32, path: &str) -> Result<u64, SystemError> {
 
     // 删除文件夹
     parent_inode.rmdir(filename)?;
+    fsnotify_delete(&parent_inode, filename, &target_inode, true, true);
 
     return Ok(0);
 }
@@ -237,8 +241,10 @@ pub fn do_unlink_at(dirfd: i32, path: &str) -> Result<u64, SystemError> {
             return Err(SystemError::ENOENT);
         }
     }
+    let inode = inode?;
+    let metadata = inode.metadata()?;
     // 禁止在目录上unlink
-    if inode.unwrap().metadata()?.file_type == FileType::Dir {
+    if metadata.file_type == FileType::Dir {
         return Err(SystemError::EPERM);
     }
 
@@ -253,6 +259,7 @@ pub fn do_unlink_at(dirfd: i32, path: &str) -> Result<u64, SystemError> {
 
     // 删除文件
     parent_inode.unlink(filename)?;
+    fsnotify_delete(&parent_inode, filename, &inode, false, metadata.nlinks <= 1);
 
     return Ok(0);
 }
@@ -296,6 +303,7 @@ pub fn do_symlinkat(from: *const u8, newdfd: i32, to: *const u8) -> Result<usize
     let buf = old_remain_path.as_bytes();
     let len = buf.len();
     new_inode.write_at(0, len, buf, SpinLock::new(FilePrivateData::Unused).lock())?;
+    fsnotify_create(&new_parent, new_name, false);
     return Ok(0);
 }
 
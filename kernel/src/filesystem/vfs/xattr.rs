@@ -0,0 +1,308 @@
+//! 扩展属性(xattr)的通用实现
+//!
+//! 属性名必须带有命名空间前缀，目前支持`user.`、`trusted.`、`security.`和`system.`四种命名空间。
+
+use alloc::{
+    collections::BTreeMap,
+    string::{String, ToString},
+    sync::Arc,
+    vec::Vec,
+};
+use system_error::SystemError;
+
+use crate::process::{capability::capable, cred::CAPFlags, ProcessManager};
+
+use super::{
+    fsnotify::fsnotify_attrib,
+    permission::{inode_permission, PermissionMask},
+    posix_acl::{posix_acl_xattr_set, PosixAclType},
+    syscall::ModeType,
+    FileType, IndexNode, Metadata,
+};
+
+/// 属性名的最大长度
+pub const XATTR_NAME_MAX: usize = 255;
+/// 属性值的最大长度
+pub const XATTR_SIZE_MAX: usize = 65536;
+/// listxattr返回的属性名列表的最大长度
+pub const XATTR_LIST_MAX: usize = 65536;
+
+pub const XATTR_USER_PREFIX: &str = "user.";
+pub const XATTR_TRUSTED_PREFIX: &str = "trusted.";
+pub const XATTR_SECURITY_PREFIX: &str = "security.";
+pub const XATTR_SYSTEM_PREFIX: &str = "system.";
+
+/// 保存文件capability的扩展属性
+pub const XATTR_NAME_CAPS: &str = "security.capability";
+
+bitflags! {
+    /// setxattr的flags参数
+    pub struct XattrFlags: u32 {
+        /// 属性已存在时失败
+        const XATTR_CREATE = 0x1;
+        /// 属性不存在时失败
+        const XATTR_REPLACE = 0x2;
+    }
+}
+
+/// 扩展属性的命名空间
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum XattrNamespace {
+    User,
+    Trusted,
+    Security,
+    System,
+}
+
+impl XattrNamespace {
+    /// 根据属性名的前缀解析命名空间
+    ///
+    /// ## 返回值
+    ///
+    /// - `Err(SystemError::EOPNOTSUPP_OR_ENOTSUP)` 不支持的命名空间，或者属性名只有前缀
+    pub fn from_name(name: &str) -> Result<Self, SystemError> {
+        let (ns, prefix) = if name.starts_with(XATTR_USER_PREFIX) {
+            (Self::User, XATTR_USER_PREFIX)
+        } else if name.starts_with(XATTR_TRUSTED_PREFIX) {
+            (Self::Trusted, XATTR_TRUSTED_PREFIX)
+        } else if name.starts_with(XATTR_SECURITY_PREFIX) {
+            (Self::Security, XATTR_SECURITY_PREFIX)
+        } else if name.starts_with(XATTR_SYSTEM_PREFIX) {
+            (Self::System, XATTR_SYSTEM_PREFIX)
+        } else {
+            return Err(SystemError::EOPNOTSUPP_OR_ENOTSUP);
+        };
+
+        if name.len() == prefix.len() {
+            return Err(SystemError::EOPNOTSUPP_OR_ENOTSUP);
+        }
+        Ok(ns)
+    }
+}
+
+/// 保存在内存中的扩展属性集合，供ramfs、tmpfs等内存文件系统使用
+#[derive(Debug, Default, Clone)]
+pub struct SimpleXattr {
+    attrs: BTreeMap<String, Vec<u8>>,
+}
+
+impl SimpleXattr {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 获取属性值
+    ///
+    /// ## 参数
+    ///
+    /// - `name` 属性名
+    /// - `buf` 存放属性值的缓冲区，长度为0时只返回属性值的长度
+    ///
+    /// ## 返回值
+    ///
+    /// - `Ok(usize)` 属性值的长度
+    /// - `Err(SystemError::ENODATA)` 属性不存在
+    /// - `Err(SystemError::ERANGE)` 缓冲区太小
+    pub fn get(&self, name: &str, buf: &mut [u8]) -> Result<usize, SystemError> {
+        let value = self.attrs.get(name).ok_or(SystemError::ENODATA)?;
+        copy_to_buf(value, buf)
+    }
+
+    /// 设置属性值
+    pub fn set(&mut self, name: &str, value: &[u8], flags: XattrFlags) -> Result<(), SystemError> {
+        let exists = self.attrs.contains_key(name);
+        if exists && flags.contains(XattrFlags::XATTR_CREATE) {
+            return Err(SystemError::EEXIST);
+        }
+        if !exists && flags.contains(XattrFlags::XATTR_REPLACE) {
+            return Err(SystemError::ENODATA);
+        }
+        self.attrs.insert(name.to_string(), value.to_vec());
+        Ok(())
+    }
+
+    /// 将所有属性名以'\0'分隔写入`buf`，`buf`长度为0时只返回所需的长度
+    pub fn list(&self, buf: &mut [u8]) -> Result<usize, SystemError> {
+        let mut names = Vec::new();
+        for name in self.attrs.keys() {
+            names.extend_from_slice(name.as_bytes());
+            names.push(0);
+        }
+        copy_to_buf(&names, buf)
+    }
+
+    /// 删除属性
+    pub fn remove(&mut self, name: &str) -> Result<(), SystemError> {
+        self.attrs
+            .remove(name)
+            .map(|_| ())
+            .ok_or(SystemError::ENODATA)
+    }
+
+    /// 获取属性值的拷贝
+    pub fn get_value(&self, name: &str) -> Option<Vec<u8>> {
+        self.attrs.get(name).cloned()
+    }
+}
+
+/// 将`src`拷贝到`buf`中，`buf`长度为0时只返回`src`的长度
+pub fn copy_to_buf(src: &[u8], buf: &mut [u8]) -> Result<usize, SystemError> {
+    if buf.is_empty() {
+        return Ok(src.len());
+    }
+    if buf.len() < src.len() {
+        return Err(SystemError::ERANGE);
+    }
+    buf[..src.len()].copy_from_slice(src);
+    Ok(src.len())
+}
+
+/// 检查属性名是否合法，并返回其所属的命名空间
+fn xattr_check_name(name: &str) -> Result<XattrNamespace, SystemError> {
+    if name.is_empty() || name.len() > XATTR_NAME_MAX {
+        return Err(SystemError::ERANGE);
+    }
+    XattrNamespace::from_name(name)
+}
+
+/// 检查当前进程是否有权限访问inode的扩展属性
+///
+/// ## 参数
+///
+/// - `inode` 目标inode
+/// - `name` 属性名
+/// - `ns` 属性所属的命名空间
+/// - `write` 是否为修改操作
+fn xattr_permission(
+    inode: &Arc<dyn IndexNode>,
+    name: &str,
+    ns: XattrNamespace,
+    write: bool,
+) -> Result<(), SystemError> {
+    let cred = ProcessManager::current_pcb().cred();
+    let is_owner = |metadata: &Metadata| {
+        cred.fsuid.data() == metadata.uid || cred.has_capability(CAPFlags::CAP_FOWNER)
+    };
+    match ns {
+        // trusted命名空间只对拥有CAP_SYS_ADMIN的进程可见，对其他进程表现为属性不存在
+        XattrNamespace::Trusted => {
+            if !cred.has_capability(CAPFlags::CAP_SYS_ADMIN) {
+                return Err(if write {
+                    SystemError::EPERM
+                } else {
+                    SystemError::ENODATA
+                });
+            }
+        }
+        // 修改文件capability需要CAP_SETFCAP，修改其他security属性需要CAP_SYS_ADMIN
+        XattrNamespace::Security => {
+            let cap = if name == XATTR_NAME_CAPS {
+                CAPFlags::CAP_SETFCAP
+            } else {
+                CAPFlags::CAP_SYS_ADMIN
+            };
+            if write && !cred.has_capability(cap) {
+                return Err(SystemError::EPERM);
+            }
+        }
+        XattrNamespace::System => {
+            if write && !is_owner(&inode.metadata()?) {
+                return Err(SystemError::EPERM);
+            }
+        }
+        XattrNamespace::User => {
+            // user命名空间的属性只能设置在普通文件和目录上
+            let metadata = inode.metadata()?;
+            if metadata.file_type != FileType::File && metadata.file_type != FileType::Dir {
+                return Err(if write {
+                    SystemError::EPERM
+                } else {
+                    SystemError::ENODATA
+                });
+            }
+            // 设置了粘滞位的目录只有所有者可以修改
+            if write
+                && metadata.file_type == FileType::Dir
+                && metadata.mode.contains(ModeType::S_ISVTX)
+                && !is_owner(&metadata)
+            {
+                return Err(SystemError::EPERM);
+            }
+            inode_permission(
+                inode,
+                if write {
+                    PermissionMask::MAY_WRITE
+                } else {
+                    PermissionMask::MAY_READ
+                },
+            )?;
+        }
+    }
+    Ok(())
+}
+
+/// 获取inode的扩展属性
+pub fn vfs_getxattr(
+    inode: &Arc<dyn IndexNode>,
+    name: &str,
+    buf: &mut [u8],
+) -> Result<usize, SystemError> {
+    let ns = xattr_check_name(name)?;
+    xattr_permission(inode, name, ns, false)?;
+    let buf_len = buf.len().min(XATTR_SIZE_MAX);
+    inode.getxattr(name, &mut buf[..buf_len])
+}
+
+/// 设置inode的扩展属性
+pub fn vfs_setxattr(
+    inode: &Arc<dyn IndexNode>,
+    name: &str,
+    value: &[u8],
+    flags: XattrFlags,
+) -> Result<(), SystemError> {
+    let ns = xattr_check_name(name)?;
+    if value.len() > XATTR_SIZE_MAX {
+        return Err(SystemError::E2BIG);
+    }
+    xattr_permission(inode, name, ns, true)?;
+    if let Some(acl_type) = PosixAclType::from_xattr_name(name) {
+        posix_acl_xattr_set(inode, acl_type, value, flags)?;
+    } else {
+        inode.setxattr(name, value, flags)?;
+    }
+    fsnotify_attrib(inode);
+    Ok(())
+}
+
+/// 列出inode的所有扩展属性名
+///
+/// 当前进程无权访问的trusted命名空间的属性不会被列出
+pub fn vfs_listxattr(inode: &Arc<dyn IndexNode>, buf: &mut [u8]) -> Result<usize, SystemError> {
+    let size = inode.listxattr(&mut [])?;
+    let mut names = vec![0u8; size];
+    let size = inode.listxattr(&mut names)?;
+    names.truncate(size);
+
+    let privileged = capable(CAPFlags::CAP_SYS_ADMIN);
+    let mut result = Vec::with_capacity(names.len());
+    for name in names.split(|c| *c == 0).filter(|n| !n.is_empty()) {
+        if !privileged && name.starts_with(XATTR_TRUSTED_PREFIX.as_bytes()) {
+            continue;
+        }
+        result.extend_from_slice(name);
+        result.push(0);
+    }
+    if result.len() > XATTR_LIST_MAX {
+        return Err(SystemError::E2BIG);
+    }
+    copy_to_buf(&result, buf)
+}
+
+/// 删除inode的扩展属性
+pub fn vfs_removexattr(inode: &Arc<dyn IndexNode>, name: &str) -> Result<(), SystemError> {
+    let ns = xattr_check_name(name)?;
+    xattr_permission(inode, name, ns, true)?;
+    inode.removexattr(name)?;
+    fsnotify_attrib(inode);
+    Ok(())
+}
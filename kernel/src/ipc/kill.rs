@@ -1,9 +1,39 @@
 use crate::arch::ipc::signal::{SigCode, Signal};
 use crate::ipc::signal_types::{SigInfo, SigType};
-use crate::process::{process_group::Pgid, Pid, ProcessManager};
+use crate::process::{
+    cred::CAPFlags, process_group::Pgid, Pid, ProcessControlBlock, ProcessManager,
+};
+use alloc::sync::Arc;
 use core::sync::atomic::compiler_fence;
 use system_error::SystemError;
 
+/// ### 检查当前进程是否有权限向目标进程发送信号
+///
+/// 发送者的uid或euid与目标进程的uid或suid相同，或者发送者拥有CAP_KILL时允许发送。
+/// 此外，SIGCONT可以发送给同一会话中的任何进程。
+///
+/// 参考 https://code.dragonos.org.cn/xref/linux-6.1.9/kernel/signal.c#815
+pub fn check_kill_permission(
+    sig: Signal,
+    target: &Arc<ProcessControlBlock>,
+) -> Result<(), SystemError> {
+    let current = ProcessManager::current_pcb();
+    let cred = current.cred();
+    let tcred = target.cred();
+    if cred.euid == tcred.suid
+        || cred.euid == tcred.uid
+        || cred.uid == tcred.suid
+        || cred.uid == tcred.uid
+        || cred.has_capability(CAPFlags::CAP_KILL)
+    {
+        return Ok(());
+    }
+    if sig == Signal::SIGCONT && current.sid() == target.sid() {
+        return Ok(());
+    }
+    Err(SystemError::EPERM)
+}
+
 /// ### 杀死一个进程
 pub fn kill_process(pid: Pid, sig: Signal) -> Result<usize, SystemError> {
     // 初始化signal info
@@ -27,18 +57,3 @@ pub fn kill_process_group(pgid: Pgid, sig: Signal) -> Result<usize, SystemError>
     }
     Ok(0)
 }
-
-/// ### 杀死所有进程
-/// - 该函数会杀死所有进程，除了当前进程和init进程
-pub fn kill_all(sig: Signal) -> Result<usize, SystemError> {
-    let current_pid = ProcessManager::current_pcb().pid();
-    let all_processes = ProcessManager::get_all_processes();
-
-    for pid_val in all_processes {
-        if pid_val == current_pid || pid_val.data() == 1 {
-            continue;
-        }
-        kill_process(pid_val, sig)?; // Call the new common function
-    }
-    Ok(0)
-}
@@ -10,7 +10,9 @@ use crate::{
     libs::spinlock::SpinLockGuard,
     mm::VirtAddr,
     process::{
-        pid::PidType, Pid, ProcessControlBlock, ProcessFlags, ProcessManager, ProcessSignalInfo,
+        job_control::{signal_continue, signal_stop_flush_continue},
+        pid::PidType,
+        Pid, ProcessControlBlock, ProcessFlags, ProcessManager, ProcessSignalInfo,
     },
     time::Instant,
 };
@@ -284,22 +286,11 @@ impl Signal {
     ///
     /// - `false` 不能发送信号
     fn prepare_sianal(&self, pcb: Arc<ProcessControlBlock>, _force: bool) -> bool {
-        let flush: SigSet;
         if !(self.into_sigset() & SIG_KERNEL_STOP_MASK).is_empty() {
-            flush = Signal::SIGCONT.into_sigset();
-            pcb.sig_info_mut()
-                .sig_shared_pending_mut()
-                .flush_by_mask(&flush);
-            // TODO 对每个子线程 flush mask
+            signal_stop_flush_continue(&pcb);
         } else if *self == Signal::SIGCONT {
-            flush = SIG_KERNEL_STOP_MASK;
-            assert!(!flush.is_empty());
-            pcb.sig_info_mut()
-                .sig_shared_pending_mut()
-                .flush_by_mask(&flush);
-            let _r = ProcessManager::wakeup_stop(&pcb);
-            // TODO 对每个子线程 flush mask
-            // 这里需要补充一段逻辑，详见https://code.dragonos.org.cn/xref/linux-6.1.9/kernel/signal.c#952
+            // 无论SIGCONT是否被忽略，都要让停止的线程组继续运行
+            signal_continue(&pcb);
         }
 
         // 一个被阻塞了的信号肯定是要被处理的
@@ -342,7 +333,8 @@ fn signal_wake_up(pcb: Arc<ProcessControlBlock>, _guard: SpinLockGuard<SignalStr
                 e
             );
         });
-    } else if state.is_stopped() {
+    } else if state.is_stopped() && fatal {
+        // 停止的进程只能被SIGKILL唤醒，其余的信号会在它被SIGCONT唤醒之后处理
         ProcessManager::wakeup_stop(&pcb).unwrap_or_else(|e| {
             wakeup_ok = false;
             warn!(
@@ -350,10 +350,16 @@ impl SigInfo {
 pub enum SigType {
     Kill(Pid),
     Alarm(Pid),
+    /// 子进程的状态发生变化时，发送给父进程的SIGCHLD
+    SigChild {
+        pid: Pid,
+        /// CLD_EXITED、CLD_STOPPED、CLD_CONTINUED等
+        code: i32,
+        status: i32,
+    },
     // 后续完善下列中的具体字段
     // Timer,
     // Rt,
-    // SigChild,
     // SigFault,
     // SigPoll,
     // SigSys,
@@ -462,8 +468,10 @@ impl SigPending {
     /// @brief 从sigpending中删除mask中被置位的信号。也就是说，比如mask的第1位被置为1,那么就从sigqueue中删除所有signum为2的信号的信息。
     pub fn flush_by_mask(&mut self, mask: &SigSet) {
         // 定义过滤器，从sigqueue中删除mask中被置位的信号
-        let filter = |x: &SigInfo| !mask.contains(SigSet::from_bits_truncate(x.sig_no as u64));
+        let filter =
+            |x: &SigInfo| x.sig_no <= 0 || !mask.contains(Signal::from(x.sig_no).into_sigset());
         self.queue.q.retain(filter);
+        self.signal.remove(*mask);
     }
 }
 
@@ -1,4 +1,5 @@
 use alloc::string::ToString;
+use alloc::sync::Arc;
 use alloc::vec::Vec;
 use core::ffi::c_int;
 
@@ -6,12 +7,12 @@ use crate::syscall::table::FormattedSyscallParam;
 use crate::syscall::table::Syscall;
 use crate::{
     arch::{ipc::signal::Signal, syscall::nr::SYS_KILL},
-    process::{process_group::Pgid, Pid, ProcessManager},
+    process::{process_group::Pgid, Pid, ProcessControlBlock, ProcessManager},
 };
 use log::warn;
 use system_error::SystemError;
 
-use crate::ipc::kill::{kill_all, kill_process, kill_process_group};
+use crate::ipc::kill::{check_kill_permission, kill_process};
 
 /// ### pid转换器，将输入的id转换成对应的pid或pgid
 /// - 如果id < -1，则为pgid
@@ -54,6 +55,27 @@ impl SysKillHandle {
         // 第二个参数是信号值
         args[1] as c_int
     }
+
+    /// 向有权限发送信号的目标进程发送信号，如果没有任何一个进程有权限，则返回EPERM
+    fn kill_permitted(
+        targets: Vec<Arc<ProcessControlBlock>>,
+        sig: Signal,
+    ) -> Result<usize, SystemError> {
+        let mut result = if targets.is_empty() {
+            Err(SystemError::ESRCH)
+        } else {
+            Err(SystemError::EPERM)
+        };
+        for target in targets {
+            if check_kill_permission(sig, &target).is_ok() {
+                result = kill_process(target.pid(), sig);
+                if result.is_err() {
+                    return result;
+                }
+            }
+        }
+        result
+    }
 }
 
 impl Syscall for SysKillHandle {
@@ -72,9 +94,32 @@ impl Syscall for SysKillHandle {
         }
 
         match converter {
-            PidConverter::Pid(pid) => kill_process(pid, sig),
-            PidConverter::Pgid(pgid) => kill_process_group(pgid, sig),
-            PidConverter::All => kill_all(sig),
+            PidConverter::Pid(pid) => {
+                let target = ProcessManager::find(pid).ok_or(SystemError::ESRCH)?;
+                check_kill_permission(sig, &target)?;
+                kill_process(pid, sig)
+            }
+            PidConverter::Pgid(pgid) => {
+                let pg = ProcessManager::find_process_group(pgid).ok_or(SystemError::ESRCH)?;
+                let targets = pg
+                    .process_group_inner
+                    .lock()
+                    .processes
+                    .values()
+                    .cloned()
+                    .collect::<Vec<_>>();
+                Self::kill_permitted(targets, sig)
+            }
+            PidConverter::All => {
+                // 不向init进程和当前进程发送信号
+                let current_pid = ProcessManager::current_pid();
+                let targets = ProcessManager::get_all_processes()
+                    .into_iter()
+                    .filter(|pid| *pid != current_pid && pid.data() != 1)
+                    .filter_map(ProcessManager::find)
+                    .collect::<Vec<_>>();
+                Self::kill_permitted(targets, sig)
+            }
         }
     }
 
@@ -0,0 +1,104 @@
+//! 脚本加载器
+//!
+//! 以`#!`开头的文件被视为脚本。加载器解析第一行中的解释器路径和可选参数，
+//! 把要加载的文件替换为解释器，然后重新查找能够加载解释器的加载器。
+//!
+//! 参考 https://code.dragonos.org.cn/xref/linux-6.1.9/fs/binfmt_script.c
+
+use alloc::{ffi::CString, string::String, vec::Vec};
+
+use crate::process::exec::{
+    load_binary_file, BinaryLoader, BinaryLoaderResult, ExecError, ExecParam,
+};
+
+/// `#!`行的最大长度（包括`#!`）
+const BINPRM_BUF_SIZE: usize = 256;
+
+#[derive(Debug)]
+pub struct ScriptLoader;
+
+pub const SCRIPT_LOADER: ScriptLoader = ScriptLoader::new();
+
+impl ScriptLoader {
+    pub const fn new() -> Self {
+        Self
+    }
+
+    /// 解析`#!`行
+    ///
+    /// ## 返回值
+    ///
+    /// (解释器路径, 可选参数)。解释器路径之后的内容（去掉首尾的空白）整体作为一个参数
+    fn parse_shebang(buf: &[u8]) -> Result<(&str, Option<&str>), ExecError> {
+        let buf = &buf[..buf.len().min(BINPRM_BUF_SIZE)];
+        let line = buf.strip_prefix(b"#!").ok_or(ExecError::NotExecutable)?;
+        let (line, truncated) = match line.iter().position(|&c| c == b'\n' || c == 0) {
+            Some(end) => (&line[..end], false),
+            None => (line, true),
+        };
+
+        let is_blank = |c: &u8| *c == b' ' || *c == b'\t';
+        let start = line
+            .iter()
+            .position(|c| !is_blank(c))
+            .ok_or(ExecError::NotExecutable)?;
+        let line = &line[start..];
+        let name_end = line.iter().position(is_blank).unwrap_or(line.len());
+        // 第一行超过了缓冲区，并且解释器路径之后没有空白，说明解释器路径可能被截断了
+        if truncated && name_end == line.len() {
+            return Err(ExecError::NotExecutable);
+        }
+
+        let name = core::str::from_utf8(&line[..name_end]).map_err(|_| ExecError::ParseError)?;
+        let rest = &line[name_end..];
+        let arg = match rest.iter().position(|c| !is_blank(c)) {
+            Some(arg_start) => {
+                let arg_end = rest.iter().rposition(|c| !is_blank(c)).unwrap() + 1;
+                Some(
+                    core::str::from_utf8(&rest[arg_start..arg_end])
+                        .map_err(|_| ExecError::ParseError)?,
+                )
+            }
+            None => None,
+        };
+        return Ok((name, arg));
+    }
+}
+
+impl BinaryLoader for ScriptLoader {
+    fn probe(&'static self, _param: &ExecParam, buf: &[u8]) -> Result<(), ExecError> {
+        if buf.starts_with(b"#!") {
+            return Ok(());
+        }
+        return Err(ExecError::NotExecutable);
+    }
+
+    fn load(
+        &'static self,
+        param: &mut ExecParam,
+        head_buf: &[u8],
+    ) -> Result<BinaryLoaderResult, ExecError> {
+        let (name, arg) = Self::parse_shebang(head_buf)?;
+        let name = String::from(name);
+        let arg = arg.map(|arg| CString::new(arg).unwrap());
+
+        let inode = ExecParam::lookup_exec(&name).map_err(ExecError::Errno)?;
+        let script = CString::new(param.interp()).map_err(|_| ExecError::InvalidParemeter)?;
+        param
+            .set_interpreter(&name, inode)
+            .map_err(ExecError::Errno)?;
+
+        // 参数列表改写为: 解释器 [参数] 脚本路径 原参数[1..]
+        let args = &mut param.init_info_mut().args;
+        if !args.is_empty() {
+            args.remove(0);
+        }
+        let mut prefix: Vec<CString> = Vec::new();
+        prefix.push(CString::new(name).unwrap());
+        prefix.extend(arg);
+        prefix.push(script);
+        args.splice(0..0, prefix);
+
+        return load_binary_file(param).map_err(ExecError::Errno);
+    }
+}
@@ -5,7 +5,7 @@ use core::{
     ops::Range,
 };
 
-use alloc::vec::Vec;
+use alloc::{sync::Arc, vec::Vec};
 use elf::{
     abi::{ET_DYN, ET_EXEC, PT_GNU_PROPERTY, PT_INTERP, PT_LOAD},
     endian::AnyEndian,
@@ -16,7 +16,7 @@ use log::error;
 use system_error::SystemError;
 
 use crate::{
-    arch::{CurrentElfArch, MMArch},
+    arch::{interrupt::TrapFrame, CurrentElfArch, MMArch},
     driver::base::block::SeekFrom,
     libs::align::page_align_up,
     mm::{
@@ -30,7 +30,7 @@ use crate::{
         exec::{
             BinaryLoader, BinaryLoaderResult, ExecError, ExecLoadMode, ExecParam, ExecParamFlags,
         },
-        ProcessFlags, ProcessManager,
+        ProcessControlBlock, ProcessFlags, ProcessManager,
     },
     syscall::user_access::{clear_user, copy_to_user},
 };
@@ -41,6 +41,19 @@ use super::rwlock::RwLockWriteGuard;
 pub trait ElfArch: Clone + Copy + Debug {
     const ELF_ET_DYN_BASE: usize;
     const ELF_PAGE_SIZE: usize;
+    /// ELF文件头中的e_machine
+    const ELF_MACHINE: u16;
+    /// core dump的NT_PRSTATUS中保存的通用寄存器（elf_gregset_t）的个数
+    const ELF_NGREG: usize;
+
+    /// 按照elf_gregset_t的格式，把线程的用户态寄存器填入`regs`
+    ///
+    /// ## 参数
+    ///
+    /// - `pcb`: 寄存器所属的线程
+    /// - `frame`: 线程进入内核时保存的用户态寄存器
+    /// - `regs`: 长度为[`ElfArch::ELF_NGREG`]的数组
+    fn core_regs(pcb: &Arc<ProcessControlBlock>, frame: &TrapFrame, regs: &mut [u64]);
 }
 
 #[derive(Debug)]
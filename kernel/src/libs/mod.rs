@@ -1,4 +1,5 @@
 pub mod align;
+pub mod binfmt_script;
 pub mod casting;
 pub mod cpumask;
 pub mod elf;
@@ -2,7 +2,12 @@ use core::hint::spin_loop;
 
 use system_error::SystemError;
 
-use crate::{arch::cpu::cpu_reset, libs::mutex::Mutex, syscall::user_access::check_and_clone_cstr};
+use crate::{
+    arch::cpu::cpu_reset,
+    libs::mutex::Mutex,
+    process::{capability::capable, cred::CAPFlags},
+    syscall::user_access::check_and_clone_cstr,
+};
 
 static SYSTEM_TRANSITION_MUTEX: Mutex<()> = Mutex::new(());
 
@@ -74,6 +79,9 @@ pub(super) fn do_sys_reboot(
     cmd: u32,
     arg: usize,
 ) -> Result<(), SystemError> {
+    if !capable(CAPFlags::CAP_SYS_BOOT) {
+        return Err(SystemError::EPERM);
+    }
     if magic1 != LINUX_REBOOT_MAGIC1
         || (magic2 != LINUX_REBOOT_MAGIC2
             && magic2 != LINUX_REBOOT_MAGIC2A
@@ -6,9 +6,8 @@ use log::{debug, warn};
 /// @FilePath: /DragonOS/kernel/src/mm/allocator/buddy.rs
 /// @Description: 伙伴分配器
 use crate::arch::MMArch;
-use crate::mm::allocator::bump::BumpAllocator;
 use crate::mm::allocator::page_frame::{FrameAllocator, PageFrameCount, PageFrameUsage};
-use crate::mm::{MemoryManagementArch, PhysAddr, PhysMemoryArea, VirtAddr};
+use crate::mm::{MemoryManagementArch, PhysAddr, VirtAddr};
 
 use core::cmp::min;
 use core::fmt::Debug;
@@ -77,20 +76,19 @@ impl<A: MemoryManagementArch> BuddyAllocator<A> {
         // 定义一个变量记录buddy表的大小
         (A::PAGE_SIZE - mem::size_of::<PageList<A>>()) / mem::size_of::<PhysAddr>();
 
-    pub unsafe fn new(mut bump_allocator: BumpAllocator<A>) -> Option<Self> {
-        let initial_free_pages = bump_allocator.usage().free();
-        let total_memory = bump_allocator.usage().total();
-        debug!("Free pages before init buddy: {:?}", initial_free_pages);
-        debug!("Buddy entries: {}", Self::BUDDY_ENTRIES);
-
+    /// 创建一个不含任何空闲内存的伙伴分配器
+    ///
+    /// ## 参数
+    ///
+    /// - `allocator` - 用于分配各阶空闲链表头部页面的分配器
+    pub unsafe fn empty(allocator: &mut dyn FrameAllocator) -> Option<Self> {
         let mut free_area: [PhysAddr; MAX_ORDER - MIN_ORDER] =
             [PhysAddr::new(0); MAX_ORDER - MIN_ORDER];
 
-        // Buddy初始占用的空间从bump分配
         for f in free_area.iter_mut() {
-            let curr_page = bump_allocator.allocate_one();
+            let curr_page = allocator.allocate_one();
             // 保存每个阶的空闲链表的头部地址
-            *f = curr_page.unwrap();
+            *f = curr_page?;
             // 清空当前页
             core::ptr::write_bytes(MMArch::phys_2_virt(*f)?.data() as *mut u8, 0, A::PAGE_SIZE);
 
@@ -98,89 +96,110 @@ impl<A: MemoryManagementArch> BuddyAllocator<A> {
             Self::write_page(*f, page_list);
         }
 
-        let mut allocator = Self {
+        Some(Self {
             free_area,
             total: PageFrameCount::new(0),
             phantom: PhantomData,
-        };
+        })
+    }
 
-        let mut total_pages_to_buddy = PageFrameCount::new(0);
-        let mut res_areas = [PhysMemoryArea::default(); 128];
-        let mut offset_in_remain_area = bump_allocator
-            .remain_areas(&mut res_areas)
-            .expect("BuddyAllocator: failed to get remain areas from bump allocator");
+    /// 把物理地址范围`[start, end)`内的页面加入伙伴分配器，并计入总页数
+    ///
+    /// ## 参数
+    ///
+    /// - `start` - 起始物理地址，必须按页对齐
+    /// - `end` - 结束物理地址，必须按页对齐
+    pub unsafe fn add_range(&mut self, start: PhysAddr, end: PhysAddr) {
+        if end <= start {
+            return;
+        }
+        let mut paddr = start.data();
+        let mut remain_pages = PageFrameCount::from_bytes(end.data() - paddr).unwrap();
 
-        let remain_areas = &res_areas[0..];
+        if remain_pages.data() == 0 {
+            return;
+        }
+        debug!("buddy add range: paddr: {paddr:#x}, remain_pages: {remain_pages:?}");
 
-        for area in remain_areas {
-            let mut paddr = (area.area_base_aligned() + offset_in_remain_area).data();
-            let mut remain_pages =
-                PageFrameCount::from_bytes(area.area_end_aligned().data() - paddr).unwrap();
+        self.total += remain_pages;
 
-            if remain_pages.data() == 0 {
-                continue;
+        // 先从低阶开始，尽可能地填满空闲链表
+        for i in MIN_ORDER..MAX_ORDER {
+            // debug!("i {i}, remain pages={}", remain_pages.data());
+            if remain_pages.data() < (1 << (i - MIN_ORDER)) {
+                break;
             }
-            debug!("area: {area:?}, paddr: {paddr:#x}, remain_pages: {remain_pages:?}");
 
-            total_pages_to_buddy += remain_pages;
+            assert!(paddr & ((1 << i) - 1) == 0);
 
-            if offset_in_remain_area != 0 {
-                offset_in_remain_area = 0;
-            }
+            if likely(i != MAX_ORDER - 1) {
+                // 要填写entry
+                if paddr & (1 << i) != 0 {
+                    self.buddy_free(PhysAddr::new(paddr), i as u8);
 
-            // 先从低阶开始，尽可能地填满空闲链表
-            for i in MIN_ORDER..MAX_ORDER {
-                // debug!("i {i}, remain pages={}", remain_pages.data());
-                if remain_pages.data() < (1 << (i - MIN_ORDER)) {
-                    break;
-                }
+                    paddr += 1 << i;
+                    remain_pages -= 1 << (i - MIN_ORDER);
+                };
+            } else {
+                // 往最大的阶数的链表中添加entry（注意要考虑到最大阶数的链表可能有多页）
+                // 断言剩余页面数量是MAX_ORDER-1阶的整数倍
 
-                assert!(paddr & ((1 << i) - 1) == 0);
+                let mut entries = (remain_pages.data() * A::PAGE_SIZE) >> i;
+                while entries > 0 {
+                    self.buddy_free(PhysAddr::new(paddr), i as u8);
+                    paddr += 1 << i;
+                    remain_pages -= 1 << (i - MIN_ORDER);
 
-                if likely(i != MAX_ORDER - 1) {
-                    // 要填写entry
-                    if paddr & (1 << i) != 0 {
-                        allocator.buddy_free(PhysAddr::new(paddr), i as u8);
+                    entries -= 1;
+                }
+            }
+        }
+        // 然后从高往低，把剩余的页面加入链表
+        let mut remain_bytes = remain_pages.data() * A::PAGE_SIZE;
 
-                        paddr += 1 << i;
-                        remain_pages -= 1 << (i - MIN_ORDER);
-                    };
-                } else {
-                    // 往最大的阶数的链表中添加entry（注意要考虑到最大阶数的链表可能有多页）
-                    // 断言剩余页面数量是MAX_ORDER-1阶的整数倍
+        assert!(remain_bytes < (1 << MAX_ORDER) - 1);
 
-                    let mut entries = (remain_pages.data() * A::PAGE_SIZE) >> i;
-                    while entries > 0 {
-                        allocator.buddy_free(PhysAddr::new(paddr), i as u8);
-                        paddr += 1 << i;
-                        remain_pages -= 1 << (i - MIN_ORDER);
+        for i in (MIN_ORDER..MAX_ORDER).rev() {
+            if remain_bytes >= (1 << i) {
+                assert!(paddr & ((1 << i) - 1) == 0);
+                self.buddy_free(PhysAddr::new(paddr), i as u8);
 
-                        entries -= 1;
-                    }
-                }
+                paddr += 1 << i;
+                remain_bytes -= 1 << i;
             }
-            // 然后从高往低，把剩余的页面加入链表
-            let mut remain_bytes = remain_pages.data() * A::PAGE_SIZE;
+        }
 
-            assert!(remain_bytes < (1 << MAX_ORDER) - 1);
+        assert!(remain_bytes == 0);
+    }
 
-            for i in (MIN_ORDER..MAX_ORDER).rev() {
-                if remain_bytes >= (1 << i) {
-                    assert!(paddr & ((1 << i) - 1) == 0);
-                    allocator.buddy_free(PhysAddr::new(paddr), i as u8);
+    /// 调整总页数，用于在伙伴分配器之间转移已经分配出去的页面的统计
+    pub fn adjust_total(&mut self, total: PageFrameCount) {
+        self.total = total;
+    }
 
-                    paddr += 1 << i;
-                    remain_bytes -= 1 << i;
+    /// 取出所有的空闲块（包括空闲链表的头部页面），销毁伙伴分配器
+    ///
+    /// ## 参数
+    ///
+    /// - `f` - 对每个空闲块调用，参数为块的起始地址和页数
+    pub unsafe fn drain(mut self, mut f: impl FnMut(PhysAddr, PageFrameCount)) {
+        // 取出空闲块的过程中，空闲链表页会被释放回低阶链表，因此需要反复扫描直到所有链表为空
+        loop {
+            let mut found = false;
+            for order in MIN_ORDER..MAX_ORDER {
+                while let Some(addr) = self.pop_exact(order as u8) {
+                    f(addr, PageFrameCount::new(1 << (order - MIN_ORDER)));
+                    found = true;
                 }
             }
-
-            assert!(remain_bytes == 0);
+            if !found {
+                break;
+            }
+        }
+        // 每个阶的空闲链表只剩下头部页面
+        for page_list_addr in self.free_area {
+            f(page_list_addr, PageFrameCount::ONE);
         }
-
-        debug!("Total pages to buddy: {:?}", total_pages_to_buddy);
-        allocator.total = total_memory;
-
-        Some(allocator)
     }
     /// 获取第j个entry的虚拟地址，
     /// j从0开始计数
@@ -218,92 +237,101 @@ impl<A: MemoryManagementArch> BuddyAllocator<A> {
         order as usize - MIN_ORDER
     }
 
-    /// 从空闲链表的开头，取出1个指定阶数的伙伴块，如果没有，则返回None
+    /// 从指定阶数的空闲链表的开头取出1个伙伴块，不会分裂更大的伙伴块
     ///
     /// ## 参数
     ///
-    /// - `order` - 伙伴块的阶数
-    fn pop_front(&mut self, order: u8) -> Option<PhysAddr> {
-        let mut alloc_in_specific_order = |spec_order: u8| {
-            // 先尝试在order阶的“空闲链表”的开头位置分配一个伙伴块
-            let mut page_list_addr = self.free_area[Self::order2index(spec_order)];
-            let mut page_list: PageList<A> = Self::read_page(page_list_addr);
-
-            // 循环删除头部的空闲链表页
-            while page_list.entry_num == 0 {
-                let next_page_list_addr = page_list.next_page;
-                // 找完了，都是空的
-                if next_page_list_addr.is_null() {
-                    return None;
-                }
-
-                if !next_page_list_addr.is_null() {
-                    // 此时page_list已经没有空闲伙伴块了，又因为非唯一页，需要删除该page_list
-                    self.free_area[Self::order2index(spec_order)] = next_page_list_addr;
-                    // debug!("FREE: page_list_addr={:b}", page_list_addr.data());
-                    unsafe {
-                        self.buddy_free(page_list_addr, MMArch::PAGE_SHIFT as u8);
-                    }
-                }
-                // 由于buddy_free可能导致首部的链表页发生变化，因此需要重新读取
-                let next_page_list_addr = self.free_area[Self::order2index(spec_order)];
-                assert!(!next_page_list_addr.is_null());
-                page_list = Self::read_page(next_page_list_addr);
-                page_list_addr = next_page_list_addr;
+    /// - `spec_order` - 伙伴块的阶数
+    fn pop_exact(&mut self, spec_order: u8) -> Option<PhysAddr> {
+        // 先尝试在order阶的“空闲链表”的开头位置分配一个伙伴块
+        let mut page_list_addr = self.free_area[Self::order2index(spec_order)];
+        let mut page_list: PageList<A> = Self::read_page(page_list_addr);
+
+        // 循环删除头部的空闲链表页
+        while page_list.entry_num == 0 {
+            let next_page_list_addr = page_list.next_page;
+            // 找完了，都是空的
+            if next_page_list_addr.is_null() {
+                return None;
             }
 
-            // 有空闲页面，直接分配
-            if page_list.entry_num > 0 {
-                let entry: PhysAddr = unsafe {
-                    A::read(Self::entry_virt_addr(
-                        page_list_addr,
-                        page_list.entry_num - 1,
-                    ))
-                };
-                // 清除该entry
+            if !next_page_list_addr.is_null() {
+                // 此时page_list已经没有空闲伙伴块了，又因为非唯一页，需要删除该page_list
+                self.free_area[Self::order2index(spec_order)] = next_page_list_addr;
+                // debug!("FREE: page_list_addr={:b}", page_list_addr.data());
                 unsafe {
-                    A::write(
-                        Self::entry_virt_addr(page_list_addr, page_list.entry_num - 1),
-                        PhysAddr::new(0),
-                    )
-                };
-                if entry.is_null() {
-                    panic!(
-                        "entry is null, entry={:?}, order={}, entry_num = {}",
-                        entry,
-                        spec_order,
-                        page_list.entry_num - 1
-                    );
+                    self.buddy_free(page_list_addr, MMArch::PAGE_SHIFT as u8);
                 }
-                // debug!("entry={entry:?}");
-
-                // 更新page_list的entry_num
-                page_list.entry_num -= 1;
-                let tmp_current_entry_num = page_list.entry_num;
-                if page_list.entry_num == 0 {
-                    if !page_list.next_page.is_null() {
-                        // 此时page_list已经没有空闲伙伴块了，又因为非唯一页，需要删除该page_list
-                        self.free_area[Self::order2index(spec_order)] = page_list.next_page;
-                        let _ = page_list;
-                        unsafe { self.buddy_free(page_list_addr, MMArch::PAGE_SHIFT as u8) };
-                    } else {
-                        Self::write_page(page_list_addr, page_list);
-                    }
+            }
+            // 由于buddy_free可能导致首部的链表页发生变化，因此需要重新读取
+            let next_page_list_addr = self.free_area[Self::order2index(spec_order)];
+            assert!(!next_page_list_addr.is_null());
+            page_list = Self::read_page(next_page_list_addr);
+            page_list_addr = next_page_list_addr;
+        }
+
+        // 有空闲页面，直接分配
+        if page_list.entry_num > 0 {
+            let entry: PhysAddr = unsafe {
+                A::read(Self::entry_virt_addr(
+                    page_list_addr,
+                    page_list.entry_num - 1,
+                ))
+            };
+            // 清除该entry
+            unsafe {
+                A::write(
+                    Self::entry_virt_addr(page_list_addr, page_list.entry_num - 1),
+                    PhysAddr::new(0),
+                )
+            };
+            if entry.is_null() {
+                panic!(
+                    "entry is null, entry={:?}, order={}, entry_num = {}",
+                    entry,
+                    spec_order,
+                    page_list.entry_num - 1
+                );
+            }
+            // debug!("entry={entry:?}");
+
+            // 更新page_list的entry_num
+            page_list.entry_num -= 1;
+            let tmp_current_entry_num = page_list.entry_num;
+            if page_list.entry_num == 0 {
+                if !page_list.next_page.is_null() {
+                    // 此时page_list已经没有空闲伙伴块了，又因为非唯一页，需要删除该page_list
+                    self.free_area[Self::order2index(spec_order)] = page_list.next_page;
+                    let _ = page_list;
+                    unsafe { self.buddy_free(page_list_addr, MMArch::PAGE_SHIFT as u8) };
                 } else {
-                    // 若entry_num不为0，说明该page_list还有空闲伙伴块，需要更新该page_list
-                    // 把更新后的page_list写回
-                    Self::write_page(page_list_addr, page_list.clone());
+                    Self::write_page(page_list_addr, page_list);
                 }
+            } else {
+                // 若entry_num不为0，说明该page_list还有空闲伙伴块，需要更新该page_list
+                // 把更新后的page_list写回
+                Self::write_page(page_list_addr, page_list.clone());
+            }
 
-                // 检测entry 是否对齐
-                if !entry.check_aligned(1 << spec_order) {
-                    panic!("entry={:?} is not aligned, spec_order={spec_order}, page_list.entry_num={}", entry, tmp_current_entry_num);
-                }
-                return Some(entry);
+            // 检测entry 是否对齐
+            if !entry.check_aligned(1 << spec_order) {
+                panic!(
+                    "entry={:?} is not aligned, spec_order={spec_order}, page_list.entry_num={}",
+                    entry, tmp_current_entry_num
+                );
             }
-            return None;
-        };
-        let result: Option<PhysAddr> = alloc_in_specific_order(order);
+            return Some(entry);
+        }
+        return None;
+    }
+
+    /// 从空闲链表的开头，取出1个指定阶数的伙伴块，如果没有，则返回None
+    ///
+    /// ## 参数
+    ///
+    /// - `order` - 伙伴块的阶数
+    fn pop_front(&mut self, order: u8) -> Option<PhysAddr> {
+        let result: Option<PhysAddr> = self.pop_exact(order);
         // debug!("result={:?}", result);
         if result.is_some() {
             return result;
@@ -313,7 +341,7 @@ impl<A: MemoryManagementArch> BuddyAllocator<A> {
         let mut current_order = (order + 1) as usize;
         let mut x: Option<PhysAddr> = None;
         while current_order < MAX_ORDER {
-            x = alloc_in_specific_order(current_order as u8);
+            x = self.pop_exact(current_order as u8);
             // debug!("current_order={:?}", current_order);
             if x.is_some() {
                 break;
@@ -4,7 +4,10 @@ use crate::{
     arch::mm::LockedFrameAllocator,
     debug::klog::mm::mm_debug_log,
     libs::align::page_align_up,
-    mm::{MMArch, MemoryManagementArch, VirtAddr},
+    mm::{
+        gfp::GfpFlags, mmzone::alloc_pages_node, numa::numa_node_id, oom_kill::out_of_memory,
+        MMArch, MemoryManagementArch, VirtAddr,
+    },
 };
 
 use core::{
@@ -33,9 +36,14 @@ impl KernelAllocator {
         // 计算需要申请的页数，向上取整
         let count = (page_align_up(layout.size()) / MMArch::PAGE_SIZE).next_power_of_two();
         let page_frame_count = PageFrameCount::new(count);
-        let (phy_addr, allocated_frame_count) = LockedFrameAllocator
-            .allocate(page_frame_count)
-            .ok_or(AllocError)?;
+        // 内核堆的内存不受进程内存策略的影响，总是优先在本地节点分配
+        let alloc = || alloc_pages_node(GfpFlags::GFP_KERNEL, page_frame_count, numa_node_id());
+        let (phy_addr, allocated_frame_count) = match alloc() {
+            Some(r) => r,
+            // 物理内存耗尽，杀死一个进程后重试一次
+            None if out_of_memory() => alloc().ok_or(AllocError)?,
+            None => return Err(AllocError),
+        };
 
         let virt_addr = unsafe { MMArch::phys_2_virt(phy_addr).ok_or(AllocError)? };
         if unlikely(virt_addr.is_null()) {
@@ -0,0 +1,271 @@
+//! 每CPU页帧缓存
+//!
+//! 单个页帧的分配与释放非常频繁，如果每次都获取伙伴分配器的锁，在多核上竞争会非常严重。
+//! 因此每个内存区域为每个CPU维护一个页帧缓存：分配时优先从本CPU的缓存中取出页帧，缓存为空时一次性从伙伴分配器
+//! 批量补充；释放时把页帧放回本CPU的缓存，缓存超过上限时批量归还给伙伴分配器。
+//!
+//! 缓存是一个双端队列：刚释放的页帧很可能还在CPU缓存中，放在热端，分配时优先使用；从伙伴分配器补充的
+//! 页帧放在冷端，归还给伙伴分配器时也从冷端取出。
+
+use alloc::vec::Vec;
+
+use crate::{
+    arch::MMArch,
+    libs::{lazy_init::Lazy, spinlock::SpinLock},
+    mm::{
+        percpu::{PerCpu, PerCpuVar},
+        PhysAddr,
+    },
+    smp::cpu::{smp_cpu_manager, ProcessorId},
+};
+
+use super::{
+    buddy::BuddyAllocator,
+    page_frame::{FrameAllocator, PageFrameCount},
+};
+
+/// 被锁保护的伙伴分配器
+pub type LockedBuddyAllocator = SpinLock<Option<BuddyAllocator<MMArch>>>;
+
+/// 每次从伙伴分配器补充、或者归还给伙伴分配器的页帧数量
+pub const PCP_BATCH: usize = 31;
+/// 每个CPU缓存的页帧数量上限
+pub const PCP_HIGH: usize = PCP_BATCH * 6;
+
+/// 一个CPU的页帧缓存
+#[derive(Debug)]
+struct PerCpuPages {
+    /// 环形队列，`head`一端为热端
+    pages: [PhysAddr; PCP_HIGH],
+    head: usize,
+    count: usize,
+    stats: PcpStats,
+}
+
+/// 每CPU页帧缓存的统计信息
+#[derive(Debug, Default, Clone, Copy)]
+pub struct PcpStats {
+    /// 直接从缓存中分配成功的次数
+    pub alloc_hit: usize,
+    /// 缓存为空、需要从伙伴分配器补充的次数
+    pub refill: usize,
+    /// 释放到缓存中的页帧数量
+    pub free: usize,
+    /// 归还给伙伴分配器的页帧数量
+    pub drained: usize,
+}
+
+impl PerCpuPages {
+    const fn new() -> Self {
+        Self {
+            pages: [PhysAddr::new(0); PCP_HIGH],
+            head: 0,
+            count: 0,
+            stats: PcpStats {
+                alloc_hit: 0,
+                refill: 0,
+                free: 0,
+                drained: 0,
+            },
+        }
+    }
+
+    fn push_hot(&mut self, paddr: PhysAddr) {
+        debug_assert!(self.count < PCP_HIGH);
+        self.head = (self.head + PCP_HIGH - 1) % PCP_HIGH;
+        self.pages[self.head] = paddr;
+        self.count += 1;
+    }
+
+    fn push_cold(&mut self, paddr: PhysAddr) {
+        debug_assert!(self.count < PCP_HIGH);
+        self.pages[(self.head + self.count) % PCP_HIGH] = paddr;
+        self.count += 1;
+    }
+
+    fn pop_hot(&mut self) -> Option<PhysAddr> {
+        if self.count == 0 {
+            return None;
+        }
+        let paddr = self.pages[self.head];
+        self.head = (self.head + 1) % PCP_HIGH;
+        self.count -= 1;
+        Some(paddr)
+    }
+
+    fn pop_cold(&mut self) -> Option<PhysAddr> {
+        if self.count == 0 {
+            return None;
+        }
+        self.count -= 1;
+        Some(self.pages[(self.head + self.count) % PCP_HIGH])
+    }
+
+    /// 从伙伴分配器批量补充页帧，返回补充的数量
+    unsafe fn refill(&mut self, buddy: &LockedBuddyAllocator) -> usize {
+        self.stats.refill += 1;
+        let mut guard = buddy.lock_irqsave();
+        let allocator = match guard.as_mut() {
+            Some(allocator) => allocator,
+            None => return 0,
+        };
+        let mut n = 0;
+        while n < PCP_BATCH {
+            match allocator.allocate_one() {
+                Some(paddr) => self.push_cold(paddr),
+                None => break,
+            }
+            n += 1;
+        }
+        n
+    }
+
+    /// 从冷端取出最多`count`个页帧归还给伙伴分配器
+    unsafe fn drain(&mut self, buddy: &LockedBuddyAllocator, count: usize) {
+        let mut guard = buddy.lock_irqsave();
+        let allocator = match guard.as_mut() {
+            Some(allocator) => allocator,
+            None => return,
+        };
+        for _ in 0..count {
+            match self.pop_cold() {
+                Some(paddr) => allocator.free_one(paddr),
+                None => break,
+            }
+            self.stats.drained += 1;
+        }
+    }
+}
+
+/// 一个内存区域的每CPU页帧缓存
+#[derive(Debug)]
+pub struct PerCpuPageCache {
+    lists: Lazy<PerCpuVar<SpinLock<PerCpuPages>>>,
+}
+
+impl PerCpuPageCache {
+    pub const fn new() -> Self {
+        Self {
+            lists: PerCpuVar::define_lazy(),
+        }
+    }
+
+    /// 初始化每CPU页帧缓存，在此之前页帧直接从伙伴分配器中分配
+    pub fn init(&self) {
+        if self.lists.initialized() {
+            return;
+        }
+        let mut data = Vec::with_capacity(PerCpu::MAX_CPU_NUM as usize);
+        for _ in 0..PerCpu::MAX_CPU_NUM {
+            data.push(SpinLock::new(PerCpuPages::new()));
+        }
+        self.lists.init(PerCpuVar::new(data).unwrap());
+    }
+
+    /// 分配页帧，单个页帧优先从本CPU的缓存中分配
+    ///
+    /// ## 参数
+    ///
+    /// - `buddy`: 缓存所属的伙伴分配器
+    /// - `count`: 页帧数量，必须是2的幂
+    pub unsafe fn allocate(
+        &self,
+        buddy: &LockedBuddyAllocator,
+        count: PageFrameCount,
+    ) -> Option<(PhysAddr, PageFrameCount)> {
+        if let (Some(lists), 1) = (self.lists.try_get(), count.data()) {
+            let mut guard = lists.get().lock_irqsave();
+            if let Some(paddr) = guard.pop_hot() {
+                guard.stats.alloc_hit += 1;
+                return Some((paddr, count));
+            }
+            if guard.refill(buddy) > 0 {
+                return guard.pop_hot().map(|paddr| (paddr, count));
+            }
+        }
+
+        return buddy.lock_irqsave().as_mut()?.allocate(count);
+    }
+
+    /// 释放页帧，单个页帧放回本CPU的缓存
+    ///
+    /// ## 参数
+    ///
+    /// - `buddy`: 缓存所属的伙伴分配器
+    /// - `address`: 页帧起始地址
+    /// - `count`: 页帧数量，必须是2的幂
+    pub unsafe fn free(
+        &self,
+        buddy: &LockedBuddyAllocator,
+        address: PhysAddr,
+        count: PageFrameCount,
+    ) {
+        if let (Some(lists), 1) = (self.lists.try_get(), count.data()) {
+            let mut guard = lists.get().lock_irqsave();
+            if guard.count == PCP_HIGH {
+                guard.drain(buddy, PCP_BATCH);
+            }
+            guard.push_hot(address);
+            guard.stats.free += 1;
+            return;
+        }
+
+        if let Some(allocator) = buddy.lock_irqsave().as_mut() {
+            allocator.free(address, count);
+        }
+    }
+
+    /// 把指定CPU缓存中的页帧全部归还给伙伴分配器
+    ///
+    /// CPU下线时需要调用此函数，否则该CPU缓存中的页帧将无法再被使用
+    pub fn drain(&self, buddy: &LockedBuddyAllocator, cpu: ProcessorId) {
+        if let Some(lists) = self.lists.try_get() {
+            let mut guard = unsafe { lists.force_get(cpu) }.lock_irqsave();
+            unsafe { guard.drain(buddy, PCP_HIGH) };
+        }
+    }
+
+    /// 把所有CPU缓存中的页帧归还给伙伴分配器，在内存不足时调用
+    pub fn drain_all(&self, buddy: &LockedBuddyAllocator) {
+        for cpu in 0..PerCpu::MAX_CPU_NUM {
+            self.drain(buddy, ProcessorId::new(cpu));
+        }
+    }
+
+    /// 所有CPU缓存中的页帧数量之和
+    ///
+    /// 这些页帧在伙伴分配器看来已经被分配，但实际上仍然是空闲的
+    pub fn nr_cached(&self) -> usize {
+        let lists = match self.lists.try_get() {
+            Some(lists) => lists,
+            None => return 0,
+        };
+        (0..PerCpu::MAX_CPU_NUM)
+            .map(|cpu| {
+                unsafe { lists.force_get(ProcessorId::new(cpu)) }
+                    .lock_irqsave()
+                    .count
+            })
+            .sum()
+    }
+
+    /// 获取系统中各个CPU的页帧缓存统计信息
+    ///
+    /// ## 返回值
+    ///
+    /// 每个CPU的`(CPU编号, 缓存中的页帧数量, 统计信息)`
+    pub fn stats(&self) -> Vec<(ProcessorId, usize, PcpStats)> {
+        let lists = match self.lists.try_get() {
+            Some(lists) => lists,
+            None => return Vec::new(),
+        };
+        smp_cpu_manager()
+            .present_cpus()
+            .iter_cpu()
+            .map(|cpu| {
+                let guard = unsafe { lists.force_get(cpu) }.lock_irqsave();
+                (cpu, guard.count, guard.stats)
+            })
+            .collect()
+    }
+}
@@ -9,9 +9,16 @@ use alloc::sync::Arc;
 
 use crate::{
     arch::{mm::PageMapper, MMArch},
+    filesystem::hugetlbfs::hugetlbfs_inode,
     libs::align::align_down,
     mm::{
-        page::{page_manager_lock_irqsave, EntryFlags},
+        huge_memory::{
+            alloc_thp, free_thp, huge_pmd_entry, split_huge_pmd, HPAGE_PMD_LEVEL, HPAGE_PMD_SIZE,
+        },
+        hugetlb::vma_hstate,
+        mempolicy::FaultPolicyGuard,
+        page::{page_manager_lock_irqsave, EntryFlags, PageEntry, PageFlush},
+        swap::{lru_add_anon, swap_in_page, SwapEntry},
         ucontext::LockedVMA,
         VirtAddr, VmFaultReason, VmFlags,
     },
@@ -19,8 +26,9 @@ use crate::{
 };
 
 use crate::mm::MemoryManagementArch;
+use system_error::SystemError;
 
-use super::page::{Page, PageFlags};
+use super::page::{page_reclaimer_lock_irqsave, Page, PageFlags, PageType};
 
 bitflags! {
     pub struct FaultFlags: u64{
@@ -137,12 +145,142 @@ impl PageFaultHandler {
 
         let guard = vma.lock_irqsave();
         let vm_flags = *guard.vm_flags();
+        let vm_policy = guard.vm_policy();
         drop(guard);
-        if unlikely(vm_flags.contains(VmFlags::VM_HUGETLB)) {
-            //TODO: 添加handle_hugetlb_fault处理大页缺页异常
+        // 缺页处理期间分配的页帧使用VMA的内存策略
+        let _policy_guard = vm_policy.map(|p| FaultPolicyGuard::new(current_pcb.clone(), p));
+        let ret = if unlikely(vm_flags.contains(VmFlags::VM_HUGETLB)) {
+            Self::handle_hugetlb_fault(&mut pfm)
         } else {
-            Self::handle_normal_fault(&mut pfm);
+            Self::handle_normal_fault(&mut pfm)
+        };
+
+        if unlikely(ret.intersects(VmFaultReason::VM_FAULT_ERROR)) {
+            return ret;
+        }
+        if ret.contains(VmFaultReason::VM_FAULT_MAJOR) {
+            current_pcb.task_rusage().inc_majflt();
+        } else {
+            current_pcb.task_rusage().inc_minflt();
+        }
+        VmFaultReason::VM_FAULT_COMPLETED
+    }
+
+    /// 处理大页映射的缺页异常
+    ///
+    /// 共享映射直接映射hugetlbfs文件中的大页；私有映射在读缺页时只读地映射文件中已有的大页，
+    /// 写缺页时从大页池中分配新的大页并复制数据
+    /// ## 参数
+    ///
+    /// - `pfm`: 缺页异常信息
+    ///
+    /// ## 返回值
+    /// - VmFaultReason: 页面错误处理信息标志
+    pub unsafe fn handle_hugetlb_fault(pfm: &mut PageFaultMessage) -> VmFaultReason {
+        let vma = pfm.vma();
+        let guard = vma.lock_irqsave();
+        let hstate = match vma_hstate(&guard) {
+            Some(hstate) => hstate,
+            None => return VmFaultReason::VM_FAULT_SIGBUS,
+        };
+        let inode = match guard.vm_file().and_then(|file| hugetlbfs_inode(&file)) {
+            Some(inode) => inode,
+            None => return VmFaultReason::VM_FAULT_SIGBUS,
+        };
+        let shared = guard.vm_flags().contains(VmFlags::VM_SHARED);
+        let write = pfm.flags().contains(FaultFlags::FAULT_FLAG_WRITE);
+        let haddr = VirtAddr::new(align_down(pfm.address().data(), hstate.size()));
+        let index = (((haddr - guard.region().start()) >> MMArch::PAGE_SHIFT)
+            + guard.file_page_offset().unwrap_or(0))
+            / hstate.nr_base_pages();
+        let vma_flags = guard.flags();
+        drop(guard);
+
+        let level = hstate.level();
+        let mapper = &mut pfm.mapper;
+        if let Some(table) = mapper.get_table(haddr, level) {
+            let i = table.index_of(haddr).unwrap();
+            let mut entry = table.entry(i).unwrap();
+            if entry.present() {
+                if !write || entry.write() {
+                    return VmFaultReason::VM_FAULT_COMPLETED;
+                }
+
+                let old = entry.address().unwrap();
+                if shared || hstate.page_count(old) == 1 {
+                    // 没有其他映射共享这个大页，直接授予写权限
+                    entry.set_flags(entry.flags().set_write(true).set_dirty(true));
+                    table.set_entry(i, entry);
+                    PageFlush::<MMArch>::new(haddr).flush();
+                    return VmFaultReason::VM_FAULT_COMPLETED;
+                }
+
+                // 私有映射的写时复制
+                let new = match hstate.alloc_page() {
+                    Some(paddr) => paddr,
+                    None => return VmFaultReason::VM_FAULT_SIGBUS,
+                };
+                MMArch::phys_2_virt(new)
+                    .unwrap()
+                    .as_ptr::<u8>()
+                    .copy_from_nonoverlapping(
+                        MMArch::phys_2_virt(old).unwrap().as_ptr::<u8>(),
+                        hstate.size(),
+                    );
+                let new_flags = entry.flags().set_write(true).set_dirty(true);
+                table.set_entry(i, PageEntry::new(new, new_flags));
+                PageFlush::<MMArch>::new(haddr).flush();
+                hstate.put_page(old);
+                return VmFaultReason::VM_FAULT_COMPLETED;
+            }
+        }
+
+        if index >= inode.size_in_pages() {
+            return VmFaultReason::VM_FAULT_SIGBUS;
+        }
+
+        let (paddr, flags) = if shared {
+            match inode.get_or_alloc_page(index) {
+                Ok(paddr) => (paddr, vma_flags),
+                Err(_) => return VmFaultReason::VM_FAULT_SIGBUS,
+            }
+        } else {
+            match (inode.get_page(index), write) {
+                // 私有映射的读缺页与文件共享大页，写缺页时再复制
+                (Some(paddr), false) => (paddr, vma_flags.set_write(false)),
+                (file_page, _) => {
+                    let paddr = match hstate.alloc_page() {
+                        Some(paddr) => paddr,
+                        None => {
+                            if let Some(file_page) = file_page {
+                                hstate.put_page(file_page);
+                            }
+                            return VmFaultReason::VM_FAULT_SIGBUS;
+                        }
+                    };
+                    if let Some(file_page) = file_page {
+                        MMArch::phys_2_virt(paddr)
+                            .unwrap()
+                            .as_ptr::<u8>()
+                            .copy_from_nonoverlapping(
+                                MMArch::phys_2_virt(file_page).unwrap().as_ptr::<u8>(),
+                                hstate.size(),
+                            );
+                        hstate.put_page(file_page);
+                    }
+                    (paddr, vma_flags)
+                }
+            }
+        };
+
+        match mapper.map_huge_phys(haddr, paddr, level, flags) {
+            Some(flush) => flush.flush(),
+            None => {
+                hstate.put_page(paddr);
+                return VmFaultReason::VM_FAULT_OOM;
+            }
         }
+        vma.lock_irqsave().set_mapped(true);
 
         VmFaultReason::VM_FAULT_COMPLETED
     }
@@ -158,30 +296,74 @@ impl PageFaultHandler {
     pub unsafe fn handle_normal_fault(pfm: &mut PageFaultMessage) -> VmFaultReason {
         let address = pfm.address_aligned_down();
         let vma = pfm.vma.clone();
-        let mapper = &mut pfm.mapper;
-        if mapper.get_entry(address, 3).is_none() {
-            mapper
+        if pfm.mapper.get_entry(address, 3).is_none() {
+            pfm.mapper
                 .allocate_table(address, 2)
                 .expect("failed to allocate PUD table");
         }
-        let page_flags = vma.lock_irqsave().flags();
 
         for level in 2..=3 {
             let level = MMArch::PAGE_LEVELS - level;
-            if mapper.get_entry(address, level).is_none() {
-                if vma.is_hugepage() {
-                    if vma.is_anonymous() {
-                        mapper.map_huge_page(address, page_flags);
-                    }
-                } else if mapper.allocate_table(address, level - 1).is_none() {
-                    return VmFaultReason::VM_FAULT_OOM;
+            if pfm.mapper.get_entry(address, level).is_some() {
+                continue;
+            }
+            // 满足条件的匿名映射优先使用透明大页，无法分配大页时回退到普通页
+            if level == HPAGE_PMD_LEVEL && vma.is_hugepage(address) {
+                let ret = Self::do_huge_pmd_anonymous_page(pfm);
+                if !ret.contains(VmFaultReason::VM_FAULT_FALLBACK) {
+                    return ret;
                 }
             }
+            if pfm.mapper.allocate_table(address, level - 1).is_none() {
+                return VmFaultReason::VM_FAULT_OOM;
+            }
+        }
+
+        let haddr = VirtAddr::new(align_down(address.data(), HPAGE_PMD_SIZE));
+        if let Some(entry) = huge_pmd_entry(pfm.mapper, haddr) {
+            if !pfm.flags().contains(FaultFlags::FAULT_FLAG_WRITE) || entry.write() {
+                return VmFaultReason::VM_FAULT_COMPLETED;
+            }
+            // 只读的透明大页发生写缺页时，拆分成普通页后按普通页处理
+            if split_huge_pmd(&vma, pfm.mapper, haddr).is_err() {
+                return VmFaultReason::VM_FAULT_OOM;
+            }
         }
 
         Self::handle_pte_fault(pfm)
     }
 
+    /// 为匿名映射分配并映射一个清零的透明大页
+    /// ## 参数
+    ///
+    /// - `pfm`: 缺页异常信息
+    ///
+    /// ## 返回值
+    /// - VmFaultReason: 页面错误处理信息标志，无法分配大页时返回VM_FAULT_FALLBACK
+    pub unsafe fn do_huge_pmd_anonymous_page(pfm: &mut PageFaultMessage) -> VmFaultReason {
+        let haddr = VirtAddr::new(align_down(pfm.address().data(), HPAGE_PMD_SIZE));
+        let vma = pfm.vma();
+        let flags = vma.lock_irqsave().flags();
+
+        let paddr = match alloc_thp() {
+            Some(paddr) => paddr,
+            None => return VmFaultReason::VM_FAULT_FALLBACK,
+        };
+        match pfm
+            .mapper
+            .map_huge_phys(haddr, paddr, HPAGE_PMD_LEVEL, flags)
+        {
+            Some(flush) => flush.flush(),
+            None => {
+                free_thp(paddr);
+                return VmFaultReason::VM_FAULT_FALLBACK;
+            }
+        }
+        vma.lock_irqsave().set_mapped(true);
+
+        VmFaultReason::VM_FAULT_COMPLETED
+    }
+
     /// 处理页表项异常
     /// ## 参数
     ///
@@ -199,8 +381,9 @@ impl PageFaultHandler {
 
         // pte存在
         if let Some(mut entry) = mapper.get_entry(address, 0) {
-            if !entry.present() {
-                ret = Self::do_swap_page(pfm);
+            if !entry.present() && !entry.protnone() {
+                // 页面已被换出
+                return Self::do_swap_page(pfm);
             }
 
             if entry.protnone() && vma.is_accessible() {
@@ -214,6 +397,17 @@ impl PageFaultHandler {
                     entry.set_flags(EntryFlags::from_data(MMArch::ENTRY_FLAG_DIRTY));
                 }
             }
+
+            // 标记页面最近被访问过，供页面回收时参考
+            let mapper = &pfm.mapper;
+            if let Some(mut entry) = mapper.get_entry(address, 0) {
+                if entry.present() && !entry.flags().has_flag(MMArch::ENTRY_FLAG_ACCESSED) {
+                    let table = mapper.get_table(address, 0).unwrap();
+                    let i = table.index_of(address).unwrap();
+                    entry.set_flags(entry.flags().set_access(true));
+                    table.set_entry(i, entry);
+                }
+            }
         } else if vma.is_anonymous() {
             ret = Self::do_anonymous_page(pfm);
         } else {
@@ -253,6 +447,12 @@ impl PageFaultHandler {
             let mut page_manager_guard = page_manager_lock_irqsave();
             let page = page_manager_guard.get_unwrap(&paddr);
             page.write_irqsave().insert_vma(vma.clone());
+            drop(page_manager_guard);
+
+            // 私有匿名页可以被换出
+            if !guard.vm_flags().contains(VmFlags::VM_SHARED) {
+                lru_add_anon(&page, address);
+            }
             VmFaultReason::VM_FAULT_COMPLETED
         } else {
             VmFaultReason::VM_FAULT_OOM
@@ -372,16 +572,24 @@ impl PageFaultHandler {
     ///
     /// ## 返回值
     /// - VmFaultReason: 页面错误处理信息标志
-    #[allow(unused_variables)]
     pub unsafe fn do_swap_page(pfm: &mut PageFaultMessage) -> VmFaultReason {
-        panic!(
-            "do_swap_page has not yet been implemented, 
-        fault message: {:?}, 
-        pid: {}\n",
-            pfm,
-            crate::process::ProcessManager::current_pid().data()
-        );
-        // TODO https://code.dragonos.org.cn/xref/linux-6.6.21/mm/memory.c#do_swap_page
+        let address = pfm.address_aligned_down();
+        let vma = pfm.vma.clone();
+        let mapper = &mut pfm.mapper;
+
+        let entry = match mapper
+            .get_entry(address, 0)
+            .and_then(|entry| SwapEntry::from_pte(&entry))
+        {
+            Some(entry) => entry,
+            None => return VmFaultReason::VM_FAULT_SIGBUS,
+        };
+
+        match swap_in_page(mapper, &vma, address, entry) {
+            Ok(()) => VmFaultReason::VM_FAULT_MAJOR | VmFaultReason::VM_FAULT_COMPLETED,
+            Err(SystemError::ENOMEM) => VmFaultReason::VM_FAULT_OOM,
+            Err(_) => VmFaultReason::VM_FAULT_SIGBUS,
+        }
     }
 
     /// 处理NUMA的缺页异常
@@ -422,6 +630,7 @@ impl PageFaultHandler {
         let mut page_manager = page_manager_lock_irqsave();
         let old_page = page_manager.get_unwrap(&old_paddr);
         let map_count = old_page.read_irqsave().map_count();
+        let is_ksm = matches!(old_page.read_irqsave().page_type(), PageType::Ksm);
         drop(page_manager);
 
         let mut entry = mapper.get_entry(address, 0).unwrap();
@@ -438,30 +647,39 @@ impl PageFaultHandler {
 
             VmFaultReason::VM_FAULT_COMPLETED
         } else if vma.is_anonymous() {
-            // 私有匿名映射，根据引用计数判断是否拷贝页面
-            if map_count == 1 {
+            // 私有匿名映射，根据引用计数判断是否拷贝页面。KSM页面总是需要拷贝
+            if map_count == 1 && !is_ksm {
                 let table = mapper.get_table(address, 0).unwrap();
                 let i = table.index_of(address).unwrap();
                 entry.set_flags(new_flags);
                 table.set_entry(i, entry);
                 VmFaultReason::VM_FAULT_COMPLETED
             } else if let Some(flush) = mapper.map(address, new_flags) {
-                let mut page_manager_guard = page_manager_lock_irqsave();
-                let old_page = page_manager_guard.get_unwrap(&old_paddr);
-                old_page.write_irqsave().remove_vma(&vma);
-                // drop(page_manager_guard);
-
                 flush.flush();
                 let paddr = mapper.translate(address).unwrap().0;
-                // let mut page_manager_guard = page_manager_lock_irqsave();
-                let page = page_manager_guard.get_unwrap(&paddr);
-                page.write_irqsave().insert_vma(vma.clone());
-
                 (MMArch::phys_2_virt(paddr).unwrap().data() as *mut u8).copy_from_nonoverlapping(
                     MMArch::phys_2_virt(old_paddr).unwrap().data() as *mut u8,
                     MMArch::PAGE_SIZE,
                 );
 
+                let mut page_manager_guard = page_manager_lock_irqsave();
+                let mut old_page_guard = old_page.write_irqsave();
+                old_page_guard.remove_vma(&vma);
+                // 其他映射已经在此期间写时复制，页面不再被映射
+                if old_page_guard.can_deallocate() {
+                    drop(old_page_guard);
+                    page_manager_guard.remove_page(&old_paddr);
+                    if !is_ksm {
+                        page_reclaimer_lock_irqsave().remove_anon_page(&old_paddr);
+                    }
+                }
+
+                let page = page_manager_guard.get_unwrap(&paddr);
+                page.write_irqsave().insert_vma(vma.clone());
+                drop(page_manager_guard);
+
+                lru_add_anon(&page, address);
+
                 VmFaultReason::VM_FAULT_COMPLETED
             } else {
                 VmFaultReason::VM_FAULT_OOM
@@ -0,0 +1,50 @@
+//! 页帧分配标志（GFP，Get Free Pages）
+//!
+//! 分配标志描述了一次页帧分配对物理内存的要求，例如只能使用某个内存区域、
+//! 只能在指定的NUMA节点上分配、分配后需要清零等。
+
+use super::mmzone::ZoneType;
+
+bitflags! {
+    /// 页帧分配标志
+    pub struct GfpFlags: u32 {
+        /// 只能从DMA区域（物理地址低于16M）中分配
+        const __GFP_DMA = 0x01;
+        /// 只能从DMA32区域及以下（物理地址低于4G）中分配
+        const __GFP_DMA32 = 0x04;
+        /// 高优先级分配，可以使用预留的内存
+        const __GFP_HIGH = 0x20;
+        /// 分配者不能睡眠（例如处于中断上下文或者持有自旋锁）
+        const __GFP_ATOMIC = 0x200;
+        /// 分配后把页帧清零
+        const __GFP_ZERO = 0x100;
+        /// 分配失败时不打印警告
+        const __GFP_NOWARN = 0x2000;
+        /// 只能在指定的NUMA节点上分配，不允许回退到其他节点
+        const __GFP_THISNODE = 0x200000;
+
+        /// 内核的普通分配
+        const GFP_KERNEL = 0;
+        /// 不能睡眠的分配，不使用进程的内存策略
+        const GFP_ATOMIC = Self::__GFP_HIGH.bits | Self::__GFP_ATOMIC.bits;
+        /// 供只能访问16M以下物理地址的设备使用
+        const GFP_DMA = Self::__GFP_DMA.bits;
+        /// 供只能访问32位物理地址的设备使用
+        const GFP_DMA32 = Self::__GFP_DMA32.bits;
+    }
+}
+
+impl GfpFlags {
+    /// 获取分配标志允许使用的最高的内存区域
+    ///
+    /// 分配时从这个区域开始，依次回退到更低的内存区域
+    pub fn zone_type(&self) -> ZoneType {
+        if self.contains(Self::__GFP_DMA) {
+            ZoneType::Dma
+        } else if self.contains(Self::__GFP_DMA32) {
+            ZoneType::Dma32
+        } else {
+            ZoneType::Normal
+        }
+    }
+}
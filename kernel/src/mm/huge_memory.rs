@@ -0,0 +1,546 @@
+//! 匿名映射的透明大页
+//!
+//! 满足条件的私有匿名映射在缺页时直接映射2M的大页，以减少TLB缺失。透明大页不在页面管理器中，
+//! 也不会被换出。当munmap、mprotect、madvise只涉及大页的一部分，或者fork需要写时复制时，
+//! 大页会被拆分成普通页。启用策略通过`/sys/kernel/mm/transparent_hugepage/enabled`设置。
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use alloc::{string::ToString, sync::Arc, vec::Vec};
+use system_error::SystemError;
+use unified_init::macros::unified_init;
+
+use crate::{
+    arch::{
+        mm::{LockedFrameAllocator, PageMapper},
+        MMArch,
+    },
+    driver::base::{kobject::KObject, kset::KSet},
+    filesystem::{
+        sysfs::{
+            file::sysfs_emit_str, sysfs_instance, Attribute, AttributeGroup, SysFSOpsSupport,
+            SYSFS_ATTR_MODE_RO, SYSFS_ATTR_MODE_RW,
+        },
+        vfs::syscall::ModeType,
+    },
+    init::initcall::INITCALL_SUBSYS,
+    libs::{
+        align::{align_down, align_up},
+        spinlock::SpinLock,
+    },
+};
+
+use super::{
+    allocator::page_frame::{FrameAllocator, PageFrameCount},
+    hugetlb::hugetlb_supported,
+    page::{
+        page_manager_lock_irqsave, page_reclaimer_lock_irqsave, EntryFlags, Flusher, PageEntry,
+        PageFlags, PageFlush, PageType,
+    },
+    swap::lru_add_anon,
+    sysfs::sys_kernel_mm_kset,
+    ucontext::{LockedVMA, UserMappings, VMA},
+    MemoryManagementArch, PhysAddr, VirtAddr, VirtRegion, VmFlags,
+};
+
+/// 透明大页所在的页表层级
+pub const HPAGE_PMD_LEVEL: usize = 1;
+/// 透明大页的大小
+pub const HPAGE_PMD_SIZE: usize = MMArch::PAGE_SIZE << MMArch::PAGE_ENTRY_SHIFT;
+/// 一个透明大页包含的普通页数量
+pub const HPAGE_PMD_NR: usize = MMArch::PAGE_ENTRY_NUM;
+
+/// 透明大页的启用策略
+static THP_MODE: AtomicUsize = AtomicUsize::new(ThpMode::Madvise as usize);
+/// 当前已映射的透明大页数量
+static NR_ANON_THPS: AtomicUsize = AtomicUsize::new(0);
+/// `/sys/kernel/mm/transparent_hugepage`的kset
+static THP_KSET: SpinLock<Option<Arc<KSet>>> = SpinLock::new(None);
+
+/// 透明大页的启用策略
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ThpMode {
+    /// 所有满足条件的匿名映射都使用透明大页
+    Always = 0,
+    /// 只有通过`MADV_HUGEPAGE`标记的映射使用透明大页
+    Madvise = 1,
+    /// 不使用透明大页
+    Never = 2,
+}
+
+impl ThpMode {
+    const ALL: [ThpMode; 3] = [ThpMode::Always, ThpMode::Madvise, ThpMode::Never];
+
+    fn name(&self) -> &'static str {
+        match self {
+            ThpMode::Always => "always",
+            ThpMode::Madvise => "madvise",
+            ThpMode::Never => "never",
+        }
+    }
+}
+
+/// 获取透明大页的启用策略
+pub fn thp_mode() -> ThpMode {
+    ThpMode::ALL[THP_MODE.load(Ordering::SeqCst)]
+}
+
+/// 设置透明大页的启用策略，已经映射的透明大页不受影响
+pub fn set_thp_mode(mode: ThpMode) {
+    THP_MODE.store(mode as usize, Ordering::SeqCst);
+}
+
+/// 当前已映射的透明大页数量
+pub fn nr_anon_thps() -> usize {
+    NR_ANON_THPS.load(Ordering::SeqCst)
+}
+
+/// 判断VMA的类型是否允许使用透明大页
+///
+/// ## 参数
+///
+/// - `vma` 要检查的VMA
+/// - `ignore_mode` 是否忽略sysfs中设置的启用策略（`MADV_COLLAPSE`不受启用策略的限制）
+pub fn thp_vma_suitable(vma: &VMA, ignore_mode: bool) -> bool {
+    if !hugetlb_supported() || vma.vm_file().is_some() {
+        return false;
+    }
+    let vm_flags = *vma.vm_flags();
+    if vm_flags.intersects(VmFlags::VM_SHARED | VmFlags::VM_HUGETLB | VmFlags::VM_NOHUGEPAGE) {
+        return false;
+    }
+    if ignore_mode {
+        return true;
+    }
+    match thp_mode() {
+        ThpMode::Always => true,
+        ThpMode::Madvise => vm_flags.contains(VmFlags::VM_HUGEPAGE),
+        ThpMode::Never => false,
+    }
+}
+
+/// 判断VMA中从`haddr`开始的2M区域能否映射为透明大页
+pub fn thp_vma_allowable(vma: &VMA, haddr: VirtAddr, ignore_mode: bool) -> bool {
+    haddr.check_aligned(HPAGE_PMD_SIZE)
+        && vma.region().start() <= haddr
+        && haddr + HPAGE_PMD_SIZE <= vma.region().end()
+        && thp_vma_suitable(vma, ignore_mode)
+}
+
+/// 获取`haddr`处映射的透明大页的页表项
+///
+/// ## 返回值
+///
+/// `haddr`处没有映射大页时返回None
+pub fn huge_pmd_entry(mapper: &PageMapper, haddr: VirtAddr) -> Option<PageEntry<MMArch>> {
+    let entry = mapper.get_entry(haddr, HPAGE_PMD_LEVEL)?;
+    if entry.present() && entry.flags().has_flag(MMArch::ENTRY_FLAG_HUGE_PAGE) {
+        Some(entry)
+    } else {
+        None
+    }
+}
+
+/// 遍历区域内所有可能映射透明大页的2M对齐的地址
+fn huge_pmds(region: VirtRegion) -> impl Iterator<Item = VirtAddr> {
+    let end = region.end().data();
+    (align_up(region.start().data(), HPAGE_PMD_SIZE)..end)
+        .step_by(HPAGE_PMD_SIZE)
+        .filter(move |haddr| haddr + HPAGE_PMD_SIZE <= end)
+        .map(VirtAddr::new)
+}
+
+/// 从伙伴分配器中分配一个清零的透明大页
+///
+/// ## 返回值
+///
+/// 无法分配连续且按2M对齐的物理内存时返回None
+pub fn alloc_thp() -> Option<PhysAddr> {
+    let count = PageFrameCount::new(HPAGE_PMD_NR);
+    let (paddr, allocated) = unsafe { LockedFrameAllocator.allocate(count)? };
+    if allocated != count || !paddr.check_aligned(HPAGE_PMD_SIZE) {
+        unsafe { LockedFrameAllocator.free(paddr, allocated) };
+        return None;
+    }
+
+    unsafe { MMArch::write_bytes(MMArch::phys_2_virt(paddr).unwrap(), 0, HPAGE_PMD_SIZE) };
+    NR_ANON_THPS.fetch_add(1, Ordering::SeqCst);
+    return Some(paddr);
+}
+
+/// 把透明大页归还给伙伴分配器
+pub fn free_thp(paddr: PhysAddr) {
+    unsafe { LockedFrameAllocator.free(paddr, PageFrameCount::new(HPAGE_PMD_NR)) };
+    NR_ANON_THPS.fetch_sub(1, Ordering::SeqCst);
+}
+
+/// 统计区域内映射的透明大页数量
+pub fn thp_count(mapper: &PageMapper, region: VirtRegion) -> usize {
+    huge_pmds(region)
+        .filter(|haddr| huge_pmd_entry(mapper, *haddr).is_some())
+        .count()
+}
+
+/// 把`haddr`处的透明大页拆分成普通页，拆分出的页面作为私有匿名页加入页面管理器，可以被换出
+///
+/// ## 参数
+///
+/// - `vma` 大页所在的VMA
+/// - `mapper` VMA所在地址空间的页表映射器，调用者需要持有地址空间的写锁
+/// - `haddr` 大页的起始虚拟地址
+///
+/// ## 返回值
+///
+/// - `Err(SystemError::ENOMEM)` 无法分配页表，大页保持不变
+pub unsafe fn split_huge_pmd(
+    vma: &Arc<LockedVMA>,
+    mapper: &mut PageMapper,
+    haddr: VirtAddr,
+) -> Result<(), SystemError> {
+    if huge_pmd_entry(mapper, haddr).is_none() {
+        return Ok(());
+    }
+    let (paddr, huge_flags, flush) = mapper
+        .unmap_huge_phys(haddr, HPAGE_PMD_LEVEL, false)
+        .unwrap();
+    flush.flush();
+
+    // 映射第一个页面时会分配页表，失败时恢复大页映射
+    let flags = huge_flags.set_huge_page(false);
+    if mapper.map_phys(haddr, paddr, flags).is_none() {
+        mapper
+            .map_huge_phys(haddr, paddr, HPAGE_PMD_LEVEL, huge_flags)
+            .unwrap()
+            .flush();
+        return Err(SystemError::ENOMEM);
+    }
+    // 大页的TLB项已经被刷新，新的页表项不会存在于TLB中
+    for i in 1..HPAGE_PMD_NR {
+        let offset = i * MMArch::PAGE_SIZE;
+        mapper
+            .map_phys(haddr + offset, paddr + offset, flags)
+            .unwrap()
+            .ignore();
+    }
+
+    let pages = page_manager_lock_irqsave()
+        .insert_pages(
+            paddr,
+            PageFrameCount::new(HPAGE_PMD_NR),
+            PageType::Normal,
+            PageFlags::empty(),
+        )
+        .expect("thp: split page is already managed");
+    for (i, page) in pages.iter().enumerate() {
+        page.write_irqsave().insert_vma(vma.clone());
+        lru_add_anon(page, haddr + i * MMArch::PAGE_SIZE);
+    }
+    NR_ANON_THPS.fetch_sub(1, Ordering::SeqCst);
+
+    return Ok(());
+}
+
+/// 拆分VMA中所有的透明大页
+pub unsafe fn split_huge_pmds(
+    vma: &Arc<LockedVMA>,
+    mapper: &mut PageMapper,
+) -> Result<(), SystemError> {
+    let guard = vma.lock_irqsave();
+    if guard.vm_flags().contains(VmFlags::VM_HUGETLB) {
+        return Ok(());
+    }
+    let region = *guard.region();
+    drop(guard);
+
+    for haddr in huge_pmds(region) {
+        split_huge_pmd(vma, mapper, haddr)?;
+    }
+    return Ok(());
+}
+
+/// 拆分跨越`region`边界的透明大页，使得对`region`的操作不会只涉及大页的一部分
+///
+/// ## 参数
+///
+/// - `mappings` 地址空间的VMA集合
+/// - `region` 将要被操作的区域
+/// - `mapper` 地址空间的页表映射器
+pub fn thp_split_boundaries(
+    mappings: &UserMappings,
+    region: VirtRegion,
+    mapper: &mut PageMapper,
+) -> Result<(), SystemError> {
+    for vaddr in [region.start(), region.end()] {
+        if vaddr.check_aligned(HPAGE_PMD_SIZE) {
+            continue;
+        }
+        let haddr = VirtAddr::new(align_down(vaddr.data(), HPAGE_PMD_SIZE));
+        if huge_pmd_entry(mapper, haddr).is_none() {
+            continue;
+        }
+        let vma = match mappings.contains(haddr) {
+            Some(vma) => vma,
+            None => continue,
+        };
+        if vma.lock_irqsave().vm_flags().contains(VmFlags::VM_HUGETLB) {
+            continue;
+        }
+        unsafe { split_huge_pmd(&vma, mapper, haddr)? };
+    }
+    return Ok(());
+}
+
+/// 取消区域内所有透明大页的映射，并释放大页
+pub fn thp_unmap(region: VirtRegion, mapper: &mut PageMapper, mut flusher: impl Flusher<MMArch>) {
+    for haddr in huge_pmds(region) {
+        if huge_pmd_entry(mapper, haddr).is_none() {
+            continue;
+        }
+        if let Some((paddr, _, flush)) =
+            unsafe { mapper.unmap_huge_phys(haddr, HPAGE_PMD_LEVEL, true) }
+        {
+            flusher.consume(flush);
+            free_thp(paddr);
+        }
+    }
+}
+
+/// 修改区域内透明大页的页表项标志
+pub fn thp_change_protection(
+    region: VirtRegion,
+    flags: EntryFlags<MMArch>,
+    mapper: &mut PageMapper,
+    mut flusher: impl Flusher<MMArch>,
+) {
+    for haddr in huge_pmds(region) {
+        if huge_pmd_entry(mapper, haddr).is_none() {
+            continue;
+        }
+        let table = mapper.get_table(haddr, HPAGE_PMD_LEVEL).unwrap();
+        let i = table.index_of(haddr).unwrap();
+        let mut entry = unsafe { table.entry(i) }.unwrap();
+        entry.set_flags(flags.set_huge_page(true));
+        unsafe { table.set_entry(i, entry) };
+        flusher.consume(PageFlush::new(haddr));
+    }
+}
+
+/// 把VMA中已经映射的普通页合并成透明大页（`MADV_COLLAPSE`）
+///
+/// 只有2M区域内所有已映射的页面都只属于当前VMA，并且没有页面被换出时才会合并，
+/// 未映射的页面在大页中被清零
+///
+/// ## 参数
+///
+/// - `locked` 要合并的VMA
+/// - `vma` `locked`的守卫
+/// - `mapper` VMA所在地址空间的页表映射器
+/// - `flusher` 页表项刷新器
+///
+/// ## 返回值
+///
+/// - `Err(SystemError::EINVAL)` VMA不是私有匿名映射
+/// - `Err(SystemError::EAGAIN)` 有区域因为页面被共享或被换出而无法合并
+/// - `Err(SystemError::ENOMEM)` 无法分配大页
+pub unsafe fn madvise_collapse(
+    locked: &LockedVMA,
+    vma: &VMA,
+    mapper: &mut PageMapper,
+    mut flusher: impl Flusher<MMArch>,
+) -> Result<(), SystemError> {
+    if !thp_vma_suitable(vma, true) {
+        return Err(SystemError::EINVAL);
+    }
+
+    let mut result = Ok(());
+    for haddr in huge_pmds(*vma.region()) {
+        if let Err(e) = collapse_huge_page(locked, vma, mapper, haddr, &mut flusher) {
+            result = Err(e);
+        }
+    }
+    return result;
+}
+
+/// 把`haddr`开始的2M区域中的普通页合并成一个透明大页
+unsafe fn collapse_huge_page(
+    locked: &LockedVMA,
+    vma: &VMA,
+    mapper: &mut PageMapper,
+    haddr: VirtAddr,
+    mut flusher: impl Flusher<MMArch>,
+) -> Result<(), SystemError> {
+    if huge_pmd_entry(mapper, haddr).is_some() {
+        return Ok(());
+    }
+    let (pmd_table, pte_table) = match (
+        mapper.get_table(haddr, HPAGE_PMD_LEVEL),
+        mapper.get_table(haddr, 0),
+    ) {
+        (Some(pmd_table), Some(pte_table)) => (pmd_table, pte_table),
+        // 区域内没有映射任何页面
+        _ => return Ok(()),
+    };
+
+    let mut page_manager_guard = page_manager_lock_irqsave();
+    let mut present = Vec::new();
+    for i in 0..HPAGE_PMD_NR {
+        let entry = pte_table.entry(i).unwrap();
+        if entry.empty() {
+            continue;
+        }
+        // 页面被换出，或者被设置为PROT_NONE
+        if !entry.present() {
+            return Err(SystemError::EAGAIN);
+        }
+        let page = entry
+            .address()
+            .ok()
+            .and_then(|paddr| page_manager_guard.get(&paddr))
+            .ok_or(SystemError::EAGAIN)?;
+        let page_guard = page.read_irqsave();
+        if page_guard.map_count() != 1
+            || page_guard.flags().contains(PageFlags::PG_UNEVICTABLE)
+            || !matches!(page_guard.page_type(), PageType::Anon(_) | PageType::Normal)
+        {
+            return Err(SystemError::EAGAIN);
+        }
+        drop(page_guard);
+        present.push((i, page));
+    }
+    if present.is_empty() {
+        return Ok(());
+    }
+
+    let new = alloc_thp().ok_or(SystemError::ENOMEM)?;
+    for (i, page) in present.iter() {
+        let dst = MMArch::phys_2_virt(new + i * MMArch::PAGE_SIZE).unwrap();
+        let src = MMArch::phys_2_virt(page.phys_address()).unwrap();
+        dst.as_ptr::<u8>()
+            .copy_from_nonoverlapping(src.as_ptr::<u8>(), MMArch::PAGE_SIZE);
+    }
+
+    // 用大页的页表项替换指向页表的页表项，然后释放页表和原来的页面
+    let pmd_index = pmd_table.index_of(haddr).unwrap();
+    pmd_table.set_entry(
+        pmd_index,
+        PageEntry::new(new, vma.flags().set_huge_page(true)),
+    );
+    mapper.allocator_mut().free_one(pte_table.phys());
+    for (i, page) in present {
+        flusher.consume(PageFlush::new(haddr + i * MMArch::PAGE_SIZE));
+
+        let paddr = page.phys_address();
+        let mut page_guard = page.write_irqsave();
+        page_guard.remove_vma(locked);
+        if page_guard.can_deallocate() {
+            drop(page_guard);
+            page_manager_guard.remove_page(&paddr);
+            page_reclaimer_lock_irqsave().remove_anon_page(&paddr);
+        }
+    }
+
+    return Ok(());
+}
+
+/// 初始化`/sys/kernel/mm/transparent_hugepage`
+#[unified_init(INITCALL_SUBSYS)]
+fn thp_sysfs_init() -> Result<(), SystemError> {
+    if !hugetlb_supported() {
+        return Ok(());
+    }
+
+    let kset = KSet::new("transparent_hugepage".to_string());
+    kset.register(Some(sys_kernel_mm_kset()))?;
+    sysfs_instance().create_groups(&kset.as_kobject(), &[&ThpAttrGroup])?;
+    *THP_KSET.lock() = Some(kset);
+
+    return Ok(());
+}
+
+#[derive(Debug)]
+struct ThpAttrGroup;
+
+impl AttributeGroup for ThpAttrGroup {
+    fn name(&self) -> Option<&str> {
+        None
+    }
+
+    fn attrs(&self) -> &[&'static dyn Attribute] {
+        &[&AttrEnabled, &AttrHpagePmdSize]
+    }
+
+    fn is_visible(
+        &self,
+        _kobj: Arc<dyn KObject>,
+        attr: &'static dyn Attribute,
+    ) -> Option<ModeType> {
+        Some(attr.mode())
+    }
+}
+
+/// `/sys/kernel/mm/transparent_hugepage/enabled`
+#[derive(Debug)]
+struct AttrEnabled;
+
+impl Attribute for AttrEnabled {
+    fn name(&self) -> &str {
+        "enabled"
+    }
+
+    fn mode(&self) -> ModeType {
+        SYSFS_ATTR_MODE_RW
+    }
+
+    fn support(&self) -> SysFSOpsSupport {
+        SysFSOpsSupport::ATTR_SHOW | SysFSOpsSupport::ATTR_STORE
+    }
+
+    fn show(&self, _kobj: Arc<dyn KObject>, buf: &mut [u8]) -> Result<usize, SystemError> {
+        let current = thp_mode();
+        let modes = ThpMode::ALL
+            .iter()
+            .map(|mode| {
+                if *mode == current {
+                    format!("[{}]", mode.name())
+                } else {
+                    mode.name().to_string()
+                }
+            })
+            .collect::<Vec<_>>();
+        return sysfs_emit_str(buf, &format!("{}\n", modes.join(" ")));
+    }
+
+    fn store(&self, _kobj: Arc<dyn KObject>, buf: &[u8]) -> Result<usize, SystemError> {
+        let name = core::str::from_utf8(buf)
+            .map_err(|_| SystemError::EINVAL)?
+            .trim_matches(|c: char| c.is_whitespace() || c == '\0');
+        let mode = ThpMode::ALL
+            .iter()
+            .find(|mode| mode.name() == name)
+            .ok_or(SystemError::EINVAL)?;
+        set_thp_mode(*mode);
+        return Ok(buf.len());
+    }
+}
+
+/// `/sys/kernel/mm/transparent_hugepage/hpage_pmd_size`
+#[derive(Debug)]
+struct AttrHpagePmdSize;
+
+impl Attribute for AttrHpagePmdSize {
+    fn name(&self) -> &str {
+        "hpage_pmd_size"
+    }
+
+    fn mode(&self) -> ModeType {
+        SYSFS_ATTR_MODE_RO
+    }
+
+    fn support(&self) -> SysFSOpsSupport {
+        SysFSOpsSupport::ATTR_SHOW
+    }
+
+    fn show(&self, _kobj: Arc<dyn KObject>, buf: &mut [u8]) -> Result<usize, SystemError> {
+        return sysfs_emit_str(buf, &format!("{}\n", HPAGE_PMD_SIZE));
+    }
+}
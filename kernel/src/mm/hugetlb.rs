@@ -0,0 +1,520 @@
+//! 大页内存池
+//!
+//! 系统启动时大页池为空，通过`/sys/kernel/mm/hugepages/hugepages-<size>kB/nr_hugepages`
+//! 从伙伴分配器中预留大页。hugetlbfs的文件和MAP_HUGETLB映射都从池中分配大页，
+//! 大页不再被使用时回到池中，而不是归还给伙伴分配器。
+
+use alloc::{
+    collections::BTreeMap,
+    string::{String, ToString},
+    sync::Arc,
+    vec::Vec,
+};
+use log::error;
+use system_error::SystemError;
+use unified_init::macros::unified_init;
+
+use crate::{
+    arch::{
+        mm::{LockedFrameAllocator, PageMapper},
+        MMArch,
+    },
+    driver::base::{kobject::KObject, kset::KSet},
+    filesystem::{
+        hugetlbfs::hugetlbfs_inode,
+        sysfs::{
+            file::sysfs_emit_str, sysfs_instance, Attribute, AttributeGroup, SysFSOpsSupport,
+            SYSFS_ATTR_MODE_RO, SYSFS_ATTR_MODE_RW,
+        },
+        vfs::syscall::ModeType,
+    },
+    init::initcall::INITCALL_SUBSYS,
+    libs::spinlock::SpinLock,
+};
+
+use super::{
+    allocator::page_frame::{FrameAllocator, PageFrameCount},
+    page::{EntryFlags, Flusher, PageFlush},
+    sysfs::sys_kernel_mm_kset,
+    ucontext::{UserMappings, VMA},
+    MemoryManagementArch, PhysAddr, VirtAddr, VirtRegion, VmFlags,
+};
+
+/// mmap和shmget的flags中，大页大小（以2为底的对数）所在的位置
+pub const HUGETLB_FLAG_ENCODE_SHIFT: usize = 26;
+pub const HUGETLB_FLAG_ENCODE_MASK: usize = 0x3f;
+
+/// 2M和1G两种大页的大页池
+static HSTATES: [Hstate; 2] = [Hstate::new(1), Hstate::new(2)];
+
+/// `/sys/kernel/mm/hugepages`及其下每种大页的kset
+static HUGEPAGES_KSETS: SpinLock<Vec<Arc<KSet>>> = SpinLock::new(Vec::new());
+
+/// 一种大小的大页的大页池
+#[derive(Debug)]
+pub struct Hstate {
+    /// 大页所在的页表层级
+    level: usize,
+    inner: SpinLock<InnerHstate>,
+}
+
+#[derive(Debug)]
+struct InnerHstate {
+    /// 池中大页的总数，包括空闲的和正在使用的大页
+    nr_pages: usize,
+    /// 通过nr_hugepages设置的池大小。正在使用的大页被释放时，若池的大小超过它，则归还给伙伴分配器
+    target_pages: usize,
+    /// 空闲的大页
+    free_pages: Vec<PhysAddr>,
+    /// 正在使用的大页的引用计数。页表项和hugetlbfs文件各持有一个引用
+    refcount: BTreeMap<PhysAddr, usize>,
+}
+
+impl Hstate {
+    const fn new(level: usize) -> Self {
+        Self {
+            level,
+            inner: SpinLock::new(InnerHstate {
+                nr_pages: 0,
+                target_pages: 0,
+                free_pages: Vec::new(),
+                refcount: BTreeMap::new(),
+            }),
+        }
+    }
+
+    /// 大页所在的页表层级，1为2M大页，2为1G大页
+    #[inline(always)]
+    pub fn level(&self) -> usize {
+        self.level
+    }
+
+    #[inline(always)]
+    pub fn shift(&self) -> usize {
+        MMArch::PAGE_SHIFT + MMArch::PAGE_ENTRY_SHIFT * self.level
+    }
+
+    /// 大页的字节数
+    #[inline(always)]
+    pub fn size(&self) -> usize {
+        1 << self.shift()
+    }
+
+    /// 一个大页包含的普通页的数量
+    #[inline(always)]
+    pub fn nr_base_pages(&self) -> usize {
+        1 << (MMArch::PAGE_ENTRY_SHIFT * self.level)
+    }
+
+    /// 大页池在sysfs中的目录名
+    pub fn name(&self) -> String {
+        format!("hugepages-{}kB", self.size() >> 10)
+    }
+
+    /// 池中大页的总数
+    pub fn nr_hugepages(&self) -> usize {
+        self.inner.lock_irqsave().nr_pages
+    }
+
+    /// 池中空闲大页的数量
+    pub fn free_hugepages(&self) -> usize {
+        self.inner.lock_irqsave().free_pages.len()
+    }
+
+    /// 调整大页池的大小
+    ///
+    /// 扩大时从伙伴分配器中分配大页，内存不足时停止分配；
+    /// 缩小时只释放空闲的大页，正在使用的大页在被释放时再归还给伙伴分配器
+    pub fn set_nr_hugepages(&self, count: usize) {
+        self.inner.lock_irqsave().target_pages = count;
+
+        loop {
+            let inner = self.inner.lock_irqsave();
+            if inner.nr_pages >= inner.target_pages {
+                break;
+            }
+            drop(inner);
+
+            let paddr = match self.alloc_from_buddy() {
+                Some(paddr) => paddr,
+                None => break,
+            };
+            let mut inner = self.inner.lock_irqsave();
+            if inner.nr_pages >= inner.target_pages {
+                // 期间池的大小被其他人修改了
+                drop(inner);
+                self.free_to_buddy(paddr);
+                break;
+            }
+            inner.nr_pages += 1;
+            inner.free_pages.push(paddr);
+        }
+
+        let mut to_free = Vec::new();
+        let mut inner = self.inner.lock_irqsave();
+        while inner.nr_pages > inner.target_pages {
+            match inner.free_pages.pop() {
+                Some(paddr) => {
+                    inner.nr_pages -= 1;
+                    to_free.push(paddr);
+                }
+                None => break,
+            }
+        }
+        drop(inner);
+
+        for paddr in to_free {
+            self.free_to_buddy(paddr);
+        }
+    }
+
+    fn alloc_from_buddy(&self) -> Option<PhysAddr> {
+        let count = PageFrameCount::new(self.nr_base_pages());
+        let (paddr, allocated) = unsafe { LockedFrameAllocator.allocate(count)? };
+        if allocated != count || !paddr.check_aligned(self.size()) {
+            unsafe { LockedFrameAllocator.free(paddr, allocated) };
+            return None;
+        }
+        return Some(paddr);
+    }
+
+    fn free_to_buddy(&self, paddr: PhysAddr) {
+        unsafe {
+            LockedFrameAllocator.free(paddr, PageFrameCount::new(self.nr_base_pages()));
+        }
+    }
+
+    /// 从大页池中分配一个清零的大页，引用计数为1
+    ///
+    /// ## 返回值
+    ///
+    /// 池中没有空闲的大页时返回None
+    pub fn alloc_page(&self) -> Option<PhysAddr> {
+        let mut inner = self.inner.lock_irqsave();
+        let paddr = inner.free_pages.pop()?;
+        inner.refcount.insert(paddr, 1);
+        drop(inner);
+
+        unsafe {
+            MMArch::write_bytes(MMArch::phys_2_virt(paddr).unwrap(), 0, self.size());
+        }
+        return Some(paddr);
+    }
+
+    /// 增加大页的引用计数
+    pub fn get_page(&self, paddr: PhysAddr) {
+        match self.inner.lock_irqsave().refcount.get_mut(&paddr) {
+            Some(count) => *count += 1,
+            None => error!("hugetlb: get_page on free huge page {:?}", paddr),
+        }
+    }
+
+    /// 减少大页的引用计数，引用计数为0时大页回到池中
+    pub fn put_page(&self, paddr: PhysAddr) {
+        let mut inner = self.inner.lock_irqsave();
+        let count = match inner.refcount.get_mut(&paddr) {
+            Some(count) => count,
+            None => {
+                error!("hugetlb: put_page on free huge page {:?}", paddr);
+                return;
+            }
+        };
+        *count -= 1;
+        if *count > 0 {
+            return;
+        }
+        inner.refcount.remove(&paddr);
+
+        if inner.nr_pages > inner.target_pages {
+            inner.nr_pages -= 1;
+            drop(inner);
+            self.free_to_buddy(paddr);
+        } else {
+            inner.free_pages.push(paddr);
+        }
+    }
+
+    /// 获取大页的引用计数
+    pub fn page_count(&self, paddr: PhysAddr) -> usize {
+        self.inner
+            .lock_irqsave()
+            .refcount
+            .get(&paddr)
+            .copied()
+            .unwrap_or(0)
+    }
+}
+
+/// 当前架构是否支持大页映射
+#[inline(always)]
+pub fn hugetlb_supported() -> bool {
+    MMArch::ENTRY_FLAG_HUGE_PAGE != 0
+}
+
+/// 当前架构支持的所有大页池
+pub fn hstates() -> &'static [Hstate] {
+    if hugetlb_supported() {
+        &HSTATES
+    } else {
+        &[]
+    }
+}
+
+/// 默认的大页池（2M）
+pub fn default_hstate() -> Option<&'static Hstate> {
+    hstates().first()
+}
+
+/// 根据大页的字节数查找大页池
+pub fn size_to_hstate(size: usize) -> Option<&'static Hstate> {
+    hstates().iter().find(|h| h.size() == size)
+}
+
+/// 根据mmap的flags中编码的大页大小查找大页池，未指定大小时使用默认的大页池
+pub fn hstate_from_flags(flags: usize) -> Option<&'static Hstate> {
+    let shift = (flags >> HUGETLB_FLAG_ENCODE_SHIFT) & HUGETLB_FLAG_ENCODE_MASK;
+    if shift == 0 {
+        return default_hstate();
+    }
+    hstates().iter().find(|h| h.shift() == shift)
+}
+
+/// 获取大页映射使用的大页池
+pub fn vma_hstate(vma: &VMA) -> Option<&'static Hstate> {
+    vma.vm_file()
+        .and_then(|file| hugetlbfs_inode(&file))
+        .map(|inode| inode.hstate())
+}
+
+/// 按大页大小遍历区域内的每个大页的起始地址
+fn huge_pages(region: VirtRegion, hstate: &Hstate) -> impl Iterator<Item = VirtAddr> {
+    (region.start().data()..region.end().data())
+        .step_by(hstate.size())
+        .map(VirtAddr::new)
+}
+
+/// 检查对`region`的操作是否会把大页映射从一个大页的中间切开
+///
+/// ## 返回值
+///
+/// - `Err(SystemError::EINVAL)` `region`与某个大页映射的交集没有按大页大小对齐
+pub fn hugetlb_check_split(mappings: &UserMappings, region: VirtRegion) -> Result<(), SystemError> {
+    for vma in mappings.conflicts(region) {
+        let guard = vma.lock_irqsave();
+        if !guard.vm_flags().contains(VmFlags::VM_HUGETLB) {
+            continue;
+        }
+        let size = vma_hstate(&guard).ok_or(SystemError::EINVAL)?.size();
+        let intersection = guard.region().intersect(&region).unwrap();
+        if !intersection.start().check_aligned(size) || !intersection.end().check_aligned(size) {
+            return Err(SystemError::EINVAL);
+        }
+    }
+    return Ok(());
+}
+
+/// 取消大页映射中所有大页的映射，并释放页表项对大页的引用
+pub fn hugetlb_unmap(vma: &VMA, mapper: &mut PageMapper, mut flusher: impl Flusher<MMArch>) {
+    let hstate = match vma_hstate(vma) {
+        Some(hstate) => hstate,
+        None => return,
+    };
+    for vaddr in huge_pages(*vma.region(), hstate) {
+        if let Some((paddr, _, flush)) =
+            unsafe { mapper.unmap_huge_phys(vaddr, hstate.level(), true) }
+        {
+            flusher.consume(flush);
+            hstate.put_page(paddr);
+        }
+    }
+}
+
+/// 修改大页映射中已经映射的大页的页表项标志
+///
+/// 私有映射的写权限只在写缺页时授予，以保证写时复制
+pub fn hugetlb_change_protection(
+    vma: &VMA,
+    flags: EntryFlags<MMArch>,
+    mapper: &mut PageMapper,
+    mut flusher: impl Flusher<MMArch>,
+) {
+    let hstate = match vma_hstate(vma) {
+        Some(hstate) => hstate,
+        None => return,
+    };
+    let shared = vma.vm_flags().contains(VmFlags::VM_SHARED);
+    for vaddr in huge_pages(*vma.region(), hstate) {
+        let table = match mapper.get_table(vaddr, hstate.level()) {
+            Some(table) => table,
+            None => continue,
+        };
+        let i = table.index_of(vaddr).unwrap();
+        let mut entry = unsafe { table.entry(i) }.unwrap();
+        if !entry.present() {
+            continue;
+        }
+        let writable = flags.has_write() && (shared || entry.write());
+        entry.set_flags(flags.set_write(writable).set_huge_page(true));
+        unsafe { table.set_entry(i, entry) };
+        flusher.consume(PageFlush::new(vaddr));
+    }
+}
+
+/// fork时把大页映射的页表项复制到子进程的页表
+///
+/// 父子进程共享同一个大页，私有映射的大页被设置为只读，在写缺页时复制
+///
+/// ## 参数
+///
+/// - `vma` 父进程的大页映射
+/// - `src` 父进程的页表
+/// - `dst` 子进程的页表
+pub fn hugetlb_copy(
+    vma: &VMA,
+    src: &mut PageMapper,
+    dst: &mut PageMapper,
+) -> Result<(), SystemError> {
+    let hstate = vma_hstate(vma).ok_or(SystemError::EINVAL)?;
+    let cow = !vma.vm_flags().contains(VmFlags::VM_SHARED);
+    for vaddr in huge_pages(*vma.region(), hstate) {
+        let table = match src.get_table(vaddr, hstate.level()) {
+            Some(table) => table,
+            None => continue,
+        };
+        let i = table.index_of(vaddr).unwrap();
+        let mut entry = unsafe { table.entry(i) }.unwrap();
+        if !entry.present() {
+            continue;
+        }
+        let paddr = entry.address().unwrap();
+        let mut flags = entry.flags();
+        if cow && flags.has_write() {
+            flags = flags.set_write(false);
+            entry.set_flags(flags);
+            unsafe { table.set_entry(i, entry) };
+            PageFlush::<MMArch>::new(vaddr).flush();
+        }
+
+        hstate.get_page(paddr);
+        match unsafe { dst.map_huge_phys(vaddr, paddr, hstate.level(), flags) } {
+            // 子进程的页表不是当前页表，无需刷新TLB
+            Some(flush) => unsafe { flush.ignore() },
+            None => {
+                hstate.put_page(paddr);
+                return Err(SystemError::ENOMEM);
+            }
+        }
+    }
+    return Ok(());
+}
+
+/// 初始化`/sys/kernel/mm/hugepages`
+#[unified_init(INITCALL_SUBSYS)]
+fn hugetlb_sysfs_init() -> Result<(), SystemError> {
+    if !hugetlb_supported() {
+        return Ok(());
+    }
+
+    let hugepages_kset = KSet::new("hugepages".to_string());
+    hugepages_kset
+        .register(Some(sys_kernel_mm_kset()))
+        .expect("register hugepages kset failed");
+
+    let mut ksets = HUGEPAGES_KSETS.lock();
+    for hstate in hstates() {
+        let kset = KSet::new(hstate.name());
+        kset.register(Some(hugepages_kset.clone()))?;
+        sysfs_instance().create_groups(&kset.as_kobject(), &[&HstateAttrGroup])?;
+        ksets.push(kset);
+    }
+    ksets.push(hugepages_kset);
+
+    return Ok(());
+}
+
+/// 根据sysfs目录名查找大页池
+fn kobj_to_hstate(kobj: &Arc<dyn KObject>) -> Result<&'static Hstate, SystemError> {
+    let name = kobj.name();
+    hstates()
+        .iter()
+        .find(|h| h.name() == name)
+        .ok_or(SystemError::EINVAL)
+}
+
+#[derive(Debug)]
+struct HstateAttrGroup;
+
+impl AttributeGroup for HstateAttrGroup {
+    fn name(&self) -> Option<&str> {
+        None
+    }
+
+    fn attrs(&self) -> &[&'static dyn Attribute] {
+        &[&AttrNrHugepages, &AttrFreeHugepages]
+    }
+
+    fn is_visible(
+        &self,
+        _kobj: Arc<dyn KObject>,
+        attr: &'static dyn Attribute,
+    ) -> Option<ModeType> {
+        Some(attr.mode())
+    }
+}
+
+/// `/sys/kernel/mm/hugepages/hugepages-<size>kB/nr_hugepages`
+#[derive(Debug)]
+struct AttrNrHugepages;
+
+impl Attribute for AttrNrHugepages {
+    fn name(&self) -> &str {
+        "nr_hugepages"
+    }
+
+    fn mode(&self) -> ModeType {
+        SYSFS_ATTR_MODE_RW
+    }
+
+    fn support(&self) -> SysFSOpsSupport {
+        SysFSOpsSupport::ATTR_SHOW | SysFSOpsSupport::ATTR_STORE
+    }
+
+    fn show(&self, kobj: Arc<dyn KObject>, buf: &mut [u8]) -> Result<usize, SystemError> {
+        let hstate = kobj_to_hstate(&kobj)?;
+        return sysfs_emit_str(buf, &format!("{}\n", hstate.nr_hugepages()));
+    }
+
+    fn store(&self, kobj: Arc<dyn KObject>, buf: &[u8]) -> Result<usize, SystemError> {
+        let hstate = kobj_to_hstate(&kobj)?;
+        let count = core::str::from_utf8(buf)
+            .map_err(|_| SystemError::EINVAL)?
+            .trim_matches(|c: char| c.is_whitespace() || c == '\0')
+            .parse::<usize>()
+            .map_err(|_| SystemError::EINVAL)?;
+        hstate.set_nr_hugepages(count);
+        return Ok(buf.len());
+    }
+}
+
+/// `/sys/kernel/mm/hugepages/hugepages-<size>kB/free_hugepages`
+#[derive(Debug)]
+struct AttrFreeHugepages;
+
+impl Attribute for AttrFreeHugepages {
+    fn name(&self) -> &str {
+        "free_hugepages"
+    }
+
+    fn mode(&self) -> ModeType {
+        SYSFS_ATTR_MODE_RO
+    }
+
+    fn support(&self) -> SysFSOpsSupport {
+        SysFSOpsSupport::ATTR_SHOW
+    }
+
+    fn show(&self, kobj: Arc<dyn KObject>, buf: &mut [u8]) -> Result<usize, SystemError> {
+        let hstate = kobj_to_hstate(&kobj)?;
+        return sysfs_emit_str(buf, &format!("{}\n", hstate.free_hugepages()));
+    }
+}
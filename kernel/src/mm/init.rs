@@ -11,6 +11,7 @@ use crate::{
     mm::{
         allocator::slab::slab_init,
         mmio_buddy::mmio_init,
+        mmzone::zones_pcp_init,
         page::{page_manager_init, page_reclaimer_init},
     },
 };
@@ -63,6 +64,8 @@ pub unsafe fn mm_init() {
     shm_manager_init();
     // enable PAGE_RECLAIMER
     page_reclaimer_init();
+    // enable per-cpu page frame caches
+    zones_pcp_init();
 
     MM_INIT
         .compare_exchange(
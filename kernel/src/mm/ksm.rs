@@ -0,0 +1,965 @@
+//! 内核同页合并(KSM)
+//!
+//! 通过`MADV_MERGEABLE`标记的私有匿名映射会被ksmd线程周期性地扫描，内容相同的页面被合并为同一个
+//! 只读的KSM页面，进程写入KSM页面时在缺页处理中进行写时复制。KSM页面不会被换出。
+//!
+//! 与Linux一致，页面的内容需要在两轮扫描之间保持不变才会参与合并：内容稳定但还没有找到相同页面的
+//! 页面记录在每轮扫描都会重建的不稳定树中，已经合并的KSM页面记录在稳定树中。两棵树都以页面内容的
+//! 校验和为键。
+//!
+//! 页面的反向映射以VMA为单位记录，一个KSM页面在每个VMA中只能被映射一次，因此同一个VMA中
+//! 内容相同的多个页面不会被合并，只有不同VMA(通常是不同进程)中的相同页面才会被合并。
+//! 运行参数与统计信息通过`/sys/kernel/mm/ksm`设置和查看。
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use alloc::{
+    collections::BTreeMap,
+    string::ToString,
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+use system_error::SystemError;
+use unified_init::macros::unified_init;
+
+use crate::{
+    arch::{mm::PageMapper, CurrentIrqArch, MMArch},
+    driver::base::{kobject::KObject, kset::KSet},
+    exception::InterruptArch,
+    filesystem::{
+        sysfs::{
+            file::sysfs_emit_str, sysfs_instance, Attribute, AttributeGroup, SysFSOpsSupport,
+            SYSFS_ATTR_MODE_RO, SYSFS_ATTR_MODE_RW,
+        },
+        vfs::syscall::ModeType,
+    },
+    init::initcall::{INITCALL_CORE, INITCALL_SUBSYS},
+    libs::spinlock::SpinLock,
+    process::{ProcessControlBlock, ProcessManager},
+    sched::{schedule, SchedMode},
+    time::{sleep::nanosleep, PosixTimeSpec},
+};
+
+use super::{
+    page::{
+        page_manager_lock_irqsave, page_reclaimer_lock_irqsave, EntryFlags, InactiveFlusher, Page,
+        PageFlags, PageType,
+    },
+    swap::lru_add_anon,
+    syscall::MadvFlags,
+    sysfs::sys_kernel_mm_kset,
+    ucontext::{AddressSpace, LockedVMA},
+    MemoryManagementArch, PhysAddr, VirtAddr, VirtRegion, VmFlags,
+};
+
+/// ksmd的运行状态
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KsmRun {
+    /// 停止合并，已经合并的页面保持不变
+    Stop = 0,
+    /// 周期性地扫描并合并页面
+    Merge = 1,
+    /// 停止合并，并拆分所有已经合并的页面
+    Unmerge = 2,
+}
+
+impl KsmRun {
+    const ALL: [KsmRun; 3] = [KsmRun::Stop, KsmRun::Merge, KsmRun::Unmerge];
+}
+
+/// ksmd的运行状态
+static KSM_RUN: AtomicUsize = AtomicUsize::new(KsmRun::Stop as usize);
+/// ksmd每次被唤醒时扫描的页面数
+static KSM_PAGES_TO_SCAN: AtomicUsize = AtomicUsize::new(100);
+/// ksmd两次扫描之间休眠的毫秒数
+static KSM_SLEEP_MILLISECS: AtomicUsize = AtomicUsize::new(20);
+/// 稳定树、不稳定树以及扫描进度
+static KSM_STATE: SpinLock<KsmState> = SpinLock::new(KsmState::new());
+/// ksmd线程
+static mut KSM_THREAD: Option<Arc<ProcessControlBlock>> = None;
+/// `/sys/kernel/mm/ksm`的kset
+static KSM_KSET: SpinLock<Option<Arc<KSet>>> = SpinLock::new(None);
+
+/// 含有`MADV_MERGEABLE`区域的地址空间
+#[derive(Debug)]
+struct KsmMmSlot {
+    mm: Weak<AddressSpace>,
+    /// 上一轮扫描时各个页面内容的校验和
+    checksums: BTreeMap<VirtAddr, u64>,
+}
+
+#[derive(Debug)]
+struct KsmState {
+    mm_slots: Vec<KsmMmSlot>,
+    /// 正在扫描的地址空间在`mm_slots`中的下标
+    scan_slot: usize,
+    /// 下一个要扫描的虚拟地址
+    scan_addr: VirtAddr,
+    /// 已经合并的KSM页面
+    stable_tree: BTreeMap<u64, Vec<Weak<Page>>>,
+    /// 本轮扫描中内容稳定、但还没有找到相同页面的匿名页
+    unstable_tree: BTreeMap<u64, Vec<Weak<Page>>>,
+    /// 本轮扫描中内容发生了变化的页面数
+    pages_volatile: usize,
+    /// 上一轮完整扫描中内容发生了变化的页面数
+    last_pages_volatile: usize,
+    /// 完成的完整扫描次数
+    full_scans: usize,
+}
+
+impl KsmState {
+    const fn new() -> Self {
+        Self {
+            mm_slots: Vec::new(),
+            scan_slot: 0,
+            scan_addr: VirtAddr::new(0),
+            stable_tree: BTreeMap::new(),
+            unstable_tree: BTreeMap::new(),
+            pages_volatile: 0,
+            last_pages_volatile: 0,
+            full_scans: 0,
+        }
+    }
+
+    /// 完成一轮完整的扫描，不稳定树在每轮扫描开始时重建
+    fn finish_full_scan(&mut self) {
+        self.scan_slot = 0;
+        self.scan_addr = VirtAddr::new(0);
+        self.unstable_tree.clear();
+        self.stable_tree.retain(|_, list| {
+            list.retain(|kpage| kpage.strong_count() > 0);
+            !list.is_empty()
+        });
+        self.last_pages_volatile = self.pages_volatile;
+        self.pages_volatile = 0;
+        self.full_scans += 1;
+    }
+
+    /// 在稳定树中查找与`page`内容相同、并且还没有被`vma`映射的KSM页面
+    fn stable_search(&mut self, checksum: u64, page: &Page, vma: &LockedVMA) -> Option<Arc<Page>> {
+        let list = self.stable_tree.get_mut(&checksum)?;
+        list.retain(|kpage| kpage.strong_count() > 0);
+        list.iter().filter_map(Weak::upgrade).find(|kpage| {
+            let guard = kpage.read_irqsave();
+            matches!(guard.page_type(), PageType::Ksm)
+                && guard.map_count() > 0
+                && !guard.vma_set().contains(vma)
+                && pages_identical(page.phys_address(), kpage.phys_address())
+        })
+    }
+
+    /// 在不稳定树中查找与`page`内容相同、并且不属于`vma`的另一个匿名页
+    ///
+    /// 同一个VMA中的两个页面无法共享同一个KSM页面，把其中一个转换为KSM页面没有意义
+    fn unstable_search(
+        &mut self,
+        checksum: u64,
+        page: &Arc<Page>,
+        vma: &LockedVMA,
+    ) -> Option<Arc<Page>> {
+        let list = self.unstable_tree.get_mut(&checksum)?;
+        list.retain(|other| other.strong_count() > 0);
+        list.iter().filter_map(Weak::upgrade).find(|other| {
+            !Arc::ptr_eq(other, page)
+                && ksm_candidate(other)
+                && !other.read_irqsave().vma_set().contains(vma)
+                && pages_identical(page.phys_address(), other.phys_address())
+        })
+    }
+
+    fn remove_unstable(&mut self, checksum: u64, page: &Arc<Page>) {
+        if let Some(list) = self.unstable_tree.get_mut(&checksum) {
+            list.retain(|other| other.as_ptr() != Arc::as_ptr(page));
+        }
+    }
+}
+
+/// 获取ksmd的运行状态
+pub fn ksm_run() -> KsmRun {
+    KsmRun::ALL[KSM_RUN.load(Ordering::SeqCst)]
+}
+
+/// 记录含有`MADV_MERGEABLE`区域的地址空间，使其被ksmd扫描
+pub fn ksm_enter(mm: Weak<AddressSpace>) {
+    let mut state = KSM_STATE.lock_irqsave();
+    if state.mm_slots.iter().any(|slot| slot.mm.ptr_eq(&mm)) {
+        return;
+    }
+    state.mm_slots.push(KsmMmSlot {
+        mm,
+        checksums: BTreeMap::new(),
+    });
+}
+
+/// 计算页面内容的校验和
+fn calc_checksum(paddr: PhysAddr) -> u64 {
+    let data = unsafe { page_slice(paddr) };
+    data.chunks_exact(8)
+        .fold(0xcbf2_9ce4_8422_2325, |hash, word| {
+            (hash ^ u64::from_ne_bytes(word.try_into().unwrap())).wrapping_mul(0x0100_0000_01b3)
+        })
+}
+
+unsafe fn page_slice(paddr: PhysAddr) -> &'static [u8] {
+    core::slice::from_raw_parts(
+        MMArch::phys_2_virt(paddr).unwrap().data() as *const u8,
+        MMArch::PAGE_SIZE,
+    )
+}
+
+fn pages_identical(a: PhysAddr, b: PhysAddr) -> bool {
+    unsafe { page_slice(a) == page_slice(b) }
+}
+
+/// 判断页面能否参与合并：只被映射一次的私有匿名页
+fn ksm_candidate(page: &Page) -> bool {
+    let guard = page.read_irqsave();
+    matches!(guard.page_type(), PageType::Anon(_))
+        && guard.map_count() == 1
+        && !guard.flags().contains(PageFlags::PG_UNEVICTABLE)
+}
+
+/// 刷新所有核心的TLB，被扫描的地址空间可能在任意核心上处于激活状态
+unsafe fn ksm_flush_tlb() {
+    MMArch::invalidate_all();
+    drop(InactiveFlusher::new());
+}
+
+/// 去掉页面的写权限，防止比较内容之后页面又被修改
+///
+/// ## 返回值
+///
+/// 页面已经不再被映射时返回false
+unsafe fn write_protect_page(mapper: &mut PageMapper, vaddr: VirtAddr) -> bool {
+    let flags = match mapper.translate(vaddr) {
+        Some((_, flags)) => flags,
+        None => return false,
+    };
+    if flags.has_write() {
+        match mapper.remap(vaddr, flags.set_write(false)) {
+            Some(flush) => flush.ignore(),
+            None => return false,
+        }
+        ksm_flush_tlb();
+    }
+    true
+}
+
+/// 把`vaddr`处的匿名页替换为内容相同的KSM页面，并释放原来的页面
+unsafe fn try_to_merge_with_ksm_page(
+    mapper: &mut PageMapper,
+    vma: &Arc<LockedVMA>,
+    vaddr: VirtAddr,
+    page: &Arc<Page>,
+    kpage: &Arc<Page>,
+) -> bool {
+    if !write_protect_page(mapper, vaddr)
+        || !pages_identical(page.phys_address(), kpage.phys_address())
+    {
+        return false;
+    }
+
+    let mut page_manager_guard = page_manager_lock_irqsave();
+    // KSM页面可能已经被释放
+    if !page_manager_guard
+        .get(&kpage.phys_address())
+        .is_some_and(|p| Arc::ptr_eq(&p, kpage))
+    {
+        return false;
+    }
+    let mut kpage_guard = kpage.write_irqsave();
+    if kpage_guard.map_count() == 0 || kpage_guard.vma_set().contains(vma) {
+        return false;
+    }
+    let flags = mapper.translate(vaddr).unwrap().1.set_write(false);
+    match mapper.map_phys(vaddr, kpage.phys_address(), flags) {
+        Some(flush) => flush.ignore(),
+        None => return false,
+    }
+    ksm_flush_tlb();
+    kpage_guard.insert_vma(vma.clone());
+    drop(kpage_guard);
+
+    let paddr = page.phys_address();
+    let mut page_guard = page.write_irqsave();
+    page_guard.remove_vma(vma);
+    if page_guard.can_deallocate() {
+        drop(page_guard);
+        page_manager_guard.remove_page(&paddr);
+        page_reclaimer_lock_irqsave().remove_anon_page(&paddr);
+    }
+    true
+}
+
+/// 在不稳定树中找到内容相同的页面后，把`vaddr`处的匿名页原地转换为KSM页面。
+/// 另一个页面在被扫描到时会合并到这个KSM页面
+unsafe fn try_to_promote_page(
+    mapper: &mut PageMapper,
+    vaddr: VirtAddr,
+    page: &Arc<Page>,
+    other: &Arc<Page>,
+) -> bool {
+    if !write_protect_page(mapper, vaddr)
+        || !pages_identical(page.phys_address(), other.phys_address())
+    {
+        return false;
+    }
+
+    let mut page_guard = page.write_irqsave();
+    if !matches!(page_guard.page_type(), PageType::Anon(_)) || page_guard.map_count() != 1 {
+        return false;
+    }
+    page_guard.set_page_type(PageType::Ksm);
+    page_guard.remove_flags(PageFlags::PG_SWAPBACKED | PageFlags::PG_LRU);
+    drop(page_guard);
+    page_reclaimer_lock_irqsave().remove_anon_page(&page.phys_address());
+    true
+}
+
+/// 扫描`vaddr`处的页面，尝试把它与内容相同的页面合并
+///
+/// ## 参数
+///
+/// - `mapper`: 页面所在地址空间的页表，调用者需要持有该地址空间的写锁
+/// - `slot`: 地址空间在`mm_slots`中的下标
+///
+/// ## 返回值
+///
+/// `vaddr`处没有映射页面时返回false
+unsafe fn cmp_and_merge_page(
+    mapper: &mut PageMapper,
+    vma: &Arc<LockedVMA>,
+    vaddr: VirtAddr,
+    slot: usize,
+) -> bool {
+    let paddr = match mapper.translate(vaddr) {
+        Some((paddr, _)) => paddr,
+        None => return false,
+    };
+    // 透明大页不在页面管理器中，不参与合并
+    let page = match page_manager_lock_irqsave().get(&paddr) {
+        Some(page) => page,
+        None => return true,
+    };
+    if !ksm_candidate(&page) {
+        return true;
+    }
+
+    let checksum = calc_checksum(paddr);
+    let mut state = KSM_STATE.lock_irqsave();
+    // 内容在两轮扫描之间发生了变化的页面暂不合并
+    if state.mm_slots[slot].checksums.insert(vaddr, checksum) != Some(checksum) {
+        state.pages_volatile += 1;
+        return true;
+    }
+
+    if let Some(kpage) = state.stable_search(checksum, &page, vma) {
+        try_to_merge_with_ksm_page(mapper, vma, vaddr, &page, &kpage);
+        return true;
+    }
+
+    if let Some(other) = state.unstable_search(checksum, &page, vma) {
+        if try_to_promote_page(mapper, vaddr, &page, &other) {
+            state.remove_unstable(checksum, &other);
+            state
+                .stable_tree
+                .entry(checksum)
+                .or_default()
+                .push(Arc::downgrade(&page));
+        }
+        return true;
+    }
+
+    state
+        .unstable_tree
+        .entry(checksum)
+        .or_default()
+        .push(Arc::downgrade(&page));
+    true
+}
+
+/// 从`start`开始扫描地址空间中的可合并区域
+///
+/// ## 参数
+///
+/// - `count`: 剩余需要扫描的页面数，只计算已经映射的页面
+///
+/// ## 返回值
+///
+/// - `Some(vaddr)` 扫描数量已经用完，下次从`vaddr`继续扫描
+/// - `None` 地址空间已经扫描完毕
+fn scan_address_space(
+    mm: &Arc<AddressSpace>,
+    slot: usize,
+    start: VirtAddr,
+    count: &mut usize,
+) -> Option<VirtAddr> {
+    let mut guard = mm.write_irqsave();
+    let mut vmas = guard
+        .mappings
+        .iter_vmas()
+        .filter_map(|vma| {
+            let vma_guard = vma.lock_irqsave();
+            if vma_guard.vm_flags().contains(VmFlags::VM_MERGEABLE)
+                && vma_guard.region().end() > start
+            {
+                Some((vma.clone(), *vma_guard.region()))
+            } else {
+                None
+            }
+        })
+        .collect::<Vec<(Arc<LockedVMA>, VirtRegion)>>();
+    vmas.sort_by_key(|(_, region)| region.start());
+
+    let mapper = &mut guard.user_mapper.utable;
+    for (vma, region) in vmas.iter() {
+        let mut vaddr = core::cmp::max(region.start(), start);
+        while vaddr < region.end() {
+            if *count == 0 {
+                return Some(vaddr);
+            }
+            if unsafe { cmp_and_merge_page(mapper, vma, vaddr, slot) } {
+                *count -= 1;
+            }
+            vaddr += MMArch::PAGE_SIZE;
+        }
+    }
+
+    // 丢弃已经不属于可合并区域的校验和
+    let mut state = KSM_STATE.lock_irqsave();
+    state.mm_slots[slot]
+        .checksums
+        .retain(|vaddr, _| vmas.iter().any(|(_, region)| region.contains(*vaddr)));
+    None
+}
+
+/// 扫描`count`个已经映射的页面
+fn ksm_do_scan(mut count: usize) {
+    while count > 0 {
+        let (slot, mm, start) = {
+            let mut state = KSM_STATE.lock_irqsave();
+            if state.mm_slots.is_empty() {
+                return;
+            }
+            if state.scan_slot >= state.mm_slots.len() {
+                state.finish_full_scan();
+            }
+            let slot = state.scan_slot;
+            (slot, state.mm_slots[slot].mm.clone(), state.scan_addr)
+        };
+
+        let mm = match mm.upgrade() {
+            Some(mm) => mm,
+            None => {
+                // 地址空间已经被释放。只有ksmd会删除mm_slots中的元素，下标不会失效
+                let mut state = KSM_STATE.lock_irqsave();
+                state.mm_slots.remove(slot);
+                state.scan_addr = VirtAddr::new(0);
+                continue;
+            }
+        };
+
+        let next = scan_address_space(&mm, slot, start, &mut count);
+        let mut state = KSM_STATE.lock_irqsave();
+        match next {
+            Some(vaddr) => state.scan_addr = vaddr,
+            None => {
+                state.scan_slot += 1;
+                state.scan_addr = VirtAddr::new(0);
+            }
+        }
+    }
+}
+
+/// 把`vaddr`处的KSM页面替换为一个私有的匿名页
+///
+/// ## 参数
+///
+/// - `mapper`: 页面所在地址空间的页表，调用者需要持有该地址空间的写锁
+/// - `flags`: VMA的页表项标志
+unsafe fn break_ksm(
+    vma: &Arc<LockedVMA>,
+    mapper: &mut PageMapper,
+    vaddr: VirtAddr,
+    flags: EntryFlags<MMArch>,
+) -> Result<(), SystemError> {
+    let paddr = match mapper.translate(vaddr) {
+        Some((paddr, _)) => paddr,
+        None => return Ok(()),
+    };
+    let mut page_manager_guard = page_manager_lock_irqsave();
+    let kpage = match page_manager_guard.get(&paddr) {
+        Some(page) if matches!(page.read_irqsave().page_type(), PageType::Ksm) => page,
+        _ => return Ok(()),
+    };
+
+    let page = page_manager_guard.create_one_page(
+        PageType::Normal,
+        PageFlags::empty(),
+        mapper.allocator_mut(),
+    )?;
+    let new_paddr = page.phys_address();
+    page.write_irqsave()
+        .copy_from_slice(kpage.read_irqsave().as_slice());
+
+    match mapper.map_phys(vaddr, new_paddr, flags) {
+        Some(flush) => flush.ignore(),
+        None => {
+            page_manager_guard.remove_page(&new_paddr);
+            return Err(SystemError::ENOMEM);
+        }
+    }
+    ksm_flush_tlb();
+    page.write_irqsave().insert_vma(vma.clone());
+
+    let mut kpage_guard = kpage.write_irqsave();
+    kpage_guard.remove_vma(vma);
+    if kpage_guard.can_deallocate() {
+        drop(kpage_guard);
+        page_manager_guard.remove_page(&paddr);
+    }
+    drop(page_manager_guard);
+
+    lru_add_anon(&page, vaddr);
+    Ok(())
+}
+
+/// 拆分VMA中所有的KSM页面
+///
+/// ## 参数
+///
+/// - `mapper`: VMA所在地址空间的页表，调用者需要持有该地址空间的写锁，但不能持有VMA的锁
+unsafe fn unmerge_ksm_pages(
+    vma: &Arc<LockedVMA>,
+    mapper: &mut PageMapper,
+) -> Result<(), SystemError> {
+    let (region, vm_flags, flags) = {
+        let guard = vma.lock_irqsave();
+        (*guard.region(), *guard.vm_flags(), guard.flags())
+    };
+    let flags = flags.set_write(vm_flags.contains(VmFlags::VM_WRITE));
+    for page in region.pages() {
+        break_ksm(vma, mapper, page.virt_address(), flags)?;
+    }
+    Ok(())
+}
+
+/// 拆分所有地址空间中已经合并的页面
+fn ksm_unmerge_all() -> Result<(), SystemError> {
+    let mms = KSM_STATE
+        .lock_irqsave()
+        .mm_slots
+        .iter()
+        .filter_map(|slot| slot.mm.upgrade())
+        .collect::<Vec<_>>();
+    for mm in mms.iter() {
+        let mut guard = mm.write_irqsave();
+        let vmas = guard
+            .mappings
+            .iter_vmas()
+            .filter(|vma| {
+                vma.lock_irqsave()
+                    .vm_flags()
+                    .contains(VmFlags::VM_MERGEABLE)
+            })
+            .cloned()
+            .collect::<Vec<_>>();
+        let mapper = &mut guard.user_mapper.utable;
+        for vma in vmas.iter() {
+            unsafe { unmerge_ksm_pages(vma, mapper)? };
+        }
+    }
+
+    let mut state = KSM_STATE.lock_irqsave();
+    state.stable_tree.clear();
+    state.unstable_tree.clear();
+    for slot in state.mm_slots.iter_mut() {
+        slot.checksums.clear();
+    }
+    Ok(())
+}
+
+impl LockedVMA {
+    /// 处理MADV_MERGEABLE与MADV_UNMERGEABLE
+    ///
+    /// 共享映射、文件映射以及大页映射不能被合并，对它们设置MADV_MERGEABLE会被忽略。
+    /// 区域内的页面只会与其他VMA中内容相同的页面合并
+    ///
+    /// ## 参数
+    ///
+    /// - `mapper`: VMA所在地址空间的页表，调用者需要持有该地址空间的写锁，但不能持有VMA的锁
+    pub fn ksm_madvise(
+        self: &Arc<Self>,
+        behavior: MadvFlags,
+        mapper: &mut PageMapper,
+    ) -> Result<(), SystemError> {
+        let mut guard = self.lock_irqsave();
+        let vm_flags = *guard.vm_flags();
+        match behavior {
+            MadvFlags::MADV_MERGEABLE => {
+                if vm_flags.contains(VmFlags::VM_MERGEABLE)
+                    || vm_flags.intersects(
+                        VmFlags::VM_SHARED
+                            | VmFlags::VM_MAYSHARE
+                            | VmFlags::VM_PFNMAP
+                            | VmFlags::VM_IO
+                            | VmFlags::VM_DONTEXPAND
+                            | VmFlags::VM_HUGETLB,
+                    )
+                    || guard.vm_file().is_some()
+                {
+                    return Ok(());
+                }
+                guard.set_vm_flags(vm_flags | VmFlags::VM_MERGEABLE);
+                if let Some(mm) = guard.address_space() {
+                    ksm_enter(mm);
+                }
+            }
+            MadvFlags::MADV_UNMERGEABLE => {
+                if !vm_flags.contains(VmFlags::VM_MERGEABLE) {
+                    return Ok(());
+                }
+                drop(guard);
+                unsafe { unmerge_ksm_pages(self, mapper)? };
+                let mut guard = self.lock_irqsave();
+                let vm_flags = *guard.vm_flags() - VmFlags::VM_MERGEABLE;
+                guard.set_vm_flags(vm_flags);
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+fn wakeup_ksmd() {
+    if let Some(pcb) = unsafe { KSM_THREAD.as_ref() } {
+        let _ = ProcessManager::wakeup(pcb);
+    }
+}
+
+fn ksm_thread() -> i32 {
+    loop {
+        if ksm_run() == KsmRun::Merge {
+            ksm_do_scan(KSM_PAGES_TO_SCAN.load(Ordering::SeqCst));
+            let ms = KSM_SLEEP_MILLISECS.load(Ordering::SeqCst);
+            let _ = nanosleep(PosixTimeSpec::new(
+                (ms / 1000) as i64,
+                ((ms % 1000) * 1_000_000) as i64,
+            ));
+            continue;
+        }
+
+        let irq_guard = unsafe { CurrentIrqArch::save_and_disable_irq() };
+        if ksm_run() != KsmRun::Merge {
+            ProcessManager::mark_sleep(true).ok();
+        }
+        drop(irq_guard);
+        schedule(SchedMode::SM_NONE);
+    }
+}
+
+/// ksmd线程初始化函数
+#[unified_init(INITCALL_CORE)]
+fn ksm_thread_init() -> Result<(), SystemError> {
+    let closure = crate::process::kthread::KernelThreadClosure::StaticEmptyClosure((
+        &(ksm_thread as fn() -> i32),
+        (),
+    ));
+    let pcb =
+        crate::process::kthread::KernelThreadMechanism::create_and_run(closure, "ksmd".to_string())
+            .ok_or("")
+            .expect("create ksmd thread failed");
+    unsafe {
+        KSM_THREAD = Some(pcb);
+    }
+    Ok(())
+}
+
+/// 初始化`/sys/kernel/mm/ksm`
+#[unified_init(INITCALL_SUBSYS)]
+fn ksm_sysfs_init() -> Result<(), SystemError> {
+    let kset = KSet::new("ksm".to_string());
+    kset.register(Some(sys_kernel_mm_kset()))?;
+    sysfs_instance().create_groups(&kset.as_kobject(), &[&KsmAttrGroup])?;
+    *KSM_KSET.lock() = Some(kset);
+
+    return Ok(());
+}
+
+fn parse_usize(buf: &[u8]) -> Result<usize, SystemError> {
+    core::str::from_utf8(buf)
+        .map_err(|_| SystemError::EINVAL)?
+        .trim_matches(|c: char| c.is_whitespace() || c == '\0')
+        .parse::<usize>()
+        .map_err(|_| SystemError::EINVAL)
+}
+
+#[derive(Debug)]
+struct KsmAttrGroup;
+
+impl AttributeGroup for KsmAttrGroup {
+    fn name(&self) -> Option<&str> {
+        None
+    }
+
+    fn attrs(&self) -> &[&'static dyn Attribute] {
+        &[
+            &AttrRun,
+            &AttrPagesToScan,
+            &AttrSleepMillisecs,
+            &AttrPagesShared,
+            &AttrPagesSharing,
+            &AttrPagesUnshared,
+            &AttrPagesVolatile,
+            &AttrFullScans,
+        ]
+    }
+
+    fn is_visible(
+        &self,
+        _kobj: Arc<dyn KObject>,
+        attr: &'static dyn Attribute,
+    ) -> Option<ModeType> {
+        Some(attr.mode())
+    }
+}
+
+/// `/sys/kernel/mm/ksm/run`
+#[derive(Debug)]
+struct AttrRun;
+
+impl Attribute for AttrRun {
+    fn name(&self) -> &str {
+        "run"
+    }
+
+    fn mode(&self) -> ModeType {
+        SYSFS_ATTR_MODE_RW
+    }
+
+    fn support(&self) -> SysFSOpsSupport {
+        SysFSOpsSupport::ATTR_SHOW | SysFSOpsSupport::ATTR_STORE
+    }
+
+    fn show(&self, _kobj: Arc<dyn KObject>, buf: &mut [u8]) -> Result<usize, SystemError> {
+        return sysfs_emit_str(buf, &format!("{}\n", ksm_run() as usize));
+    }
+
+    fn store(&self, _kobj: Arc<dyn KObject>, buf: &[u8]) -> Result<usize, SystemError> {
+        let run = *KsmRun::ALL
+            .get(parse_usize(buf)?)
+            .ok_or(SystemError::EINVAL)?;
+        KSM_RUN.store(run as usize, Ordering::SeqCst);
+        match run {
+            KsmRun::Merge => wakeup_ksmd(),
+            KsmRun::Unmerge => {
+                if let Err(e) = ksm_unmerge_all() {
+                    KSM_RUN.store(KsmRun::Stop as usize, Ordering::SeqCst);
+                    return Err(e);
+                }
+            }
+            KsmRun::Stop => {}
+        }
+        return Ok(buf.len());
+    }
+}
+
+/// `/sys/kernel/mm/ksm/pages_to_scan`
+#[derive(Debug)]
+struct AttrPagesToScan;
+
+impl Attribute for AttrPagesToScan {
+    fn name(&self) -> &str {
+        "pages_to_scan"
+    }
+
+    fn mode(&self) -> ModeType {
+        SYSFS_ATTR_MODE_RW
+    }
+
+    fn support(&self) -> SysFSOpsSupport {
+        SysFSOpsSupport::ATTR_SHOW | SysFSOpsSupport::ATTR_STORE
+    }
+
+    fn show(&self, _kobj: Arc<dyn KObject>, buf: &mut [u8]) -> Result<usize, SystemError> {
+        return sysfs_emit_str(
+            buf,
+            &format!("{}\n", KSM_PAGES_TO_SCAN.load(Ordering::SeqCst)),
+        );
+    }
+
+    fn store(&self, _kobj: Arc<dyn KObject>, buf: &[u8]) -> Result<usize, SystemError> {
+        KSM_PAGES_TO_SCAN.store(parse_usize(buf)?, Ordering::SeqCst);
+        return Ok(buf.len());
+    }
+}
+
+/// `/sys/kernel/mm/ksm/sleep_millisecs`
+#[derive(Debug)]
+struct AttrSleepMillisecs;
+
+impl Attribute for AttrSleepMillisecs {
+    fn name(&self) -> &str {
+        "sleep_millisecs"
+    }
+
+    fn mode(&self) -> ModeType {
+        SYSFS_ATTR_MODE_RW
+    }
+
+    fn support(&self) -> SysFSOpsSupport {
+        SysFSOpsSupport::ATTR_SHOW | SysFSOpsSupport::ATTR_STORE
+    }
+
+    fn show(&self, _kobj: Arc<dyn KObject>, buf: &mut [u8]) -> Result<usize, SystemError> {
+        return sysfs_emit_str(
+            buf,
+            &format!("{}\n", KSM_SLEEP_MILLISECS.load(Ordering::SeqCst)),
+        );
+    }
+
+    fn store(&self, _kobj: Arc<dyn KObject>, buf: &[u8]) -> Result<usize, SystemError> {
+        KSM_SLEEP_MILLISECS.store(parse_usize(buf)?, Ordering::SeqCst);
+        return Ok(buf.len());
+    }
+}
+
+/// 统计稳定树中的KSM页面
+///
+/// ## 返回值
+///
+/// `(pages_shared, pages_sharing)`，即KSM页面的数量，以及除此之外共享这些页面的映射数量
+fn ksm_stable_stats() -> (usize, usize) {
+    let state = KSM_STATE.lock_irqsave();
+    state
+        .stable_tree
+        .values()
+        .flatten()
+        .filter_map(Weak::upgrade)
+        .map(|kpage| kpage.read_irqsave().map_count())
+        .filter(|map_count| *map_count > 0)
+        .fold((0, 0), |(shared, sharing), map_count| {
+            (shared + 1, sharing + map_count - 1)
+        })
+}
+
+/// `/sys/kernel/mm/ksm/pages_shared`
+#[derive(Debug)]
+struct AttrPagesShared;
+
+impl Attribute for AttrPagesShared {
+    fn name(&self) -> &str {
+        "pages_shared"
+    }
+
+    fn mode(&self) -> ModeType {
+        SYSFS_ATTR_MODE_RO
+    }
+
+    fn support(&self) -> SysFSOpsSupport {
+        SysFSOpsSupport::ATTR_SHOW
+    }
+
+    fn show(&self, _kobj: Arc<dyn KObject>, buf: &mut [u8]) -> Result<usize, SystemError> {
+        return sysfs_emit_str(buf, &format!("{}\n", ksm_stable_stats().0));
+    }
+}
+
+/// `/sys/kernel/mm/ksm/pages_sharing`
+///
+/// 一个KSM页面在每个VMA中最多被映射一次，因此单个`MADV_MERGEABLE`区域内部的重复页面不计入其中
+#[derive(Debug)]
+struct AttrPagesSharing;
+
+impl Attribute for AttrPagesSharing {
+    fn name(&self) -> &str {
+        "pages_sharing"
+    }
+
+    fn mode(&self) -> ModeType {
+        SYSFS_ATTR_MODE_RO
+    }
+
+    fn support(&self) -> SysFSOpsSupport {
+        SysFSOpsSupport::ATTR_SHOW
+    }
+
+    fn show(&self, _kobj: Arc<dyn KObject>, buf: &mut [u8]) -> Result<usize, SystemError> {
+        return sysfs_emit_str(buf, &format!("{}\n", ksm_stable_stats().1));
+    }
+}
+
+/// `/sys/kernel/mm/ksm/pages_unshared`
+#[derive(Debug)]
+struct AttrPagesUnshared;
+
+impl Attribute for AttrPagesUnshared {
+    fn name(&self) -> &str {
+        "pages_unshared"
+    }
+
+    fn mode(&self) -> ModeType {
+        SYSFS_ATTR_MODE_RO
+    }
+
+    fn support(&self) -> SysFSOpsSupport {
+        SysFSOpsSupport::ATTR_SHOW
+    }
+
+    fn show(&self, _kobj: Arc<dyn KObject>, buf: &mut [u8]) -> Result<usize, SystemError> {
+        let count = KSM_STATE
+            .lock_irqsave()
+            .unstable_tree
+            .values()
+            .flatten()
+            .filter(|page| page.strong_count() > 0)
+            .count();
+        return sysfs_emit_str(buf, &format!("{}\n", count));
+    }
+}
+
+/// `/sys/kernel/mm/ksm/pages_volatile`
+#[derive(Debug)]
+struct AttrPagesVolatile;
+
+impl Attribute for AttrPagesVolatile {
+    fn name(&self) -> &str {
+        "pages_volatile"
+    }
+
+    fn mode(&self) -> ModeType {
+        SYSFS_ATTR_MODE_RO
+    }
+
+    fn support(&self) -> SysFSOpsSupport {
+        SysFSOpsSupport::ATTR_SHOW
+    }
+
+    fn show(&self, _kobj: Arc<dyn KObject>, buf: &mut [u8]) -> Result<usize, SystemError> {
+        let count = KSM_STATE.lock_irqsave().last_pages_volatile;
+        return sysfs_emit_str(buf, &format!("{}\n", count));
+    }
+}
+
+/// `/sys/kernel/mm/ksm/full_scans`
+#[derive(Debug)]
+struct AttrFullScans;
+
+impl Attribute for AttrFullScans {
+    fn name(&self) -> &str {
+        "full_scans"
+    }
+
+    fn mode(&self) -> ModeType {
+        SYSFS_ATTR_MODE_RO
+    }
+
+    fn support(&self) -> SysFSOpsSupport {
+        SysFSOpsSupport::ATTR_SHOW
+    }
+
+    fn show(&self, _kobj: Arc<dyn KObject>, buf: &mut [u8]) -> Result<usize, SystemError> {
+        let count = KSM_STATE.lock_irqsave().full_scans;
+        return sysfs_emit_str(buf, &format!("{}\n", count));
+    }
+}
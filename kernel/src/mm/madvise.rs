@@ -1,43 +1,61 @@
+use alloc::sync::Arc;
 use system_error::SystemError;
 
 use crate::arch::{mm::PageMapper, MMArch};
 
-use super::{page::Flusher, syscall::MadvFlags, ucontext::LockedVMA, VmFlags};
+use super::{
+    fault::{FaultFlags, PageFaultHandler, PageFaultMessage},
+    huge_memory::{huge_pmd_entry, madvise_collapse, thp_unmap, HPAGE_PMD_SIZE},
+    page::{
+        page_manager_lock_irqsave, page_reclaimer_lock_irqsave, Flusher, PageFlags, PageFlush,
+        PageType,
+    },
+    swap::{pageout_anon_page, swap_entry_at, swap_free, swap_in_page, take_swap_entry},
+    syscall::MadvFlags,
+    ucontext::{LockedVMA, VMA},
+    MemoryManagementArch, VirtAddr, VmFaultReason, VmFlags,
+};
 
 impl LockedVMA {
     pub fn do_madvise(
-        &self,
+        self: &Arc<Self>,
         behavior: MadvFlags,
-        _mapper: &mut PageMapper,
-        _flusher: impl Flusher<MMArch>,
+        mapper: &mut PageMapper,
+        flusher: impl Flusher<MMArch>,
     ) -> Result<(), SystemError> {
+        // 这些操作需要处理缺页或者读入页面，不能持有VMA的锁
+        match behavior {
+            MadvFlags::MADV_WILLNEED => return self.madvise_willneed(mapper),
+            MadvFlags::MADV_POPULATE_READ | MadvFlags::MADV_POPULATE_WRITE => {
+                return self.populate(behavior == MadvFlags::MADV_POPULATE_WRITE, mapper);
+            }
+            MadvFlags::MADV_MERGEABLE | MadvFlags::MADV_UNMERGEABLE => {
+                return self.ksm_madvise(behavior, mapper);
+            }
+            _ => {}
+        }
+
         //TODO https://code.dragonos.org.cn/xref/linux-6.6.21/mm/madvise.c?fi=madvise#do_madvise
         let mut vma = self.lock_irqsave();
         let mut new_flags = *vma.vm_flags();
         match behavior {
-            MadvFlags::MADV_REMOVE => {
-                // TODO
-            }
-
-            MadvFlags::MADV_WILLNEED => {
-                // TODO
-            }
+            MadvFlags::MADV_REMOVE => self.madvise_remove(&vma, mapper, flusher)?,
 
-            MadvFlags::MADV_COLD => {
-                // TODO
+            MadvFlags::MADV_DONTNEED | MadvFlags::MADV_DONTNEED_LOCKED => {
+                if vma.vm_flags().contains(VmFlags::VM_PFNMAP)
+                    || (behavior == MadvFlags::MADV_DONTNEED
+                        && vma.vm_flags().contains(VmFlags::VM_LOCKED))
+                {
+                    return Err(SystemError::EINVAL);
+                }
+                self.zap_pages(&vma, mapper, flusher);
             }
 
-            MadvFlags::MADV_PAGEOUT => {
-                // TODO
-            }
+            MadvFlags::MADV_COLD => self.madvise_cold_or_pageout(&vma, mapper, flusher, false)?,
 
-            MadvFlags::MADV_FREE => {
-                // TODO
-            }
+            MadvFlags::MADV_PAGEOUT => self.madvise_cold_or_pageout(&vma, mapper, flusher, true)?,
 
-            MadvFlags::MADV_POPULATE_READ | MadvFlags::MADV_POPULATE_WRITE => {
-                // TODO
-            }
+            MadvFlags::MADV_FREE => self.madvise_free(&vma, mapper, flusher)?,
 
             MadvFlags::MADV_NORMAL => {
                 new_flags = new_flags & !VmFlags::VM_RAND_READ & !VmFlags::VM_SEQ_READ
@@ -71,14 +89,281 @@ impl LockedVMA {
             //MADV_DODUMP不支持巨页映射，后续需要添加判断条件
             MadvFlags::MADV_DODUMP => new_flags &= !VmFlags::VM_DONTDUMP,
 
-            MadvFlags::MADV_MERGEABLE | MadvFlags::MADV_UNMERGEABLE => {}
+            MadvFlags::MADV_HUGEPAGE => {
+                new_flags = (new_flags & !VmFlags::VM_NOHUGEPAGE) | VmFlags::VM_HUGEPAGE
+            }
 
-            MadvFlags::MADV_HUGEPAGE | MadvFlags::MADV_NOHUGEPAGE => {}
+            MadvFlags::MADV_NOHUGEPAGE => {
+                new_flags = (new_flags & !VmFlags::VM_HUGEPAGE) | VmFlags::VM_NOHUGEPAGE
+            }
 
-            MadvFlags::MADV_COLLAPSE => {}
+            MadvFlags::MADV_COLLAPSE => unsafe { madvise_collapse(self, &vma, mapper, flusher)? },
             _ => {}
         }
         vma.set_vm_flags(new_flags);
         Ok(())
     }
+
+    /// MADV_WILLNEED: 预读文件映射对应的页缓存，或者把匿名映射中被换出的页面读回内存
+    fn madvise_willneed(self: &Arc<Self>, mapper: &mut PageMapper) -> Result<(), SystemError> {
+        let (region, file, file_pgoff) = {
+            let vma = self.lock_irqsave();
+            (*vma.region(), vma.vm_file(), vma.file_page_offset())
+        };
+
+        if let Some(file) = file {
+            if let Some(page_cache) = file.inode().page_cache() {
+                page_cache
+                    .lock_irqsave()
+                    .readahead(file_pgoff.unwrap_or(0), region.size() >> MMArch::PAGE_SHIFT)?;
+            }
+            return Ok(());
+        }
+
+        for page in region.pages() {
+            let vaddr = page.virt_address();
+            if let Some(entry) = swap_entry_at(mapper, vaddr) {
+                unsafe { swap_in_page(mapper, self, vaddr, entry)? };
+            }
+        }
+        Ok(())
+    }
+
+    /// 通过缺页处理预先建立VMA内所有页面的映射，用于MADV_POPULATE_READ/MADV_POPULATE_WRITE和mlock
+    ///
+    /// ## 参数
+    ///
+    /// - `write`: 是否以写的方式建立映射，写时拷贝的页面会被提前拷贝
+    /// - `mapper`: 页表映射器，调用者不能持有VMA的锁
+    pub fn populate(
+        self: &Arc<Self>,
+        write: bool,
+        mapper: &mut PageMapper,
+    ) -> Result<(), SystemError> {
+        let (region, vm_flags) = {
+            let vma = self.lock_irqsave();
+            (*vma.region(), *vma.vm_flags())
+        };
+        let required = if write {
+            VmFlags::VM_WRITE
+        } else {
+            VmFlags::VM_READ
+        };
+        if vm_flags.intersects(VmFlags::VM_PFNMAP | VmFlags::VM_IO) || !vm_flags.contains(required)
+        {
+            return Err(SystemError::EINVAL);
+        }
+
+        let fault_flags = if write {
+            FaultFlags::FAULT_FLAG_WRITE
+        } else {
+            FaultFlags::empty()
+        };
+        let mut vaddr = region.start();
+        while vaddr < region.end() {
+            // 跳过已经建立映射的大页
+            let haddr = VirtAddr::new(vaddr.data() & !(HPAGE_PMD_SIZE - 1));
+            if let Some(entry) = huge_pmd_entry(mapper, haddr) {
+                if !write || entry.write() {
+                    vaddr = haddr + HPAGE_PMD_SIZE;
+                    continue;
+                }
+            }
+
+            let populated = mapper
+                .translate(vaddr)
+                .is_some_and(|(_, flags)| !write || flags.has_write());
+            if !populated {
+                let message = PageFaultMessage::new(self.clone(), vaddr, fault_flags, mapper);
+                let fault = unsafe { PageFaultHandler::handle_mm_fault(message) };
+                if fault.contains(VmFaultReason::VM_FAULT_OOM) {
+                    return Err(SystemError::ENOMEM);
+                }
+                if fault.intersects(
+                    VmFaultReason::VM_FAULT_SIGBUS
+                        | VmFaultReason::VM_FAULT_SIGSEGV
+                        | VmFaultReason::VM_FAULT_HWPOISON,
+                ) {
+                    return Err(SystemError::EFAULT);
+                }
+            }
+            vaddr += MMArch::PAGE_SIZE;
+        }
+        Ok(())
+    }
+
+    /// MADV_REMOVE: 解除映射并释放共享内存文件中对应范围的页面
+    fn madvise_remove(
+        &self,
+        vma: &VMA,
+        mapper: &mut PageMapper,
+        flusher: impl Flusher<MMArch>,
+    ) -> Result<(), SystemError> {
+        if vma.vm_flags().contains(VmFlags::VM_LOCKED) {
+            return Err(SystemError::EINVAL);
+        }
+        if !vma
+            .vm_flags()
+            .contains(VmFlags::VM_SHARED | VmFlags::VM_WRITE)
+        {
+            return Err(SystemError::EACCES);
+        }
+        let file = vma.vm_file().ok_or(SystemError::EINVAL)?;
+        // 只有没有后备存储的共享内存文件支持在文件中打洞
+        let page_cache = file
+            .inode()
+            .page_cache()
+            .filter(|page_cache| page_cache.lock_irqsave().is_shmem())
+            .ok_or(SystemError::EOPNOTSUPP_OR_ENOTSUP)?;
+
+        self.zap_pages(vma, mapper, flusher);
+
+        let start = vma.file_page_offset().unwrap_or(0);
+        let end = start + (vma.region().size() >> MMArch::PAGE_SHIFT);
+        page_cache.lock_irqsave().remove_range(start, end);
+        Ok(())
+    }
+
+    /// MADV_COLD/MADV_PAGEOUT: 把只被当前VMA映射的页面标记为最久未使用，
+    /// `pageout`为true时立即回收这些页面
+    fn madvise_cold_or_pageout(
+        &self,
+        vma: &VMA,
+        mapper: &mut PageMapper,
+        mut flusher: impl Flusher<MMArch>,
+        pageout: bool,
+    ) -> Result<(), SystemError> {
+        if vma
+            .vm_flags()
+            .intersects(VmFlags::VM_LOCKED | VmFlags::VM_PFNMAP | VmFlags::VM_HUGETLB)
+        {
+            return Err(SystemError::EINVAL);
+        }
+
+        for page in vma.region().pages() {
+            let vaddr = page.virt_address();
+            // 大页的页表项不在最后一级页表中，get_table会返回None
+            let table = match mapper.get_table(vaddr, 0) {
+                Some(table) => table,
+                None => continue,
+            };
+            let i = table.index_of(vaddr).unwrap();
+            let mut entry = unsafe { table.entry(i) }.unwrap();
+            if !entry.present() {
+                continue;
+            }
+            let paddr = match entry.address() {
+                Ok(paddr) => paddr,
+                Err(_) => continue,
+            };
+            let page = match page_manager_lock_irqsave().get(&paddr) {
+                Some(page) => page,
+                None => continue,
+            };
+            let page_type = {
+                let page_guard = page.read_irqsave();
+                if page_guard.map_count() != 1
+                    || page_guard.flags().contains(PageFlags::PG_UNEVICTABLE)
+                {
+                    continue;
+                }
+                page_guard.page_type().clone()
+            };
+
+            if pageout {
+                match page_type {
+                    PageType::Anon(_) => {
+                        if unsafe { pageout_anon_page(mapper, self, vaddr) }.is_ok() {
+                            continue;
+                        }
+                    }
+                    PageType::File(_) => {
+                        // 文件页解除映射后由页面回收器负责回写和释放
+                        let dirty = entry.flags().has_flag(MMArch::ENTRY_FLAG_DIRTY);
+                        let (_, _, flush) = unsafe { mapper.unmap_phys(vaddr, false) }.unwrap();
+                        flusher.consume(flush);
+
+                        let mut page_guard = page.write_irqsave();
+                        if dirty {
+                            page_guard.add_flags(PageFlags::PG_DIRTY);
+                        }
+                        page_guard.remove_vma(self);
+                        drop(page_guard);
+                        page_reclaimer_lock_irqsave().deactivate_page(&paddr);
+                        continue;
+                    }
+                    _ => {}
+                }
+            }
+
+            // 清除访问位，页面回收时不再认为页面最近被访问过
+            entry.set_flags(entry.flags().set_access(false));
+            unsafe { table.set_entry(i, entry) };
+            flusher.consume(PageFlush::new(vaddr));
+            page_reclaimer_lock_irqsave().deactivate_page(&paddr);
+        }
+        Ok(())
+    }
+
+    /// MADV_FREE: 把私有匿名页标记为可以延迟释放
+    ///
+    /// 页面被清除PG_SWAPBACKED标志和页表项的脏位，页面回收时如果页面没有被再次写入，
+    /// 则直接丢弃而不换出，否则页面被当作普通匿名页处理
+    fn madvise_free(
+        &self,
+        vma: &VMA,
+        mapper: &mut PageMapper,
+        mut flusher: impl Flusher<MMArch>,
+    ) -> Result<(), SystemError> {
+        if vma.vm_file().is_some()
+            || vma
+                .vm_flags()
+                .intersects(VmFlags::VM_SHARED | VmFlags::VM_LOCKED | VmFlags::VM_PFNMAP)
+        {
+            return Err(SystemError::EINVAL);
+        }
+
+        // 透明大页不会被换出，直接释放
+        thp_unmap(*vma.region(), mapper, &mut flusher);
+
+        for page in vma.region().pages() {
+            let vaddr = page.virt_address();
+            // 被换出的页面不需要再读回，直接释放交换槽位
+            if let Some(entry) = unsafe { take_swap_entry(mapper, vaddr) } {
+                swap_free(entry);
+                continue;
+            }
+
+            let table = match mapper.get_table(vaddr, 0) {
+                Some(table) => table,
+                None => continue,
+            };
+            let i = table.index_of(vaddr).unwrap();
+            let mut entry = unsafe { table.entry(i) }.unwrap();
+            if !entry.present() {
+                continue;
+            }
+            let paddr = match entry.address() {
+                Ok(paddr) => paddr,
+                Err(_) => continue,
+            };
+            let page = match page_manager_lock_irqsave().get(&paddr) {
+                Some(page) => page,
+                None => continue,
+            };
+
+            let mut page_guard = page.write_irqsave();
+            if !matches!(page_guard.page_type(), PageType::Anon(_)) || page_guard.map_count() != 1 {
+                continue;
+            }
+            page_guard.remove_flags(PageFlags::PG_SWAPBACKED | PageFlags::PG_DIRTY);
+            drop(page_guard);
+
+            entry.set_flags(entry.flags().set_dirty(false).set_access(false));
+            unsafe { table.set_entry(i, entry) };
+            flusher.consume(PageFlush::new(vaddr));
+            page_reclaimer_lock_irqsave().deactivate_page(&paddr);
+        }
+        Ok(())
+    }
 }
@@ -0,0 +1,93 @@
+//! memfd_create支持
+//!
+//! memfd是由页缓存保存数据的匿名文件，可以被ftruncate、mmap(MAP_SHARED)以及在进程之间传递。
+//! 使用MFD_ALLOW_SEALING创建的memfd可以通过fcntl(F_ADD_SEALS)添加seal，限制文件之后的修改。
+
+use alloc::format;
+use system_error::SystemError;
+
+use crate::{
+    filesystem::{
+        hugetlbfs::hugetlb_file_setup,
+        tmpfs::shmem_file_setup,
+        vfs::{
+            fcntl::SealFlags,
+            file::{File, FileMode},
+        },
+    },
+    process::ProcessManager,
+    syscall::{user_access::check_and_clone_cstr, Syscall},
+};
+
+use super::hugetlb::{hstate_from_flags, HUGETLB_FLAG_ENCODE_MASK, HUGETLB_FLAG_ENCODE_SHIFT};
+
+/// memfd名称的最大长度，不包括"memfd:"前缀
+const MFD_NAME_MAX_LEN: usize = 255 - "memfd:".len();
+
+bitflags! {
+    /// memfd_create的flags参数
+    pub struct MemfdFlags: u32 {
+        /// 为文件描述符设置close-on-exec
+        const MFD_CLOEXEC = 0x0001;
+        /// 允许为文件添加seal
+        const MFD_ALLOW_SEALING = 0x0002;
+        /// 在hugetlbfs中创建文件，大页大小编码在高位中
+        const MFD_HUGETLB = 0x0004;
+    }
+}
+
+impl Syscall {
+    /// # 创建一个匿名文件
+    ///
+    /// ## 参数
+    ///
+    /// - `name`: 文件名，只用于调试，可以重复
+    /// - `flags`: MemfdFlags
+    ///
+    /// ## 返回值
+    ///
+    /// 新文件的文件描述符
+    pub fn memfd_create(name: *const u8, flags: u32) -> Result<usize, SystemError> {
+        let hugetlb_mask = (HUGETLB_FLAG_ENCODE_MASK << HUGETLB_FLAG_ENCODE_SHIFT) as u32;
+        let memfd_flags =
+            MemfdFlags::from_bits(flags & !hugetlb_mask).ok_or(SystemError::EINVAL)?;
+        // 只有MFD_HUGETLB可以指定大页大小
+        if flags & hugetlb_mask != 0 && !memfd_flags.contains(MemfdFlags::MFD_HUGETLB) {
+            return Err(SystemError::EINVAL);
+        }
+
+        // 多读一个字节，用于判断名称是否过长
+        let name = check_and_clone_cstr(name, Some(MFD_NAME_MAX_LEN + 1))?;
+        let name = name.to_str().map_err(|_| SystemError::EINVAL)?;
+        if name.len() > MFD_NAME_MAX_LEN {
+            return Err(SystemError::EINVAL);
+        }
+        let name = format!("memfd:{}", name);
+
+        let mut mode = FileMode::O_RDWR;
+        if memfd_flags.contains(MemfdFlags::MFD_CLOEXEC) {
+            mode |= FileMode::O_CLOEXEC;
+        }
+
+        let file = if memfd_flags.contains(MemfdFlags::MFD_HUGETLB) {
+            // TODO hugetlbfs暂不支持seal
+            if memfd_flags.contains(MemfdFlags::MFD_ALLOW_SEALING) {
+                return Err(SystemError::EINVAL);
+            }
+            let hstate = hstate_from_flags(flags as usize).ok_or(SystemError::EINVAL)?;
+            File::new(hugetlb_file_setup(hstate, 0)?.inode(), mode)?
+        } else {
+            let seals = if memfd_flags.contains(MemfdFlags::MFD_ALLOW_SEALING) {
+                SealFlags::empty()
+            } else {
+                SealFlags::F_SEAL_SEAL
+            };
+            shmem_file_setup(&name, seals, mode)?
+        };
+
+        let binding = ProcessManager::current_pcb().fd_table();
+        let mut fd_table_guard = binding.write();
+        let fd = fd_table_guard.alloc_fd(file, None).map(|x| x as usize);
+        return fd;
+    }
+}
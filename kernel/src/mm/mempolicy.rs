@@ -0,0 +1,420 @@
+//! NUMA内存策略
+//!
+//! 内存策略决定了页帧在哪些节点上分配。进程可以通过`set_mempolicy`设置自己的内存策略，
+//! 也可以通过`mbind`为一段地址范围设置内存策略。处理缺页异常时，如果缺页地址所在的VMA设置了内存策略，
+//! 则使用VMA的策略，否则使用进程的策略。
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use alloc::{sync::Arc, vec::Vec};
+use system_error::SystemError;
+
+use crate::{
+    arch::{CurrentIrqArch, MMArch},
+    exception::InterruptArch,
+    process::{ProcessControlBlock, ProcessFlags, ProcessManager},
+    syscall::user_access::{UserBufferReader, UserBufferWriter},
+};
+
+use super::{
+    gfp::GfpFlags,
+    huge_memory::thp_split_boundaries,
+    hugetlb::hugetlb_check_split,
+    numa::{node_online_map, nr_node_ids, numa_enabled, numa_node_id, phys_to_nid, NodeMask},
+    ucontext::InnerAddressSpace,
+    MemoryManagementArch, VirtAddr, VirtRegion,
+};
+
+/// 使用上一级的策略（VMA使用进程的策略，进程在本地节点分配）
+pub const MPOL_DEFAULT: u32 = 0;
+/// 优先在指定节点分配
+pub const MPOL_PREFERRED: u32 = 1;
+/// 只在指定的节点集合中分配
+pub const MPOL_BIND: u32 = 2;
+/// 在指定的节点集合中轮流分配
+pub const MPOL_INTERLEAVE: u32 = 3;
+/// 在本地节点分配
+pub const MPOL_LOCAL: u32 = 4;
+const MPOL_MAX: u32 = 5;
+
+bitflags! {
+    /// 与策略模式一起传入的标志
+    pub struct MemPolicyModeFlags: u32 {
+        /// 节点集合是物理节点编号，不随cpuset变化
+        const MPOL_F_STATIC_NODES = 1 << 15;
+        /// 节点集合是相对于允许使用的节点的编号
+        const MPOL_F_RELATIVE_NODES = 1 << 14;
+    }
+
+    /// get_mempolicy的flags参数
+    pub struct GetMemPolicyFlags: u32 {
+        /// 返回节点编号而不是策略
+        const MPOL_F_NODE = 1 << 0;
+        /// 查询`addr`所在地址范围的策略
+        const MPOL_F_ADDR = 1 << 1;
+        /// 返回允许使用的节点集合
+        const MPOL_F_MEMS_ALLOWED = 1 << 2;
+    }
+
+    /// mbind的flags参数
+    pub struct MbindFlags: u32 {
+        /// 地址范围中存在不符合策略的页面时返回EIO
+        const MPOL_MF_STRICT = 1 << 0;
+        /// 迁移只被当前进程映射的、不符合策略的页面
+        const MPOL_MF_MOVE = 1 << 1;
+        /// 迁移所有不符合策略的页面
+        const MPOL_MF_MOVE_ALL = 1 << 2;
+    }
+}
+
+/// 内存策略
+#[derive(Debug)]
+pub struct MemPolicy {
+    mode: u32,
+    flags: MemPolicyModeFlags,
+    /// 策略使用的节点，对MPOL_PREFERRED只有一个节点，对MPOL_LOCAL为空
+    nodes: NodeMask,
+    /// MPOL_INTERLEAVE上一次分配使用的节点
+    il_prev: AtomicUsize,
+}
+
+impl MemPolicy {
+    /// 创建内存策略
+    ///
+    /// ## 参数
+    ///
+    /// - `mode`: 策略模式
+    /// - `flags`: 模式标志
+    /// - `nodes`: 用户传入的节点集合
+    ///
+    /// ## 返回值
+    ///
+    /// - `Ok(None)`: MPOL_DEFAULT策略
+    /// - `Ok(Some(policy))`: 创建的策略
+    /// - `Err(SystemError::EINVAL)`: 模式与节点集合不匹配
+    pub fn new(
+        mode: u32,
+        flags: MemPolicyModeFlags,
+        nodes: NodeMask,
+    ) -> Result<Option<Arc<Self>>, SystemError> {
+        if mode == MPOL_DEFAULT {
+            if !nodes.is_empty() {
+                return Err(SystemError::EINVAL);
+            }
+            return Ok(None);
+        }
+
+        let mut mode = mode;
+        if nodes.is_empty() {
+            // 空节点集合的MPOL_PREFERRED等价于MPOL_LOCAL
+            if mode == MPOL_PREFERRED && flags.is_empty() {
+                mode = MPOL_LOCAL;
+            } else if mode != MPOL_LOCAL {
+                return Err(SystemError::EINVAL);
+            }
+        } else if mode == MPOL_LOCAL {
+            return Err(SystemError::EINVAL);
+        }
+
+        let online = node_online_map();
+        let mut nodes = if flags.contains(MemPolicyModeFlags::MPOL_F_RELATIVE_NODES) {
+            // 第i个相对节点映射为第(i % 在线节点数)个在线节点
+            let mut relative = NodeMask::new();
+            for i in nodes.iter() {
+                if let Some(nid) = online.nth(i % online.weight()) {
+                    relative.set(nid);
+                }
+            }
+            relative
+        } else {
+            nodes.intersection(&online)
+        };
+        if mode != MPOL_LOCAL && nodes.is_empty() {
+            return Err(SystemError::EINVAL);
+        }
+        if mode == MPOL_PREFERRED {
+            let first = nodes.first().unwrap();
+            nodes = NodeMask::new();
+            nodes.set(first);
+        }
+
+        Ok(Some(Arc::new(Self {
+            mode,
+            flags,
+            nodes,
+            il_prev: AtomicUsize::new(MAX_IL_PREV),
+        })))
+    }
+
+    pub fn mode(&self) -> u32 {
+        self.mode
+    }
+
+    pub fn nodes(&self) -> NodeMask {
+        self.nodes
+    }
+
+    /// 策略模式以及模式标志，用于返回给用户
+    pub fn mode_with_flags(&self) -> u32 {
+        self.mode | self.flags.bits()
+    }
+
+    /// 获取分配页帧时的首选节点和允许使用的节点
+    pub fn nodes_for_alloc(&self) -> (usize, NodeMask) {
+        let local = numa_node_id();
+        match self.mode {
+            MPOL_PREFERRED => (self.nodes.first().unwrap_or(local), node_online_map()),
+            MPOL_BIND => {
+                let preferred = if self.nodes.test(local) {
+                    local
+                } else {
+                    self.nodes.first().unwrap_or(local)
+                };
+                (preferred, self.nodes)
+            }
+            MPOL_INTERLEAVE => (self.interleave_next(), node_online_map()),
+            _ => (local, node_online_map()),
+        }
+    }
+
+    /// 轮流选择MPOL_INTERLEAVE的下一个节点
+    fn interleave_next(&self) -> usize {
+        let prev = self.il_prev.load(Ordering::Relaxed);
+        let next = self.nodes.next_wrap(prev).unwrap_or(0);
+        self.il_prev.store(next, Ordering::Relaxed);
+        next
+    }
+
+    /// MPOL_INTERLEAVE下一次分配将使用的节点
+    pub fn interleave_peek(&self) -> usize {
+        self.nodes
+            .next_wrap(self.il_prev.load(Ordering::Relaxed))
+            .unwrap_or(0)
+    }
+
+    /// 节点`nid`上的页面是否符合策略
+    fn allows(&self, nid: usize) -> bool {
+        match self.mode {
+            MPOL_BIND | MPOL_INTERLEAVE | MPOL_PREFERRED => self.nodes.test(nid),
+            _ => true,
+        }
+    }
+}
+
+/// `il_prev`的初始值，使第一次轮流分配从编号最小的节点开始
+const MAX_IL_PREV: usize = usize::MAX >> 1;
+
+/// 获取当前进程分配页帧时的首选节点和允许使用的节点
+///
+/// 以下情况不访问进程的内存策略，直接在本地节点分配：
+/// - 只有一个节点，或者进程管理器尚未初始化
+/// - 不能睡眠的分配（GFP_ATOMIC），以及中断被关闭时的分配（例如处于中断上下文）
+/// - 内核线程的分配
+///
+/// ## 参数
+///
+/// - `gfp`: 分配标志
+pub fn policy_nodes(gfp: GfpFlags) -> (usize, NodeMask) {
+    let local = (numa_node_id(), node_online_map());
+    if !numa_enabled()
+        || !ProcessManager::initialized()
+        || gfp.contains(GfpFlags::__GFP_ATOMIC)
+        || !CurrentIrqArch::is_irq_enabled()
+    {
+        return local;
+    }
+    let pcb = ProcessManager::current_pcb();
+    if pcb.flags().contains(ProcessFlags::KTHREAD) {
+        return local;
+    }
+    match pcb.alloc_mempolicy() {
+        Some(policy) => policy.nodes_for_alloc(),
+        None => local,
+    }
+}
+
+/// 处理缺页异常期间，让页帧分配使用VMA的内存策略，离开作用域时恢复
+pub struct FaultPolicyGuard {
+    pcb: Arc<ProcessControlBlock>,
+    prev: Option<Arc<MemPolicy>>,
+}
+
+impl FaultPolicyGuard {
+    pub fn new(pcb: Arc<ProcessControlBlock>, policy: Arc<MemPolicy>) -> Self {
+        let prev = pcb.swap_fault_mempolicy(Some(policy));
+        Self { pcb, prev }
+    }
+}
+
+impl Drop for FaultPolicyGuard {
+    fn drop(&mut self) {
+        self.pcb.swap_fault_mempolicy(self.prev.take());
+    }
+}
+
+/// 解析策略模式参数
+///
+/// ## 返回值
+///
+/// 策略模式以及模式标志
+pub fn parse_mode(mode: u32) -> Result<(u32, MemPolicyModeFlags), SystemError> {
+    let flags = MemPolicyModeFlags::from_bits_truncate(mode);
+    let mode = mode & !MemPolicyModeFlags::all().bits();
+    if mode >= MPOL_MAX || flags.is_all() {
+        return Err(SystemError::EINVAL);
+    }
+    if mode == MPOL_LOCAL && !flags.is_empty() {
+        return Err(SystemError::EINVAL);
+    }
+    Ok((mode, flags))
+}
+
+/// 从用户空间读取节点集合
+///
+/// ## 参数
+///
+/// - `nmask`: 用户空间的节点位图
+/// - `maxnode`: 位图的位数加1
+pub fn get_nodes(nmask: *const u64, maxnode: usize) -> Result<NodeMask, SystemError> {
+    let maxnode = maxnode.saturating_sub(1);
+    if maxnode == 0 || nmask.is_null() {
+        return Ok(NodeMask::new());
+    }
+    if maxnode > MMArch::PAGE_SIZE * 8 {
+        return Err(SystemError::EINVAL);
+    }
+    let nlongs = maxnode.div_ceil(64);
+    let reader = UserBufferReader::new(nmask, nlongs * core::mem::size_of::<u64>(), true)?;
+    let mut words: Vec<u64> = alloc::vec![0; nlongs];
+    reader.copy_from_user(&mut words, 0)?;
+    if maxnode % 64 != 0 {
+        words[nlongs - 1] &= (1u64 << (maxnode % 64)) - 1;
+    }
+
+    let mask = NodeMask::from_bits_truncate(words[0]);
+    // 超出系统支持的节点数量的位不能被设置
+    if words[0] != mask.bits() || words[1..].iter().any(|w| *w != 0) {
+        return Err(SystemError::EINVAL);
+    }
+    Ok(mask)
+}
+
+/// 把节点集合写入用户空间
+///
+/// ## 参数
+///
+/// - `nmask`: 用户空间的节点位图，为空时不写入
+/// - `maxnode`: 位图的位数
+/// - `nodes`: 节点集合
+pub fn copy_nodes_to_user(
+    nmask: *mut u64,
+    maxnode: usize,
+    nodes: NodeMask,
+) -> Result<(), SystemError> {
+    if nmask.is_null() {
+        return Ok(());
+    }
+    if maxnode < nr_node_ids() {
+        return Err(SystemError::EINVAL);
+    }
+    let nlongs = maxnode.saturating_sub(1).div_ceil(64);
+    if nlongs == 0 {
+        return Ok(());
+    }
+    let mut words: Vec<u64> = alloc::vec![0; nlongs];
+    words[0] = nodes.bits();
+    let mut writer = UserBufferWriter::new(nmask, nlongs * core::mem::size_of::<u64>(), true)?;
+    writer.copy_to_user(&words, 0)?;
+    Ok(())
+}
+
+impl InnerAddressSpace {
+    /// 为`region`范围内的VMA设置内存策略
+    ///
+    /// ## 参数
+    ///
+    /// - `region`: 地址范围，必须全部被VMA覆盖
+    /// - `policy`: 内存策略，为None时恢复为MPOL_DEFAULT
+    /// - `flags`: mbind的标志
+    ///
+    /// ## 返回值
+    ///
+    /// 指定了MPOL_MF_STRICT并且地址范围中存在不符合策略的页面时，返回`Err(SystemError::EIO)`。
+    /// 目前不支持页面迁移，MPOL_MF_MOVE和MPOL_MF_MOVE_ALL不会移动已有的页面。
+    pub fn mbind(
+        &mut self,
+        region: VirtRegion,
+        policy: Option<Arc<MemPolicy>>,
+        flags: MbindFlags,
+    ) -> Result<(), SystemError> {
+        let regions = self.mappings.conflicts(region).collect::<Vec<_>>();
+        let covered: usize = regions
+            .iter()
+            .filter_map(|vma| vma.lock_irqsave().region().intersect(&region))
+            .map(|r| r.size())
+            .sum();
+        if covered != region.size() {
+            return Err(SystemError::EFAULT);
+        }
+
+        let mapper = &mut self.user_mapper.utable;
+        hugetlb_check_split(&self.mappings, region)?;
+        thp_split_boundaries(&self.mappings, region, mapper)?;
+
+        for r in regions {
+            let r = *r.lock_irqsave().region();
+            let r = self.mappings.remove_vma(&r).unwrap();
+
+            let intersection = r.lock_irqsave().region().intersect(&region).unwrap();
+            let split_result = r
+                .extract(intersection, mapper)
+                .expect("Failed to extract VMA");
+
+            if let Some(before) = split_result.prev {
+                self.mappings.insert_vma(before);
+            }
+            if let Some(after) = split_result.after {
+                self.mappings.insert_vma(after);
+            }
+
+            r.lock_irqsave().set_vm_policy(policy.clone());
+            self.mappings.insert_vma(r);
+        }
+
+        if flags.contains(MbindFlags::MPOL_MF_STRICT) {
+            if let Some(policy) = policy {
+                let misplaced = region.pages().any(|page| {
+                    mapper
+                        .translate(page.virt_address())
+                        .is_some_and(|(paddr, _)| !policy.allows(phys_to_nid(paddr)))
+                });
+                if misplaced {
+                    return Err(SystemError::EIO);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// 获取`vaddr`所在VMA的内存策略以及`vaddr`处的页面所在的节点
+    ///
+    /// ## 返回值
+    ///
+    /// (VMA的内存策略, 页面所在的节点)，页面尚未分配时返回按照策略下一次分配将使用的节点
+    pub fn vma_policy_at(
+        &self,
+        vaddr: VirtAddr,
+    ) -> Result<(Option<Arc<MemPolicy>>, usize), SystemError> {
+        let vma = self.mappings.contains(vaddr).ok_or(SystemError::EFAULT)?;
+        let policy = vma.lock_irqsave().vm_policy();
+        let nid = match self.user_mapper.utable.translate(vaddr) {
+            Some((paddr, _)) => phys_to_nid(paddr),
+            None => match policy.as_ref() {
+                Some(p) if p.mode() == MPOL_INTERLEAVE => p.interleave_peek(),
+                Some(p) => p.nodes_for_alloc().0,
+                None => numa_node_id(),
+            },
+        };
+        Ok((policy, nid))
+    }
+}
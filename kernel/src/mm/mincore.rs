@@ -0,0 +1,52 @@
+use alloc::vec::Vec;
+use system_error::SystemError;
+
+use crate::arch::{mm::PageMapper, MMArch};
+
+use super::{ucontext::InnerAddressSpace, MemoryManagementArch, VirtAddr, VirtRegion};
+
+/// 判断虚拟地址所在的页面是否已经映射到物理内存，包括大页
+fn page_present(mapper: &PageMapper, vaddr: VirtAddr) -> bool {
+    (0..MMArch::PAGE_LEVELS - 1).any(|level| {
+        mapper.get_entry(vaddr, level).is_some_and(|entry| {
+            entry.present()
+                && (level == 0
+                    || (MMArch::ENTRY_FLAG_HUGE_PAGE != 0
+                        && entry.flags().has_flag(MMArch::ENTRY_FLAG_HUGE_PAGE)))
+        })
+    })
+}
+
+impl InnerAddressSpace {
+    /// 查询`region`范围内的页面是否驻留在内存中
+    ///
+    /// 对于文件映射，没有被映射但是已经在页缓存中的页面也视为驻留在内存中
+    ///
+    /// ## 返回值
+    ///
+    /// - `Ok(Vec<u8>)` 每个页面对应一个字节，最低位为1表示页面驻留在内存中
+    /// - `Err(SystemError::ENOMEM)` 区域中有未被映射的部分
+    pub fn mincore(&self, region: VirtRegion) -> Result<Vec<u8>, SystemError> {
+        let mapper = &self.user_mapper.utable;
+        let mut vec = Vec::with_capacity(region.size() >> MMArch::PAGE_SHIFT);
+        let mut vaddr = region.start();
+        while vaddr < region.end() {
+            let vma = self.mappings.contains(vaddr).ok_or(SystemError::ENOMEM)?;
+            let guard = vma.lock_irqsave();
+            let end = core::cmp::min(guard.region().end(), region.end());
+            let page_cache = guard.vm_file().and_then(|file| file.inode().page_cache());
+
+            while vaddr < end {
+                let present = page_present(mapper, vaddr)
+                    || page_cache.as_ref().is_some_and(|page_cache| {
+                        let index = guard.file_page_offset().unwrap_or(0)
+                            + ((vaddr - guard.region().start()) >> MMArch::PAGE_SHIFT);
+                        page_cache.lock_irqsave().get_page(index).is_some()
+                    });
+                vec.push(present as u8);
+                vaddr += MMArch::PAGE_SIZE;
+            }
+        }
+        Ok(vec)
+    }
+}
@@ -0,0 +1,307 @@
+//! 内存锁定(mlock)支持
+//!
+//! 被锁定的VMA带有`VM_LOCKED`标志，其中的页面在锁定时立即建立映射（`VM_LOCKONFAULT`的VMA除外），
+//! 并且不会被页面回收器换出或者回收。
+
+use alloc::{sync::Arc, vec::Vec};
+use system_error::SystemError;
+
+use crate::{
+    arch::{mm::PageMapper, MMArch},
+    process::{
+        cred::CAPFlags,
+        resource::{RLimitID, RLIM_INFINITY},
+        ProcessManager,
+    },
+};
+
+use super::{
+    huge_memory::thp_split_boundaries,
+    hugetlb::hugetlb_check_split,
+    page::{page_manager_lock_irqsave, page_reclaimer_lock_irqsave, PageType},
+    ucontext::{InnerAddressSpace, LockedVMA},
+    MemoryManagementArch, VirtAddr, VirtRegion, VmFlags,
+};
+
+/// 与内存锁定相关的VMA标志
+pub const VM_LOCKED_MASK: VmFlags =
+    VmFlags::from_bits_truncate(VmFlags::VM_LOCKED.bits() | VmFlags::VM_LOCKONFAULT.bits());
+
+bitflags! {
+    /// mlock2的flags参数
+    pub struct MlockFlags: u32 {
+        /// 只锁定已经建立映射的页面，其余页面在缺页时锁定
+        const MLOCK_ONFAULT = 0x01;
+    }
+
+    /// mlockall的flags参数
+    pub struct MclFlags: u32 {
+        /// 锁定当前已经映射的所有页面
+        const MCL_CURRENT = 1;
+        /// 锁定之后映射的所有页面
+        const MCL_FUTURE = 2;
+        /// 与MCL_CURRENT或MCL_FUTURE一起使用，页面在缺页时才被锁定
+        const MCL_ONFAULT = 4;
+    }
+}
+
+/// 获取当前进程可以锁定的内存字节数
+///
+/// ## 返回值
+///
+/// - `Ok(None)` 不受限制
+/// - `Ok(Some(limit))` 最多可以锁定`limit`字节
+/// - `Err(SystemError::EPERM)` 当前进程不允许锁定内存
+fn mlock_limit() -> Result<Option<usize>, SystemError> {
+    let pcb = ProcessManager::current_pcb();
+    if pcb.cred().has_capability(CAPFlags::CAP_IPC_LOCK) {
+        return Ok(None);
+    }
+    let limit = pcb.get_rlimit(RLimitID::Memlock).rlim_cur;
+    if limit == 0 {
+        return Err(SystemError::EPERM);
+    }
+    if limit == RLIM_INFINITY {
+        return Ok(None);
+    }
+    Ok(Some(limit as usize))
+}
+
+/// 把页面从匿名页链表中移除或者放回，被锁定的页面不会被换出
+///
+/// ## 参数
+///
+/// - `vma`: 页面所在的VMA
+/// - `mapper`: 页表映射器
+/// - `locked`: VMA是否被锁定
+fn mlock_vma_pages(vma: &LockedVMA, mapper: &PageMapper, locked: bool) {
+    let region = *vma.lock_irqsave().region();
+    for page in region.pages() {
+        let paddr = match mapper.translate(page.virt_address()) {
+            Some((paddr, _)) => paddr,
+            None => continue,
+        };
+        let page = match page_manager_lock_irqsave().get(&paddr) {
+            Some(page) => page,
+            None => continue,
+        };
+        if !matches!(page.read_irqsave().page_type(), PageType::Anon(_)) {
+            continue;
+        }
+        let mut reclaimer = page_reclaimer_lock_irqsave();
+        if locked {
+            reclaimer.remove_anon_page(&paddr);
+        } else {
+            reclaimer.insert_anon_page(paddr, &page);
+        }
+    }
+}
+
+impl InnerAddressSpace {
+    /// 地址空间中被锁定的内存字节数
+    pub fn locked_vm(&self) -> usize {
+        self.mappings
+            .iter_vmas()
+            .map(|vma| {
+                let guard = vma.lock_irqsave();
+                if guard.vm_flags().contains(VmFlags::VM_LOCKED) {
+                    guard.region().size()
+                } else {
+                    0
+                }
+            })
+            .sum()
+    }
+
+    /// 地址空间中所有映射的字节数
+    pub fn total_vm(&self) -> usize {
+        self.mappings
+            .iter_vmas()
+            .map(|vma| vma.lock_irqsave().region().size())
+            .sum()
+    }
+
+    /// 检查再锁定`len`字节的内存后是否会超过RLIMIT_MEMLOCK，用于MAP_LOCKED以及mlockall(MCL_FUTURE)之后的映射
+    pub fn mlock_future_ok(&self, len: usize) -> bool {
+        match mlock_limit() {
+            Ok(None) => true,
+            Ok(Some(limit)) => self.locked_vm() + len <= limit,
+            Err(_) => false,
+        }
+    }
+
+    /// 修改`region`范围内VMA的锁定标志
+    ///
+    /// ## 参数
+    ///
+    /// - `region`: 需要修改的区域，必须全部被VMA覆盖
+    /// - `lock_flags`: 新的锁定标志，为空时解除锁定
+    fn apply_vma_lock_flags(
+        &mut self,
+        region: VirtRegion,
+        lock_flags: VmFlags,
+    ) -> Result<(), SystemError> {
+        let regions = self.mappings.conflicts(region).collect::<Vec<_>>();
+        let covered: usize = regions
+            .iter()
+            .filter_map(|vma| vma.lock_irqsave().region().intersect(&region))
+            .map(|r| r.size())
+            .sum();
+        if covered != region.size() {
+            return Err(SystemError::ENOMEM);
+        }
+
+        let mapper = &mut self.user_mapper.utable;
+        hugetlb_check_split(&self.mappings, region)?;
+        thp_split_boundaries(&self.mappings, region, mapper)?;
+
+        for r in regions {
+            let r = *r.lock_irqsave().region();
+            let r = self.mappings.remove_vma(&r).unwrap();
+
+            let intersection = r.lock_irqsave().region().intersect(&region).unwrap();
+            let split_result = r
+                .extract(intersection, mapper)
+                .expect("Failed to extract VMA");
+
+            if let Some(before) = split_result.prev {
+                self.mappings.insert_vma(before);
+            }
+            if let Some(after) = split_result.after {
+                self.mappings.insert_vma(after);
+            }
+
+            {
+                let mut guard = r.lock_irqsave();
+                let vm_flags = (*guard.vm_flags() - VM_LOCKED_MASK) | lock_flags;
+                guard.set_vm_flags(vm_flags);
+            }
+            mlock_vma_pages(&r, mapper, !lock_flags.is_empty());
+            self.mappings.insert_vma(r);
+        }
+        Ok(())
+    }
+
+    /// 为`region`范围内被锁定的VMA建立页面映射，`VM_LOCKONFAULT`的VMA在缺页时才建立映射
+    pub fn mm_populate(&mut self, region: VirtRegion) -> Result<(), SystemError> {
+        let vmas = self
+            .mappings
+            .conflicts(region)
+            .collect::<Vec<Arc<LockedVMA>>>();
+        let mapper = &mut self.user_mapper.utable;
+        for vma in vmas {
+            let vm_flags = *vma.lock_irqsave().vm_flags();
+            if !vm_flags.contains(VmFlags::VM_LOCKED)
+                || vm_flags
+                    .intersects(VmFlags::VM_LOCKONFAULT | VmFlags::VM_IO | VmFlags::VM_PFNMAP)
+            {
+                continue;
+            }
+            // 可写的私有映射需要提前完成写时拷贝
+            let write =
+                vm_flags.contains(VmFlags::VM_WRITE) && !vm_flags.contains(VmFlags::VM_SHARED);
+            if !write && !vm_flags.contains(VmFlags::VM_READ) {
+                continue;
+            }
+            vma.populate(write, mapper)?;
+        }
+        Ok(())
+    }
+
+    /// 锁定`region`范围内的内存
+    ///
+    /// ## 参数
+    ///
+    /// - `region`: 需要锁定的区域
+    /// - `flags`: mlock2的标志
+    pub fn mlock(&mut self, region: VirtRegion, flags: MlockFlags) -> Result<(), SystemError> {
+        if let Some(limit) = mlock_limit()? {
+            // 区域中已经被锁定的部分不重复计算
+            let already_locked: usize = self
+                .mappings
+                .conflicts(region)
+                .filter_map(|vma| {
+                    let guard = vma.lock_irqsave();
+                    if guard.vm_flags().contains(VmFlags::VM_LOCKED) {
+                        guard.region().intersect(&region)
+                    } else {
+                        None
+                    }
+                })
+                .map(|r| r.size())
+                .sum();
+            if self.locked_vm() + region.size() - already_locked > limit {
+                return Err(SystemError::ENOMEM);
+            }
+        }
+
+        let mut lock_flags = VmFlags::VM_LOCKED;
+        if flags.contains(MlockFlags::MLOCK_ONFAULT) {
+            lock_flags |= VmFlags::VM_LOCKONFAULT;
+        }
+        self.apply_vma_lock_flags(region, lock_flags)?;
+
+        self.mm_populate(region).map_err(|e| match e {
+            SystemError::EFAULT => SystemError::ENOMEM,
+            SystemError::ENOMEM => SystemError::EAGAIN_OR_EWOULDBLOCK,
+            e => e,
+        })
+    }
+
+    /// 解除`region`范围内内存的锁定
+    pub fn munlock(&mut self, region: VirtRegion) -> Result<(), SystemError> {
+        self.apply_vma_lock_flags(region, VmFlags::VM_NONE)
+    }
+
+    /// 锁定地址空间中当前以及之后映射的内存
+    pub fn mlockall(&mut self, flags: MclFlags) -> Result<(), SystemError> {
+        if flags.is_empty()
+            || flags.bits() & !MclFlags::all().bits() != 0
+            || flags == MclFlags::MCL_ONFAULT
+        {
+            return Err(SystemError::EINVAL);
+        }
+        let limit = mlock_limit()?;
+        if flags.contains(MclFlags::MCL_CURRENT) && limit.is_some_and(|l| self.total_vm() > l) {
+            return Err(SystemError::ENOMEM);
+        }
+
+        let mut lock_flags = VmFlags::VM_LOCKED;
+        if flags.contains(MclFlags::MCL_ONFAULT) {
+            lock_flags |= VmFlags::VM_LOCKONFAULT;
+        }
+
+        self.def_flags -= VM_LOCKED_MASK;
+        if flags.contains(MclFlags::MCL_FUTURE) {
+            self.def_flags |= lock_flags;
+        }
+
+        if flags.contains(MclFlags::MCL_CURRENT) {
+            self.set_all_vma_lock_flags(lock_flags);
+            // 与Linux一致，建立映射失败时不返回错误
+            let _ = self.mm_populate(VirtRegion::new(
+                VirtAddr::new(0),
+                MMArch::USER_END_VADDR.data(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// 解除地址空间中所有内存的锁定
+    pub fn munlockall(&mut self) {
+        self.def_flags -= VM_LOCKED_MASK;
+        self.set_all_vma_lock_flags(VmFlags::VM_NONE);
+    }
+
+    fn set_all_vma_lock_flags(&mut self, lock_flags: VmFlags) {
+        let mapper = &self.user_mapper.utable;
+        for vma in self.mappings.iter_vmas() {
+            {
+                let mut guard = vma.lock_irqsave();
+                let vm_flags = (*guard.vm_flags() - VM_LOCKED_MASK) | lock_flags;
+                guard.set_vm_flags(vm_flags);
+            }
+            mlock_vma_pages(vma, mapper, !lock_flags.is_empty());
+        }
+    }
+}
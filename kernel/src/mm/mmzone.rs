@@ -0,0 +1,459 @@
+//! 物理内存区域
+//!
+//! 每个NUMA节点的物理内存按照地址划分为DMA、DMA32、Normal三个区域，每个区域有自己的伙伴分配器和每CPU页帧缓存。
+//! 分配页帧时，根据分配标志确定允许使用的最高区域，优先在首选节点上从高到低尝试各个区域，
+//! 然后再尝试其他节点，以便把低地址的内存留给有地址限制的设备使用。
+//!
+//! 释放页帧时，根据页帧的物理地址找到它所属的区域。
+
+use core::{
+    intrinsics::unlikely,
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+};
+
+use log::{debug, info};
+
+use crate::{
+    arch::{CurrentIrqArch, MMArch},
+    exception::InterruptArch,
+    mm::{
+        allocator::{
+            buddy::BuddyAllocator,
+            bump::BumpAllocator,
+            page_frame::{FrameAllocator, PageFrameCount, PageFrameUsage},
+            pcp::{LockedBuddyAllocator, PerCpuPageCache},
+        },
+        memblock::mem_block_manager,
+        MemoryManagementArch, PhysAddr, PhysMemoryArea,
+    },
+    smp::cpu::ProcessorId,
+};
+
+use super::{
+    gfp::GfpFlags,
+    mempolicy::policy_nodes,
+    numa::{
+        node_bytes_in_range, node_online_map, nr_node_ids, phys_to_nid, range_in_one_node,
+        NodeMask, MAX_NUMNODES,
+    },
+};
+
+/// 每个节点的内存区域数量
+pub const MAX_NR_ZONES: usize = 3;
+
+/// 内存区域的类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ZoneType {
+    /// 物理地址低于16M的内存，供ISA等只能访问24位地址的设备使用
+    Dma = 0,
+    /// 物理地址低于4G的内存，供只能访问32位地址的设备使用
+    Dma32 = 1,
+    /// 其余的内存
+    Normal = 2,
+}
+
+impl ZoneType {
+    pub const ALL: [ZoneType; MAX_NR_ZONES] = [ZoneType::Dma, ZoneType::Dma32, ZoneType::Normal];
+
+    /// 区域的起始物理地址
+    pub const fn start(&self) -> usize {
+        match self {
+            ZoneType::Dma => 0,
+            ZoneType::Dma32 => 16 << 20,
+            ZoneType::Normal => 1 << 32,
+        }
+    }
+
+    /// 区域的结束物理地址（不包含）
+    pub const fn end(&self) -> usize {
+        match self {
+            ZoneType::Dma => 16 << 20,
+            ZoneType::Dma32 => 1 << 32,
+            ZoneType::Normal => usize::MAX,
+        }
+    }
+
+    pub const fn name(&self) -> &'static str {
+        match self {
+            ZoneType::Dma => "DMA",
+            ZoneType::Dma32 => "DMA32",
+            ZoneType::Normal => "Normal",
+        }
+    }
+
+    /// 获取物理地址所属的区域类型
+    pub fn of(paddr: PhysAddr) -> ZoneType {
+        let paddr = paddr.data();
+        if paddr < ZoneType::Dma.end() {
+            ZoneType::Dma
+        } else if paddr < ZoneType::Dma32.end() {
+            ZoneType::Dma32
+        } else {
+            ZoneType::Normal
+        }
+    }
+
+    /// 把物理地址范围`[start, end)`裁剪到区域内
+    fn clamp(&self, start: PhysAddr, end: PhysAddr) -> (PhysAddr, PhysAddr) {
+        let s = start.data().max(self.start());
+        let e = end.data().min(self.end());
+        (PhysAddr::new(s), PhysAddr::new(e.max(s)))
+    }
+}
+
+/// 一个节点上的一个内存区域
+#[derive(Debug)]
+pub struct Zone {
+    buddy: LockedBuddyAllocator,
+    pcp: PerCpuPageCache,
+    /// 区域内的物理内存页数
+    present_pages: AtomicUsize,
+    /// 区域是否已经建立了伙伴分配器
+    populated: AtomicBool,
+}
+
+impl Zone {
+    const fn new() -> Self {
+        Self {
+            buddy: LockedBuddyAllocator::new(None),
+            pcp: PerCpuPageCache::new(),
+            present_pages: AtomicUsize::new(0),
+            populated: AtomicBool::new(false),
+        }
+    }
+
+    #[inline]
+    pub fn populated(&self) -> bool {
+        self.populated.load(Ordering::Acquire)
+    }
+
+    pub fn present_pages(&self) -> usize {
+        self.present_pages.load(Ordering::Relaxed)
+    }
+
+    /// 区域的页帧使用情况，每CPU缓存中的页帧算作空闲
+    pub fn usage(&self) -> PageFrameUsage {
+        let usage = match self.buddy.lock_irqsave().as_ref() {
+            Some(allocator) => unsafe { allocator.usage() },
+            None => return PageFrameUsage::new(PageFrameCount::new(0), PageFrameCount::new(0)),
+        };
+        let cached = PageFrameCount::new(self.pcp.nr_cached().min(usage.used().data()));
+        PageFrameUsage::new(usage.used() - cached, usage.total())
+    }
+
+    pub fn pcp(&self) -> &PerCpuPageCache {
+        &self.pcp
+    }
+
+    /// 安装区域的伙伴分配器
+    unsafe fn install(&self, mut allocator: BuddyAllocator<MMArch>, present_pages: usize) {
+        allocator.adjust_total(PageFrameCount::new(present_pages));
+        *self.buddy.lock_irqsave() = Some(allocator);
+        self.present_pages.store(present_pages, Ordering::Relaxed);
+        self.populated.store(true, Ordering::Release);
+    }
+}
+
+/// 所有节点的所有内存区域，按照`[节点][区域类型]`索引
+static NODE_ZONES: [[Zone; MAX_NR_ZONES]; MAX_NUMNODES] =
+    [const { [const { Zone::new() }; MAX_NR_ZONES] }; MAX_NUMNODES];
+
+#[inline]
+pub fn zone(nid: usize, zone_type: ZoneType) -> &'static Zone {
+    &NODE_ZONES[nid][zone_type as usize]
+}
+
+/// 遍历所有已经建立了伙伴分配器的区域
+pub fn populated_zones() -> impl Iterator<Item = (usize, ZoneType, &'static Zone)> {
+    (0..nr_node_ids()).flat_map(|nid| {
+        ZoneType::ALL.into_iter().filter_map(move |zone_type| {
+            let z = zone(nid, zone_type);
+            if z.populated() {
+                Some((nid, zone_type, z))
+            } else {
+                None
+            }
+        })
+    })
+}
+
+/// 统计节点`nid`在区域`zone_type`中的物理内存页数
+fn node_zone_present_pages(nid: usize, zone_type: ZoneType) -> usize {
+    let mut bytes = 0;
+    for area in mem_block_manager().to_iter_available() {
+        let (start, end) = zone_type.clamp(area.area_base_aligned(), area.area_end_aligned());
+        bytes += node_bytes_in_range(nid, start, end);
+    }
+    bytes >> MMArch::PAGE_SHIFT
+}
+
+/// 用bump分配器剩余的内存初始化各个内存区域的伙伴分配器，此时所有内存都属于节点0
+///
+/// ## 参数
+///
+/// - `bump_allocator`: 映射完所有物理内存之后的bump分配器
+pub unsafe fn zones_init(mut bump_allocator: BumpAllocator<MMArch>) {
+    debug!(
+        "Free pages before init buddy: {:?}",
+        bump_allocator.usage().free()
+    );
+
+    // 先为所有包含物理内存的区域创建伙伴分配器，这样之后释放的任何页帧都能找到所属的区域
+    let mut allocators: [Option<BuddyAllocator<MMArch>>; MAX_NR_ZONES] = [None, None, None];
+    for zone_type in ZoneType::ALL {
+        if node_zone_present_pages(0, zone_type) > 0 {
+            allocators[zone_type as usize] = Some(
+                BuddyAllocator::empty(&mut bump_allocator)
+                    .expect("zones_init: failed to create buddy allocator"),
+            );
+        }
+    }
+
+    let mut res_areas = [PhysMemoryArea::default(); 128];
+    let mut offset_in_remain_area = bump_allocator
+        .remain_areas(&mut res_areas)
+        .expect("zones_init: failed to get remain areas from bump allocator");
+
+    for area in res_areas.iter() {
+        let start = area.area_base_aligned() + offset_in_remain_area;
+        offset_in_remain_area = 0;
+        for zone_type in ZoneType::ALL {
+            let (s, e) = zone_type.clamp(start, area.area_end_aligned());
+            if let Some(allocator) = allocators[zone_type as usize].as_mut() {
+                allocator.add_range(s, e);
+            }
+        }
+    }
+
+    for (zone_type, allocator) in ZoneType::ALL.into_iter().zip(allocators) {
+        if let Some(allocator) = allocator {
+            let present_pages = node_zone_present_pages(0, zone_type);
+            info!("Zone {}: {} pages", zone_type.name(), present_pages);
+            zone(0, zone_type).install(allocator, present_pages);
+        }
+    }
+}
+
+/// 为所有已经建立的区域初始化每CPU页帧缓存
+pub fn zones_pcp_init() {
+    for (_, _, z) in populated_zones() {
+        z.pcp.init();
+    }
+}
+
+/// 按照NUMA节点重新划分各个区域的伙伴分配器
+///
+/// 取出节点0上各个区域的伙伴分配器中的所有空闲块，按照块所属的节点放入新的伙伴分配器中。
+/// 迁移期间不能从堆中分配内存，也不能打印日志。
+///
+/// ## Safety
+///
+/// 必须在其他CPU启动之前、节点信息设置完成之后调用
+pub(super) unsafe fn zones_repartition() {
+    let nr_nodes = nr_node_ids();
+    for zone_type in ZoneType::ALL {
+        let old_zone = zone(0, zone_type);
+        if !old_zone.populated() {
+            continue;
+        }
+        let irq_guard = CurrentIrqArch::save_and_disable_irq();
+        old_zone.pcp.drain_all(&old_zone.buddy);
+        let mut old = match old_zone.buddy.lock_irqsave().take() {
+            Some(old) => old,
+            None => continue,
+        };
+        old_zone.populated.store(false, Ordering::Release);
+
+        let mut present: [usize; MAX_NUMNODES] = [0; MAX_NUMNODES];
+        let mut allocators: [Option<BuddyAllocator<MMArch>>; MAX_NUMNODES] =
+            [const { None }; MAX_NUMNODES];
+        for nid in 0..nr_nodes {
+            present[nid] = node_zone_present_pages(nid, zone_type);
+            if present[nid] > 0 {
+                allocators[nid] = BuddyAllocator::empty(&mut old);
+            }
+        }
+
+        old.drain(|paddr, count| place_free_block(&mut allocators, paddr, count));
+
+        for nid in 0..nr_nodes {
+            if let Some(allocator) = allocators[nid].take() {
+                zone(nid, zone_type).install(allocator, present[nid]);
+            }
+        }
+        drop(irq_guard);
+    }
+
+    zones_pcp_init();
+}
+
+/// 把空闲块放入它所属节点的伙伴分配器，跨越节点边界的块会被拆分
+unsafe fn place_free_block(
+    allocators: &mut [Option<BuddyAllocator<MMArch>>; MAX_NUMNODES],
+    paddr: PhysAddr,
+    count: PageFrameCount,
+) {
+    let end = paddr + count.bytes();
+    if count.data() > 1 && !range_in_one_node(paddr, end) {
+        let half = PageFrameCount::new(count.data() / 2);
+        place_free_block(allocators, paddr, half);
+        place_free_block(allocators, paddr + half.bytes(), half);
+        return;
+    }
+
+    let nid = phys_to_nid(paddr);
+    let allocator = if allocators[nid].is_some() {
+        allocators[nid].as_mut()
+    } else {
+        allocators.iter_mut().find_map(|a| a.as_mut())
+    };
+    if let Some(allocator) = allocator {
+        allocator.free(paddr, count);
+    }
+}
+
+/// 按照节点优先级依次尝试的节点：首选节点在前，其余节点在后
+fn zonelist_nodes(gfp: GfpFlags, preferred: usize, nodes: NodeMask) -> impl Iterator<Item = usize> {
+    let thisnode = gfp.contains(GfpFlags::__GFP_THISNODE);
+    let first = if nodes.test(preferred) || thisnode {
+        Some(preferred)
+    } else {
+        nodes.first()
+    };
+    first.into_iter().chain(
+        nodes
+            .iter()
+            .filter(move |nid| !thisnode && Some(*nid) != first),
+    )
+}
+
+unsafe fn get_page_from_zonelist(
+    gfp: GfpFlags,
+    count: PageFrameCount,
+    preferred: usize,
+    nodes: NodeMask,
+) -> Option<(PhysAddr, PageFrameCount)> {
+    let highest = gfp.zone_type();
+    for nid in zonelist_nodes(gfp, preferred, nodes) {
+        for zone_type in ZoneType::ALL.into_iter().rev() {
+            if zone_type > highest {
+                continue;
+            }
+            let z = zone(nid, zone_type);
+            if !z.populated() {
+                continue;
+            }
+            if let Some(r) = z.pcp.allocate(&z.buddy, count) {
+                return Some(r);
+            }
+        }
+    }
+    None
+}
+
+/// 把所有区域的每CPU页帧缓存归还给伙伴分配器
+fn drain_all_zones() {
+    for (_, _, z) in populated_zones() {
+        z.pcp.drain_all(&z.buddy);
+    }
+}
+
+/// 在节点集合`nodes`中分配页帧，优先使用节点`preferred`
+///
+/// ## 参数
+///
+/// - `gfp`: 分配标志
+/// - `count`: 页帧数量，会被向上取整为2的幂
+/// - `preferred`: 首选节点
+/// - `nodes`: 允许使用的节点
+pub unsafe fn alloc_pages_nodemask(
+    gfp: GfpFlags,
+    count: PageFrameCount,
+    preferred: usize,
+    nodes: NodeMask,
+) -> Option<(PhysAddr, PageFrameCount)> {
+    let count = count.next_power_of_two();
+    let mut nodes = nodes.intersection(&node_online_map());
+    if nodes.is_empty() {
+        nodes = node_online_map();
+    }
+    let preferred = if preferred < nr_node_ids() {
+        preferred
+    } else {
+        nodes.first()?
+    };
+
+    // 内存不足时，其他CPU的缓存中可能还有空闲页帧，归还之后再试一次
+    let r = get_page_from_zonelist(gfp, count, preferred, nodes).or_else(|| {
+        drain_all_zones();
+        get_page_from_zonelist(gfp, count, preferred, nodes)
+    })?;
+
+    if gfp.contains(GfpFlags::__GFP_ZERO) {
+        let vaddr = MMArch::phys_2_virt(r.0).unwrap();
+        core::ptr::write_bytes(vaddr.data() as *mut u8, 0, r.1.bytes());
+    }
+    Some(r)
+}
+
+/// 在指定节点上分配页帧，除非指定了`__GFP_THISNODE`，否则可以回退到其他节点
+pub unsafe fn alloc_pages_node(
+    gfp: GfpFlags,
+    count: PageFrameCount,
+    nid: usize,
+) -> Option<(PhysAddr, PageFrameCount)> {
+    alloc_pages_nodemask(gfp, count, nid, node_online_map())
+}
+
+/// 按照当前进程的内存策略分配页帧，不能睡眠的分配以及内核线程的分配在本地节点进行
+///
+/// ## 参数
+///
+/// - `gfp`: 分配标志
+/// - `count`: 页帧数量，会被向上取整为2的幂
+pub unsafe fn alloc_pages(
+    gfp: GfpFlags,
+    count: PageFrameCount,
+) -> Option<(PhysAddr, PageFrameCount)> {
+    let (preferred, nodes) = policy_nodes(gfp);
+    alloc_pages_nodemask(gfp, count, preferred, nodes)
+}
+
+/// 释放页帧到它所属的区域
+///
+/// ## 参数
+///
+/// - `paddr`: 页帧起始地址
+/// - `count`: 页帧数量，必须是2的幂
+pub unsafe fn free_pages(paddr: PhysAddr, count: PageFrameCount) {
+    let zone_type = ZoneType::of(paddr);
+    let mut z = zone(phys_to_nid(paddr), zone_type);
+    if unlikely(!z.populated()) {
+        // 地址不在任何区域的物理内存范围内，放到同类型或者任意一个已经建立的区域中
+        z = populated_zones()
+            .find(|(_, t, _)| *t == zone_type)
+            .or_else(|| populated_zones().next())
+            .expect("free_pages: no populated zone")
+            .2;
+    }
+    z.pcp.free(&z.buddy, paddr, count);
+}
+
+/// 所有区域的页帧使用情况之和
+pub fn zones_usage() -> PageFrameUsage {
+    let mut used = PageFrameCount::new(0);
+    let mut total = PageFrameCount::new(0);
+    for (_, _, z) in populated_zones() {
+        let usage = z.usage();
+        used += usage.used();
+        total += usage.total();
+    }
+    PageFrameUsage::new(used, total)
+}
+
+/// 把指定CPU在所有区域中缓存的页帧归还给伙伴分配器，CPU下线时调用
+pub fn drain_cpu_pages(cpu: ProcessorId) {
+    for (_, _, z) in populated_zones() {
+        z.pcp.drain(&z.buddy, cpu);
+    }
+}
@@ -23,15 +23,28 @@ use self::{
 pub mod allocator;
 pub mod early_ioremap;
 pub mod fault;
+pub mod gfp;
+pub mod huge_memory;
+pub mod hugetlb;
 pub mod init;
 pub mod kernel_mapper;
+pub mod ksm;
 pub mod madvise;
 pub mod memblock;
+pub mod memfd;
+pub mod mempolicy;
+pub mod mincore;
+pub mod mlock;
 pub mod mmio_buddy;
+pub mod mmzone;
 pub mod no_init;
+pub mod numa;
+pub mod oom_kill;
 pub mod page;
 pub mod percpu;
+pub mod swap;
 pub mod syscall;
+pub mod sysfs;
 pub mod ucontext;
 
 /// 内核INIT进程的用户地址空间结构体（仅在process_init中初始化）
@@ -74,6 +87,9 @@ bitflags! {
         const VM_ARCH_1 = 0x01000000;
         const VM_WIPEONFORK = 0x02000000;
         const VM_DONTDUMP = 0x04000000;
+        const VM_HUGEPAGE = 0x20000000;
+        const VM_NOHUGEPAGE = 0x40000000;
+        const VM_MERGEABLE = 0x80000000;
     }
 
     /// 描述页面错误处理过程中发生的不同情况或结果
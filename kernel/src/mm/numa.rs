@@ -0,0 +1,314 @@
+//! NUMA节点
+//!
+//! 启动时所有物理内存都属于节点0。ACPI初始化完成后，根据SRAT表中的内存亲和性结构和处理器亲和性结构，
+//! 把物理内存和CPU划分到不同的节点上，并按照节点重新划分各个内存区域的伙伴分配器。
+//!
+//! 节点信息只在启动阶段（其他CPU启动之前）写入一次，之后只读，因此使用原子变量保存，
+//! 在页帧释放等热路径上查询时不需要加锁。
+
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+use alloc::vec::Vec;
+use log::{info, warn};
+
+use crate::{
+    mm::{percpu::PerCpu, PhysAddr},
+    smp::{core::smp_get_processor_id, cpu::ProcessorId},
+};
+
+use super::mmzone::zones_repartition;
+
+/// 系统支持的最大NUMA节点数量
+pub const MAX_NUMNODES: usize = 8;
+/// 最多记录的节点内存块数量
+const MAX_NUMA_MEMBLKS: usize = 64;
+
+/// NUMA节点的集合
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct NodeMask(u64);
+
+impl NodeMask {
+    pub const fn new() -> Self {
+        Self(0)
+    }
+
+    /// 从用户传入的位图创建节点集合，超出[`MAX_NUMNODES`]的位会被忽略
+    pub const fn from_bits_truncate(bits: u64) -> Self {
+        Self(bits & ((1 << MAX_NUMNODES) - 1))
+    }
+
+    pub const fn bits(&self) -> u64 {
+        self.0
+    }
+
+    pub fn set(&mut self, nid: usize) {
+        if nid < MAX_NUMNODES {
+            self.0 |= 1 << nid;
+        }
+    }
+
+    pub fn test(&self, nid: usize) -> bool {
+        nid < MAX_NUMNODES && self.0 & (1 << nid) != 0
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0 == 0
+    }
+
+    pub fn weight(&self) -> usize {
+        self.0.count_ones() as usize
+    }
+
+    /// 集合中编号最小的节点
+    pub fn first(&self) -> Option<usize> {
+        if self.0 == 0 {
+            None
+        } else {
+            Some(self.0.trailing_zeros() as usize)
+        }
+    }
+
+    /// 集合中编号大于`nid`的第一个节点，没有则回绕到编号最小的节点
+    pub fn next_wrap(&self, nid: usize) -> Option<usize> {
+        let higher = if nid + 1 >= 64 {
+            0
+        } else {
+            self.0 & !((1u64 << (nid + 1)) - 1)
+        };
+        if higher != 0 {
+            Some(higher.trailing_zeros() as usize)
+        } else {
+            self.first()
+        }
+    }
+
+    /// 第`n`个（从0开始）节点
+    pub fn nth(&self, n: usize) -> Option<usize> {
+        self.iter().nth(n)
+    }
+
+    pub fn intersection(&self, other: &Self) -> Self {
+        Self(self.0 & other.0)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = usize> {
+        let bits = self.0;
+        (0..MAX_NUMNODES).filter(move |nid| bits & (1 << nid) != 0)
+    }
+}
+
+/// 属于某个节点的一段物理内存
+#[derive(Debug)]
+struct NumaMemblk {
+    start: AtomicUsize,
+    end: AtomicUsize,
+    nid: AtomicUsize,
+}
+
+static NUMA_MEMBLKS: [NumaMemblk; MAX_NUMA_MEMBLKS] = [const {
+    NumaMemblk {
+        start: AtomicUsize::new(0),
+        end: AtomicUsize::new(0),
+        nid: AtomicUsize::new(0),
+    }
+}; MAX_NUMA_MEMBLKS];
+static NR_NUMA_MEMBLKS: AtomicUsize = AtomicUsize::new(0);
+/// 节点数量，节点编号为`0..NR_NODE_IDS`
+static NR_NODE_IDS: AtomicUsize = AtomicUsize::new(1);
+static CPU_TO_NODE: [AtomicUsize; PerCpu::MAX_CPU_NUM as usize] =
+    [const { AtomicUsize::new(0) }; PerCpu::MAX_CPU_NUM as usize];
+static NUMA_ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// 固件（如ACPI SRAT）提供的NUMA拓扑信息，其中的节点以固件的邻近域（proximity domain）编号表示
+#[derive(Debug, Default)]
+pub struct NumaMeminfo {
+    /// (邻近域, 起始物理地址, 结束物理地址)
+    memblks: Vec<(u32, PhysAddr, PhysAddr)>,
+    /// (CPU编号, 邻近域)
+    cpus: Vec<(ProcessorId, u32)>,
+}
+
+impl NumaMeminfo {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add_memblk(&mut self, domain: u32, start: PhysAddr, end: PhysAddr) {
+        if start < end {
+            self.memblks.push((domain, start, end));
+        }
+    }
+
+    pub fn add_cpu(&mut self, cpu: ProcessorId, domain: u32) {
+        self.cpus.push((cpu, domain));
+    }
+}
+
+/// 系统中的节点数量
+#[inline]
+pub fn nr_node_ids() -> usize {
+    NR_NODE_IDS.load(Ordering::Relaxed)
+}
+
+/// 是否划分了多个NUMA节点
+#[inline]
+pub fn numa_enabled() -> bool {
+    NUMA_ENABLED.load(Ordering::Relaxed)
+}
+
+/// 所有在线节点的集合
+pub fn node_online_map() -> NodeMask {
+    let mut mask = NodeMask::new();
+    for nid in 0..nr_node_ids() {
+        mask.set(nid);
+    }
+    mask
+}
+
+/// 获取指定CPU所在的节点
+pub fn cpu_to_node(cpu: ProcessorId) -> usize {
+    CPU_TO_NODE
+        .get(cpu.data() as usize)
+        .map(|n| n.load(Ordering::Relaxed))
+        .unwrap_or(0)
+}
+
+/// 获取当前CPU所在的节点
+#[inline]
+pub fn numa_node_id() -> usize {
+    if !numa_enabled() {
+        return 0;
+    }
+    cpu_to_node(smp_get_processor_id())
+}
+
+/// 获取物理地址所在的节点，不属于任何节点内存块的地址视为属于节点0
+pub fn phys_to_nid(paddr: PhysAddr) -> usize {
+    if !numa_enabled() {
+        return 0;
+    }
+    let paddr = paddr.data();
+    for blk in NUMA_MEMBLKS
+        .iter()
+        .take(NR_NUMA_MEMBLKS.load(Ordering::Relaxed))
+    {
+        if paddr >= blk.start.load(Ordering::Relaxed) && paddr < blk.end.load(Ordering::Relaxed) {
+            return blk.nid.load(Ordering::Relaxed);
+        }
+    }
+    0
+}
+
+/// 判断物理地址范围`[start, end)`是否完全位于同一个节点中
+pub fn range_in_one_node(start: PhysAddr, end: PhysAddr) -> bool {
+    if !numa_enabled() {
+        return true;
+    }
+    let (start, end) = (start.data(), end.data());
+    for blk in NUMA_MEMBLKS
+        .iter()
+        .take(NR_NUMA_MEMBLKS.load(Ordering::Relaxed))
+    {
+        let bs = blk.start.load(Ordering::Relaxed);
+        let be = blk.end.load(Ordering::Relaxed);
+        if (bs > start && bs < end) || (be > start && be < end) {
+            return false;
+        }
+    }
+    true
+}
+
+/// 统计物理地址范围`[start, end)`中属于节点`nid`的字节数
+pub fn node_bytes_in_range(nid: usize, start: PhysAddr, end: PhysAddr) -> usize {
+    if end <= start {
+        return 0;
+    }
+    if !numa_enabled() {
+        return if nid == 0 { end - start } else { 0 };
+    }
+    let (start, end) = (start.data(), end.data());
+    let mut owned = 0;
+    let mut covered = 0;
+    for blk in NUMA_MEMBLKS
+        .iter()
+        .take(NR_NUMA_MEMBLKS.load(Ordering::Relaxed))
+    {
+        let s = blk.start.load(Ordering::Relaxed).max(start);
+        let e = blk.end.load(Ordering::Relaxed).min(end);
+        if s >= e {
+            continue;
+        }
+        covered += e - s;
+        if blk.nid.load(Ordering::Relaxed) == nid {
+            owned += e - s;
+        }
+    }
+    if nid == 0 {
+        owned += (end - start).saturating_sub(covered);
+    }
+    owned
+}
+
+/// 根据固件提供的拓扑信息划分NUMA节点
+///
+/// 必须在其他CPU启动之前调用。只有一个节点时什么也不做。
+pub fn numa_init(info: NumaMeminfo) {
+    // 邻近域按出现的先后顺序映射为连续的节点编号
+    let mut domains: [u32; MAX_NUMNODES] = [0; MAX_NUMNODES];
+    let mut nr_nodes = 0;
+    let mut domain_to_nid = |domain: u32| -> Option<usize> {
+        if let Some(nid) = domains[..nr_nodes].iter().position(|d| *d == domain) {
+            return Some(nid);
+        }
+        if nr_nodes == MAX_NUMNODES {
+            warn!("NUMA: too many proximity domains, ignore domain {domain}");
+            return None;
+        }
+        domains[nr_nodes] = domain;
+        nr_nodes += 1;
+        Some(nr_nodes - 1)
+    };
+
+    let mut memblks: Vec<(usize, PhysAddr, PhysAddr)> = Vec::new();
+    for (domain, start, end) in info.memblks.iter() {
+        if let Some(nid) = domain_to_nid(*domain) {
+            memblks.push((nid, *start, *end));
+        }
+    }
+    let mut cpus: Vec<(ProcessorId, usize)> = Vec::new();
+    for (cpu, domain) in info.cpus.iter() {
+        if let Some(nid) = domain_to_nid(*domain) {
+            cpus.push((*cpu, nid));
+        }
+    }
+
+    if nr_nodes <= 1 {
+        info!("NUMA: single node, NUMA disabled");
+        return;
+    }
+    if memblks.len() > MAX_NUMA_MEMBLKS {
+        warn!(
+            "NUMA: too many memory blocks ({}), NUMA disabled",
+            memblks.len()
+        );
+        return;
+    }
+
+    for (i, (nid, start, end)) in memblks.iter().enumerate() {
+        info!("NUMA: node {nid} [{:#x}, {:#x})", start.data(), end.data());
+        NUMA_MEMBLKS[i].start.store(start.data(), Ordering::Relaxed);
+        NUMA_MEMBLKS[i].end.store(end.data(), Ordering::Relaxed);
+        NUMA_MEMBLKS[i].nid.store(*nid, Ordering::Relaxed);
+    }
+    NR_NUMA_MEMBLKS.store(memblks.len(), Ordering::Relaxed);
+    for (cpu, nid) in cpus {
+        if let Some(n) = CPU_TO_NODE.get(cpu.data() as usize) {
+            n.store(nid, Ordering::Relaxed);
+        }
+    }
+    NR_NODE_IDS.store(nr_nodes, Ordering::Relaxed);
+    NUMA_ENABLED.store(true, Ordering::SeqCst);
+
+    unsafe { zones_repartition() };
+    info!("NUMA: {nr_nodes} nodes initialized");
+}
@@ -0,0 +1,324 @@
+//! 内存不足（OOM）时的进程杀手
+//!
+//! 物理内存耗尽时，根据进程的内存占用和`oom_score_adj`选出得分最高的进程，
+//! 由oom_reaper线程向其发送SIGKILL，并在它退出之前回收它的匿名内存。
+//!
+//! 内存分配失败的路径上只能使用try-lock选出受害者，不能睡眠，也不能分配内存，
+//! 发送信号、打印日志以及回收内存都交给oom_reaper线程完成。
+
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use alloc::{string::ToString, sync::Arc, vec::Vec};
+use log::{error, info, warn};
+use system_error::SystemError;
+use unified_init::macros::unified_init;
+
+use crate::{
+    arch::{
+        ipc::signal::{SigCode, Signal},
+        mm::LockedFrameAllocator,
+        CurrentIrqArch, MMArch,
+    },
+    exception::InterruptArch,
+    init::initcall::INITCALL_CORE,
+    ipc::signal_types::{SigInfo, SigType},
+    libs::spinlock::SpinLock,
+    process::{Pid, ProcessControlBlock, ProcessFlags, ProcessManager},
+    sched::{schedule, SchedMode},
+};
+
+use super::{
+    allocator::page_frame::FrameAllocator,
+    swap::swap_total_pages,
+    ucontext::{AddressSpace, MemoryUsage},
+    MemoryManagementArch,
+};
+
+/// oom_score_adj的最小值，取该值的进程不会被OOM killer杀死
+pub const OOM_SCORE_ADJ_MIN: i32 = -1000;
+/// oom_score_adj的最大值
+pub const OOM_SCORE_ADJ_MAX: i32 = 1000;
+
+/// 保证同一时刻只有一个执行流在选择受害者，同时防止选择过程中的内存分配失败导致递归
+static OOM_LOCK: AtomicBool = AtomicBool::new(false);
+
+/// 等待被杀死并回收内存的受害者。在回收完成之前不会选择新的受害者
+static OOM_REAP_PENDING: SpinLock<Option<OomVictim>> = SpinLock::new(None);
+
+/// oom_reaper线程
+static mut OOM_REAPER_THREAD: Option<Arc<ProcessControlBlock>> = None;
+
+/// OOM killer选出的受害者
+#[derive(Clone)]
+struct OomVictim {
+    pcb: Arc<ProcessControlBlock>,
+    vm: Arc<AddressSpace>,
+    usage: MemoryUsage,
+    points: i64,
+    /// 是否已经向受害者发送了SIGKILL
+    killed: bool,
+}
+
+#[inline]
+fn pages_to_kb(pages: usize) -> usize {
+    (pages * MMArch::PAGE_SIZE) >> 10
+}
+
+/// 计算分数时使用的总页数，即物理内存与交换空间之和
+fn oom_total_pages() -> usize {
+    let usage = unsafe { LockedFrameAllocator.usage() };
+    usage.total().data() + swap_total_pages()
+}
+
+/// 计算进程的OOM分数
+///
+/// ## 返回值
+///
+/// - `Some((points, vm, usage))` 进程的分数、地址空间及其内存使用情况
+/// - `None` 进程不能被杀死，或者暂时无法统计它的内存使用情况
+fn oom_badness(
+    pcb: &Arc<ProcessControlBlock>,
+    totalpages: usize,
+) -> Option<(i64, Arc<AddressSpace>, MemoryUsage)> {
+    // 内核线程和init进程不能被杀死
+    if pcb.is_kthread() || pcb.pid() == Pid::new(1) {
+        return None;
+    }
+    let adj = pcb.oom_score_adj();
+    if adj == OOM_SCORE_ADJ_MIN {
+        return None;
+    }
+    let vm = pcb.try_basic()?.user_vm()?;
+    let usage = vm.try_read_irqsave()?.try_memory_usage()?;
+
+    let points = (usage.rss + usage.swap) as i64 + adj as i64 * (totalpages as i64 / 1000);
+    return Some((points, vm, usage));
+}
+
+/// 获取进程在/proc/<pid>/oom_score中显示的分数，范围为[0, 2000]
+pub fn oom_score(pcb: &Arc<ProcessControlBlock>) -> usize {
+    let totalpages = oom_total_pages().max(1);
+    match oom_badness(pcb, totalpages) {
+        Some((points, _, _)) => (1000 + points * 1000 / totalpages as i64).clamp(0, 2000) as usize,
+        None => 0,
+    }
+}
+
+/// 选择得分最高的进程作为受害者
+///
+/// ## 返回值
+///
+/// - `Ok(Some(victim))` 选中的受害者
+/// - `Ok(None)` 没有可以被杀死的进程
+/// - `Err(EAGAIN_OR_EWOULDBLOCK)` 进程表正被其他人持有，稍后重试
+fn select_bad_process(totalpages: usize) -> Result<Option<OomVictim>, SystemError> {
+    let mut chosen: Option<OomVictim> = None;
+    ProcessManager::try_for_each_process(|pcb| {
+        // 同一线程组的线程共享地址空间，只需要考虑线程组的组长
+        if pcb.pid() != pcb.tgid() || pcb.flags().contains(ProcessFlags::OOM_VICTIM) {
+            return;
+        }
+        if let Some((points, vm, usage)) = oom_badness(pcb, totalpages) {
+            if chosen.as_ref().map_or(true, |c| points > c.points) {
+                chosen = Some(OomVictim {
+                    pcb: pcb.clone(),
+                    vm,
+                    usage,
+                    points,
+                    killed: false,
+                });
+            }
+        }
+    })?;
+    return Ok(chosen);
+}
+
+/// 杀死受害者以及所有与它共享地址空间的进程
+///
+/// 只在oom_reaper线程或者缺页异常的上下文中调用，可以持有会睡眠的锁
+fn oom_kill_process(victim: &OomVictim) {
+    let mut pids: Vec<Pid> = Vec::new();
+    while ProcessManager::try_for_each_process(|pcb| {
+        let shared = pcb
+            .basic()
+            .user_vm()
+            .is_some_and(|vm| Arc::ptr_eq(&vm, &victim.vm));
+        // 内存不足时追加元素可能失败，此时只杀死受害者本身
+        if shared && pcb.pid() != Pid::new(1) && pids.try_reserve(1).is_ok() {
+            pids.push(pcb.pid());
+        }
+    })
+    .is_err()
+    {
+        pids.clear();
+        schedule(SchedMode::SM_NONE);
+    }
+    if !pids.contains(&victim.pcb.pid()) {
+        pids.clear();
+        pids.push(victim.pcb.pid());
+    }
+
+    for pid in pids {
+        if let Some(pcb) = ProcessManager::find(pid) {
+            pcb.flags().insert(ProcessFlags::OOM_VICTIM);
+        }
+        let mut info = SigInfo::new(Signal::SIGKILL, 0, SigCode::Kernel, SigType::Kill(pid));
+        let _ = Signal::SIGKILL.send_signal_info(Some(&mut info), pid);
+    }
+
+    error!(
+        "Out of memory: Killed process {:?} ({}) total-vm:{}kB, rss:{}kB, swap:{}kB, UID:{} oom_score_adj:{}",
+        victim.pcb.pid(),
+        victim.pcb.basic().name(),
+        pages_to_kb(victim.usage.total_vm),
+        pages_to_kb(victim.usage.rss),
+        pages_to_kb(victim.usage.swap),
+        victim.pcb.cred().uid.data(),
+        victim.pcb.oom_score_adj()
+    );
+}
+
+/// 内存不足时调用，选择一个受害者，由oom_reaper线程杀死它并回收它的内存
+///
+/// 可以在内存分配失败的路径上调用：只使用try-lock，不会睡眠，也不会分配内存
+///
+/// ## 返回值
+///
+/// 若已经选出了受害者，或者之前的受害者的内存即将被回收，返回true。
+/// 若系统中没有可以被杀死的进程，返回false
+pub fn out_of_memory() -> bool {
+    if !ProcessManager::initialized() {
+        return false;
+    }
+    if OOM_LOCK
+        .compare_exchange(false, true, Ordering::AcqRel, Ordering::Relaxed)
+        .is_err()
+    {
+        // 其他执行流正在处理内存不足
+        return true;
+    }
+    let r = do_out_of_memory();
+    OOM_LOCK.store(false, Ordering::Release);
+    if r {
+        wakeup_oom_reaper();
+    }
+    return r;
+}
+
+fn do_out_of_memory() -> bool {
+    let mut pending = match OOM_REAP_PENDING.try_lock_irqsave() {
+        Ok(pending) => pending,
+        Err(_) => return true,
+    };
+    // 上一个受害者的内存还没有被回收，等待它被回收
+    if pending.is_some() {
+        return true;
+    }
+
+    let totalpages = oom_total_pages();
+    match select_bad_process(totalpages) {
+        Ok(Some(victim)) => {
+            *pending = Some(victim);
+            true
+        }
+        Ok(None) => false,
+        Err(_) => true,
+    }
+}
+
+/// 缺页异常因内存不足（VM_FAULT_OOM）而失败时调用
+///
+/// 调用者不能持有任何锁。返回之后缺页异常会被重新触发。
+/// 系统中没有其他可以被杀死的进程时，杀死当前进程
+pub fn pagefault_out_of_memory() {
+    if out_of_memory() {
+        // 在当前上下文中立即杀死受害者并回收它的内存，不必等待oom_reaper线程
+        oom_reap_pending();
+        return;
+    }
+
+    let pid = ProcessManager::current_pid();
+    warn!(
+        "Out of memory and no killable processes, killing the faulting process {:?}",
+        pid
+    );
+    let mut info = SigInfo::new(Signal::SIGKILL, 0, SigCode::Kernel, SigType::Kill(pid));
+    let _ = Signal::SIGKILL.send_signal_info(Some(&mut info), pid);
+}
+
+/// 杀死等待中的受害者，并回收它的匿名内存
+fn oom_reap_pending() {
+    let (victim, kill) = {
+        let mut pending = OOM_REAP_PENDING.lock_irqsave();
+        let victim = match pending.as_mut() {
+            Some(victim) => victim,
+            None => return,
+        };
+        let kill = !victim.killed;
+        victim.killed = true;
+        (victim.clone(), kill)
+    };
+    if kill {
+        oom_kill_process(&victim);
+    }
+
+    let pid = victim.pcb.pid();
+    let vm = victim.vm;
+    let mut guard = vm.write_irqsave();
+    guard.reap_anonymous();
+    let rss = guard.try_memory_usage().map_or(0, |usage| usage.rss);
+    drop(guard);
+
+    let mut pending = OOM_REAP_PENDING.lock_irqsave();
+    if pending
+        .as_ref()
+        .is_some_and(|pending| Arc::ptr_eq(&pending.vm, &vm))
+    {
+        *pending = None;
+    }
+    drop(pending);
+
+    info!(
+        "oom_reaper: reaped process {:?}, now rss:{}kB",
+        pid,
+        pages_to_kb(rss)
+    );
+}
+
+fn wakeup_oom_reaper() {
+    if let Some(pcb) = unsafe { OOM_REAPER_THREAD.as_ref() } {
+        let _ = ProcessManager::wakeup(pcb);
+    }
+}
+
+fn oom_reaper_thread() -> i32 {
+    loop {
+        oom_reap_pending();
+
+        let irq_guard = unsafe { CurrentIrqArch::save_and_disable_irq() };
+        if OOM_REAP_PENDING.lock().is_none() {
+            ProcessManager::mark_sleep(true).ok();
+        }
+        drop(irq_guard);
+        schedule(SchedMode::SM_NONE);
+    }
+}
+
+/// oom_reaper线程初始化函数
+#[unified_init(INITCALL_CORE)]
+fn oom_reaper_thread_init() -> Result<(), SystemError> {
+    let closure = crate::process::kthread::KernelThreadClosure::StaticEmptyClosure((
+        &(oom_reaper_thread as fn() -> i32),
+        (),
+    ));
+    let pcb = crate::process::kthread::KernelThreadMechanism::create_and_run(
+        closure,
+        "oom_reaper".to_string(),
+    )
+    .ok_or("")
+    .expect("create oom_reaper thread failed");
+    unsafe {
+        OOM_REAPER_THREAD = Some(pcb);
+    }
+    Ok(())
+}
@@ -32,8 +32,9 @@ use super::{
     allocator::page_frame::{
         deallocate_page_frames, FrameAllocator, PageFrameCount, PhysPageFrame,
     },
+    swap::{self, swap_duplicate, SwapEntry},
     syscall::ProtFlags,
-    ucontext::LockedVMA,
+    ucontext::{AddressSpace, LockedVMA},
     MemoryManagementArch, PageTableKind, PhysAddr, VirtAddr,
 };
 
@@ -169,6 +170,30 @@ impl PageManager {
             MMArch::write_bytes(vaddr, 0, MMArch::PAGE_SIZE * count.data());
         }
 
+        let ret = self.insert_pages(start_paddr, count, page_type, flags)?;
+        Ok((start_paddr, ret))
+    }
+
+    /// # 为已经分配的物理页帧创建页面并加入管理器
+    ///
+    /// ## 参数
+    ///
+    /// - `start_paddr`: 起始物理地址
+    /// - `count`: 页面数量
+    /// - `page_type`: 页面类型
+    /// - `flags`: 页面标志
+    ///
+    /// ## 返回值
+    ///
+    /// - `Ok(Vec<Arc<Page>>)`: 新页面集合
+    /// - `Err(SystemError)`: 错误码
+    pub fn insert_pages(
+        &mut self,
+        start_paddr: PhysAddr,
+        count: PageFrameCount,
+        page_type: PageType,
+        flags: PageFlags,
+    ) -> Result<Vec<Arc<Page>>, SystemError> {
         let mut cur_phys = PhysPageFrame::new(start_paddr);
         let mut ret: Vec<Arc<Page>> = Vec::new();
         for _ in 0..count.data() {
@@ -182,7 +207,7 @@ impl PageManager {
             ret.push(page);
             cur_phys = cur_phys.next();
         }
-        Ok((start_paddr, ret))
+        Ok(ret)
     }
 
     /// # 拷贝管理器中原有页面并加入管理器，同时拷贝原页面内容
@@ -257,12 +282,24 @@ fn page_reclaim_thread() -> i32 {
         // log::info!("usage{:?}", usage);
 
         // 保留4096个页面，总计16MB的空闲空间
+        let mut freed = 0;
         if usage.free().data() < 4096 {
             let page_to_free = 4096;
-            page_reclaimer_lock_irqsave().shrink_list(PageFrameCount::new(page_to_free));
+            freed = page_reclaimer_lock_irqsave().shrink_list(PageFrameCount::new(page_to_free));
+            if freed < page_to_free {
+                // 文件页不足时，将匿名页换出到交换设备
+                freed += swap::shrink_anon_list(page_to_free - freed);
+            }
         } else {
             //TODO 暂时让页面回收线程负责脏页回写任务，后续需要分离
             page_reclaimer_lock_irqsave().flush_dirty_pages();
+        }
+
+        // 回收过程中引用的地址空间必须在释放回收器的锁之后再释放
+        let spaces = page_reclaimer_lock_irqsave().take_deferred_spaces();
+        drop(spaces);
+
+        if freed == 0 {
             // 休眠5秒
             // log::info!("sleep");
             let _ = nanosleep(PosixTimeSpec::new(0, 500_000_000));
@@ -278,12 +315,18 @@ pub fn page_reclaimer_lock_irqsave() -> SpinLockGuard<'static, PageReclaimer> {
 /// 页面回收器
 pub struct PageReclaimer {
     lru: LruCache<PhysAddr, Arc<Page>>,
+    /// 非活跃匿名页链表，其中的页面可以被换出到交换设备
+    inactive_anon: LruCache<PhysAddr, Arc<Page>>,
+    /// 回写脏页时引用的地址空间。若在持有回收器的锁时释放最后一个引用，销毁地址空间会导致死锁
+    deferred_spaces: Vec<Arc<AddressSpace>>,
 }
 
 impl PageReclaimer {
     pub fn new() -> Self {
         Self {
             lru: LruCache::unbounded(),
+            inactive_anon: LruCache::unbounded(),
+            deferred_spaces: Vec::new(),
         }
     }
 
@@ -299,29 +342,85 @@ impl PageReclaimer {
         self.lru.pop(paddr)
     }
 
+    /// 将匿名页加入非活跃匿名页链表
+    pub fn insert_anon_page(&mut self, paddr: PhysAddr, page: &Arc<Page>) {
+        self.inactive_anon.put(paddr, page.clone());
+    }
+
+    /// 将匿名页从非活跃匿名页链表中移除
+    pub fn remove_anon_page(&mut self, paddr: &PhysAddr) -> Option<Arc<Page>> {
+        self.inactive_anon.pop(paddr)
+    }
+
+    /// 取出最久未被使用的匿名页
+    pub fn pop_anon_page(&mut self) -> Option<Arc<Page>> {
+        self.inactive_anon.pop_lru().map(|(_, page)| page)
+    }
+
+    /// 将页面移动到所在链表的最久未使用端，使其优先被回收
+    pub fn deactivate_page(&mut self, paddr: &PhysAddr) {
+        self.lru.demote(paddr);
+        self.inactive_anon.demote(paddr);
+    }
+
+    /// 非活跃匿名页链表的长度
+    pub fn nr_anon_pages(&self) -> usize {
+        self.inactive_anon.len()
+    }
+
+    /// 取出回写脏页时引用的地址空间，调用者需要在释放回收器的锁之后再释放它们
+    pub fn take_deferred_spaces(&mut self) -> Vec<Arc<AddressSpace>> {
+        mem::take(&mut self.deferred_spaces)
+    }
+
     /// lru链表缩减
     /// ## 参数
     ///
     /// - `count`: 需要缩减的页面数量
-    pub fn shrink_list(&mut self, count: PageFrameCount) {
+    ///
+    /// ## 返回值
+    /// - 实际回收的页面数量
+    pub fn shrink_list(&mut self, count: PageFrameCount) -> usize {
+        let mut freed = 0;
         for _ in 0..count.data() {
-            let (_, page) = self.lru.pop_lru().expect("pagecache is empty");
+            let (_, page) = match self.lru.pop_lru() {
+                Some(entry) => entry,
+                None => break,
+            };
             let mut guard = page.write_irqsave();
             if let PageType::File(info) = guard.page_type().clone() {
                 let page_cache = &info.page_cache;
                 let page_index = info.index;
                 let paddr = guard.phys_address();
-                if guard.flags().contains(PageFlags::PG_DIRTY) {
+                // 被mlock锁定的页面不能被回收
+                let locked = guard.vma_set().iter().any(|vma| {
+                    vma.try_lock_irqsave().map_or(true, |vma| {
+                        vma.vm_flags().contains(super::VmFlags::VM_LOCKED)
+                    })
+                });
+                let reclaimable = if locked {
+                    false
+                } else if guard.flags().contains(PageFlags::PG_DIRTY) {
                     // 先回写脏页
-                    Self::page_writeback(&mut guard, true);
+                    Self::page_writeback(&mut guard, true, &mut self.deferred_spaces)
+                } else {
+                    // 仍被映射的干净页面暂不回收
+                    guard.map_count() == 0
+                };
+                if !reclaimable {
+                    drop(guard);
+                    self.lru.put(paddr, page);
+                    continue;
                 }
 
                 // 删除页面
                 page_cache.lock_irqsave().remove_page(page_index);
                 page_manager_lock_irqsave().remove_page(&paddr);
                 self.remove_page(&paddr);
+                freed += 1;
             }
         }
+        freed
     }
 
     /// 唤醒页面回收线程
@@ -335,43 +434,60 @@ impl PageReclaimer {
     ///
     /// - `guard`: 需要回写的脏页
     /// - `unmap`: 是否取消映射
+    /// - `deferred_spaces`: 用于保存回写过程中引用的地址空间，由调用者在释放锁之后释放
     ///
     /// ## 返回值
-    /// - VmFaultReason: 页面错误处理信息标志
-    pub fn page_writeback(guard: &mut RwLockWriteGuard<InnerPage>, unmap: bool) {
+    /// - true: 回写成功
+    /// - false: 页面所在的地址空间正在被使用，暂时无法回写
+    pub fn page_writeback(
+        guard: &mut RwLockWriteGuard<InnerPage>,
+        unmap: bool,
+        deferred_spaces: &mut Vec<Arc<AddressSpace>>,
+    ) -> bool {
         // log::debug!("page writeback: {:?}", guard.phys_addr);
 
         let (page_cache, page_index) = match guard.page_type() {
             PageType::File(info) => (info.page_cache.clone(), info.index),
             _ => {
                 log::warn!("try to writeback a non-file page");
-                return;
+                return false;
             }
         };
         let paddr = guard.phys_address();
         let inode = page_cache.inode().clone().unwrap().upgrade().unwrap();
 
-        for vma in guard.vma_set() {
-            let address_space = vma.lock_irqsave().address_space().and_then(|x| x.upgrade());
+        // 其他路径按照 地址空间->VMA->页面 的顺序加锁，而这里已经持有页面的锁，因此只能尝试加锁
+        let vmas = guard.vma_set().iter().cloned().collect::<Vec<_>>();
+        for vma in vmas {
+            let vma_guard = match vma.try_lock_irqsave() {
+                Ok(vma_guard) => vma_guard,
+                Err(_) => return false,
+            };
+            let address_space = vma_guard.address_space().and_then(|x| x.upgrade());
+            let virt = vma_guard.page_address(page_index).unwrap();
+            drop(vma_guard);
             if address_space.is_none() {
                 continue;
             }
             let address_space = address_space.unwrap();
-            let mut guard = address_space.write();
-            let mapper = &mut guard.user_mapper.utable;
-            let virt = vma.lock_irqsave().page_address(page_index).unwrap();
+            deferred_spaces.push(address_space.clone());
+            let mut space_guard = match address_space.try_write_irqsave() {
+                Some(space_guard) => space_guard,
+                None => return false,
+            };
+            let mapper = &mut space_guard.user_mapper.utable;
             if unmap {
                 unsafe {
-                    // 取消页表映射
-                    mapper.unmap(virt, false).unwrap().flush();
+                    // 取消页表映射，物理页在页面被移出页面管理器后释放
+                    if let Some((_, _, flush)) = mapper.unmap_phys(virt, false) {
+                        flush.flush();
+                    }
                 }
-            } else {
+                guard.remove_vma(&vma);
+            } else if let Some(entry) = mapper.get_entry(virt, 0) {
                 unsafe {
                     // 保护位设为只读
-                    mapper.remap(
-                        virt,
-                        mapper.get_entry(virt, 0).unwrap().flags().set_write(false),
-                    )
+                    mapper.remap(virt, entry.flags().set_write(false))
                 };
             }
         }
@@ -405,6 +521,7 @@ impl PageReclaimer {
 
         // 清除标记
         guard.remove_flags(PageFlags::PG_DIRTY);
+        true
     }
 
     /// lru脏页刷新
@@ -414,7 +531,7 @@ impl PageReclaimer {
         for (_paddr, page) in iter {
             let mut guard = page.write_irqsave();
             if guard.flags().contains(PageFlags::PG_DIRTY) {
-                Self::page_writeback(&mut guard, false);
+                Self::page_writeback(&mut guard, false, &mut self.deferred_spaces);
             }
         }
     }
@@ -677,6 +794,16 @@ pub enum PageType {
     File(FileMapInfo),
     /// 共享内存页，记录ShmId
     Shm(ShmId),
+    /// 私有匿名页，可以被换出到交换设备
+    Anon(AnonMapInfo),
+    /// KSM合并后的只读匿名页，可能被多个VMA共享，不会被换出
+    Ksm,
+}
+
+#[derive(Debug, Clone)]
+pub struct AnonMapInfo {
+    /// 页面被映射到的虚拟地址（fork出的进程中的虚拟地址与之相同，mremap搬动页面时随之更新）
+    pub vaddr: VirtAddr,
 }
 
 #[derive(Debug, Clone)]
@@ -823,10 +950,16 @@ impl<Arch: MemoryManagementArch> PageTable<Arch> {
             return None;
         }
 
+        let entry = self.entry(index)?;
+        // 大页的页表项直接指向物理页，而不是下一级页表
+        if Arch::ENTRY_FLAG_HUGE_PAGE != 0 && entry.flags().has_flag(Arch::ENTRY_FLAG_HUGE_PAGE) {
+            return None;
+        }
+
         // 返回下一级页表
         return Some(PageTable::new(
             self.entry_base(index)?,
-            self.entry(index)?.address().ok()?,
+            entry.address().ok()?,
             self.level - 1,
         ));
     }
@@ -849,7 +982,11 @@ impl<Arch: MemoryManagementArch> PageTable<Arch> {
         if self.level == 0 {
             for i in 0..Arch::PAGE_ENTRY_NUM {
                 if let Some(mut entry) = self.entry(i) {
-                    if entry.present() {
+                    if let Some(swap_entry) = SwapEntry::from_pte(&entry) {
+                        // 被换出的页面，新旧页表共享交换槽位
+                        swap_duplicate(swap_entry);
+                        new_table.set_entry(i, entry);
+                    } else if entry.present() {
                         if copy_on_write {
                             let mut new_flags = entry.flags().set_write(false);
                             entry.set_flags(new_flags);
@@ -1489,51 +1626,65 @@ impl<Arch: MemoryManagementArch, F: FrameAllocator> PageMapper<Arch, F> {
         }
     }
 
-    /// 进行大页映射
-    pub unsafe fn map_huge_page(
+    /// 将大页映射到虚拟地址，所需的各级页表不存在时会被分配
+    ///
+    /// ## 参数
+    ///
+    /// - `virt`: 虚拟地址，需要按大页大小对齐
+    /// - `phys`: 大页的物理地址，需要按大页大小对齐
+    /// - `level`: 大页所在的页表层级，1为2M大页，2为1G大页
+    /// - `flags`: 页表项的标志
+    ///
+    /// ## 返回值
+    /// - Some(PageFlush<Arch>): 页表项刷新器
+    /// - None: 参数不合法、页表分配失败，或者该范围已经存在映射
+    pub unsafe fn map_huge_phys(
         &mut self,
         virt: VirtAddr,
+        phys: PhysAddr,
+        level: usize,
         flags: EntryFlags<Arch>,
     ) -> Option<PageFlush<Arch>> {
-        // 验证虚拟地址是否对齐
-        if !(virt.check_aligned(Arch::PAGE_SIZE)) {
-            error!("Try to map unaligned page: virt={:?}", virt);
+        let huge_size = Arch::PAGE_SIZE << (Arch::PAGE_ENTRY_SHIFT * level);
+        if level == 0
+            || level > 2
+            || !(virt.check_aligned(huge_size) && phys.check_aligned(huge_size))
+        {
+            error!(
+                "Try to map unaligned huge page: virt={:?}, phys={:?}, level={}",
+                virt, phys, level
+            );
             return None;
         }
 
         let virt = VirtAddr::new(virt.data() & (!Arch::PAGE_NEGATIVE_MASK));
 
         let mut table = self.table();
-        loop {
+        while table.level() > level {
             let i = table.index_of(virt)?;
-            assert!(i < Arch::PAGE_ENTRY_NUM);
-            let next_table = table.next_level_table(i);
-            if let Some(next_table) = next_table {
-                table = next_table;
-            } else {
-                break;
-            }
-        }
-
-        // 支持2M、1G大页，即页表层级为1、2级的页表可以映射大页
-        if table.level == 0 || table.level > 2 {
-            return None;
+            table = match table.next_level_table(i) {
+                Some(next_table) => next_table,
+                None => {
+                    // 不覆盖已有的大页映射
+                    if !table.entry(i)?.empty() {
+                        return None;
+                    }
+                    let frame = self.frame_allocator.allocate_one()?;
+                    MMArch::write_bytes(MMArch::phys_2_virt(frame).unwrap(), 0, MMArch::PAGE_SIZE);
+                    let table_flags: EntryFlags<Arch> =
+                        EntryFlags::new_page_table(virt.kind() == PageTableKind::User);
+                    table.set_entry(i, PageEntry::new(frame, table_flags));
+                    table.next_level_table(i)?
+                }
+            };
         }
 
-        let (phys, count) = self.frame_allocator.allocate(PageFrameCount::new(
-            Arch::PAGE_ENTRY_NUM.pow(table.level as u32),
-        ))?;
-
-        MMArch::write_bytes(
-            MMArch::phys_2_virt(phys).unwrap(),
-            0,
-            MMArch::PAGE_SIZE * count.data(),
-        );
-
+        compiler_fence(Ordering::SeqCst);
         table.set_entry(
             table.index_of(virt)?,
             PageEntry::new(phys, flags.set_huge_page(true)),
         )?;
+        compiler_fence(Ordering::SeqCst);
         Some(PageFlush::new(virt))
     }
 
@@ -1750,7 +1901,34 @@ impl<Arch: MemoryManagementArch, F: FrameAllocator> PageMapper<Arch, F> {
         }
 
         let table = self.table();
-        return unmap_phys_inner(virt, &table, unmap_parents, self.allocator_mut())
+        return unmap_phys_inner(virt, &table, 0, unmap_parents, self.allocator_mut())
+            .map(|(paddr, flags)| (paddr, flags, PageFlush::<Arch>::new(virt)));
+    }
+
+    /// 取消大页的映射，并返回大页的物理地址和页表项的flags
+    ///
+    /// ## 参数
+    ///
+    /// - virt 虚拟地址，需要按大页大小对齐
+    /// - level 大页所在的页表层级
+    /// - unmap_parents 是否在父页表内，取消空闲子页表的映射
+    ///
+    /// ## 返回值
+    ///
+    /// 如果取消成功，返回物理地址和页表项的flags，否则返回None
+    pub unsafe fn unmap_huge_phys(
+        &mut self,
+        virt: VirtAddr,
+        level: usize,
+        unmap_parents: bool,
+    ) -> Option<(PhysAddr, EntryFlags<Arch>, PageFlush<Arch>)> {
+        if !virt.check_aligned(Arch::PAGE_SIZE << (Arch::PAGE_ENTRY_SHIFT * level)) {
+            error!("Try to unmap unaligned huge page: virt={:?}", virt);
+            return None;
+        }
+
+        let table = self.table();
+        return unmap_phys_inner(virt, &table, level, unmap_parents, self.allocator_mut())
             .map(|(paddr, flags)| (paddr, flags, PageFlush::<Arch>::new(virt)));
     }
 
@@ -1780,6 +1958,7 @@ impl<Arch: MemoryManagementArch, F: FrameAllocator> PageMapper<Arch, F> {
 ///
 /// - vaddr 虚拟地址
 /// - table 页表
+/// - level 被取消映射的页表项所在的层级，普通页为0，大页为1或2
 /// - unmap_parents 是否在父页表内，取消空闲子页表的映射
 /// - allocator 页面分配器（如果页表从这个分配器分配，那么在取消映射时，也需要归还到这个分配器内）
 ///
@@ -1789,14 +1968,15 @@ impl<Arch: MemoryManagementArch, F: FrameAllocator> PageMapper<Arch, F> {
 unsafe fn unmap_phys_inner<Arch: MemoryManagementArch>(
     vaddr: VirtAddr,
     table: &PageTable<Arch>,
+    level: usize,
     unmap_parents: bool,
     allocator: &mut impl FrameAllocator,
 ) -> Option<(PhysAddr, EntryFlags<Arch>)> {
     // 获取页表项的索引
     let i = table.index_of(vaddr)?;
 
-    // 如果当前是最后一级页表，直接取消页面映射
-    if table.level() == 0 {
+    // 如果已经到达目标层级，直接取消页面映射
+    if table.level() == level {
         let entry = table.entry(i)?;
         table.set_entry(i, PageEntry::from_usize(0));
         return Some((entry.address().ok()?, entry.flags()));
@@ -1804,17 +1984,17 @@ unsafe fn unmap_phys_inner<Arch: MemoryManagementArch>(
 
     let subtable = table.next_level_table(i)?;
     // 递归地取消映射
-    let result = unmap_phys_inner(vaddr, &subtable, unmap_parents, allocator)?;
+    let result = unmap_phys_inner(vaddr, &subtable, level, unmap_parents, allocator)?;
 
     // TODO: This is a bad idea for architectures where the kernel mappings are done in the process tables,
     // as these mappings may become out of sync
     if unmap_parents {
         // 如果子页表已经没有映射的页面了，就取消子页表的映射
 
-        // 检查子页表中是否还有映射的页面
+        // 检查子页表中是否还有映射的页面（被换出的页面也需要保留）
         let x = (0..Arch::PAGE_ENTRY_NUM)
             .map(|k| subtable.entry(k).expect("invalid page entry"))
-            .any(|e| e.present());
+            .any(|e| !e.empty());
         if !x {
             // 如果没有，就取消子页表的映射
             table.set_entry(i, PageEntry::from_usize(0));
@@ -0,0 +1,918 @@
+//! 匿名页的交换(swap)支持
+//!
+//! 被换出的匿名页在页表中以一个不存在的页表项(swap entry)表示，其中记录了交换设备的编号以及页面在设备中的槽位。
+//! 访问被换出的页面时，由`PageFaultHandler::do_swap_page`把页面读回内存。
+//!
+//! 交换设备可以是块设备或者普通文件，但都必须以`mkswap`生成的交换区头部开始。
+
+use alloc::{collections::BTreeMap, sync::Arc, vec, vec::Vec};
+use log::info;
+use system_error::SystemError;
+
+use crate::{
+    arch::{mm::PageMapper, MMArch},
+    driver::base::block::{block_device::LBA_SIZE, gendisk::GenDisk, manager::block_dev_manager},
+    filesystem::vfs::{
+        fcntl::AtFlags, utils::user_path_at, FilePrivateData, FileType, IndexNode,
+        VFS_MAX_FOLLOW_SYMLINK_TIMES,
+    },
+    libs::spinlock::SpinLock,
+    process::{capability::capable, cred::CAPFlags, ProcessManager},
+};
+
+use super::{
+    page::{
+        page_manager_lock_irqsave, page_reclaimer_lock_irqsave, AnonMapInfo, InactiveFlusher, Page,
+        PageEntry, PageFlags, PageType,
+    },
+    ucontext::{AddressSpace, InnerAddressSpace, LockedVMA},
+    MemoryManagementArch, VirtAddr, VmFlags,
+};
+
+/// swap entry中交换设备编号所在的位置，第0位为页表项的存在位，必须为0
+const SWP_TYPE_SHIFT: usize = 1;
+/// 交换设备编号的位数
+const SWP_TYPE_BITS: usize = 4;
+const SWP_TYPE_MASK: usize = (1 << SWP_TYPE_BITS) - 1;
+
+/// 最多能同时启用的交换设备数量
+pub const MAX_SWAPFILES: usize = 1 << SWP_TYPE_BITS;
+
+/// 交换区头部的签名，位于第一页的末尾
+const SWAP_MAGIC: &[u8] = b"SWAPSPACE2";
+/// 交换区头部的版本号、页数等信息在第一页中的偏移（前1024字节为引导扇区）
+const SWAP_HEADER_INFO_OFFSET: usize = 1024;
+/// 坏页列表在第一页中的偏移
+const SWAP_HEADER_BADPAGES_OFFSET: usize = 1536;
+
+/// 不可使用的槽位（交换区头部或者坏页）
+const SWAP_MAP_BAD: u32 = u32::MAX;
+
+/// swapon的flags中表示优先级的部分
+const SWAP_FLAG_PRIO_MASK: u32 = 0x7fff;
+
+bitflags! {
+    /// swapon的flags参数
+    pub struct SwapFlags: u32 {
+        /// 使用flags中指定的优先级
+        const SWAP_FLAG_PREFER = 0x8000;
+        /// 启用discard（当前忽略）
+        const SWAP_FLAG_DISCARD = 0x10000;
+        const SWAP_FLAG_DISCARD_ONCE = 0x20000;
+        const SWAP_FLAG_DISCARD_PAGES = 0x40000;
+    }
+}
+
+/// 被换出页面在页表项中的表示
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SwapEntry {
+    /// 交换设备编号
+    swap_type: usize,
+    /// 页面在交换设备中的槽位
+    offset: usize,
+}
+
+impl SwapEntry {
+    /// 从页表项中解析swap entry
+    ///
+    /// ## 返回值
+    ///
+    /// - `Some(SwapEntry)` 页表项表示一个被换出的页面
+    /// - `None` 页表项为空，或者指向一个物理页
+    pub fn from_pte<Arch: MemoryManagementArch>(entry: &PageEntry<Arch>) -> Option<Self> {
+        if entry.present() || entry.empty() || entry.protnone() {
+            return None;
+        }
+        let data = entry.data();
+        Some(Self {
+            swap_type: (data >> SWP_TYPE_SHIFT) & SWP_TYPE_MASK,
+            offset: data >> Arch::PAGE_SHIFT,
+        })
+    }
+
+    /// 生成表示当前swap entry的页表项
+    pub fn to_pte<Arch: MemoryManagementArch>(&self) -> PageEntry<Arch> {
+        // 槽位0总是被交换区头部占用，因此生成的页表项一定非空
+        PageEntry::from_usize(
+            (self.offset << Arch::PAGE_SHIFT) | (self.swap_type << SWP_TYPE_SHIFT),
+        )
+    }
+}
+
+/// 交换设备的后备存储
+#[derive(Debug)]
+enum SwapBacking {
+    Block(Arc<GenDisk>),
+    File(Arc<dyn IndexNode>),
+}
+
+impl SwapBacking {
+    /// 根据路径及其对应的inode打开后备存储
+    fn open(path: &str, inode: &Arc<dyn IndexNode>) -> Result<Self, SystemError> {
+        match inode.metadata()?.file_type {
+            FileType::BlockDevice => block_dev_manager()
+                .lookup_gendisk_by_path(path)
+                .or_else(|| {
+                    let name = inode.dname().ok()?;
+                    block_dev_manager().lookup_gendisk_by_path(name.as_ref())
+                })
+                .map(SwapBacking::Block)
+                .ok_or(SystemError::ENODEV),
+            FileType::File => Ok(SwapBacking::File(inode.clone())),
+            _ => Err(SystemError::EINVAL),
+        }
+    }
+
+    /// 后备存储的大小（字节）
+    fn size(&self) -> Result<usize, SystemError> {
+        match self {
+            SwapBacking::Block(disk) => Ok(disk.range().len() * LBA_SIZE),
+            SwapBacking::File(inode) => Ok(inode.metadata()?.size as usize),
+        }
+    }
+
+    fn same_as(&self, other: &SwapBacking) -> bool {
+        match (self, other) {
+            (SwapBacking::Block(a), SwapBacking::Block(b)) => Arc::ptr_eq(a, b),
+            (SwapBacking::File(a), SwapBacking::File(b)) => match (a.metadata(), b.metadata()) {
+                (Ok(a), Ok(b)) => a.dev_id == b.dev_id && a.inode_id == b.inode_id,
+                _ => false,
+            },
+            _ => false,
+        }
+    }
+
+    fn read(&self, offset: usize, buf: &mut [u8]) -> Result<(), SystemError> {
+        let len = match self {
+            SwapBacking::Block(disk) => disk.read_at_bytes(buf, offset)?,
+            SwapBacking::File(inode) => inode.read_direct(
+                offset,
+                buf.len(),
+                buf,
+                SpinLock::new(FilePrivateData::Unused).lock(),
+            )?,
+        };
+        if len != buf.len() {
+            return Err(SystemError::EIO);
+        }
+        Ok(())
+    }
+
+    fn write(&self, offset: usize, buf: &[u8]) -> Result<(), SystemError> {
+        let len = match self {
+            SwapBacking::Block(disk) => disk.write_at_bytes(buf, offset)?,
+            SwapBacking::File(inode) => inode.write_direct(
+                offset,
+                buf.len(),
+                buf,
+                SpinLock::new(FilePrivateData::Unused).lock(),
+            )?,
+        };
+        if len != buf.len() {
+            return Err(SystemError::EIO);
+        }
+        Ok(())
+    }
+}
+
+/// 已启用的交换设备
+#[derive(Debug)]
+struct SwapDevice {
+    swap_type: usize,
+    backing: SwapBacking,
+    prio: i32,
+    inner: SpinLock<InnerSwapDevice>,
+}
+
+#[derive(Debug)]
+struct InnerSwapDevice {
+    /// 每个槽位的引用计数，0表示空闲
+    swap_map: Vec<u32>,
+    /// 可用的槽位数（不含交换区头部和坏页）
+    pages: usize,
+    /// 已分配的槽位数
+    inuse_pages: usize,
+    /// 下一次分配时开始查找的槽位
+    cluster_next: usize,
+    /// 是否允许分配新的槽位（swapoff时会被清除）
+    writeok: bool,
+}
+
+impl InnerSwapDevice {
+    fn alloc_slot(&mut self, count: u32) -> Option<usize> {
+        if !self.writeok || self.inuse_pages >= self.pages {
+            return None;
+        }
+        let nr_slots = self.swap_map.len();
+        for i in 0..nr_slots {
+            let offset = (self.cluster_next + i) % nr_slots;
+            if self.swap_map[offset] == 0 {
+                self.swap_map[offset] = count;
+                self.inuse_pages += 1;
+                self.cluster_next = offset + 1;
+                return Some(offset);
+            }
+        }
+        None
+    }
+}
+
+impl SwapDevice {
+    fn read_page(&self, offset: usize, buf: &mut [u8]) -> Result<(), SystemError> {
+        self.backing.read(offset * MMArch::PAGE_SIZE, buf)
+    }
+
+    fn write_page(&self, offset: usize, buf: &[u8]) -> Result<(), SystemError> {
+        self.backing.write(offset * MMArch::PAGE_SIZE, buf)
+    }
+}
+
+struct SwapManager {
+    /// 以交换设备编号为键
+    devices: BTreeMap<usize, Arc<SwapDevice>>,
+    /// 未指定优先级的交换设备使用的优先级，依次递减
+    least_priority: i32,
+}
+
+static SWAP_MANAGER: SpinLock<SwapManager> = SpinLock::new(SwapManager {
+    devices: BTreeMap::new(),
+    least_priority: 0,
+});
+
+fn swap_device(swap_type: usize) -> Option<Arc<SwapDevice>> {
+    SWAP_MANAGER.lock_irqsave().devices.get(&swap_type).cloned()
+}
+
+/// 分配一个交换槽位
+///
+/// ## 参数
+///
+/// - `count` 槽位的初始引用计数，即将要指向该槽位的页表项数量
+///
+/// ## 返回值
+///
+/// - `Some(SwapEntry)` 优先级最高的可用交换设备中的槽位
+/// - `None` 交换空间不足
+fn swap_alloc(count: u32) -> Option<SwapEntry> {
+    let mut devices = SWAP_MANAGER
+        .lock_irqsave()
+        .devices
+        .values()
+        .cloned()
+        .collect::<Vec<_>>();
+    devices.sort_by(|a, b| b.prio.cmp(&a.prio));
+
+    devices.iter().find_map(|device| {
+        let offset = device.inner.lock_irqsave().alloc_slot(count)?;
+        Some(SwapEntry {
+            swap_type: device.swap_type,
+            offset,
+        })
+    })
+}
+
+/// 增加槽位的引用计数（复制指向该槽位的页表项时调用）
+pub fn swap_duplicate(entry: SwapEntry) {
+    if let Some(device) = swap_device(entry.swap_type) {
+        let mut inner = device.inner.lock_irqsave();
+        if let Some(count) = inner.swap_map.get_mut(entry.offset) {
+            if *count != 0 && *count != SWAP_MAP_BAD {
+                *count += 1;
+            }
+        }
+    }
+}
+
+/// 减少槽位的引用计数，引用计数为0时释放槽位
+pub fn swap_free(entry: SwapEntry) {
+    if let Some(device) = swap_device(entry.swap_type) {
+        let mut inner = device.inner.lock_irqsave();
+        if let Some(count) = inner.swap_map.get_mut(entry.offset) {
+            if *count == 0 || *count == SWAP_MAP_BAD {
+                log::warn!("swap_free: bad swap entry {:?}", entry);
+                return;
+            }
+            *count -= 1;
+            if *count == 0 {
+                inner.inuse_pages -= 1;
+            }
+        }
+    }
+}
+
+/// 交换空间的总页数
+pub fn swap_total_pages() -> usize {
+    SWAP_MANAGER
+        .lock_irqsave()
+        .devices
+        .values()
+        .map(|device| {
+            let inner = device.inner.lock_irqsave();
+            if inner.writeok {
+                inner.pages
+            } else {
+                0
+            }
+        })
+        .sum()
+}
+
+/// 交换空间的空闲页数
+pub fn swap_free_pages() -> usize {
+    SWAP_MANAGER
+        .lock_irqsave()
+        .devices
+        .values()
+        .map(|device| {
+            let inner = device.inner.lock_irqsave();
+            if inner.writeok {
+                inner.pages - inner.inuse_pages
+            } else {
+                0
+            }
+        })
+        .sum()
+}
+
+/// 获取虚拟地址对应的swap entry
+pub fn swap_entry_at(mapper: &PageMapper, vaddr: VirtAddr) -> Option<SwapEntry> {
+    mapper
+        .get_entry(vaddr, 0)
+        .and_then(|entry| SwapEntry::from_pte(&entry))
+}
+
+/// 清除虚拟地址对应的swap entry，并返回被清除的swap entry
+///
+/// 调用者负责释放返回的swap entry对应的槽位
+pub unsafe fn take_swap_entry(mapper: &PageMapper, vaddr: VirtAddr) -> Option<SwapEntry> {
+    let entry = swap_entry_at(mapper, vaddr)?;
+    let table = mapper.get_table(vaddr, 0)?;
+    let i = table.index_of(vaddr)?;
+    table.set_entry(i, PageEntry::from_usize(0));
+    Some(entry)
+}
+
+/// 将私有匿名页加入非活跃匿名页链表，使其能够被换出
+///
+/// ## 参数
+///
+/// - `page` 匿名页，调用者不能持有页面的锁
+/// - `vaddr` 页面被映射到的虚拟地址
+pub fn lru_add_anon(page: &Arc<Page>, vaddr: VirtAddr) {
+    let mut guard = page.write_irqsave();
+    guard.set_page_type(PageType::Anon(AnonMapInfo { vaddr }));
+    guard.add_flags(PageFlags::PG_SWAPBACKED | PageFlags::PG_LRU);
+    drop(guard);
+    page_reclaimer_lock_irqsave().insert_anon_page(page.phys_address(), page);
+}
+
+/// 把被换出的页面读回内存，并在`vma`中重新建立映射
+///
+/// ## 参数
+///
+/// - `mapper` `vma`所在地址空间的页表映射器，调用者需要持有地址空间的写锁
+/// - `vma` 页面所在的VMA
+/// - `vaddr` 页面的虚拟地址
+/// - `entry` 页表项中记录的swap entry
+pub unsafe fn swap_in_page(
+    mapper: &mut PageMapper,
+    vma: &Arc<LockedVMA>,
+    vaddr: VirtAddr,
+    entry: SwapEntry,
+) -> Result<(), SystemError> {
+    let device = swap_device(entry.swap_type).ok_or(SystemError::EINVAL)?;
+    let page = page_manager_lock_irqsave().create_one_page(
+        PageType::Normal,
+        PageFlags::empty(),
+        mapper.allocator_mut(),
+    )?;
+    let paddr = page.phys_address();
+
+    let r = device.read_page(entry.offset, page.write_irqsave().as_slice_mut());
+    if let Err(e) = r {
+        page_manager_lock_irqsave().remove_page(&paddr);
+        return Err(e);
+    }
+
+    let flags = vma.lock_irqsave().flags();
+    match mapper.map_phys(vaddr, paddr, flags) {
+        Some(flush) => flush.flush(),
+        None => {
+            page_manager_lock_irqsave().remove_page(&paddr);
+            return Err(SystemError::ENOMEM);
+        }
+    }
+    page.write_irqsave().insert_vma(vma.clone());
+    swap_free(entry);
+
+    if !vma.lock_irqsave().vm_flags().contains(VmFlags::VM_SHARED) {
+        lru_add_anon(&page, vaddr);
+    }
+    Ok(())
+}
+
+/// 立即换出一个只被当前地址空间映射的匿名页，用于MADV_PAGEOUT
+///
+/// 被MADV_FREE标记且之后没有被写过的页面会被直接丢弃
+///
+/// ## 参数
+///
+/// - `mapper` 页面所在地址空间的页表，调用者需要持有该地址空间的写锁
+/// - `vma` 页面所在的VMA
+/// - `vaddr` 页面的虚拟地址
+///
+/// ## 返回值
+///
+/// - `Err(SystemError::EAGAIN)` 页面不存在、被多处映射或者不能被换出
+/// - `Err(SystemError::ENOSPC)` 没有可用的交换空间
+pub unsafe fn pageout_anon_page(
+    mapper: &PageMapper,
+    vma: &LockedVMA,
+    vaddr: VirtAddr,
+) -> Result<(), SystemError> {
+    let table = mapper.get_table(vaddr, 0).ok_or(SystemError::EAGAIN)?;
+    let i = table.index_of(vaddr).unwrap();
+    let old_entry = table.entry(i).ok_or(SystemError::EAGAIN)?;
+    if !old_entry.present() {
+        return Err(SystemError::EAGAIN);
+    }
+    let paddr = old_entry.address().map_err(|_| SystemError::EAGAIN)?;
+    let page = page_manager_lock_irqsave()
+        .get(&paddr)
+        .ok_or(SystemError::EAGAIN)?;
+
+    let mut page_guard = page.write_irqsave();
+    if !matches!(page_guard.page_type(), PageType::Anon(_))
+        || page_guard.map_count() != 1
+        || page_guard.flags().contains(PageFlags::PG_UNEVICTABLE)
+    {
+        return Err(SystemError::EAGAIN);
+    }
+
+    let dirty = old_entry.flags().has_flag(MMArch::ENTRY_FLAG_DIRTY);
+    if page_guard.flags().contains(PageFlags::PG_SWAPBACKED) || dirty {
+        let entry = swap_alloc(1).ok_or(SystemError::ENOSPC)?;
+        let device = swap_device(entry.swap_type).unwrap();
+        table.set_entry(i, entry.to_pte());
+        MMArch::invalidate_all();
+        drop(InactiveFlusher::new());
+
+        if let Err(e) = device.write_page(entry.offset, page_guard.as_slice()) {
+            table.set_entry(i, old_entry);
+            swap_free(entry);
+            return Err(e);
+        }
+        page_guard.add_flags(PageFlags::PG_SWAPBACKED);
+    } else {
+        table.set_entry(i, PageEntry::from_usize(0));
+        MMArch::invalidate_all();
+        drop(InactiveFlusher::new());
+    }
+
+    page_guard.remove_vma(vma);
+    drop(page_guard);
+    page_manager_lock_irqsave().remove_page(&paddr);
+    page_reclaimer_lock_irqsave().remove_anon_page(&paddr);
+    Ok(())
+}
+
+/// 尝试换出一个页面的结果
+enum SwapOutResult {
+    /// 页面已经被换出
+    Success,
+    /// 页面暂时不能被换出，需要放回链表
+    Retry,
+    /// 页面不能被换出，从链表中移除
+    Skip,
+    /// 交换空间不足
+    NoSpace,
+}
+
+/// 尝试把一个匿名页换出到交换设备
+///
+/// 调用者不能持有任何页面、VMA、地址空间以及页面回收器的锁
+fn try_to_swap_out(page: &Arc<Page>) -> SwapOutResult {
+    let paddr = page.phys_address();
+    let (vaddr, vmas) = {
+        let guard = page.read_irqsave();
+        let vaddr = match guard.page_type() {
+            PageType::Anon(info) => info.vaddr,
+            _ => return SwapOutResult::Skip,
+        };
+        if guard.flags().contains(PageFlags::PG_UNEVICTABLE) {
+            return SwapOutResult::Skip;
+        }
+        (vaddr, guard.vma_set().iter().cloned().collect::<Vec<_>>())
+    };
+    if vmas.is_empty() {
+        // 页面已经被释放
+        return SwapOutResult::Skip;
+    }
+
+    // 找到映射了该页面的所有地址空间
+    let mut spaces: Vec<Arc<AddressSpace>> = Vec::new();
+    let mut targets: Vec<(Arc<LockedVMA>, usize)> = Vec::new();
+    for vma in vmas {
+        let guard = vma.lock_irqsave();
+        if guard.vm_file().is_some() || guard.vm_flags().contains(VmFlags::VM_SHARED) {
+            return SwapOutResult::Skip;
+        }
+        // 被锁定的页面从链表中移除，解除锁定时再放回
+        if guard.vm_flags().contains(VmFlags::VM_LOCKED) {
+            return SwapOutResult::Skip;
+        }
+        let space = match guard.address_space().and_then(|space| space.upgrade()) {
+            Some(space) => space,
+            None => return SwapOutResult::Retry,
+        };
+        drop(guard);
+        let idx = match spaces.iter().position(|s| Arc::ptr_eq(s, &space)) {
+            Some(idx) => idx,
+            None => {
+                spaces.push(space);
+                spaces.len() - 1
+            }
+        };
+        targets.push((vma, idx));
+    }
+
+    // 持有所有地址空间的写锁，防止页表在换出过程中被修改
+    let mut guards = Vec::with_capacity(spaces.len());
+    for space in spaces.iter() {
+        match space.try_write_irqsave() {
+            Some(guard) => guards.push(guard),
+            None => return SwapOutResult::Retry,
+        }
+    }
+
+    // 检查页表项，若页面最近被访问过，则清除访问位并保留页面
+    let mut referenced = false;
+    for (_, idx) in targets.iter() {
+        let mapper = &guards[*idx].user_mapper.utable;
+        let table = match mapper.get_table(vaddr, 0) {
+            Some(table) => table,
+            None => return SwapOutResult::Skip,
+        };
+        let i = table.index_of(vaddr).unwrap();
+        let mut entry = unsafe { table.entry(i) }.unwrap();
+        if entry.address() != Ok(paddr) {
+            return SwapOutResult::Skip;
+        }
+        if entry.flags().has_flag(MMArch::ENTRY_FLAG_ACCESSED) {
+            referenced = true;
+            entry.set_flags(entry.flags().set_access(false));
+            unsafe { table.set_entry(i, entry) };
+        }
+    }
+    if referenced {
+        return SwapOutResult::Retry;
+    }
+
+    let mut page_guard = page.write_irqsave();
+    if page_guard.map_count() != targets.len() {
+        return SwapOutResult::Retry;
+    }
+
+    // 被MADV_FREE标记之后没有再被写过的页面，其内容可以直接丢弃
+    if !page_guard.flags().contains(PageFlags::PG_SWAPBACKED) {
+        let dirty = targets.iter().any(|(_, idx)| {
+            let table = guards[*idx].user_mapper.utable.get_table(vaddr, 0).unwrap();
+            let i = table.index_of(vaddr).unwrap();
+            let entry = unsafe { table.entry(i) }.unwrap();
+            entry.flags().has_flag(MMArch::ENTRY_FLAG_DIRTY)
+        });
+        if !dirty {
+            for (_, idx) in targets.iter() {
+                let table = guards[*idx].user_mapper.utable.get_table(vaddr, 0).unwrap();
+                let i = table.index_of(vaddr).unwrap();
+                unsafe { table.set_entry(i, PageEntry::from_usize(0)) };
+            }
+            unsafe { MMArch::invalidate_all() };
+            drop(InactiveFlusher::new());
+
+            for (vma, _) in targets.iter() {
+                page_guard.remove_vma(vma);
+            }
+            drop(page_guard);
+            page_manager_lock_irqsave().remove_page(&paddr);
+            drop(guards);
+            return SwapOutResult::Success;
+        }
+        // 页面被重新写入，恢复为普通匿名页
+        page_guard.add_flags(PageFlags::PG_SWAPBACKED);
+    }
+
+    let entry = match swap_alloc(targets.len() as u32) {
+        Some(entry) => entry,
+        None => return SwapOutResult::NoSpace,
+    };
+    let device = swap_device(entry.swap_type).unwrap();
+
+    // 先把页表项替换为swap entry，再写出页面，保证写出的是页面的最终内容
+    let mut old_entries = Vec::with_capacity(targets.len());
+    for (_, idx) in targets.iter() {
+        let table = guards[*idx].user_mapper.utable.get_table(vaddr, 0).unwrap();
+        let i = table.index_of(vaddr).unwrap();
+        unsafe {
+            old_entries.push(table.entry(i).unwrap());
+            table.set_entry(i, entry.to_pte());
+        }
+    }
+    // 页表可能在任意核心上处于激活状态，需要刷新所有核心的TLB
+    unsafe { MMArch::invalidate_all() };
+    drop(InactiveFlusher::new());
+
+    if device
+        .write_page(entry.offset, unsafe { page_guard.as_slice() })
+        .is_err()
+    {
+        // 写出失败，恢复原来的映射
+        for ((_, idx), old_entry) in targets.iter().zip(old_entries) {
+            let table = guards[*idx].user_mapper.utable.get_table(vaddr, 0).unwrap();
+            let i = table.index_of(vaddr).unwrap();
+            unsafe { table.set_entry(i, old_entry) };
+        }
+        for _ in 0..targets.len() {
+            swap_free(entry);
+        }
+        return SwapOutResult::Retry;
+    }
+
+    for (vma, _) in targets.iter() {
+        page_guard.remove_vma(vma);
+    }
+    drop(page_guard);
+    page_manager_lock_irqsave().remove_page(&paddr);
+    drop(guards);
+
+    SwapOutResult::Success
+}
+
+/// 从非活跃匿名页链表中换出页面
+///
+/// ## 参数
+///
+/// - `count` 需要回收的页面数量
+///
+/// ## 返回值
+///
+/// 实际换出的页面数量
+pub fn shrink_anon_list(count: usize) -> usize {
+    // 没有可用的交换空间时，只能回收被MADV_FREE标记的页面
+    let mut can_swap = swap_free_pages() > 0;
+
+    let nr_scan = page_reclaimer_lock_irqsave().nr_anon_pages();
+    let mut freed = 0;
+    for _ in 0..nr_scan {
+        if freed >= count {
+            break;
+        }
+        let page = match page_reclaimer_lock_irqsave().pop_anon_page() {
+            Some(page) => page,
+            None => break,
+        };
+        if !can_swap
+            && page
+                .read_irqsave()
+                .flags()
+                .contains(PageFlags::PG_SWAPBACKED)
+        {
+            page_reclaimer_lock_irqsave().insert_anon_page(page.phys_address(), &page);
+            continue;
+        }
+        match try_to_swap_out(&page) {
+            SwapOutResult::Success => freed += 1,
+            SwapOutResult::Skip => {}
+            SwapOutResult::Retry => {
+                page_reclaimer_lock_irqsave().insert_anon_page(page.phys_address(), &page);
+            }
+            SwapOutResult::NoSpace => {
+                page_reclaimer_lock_irqsave().insert_anon_page(page.phys_address(), &page);
+                can_swap = false;
+            }
+        }
+    }
+    freed
+}
+
+/// 把地址空间中所有位于指定交换设备上的页面读回内存
+///
+/// ## 返回值
+///
+/// 读回的页面数量
+fn unuse_address_space(
+    space: &mut InnerAddressSpace,
+    swap_type: usize,
+) -> Result<usize, SystemError> {
+    let vmas = space.mappings.iter_vmas().cloned().collect::<Vec<_>>();
+    let mapper = &mut space.user_mapper.utable;
+    let mut count = 0;
+    for vma in vmas {
+        if !vma.is_anonymous() {
+            continue;
+        }
+        let region = *vma.lock_irqsave().region();
+        for page in region.pages() {
+            let vaddr = page.virt_address();
+            if let Some(entry) = swap_entry_at(mapper, vaddr) {
+                if entry.swap_type == swap_type {
+                    unsafe { swap_in_page(mapper, &vma, vaddr, entry)? };
+                    count += 1;
+                }
+            }
+        }
+    }
+    Ok(count)
+}
+
+/// 把交换设备上的所有页面读回内存
+fn try_to_unuse(device: &Arc<SwapDevice>) -> Result<(), SystemError> {
+    loop {
+        let mut spaces: Vec<Arc<AddressSpace>> = Vec::new();
+        for pid in ProcessManager::get_all_processes() {
+            let pcb = match ProcessManager::find(pid) {
+                Some(pcb) => pcb,
+                None => continue,
+            };
+            let vm = pcb.basic().user_vm();
+            if let Some(vm) = vm {
+                if !spaces.iter().any(|space| Arc::ptr_eq(space, &vm)) {
+                    spaces.push(vm);
+                }
+            }
+        }
+
+        let mut count = 0;
+        for space in spaces.iter() {
+            count += unuse_address_space(&mut space.write_irqsave(), device.swap_type)?;
+        }
+        drop(spaces);
+
+        if device.inner.lock_irqsave().inuse_pages == 0 {
+            return Ok(());
+        }
+        if count == 0 {
+            // 剩余的槽位已经没有页表项引用
+            return Err(SystemError::EBUSY);
+        }
+    }
+}
+
+/// 解析交换区头部
+///
+/// ## 返回值
+///
+/// 槽位的引用计数表，交换区头部和坏页被标记为不可用
+fn parse_swap_header(header: &[u8], size: usize) -> Result<Vec<u32>, SystemError> {
+    let page_size = MMArch::PAGE_SIZE;
+    if &header[page_size - SWAP_MAGIC.len()..page_size] != SWAP_MAGIC {
+        return Err(SystemError::EINVAL);
+    }
+
+    let read_u32 =
+        |offset: usize| u32::from_ne_bytes(header[offset..offset + 4].try_into().unwrap()) as usize;
+    let version = read_u32(SWAP_HEADER_INFO_OFFSET);
+    let last_page = read_u32(SWAP_HEADER_INFO_OFFSET + 4);
+    let nr_badpages = read_u32(SWAP_HEADER_INFO_OFFSET + 8);
+    if version != 1 {
+        return Err(SystemError::EINVAL);
+    }
+    let max_badpages = (page_size - SWAP_MAGIC.len() - SWAP_HEADER_BADPAGES_OFFSET) / 4;
+    if nr_badpages > max_badpages {
+        return Err(SystemError::EINVAL);
+    }
+
+    let nr_slots = core::cmp::min(last_page + 1, size / page_size);
+    if nr_slots <= 1 {
+        return Err(SystemError::EINVAL);
+    }
+    let mut swap_map = vec![0; nr_slots];
+    swap_map[0] = SWAP_MAP_BAD;
+    for i in 0..nr_badpages {
+        let bad = read_u32(SWAP_HEADER_BADPAGES_OFFSET + i * 4);
+        if bad == 0 || bad > last_page {
+            return Err(SystemError::EINVAL);
+        }
+        if bad < nr_slots {
+            swap_map[bad] = SWAP_MAP_BAD;
+        }
+    }
+    Ok(swap_map)
+}
+
+/// 根据路径打开交换设备的后备存储
+fn open_swap_backing(path: &str) -> Result<SwapBacking, SystemError> {
+    let pcb = ProcessManager::current_pcb();
+    let (begin_inode, remain_path) = user_path_at(&pcb, AtFlags::AT_FDCWD.bits(), path)?;
+    let inode = begin_inode.lookup_follow_symlink(&remain_path, VFS_MAX_FOLLOW_SYMLINK_TIMES)?;
+    SwapBacking::open(path, &inode)
+}
+
+/// 启用交换设备
+///
+/// ## 参数
+///
+/// - `path` 块设备或者交换文件的路径
+/// - `flags` swapon的flags参数，低15位为优先级
+pub fn do_swapon(path: &str, flags: u32) -> Result<(), SystemError> {
+    if !capable(CAPFlags::CAP_SYS_ADMIN) {
+        return Err(SystemError::EPERM);
+    }
+    let swap_flags =
+        SwapFlags::from_bits(flags & !SWAP_FLAG_PRIO_MASK).ok_or(SystemError::EINVAL)?;
+
+    let backing = open_swap_backing(path)?;
+    let mut header = vec![0u8; MMArch::PAGE_SIZE];
+    backing.read(0, &mut header).map_err(|e| {
+        if e == SystemError::ENOSYS {
+            // 文件系统不支持直接读写，无法作为交换文件
+            SystemError::EINVAL
+        } else {
+            e
+        }
+    })?;
+    let swap_map = parse_swap_header(&header, backing.size()?)?;
+    let pages = swap_map.iter().filter(|count| **count == 0).count();
+    if pages == 0 {
+        return Err(SystemError::EINVAL);
+    }
+
+    let mut manager = SWAP_MANAGER.lock_irqsave();
+    if manager
+        .devices
+        .values()
+        .any(|device| device.backing.same_as(&backing))
+    {
+        return Err(SystemError::EBUSY);
+    }
+    let swap_type = (0..MAX_SWAPFILES)
+        .find(|swap_type| !manager.devices.contains_key(swap_type))
+        .ok_or(SystemError::EPERM)?;
+    let prio = if swap_flags.contains(SwapFlags::SWAP_FLAG_PREFER) {
+        (flags & SWAP_FLAG_PRIO_MASK) as i32
+    } else {
+        manager.least_priority -= 1;
+        manager.least_priority
+    };
+
+    manager.devices.insert(
+        swap_type,
+        Arc::new(SwapDevice {
+            swap_type,
+            backing,
+            prio,
+            inner: SpinLock::new(InnerSwapDevice {
+                swap_map,
+                pages,
+                inuse_pages: 0,
+                cluster_next: 1,
+                writeok: true,
+            }),
+        }),
+    );
+    drop(manager);
+
+    info!(
+        "Adding {}k swap on {}. Priority:{}",
+        (pages * MMArch::PAGE_SIZE) >> 10,
+        path,
+        prio
+    );
+    Ok(())
+}
+
+/// 停用交换设备，设备上所有的页面都会被读回内存
+pub fn do_swapoff(path: &str) -> Result<(), SystemError> {
+    if !capable(CAPFlags::CAP_SYS_ADMIN) {
+        return Err(SystemError::EPERM);
+    }
+
+    let backing = open_swap_backing(path)?;
+    let device = SWAP_MANAGER
+        .lock_irqsave()
+        .devices
+        .values()
+        .find(|device| device.backing.same_as(&backing))
+        .cloned()
+        .ok_or(SystemError::EINVAL)?;
+
+    {
+        let mut inner = device.inner.lock_irqsave();
+        if !inner.writeok {
+            // 另一个swapoff正在进行
+            return Err(SystemError::EBUSY);
+        }
+        inner.writeok = false;
+    }
+
+    if let Err(e) = try_to_unuse(&device) {
+        device.inner.lock_irqsave().writeok = true;
+        return Err(e);
+    }
+
+    SWAP_MANAGER
+        .lock_irqsave()
+        .devices
+        .remove(&device.swap_type);
+    Ok(())
+}
@@ -7,16 +7,32 @@ use system_error::SystemError;
 use crate::{
     arch::MMArch,
     driver::base::block::SeekFrom,
+    filesystem::{
+        hugetlbfs::{hugetlb_file_setup, hugetlbfs_inode},
+        vfs::MAX_PATHLEN,
+    },
     ipc::shm::ShmFlags,
-    libs::align::{check_aligned, page_align_up},
+    libs::align::{align_up, check_aligned, page_align_down, page_align_up},
     mm::MemoryManagementArch,
-    syscall::Syscall,
+    process::{capability::capable, cred::CAPFlags, ProcessManager},
+    syscall::{
+        user_access::{check_and_clone_cstr, UserBufferWriter},
+        Syscall,
+    },
 };
 
 use super::{
     allocator::page_frame::{PageFrameCount, VirtPageFrame},
+    hugetlb::{hstate_from_flags, vma_hstate},
+    mempolicy::{
+        copy_nodes_to_user, get_nodes, parse_mode, GetMemPolicyFlags, MbindFlags, MemPolicy,
+        MPOL_DEFAULT, MPOL_INTERLEAVE,
+    },
+    mlock::{MclFlags, MlockFlags},
+    numa::node_online_map,
+    swap::{do_swapoff, do_swapon},
     ucontext::{AddressSpace, DEFAULT_MMAP_MIN_ADDR},
-    verify_area, MsFlags, VirtAddr, VmFlags,
+    verify_area, MsFlags, VirtAddr, VirtRegion, VmFlags,
 };
 
 bitflags! {
@@ -101,6 +117,8 @@ bitflags! {
         const MADV_SOFT_OFFLINE = 101;
 
         /// 应用程序建议内核尝试合并指定范围内内容相同的页面
+        ///
+        /// 只有不同VMA之间内容相同的页面会被合并，同一个映射区域内部的重复页面不会被合并
         const MADV_MERGEABLE = 12;
         /// 取消 MADV_MERGEABLE 的效果，不再合并页面
         const MADV_UNMERGEABLE = 13;
@@ -262,14 +280,20 @@ impl Syscall {
             return Ok(address_space.brk);
         }
 
+        let old_brk = address_space.brk;
         unsafe {
             // log::debug!("brk: set_brk new_addr={:?}", new_addr);
             address_space
                 .set_brk(VirtAddr::new(page_align_up(new_addr.data())))
                 .ok();
+        }
 
-            return Ok(address_space.sbrk(0).unwrap());
+        // mlockall(MCL_FUTURE)之后扩展的堆需要立即建立页面映射
+        if let Some(region) = VirtRegion::between(old_brk, address_space.brk) {
+            let _ = address_space.mm_populate(region);
         }
+
+        return Ok(unsafe { address_space.sbrk(0).unwrap() });
     }
 
     pub fn sbrk(incr: isize) -> Result<VirtAddr, SystemError> {
@@ -305,6 +329,8 @@ impl Syscall {
         fd: i32,
         offset: usize,
     ) -> Result<usize, SystemError> {
+        // 大页的大小编码在flags的高位中
+        let hstate = hstate_from_flags(map_flags);
         let map_flags = MapFlags::from_bits_truncate(map_flags as u64);
         let prot_flags = ProtFlags::from_bits_truncate(prot_flags as u64);
 
@@ -318,13 +344,57 @@ impl Syscall {
             return Err(SystemError::EINVAL);
         }
 
-        // 暂时不支持巨页映射
-        if map_flags.contains(MapFlags::MAP_HUGETLB) {
-            error!("mmap: not support huge page mapping");
-            return Err(SystemError::ENOSYS);
+        if len == 0 {
+            return Err(SystemError::EINVAL);
         }
+
+        // hugetlbfs文件映射，以及MAP_HUGETLB匿名映射
+        let hugetlb_file = if map_flags.contains(MapFlags::MAP_ANONYMOUS) {
+            if map_flags.contains(MapFlags::MAP_HUGETLB) {
+                let hstate = hstate.ok_or(SystemError::EINVAL)?;
+                Some((hugetlb_file_setup(hstate, align_up(len, hstate.size()))?, 0))
+            } else {
+                None
+            }
+        } else {
+            let file = ProcessManager::current_pcb()
+                .fd_table()
+                .read()
+                .get_file_by_fd(fd)
+                .ok_or(SystemError::EBADF)?;
+            if hugetlbfs_inode(&file).is_some() {
+                Some((file, offset))
+            } else if map_flags.contains(MapFlags::MAP_HUGETLB) {
+                return Err(SystemError::EINVAL);
+            } else {
+                None
+            }
+        };
+
         let current_address_space = AddressSpace::current()?;
-        let start_page = if map_flags.contains(MapFlags::MAP_ANONYMOUS) {
+        {
+            // 被锁定的映射不能超过RLIMIT_MEMLOCK
+            let space = current_address_space.read();
+            if (map_flags.contains(MapFlags::MAP_LOCKED)
+                || space.def_flags.contains(VmFlags::VM_LOCKED))
+                && !space.mlock_future_ok(page_align_up(len))
+            {
+                return Err(SystemError::EAGAIN_OR_EWOULDBLOCK);
+            }
+        }
+
+        let start_page = if let Some((file, offset)) = hugetlb_file {
+            let hstate = hugetlbfs_inode(&file).unwrap().hstate();
+            current_address_space.write().hugetlb_mapping(
+                start_vaddr,
+                len,
+                prot_flags,
+                map_flags,
+                file,
+                hstate,
+                offset,
+            )?
+        } else if map_flags.contains(MapFlags::MAP_ANONYMOUS) {
             // 匿名映射
             current_address_space.write().map_anonymous(
                 start_vaddr,
@@ -347,6 +417,12 @@ impl Syscall {
                 false,
             )?
         };
+
+        // 被锁定的映射需要立即建立页面映射，失败时不影响映射本身
+        let _ = current_address_space.write().mm_populate(VirtRegion::new(
+            start_page.virt_address(),
+            page_align_up(len),
+        ));
         return Ok(start_page.virt_address().data());
     }
 
@@ -408,10 +484,23 @@ impl Syscall {
         let vma = vma.unwrap();
         let vm_flags = *vma.lock_irqsave().vm_flags();
 
-        // 暂时不支持巨页映射
+        // 大页映射只支持原地缩小
         if vm_flags.contains(VmFlags::VM_HUGETLB) {
-            error!("mmap: not support huge page mapping");
-            return Err(SystemError::ENOSYS);
+            let size = vma_hstate(&vma.lock_irqsave())
+                .ok_or(SystemError::EINVAL)?
+                .size();
+            if !old_vaddr.check_aligned(size)
+                || !check_aligned(new_len, size)
+                || new_len > old_len
+                || mremap_flags
+                    .intersects(MremapFlags::MREMAP_FIXED | MremapFlags::MREMAP_DONTUNMAP)
+            {
+                return Err(SystemError::EINVAL);
+            }
+            if old_len > new_len {
+                Self::munmap(old_vaddr + new_len, old_len - new_len)?;
+            }
+            return Ok(old_vaddr.data());
         }
 
         // 缩小旧内存映射区域
@@ -536,8 +625,7 @@ impl Syscall {
 
         current_address_space
             .write()
-            .madvise(start_frame, page_count, madv_flags)
-            .map_err(|_| SystemError::EINVAL)?;
+            .madvise(start_frame, page_count, madv_flags)?;
         return Ok(0);
     }
 
@@ -651,4 +739,253 @@ impl Syscall {
         }
         return err;
     }
+
+    /// ## mlock2系统调用，mlock相当于flags为0的mlock2
+    ///
+    /// ## 参数
+    ///
+    /// - `start_vaddr`：起始地址，会被向下对齐到页边界
+    /// - `len`：长度
+    /// - `flags`：mlock2的标志
+    pub fn mlock2(start_vaddr: VirtAddr, len: usize, flags: u32) -> Result<usize, SystemError> {
+        let flags = MlockFlags::from_bits(flags).ok_or(SystemError::EINVAL)?;
+        if let Some(region) = mlock_region(start_vaddr, len)? {
+            AddressSpace::current()?.write().mlock(region, flags)?;
+        }
+        return Ok(0);
+    }
+
+    /// ## munlock系统调用
+    ///
+    /// ## 参数
+    ///
+    /// - `start_vaddr`：起始地址，会被向下对齐到页边界
+    /// - `len`：长度
+    pub fn munlock(start_vaddr: VirtAddr, len: usize) -> Result<usize, SystemError> {
+        if let Some(region) = mlock_region(start_vaddr, len)? {
+            AddressSpace::current()?.write().munlock(region)?;
+        }
+        return Ok(0);
+    }
+
+    /// ## mlockall系统调用
+    ///
+    /// ## 参数
+    ///
+    /// - `flags`：MCL_CURRENT、MCL_FUTURE以及MCL_ONFAULT的组合
+    pub fn mlockall(flags: u32) -> Result<usize, SystemError> {
+        let flags = MclFlags::from_bits(flags).ok_or(SystemError::EINVAL)?;
+        AddressSpace::current()?.write().mlockall(flags)?;
+        return Ok(0);
+    }
+
+    /// ## munlockall系统调用
+    pub fn munlockall() -> Result<usize, SystemError> {
+        AddressSpace::current()?.write().munlockall();
+        return Ok(0);
+    }
+
+    /// ## mincore系统调用
+    ///
+    /// ## 参数
+    ///
+    /// - `start_vaddr`：起始地址(必须对齐到页)
+    /// - `len`：长度
+    /// - `vec`：用户空间的数组，每个页面对应一个字节
+    pub fn mincore(start_vaddr: VirtAddr, len: usize, vec: *mut u8) -> Result<usize, SystemError> {
+        if !start_vaddr.check_aligned(MMArch::PAGE_SIZE) {
+            return Err(SystemError::EINVAL);
+        }
+        if len == 0 {
+            return Ok(0);
+        }
+        let len = page_align_up(len);
+        if verify_area(start_vaddr, len).is_err() {
+            return Err(SystemError::ENOMEM);
+        }
+
+        let pages = len >> MMArch::PAGE_SHIFT;
+        let mut writer = UserBufferWriter::new(vec, pages, true)?;
+        let residency = AddressSpace::current()?
+            .read()
+            .mincore(VirtRegion::new(start_vaddr, len))?;
+        writer.copy_to_user(&residency, 0)?;
+        return Ok(0);
+    }
+
+    /// ## swapon系统调用
+    ///
+    /// ## 参数
+    ///
+    /// - `path`：交换设备或交换文件的路径
+    /// - `swap_flags`：交换标志，低15位为优先级
+    pub fn swapon(path: *const u8, swap_flags: u32) -> Result<usize, SystemError> {
+        let path = check_and_clone_cstr(path, Some(MAX_PATHLEN))?
+            .into_string()
+            .map_err(|_| SystemError::EINVAL)?;
+        do_swapon(&path, swap_flags)?;
+        return Ok(0);
+    }
+
+    /// ## swapoff系统调用
+    ///
+    /// ## 参数
+    ///
+    /// - `path`：交换设备或交换文件的路径
+    pub fn swapoff(path: *const u8) -> Result<usize, SystemError> {
+        let path = check_and_clone_cstr(path, Some(MAX_PATHLEN))?
+            .into_string()
+            .map_err(|_| SystemError::EINVAL)?;
+        do_swapoff(&path)?;
+        return Ok(0);
+    }
+
+    /// ## mbind系统调用
+    ///
+    /// ## 参数
+    ///
+    /// - `start_vaddr`：起始地址(必须对齐到页)
+    /// - `len`：长度
+    /// - `mode`：策略模式以及模式标志
+    /// - `nmask`：用户空间的节点位图
+    /// - `maxnode`：位图的位数加1
+    /// - `flags`：MPOL_MF_STRICT、MPOL_MF_MOVE以及MPOL_MF_MOVE_ALL的组合
+    pub fn mbind(
+        start_vaddr: VirtAddr,
+        len: usize,
+        mode: u32,
+        nmask: *const u64,
+        maxnode: usize,
+        flags: u32,
+    ) -> Result<usize, SystemError> {
+        let flags = MbindFlags::from_bits(flags).ok_or(SystemError::EINVAL)?;
+        if flags.contains(MbindFlags::MPOL_MF_MOVE_ALL) && !capable(CAPFlags::CAP_SYS_NICE) {
+            return Err(SystemError::EPERM);
+        }
+        if !start_vaddr.check_aligned(MMArch::PAGE_SIZE) {
+            return Err(SystemError::EINVAL);
+        }
+        let len = len
+            .checked_add(MMArch::PAGE_SIZE - 1)
+            .ok_or(SystemError::EINVAL)?
+            & !(MMArch::PAGE_SIZE - 1);
+        if verify_area(start_vaddr, len).is_err() {
+            return Err(SystemError::EINVAL);
+        }
+        let (mode, mode_flags) = parse_mode(mode)?;
+        let nodes = get_nodes(nmask, maxnode)?;
+        let policy = MemPolicy::new(mode, mode_flags, nodes)?;
+        if len == 0 {
+            return Ok(0);
+        }
+
+        AddressSpace::current()?
+            .write()
+            .mbind(VirtRegion::new(start_vaddr, len), policy, flags)?;
+        return Ok(0);
+    }
+
+    /// ## set_mempolicy系统调用
+    ///
+    /// ## 参数
+    ///
+    /// - `mode`：策略模式以及模式标志
+    /// - `nmask`：用户空间的节点位图
+    /// - `maxnode`：位图的位数加1
+    pub fn set_mempolicy(
+        mode: u32,
+        nmask: *const u64,
+        maxnode: usize,
+    ) -> Result<usize, SystemError> {
+        let (mode, mode_flags) = parse_mode(mode)?;
+        let nodes = get_nodes(nmask, maxnode)?;
+        let policy = MemPolicy::new(mode, mode_flags, nodes)?;
+        ProcessManager::current_pcb().set_mempolicy(policy);
+        return Ok(0);
+    }
+
+    /// ## get_mempolicy系统调用
+    ///
+    /// ## 参数
+    ///
+    /// - `policy`：用于返回策略模式（或者节点编号）的用户空间指针，可以为空
+    /// - `nmask`：用于返回节点集合的用户空间位图，可以为空
+    /// - `maxnode`：位图的位数
+    /// - `addr`：指定MPOL_F_ADDR时，查询该地址所在地址范围的策略
+    /// - `flags`：MPOL_F_NODE、MPOL_F_ADDR以及MPOL_F_MEMS_ALLOWED的组合
+    pub fn get_mempolicy(
+        policy: *mut i32,
+        nmask: *mut u64,
+        maxnode: usize,
+        addr: usize,
+        flags: u32,
+    ) -> Result<usize, SystemError> {
+        let flags = GetMemPolicyFlags::from_bits(flags).ok_or(SystemError::EINVAL)?;
+
+        let (value, nodes) = if flags.contains(GetMemPolicyFlags::MPOL_F_MEMS_ALLOWED) {
+            if flags != GetMemPolicyFlags::MPOL_F_MEMS_ALLOWED {
+                return Err(SystemError::EINVAL);
+            }
+            (MPOL_DEFAULT as i32, node_online_map())
+        } else if flags.contains(GetMemPolicyFlags::MPOL_F_ADDR) {
+            let (vma_policy, nid) = AddressSpace::current()?
+                .read()
+                .vma_policy_at(VirtAddr::new(addr))?;
+            let value = if flags.contains(GetMemPolicyFlags::MPOL_F_NODE) {
+                nid as i32
+            } else {
+                vma_policy
+                    .as_ref()
+                    .map(|p| p.mode_with_flags())
+                    .unwrap_or(MPOL_DEFAULT) as i32
+            };
+            (value, vma_policy.map(|p| p.nodes()).unwrap_or_default())
+        } else {
+            if addr != 0 {
+                return Err(SystemError::EINVAL);
+            }
+            let task_policy = ProcessManager::current_pcb().mempolicy();
+            let value = if flags.contains(GetMemPolicyFlags::MPOL_F_NODE) {
+                match task_policy.as_ref() {
+                    Some(p) if p.mode() == MPOL_INTERLEAVE => p.interleave_peek() as i32,
+                    _ => return Err(SystemError::EINVAL),
+                }
+            } else {
+                task_policy
+                    .as_ref()
+                    .map(|p| p.mode_with_flags())
+                    .unwrap_or(MPOL_DEFAULT) as i32
+            };
+            (value, task_policy.map(|p| p.nodes()).unwrap_or_default())
+        };
+
+        if !policy.is_null() {
+            let mut writer = UserBufferWriter::new(policy, core::mem::size_of::<i32>(), true)?;
+            writer.copy_one_to_user(&value, 0)?;
+        }
+        copy_nodes_to_user(nmask, maxnode, nodes)?;
+        return Ok(0);
+    }
+}
+
+/// 把mlock系列系统调用的参数转换为按页对齐的区域
+///
+/// ## 返回值
+///
+/// - `Ok(None)` 长度为0，不需要做任何操作
+/// - `Ok(Some(region))` 需要处理的区域
+fn mlock_region(start_vaddr: VirtAddr, len: usize) -> Result<Option<VirtRegion>, SystemError> {
+    let start = page_align_down(start_vaddr.data());
+    let len = len
+        .checked_add(start_vaddr.data() - start)
+        .and_then(|len| len.checked_add(MMArch::PAGE_SIZE - 1))
+        .ok_or(SystemError::EINVAL)?
+        & !(MMArch::PAGE_SIZE - 1);
+    if len == 0 {
+        return Ok(None);
+    }
+    if verify_area(VirtAddr::new(start), len).is_err() {
+        return Err(SystemError::ENOMEM);
+    }
+    return Ok(Some(VirtRegion::new(VirtAddr::new(start), len)));
 }
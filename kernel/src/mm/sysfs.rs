@@ -0,0 +1,29 @@
+use alloc::{string::ToString, sync::Arc};
+use system_error::SystemError;
+use unified_init::macros::unified_init;
+
+use crate::{
+    driver::base::kset::KSet, init::initcall::INITCALL_POSTCORE, misc::ksysfs::sys_kernel_kset,
+};
+
+/// `/sys/kernel/mm`的kset
+static mut SYS_KERNEL_MM_KSET_INSTANCE: Option<Arc<KSet>> = None;
+
+#[inline(always)]
+pub fn sys_kernel_mm_kset() -> Arc<KSet> {
+    unsafe { SYS_KERNEL_MM_KSET_INSTANCE.clone().unwrap() }
+}
+
+/// 初始化内存管理模块在sysfs中的目录
+#[unified_init(INITCALL_POSTCORE)]
+fn mm_sysfs_init() -> Result<(), SystemError> {
+    let mm_kset = KSet::new("mm".to_string());
+    mm_kset
+        .register(Some(sys_kernel_kset()))
+        .expect("register mm kset failed");
+    unsafe {
+        SYS_KERNEL_MM_KSET_INSTANCE = Some(mm_kset);
+    }
+
+    return Ok(());
+}
@@ -20,15 +20,35 @@ use system_error::SystemError;
 use crate::{
     arch::{mm::PageMapper, CurrentIrqArch, MMArch},
     exception::InterruptArch,
-    filesystem::vfs::file::File,
+    filesystem::{
+        hugetlbfs::hugetlbfs_inode,
+        vfs::{file::File, IndexNode},
+    },
     ipc::shm::{shm_manager_lock, ShmFlags},
     libs::{
-        align::page_align_up,
+        align::{align_down, align_up, page_align_up},
         rwlock::RwLock,
         spinlock::{SpinLock, SpinLockGuard},
     },
-    mm::page::page_manager_lock_irqsave,
-    process::ProcessManager,
+    mm::{
+        huge_memory::{
+            split_huge_pmds, thp_change_protection, thp_count, thp_split_boundaries, thp_unmap,
+            thp_vma_allowable, HPAGE_PMD_NR, HPAGE_PMD_SIZE,
+        },
+        hugetlb::{
+            hugetlb_change_protection, hugetlb_check_split, hugetlb_copy, hugetlb_unmap, Hstate,
+        },
+        ksm::ksm_enter,
+        mempolicy::MemPolicy,
+        mlock::VM_LOCKED_MASK,
+        oom_kill::out_of_memory,
+        page::{page_manager_lock_irqsave, page_reclaimer_lock_irqsave},
+        swap::{lru_add_anon, swap_entry_at, swap_free, take_swap_entry},
+    },
+    process::{
+        resource::{rlimit, RLimitID},
+        ProcessManager,
+    },
     syscall::user_access::{UserBufferReader, UserBufferWriter},
 };
 
@@ -36,7 +56,7 @@ use super::{
     allocator::page_frame::{
         deallocate_page_frames, PageFrameCount, PhysPageFrame, VirtPageFrame, VirtPageFrameIter,
     },
-    page::{EntryFlags, Flusher, InactiveFlusher, PageFlushAll, PageType},
+    page::{AnonMapInfo, EntryFlags, Flusher, InactiveFlusher, PageEntry, PageFlushAll, PageType},
     syscall::{MadvFlags, MapFlags, MremapFlags, ProtFlags},
     MemoryManagementArch, PageTableKind, VirtAddr, VirtRegion, VmFlags,
 };
@@ -66,10 +86,11 @@ pub struct AddressSpace {
 impl AddressSpace {
     pub fn new(create_stack: bool) -> Result<Arc<Self>, SystemError> {
         let inner = InnerAddressSpace::new(create_stack)?;
-        let result = Self {
+        let result = Arc::new(Self {
             inner: RwLock::new(inner),
-        };
-        return Ok(Arc::new(result));
+        });
+        result.write().mappings.set_owner(Arc::downgrade(&result));
+        return Ok(result);
     }
 
     /// 从pcb中获取当前进程的地址空间结构体的Arc指针
@@ -127,6 +148,12 @@ pub struct InnerAddressSpace {
     pub end_code: VirtAddr,
     pub start_data: VirtAddr,
     pub end_data: VirtAddr,
+
+    /// 新建VMA时默认附加的标志，mlockall(MCL_FUTURE)通过它锁定之后的映射
+    pub def_flags: VmFlags,
+
+    /// execve时压入用户栈的辅助向量，生成core dump时写入NT_AUXV
+    pub saved_auxv: Vec<(usize, usize)>,
 }
 
 impl InnerAddressSpace {
@@ -144,6 +171,8 @@ impl InnerAddressSpace {
             end_code: VirtAddr(0),
             start_data: VirtAddr(0),
             end_data: VirtAddr(0),
+            def_flags: VmFlags::VM_NONE,
+            saved_auxv: Vec::new(),
         };
         if create_stack {
             // debug!("to create user stack.");
@@ -163,6 +192,10 @@ impl InnerAddressSpace {
         let irq_guard = unsafe { CurrentIrqArch::save_and_disable_irq() };
         let new_addr_space = AddressSpace::new(false)?;
         let mut new_guard = new_addr_space.write();
+        // 透明大页的页表项不会被UserMapper克隆，先拆分成普通页，由普通页进行写时复制
+        for vma in self.mappings.vmas.iter() {
+            unsafe { split_huge_pmds(vma, &mut self.user_mapper.utable)? };
+        }
         unsafe {
             new_guard
                 .user_mapper
@@ -177,6 +210,7 @@ impl InnerAddressSpace {
 
         // 拷贝空洞
         new_guard.mappings.vm_holes = self.mappings.vm_holes.clone();
+        new_guard.saved_auxv = self.saved_auxv.clone();
 
         for vma in self.mappings.vmas.iter() {
             // TODO: 增加对VMA是否为文件映射的判断，如果是的话，就跳过
@@ -184,10 +218,23 @@ impl InnerAddressSpace {
             let vma_guard: SpinLockGuard<'_, VMA> = vma.lock_irqsave();
 
             // 仅拷贝VMA信息并添加反向映射，因为UserMapper克隆时已经分配了新的物理页
-            let new_vma = LockedVMA::new(vma_guard.clone_info_only());
+            let mut new_vma_info = vma_guard.clone_info_only();
+            new_vma_info.user_address_space = Some(Arc::downgrade(&new_addr_space));
+            // 子进程不继承内存锁定
+            new_vma_info.vm_flags -= VM_LOCKED_MASK;
+            let new_vma = LockedVMA::new(new_vma_info);
             new_guard.mappings.vmas.insert(new_vma.clone());
             // debug!("new vma: {:x?}", new_vma);
             let new_vma_guard = new_vma.lock_irqsave();
+            // 大页映射的页表项不会被UserMapper克隆，需要单独复制
+            if vma_guard.vm_flags().contains(VmFlags::VM_HUGETLB) {
+                hugetlb_copy(
+                    &vma_guard,
+                    &mut self.user_mapper.utable,
+                    &mut new_guard.user_mapper.utable,
+                )?;
+                continue;
+            }
             let new_mapper = &new_guard.user_mapper.utable;
             let mut page_manager_guard = page_manager_lock_irqsave();
             for page in new_vma_guard.pages().map(|p| p.virt_address()) {
@@ -201,6 +248,14 @@ impl InnerAddressSpace {
             drop(vma_guard);
             drop(new_vma_guard);
         }
+        // 子进程继承MADV_MERGEABLE，同样需要被ksmd扫描
+        if self.mappings.vmas.iter().any(|vma| {
+            vma.lock_irqsave()
+                .vm_flags()
+                .contains(VmFlags::VM_MERGEABLE)
+        }) {
+            ksm_enter(Arc::downgrade(&new_addr_space));
+        }
         drop(new_guard);
         drop(irq_guard);
         return Ok(new_addr_space);
@@ -215,6 +270,10 @@ impl InnerAddressSpace {
             // Don't exceed the maximum stack size
             return false;
         }
+        if new_size as u64 > rlimit(RLimitID::Stack) {
+            // Don't exceed RLIMIT_STACK
+            return false;
+        }
         return true;
     }
 
@@ -403,12 +462,31 @@ impl InnerAddressSpace {
         }
         let pgoff = offset >> MMArch::PAGE_SHIFT;
 
-        let start_page: VirtPageFrame = self.mmap(
+        // 被F_SEAL_WRITE或F_SEAL_FUTURE_WRITE封印的文件不允许建立可写的共享映射，
+        // 同时去掉VM_MAYWRITE，防止之后通过mprotect添加写权限。
+        // 检查seal的同时持有一次可写映射计数，直到VMA建立，避免与F_ADD_SEALS竞争
+        let inode = file.as_ref().unwrap().inode();
+        let mut may_write = true;
+        let mut writable_pinned = false;
+        if map_flags.contains(MapFlags::MAP_SHARED) {
+            match inode.mapping_map_writable() {
+                Ok(()) => writable_pinned = true,
+                Err(SystemError::EPERM) if !prot_flags.contains(ProtFlags::PROT_WRITE) => {
+                    may_write = false;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+
+        let r = self.mmap(
             round_hint_to_min(start_vaddr),
             PageFrameCount::from_bytes(len).unwrap(),
             prot_flags,
             map_flags,
-            |page, count, vm_flags, flags, mapper, flusher| {
+            |page, count, mut vm_flags, flags, mapper, flusher| {
+                if !may_write {
+                    vm_flags -= VmFlags::VM_MAYWRITE;
+                }
                 if allocate_at_once {
                     VMA::zeroed(
                         page,
@@ -431,7 +509,11 @@ impl InnerAddressSpace {
                     )))
                 }
             },
-        )?;
+        );
+        if writable_pinned {
+            inode.mapping_unmap_writable();
+        }
+        let start_page: VirtPageFrame = r?;
         // todo!(impl mmap for other file)
         // https://github.com/DragonOS-Community/DragonOS/pull/912#discussion_r1765334272
         let file = file.unwrap();
@@ -439,6 +521,105 @@ impl InnerAddressSpace {
         return Ok(start_page);
     }
 
+    /// 把hugetlbfs文件映射到进程的地址空间，MAP_HUGETLB匿名映射也通过内部的hugetlbfs文件实现
+    ///
+    /// 映射的起始地址、长度和文件偏移都按大页大小对齐，大页在缺页时才从大页池中分配
+    ///
+    /// ## 参数
+    ///
+    /// - `start_vaddr`：映射的起始地址，为0时由内核选择
+    /// - `len`：映射的长度
+    /// - `prot_flags`：保护标志
+    /// - `map_flags`：映射标志
+    /// - `file`：被映射的hugetlbfs文件
+    /// - `hstate`：文件使用的大页池
+    /// - `offset`：映射在文件中的偏移
+    ///
+    /// ## 返回值
+    ///
+    /// - `Ok(VirtPageFrame)`：映射的起始虚拟页帧
+    /// - `Err(SystemError::EINVAL)`：地址或偏移没有按大页大小对齐
+    /// - `Err(SystemError::ENOMEM)`：大页池中空闲的大页不足，或没有足够的地址空间
+    #[allow(clippy::too_many_arguments)]
+    pub fn hugetlb_mapping(
+        &mut self,
+        start_vaddr: VirtAddr,
+        len: usize,
+        prot_flags: ProtFlags,
+        map_flags: MapFlags,
+        file: Arc<File>,
+        hstate: &'static Hstate,
+        offset: usize,
+    ) -> Result<VirtPageFrame, SystemError> {
+        let size = hstate.size();
+        if offset % size != 0 {
+            return Err(SystemError::EINVAL);
+        }
+        let len = align_up(len, size);
+        let fixed = map_flags.intersects(MapFlags::MAP_FIXED | MapFlags::MAP_FIXED_NOREPLACE);
+        if fixed && !start_vaddr.check_aligned(size) {
+            return Err(SystemError::EINVAL);
+        }
+
+        let inode = hugetlbfs_inode(&file).ok_or(SystemError::EINVAL)?;
+        let first = offset / size;
+        let count = len / size;
+
+        // 预先检查大页池中是否有足够的大页，避免之后在缺页时才因为大页不足而收到SIGBUS
+        if !map_flags.contains(MapFlags::MAP_NORESERVE) {
+            let needed = if map_flags.contains(MapFlags::MAP_SHARED) {
+                inode.count_missing(first, count)
+            } else {
+                count
+            };
+            if needed > hstate.free_hugepages() {
+                return Err(SystemError::ENOMEM);
+            }
+        }
+
+        let addr = if fixed {
+            start_vaddr
+        } else {
+            let hint = VirtAddr::new(align_up(start_vaddr.data(), size));
+            let hint_region = VirtRegion::new(hint, len);
+            if hint.data() >= self.mmap_min.data()
+                && hint_region.end() < MMArch::USER_END_VADDR
+                && self.mappings.conflicts(hint_region).next().is_none()
+            {
+                hint
+            } else {
+                let region = self
+                    .mappings
+                    .find_free(self.mmap_min, len + size)
+                    .ok_or(SystemError::ENOMEM)?;
+                VirtAddr::new(align_up(region.start().data(), size))
+            }
+        };
+
+        let pgoff = offset >> MMArch::PAGE_SHIFT;
+        let start_page = self.mmap(
+            Some(addr),
+            PageFrameCount::from_bytes(len).unwrap(),
+            prot_flags,
+            map_flags,
+            |page, count, vm_flags, flags, _mapper, _flusher| {
+                Ok(LockedVMA::new(VMA::new(
+                    VirtRegion::new(page.virt_address(), count.bytes()),
+                    vm_flags | VmFlags::VM_HUGETLB,
+                    flags,
+                    Some(file),
+                    Some(pgoff),
+                    false,
+                )))
+            },
+        )?;
+
+        if prot_flags.contains(ProtFlags::PROT_WRITE) {
+            inode.extend_size(offset + len);
+        }
+        return Ok(start_page);
+    }
+
     /// 向进程的地址空间映射页面
     ///
     /// # 参数
@@ -495,7 +676,12 @@ impl InnerAddressSpace {
             | VmFlags::from(map_flags)
             | VmFlags::VM_MAYREAD
             | VmFlags::VM_MAYWRITE
-            | VmFlags::VM_MAYEXEC;
+            | VmFlags::VM_MAYEXEC
+            | self.def_flags;
+
+        if !self.may_expand_vm(vm_flags, &region) {
+            return Err(SystemError::ENOMEM);
+        }
 
         // debug!("mmap: page: {:?}, region={region:?}", page.virt_address());
 
@@ -522,6 +708,33 @@ impl InnerAddressSpace {
         return Ok(page);
     }
 
+    /// 检查映射`region`之后，地址空间的大小是否超出RLIMIT_AS和RLIMIT_DATA
+    ///
+    /// 参考 https://code.dragonos.org.cn/xref/linux-6.1.9/mm/mmap.c#3380
+    fn may_expand_vm(&self, vm_flags: VmFlags, region: &VirtRegion) -> bool {
+        // 可写、私有、不向下增长的映射计入数据段
+        let is_data = |flags: VmFlags| {
+            flags & (VmFlags::VM_WRITE | VmFlags::VM_SHARED | VmFlags::VM_GROWSDOWN)
+                == VmFlags::VM_WRITE
+        };
+
+        let mut total = region.size();
+        let mut data = if is_data(vm_flags) { region.size() } else { 0 };
+        for vma in self.mappings.iter_vmas() {
+            let guard = vma.lock_irqsave();
+            let vma_region = guard.region();
+            // 与新映射重叠的部分会被替换掉，不重复计算
+            let size =
+                vma_region.size() - vma_region.intersect(region).map(|r| r.size()).unwrap_or(0);
+            total += size;
+            if is_data(*guard.vm_flags()) {
+                data += size;
+            }
+        }
+
+        return total as u64 <= rlimit(RLimitID::As) && data as u64 <= rlimit(RLimitID::Data);
+    }
+
     /// 重映射内存区域
     ///
     /// # 参数
@@ -574,6 +787,40 @@ impl InnerAddressSpace {
             self.munmap(start_page, page_count)?;
         }
 
+        // 私有匿名映射直接把页表项搬到新的区域，不复制页面内容
+        let old_vma = self
+            .mappings
+            .contains(old_vaddr)
+            .ok_or(SystemError::EFAULT)?;
+        let movable = MMArch::PAGE_FAULT_ENABLED && {
+            let guard = old_vma.lock_irqsave();
+            guard.vm_file().is_none()
+                && !guard
+                    .vm_flags()
+                    .intersects(VmFlags::VM_SHARED | VmFlags::VM_HUGETLB)
+                && guard.region().end() >= old_vaddr + old_len
+        };
+        if movable {
+            unsafe { split_huge_pmds(&old_vma, &mut self.user_mapper.utable)? };
+            let new_page =
+                self.map_anonymous(new_vaddr, new_len, prot_flags, map_flags, true, false)?;
+            let new_page_vaddr = new_page.virt_address();
+            let new_vma = self.mappings.contains(new_page_vaddr).unwrap();
+            if let Err(e) =
+                self.move_page_tables(&old_vma, old_vaddr, &new_vma, new_page_vaddr, old_len)
+            {
+                self.munmap(new_page, PageFrameCount::from_bytes(new_len).unwrap())
+                    .ok();
+                return Err(e);
+            }
+            // 被锁定的映射需要为扩大的部分建立页面映射
+            if new_len > old_len {
+                let _ =
+                    self.mm_populate(VirtRegion::new(new_page_vaddr + old_len, new_len - old_len));
+            }
+            return Ok(new_page_vaddr);
+        }
+
         // 获取映射后的新内存页面
         let new_page = self.map_anonymous(new_vaddr, new_len, prot_flags, map_flags, true, true)?;
         let new_page_vaddr = new_page.virt_address();
@@ -591,6 +838,63 @@ impl InnerAddressSpace {
         return Ok(new_page_vaddr);
     }
 
+    /// 把`[old_vaddr, old_vaddr + len)`的页表项搬到`new_vaddr`处，并更新页面的反向映射
+    ///
+    /// 被换出的页面只搬动swap entry，换入时会以新的地址加入匿名页链表。
+    /// 失败时已经搬动的页表项会被搬回原处。
+    ///
+    /// ## 参数
+    ///
+    /// - `old_vma` 原区域所在的VMA，其中不能有透明大页
+    /// - `new_vma` 新区域所在的VMA，其中还没有任何页面映射
+    ///
+    /// ## 返回值
+    ///
+    /// - `Err(SystemError::ENOMEM)` 无法为新区域分配页表
+    fn move_page_tables(
+        &mut self,
+        old_vma: &Arc<LockedVMA>,
+        old_vaddr: VirtAddr,
+        new_vma: &Arc<LockedVMA>,
+        new_vaddr: VirtAddr,
+        len: usize,
+    ) -> Result<(), SystemError> {
+        let mapper = &mut self.user_mapper.utable;
+        let mut moved = 0;
+        while moved < len {
+            let r = unsafe {
+                move_one_pte(
+                    mapper,
+                    (old_vma, old_vaddr + moved),
+                    (new_vma, new_vaddr + moved),
+                )
+            };
+            if let Err(e) = r {
+                // 原处的页表仍然存在，搬回去不会失败
+                for offset in (0..moved).step_by(MMArch::PAGE_SIZE) {
+                    unsafe {
+                        move_one_pte(
+                            mapper,
+                            (new_vma, new_vaddr + offset),
+                            (old_vma, old_vaddr + offset),
+                        )
+                        .unwrap()
+                    };
+                }
+                return Err(e);
+            }
+            moved += MMArch::PAGE_SIZE;
+        }
+        if old_vma.mapped() {
+            new_vma.lock_irqsave().set_mapped(true);
+        }
+
+        // 页表可能在任意核心上处于激活状态，需要刷新所有核心的TLB
+        unsafe { MMArch::invalidate_all() };
+        drop(InactiveFlusher::new());
+        return Ok(());
+    }
+
     /// 取消进程的地址空间中的映射
     ///
     /// # 参数
@@ -608,6 +912,8 @@ impl InnerAddressSpace {
         page_count: PageFrameCount,
     ) -> Result<(), SystemError> {
         let to_unmap = VirtRegion::new(start_page.virt_address(), page_count.bytes());
+        hugetlb_check_split(&self.mappings, to_unmap)?;
+        thp_split_boundaries(&self.mappings, to_unmap, &mut self.user_mapper.utable)?;
         let mut flusher: PageFlushAll<MMArch> = PageFlushAll::new();
 
         let regions: Vec<Arc<LockedVMA>> = self.mappings.conflicts(to_unmap).collect::<Vec<_>>();
@@ -661,6 +967,8 @@ impl InnerAddressSpace {
         let mapper = &mut self.user_mapper.utable;
         let region = VirtRegion::new(start_page.virt_address(), page_count.bytes());
         // debug!("mprotect: region: {:?}", region);
+        hugetlb_check_split(&self.mappings, region)?;
+        thp_split_boundaries(&self.mappings, region, mapper)?;
 
         let regions = self.mappings.conflicts(region).collect::<Vec<_>>();
         // debug!("mprotect: regions: {:?}", regions);
@@ -689,7 +997,10 @@ impl InnerAddressSpace {
                 self.mappings.insert_vma(r.clone());
                 return Err(SystemError::EACCES);
             }
-            r_guard.set_vm_flags(VmFlags::from(prot_flags));
+            // 只修改访问权限，保留VM_SHARED、VM_HUGETLB等其他标志
+            let access_flags = VmFlags::VM_READ | VmFlags::VM_WRITE | VmFlags::VM_EXEC;
+            let vm_flags = (*r_guard.vm_flags() - access_flags) | VmFlags::from(prot_flags);
+            r_guard.set_vm_flags(vm_flags);
 
             let new_flags: EntryFlags<MMArch> = r_guard
                 .flags()
@@ -722,13 +1033,18 @@ impl InnerAddressSpace {
         let mapper = &mut self.user_mapper.utable;
 
         let region = VirtRegion::new(start_page.virt_address(), page_count.bytes());
+        hugetlb_check_split(&self.mappings, region)?;
+        thp_split_boundaries(&self.mappings, region, mapper)?;
         let regions = self.mappings.conflicts(region).collect::<Vec<_>>();
 
+        // 范围内存在未映射的部分时，仍然处理已映射的部分，最后返回ENOMEM
+        let mut mapped_size = 0;
         for r in regions {
             let r = *r.lock_irqsave().region();
             let r = self.mappings.remove_vma(&r).unwrap();
 
             let intersection = r.lock_irqsave().region().intersect(&region).unwrap();
+            mapped_size += intersection.size();
             let split_result = r
                 .extract(intersection, mapper)
                 .expect("Failed to extract VMA");
@@ -739,8 +1055,12 @@ impl InnerAddressSpace {
             if let Some(after) = split_result.after {
                 self.mappings.insert_vma(after);
             }
-            r.do_madvise(behavior, mapper, &mut *flusher)?;
+            let ret = r.do_madvise(behavior, mapper, &mut *flusher);
             self.mappings.insert_vma(r);
+            ret?;
+        }
+        if mapped_size < region.size() {
+            return Err(SystemError::ENOMEM);
         }
         Ok(())
     }
@@ -772,6 +1092,55 @@ impl InnerAddressSpace {
         }
     }
 
+    /// 统计地址空间的内存使用情况
+    ///
+    /// 只使用尝试加锁的方式访问VMA，以便在内存不足的处理路径中调用
+    ///
+    /// ## 返回值
+    ///
+    /// 若有VMA正被其他人锁定，返回`None`
+    pub fn try_memory_usage(&self) -> Option<MemoryUsage> {
+        let mapper = &self.user_mapper.utable;
+        let mut usage = MemoryUsage::default();
+        for vma in self.mappings.iter_vmas() {
+            let region = *vma.try_lock_irqsave().ok()?.region();
+            usage.total_vm += region.size() >> MMArch::PAGE_SHIFT;
+            for page in region.pages() {
+                if mapper.translate(page.virt_address()).is_some() {
+                    usage.rss += 1;
+                } else if swap_entry_at(mapper, page.virt_address()).is_some() {
+                    usage.swap += 1;
+                }
+            }
+            usage.rss += thp_count(mapper, region) * HPAGE_PMD_NR;
+        }
+        return Some(usage);
+    }
+
+    /// 释放所有私有匿名映射占用的物理页和交换槽位，VMA本身保持不变
+    ///
+    /// 用于在被OOM killer杀死的进程退出之前尽快回收它的内存
+    pub fn reap_anonymous(&mut self) {
+        let (mut active, mut inactive);
+        let flusher = if self.is_current() {
+            active = PageFlushAll::new();
+            &mut active as &mut dyn Flusher<MMArch>
+        } else {
+            inactive = InactiveFlusher::new();
+            &mut inactive as &mut dyn Flusher<MMArch>
+        };
+
+        for vma in self.mappings.iter_vmas() {
+            let guard = vma.lock_irqsave();
+            let private_anon =
+                guard.vm_file().is_none() && !guard.vm_flags().contains(VmFlags::VM_SHARED);
+            drop(guard);
+            if private_anon {
+                vma.unmap(&mut self.user_mapper.utable, &mut *flusher);
+            }
+        }
+    }
+
     /// 设置进程的堆的内存空间
     ///
     /// ## 参数
@@ -791,6 +1160,10 @@ impl InnerAddressSpace {
         let old_brk = self.brk;
 
         if new_brk > self.brk {
+            let data_size = (new_brk - self.brk_start) + (self.end_data - self.start_data);
+            if data_size as u64 > rlimit(RLimitID::Data) {
+                return Err(SystemError::ENOMEM);
+            }
             let len = new_brk - self.brk;
             let prot_flags = ProtFlags::PROT_READ | ProtFlags::PROT_WRITE | ProtFlags::PROT_EXEC;
             let map_flags = MapFlags::MAP_PRIVATE | MapFlags::MAP_ANONYMOUS | MapFlags::MAP_FIXED;
@@ -880,6 +1253,67 @@ impl InnerAddressSpace {
     }
 }
 
+/// 把`from`处的页表项原样搬到`to`处，`to`处的页表不存在时会先分配页表
+///
+/// 页表项指向的页面会从`from`的VMA转移到`to`的VMA，私有匿名页的虚拟地址一并更新，
+/// 以便页面回收时能在新的位置找到它。
+///
+/// ## 返回值
+///
+/// - `Err(SystemError::ENOMEM)` 无法分配页表，`from`处的页表项保持不变
+unsafe fn move_one_pte(
+    mapper: &mut PageMapper,
+    from: (&Arc<LockedVMA>, VirtAddr),
+    to: (&Arc<LockedVMA>, VirtAddr),
+) -> Result<(), SystemError> {
+    let Some(src) = mapper.get_table(from.1, 0) else {
+        return Ok(());
+    };
+    let i = src.index_of(from.1).unwrap();
+    let entry = src.entry(i).unwrap();
+    if entry.empty() {
+        return Ok(());
+    }
+
+    for level in (0..MMArch::PAGE_LEVELS - 1).rev() {
+        if mapper.get_table(to.1, level).is_none() {
+            mapper
+                .allocate_table(to.1, level)
+                .ok_or(SystemError::ENOMEM)?;
+        }
+    }
+    let dst = mapper.get_table(to.1, 0).unwrap();
+    let j = dst.index_of(to.1).unwrap();
+    dst.set_entry(j, entry);
+    src.set_entry(i, PageEntry::from_usize(0));
+
+    // swap entry不指向任何页面
+    if !entry.present() && !entry.protnone() {
+        return Ok(());
+    }
+    let paddr = entry.address().unwrap_or_else(|paddr| paddr);
+    if let Some(page) = page_manager_lock_irqsave().get(&paddr) {
+        let mut guard = page.write_irqsave();
+        guard.remove_vma(from.0);
+        guard.insert_vma(to.0.clone());
+        if let PageType::Anon(_) = guard.page_type() {
+            guard.set_page_type(PageType::Anon(AnonMapInfo { vaddr: to.1 }));
+        }
+    }
+    return Ok(());
+}
+
+/// 地址空间的内存使用统计，单位均为页
+#[derive(Debug, Default, Clone, Copy)]
+pub struct MemoryUsage {
+    /// 所有VMA的总大小
+    pub total_vm: usize,
+    /// 驻留在物理内存中的页数
+    pub rss: usize,
+    /// 被换出到交换设备的页数
+    pub swap: usize,
+}
+
 impl Drop for InnerAddressSpace {
     fn drop(&mut self) {
         unsafe {
@@ -933,6 +1367,8 @@ pub struct UserMappings {
     vmas: HashSet<Arc<LockedVMA>>,
     /// 当前用户空间的VMA空洞
     vm_holes: BTreeMap<VirtAddr, usize>,
+    /// 映射所属的地址空间
+    owner: Weak<AddressSpace>,
 }
 
 impl UserMappings {
@@ -941,9 +1377,18 @@ impl UserMappings {
             vmas: HashSet::new(),
             vm_holes: core::iter::once((VirtAddr::new(0), MMArch::USER_END_VADDR.data()))
                 .collect::<BTreeMap<_, _>>(),
+            owner: Weak::new(),
         };
     }
 
+    /// 设置映射所属的地址空间，并更新已有VMA的反向引用
+    fn set_owner(&mut self, owner: Weak<AddressSpace>) {
+        for vma in self.vmas.iter() {
+            vma.lock_irqsave().user_address_space = Some(owner.clone());
+        }
+        self.owner = owner;
+    }
+
     /// 判断当前进程的VMA内，是否有包含指定的虚拟地址的VMA。
     ///
     /// 如果有，返回包含指定虚拟地址的VMA的Arc指针，否则返回None。
@@ -1080,6 +1525,7 @@ impl UserMappings {
         assert!(self.conflicts(region).next().is_none());
         self.reserve_hole(&region);
 
+        vma.lock_irqsave().user_address_space = Some(self.owner.clone());
         self.vmas.insert(vma);
     }
 
@@ -1161,6 +1607,10 @@ impl LockedVMA {
         return self.vma.lock_irqsave();
     }
 
+    pub fn try_lock_irqsave(&self) -> Result<SpinLockGuard<VMA>, SystemError> {
+        return self.vma.try_lock_irqsave();
+    }
+
     /// 调整当前VMA的页面的标志位
     ///
     /// TODO：增加调整虚拟页映射的物理地址的功能
@@ -1176,6 +1626,11 @@ impl LockedVMA {
         mut flusher: impl Flusher<MMArch>,
     ) -> Result<(), SystemError> {
         let mut guard = self.lock_irqsave();
+        if guard.vm_flags().contains(VmFlags::VM_HUGETLB) {
+            hugetlb_change_protection(&guard, flags, mapper, flusher);
+            guard.flags = flags;
+            return Ok(());
+        }
         for page in guard.region.pages() {
             // 暂时要求所有的页帧都已经映射到页表
             // TODO: 引入Lazy Mapping, 通过缺页中断来映射页帧，这里就不必要求所有的页帧都已经映射到页表了
@@ -1194,35 +1649,82 @@ impl LockedVMA {
         // todo: 如果当前vma与文件相关，完善文件相关的逻辑
         let mut guard = self.lock_irqsave();
 
-        // 获取物理页的anon_vma的守卫
-        let mut page_manager_guard: SpinLockGuard<'_, crate::mm::page::PageManager> =
-            page_manager_lock_irqsave();
-
-        // 获取映射的物理地址
-        if let Some((paddr, _flags)) = mapper.translate(guard.region().start()) {
-            // 如果是共享页，执行释放操作
-            let page = page_manager_guard.get(&paddr).unwrap();
-            let page_guard = page.read_irqsave();
-            if let PageType::Shm(shm_id) = page_guard.page_type() {
-                let mut shm_manager_guard = shm_manager_lock();
-                if let Some(kernel_shm) = shm_manager_guard.get_mut(shm_id) {
-                    // 更新最后一次断开连接时间
-                    kernel_shm.update_dtim();
-
-                    // 映射计数减少
-                    kernel_shm.decrease_count();
-
-                    // 释放shm_id
-                    if kernel_shm.map_count() == 0 && kernel_shm.mode().contains(ShmFlags::SHM_DEST)
-                    {
-                        shm_manager_guard.free_id(shm_id);
+        if guard.vm_flags().contains(VmFlags::VM_HUGETLB) {
+            hugetlb_unmap(&guard, mapper, flusher);
+            guard.mapped = false;
+            return;
+        }
+        {
+            // 获取物理页的anon_vma的守卫
+            let page_manager_guard: SpinLockGuard<'_, crate::mm::page::PageManager> =
+                page_manager_lock_irqsave();
+
+            // 获取映射的物理地址
+            if let Some((paddr, _flags)) = mapper.translate(guard.region().start()) {
+                // 如果是共享页，执行释放操作
+                let page = page_manager_guard.get(&paddr).unwrap();
+                let page_guard = page.read_irqsave();
+                if let PageType::Shm(shm_id) = page_guard.page_type() {
+                    let mut shm_manager_guard = shm_manager_lock();
+                    if let Some(kernel_shm) = shm_manager_guard.get_mut(shm_id) {
+                        // 更新最后一次断开连接时间
+                        kernel_shm.update_dtim();
+
+                        // 映射计数减少
+                        kernel_shm.decrease_count();
+
+                        // 释放shm_id
+                        if kernel_shm.map_count() == 0
+                            && kernel_shm.mode().contains(ShmFlags::SHM_DEST)
+                        {
+                            shm_manager_guard.free_id(shm_id);
+                        }
                     }
                 }
             }
         }
 
+        self.zap_pages(&guard, mapper, &mut flusher);
+        guard.mapped = false;
+
+        // 当vma对应共享文件的写映射时，唤醒脏页回写线程
+        if guard.vm_file().is_some()
+            && guard
+                .vm_flags()
+                .contains(VmFlags::VM_SHARED | VmFlags::VM_WRITE)
+        {
+            crate::mm::page::PageReclaimer::wakeup_claim_thread();
+        }
+    }
+
+    /// 解除VMA内所有页面的映射，VMA本身保持不变
+    ///
+    /// 不再被映射的物理页会被释放，被换出页面占用的交换槽位也会被回收
+    ///
+    /// ## 参数
+    ///
+    /// - `guard`: 当前VMA的守卫
+    /// - `mapper`: 页表映射器
+    /// - `flusher`: 页表项刷新器
+    pub fn zap_pages(
+        &self,
+        guard: &VMA,
+        mapper: &mut PageMapper,
+        mut flusher: impl Flusher<MMArch>,
+    ) {
+        if guard.vm_flags().contains(VmFlags::VM_HUGETLB) {
+            hugetlb_unmap(guard, mapper, flusher);
+            return;
+        }
+        thp_unmap(guard.region, mapper, &mut flusher);
+
+        let mut page_manager_guard = page_manager_lock_irqsave();
         for page in guard.region.pages() {
             if mapper.translate(page.virt_address()).is_none() {
+                // 释放被换出页面占用的交换槽位
+                if let Some(entry) = unsafe { take_swap_entry(mapper, page.virt_address()) } {
+                    swap_free(entry);
+                }
                 continue;
             }
             let (paddr, _, flush) = unsafe { mapper.unmap_phys(page.virt_address(), true) }
@@ -1236,21 +1738,13 @@ impl LockedVMA {
             // 如果物理页的vma链表长度为0并且未标记为不可回收，则释放物理页.
             // TODO 后续由lru释放物理页面
             if page_guard.can_deallocate() {
+                drop(page_guard);
                 page_manager_guard.remove_page(&paddr);
+                page_reclaimer_lock_irqsave().remove_anon_page(&paddr);
             }
 
             flusher.consume(flush);
         }
-        guard.mapped = false;
-
-        // 当vma对应共享文件的写映射时，唤醒脏页回写线程
-        if guard.vm_file().is_some()
-            && guard
-                .vm_flags()
-                .contains(VmFlags::VM_SHARED | VmFlags::VM_WRITE)
-        {
-            crate::mm::page::PageReclaimer::wakeup_claim_thread();
-        }
     }
 
     pub fn mapped(&self) -> bool {
@@ -1290,26 +1784,51 @@ impl LockedVMA {
             }
         }
 
+        // 大页映射的页面不在页面管理器中，切分后的VMA沿用原VMA的映射状态
+        let hugetlb = guard.vm_flags().contains(VmFlags::VM_HUGETLB);
+        // 切分后的VMA在文件中的偏移页数
+        let start = guard.region.start();
+        let pgoff_at = |vaddr: VirtAddr| {
+            guard
+                .file_pgoff
+                .map(|pgoff| pgoff + ((vaddr - start) >> MMArch::PAGE_SHIFT))
+        };
         let before: Option<Arc<LockedVMA>> = guard.region.before(&region).map(|virt_region| {
             let mut vma: VMA = unsafe { guard.clone() };
             vma.region = virt_region;
-            vma.mapped = false;
+            vma.mapped = hugetlb && guard.mapped;
             let vma: Arc<LockedVMA> = LockedVMA::new(vma);
             vma
         });
 
         let after: Option<Arc<LockedVMA>> = guard.region.after(&region).map(|virt_region| {
             let mut vma: VMA = unsafe { guard.clone() };
+            vma.file_pgoff = pgoff_at(virt_region.start());
             vma.region = virt_region;
-            vma.mapped = false;
+            vma.mapped = hugetlb && guard.mapped;
             let vma: Arc<LockedVMA> = LockedVMA::new(vma);
             vma
         });
 
+        let file_pgoff = pgoff_at(region.start());
+        guard.file_pgoff = file_pgoff;
+        if hugetlb {
+            guard.region = region;
+            return Some(VMASplitResult::new(
+                before,
+                guard.self_ref.upgrade().unwrap(),
+                after,
+            ));
+        }
+
         // 重新设置before、after这两个VMA里面的物理页的anon_vma
         let mut page_manager_guard = page_manager_lock_irqsave();
         if let Some(before) = before.clone() {
-            let virt_iter = before.lock_irqsave().region.iter_pages();
+            let before_region = before.lock_irqsave().region;
+            if thp_count(utable, before_region) > 0 {
+                before.lock_irqsave().mapped = true;
+            }
+            let virt_iter = before_region.iter_pages();
             for frame in virt_iter {
                 if let Some((paddr, _)) = utable.translate(frame.virt_address()) {
                     let page = page_manager_guard.get_unwrap(&paddr);
@@ -1317,12 +1836,18 @@ impl LockedVMA {
                     page_guard.insert_vma(before.clone());
                     page_guard.remove_vma(self);
                     before.lock_irqsave().mapped = true;
+                } else if swap_entry_at(utable, frame.virt_address()).is_some() {
+                    before.lock_irqsave().mapped = true;
                 }
             }
         }
 
         if let Some(after) = after.clone() {
-            let virt_iter = after.lock_irqsave().region.iter_pages();
+            let after_region = after.lock_irqsave().region;
+            if thp_count(utable, after_region) > 0 {
+                after.lock_irqsave().mapped = true;
+            }
+            let virt_iter = after_region.iter_pages();
             for frame in virt_iter {
                 if let Some((paddr, _)) = utable.translate(frame.virt_address()) {
                     let page = page_manager_guard.get_unwrap(&paddr);
@@ -1330,6 +1855,8 @@ impl LockedVMA {
                     page_guard.insert_vma(after.clone());
                     page_guard.remove_vma(self);
                     after.lock_irqsave().mapped = true;
+                } else if swap_entry_at(utable, frame.virt_address()).is_some() {
+                    after.lock_irqsave().mapped = true;
                 }
             }
         }
@@ -1370,10 +1897,10 @@ impl LockedVMA {
         guard.vm_file.is_none()
     }
 
-    /// 判断VMA是否为大页映射
-    pub fn is_hugepage(&self) -> bool {
-        //TODO: 实现巨页映射判断逻辑，目前不支持巨页映射
-        false
+    /// 判断VMA中包含`vaddr`的2M区域能否映射为透明大页
+    pub fn is_hugepage(&self, vaddr: VirtAddr) -> bool {
+        let haddr = VirtAddr::new(align_down(vaddr.data(), HPAGE_PMD_SIZE));
+        thp_vma_allowable(&self.lock_irqsave(), haddr, false)
     }
 }
 
@@ -1423,6 +1950,8 @@ pub struct VMA {
     vm_file: Option<Arc<File>>,
     /// VMA映射的文件部分相对于整个文件的偏移页数
     file_pgoff: Option<usize>,
+    /// VMA的内存策略，为None时使用进程的内存策略
+    vm_policy: Option<Arc<MemPolicy>>,
 
     provider: Provider,
 }
@@ -1451,7 +1980,7 @@ impl VMA {
         pgoff: Option<usize>,
         mapped: bool,
     ) -> Self {
-        VMA {
+        let vma = VMA {
             region,
             vm_flags,
             flags,
@@ -1461,6 +1990,28 @@ impl VMA {
             provider: Provider::Allocated,
             vm_file: file,
             file_pgoff: pgoff,
+            vm_policy: None,
+        };
+        vma.mapping_allow_writable();
+        vma
+    }
+
+    /// 若VMA是文件的可写共享映射，返回被映射文件的inode
+    fn writable_mapping_inode(&self) -> Option<Arc<dyn IndexNode>> {
+        if self
+            .vm_flags
+            .contains(VmFlags::VM_SHARED | VmFlags::VM_MAYWRITE)
+        {
+            self.vm_file.as_ref().map(|file| file.inode())
+        } else {
+            None
+        }
+    }
+
+    /// 每个可写的共享映射VMA都计入文件的可写映射数量，VMA被释放时减少
+    fn mapping_allow_writable(&self) {
+        if let Some(inode) = self.writable_mapping_inode() {
+            inode.mapping_allow_writable();
         }
     }
 
@@ -1481,7 +2032,19 @@ impl VMA {
     }
 
     pub fn set_vm_flags(&mut self, vm_flags: VmFlags) {
+        if let Some(inode) = self.writable_mapping_inode() {
+            inode.mapping_unmap_writable();
+        }
         self.vm_flags = vm_flags;
+        self.mapping_allow_writable();
+    }
+
+    pub fn vm_policy(&self) -> Option<Arc<MemPolicy>> {
+        return self.vm_policy.clone();
+    }
+
+    pub fn set_vm_policy(&mut self, policy: Option<Arc<MemPolicy>>) {
+        self.vm_policy = policy;
     }
 
     pub fn set_region_size(&mut self, new_region_size: usize) {
@@ -1502,7 +2065,7 @@ impl VMA {
     ///
     /// 由于这样操作可能由于错误的拷贝，导致内存泄露、内存重复释放等问题，所以需要小心使用。
     pub unsafe fn clone(&self) -> Self {
-        return Self {
+        let vma = Self {
             region: self.region,
             vm_flags: self.vm_flags,
             flags: self.flags,
@@ -1512,11 +2075,14 @@ impl VMA {
             provider: Provider::Allocated,
             file_pgoff: self.file_pgoff,
             vm_file: self.vm_file.clone(),
+            vm_policy: self.vm_policy.clone(),
         };
+        vma.mapping_allow_writable();
+        return vma;
     }
 
     pub fn clone_info_only(&self) -> Self {
-        return Self {
+        let vma = Self {
             region: self.region,
             vm_flags: self.vm_flags,
             flags: self.flags,
@@ -1526,7 +2092,10 @@ impl VMA {
             provider: Provider::Allocated,
             file_pgoff: self.file_pgoff,
             vm_file: self.vm_file.clone(),
+            vm_policy: self.vm_policy.clone(),
         };
+        vma.mapping_allow_writable();
+        return vma;
     }
 
     #[inline(always)]
@@ -1552,12 +2121,29 @@ impl VMA {
         mapper: &mut PageMapper,
         mut flusher: impl Flusher<MMArch>,
     ) -> Result<(), SystemError> {
+        if self.vm_flags.contains(VmFlags::VM_HUGETLB) {
+            hugetlb_change_protection(self, flags, mapper, flusher);
+            self.flags = flags;
+            return Ok(());
+        }
+        thp_change_protection(self.region, flags, mapper, &mut flusher);
+        let mergeable = self.vm_flags.contains(VmFlags::VM_MERGEABLE);
         for page in self.region.pages() {
             // debug!("remap page {:?}", page.virt_address());
-            if mapper.translate(page.virt_address()).is_some() {
+            if let Some((paddr, _)) = mapper.translate(page.virt_address()) {
+                // KSM页面被多个进程共享，必须保持写保护，由写时复制拆分
+                let page_flags = if mergeable
+                    && page_manager_lock_irqsave()
+                        .get(&paddr)
+                        .is_some_and(|p| matches!(p.read_irqsave().page_type(), PageType::Ksm))
+                {
+                    flags.set_write(false)
+                } else {
+                    flags
+                };
                 let r = unsafe {
                     mapper
-                        .remap(page.virt_address(), flags)
+                        .remap(page.virt_address(), page_flags)
                         .expect("Failed to remap")
                 };
                 flusher.consume(r);
@@ -1575,6 +2161,12 @@ impl VMA {
     ///
     /// - `prot_flags` 要检查的标志位
     pub fn can_have_flags(&self, prot_flags: ProtFlags) -> bool {
+        if prot_flags.contains(ProtFlags::PROT_WRITE)
+            && self.vm_flags.contains(VmFlags::VM_SHARED)
+            && !self.vm_flags.contains(VmFlags::VM_MAYWRITE)
+        {
+            return false;
+        }
         let is_downgrade = (self.flags.has_write() || !prot_flags.contains(ProtFlags::PROT_WRITE))
             && (self.flags.has_execute() || !prot_flags.contains(ProtFlags::PROT_EXEC));
 
@@ -1670,6 +2262,7 @@ impl VMA {
         file: Option<Arc<File>>,
         pgoff: Option<usize>,
     ) -> Result<Arc<LockedVMA>, SystemError> {
+        let anon_private = file.is_none() && !vm_flags.contains(VmFlags::VM_SHARED);
         let mut cur_dest: VirtPageFrame = destination;
         // debug!(
         //     "VMA::zeroed: page_count = {:?}, destination={destination:?}",
@@ -1680,9 +2273,24 @@ impl VMA {
             //     "VMA::zeroed: cur_dest={cur_dest:?}, vaddr = {:?}",
             //     cur_dest.virt_address()
             // );
-            let r = unsafe { mapper.map(cur_dest.virt_address(), flags) }
-                .expect("Failed to map zero, may be OOM error");
-            // todo: 增加OOM处理
+            let r = match unsafe { mapper.map(cur_dest.virt_address(), flags) } {
+                Some(r) => r,
+                None => {
+                    // 内存不足：交给OOM killer释放内存，并撤销已经建立的映射。
+                    // 不支持缺页异常的架构（如riscv64）在这里立即分配所有页面
+                    out_of_memory();
+                    let mut page_manager_guard = page_manager_lock_irqsave();
+                    for frame in VirtPageFrameIter::new(destination, cur_dest) {
+                        if let Some((paddr, _, flush)) =
+                            unsafe { mapper.unmap_phys(frame.virt_address(), true) }
+                        {
+                            flusher.consume(flush);
+                            page_manager_guard.remove_page(&paddr);
+                        }
+                    }
+                    return Err(SystemError::ENOMEM);
+                }
+            };
 
             // 稍后再刷新TLB，这里取消刷新
             flusher.consume(r);
@@ -1706,12 +2314,22 @@ impl VMA {
         let mut page_manager_guard = page_manager_lock_irqsave();
         let virt_iter: VirtPageFrameIter =
             VirtPageFrameIter::new(destination, destination.add(page_count));
+        let mut anon_pages = Vec::new();
         for frame in virt_iter {
             let paddr = mapper.translate(frame.virt_address()).unwrap().0;
 
             // 将VMA加入到anon_vma
             let page = page_manager_guard.get_unwrap(&paddr);
             page.write_irqsave().insert_vma(r.clone());
+            if anon_private {
+                anon_pages.push((page, frame.virt_address()));
+            }
+        }
+        drop(page_manager_guard);
+
+        // 私有匿名页可以被换出
+        for (page, vaddr) in anon_pages {
+            lru_add_anon(&page, vaddr);
         }
         // debug!("VMA::zeroed: done");
         return Ok(r);
@@ -1733,6 +2351,9 @@ impl Drop for VMA {
     fn drop(&mut self) {
         // 当VMA被释放时，需要确保它已经被从页表中解除映射
         assert!(!self.mapped, "VMA is still mapped");
+        if let Some(inode) = self.writable_mapping_inode() {
+            inode.mapping_unmap_writable();
+        }
     }
 }
 
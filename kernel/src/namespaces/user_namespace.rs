@@ -11,6 +11,7 @@ use system_error::SystemError;
 
 use crate::namespaces::namespace::NsCommon;
 use crate::namespaces::ucount::UCounts;
+use crate::process::cred::Kgid;
 use crate::process::fork::CloneFlags;
 use crate::process::Pid;
 use alloc::sync::Arc;
@@ -20,6 +21,8 @@ use super::ucount::Ucount::Counts;
 
 const UID_GID_MAP_MAX_BASE_EXTENTS: usize = 5;
 const UCOUNT_MAX: u32 = 62636;
+/// 无法映射到当前user namespace中的id，在返回给用户态时显示为该值
+const OVERFLOW_GID: u32 = 65534;
 /// 管理用户ID和组ID的映射
 #[allow(dead_code)]
 #[derive(Clone, Debug)]
@@ -100,6 +103,30 @@ impl UidGidMap {
             extent: vec![UidGidExtent::new(); UID_GID_MAP_MAX_BASE_EXTENTS],
         }
     }
+
+    fn extents(&self) -> &[UidGidExtent] {
+        &self.extent[..self.nr_extents as usize]
+    }
+
+    /// 把namespace中的id映射为内核中的id，无法映射时返回None
+    ///
+    /// 参考 https://code.dragonos.org.cn/xref/linux-6.1.9/kernel/user_namespace.c#327
+    fn map_id_down(&self, id: u32) -> Option<u32> {
+        self.extents()
+            .iter()
+            .find(|e| id >= e.first && (id - e.first) < e.count)
+            .map(|e| e.lower_first + (id - e.first))
+    }
+
+    /// 把内核中的id映射为namespace中的id，无法映射时返回None
+    ///
+    /// 参考 https://code.dragonos.org.cn/xref/linux-6.1.9/kernel/user_namespace.c#390
+    fn map_id_up(&self, id: u32) -> Option<u32> {
+        self.extents()
+            .iter()
+            .find(|e| id >= e.lower_first && (id - e.lower_first) < e.count)
+            .map(|e| e.first + (id - e.lower_first))
+    }
 }
 
 impl UidGidExtent {
@@ -132,4 +159,33 @@ impl UserNamespace {
             rlimit_max: vec![65535, 10, 32000, 64 * 1024],
         }
     }
+
+    /// 把用户态传入的gid映射为内核中的gid
+    ///
+    /// ## 返回值
+    ///
+    /// gid无法在该namespace中映射时返回None
+    ///
+    /// 参考 https://code.dragonos.org.cn/xref/linux-6.1.9/kernel/user_namespace.c#469
+    pub fn make_kgid(&self, gid: u32) -> Option<Kgid> {
+        // (gid_t)-1 不是有效的gid
+        if gid == u32::MAX {
+            return None;
+        }
+        self.gid_map
+            .map_id_down(gid)
+            .map(|gid| Kgid::new(gid as usize))
+    }
+
+    /// 把内核中的gid映射为该namespace中的gid，用于返回给用户态
+    ///
+    /// 无法映射时返回overflowgid
+    ///
+    /// 参考 https://code.dragonos.org.cn/xref/linux-6.1.9/kernel/user_namespace.c#513
+    pub fn from_kgid_munged(&self, kgid: Kgid) -> u32 {
+        u32::try_from(kgid.data())
+            .ok()
+            .and_then(|gid| self.gid_map.map_id_up(gid))
+            .unwrap_or(OVERFLOW_GID)
+    }
 }
@@ -29,7 +29,7 @@ use crate::{
         spinlock::{SpinLock, SpinLockGuard},
         wait_queue::EventWaitQueue,
     },
-    process::{Pid, ProcessManager},
+    process::{capability::capable, cred::CAPFlags, Pid, ProcessManager},
     sched::{schedule, SchedMode},
 };
 
@@ -77,7 +77,12 @@ pub(super) fn new_socket(
         AddressFamily::INet => match socket_type {
             PosixSocketType::Stream => Box::new(TcpSocket::new(SocketOptions::default())),
             PosixSocketType::Datagram => Box::new(UdpSocket::new(SocketOptions::default())),
-            PosixSocketType::Raw => Box::new(RawSocket::new(protocol, SocketOptions::default())),
+            PosixSocketType::Raw => {
+                if !capable(CAPFlags::CAP_NET_RAW) {
+                    return Err(SystemError::EPERM);
+                }
+                Box::new(RawSocket::new(protocol, SocketOptions::default()))
+            }
             _ => {
                 return Err(SystemError::EINVAL);
             }
@@ -0,0 +1,310 @@
+//! Linux capabilities
+//!
+//! 包括capget/capset系统调用、execve时capability集合的变换，以及文件capability的解析
+//!
+//! 参考 https://code.dragonos.org.cn/xref/linux-6.1.9/security/commoncap.c
+
+use alloc::sync::Arc;
+use system_error::SystemError;
+
+use crate::{
+    filesystem::vfs::{syscall::ModeType, xattr::XATTR_NAME_CAPS, IndexNode},
+    syscall::{
+        user_access::{UserBufferReader, UserBufferWriter},
+        Syscall,
+    },
+};
+
+use super::{
+    cred::{CAPFlags, Cred, Kgid, Kuid},
+    Pid, ProcessControlBlock, ProcessManager,
+};
+
+const LINUX_CAPABILITY_VERSION_1: u32 = 0x19980330;
+const LINUX_CAPABILITY_VERSION_2: u32 = 0x20071026;
+const LINUX_CAPABILITY_VERSION_3: u32 = 0x20080522;
+
+const VFS_CAP_REVISION_MASK: u32 = 0xFF000000;
+const VFS_CAP_FLAGS_EFFECTIVE: u32 = 0x000001;
+const VFS_CAP_REVISION_1: u32 = 0x01000000;
+const VFS_CAP_REVISION_2: u32 = 0x02000000;
+const VFS_CAP_REVISION_3: u32 = 0x03000000;
+
+const XATTR_CAPS_SZ_1: usize = 12;
+const XATTR_CAPS_SZ_2: usize = 20;
+const XATTR_CAPS_SZ_3: usize = 24;
+
+/// capget/capset的头部
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct CapUserHeader {
+    pub version: u32,
+    pub pid: i32,
+}
+
+/// capget/capset的数据，每一项保存32个capability
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CapUserData {
+    pub effective: u32,
+    pub permitted: u32,
+    pub inheritable: u32,
+}
+
+/// 判断当前进程是否拥有`cap`
+pub fn capable(cap: CAPFlags) -> bool {
+    ProcessManager::current_pcb().cred().has_capability(cap)
+}
+
+/// 从可执行文件的扩展属性中读取到的capability
+#[derive(Debug, Clone, Copy)]
+struct FileCaps {
+    permitted: CAPFlags,
+    inheritable: CAPFlags,
+    effective: bool,
+}
+
+impl FileCaps {
+    /// 读取并解析inode的`security.capability`扩展属性
+    ///
+    /// 文件系统不支持扩展属性，或者属性不存在、格式不正确时，返回None
+    fn from_inode(inode: &Arc<dyn IndexNode>) -> Option<Self> {
+        let mut buf = [0u8; XATTR_CAPS_SZ_3];
+        let size = inode.getxattr(XATTR_NAME_CAPS, &mut buf).ok()?;
+        if size < 4 {
+            return None;
+        }
+        let word = |i: usize| u32::from_le_bytes(buf[i * 4..i * 4 + 4].try_into().unwrap());
+        let magic_etc = word(0);
+        let u32s = match (magic_etc & VFS_CAP_REVISION_MASK, size) {
+            (VFS_CAP_REVISION_1, XATTR_CAPS_SZ_1) => 1,
+            (VFS_CAP_REVISION_2, XATTR_CAPS_SZ_2) => 2,
+            // 只支持属于初始user namespace（rootid为0）的文件capability
+            (VFS_CAP_REVISION_3, XATTR_CAPS_SZ_3) if word(5) == 0 => 2,
+            _ => {
+                log::warn!("invalid file capability, magic_etc: {:#x}", magic_etc);
+                return None;
+            }
+        };
+
+        let mut permitted = 0u64;
+        let mut inheritable = 0u64;
+        for i in 0..u32s {
+            permitted |= (word(1 + i * 2) as u64) << (32 * i);
+            inheritable |= (word(2 + i * 2) as u64) << (32 * i);
+        }
+        Some(Self {
+            permitted: CAPFlags::from_bits_truncate(permitted),
+            inheritable: CAPFlags::from_bits_truncate(inheritable),
+            effective: magic_etc & VFS_CAP_FLAGS_EFFECTIVE != 0,
+        })
+    }
+}
+
+/// execve时，根据可执行文件的set-user-ID/set-group-ID位和文件capability计算新的凭证
+///
+/// ## 参数
+///
+/// - `pcb` 执行execve的进程
+/// - `inode` 被执行的文件
+///
+/// ## 返回值
+///
+/// 是否需要以安全模式运行新程序（AT_SECURE）
+///
+/// 参考 https://code.dragonos.org.cn/xref/linux-6.1.9/security/commoncap.c#903
+pub fn exec_update_creds(pcb: &Arc<ProcessControlBlock>, inode: &Arc<dyn IndexNode>) -> bool {
+    let no_new_privs = pcb.no_new_privs();
+    // 读取元数据和扩展属性可能需要访问磁盘，因此要在获取凭证的锁之前完成
+    let metadata = if no_new_privs {
+        None
+    } else {
+        inode.metadata().ok()
+    };
+    let file_caps = FileCaps::from_inode(inode);
+
+    let mut cred = pcb.cred.lock();
+    let old = cred.clone();
+    let mut new = cred.clone();
+    let root = Kuid::new(0);
+
+    if let Some(metadata) = metadata {
+        if metadata.mode.contains(ModeType::S_ISUID) {
+            new.euid = Kuid::new(metadata.uid);
+        }
+        if metadata
+            .mode
+            .contains(ModeType::S_ISGID | ModeType::S_IXGRP)
+        {
+            new.egid = Kgid::new(metadata.gid);
+        }
+    }
+
+    let mut effective = false;
+    new.cap_permitted = CAPFlags::CAP_EMPTY_SET;
+    if let Some(fcaps) = file_caps {
+        new.cap_permitted =
+            (fcaps.permitted & old.cap_bset) | (fcaps.inheritable & old.cap_inheritable);
+        effective = fcaps.effective;
+    }
+
+    // root执行程序时，认为文件拥有全部的capability。
+    // set-user-ID-root的程序如果带有文件capability，则以文件capability为准
+    if !(file_caps.is_some() && new.uid != root && new.euid == root) {
+        if new.euid == root || new.uid == root {
+            new.cap_permitted = old.cap_bset | old.cap_inheritable;
+        }
+        if new.euid == root {
+            effective = true;
+        }
+    }
+
+    let is_setid = new.euid != old.uid || new.egid != old.gid;
+    let cap_gained = !old.cap_permitted.contains(new.cap_permitted);
+    // 设置了no_new_privs时，新程序获得的权限不能超过原有的权限
+    if no_new_privs && (is_setid || cap_gained) {
+        new.euid = new.uid;
+        new.egid = new.gid;
+        new.cap_permitted &= old.cap_permitted;
+    }
+
+    new.suid = new.euid;
+    new.fsuid = new.euid;
+    new.sgid = new.egid;
+    new.fsgid = new.egid;
+
+    if file_caps.is_some() || is_setid {
+        new.cap_ambient = CAPFlags::CAP_EMPTY_SET;
+    }
+    new.cap_permitted |= new.cap_ambient;
+    new.cap_effective = if effective {
+        new.cap_permitted
+    } else {
+        new.cap_ambient
+    };
+
+    let secure = is_setid
+        || (new.uid != root && (effective || !new.cap_ambient.contains(new.cap_permitted)));
+    *cred = new;
+    return secure;
+}
+
+impl Syscall {
+    /// 检查capget/capset的版本号
+    ///
+    /// ## 返回值
+    ///
+    /// - `Ok((usize, i32))` 用户数据中[`CapUserData`]的个数，以及头部中的pid
+    /// - `Err(SystemError::EINVAL)` 不支持的版本，此时会把内核推荐的版本写回到头部
+    fn cap_validate_magic(header: *mut CapUserHeader) -> Result<(usize, i32), SystemError> {
+        let reader = UserBufferReader::new(header, core::mem::size_of::<CapUserHeader>(), true)?;
+        let hdr = *reader.read_one_from_user::<CapUserHeader>(0)?;
+        match hdr.version {
+            LINUX_CAPABILITY_VERSION_1 => Ok((1, hdr.pid)),
+            LINUX_CAPABILITY_VERSION_2 | LINUX_CAPABILITY_VERSION_3 => Ok((2, hdr.pid)),
+            _ => {
+                let mut writer =
+                    UserBufferWriter::new(header, core::mem::size_of::<CapUserHeader>(), true)?;
+                writer.copy_one_to_user(&LINUX_CAPABILITY_VERSION_3, 0)?;
+                Err(SystemError::EINVAL)
+            }
+        }
+    }
+
+    /// # capget系统调用
+    ///
+    /// 获取进程的capability集合
+    ///
+    /// ## 参数
+    ///
+    /// - `header` 版本号和目标进程的pid，pid为0时表示当前进程
+    /// - `data` 用于返回capability集合，为NULL时只检查版本号
+    pub fn capget(
+        header: *mut CapUserHeader,
+        data: *mut CapUserData,
+    ) -> Result<usize, SystemError> {
+        let (u32s, pid) = match Self::cap_validate_magic(header) {
+            Ok(r) => r,
+            // 用户态通过传入NULL来查询内核支持的版本
+            Err(SystemError::EINVAL) if data.is_null() => return Ok(0),
+            Err(e) => return Err(e),
+        };
+        if data.is_null() {
+            return Ok(0);
+        }
+        if pid < 0 {
+            return Err(SystemError::EINVAL);
+        }
+
+        let cred: Cred = if pid == 0 {
+            ProcessManager::current_pcb().cred()
+        } else {
+            ProcessManager::find(Pid::new(pid as usize))
+                .ok_or(SystemError::ESRCH)?
+                .cred()
+        };
+
+        let mut kdata = [CapUserData::default(); 2];
+        for (i, d) in kdata.iter_mut().enumerate() {
+            d.effective = (cred.cap_effective.bits() >> (32 * i)) as u32;
+            d.permitted = (cred.cap_permitted.bits() >> (32 * i)) as u32;
+            d.inheritable = (cred.cap_inheritable.bits() >> (32 * i)) as u32;
+        }
+        let mut writer =
+            UserBufferWriter::new(data, u32s * core::mem::size_of::<CapUserData>(), true)?;
+        writer.copy_to_user(&kdata[..u32s], 0)?;
+        return Ok(0);
+    }
+
+    /// # capset系统调用
+    ///
+    /// 设置当前进程的capability集合
+    ///
+    /// ## 参数
+    ///
+    /// - `header` 版本号和目标进程的pid，只能设置当前进程
+    /// - `data` 新的capability集合
+    pub fn capset(
+        header: *mut CapUserHeader,
+        data: *const CapUserData,
+    ) -> Result<usize, SystemError> {
+        let (u32s, pid) = Self::cap_validate_magic(header)?;
+        let pcb = ProcessManager::current_pcb();
+        if pid != 0 && pid as usize != pcb.pid().data() {
+            return Err(SystemError::EPERM);
+        }
+
+        let reader = UserBufferReader::new(data, u32s * core::mem::size_of::<CapUserData>(), true)?;
+        let udata = reader.read_from_user::<CapUserData>(0)?;
+        let (mut effective, mut permitted, mut inheritable) = (0u64, 0u64, 0u64);
+        for (i, d) in udata.iter().take(u32s).enumerate() {
+            effective |= (d.effective as u64) << (32 * i);
+            permitted |= (d.permitted as u64) << (32 * i);
+            inheritable |= (d.inheritable as u64) << (32 * i);
+        }
+        let effective = CAPFlags::from_bits_truncate(effective);
+        let permitted = CAPFlags::from_bits_truncate(permitted);
+        let inheritable = CAPFlags::from_bits_truncate(inheritable);
+
+        let mut cred = pcb.cred.lock();
+        // 没有CAP_SETPCAP时，inheritable集合不能超出原有的permitted集合
+        if !cred.has_capability(CAPFlags::CAP_SETPCAP)
+            && !(cred.cap_inheritable | cred.cap_permitted).contains(inheritable)
+        {
+            return Err(SystemError::EPERM);
+        }
+        if !(cred.cap_inheritable | cred.cap_bset).contains(inheritable)
+            || !cred.cap_permitted.contains(permitted)
+            || !permitted.contains(effective)
+        {
+            return Err(SystemError::EPERM);
+        }
+
+        cred.cap_effective = effective;
+        cred.cap_permitted = permitted;
+        cred.cap_inheritable = inheritable;
+        // ambient集合必须是permitted和inheritable的子集
+        cred.cap_ambient &= permitted & inheritable;
+        return Ok(0);
+    }
+}
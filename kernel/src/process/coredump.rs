@@ -0,0 +1,791 @@
+//! core dump
+//!
+//! 进程因为SIGSEGV、SIGABRT等信号终止时，把进程的内存和各个线程的寄存器保存为ELF格式的core文件，
+//! 以便使用gdb进行事后调试。core文件的路径由`/proc/sys/kernel/core_pattern`决定，
+//! 大小受到`RLIMIT_CORE`的限制。
+//!
+//! 参考 https://code.dragonos.org.cn/xref/linux-6.1.9/fs/coredump.c
+//! 和 https://code.dragonos.org.cn/xref/linux-6.1.9/fs/binfmt_elf.c#2060
+
+use core::fmt::Write;
+use core::mem::size_of;
+
+use alloc::{
+    string::{String, ToString},
+    sync::Arc,
+    vec::Vec,
+};
+use elf::abi::{ET_CORE, PF_R, PF_W, PF_X, PT_LOAD, PT_NOTE};
+use log::warn;
+use system_error::SystemError;
+
+use crate::{
+    arch::{interrupt::TrapFrame, ipc::signal::Signal, mm::PageMapper, CurrentElfArch, MMArch},
+    filesystem::vfs::{
+        fcntl::AtFlags,
+        file::{File, FileMode},
+        permission::{inode_permission, PermissionMask},
+        syscall::ModeType,
+        utils::{rsplit_path, user_path_at},
+        FileType, IndexNode, ROOT_INODE, VFS_MAX_FOLLOW_SYMLINK_TIMES,
+    },
+    libs::{elf::ElfArch, rwlock::RwLock, spinlock::SpinLock, wait_queue::WaitQueue},
+    mm::{ucontext::AddressSpace, MemoryManagementArch, PhysAddr, VirtAddr, VmFlags},
+    time::PosixTimeSpec,
+};
+
+use super::{
+    prctl::SUID_DUMP_DISABLE, resource::RLimitID, syscall::PosixOldUtsName, Pid,
+    ProcessControlBlock, ProcessFlags, ProcessManager,
+};
+
+/// 进程生成了core文件时，在退出状态中设置的标志
+pub const WCOREFLAG: usize = 0x80;
+
+/// core_pattern的最大长度
+pub const CORENAME_MAX_SIZE: usize = 128;
+
+/// 默认的core_pattern，在当前工作目录下生成名为core的文件
+const DEFAULT_CORE_PATTERN: &str = "core";
+
+/// core_pattern，为None时使用[`DEFAULT_CORE_PATTERN`]
+static CORE_PATTERN: RwLock<Option<String>> = RwLock::new(None);
+
+/// 获取core_pattern
+pub fn core_pattern() -> String {
+    CORE_PATTERN
+        .read()
+        .clone()
+        .unwrap_or_else(|| DEFAULT_CORE_PATTERN.to_string())
+}
+
+/// 设置core_pattern
+///
+/// ## 返回值
+///
+/// - `Err(SystemError::EINVAL)` 长度超过了[`CORENAME_MAX_SIZE`]
+pub fn set_core_pattern(pattern: &str) -> Result<(), SystemError> {
+    if pattern.len() >= CORENAME_MAX_SIZE {
+        return Err(SystemError::EINVAL);
+    }
+    *CORE_PATTERN.write() = Some(pattern.to_string());
+    return Ok(());
+}
+
+const NT_PRSTATUS: u32 = 1;
+const NT_PRPSINFO: u32 = 3;
+const NT_AUXV: u32 = 6;
+const NT_FILE: u32 = 0x46494c45;
+
+/// ELF文件头
+#[repr(C)]
+#[derive(Debug, Default)]
+struct Elf64Ehdr {
+    e_ident: [u8; 16],
+    e_type: u16,
+    e_machine: u16,
+    e_version: u32,
+    e_entry: u64,
+    e_phoff: u64,
+    e_shoff: u64,
+    e_flags: u32,
+    e_ehsize: u16,
+    e_phentsize: u16,
+    e_phnum: u16,
+    e_shentsize: u16,
+    e_shnum: u16,
+    e_shstrndx: u16,
+}
+
+/// ELF程序头
+#[repr(C)]
+#[derive(Debug, Default)]
+struct Elf64Phdr {
+    p_type: u32,
+    p_flags: u32,
+    p_offset: u64,
+    p_vaddr: u64,
+    p_paddr: u64,
+    p_filesz: u64,
+    p_memsz: u64,
+    p_align: u64,
+}
+
+#[repr(C)]
+#[derive(Debug, Default)]
+struct ElfSiginfo {
+    si_signo: i32,
+    si_code: i32,
+    si_errno: i32,
+}
+
+/// NT_PRSTATUS的内容，对应Linux的`struct elf_prstatus`
+#[repr(C)]
+#[derive(Debug)]
+struct ElfPrstatus {
+    pr_info: ElfSiginfo,
+    pr_cursig: i16,
+    pr_sigpend: u64,
+    pr_sighold: u64,
+    pr_pid: i32,
+    pr_ppid: i32,
+    pr_pgrp: i32,
+    pr_sid: i32,
+    pr_utime: [i64; 2],
+    pr_stime: [i64; 2],
+    pr_cutime: [i64; 2],
+    pr_cstime: [i64; 2],
+    pr_reg: [u64; CurrentElfArch::ELF_NGREG],
+    pr_fpvalid: i32,
+}
+
+/// NT_PRPSINFO的内容，对应Linux的`struct elf_prpsinfo`
+#[repr(C)]
+#[derive(Debug)]
+struct ElfPrpsinfo {
+    pr_state: i8,
+    pr_sname: u8,
+    pr_zomb: i8,
+    pr_nice: i8,
+    pr_flag: u64,
+    pr_uid: u32,
+    pr_gid: u32,
+    pr_pid: i32,
+    pr_ppid: i32,
+    pr_pgrp: i32,
+    pr_sid: i32,
+    pr_fname: [u8; 16],
+    pr_psargs: [u8; 80],
+}
+
+/// 创建一个所有字节（包括填充字节）都为0的结构体
+fn zeroed<T>() -> T {
+    unsafe { core::mem::MaybeUninit::<T>::zeroed().assume_init() }
+}
+
+fn as_bytes<T>(value: &T) -> &[u8] {
+    unsafe { core::slice::from_raw_parts(value as *const T as *const u8, size_of::<T>()) }
+}
+
+/// 向`buf`追加一个名称为"CORE"的ELF note
+fn push_note(buf: &mut Vec<u8>, note_type: u32, desc: &[u8]) {
+    const NAME: &[u8] = b"CORE\0\0\0\0";
+    buf.extend_from_slice(&5u32.to_ne_bytes());
+    buf.extend_from_slice(&(desc.len() as u32).to_ne_bytes());
+    buf.extend_from_slice(&note_type.to_ne_bytes());
+    buf.extend_from_slice(NAME);
+    buf.extend_from_slice(desc);
+    buf.resize(buf.len().next_multiple_of(4), 0);
+}
+
+/// 要写入core文件的VMA
+#[derive(Debug)]
+struct CoreVma {
+    start: VirtAddr,
+    end: VirtAddr,
+    flags: VmFlags,
+    /// 写入core文件的字节数，其余部分在core文件中不占空间
+    dump_size: usize,
+    /// 映射的文件的路径以及VMA在文件中的页偏移
+    file: Option<(String, usize)>,
+}
+
+/// 把`RLIMIT_CORE`考虑在内的core文件写入器
+struct CoreWriter {
+    file: File,
+    written: usize,
+    limit: usize,
+}
+
+impl CoreWriter {
+    fn emit(&mut self, buf: &[u8]) -> Result<(), SystemError> {
+        if self.written + buf.len() > self.limit {
+            return Err(SystemError::EFBIG);
+        }
+        let mut pos = 0;
+        while pos < buf.len() {
+            let n = self.file.write(buf.len() - pos, &buf[pos..])?;
+            if n == 0 {
+                return Err(SystemError::EIO);
+            }
+            pos += n;
+        }
+        self.written += buf.len();
+        return Ok(());
+    }
+
+    /// 补0，直到写入的字节数是`align`的整数倍
+    fn align(&mut self, align: usize) -> Result<(), SystemError> {
+        let pad = self.written.next_multiple_of(align) - self.written;
+        return self.emit(&alloc::vec![0u8; pad]);
+    }
+}
+
+/// 获取用户虚拟地址对应的物理地址，包括大页。页面没有被映射时返回None
+fn user_page_phys(mapper: &PageMapper, vaddr: VirtAddr) -> Option<PhysAddr> {
+    (0..MMArch::PAGE_LEVELS - 1).find_map(|level| {
+        let entry = mapper.get_entry(vaddr, level)?;
+        if !entry.present() {
+            return None;
+        }
+        if level != 0
+            && (MMArch::ENTRY_FLAG_HUGE_PAGE == 0
+                || !entry.flags().has_flag(MMArch::ENTRY_FLAG_HUGE_PAGE))
+        {
+            return None;
+        }
+        let page_size = MMArch::PAGE_SIZE << (MMArch::PAGE_ENTRY_SHIFT * level);
+        Some(entry.address().ok()? + (vaddr.data() & (page_size - 1)))
+    })
+}
+
+/// 把用户虚拟地址所在的页面的内容复制到`buf`中，页面没有被映射时填充0
+///
+/// 被换出到交换分区的页面也会被填充为0
+fn read_user_page(vm: &Arc<AddressSpace>, vaddr: VirtAddr, buf: &mut [u8]) {
+    let guard = vm.read();
+    match user_page_phys(&guard.user_mapper.utable, vaddr) {
+        Some(paddr) => {
+            let src = unsafe { MMArch::phys_2_virt(paddr) }.unwrap();
+            buf.copy_from_slice(unsafe {
+                core::slice::from_raw_parts(src.data() as *const u8, buf.len())
+            });
+        }
+        None => buf.fill(0),
+    }
+}
+
+/// 收集要写入core文件的VMA，并计算每个VMA要写入的字节数
+///
+/// 参考Linux的`vma_dump_size`，使用默认的coredump_filter(0x33)
+fn collect_vmas(vm: &Arc<AddressSpace>) -> Vec<CoreVma> {
+    let guard = vm.read();
+    let mut result = Vec::new();
+    for vma in guard.mappings.iter_vmas() {
+        let (region, flags, file, pgoff) = {
+            let vma = vma.lock_irqsave();
+            (
+                *vma.region(),
+                *vma.vm_flags(),
+                vma.vm_file(),
+                vma.file_page_offset().unwrap_or(0),
+            )
+        };
+        let file = file.map(|file| (file.inode().absolute_path().unwrap_or_default(), pgoff));
+
+        let dump_size = if flags.intersects(VmFlags::VM_DONTDUMP | VmFlags::VM_IO) {
+            0
+        } else if flags.contains(VmFlags::VM_SHARED) && flags.contains(VmFlags::VM_HUGETLB) {
+            0
+        } else if file.is_none() || flags.contains(VmFlags::VM_HUGETLB) {
+            region.size()
+        } else if !flags.contains(VmFlags::VM_SHARED) && flags.contains(VmFlags::VM_WRITE) {
+            // 私有的可写文件映射中可能有写时复制产生的匿名页
+            region.size()
+        } else if file.as_ref().is_some_and(|(_, pgoff)| *pgoff == 0)
+            && flags.contains(VmFlags::VM_READ)
+        {
+            // 保存ELF文件头所在的页，以便调试器找到build-id
+            let mut magic = [0u8; 4];
+            if let Some(paddr) = user_page_phys(&guard.user_mapper.utable, region.start()) {
+                let src = unsafe { MMArch::phys_2_virt(paddr) }.unwrap();
+                magic.copy_from_slice(unsafe {
+                    core::slice::from_raw_parts(src.data() as *const u8, magic.len())
+                });
+            }
+            if magic == *b"\x7fELF" {
+                MMArch::PAGE_SIZE
+            } else {
+                0
+            }
+        } else {
+            0
+        };
+
+        result.push(CoreVma {
+            start: region.start(),
+            end: region.end(),
+            flags,
+            dump_size,
+            file,
+        });
+    }
+    result.sort_by_key(|vma| vma.start);
+    return result;
+}
+
+/// 生成线程的NT_PRSTATUS
+fn fill_prstatus(pcb: &Arc<ProcessControlBlock>, frame: &TrapFrame, sig: Signal) -> ElfPrstatus {
+    let mut prstatus: ElfPrstatus = zeroed();
+    prstatus.pr_info.si_signo = sig as i32;
+    prstatus.pr_cursig = sig as i16;
+    {
+        let sig_info = pcb.sig_info_irqsave();
+        prstatus.pr_sigpend = sig_info.sig_pending().signal().bits();
+        prstatus.pr_sighold = sig_info.sig_blocked().bits();
+    }
+    prstatus.pr_pid = pcb.pid().data() as i32;
+    prstatus.pr_ppid = pcb.basic().ppid().data() as i32;
+    prstatus.pr_pgrp = pcb.pgid().data() as i32;
+    prstatus.pr_sid = pcb.sid().data() as i32;
+    CurrentElfArch::core_regs(pcb, frame, &mut prstatus.pr_reg);
+    return prstatus;
+}
+
+/// 生成进程的NT_PRPSINFO
+fn fill_prpsinfo(pcb: &Arc<ProcessControlBlock>) -> ElfPrpsinfo {
+    let mut psinfo: ElfPrpsinfo = zeroed();
+    psinfo.pr_sname = b'R';
+    let cred = pcb.cred();
+    psinfo.pr_uid = cred.uid.data() as u32;
+    psinfo.pr_gid = cred.gid.data() as u32;
+    psinfo.pr_pid = pcb.tgid().data() as i32;
+    psinfo.pr_ppid = pcb.basic().ppid().data() as i32;
+    psinfo.pr_pgrp = pcb.pgid().data() as i32;
+    psinfo.pr_sid = pcb.sid().data() as i32;
+
+    let exe = pcb.execute_path();
+    let fname = exe.rsplit('/').next().unwrap_or("").as_bytes();
+    let len = fname.len().min(psinfo.pr_fname.len() - 1);
+    psinfo.pr_fname[..len].copy_from_slice(&fname[..len]);
+
+    let basic = pcb.basic();
+    let psargs = basic.name().as_bytes();
+    let len = psargs.len().min(psinfo.pr_psargs.len() - 1);
+    psinfo.pr_psargs[..len].copy_from_slice(&psargs[..len]);
+    return psinfo;
+}
+
+/// 生成NT_FILE：所有文件映射的地址范围、文件偏移以及文件路径
+fn fill_files_note(vmas: &[CoreVma]) -> Vec<u8> {
+    let files: Vec<(&CoreVma, &String, usize)> = vmas
+        .iter()
+        .filter_map(|vma| vma.file.as_ref().map(|(path, pgoff)| (vma, path, *pgoff)))
+        .collect();
+    let mut desc = Vec::new();
+    desc.extend_from_slice(&(files.len() as u64).to_ne_bytes());
+    desc.extend_from_slice(&(MMArch::PAGE_SIZE as u64).to_ne_bytes());
+    for (vma, _, pgoff) in files.iter() {
+        desc.extend_from_slice(&(vma.start.data() as u64).to_ne_bytes());
+        desc.extend_from_slice(&(vma.end.data() as u64).to_ne_bytes());
+        desc.extend_from_slice(&(*pgoff as u64).to_ne_bytes());
+    }
+    for (_, path, _) in files.iter() {
+        desc.extend_from_slice(path.as_bytes());
+        desc.push(0);
+    }
+    return desc;
+}
+
+/// 生成所有的note
+///
+/// 与Linux一致，当前线程的NT_PRSTATUS在最前面，之后是进程的note，最后是其他线程的NT_PRSTATUS
+fn fill_notes(
+    pcb: &Arc<ProcessControlBlock>,
+    frame: &TrapFrame,
+    sig: Signal,
+    vm: &Arc<AddressSpace>,
+    vmas: &[CoreVma],
+) -> Vec<u8> {
+    let mut notes = Vec::new();
+    push_note(
+        &mut notes,
+        NT_PRSTATUS,
+        as_bytes(&fill_prstatus(pcb, frame, sig)),
+    );
+    push_note(&mut notes, NT_PRPSINFO, as_bytes(&fill_prpsinfo(pcb)));
+
+    let mut auxv: Vec<u8> = Vec::new();
+    for (key, value) in vm.read().saved_auxv.iter().chain([(0, 0)].iter()) {
+        auxv.extend_from_slice(&(*key as u64).to_ne_bytes());
+        auxv.extend_from_slice(&(*value as u64).to_ne_bytes());
+    }
+    push_note(&mut notes, NT_AUXV, &auxv);
+    push_note(&mut notes, NT_FILE, &fill_files_note(vmas));
+
+    // 其他线程已经在coredump_wait中停下来，它们的用户态寄存器保存在其内核栈的栈顶
+    pcb.for_each_thread(|thread| {
+        if thread.pid() == pcb.pid() || thread.is_exited() {
+            return;
+        }
+        let frame = unsafe {
+            &*((thread.kernel_stack().stack_max_address().data() - size_of::<TrapFrame>())
+                as *const TrapFrame)
+        };
+        push_note(
+            &mut notes,
+            NT_PRSTATUS,
+            as_bytes(&fill_prstatus(thread, frame, sig)),
+        );
+    });
+    return notes;
+}
+
+/// 根据core_pattern生成core文件的路径
+///
+/// 支持的格式说明符与Linux相同：`%%` `%p` `%P` `%i` `%I` `%u` `%g` `%s` `%t` `%h` `%e` `%E` `%c`
+fn format_corename(
+    pcb: &Arc<ProcessControlBlock>,
+    pattern: &str,
+    sig: Signal,
+    limit: u64,
+) -> String {
+    let mut name = String::new();
+    let cred = pcb.cred();
+    let mut chars = pattern.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            name.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('%') => name.push('%'),
+            Some('p') | Some('P') => write!(name, "{}", pcb.tgid().data()).unwrap(),
+            Some('i') | Some('I') => write!(name, "{}", pcb.pid().data()).unwrap(),
+            Some('u') => write!(name, "{}", cred.uid.data()).unwrap(),
+            Some('g') => write!(name, "{}", cred.gid.data()).unwrap(),
+            Some('s') => write!(name, "{}", sig as i32).unwrap(),
+            Some('t') => write!(name, "{}", PosixTimeSpec::now().tv_sec).unwrap(),
+            Some('h') => {
+                let uts = PosixOldUtsName::new();
+                let len = uts.nodename.iter().position(|&b| b == 0).unwrap_or(0);
+                name.push_str(core::str::from_utf8(&uts.nodename[..len]).unwrap_or(""));
+            }
+            Some('e') => {
+                let exe = pcb.execute_path();
+                let comm = exe.rsplit('/').next().unwrap_or("");
+                name.extend(
+                    comm.chars()
+                        .take(15)
+                        .map(|c| if c == '/' { '!' } else { c }),
+                );
+            }
+            Some('E') => name.push_str(&pcb.execute_path().replace('/', "!")),
+            Some('c') => write!(name, "{}", limit).unwrap(),
+            // 不认识的格式说明符被忽略
+            _ => {}
+        }
+    }
+    return name;
+}
+
+/// 创建core文件
+///
+/// 与Linux一致，不跟随符号链接，并且只覆盖当前用户拥有的、只有一个硬链接的普通文件
+fn open_core_file(pcb: &Arc<ProcessControlBlock>, corename: &str) -> Result<File, SystemError> {
+    let (_, path) = user_path_at(pcb, AtFlags::AT_FDCWD.bits(), corename)?;
+    let inode: Arc<dyn IndexNode> =
+        match ROOT_INODE().lookup_follow_symlink2(&path, VFS_MAX_FOLLOW_SYMLINK_TIMES, false) {
+            Ok(inode) => {
+                let metadata = inode.metadata()?;
+                if metadata.file_type != FileType::File
+                    || metadata.nlinks > 1
+                    || metadata.uid != pcb.cred().fsuid.data()
+                {
+                    return Err(SystemError::EPERM);
+                }
+                inode_permission(&inode, PermissionMask::MAY_WRITE)?;
+                inode
+            }
+            Err(SystemError::ENOENT) => {
+                let (filename, parent_path) = rsplit_path(&path);
+                let parent = ROOT_INODE().lookup(parent_path.unwrap_or("/"))?;
+                inode_permission(
+                    &parent,
+                    PermissionMask::MAY_WRITE | PermissionMask::MAY_EXEC,
+                )?;
+                parent.create(
+                    filename,
+                    FileType::File,
+                    ModeType::from_bits_truncate(0o600),
+                )?
+            }
+            Err(e) => return Err(e),
+        };
+    let file = File::new(inode, FileMode::O_WRONLY)?;
+    file.ftruncate(0)?;
+    return Ok(file);
+}
+
+/// 把进程的内存和寄存器写入core文件
+fn elf_core_dump(
+    pcb: &Arc<ProcessControlBlock>,
+    frame: &TrapFrame,
+    sig: Signal,
+    vm: &Arc<AddressSpace>,
+    writer: &mut CoreWriter,
+) -> Result<(), SystemError> {
+    let vmas = collect_vmas(vm);
+    let notes = fill_notes(pcb, frame, sig, vm, &vmas);
+
+    let phnum = vmas.len() + 1;
+    let notes_offset = size_of::<Elf64Ehdr>() + phnum * size_of::<Elf64Phdr>();
+    let data_offset = (notes_offset + notes.len()).next_multiple_of(MMArch::PAGE_SIZE);
+
+    let mut ehdr = Elf64Ehdr::default();
+    // ELFCLASS64, ELFDATA2LSB, EV_CURRENT
+    ehdr.e_ident[..7].copy_from_slice(&[0x7f, b'E', b'L', b'F', 2, 1, 1]);
+    ehdr.e_type = ET_CORE;
+    ehdr.e_machine = CurrentElfArch::ELF_MACHINE;
+    ehdr.e_version = 1;
+    ehdr.e_phoff = size_of::<Elf64Ehdr>() as u64;
+    ehdr.e_ehsize = size_of::<Elf64Ehdr>() as u16;
+    ehdr.e_phentsize = size_of::<Elf64Phdr>() as u16;
+    ehdr.e_phnum = phnum.min(u16::MAX as usize) as u16;
+    writer.emit(as_bytes(&ehdr))?;
+
+    let note_phdr = Elf64Phdr {
+        p_type: PT_NOTE,
+        p_offset: notes_offset as u64,
+        p_filesz: notes.len() as u64,
+        ..Default::default()
+    };
+    writer.emit(as_bytes(&note_phdr))?;
+
+    let mut offset = data_offset;
+    for vma in vmas.iter() {
+        let mut p_flags = 0;
+        if vma.flags.contains(VmFlags::VM_READ) {
+            p_flags |= PF_R;
+        }
+        if vma.flags.contains(VmFlags::VM_WRITE) {
+            p_flags |= PF_W;
+        }
+        if vma.flags.contains(VmFlags::VM_EXEC) {
+            p_flags |= PF_X;
+        }
+        let phdr = Elf64Phdr {
+            p_type: PT_LOAD,
+            p_flags,
+            p_offset: offset as u64,
+            p_vaddr: vma.start.data() as u64,
+            p_paddr: 0,
+            p_filesz: vma.dump_size as u64,
+            p_memsz: (vma.end - vma.start) as u64,
+            p_align: MMArch::PAGE_SIZE as u64,
+        };
+        writer.emit(as_bytes(&phdr))?;
+        offset += vma.dump_size;
+    }
+
+    writer.emit(&notes)?;
+    writer.align(MMArch::PAGE_SIZE)?;
+
+    let mut page = alloc::vec![0u8; MMArch::PAGE_SIZE];
+    for vma in vmas.iter() {
+        let mut vaddr = vma.start;
+        while vaddr < vma.start + vma.dump_size {
+            read_user_page(vm, vaddr, &mut page);
+            writer.emit(&page)?;
+            vaddr += MMArch::PAGE_SIZE;
+        }
+    }
+    return Ok(());
+}
+
+/// 生成core dump期间线程组的状态，保存在线程组leader的pcb中
+///
+/// 参考 https://code.dragonos.org.cn/xref/linux-6.1.9/include/linux/mm_types.h#22
+#[derive(Debug)]
+pub struct CoreState {
+    /// 生成core dump的线程
+    dumper: Pid,
+    inner: SpinLock<CoreStateInner>,
+    /// 其他线程都停下来时唤醒dumper
+    startup: WaitQueue,
+    /// core dump完成时唤醒停下来的线程
+    finish: WaitQueue,
+}
+
+#[derive(Debug)]
+struct CoreStateInner {
+    /// 被终止、但还没有停下来的线程
+    waiting: Vec<Pid>,
+    /// core dump是否已经完成
+    done: bool,
+}
+
+impl CoreState {
+    fn new(dumper: Pid) -> Self {
+        Self {
+            dumper,
+            inner: SpinLock::new(CoreStateInner {
+                waiting: Vec::new(),
+                done: false,
+            }),
+            startup: WaitQueue::default(),
+            finish: WaitQueue::default(),
+        }
+    }
+}
+
+/// 获取保存core dump状态的线程组leader，没有leader时返回自身
+fn core_state_leader(pcb: &Arc<ProcessControlBlock>) -> Arc<ProcessControlBlock> {
+    pcb.thread
+        .read_irqsave()
+        .group_leader()
+        .unwrap_or_else(|| pcb.clone())
+}
+
+/// 终止线程组中的其他线程，并等待它们在退出流程中停下来，使得生成core dump期间地址空间和寄存器不再变化
+///
+/// ## 返回值
+///
+/// 线程组中的其他线程已经在生成core dump时返回None，此时当前线程不应再生成core dump
+///
+/// 参考 https://code.dragonos.org.cn/xref/linux-6.1.9/fs/coredump.c#398
+fn coredump_wait(pcb: &Arc<ProcessControlBlock>) -> Option<Arc<CoreState>> {
+    let leader = core_state_leader(pcb);
+    let state = Arc::new(CoreState::new(pcb.pid()));
+    {
+        let mut guard = leader.core_state.lock_irqsave();
+        if guard.is_some() {
+            return None;
+        }
+        // 已经进入退出流程的线程不会再执行用户态代码，不需要等待
+        let mut inner = state.inner.lock_irqsave();
+        pcb.for_each_thread(|thread| {
+            if !Arc::ptr_eq(thread, pcb) && !thread.flags().contains(ProcessFlags::EXITING) {
+                inner.waiting.push(thread.pid());
+            }
+        });
+        drop(inner);
+        *guard = Some(state.clone());
+    }
+
+    let targets = state.inner.lock_irqsave().waiting.clone();
+    for pid in targets {
+        if Signal::SIGKILL.send_signal_info(None, pid).is_err() {
+            state.inner.lock_irqsave().waiting.retain(|p| *p != pid);
+        }
+    }
+
+    loop {
+        let inner = state.inner.lock_irqsave();
+        if inner.waiting.is_empty() {
+            break;
+        }
+        state.startup.sleep_uninterruptible_unlock_spinlock(inner);
+    }
+    return Some(state);
+}
+
+/// core dump完成，唤醒停下来的线程继续退出
+fn coredump_finish(pcb: &Arc<ProcessControlBlock>, state: &Arc<CoreState>) {
+    core_state_leader(pcb).core_state.lock_irqsave().take();
+    state.inner.lock_irqsave().done = true;
+    state.finish.wakeup_all(None);
+}
+
+/// 线程退出时调用：如果线程组正在生成core dump，在释放地址空间之前停下来，等待dump完成
+///
+/// 参考 https://code.dragonos.org.cn/xref/linux-6.1.9/kernel/exit.c#427
+pub fn coredump_task_exit(pcb: &Arc<ProcessControlBlock>) {
+    let leader = core_state_leader(pcb);
+    let state = {
+        let guard = leader.core_state.lock_irqsave();
+        // 与coredump_wait在同一把锁下设置，之后开始的core dump不会再等待当前线程
+        pcb.flags().insert(ProcessFlags::EXITING);
+        guard.clone()
+    };
+    let Some(state) = state else {
+        return;
+    };
+    if state.dumper == pcb.pid() {
+        return;
+    }
+
+    let mut inner = state.inner.lock_irqsave();
+    let len = inner.waiting.len();
+    inner.waiting.retain(|p| *p != pcb.pid());
+    if inner.waiting.len() == len {
+        // core dump开始之后才创建的线程
+        return;
+    }
+    if inner.waiting.is_empty() {
+        state.startup.wakeup(None);
+    }
+    while !inner.done {
+        state.finish.sleep_uninterruptible_unlock_spinlock(inner);
+        inner = state.inner.lock_irqsave();
+    }
+}
+
+/// 为当前进程生成core dump
+///
+/// ## 参数
+///
+/// - `sig`: 导致进程终止的信号
+/// - `frame`: 当前线程进入内核时保存的用户态寄存器
+///
+/// ## 返回值
+///
+/// 生成了core文件时返回true，此时进程的退出状态中应当设置[`WCOREFLAG`]
+pub fn do_coredump(sig: Signal, frame: &TrapFrame) -> bool {
+    let pcb = ProcessManager::current_pcb();
+    if pcb.dumpable() == SUID_DUMP_DISABLE {
+        return false;
+    }
+    let limit = pcb.get_rlimit(RLimitID::Core).rlim_cur;
+    // core文件至少要能容纳一个页
+    if limit < MMArch::PAGE_SIZE as u64 {
+        return false;
+    }
+    let Some(vm) = pcb.basic().user_vm() else {
+        return false;
+    };
+
+    let Some(state) = coredump_wait(&pcb) else {
+        return false;
+    };
+    let dumped = write_core(&pcb, sig, frame, &vm, limit);
+    coredump_finish(&pcb, &state);
+    return dumped;
+}
+
+/// 按照core_pattern创建core文件并写入
+fn write_core(
+    pcb: &Arc<ProcessControlBlock>,
+    sig: Signal,
+    frame: &TrapFrame,
+    vm: &Arc<AddressSpace>,
+    limit: u64,
+) -> bool {
+    let pattern = core_pattern();
+    if pattern.starts_with('|') {
+        warn!("coredump: piping core dumps to a program is not supported");
+        return false;
+    }
+    let corename = format_corename(pcb, &pattern, sig, limit);
+    if corename.is_empty() {
+        return false;
+    }
+
+    let file = match open_core_file(pcb, &corename) {
+        Ok(file) => file,
+        Err(e) => {
+            warn!(
+                "coredump: pid {:?}: failed to create core file {}: {:?}",
+                pcb.pid(),
+                corename,
+                e
+            );
+            return false;
+        }
+    };
+    let mut writer = CoreWriter {
+        file,
+        written: 0,
+        limit: limit.min(usize::MAX as u64) as usize,
+    };
+    if let Err(e) = elf_core_dump(pcb, frame, sig, vm, &mut writer) {
+        warn!(
+            "coredump: pid {:?}: failed to write core file {}: {:?}",
+            pcb.pid(),
+            corename,
+            e
+        );
+        return false;
+    }
+    return true;
+}
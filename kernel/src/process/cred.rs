@@ -4,18 +4,78 @@ use alloc::vec::Vec;
 
 const GLOBAL_ROOT_UID: Kuid = Kuid(0);
 const GLOBAL_ROOT_GID: Kgid = Kgid(0);
+/// 进程最多可以拥有的附加组数量
+pub const NGROUPS_MAX: usize = 65536;
 pub static INIT_CRED: Cred = Cred::init();
 
 int_like!(Kuid, AtomicKuid, usize, AtomicUsize);
 int_like!(Kgid, AtomicKgid, usize, AtomicUsize);
 
 bitflags! {
+    /// capability集合
+    ///
+    /// 参考 https://code.dragonos.org.cn/xref/linux-6.1.9/include/uapi/linux/capability.h
     pub struct CAPFlags:u64{
         const CAP_EMPTY_SET = 0;
         const CAP_FULL_SET = (1 << 41) - 1;
+        const CAP_CHOWN = 1 << 0;
+        const CAP_DAC_OVERRIDE = 1 << 1;
+        const CAP_DAC_READ_SEARCH = 1 << 2;
+        const CAP_FOWNER = 1 << 3;
+        const CAP_FSETID = 1 << 4;
+        const CAP_KILL = 1 << 5;
+        const CAP_SETGID = 1 << 6;
+        const CAP_SETUID = 1 << 7;
+        const CAP_SETPCAP = 1 << 8;
+        const CAP_LINUX_IMMUTABLE = 1 << 9;
+        const CAP_NET_BIND_SERVICE = 1 << 10;
+        const CAP_NET_BROADCAST = 1 << 11;
+        const CAP_NET_ADMIN = 1 << 12;
+        const CAP_NET_RAW = 1 << 13;
+        const CAP_IPC_LOCK = 1 << 14;
+        const CAP_IPC_OWNER = 1 << 15;
+        const CAP_SYS_MODULE = 1 << 16;
+        const CAP_SYS_RAWIO = 1 << 17;
+        const CAP_SYS_CHROOT = 1 << 18;
+        const CAP_SYS_PTRACE = 1 << 19;
+        const CAP_SYS_PACCT = 1 << 20;
+        const CAP_SYS_ADMIN = 1 << 21;
+        const CAP_SYS_BOOT = 1 << 22;
+        const CAP_SYS_NICE = 1 << 23;
+        const CAP_SYS_RESOURCE = 1 << 24;
+        const CAP_SYS_TIME = 1 << 25;
+        const CAP_SYS_TTY_CONFIG = 1 << 26;
+        const CAP_MKNOD = 1 << 27;
+        const CAP_LEASE = 1 << 28;
+        const CAP_AUDIT_WRITE = 1 << 29;
+        const CAP_AUDIT_CONTROL = 1 << 30;
+        const CAP_SETFCAP = 1 << 31;
+        const CAP_MAC_OVERRIDE = 1 << 32;
+        const CAP_MAC_ADMIN = 1 << 33;
+        const CAP_SYSLOG = 1 << 34;
+        const CAP_WAKE_ALARM = 1 << 35;
+        const CAP_BLOCK_SUSPEND = 1 << 36;
+        const CAP_AUDIT_READ = 1 << 37;
+        const CAP_PERFMON = 1 << 38;
+        const CAP_BPF = 1 << 39;
+        const CAP_CHECKPOINT_RESTORE = 1 << 40;
+        /// fsuid变化时需要随之调整的capability
+        const CAP_FS_SET = Self::CAP_CHOWN.bits
+            | Self::CAP_MKNOD.bits
+            | Self::CAP_DAC_OVERRIDE.bits
+            | Self::CAP_DAC_READ_SEARCH.bits
+            | Self::CAP_FOWNER.bits
+            | Self::CAP_FSETID.bits
+            | Self::CAP_LINUX_IMMUTABLE.bits
+            | Self::CAP_MAC_OVERRIDE.bits;
     }
 }
 
+impl CAPFlags {
+    /// 最大的capability编号
+    pub const CAP_LAST_CAP: usize = 40;
+}
+
 pub enum CredFsCmp {
     Equal,
     Less,
@@ -70,7 +130,7 @@ impl Cred {
             cap_permitted: CAPFlags::CAP_FULL_SET,
             cap_effective: CAPFlags::CAP_FULL_SET,
             cap_bset: CAPFlags::CAP_FULL_SET,
-            cap_ambient: CAPFlags::CAP_FULL_SET,
+            cap_ambient: CAPFlags::CAP_EMPTY_SET,
             group_info: None,
         }
     }
@@ -162,6 +222,66 @@ impl Cred {
     pub fn setfsgid(&mut self, fsgid: usize) {
         self.fsgid.0 = fsgid;
     }
+
+    /// 判断凭证的有效capability集合中是否包含`cap`
+    pub fn has_capability(&self, cap: CAPFlags) -> bool {
+        self.cap_effective.contains(cap)
+    }
+
+    /// uid改变之后，根据新旧uid调整capability集合
+    ///
+    /// ## 参数
+    ///
+    /// - `old` 修改uid之前的凭证
+    ///
+    /// 参考 https://code.dragonos.org.cn/xref/linux-6.1.9/security/commoncap.c#1002
+    pub fn fix_setuid_caps(&mut self, old: &Cred) {
+        let root = GLOBAL_ROOT_UID;
+        // 所有uid都不再是root时，清除所有的capability
+        if (old.uid == root || old.euid == root || old.suid == root)
+            && (self.uid != root && self.euid != root && self.suid != root)
+        {
+            self.cap_permitted = CAPFlags::CAP_EMPTY_SET;
+            self.cap_effective = CAPFlags::CAP_EMPTY_SET;
+            self.cap_ambient = CAPFlags::CAP_EMPTY_SET;
+        }
+        if old.euid == root && self.euid != root {
+            self.cap_effective = CAPFlags::CAP_EMPTY_SET;
+        }
+        if old.euid != root && self.euid == root {
+            self.cap_effective = self.cap_permitted;
+        }
+
+        // fsuid只影响与文件系统相关的capability
+        if old.fsuid == root && self.fsuid != root {
+            self.cap_effective.remove(CAPFlags::CAP_FS_SET);
+        }
+        if old.fsuid != root && self.fsuid == root {
+            self.cap_effective |= self.cap_permitted & CAPFlags::CAP_FS_SET;
+        }
+    }
+
+    /// 设置进程的附加组
+    ///
+    /// 参考 https://code.dragonos.org.cn/xref/linux-6.1.9/kernel/groups.c#116
+    pub fn set_groups(&mut self, mut gids: Vec<Kgid>) {
+        gids.sort_unstable();
+        self.group_info = if gids.is_empty() {
+            None
+        } else {
+            Some(GroupInfo { gids })
+        };
+    }
+
+    /// 判断进程是否属于指定的组（fsgid或附加组）
+    pub fn in_group_p(&self, gid: Kgid) -> bool {
+        if self.fsgid == gid {
+            return true;
+        }
+        self.group_info
+            .as_ref()
+            .is_some_and(|info| info.gids.contains(&gid))
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Default)]
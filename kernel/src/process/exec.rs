@@ -1,12 +1,19 @@
 use core::{fmt::Debug, ptr::null};
 
 use alloc::{collections::BTreeMap, ffi::CString, string::String, sync::Arc, vec::Vec};
+use log::error;
 use system_error::SystemError;
 
 use crate::{
     driver::base::block::SeekFrom,
-    filesystem::vfs::file::{File, FileMode},
-    libs::elf::ELF_LOADER,
+    filesystem::{
+        binfmt_misc::BINFMT_MISC_LOADER,
+        vfs::{
+            file::{File, FileMode},
+            IndexNode,
+        },
+    },
+    libs::{binfmt_script::SCRIPT_LOADER, elf::ELF_LOADER},
     mm::{
         ucontext::{AddressSpace, UserStack},
         VirtAddr,
@@ -16,7 +23,13 @@ use crate::{
 use super::ProcessManager;
 
 /// 系统支持的所有二进制文件加载器的列表
-const BINARY_LOADERS: [&'static dyn BinaryLoader; 1] = [&ELF_LOADER];
+///
+/// binfmt_misc排在最前面，使得用户注册的解释器可以接管本来由其他加载器处理的格式
+const BINARY_LOADERS: [&'static dyn BinaryLoader; 3] =
+    [&BINFMT_MISC_LOADER, &ELF_LOADER, &SCRIPT_LOADER];
+
+/// 脚本、binfmt_misc等加载器把文件替换为解释器的最大嵌套层数
+const BINPRM_MAX_RECURSION: usize = 4;
 
 pub trait BinaryLoader: 'static + Debug {
     /// 检查二进制文件是否为当前加载器支持的格式
@@ -65,6 +78,8 @@ pub enum ExecError {
     InvalidParemeter,
     /// 无效的地址
     BadAddress(Option<VirtAddr>),
+    /// 加载解释器时产生的错误
+    Errno(SystemError),
     Other(String),
 }
 impl From<ExecError> for SystemError {
@@ -78,6 +93,7 @@ impl From<ExecError> for SystemError {
             ExecError::OutOfMemory => SystemError::ENOMEM,
             ExecError::InvalidParemeter => SystemError::EINVAL,
             ExecError::BadAddress(_addr) => SystemError::EFAULT,
+            ExecError::Errno(errno) => errno,
             ExecError::Other(_msg) => SystemError::ENOEXEC,
         }
     }
@@ -93,6 +109,12 @@ bitflags! {
 #[derive(Debug)]
 pub struct ExecParam {
     file: File,
+    /// 传给execve的文件路径
+    filename: String,
+    /// 当前要加载的文件的路径。文件被替换为解释器之后，这里是解释器的路径
+    interp: String,
+    /// 文件被替换为解释器的次数
+    interp_depth: usize,
     vm: Arc<AddressSpace>,
     /// 一些标志位
     flags: ExecParamFlags,
@@ -115,24 +137,66 @@ impl ExecParam {
         vm: Arc<AddressSpace>,
         flags: ExecParamFlags,
     ) -> Result<Self, SystemError> {
-        let pwd = ProcessManager::current_pcb().pwd_inode();
-        let inode = pwd.lookup(file_path)?;
+        let inode = Self::lookup_exec(file_path)?;
 
         // 读取文件头部，用于判断文件类型
         let file = File::new(inode, FileMode::O_RDONLY)?;
 
         Ok(Self {
             file,
+            filename: String::from(file_path),
+            interp: String::from(file_path),
+            interp_depth: 0,
             vm,
             flags,
             init_info: ProcInitInfo::new(ProcessManager::current_pcb().basic().name()),
         })
     }
 
+    /// 查找要执行的文件，相对路径从当前工作目录开始查找
+    pub fn lookup_exec(file_path: &str) -> Result<Arc<dyn IndexNode>, SystemError> {
+        let pwd = ProcessManager::current_pcb().pwd_inode();
+        return pwd.lookup(file_path);
+    }
+
     pub fn vm(&self) -> &Arc<AddressSpace> {
         &self.vm
     }
 
+    /// 传给execve的文件路径
+    pub fn filename(&self) -> &str {
+        &self.filename
+    }
+
+    /// 当前要加载的文件的路径
+    pub fn interp(&self) -> &str {
+        &self.interp
+    }
+
+    /// 把要加载的文件替换为解释器，之后由其他加载器加载解释器
+    ///
+    /// ## 参数
+    ///
+    /// - `path`: 解释器的路径
+    /// - `inode`: 解释器的inode
+    ///
+    /// ## 返回值
+    ///
+    /// - `Err(SystemError::ELOOP)` 解释器的嵌套层数超过了[`BINPRM_MAX_RECURSION`]
+    pub fn set_interpreter(
+        &mut self,
+        path: &str,
+        inode: Arc<dyn IndexNode>,
+    ) -> Result<(), SystemError> {
+        if self.interp_depth >= BINPRM_MAX_RECURSION {
+            return Err(SystemError::ELOOP);
+        }
+        self.file = File::new(inode, FileMode::O_RDONLY)?;
+        self.interp = String::from(path);
+        self.interp_depth += 1;
+        return Ok(());
+    }
+
     pub fn flags(&self) -> &ExecParamFlags {
         &self.flags
     }
@@ -189,9 +253,10 @@ pub fn load_binary_file(param: &mut ExecParam) -> Result<BinaryLoaderResult, Sys
     assert!(param.vm().is_current());
     // debug!("load_binary_file: to load with param: {:?}", param);
 
-    let result: BinaryLoaderResult = loader
-        .load(param, &head_buf)
-        .unwrap_or_else(|e| panic!("load_binary_file failed: error: {e:?}, param: {param:?}"));
+    let result: BinaryLoaderResult = loader.load(param, &head_buf).map_err(|e| {
+        error!("load_binary_file failed: error: {e:?}, param: {param:?}");
+        SystemError::from(e)
+    })?;
 
     // debug!("load_binary_file: load success: {result:?}");
     return Ok(result);
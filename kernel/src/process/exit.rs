@@ -1,17 +1,17 @@
-use alloc::sync::Arc;
-use core::intrinsics::likely;
+use alloc::{sync::Arc, vec, vec::Vec};
+use core::{intrinsics::likely, sync::atomic::Ordering};
 use system_error::SystemError;
 
 use crate::{
     arch::ipc::signal::{SigChildCode, Signal},
     ipc::syscall::sys_kill::PidConverter,
-    sched::{schedule, SchedMode},
+    sched::SchedMode,
     syscall::user_access::UserBufferWriter,
-    time::{sleep::nanosleep, Duration},
 };
 
 use super::{
-    abi::WaitOption, resource::RUsage, Pid, ProcessControlBlock, ProcessManager, ProcessState,
+    abi::WaitOption, coredump::WCOREFLAG, resource::RUsage, Pid, ProcessControlBlock,
+    ProcessManager, ProcessState,
 };
 
 /// 内核wait4时的参数
@@ -22,15 +22,66 @@ pub struct KernelWaitOption<'a> {
     pub ret_status: i32,
     pub ret_info: Option<WaitIdInfo>,
     pub ret_rusage: Option<&'a mut RUsage>,
-    pub no_task_error: Option<SystemError>,
 }
 
 #[derive(Debug, Clone)]
-#[allow(dead_code)]
 pub struct WaitIdInfo {
     pub pid: Pid,
     pub status: i32,
     pub cause: i32,
+    pub uid: u32,
+}
+
+/// waitid的idtype：等待任意子进程
+pub const P_ALL: u32 = 0;
+/// waitid的idtype：等待指定pid的子进程
+pub const P_PID: u32 = 1;
+/// waitid的idtype：等待指定进程组中的子进程
+pub const P_PGID: u32 = 2;
+/// waitid的idtype：等待pidfd指向的子进程
+pub const P_PIDFD: u32 = 3;
+
+/// waitid返回给用户态的siginfo，与Linux的`siginfo_t`中SIGCHLD对应的布局一致
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct WaitIdSigInfo {
+    pub si_signo: i32,
+    pub si_errno: i32,
+    pub si_code: i32,
+    _pad0: i32,
+    pub si_pid: i32,
+    pub si_uid: u32,
+    pub si_status: i32,
+    _pad1: i32,
+    pub si_utime: i64,
+    pub si_stime: i64,
+    _pad2: [u64; 10],
+}
+
+impl From<&WaitIdInfo> for WaitIdSigInfo {
+    fn from(info: &WaitIdInfo) -> Self {
+        let (code, status) = if info.cause == i32::from(SigChildCode::Exited) {
+            // 把wait4格式的退出状态转换为CLD_EXITED/CLD_KILLED/CLD_DUMPED
+            let sig = info.status & 0x7f;
+            if sig == 0 {
+                (i32::from(SigChildCode::Exited), (info.status >> 8) & 0xff)
+            } else if info.status & WCOREFLAG as i32 != 0 {
+                (i32::from(SigChildCode::Dumped), sig)
+            } else {
+                (i32::from(SigChildCode::Killed), sig)
+            }
+        } else {
+            (info.cause, info.status)
+        };
+        Self {
+            si_signo: Signal::SIGCHLD as i32,
+            si_code: code,
+            si_pid: info.pid.data() as i32,
+            si_uid: info.uid,
+            si_status: status,
+            ..Default::default()
+        }
+    }
 }
 
 impl KernelWaitOption<'_> {
@@ -41,7 +92,6 @@ impl KernelWaitOption<'_> {
             ret_status: 0,
             ret_info: None,
             ret_rusage: None,
-            no_task_error: None,
         }
     }
 }
@@ -76,127 +126,207 @@ pub fn kernel_wait4(
     return Ok(r);
 }
 
-/// 参考 https://code.dragonos.org.cn/xref/linux-6.1.9/kernel/exit.c#1573
-fn do_wait(kwo: &mut KernelWaitOption) -> Result<usize, SystemError> {
-    let mut retval: Result<usize, SystemError>;
-    let mut tmp_child_pcb: Option<Arc<ProcessControlBlock>> = None;
-    macro_rules! notask {
-        ($outer: lifetime) => {
-            if let Some(err) = &kwo.no_task_error {
-                retval = Err(err.clone());
-            } else {
-                retval = Ok(0);
-            }
+/// waitid的内核实现
+///
+/// ## 参数
+///
+/// - `converter` 要等待的子进程
+/// - `infop` 用于返回子进程状态的siginfo，为None时不返回
+/// - `options` 等待选项，至少包含`WEXITED`、`WSTOPPED`、`WCONTINUED`之一
+/// - `rusage_buf` 用于返回子进程的资源使用情况
+///
+/// ## 返回值
+///
+/// 成功时返回0。设置了`WNOHANG`并且没有子进程的状态发生变化时，siginfo会被清零
+pub fn kernel_waitid(
+    converter: PidConverter,
+    infop: Option<UserBufferWriter<'_>>,
+    options: WaitOption,
+    rusage_buf: Option<&mut RUsage>,
+) -> Result<usize, SystemError> {
+    let mut kwo = KernelWaitOption::new(converter, options);
+    kwo.ret_info = Some(WaitIdInfo {
+        pid: Pid(0),
+        status: 0,
+        cause: 0,
+        uid: 0,
+    });
+    kwo.ret_rusage = rusage_buf;
 
-            if retval.is_err() && !kwo.options.contains(WaitOption::WNOHANG) {
-                retval = Err(SystemError::ERESTARTSYS);
-                if !ProcessManager::current_pcb().has_pending_signal_fast() {
-                    schedule(SchedMode::SM_PREEMPT);
-                    // todo: 增加子进程退出的回调后，这里可以直接等待在自身的child_wait等待队列上。
-                    continue;
-                } else {
-                    break $outer;
-                }
-            } else {
-                break $outer;
-            }
+    let r = do_wait(&mut kwo)?;
+
+    if let Some(mut infop) = infop {
+        let siginfo = match &kwo.ret_info {
+            Some(info) if r != 0 => WaitIdSigInfo::from(info),
+            _ => WaitIdSigInfo::default(),
         };
+        infop.copy_one_to_user(&siginfo, 0)?;
     }
-    // todo: 在signal struct里面增加等待队列，并在这里初始化子进程退出的回调，使得子进程退出时，能唤醒当前进程。
-
-    'outer: loop {
-        kwo.no_task_error = Some(SystemError::ECHILD);
-        match kwo.pid_converter {
-            PidConverter::Pid(pid) => {
-                let child_pcb = ProcessManager::find(pid)
-                    .ok_or(SystemError::ECHILD)
-                    .unwrap();
-                // 获取weak引用，以便于在do_waitpid中能正常drop pcb
-                let child_weak = Arc::downgrade(&child_pcb);
-                let r: Option<Result<usize, SystemError>> = do_waitpid(child_pcb, kwo);
-                if let Some(r) = r {
-                    retval = r;
-                    break 'outer;
-                } else if let Err(SystemError::ESRCH) =
-                    child_weak.upgrade().unwrap().wait_queue.sleep()
-                {
-                    // log::debug!("do_wait: child_pcb sleep failed");
-                    continue;
-                }
+    return Ok(0);
+}
+
+/// 为退出进程的子进程寻找新的父进程
+///
+/// 如果祖先进程中存在child subreaper，则由最近的、尚未退出的child subreaper收养，
+/// 否则由init进程收养
+///
+/// 参考 https://code.dragonos.org.cn/xref/linux-6.1.9/kernel/exit.c#find_new_reaper
+pub(super) fn find_new_reaper(father: &ProcessControlBlock) -> Option<Arc<ProcessControlBlock>> {
+    if father.has_child_subreaper() {
+        let mut ancestor = father.real_parent_pcb.read_irqsave().upgrade();
+        while let Some(pcb) = ancestor {
+            if pcb.pid() == Pid(1) {
+                break;
             }
-            PidConverter::All => {
-                // 等待任意子进程
-                // todo: 这里有问题！应当让当前进程sleep到自身的child_wait等待队列上，这样才高效。（还没实现）
-                let current_pcb = ProcessManager::current_pcb();
-                loop {
-                    let rd_childen = current_pcb.children.read();
-                    if rd_childen.is_empty() {
-                        break;
-                    }
-                    for pid in rd_childen.iter() {
-                        let pcb = ProcessManager::find(*pid).ok_or(SystemError::ECHILD)?;
-                        let sched_guard = pcb.sched_info().inner_lock_read_irqsave();
-                        let state = sched_guard.state();
-                        if state.is_exited() {
-                            kwo.ret_status = state.exit_code().unwrap() as i32;
-                            kwo.no_task_error = None;
-                            // 由于pcb的drop方法里面要获取父进程的children字段的写锁，所以这里不能直接drop pcb，
-                            // 而是要先break到外层循环，以便释放父进程的children字段的锁,才能drop pcb。
-                            // 否则会死锁。
-                            tmp_child_pcb = Some(pcb.clone());
-                            unsafe { ProcessManager::release(*pid) };
-                            retval = Ok((*pid).into());
-                            break 'outer;
-                        }
-                    }
-                    drop(rd_childen);
-                    nanosleep(Duration::from_millis(100).into())?;
-                }
+            if pcb.is_child_subreaper() && !pcb.is_exited() {
+                return Some(pcb);
             }
-            PidConverter::Pgid(pgid) => {
-                let pg = ProcessManager::find_process_group(pgid).ok_or(SystemError::ESRCH)?;
-                loop {
-                    let inner = pg.process_group_inner.lock();
-                    for (_, pcb) in inner.processes.iter() {
-                        let sched_guard = pcb.sched_info().inner_lock_read_irqsave();
-                        let state = sched_guard.state();
-                        if state.is_exited() {
-                            kwo.ret_status = state.exit_code().unwrap() as i32;
-                            kwo.no_task_error = None;
-                            // 由于pcb的drop方法里面要获取父进程的children字段的写锁，所以这里不能直接drop pcb，
-                            // 而是要先break到外层循环，以便释放父进程的children字段的锁,才能drop pcb。
-                            // 否则会死锁。
-                            tmp_child_pcb = Some(pcb.clone());
-                            let pid = pcb.pid();
-                            unsafe { ProcessManager::release(pid) };
-                            retval = Ok((pid).into());
-                            break 'outer;
-                        }
-                    }
-                    drop(inner);
-                    nanosleep(Duration::from_millis(100).into())?;
-                }
+            ancestor = pcb.real_parent_pcb.read_irqsave().upgrade();
+        }
+    }
+    return ProcessManager::find(Pid(1));
+}
+
+impl ProcessControlBlock {
+    /// 获取在wait中等待子进程状态变化的线程组leader，没有leader时返回自身
+    fn child_wait_leader(&self) -> Arc<ProcessControlBlock> {
+        self.thread
+            .read_irqsave()
+            .group_leader()
+            .unwrap_or_else(|| self.self_ref.upgrade().unwrap())
+    }
+
+    /// 子进程的状态发生变化（退出、停止或继续运行）时，唤醒当前线程组中在wait中等待的线程
+    ///
+    /// 参考 https://code.dragonos.org.cn/xref/linux-6.1.9/kernel/exit.c#1488
+    pub(super) fn wake_up_child_waiters(&self) {
+        let leader = self.child_wait_leader();
+        leader.wait_chldexit_seq.fetch_add(1, Ordering::SeqCst);
+        leader
+            .wait_chldexit
+            .wakeup_all(Some(ProcessState::Blocked(true)));
+    }
+
+    /// 当前进程的状态发生变化时，唤醒在wait中等待它的父进程
+    pub(super) fn wake_up_parent(&self) {
+        if let Some(parent) = self.real_parent_pcb.read_irqsave().upgrade() {
+            parent.wake_up_child_waiters();
+        }
+    }
+}
+
+/// 检查一遍符合条件的子进程
+///
+/// ## 返回值
+///
+/// - `Some(r)` 找到了状态发生变化的子进程，或者没有可以等待的子进程
+/// - `None` 存在可以等待的子进程，但是它们的状态都没有变化
+fn wait_consider_children(
+    current: &Arc<ProcessControlBlock>,
+    kwo: &mut KernelWaitOption,
+) -> Option<Result<usize, SystemError>> {
+    let children = match kwo.pid_converter {
+        PidConverter::Pid(pid) => {
+            let child_pcb = match ProcessManager::find(pid) {
+                Some(child_pcb) => child_pcb,
+                None => return Some(Err(SystemError::ECHILD)),
+            };
+            // 只能等待自身或同一线程组中其它线程的子进程
+            let is_child = child_pcb
+                .real_parent_pcb
+                .read_irqsave()
+                .upgrade()
+                .is_some_and(|parent| parent.tgid() == current.tgid());
+            if !is_child {
+                return Some(Err(SystemError::ECHILD));
             }
+            vec![child_pcb]
         }
-        notask!('outer);
+        PidConverter::All | PidConverter::Pgid(_) => {
+            // 先收集符合条件的子进程，再释放children字段的读锁。
+            // 由于pcb的drop方法里面要获取父进程的children字段的写锁，
+            // 所以不能在持有读锁的时候回收子进程，否则会死锁。
+            current
+                .children
+                .read()
+                .iter()
+                .filter_map(|pid| ProcessManager::find(*pid))
+                .filter(|pcb| match kwo.pid_converter {
+                    PidConverter::Pgid(pgid) => pcb.pgid() == pgid,
+                    _ => true,
+                })
+                .collect::<Vec<_>>()
+        }
+    };
+    // 没有WEXITED时，已经退出的子进程不会再发生状态变化，不算作可以等待的子进程
+    let children = children
+        .into_iter()
+        .filter(|pcb| kwo.options.contains(WaitOption::WEXITED) || !pcb.is_exited())
+        .collect::<Vec<_>>();
+    if children.is_empty() {
+        return Some(Err(SystemError::ECHILD));
     }
+    for pcb in children {
+        if let Some(r) = do_waitpid(pcb, kwo) {
+            return Some(r);
+        }
+    }
+    return None;
+}
+
+/// 参考 https://code.dragonos.org.cn/xref/linux-6.1.9/kernel/exit.c#1573
+fn do_wait(kwo: &mut KernelWaitOption) -> Result<usize, SystemError> {
+    let current = ProcessManager::current_pcb();
+    // 子进程状态变化时，唤醒的是父进程线程组leader上的等待队列
+    let leader = current.child_wait_leader();
+
+    let retval = loop {
+        // 先记录序号再检查子进程，这样在检查之后、睡眠之前发生的状态变化不会被错过
+        let seq = leader.wait_chldexit_seq.load(Ordering::SeqCst);
+        if let Some(r) = wait_consider_children(&current, kwo) {
+            break r;
+        }
+        if kwo.options.contains(WaitOption::WNOHANG) {
+            break Ok(0);
+        }
+        let r = wq_wait_event_interruptible!(
+            leader.wait_chldexit,
+            leader.wait_chldexit_seq.load(Ordering::SeqCst) != seq,
+            {}
+        );
+        if r.is_err() {
+            break Err(SystemError::ERESTARTSYS);
+        }
+    };
 
-    drop(tmp_child_pcb);
     ProcessManager::current_pcb()
         .sched_info
         .inner_lock_write_irqsave()
         .set_state(ProcessState::Runnable);
 
-    // log::debug!(
-    //     "do_wait, kwo.pid: {}, retval = {:?}, kwo: {:?}",
-    //     kwo.pid,
-    //     retval,
-    //     kwo.no_task_error
-    // );
-
     return retval;
 }
 
+/// 回收已经退出的子进程时，填写waitid需要的信息
+fn fill_exited_info(child_pcb: &ProcessControlBlock, kwo: &mut KernelWaitOption) {
+    if let Some(info) = &mut kwo.ret_info {
+        *info = WaitIdInfo {
+            pid: child_pcb.pid(),
+            status: kwo.ret_status,
+            cause: SigChildCode::Exited.into(),
+            uid: child_pcb.cred().uid.data() as u32,
+        };
+    }
+}
+
+/// 回收子进程前，统计子进程的资源使用情况，并写入`kwo.ret_rusage`
+fn account_reaped_child(child_pcb: &ProcessControlBlock, kwo: &mut KernelWaitOption) {
+    let stats = ProcessManager::current_pcb().account_reaped_child(child_pcb);
+    if let Some(rusage) = kwo.ret_rusage.as_deref_mut() {
+        *rusage = stats.into();
+    }
+}
+
 fn do_waitpid(
     child_pcb: Arc<ProcessControlBlock>,
     kwo: &mut KernelWaitOption,
@@ -204,47 +334,6 @@ fn do_waitpid(
     let state = child_pcb.sched_info().inner_lock_read_irqsave().state();
     // 获取退出码
     match state {
-        ProcessState::Runnable => {
-            if kwo.options.contains(WaitOption::WNOHANG)
-                || kwo.options.contains(WaitOption::WNOWAIT)
-            {
-                if let Some(info) = &mut kwo.ret_info {
-                    *info = WaitIdInfo {
-                        pid: child_pcb.pid(),
-                        status: Signal::SIGCONT as i32,
-                        cause: SigChildCode::Continued.into(),
-                    };
-                } else {
-                    kwo.ret_status = 0xffff;
-                }
-
-                return Some(Ok(0));
-            }
-        }
-        ProcessState::Blocked(_) | ProcessState::Stopped => {
-            // todo: 在stopped里面，添加code字段，表示停止的原因
-            let exitcode = 0;
-            // 由于目前不支持ptrace，因此这个值为false
-            let ptrace = false;
-
-            if (!ptrace) && (!kwo.options.contains(WaitOption::WUNTRACED)) {
-                kwo.ret_status = 0;
-                return Some(Ok(0));
-            }
-
-            if likely(!(kwo.options.contains(WaitOption::WNOWAIT))) {
-                kwo.ret_status = (exitcode << 8) | 0x7f;
-            }
-            if let Some(infop) = &mut kwo.ret_info {
-                *infop = WaitIdInfo {
-                    pid: child_pcb.pid(),
-                    status: exitcode,
-                    cause: SigChildCode::Stopped.into(),
-                };
-            }
-
-            return Some(Ok(child_pcb.pid().data()));
-        }
         ProcessState::Exited(status) => {
             let pid = child_pcb.pid();
             // debug!("wait4: child exited, pid: {:?}, status: {status}\n", pid);
@@ -255,22 +344,53 @@ fn do_waitpid(
 
             // todo: 增加对线程组的group leader的处理
 
-            if let Some(infop) = &mut kwo.ret_info {
-                *infop = WaitIdInfo {
-                    pid,
-                    status: status as i32,
-                    cause: SigChildCode::Exited.into(),
-                };
-            }
-
             kwo.ret_status = status as i32;
+            fill_exited_info(&child_pcb, kwo);
+
+            // WNOWAIT：只报告子进程的状态，子进程保持可等待状态
+            if kwo.options.contains(WaitOption::WNOWAIT) {
+                return Some(Ok(pid.into()));
+            }
 
+            account_reaped_child(&child_pcb, kwo);
             child_pcb.clear_pg_and_session_reference();
             drop(child_pcb);
             // debug!("wait4: to release {pid:?}");
             unsafe { ProcessManager::release(pid) };
             return Some(Ok(pid.into()));
         }
+        _ => {
+            let consume = !kwo.options.contains(WaitOption::WNOWAIT);
+            if kwo.options.contains(WaitOption::WSTOPPED) {
+                if let Some(sig) = child_pcb.wait_task_stopped(consume) {
+                    kwo.ret_status = ((sig as i32) << 8) | 0x7f;
+                    if let Some(infop) = &mut kwo.ret_info {
+                        *infop = WaitIdInfo {
+                            pid: child_pcb.pid(),
+                            status: sig as i32,
+                            cause: SigChildCode::Stopped.into(),
+                            uid: child_pcb.cred().uid.data() as u32,
+                        };
+                    }
+                    return Some(Ok(child_pcb.pid().data()));
+                }
+            }
+
+            if kwo.options.contains(WaitOption::WCONTINUED)
+                && child_pcb.wait_task_continued(consume)
+            {
+                kwo.ret_status = 0xffff;
+                if let Some(infop) = &mut kwo.ret_info {
+                    *infop = WaitIdInfo {
+                        pid: child_pcb.pid(),
+                        status: Signal::SIGCONT as i32,
+                        cause: SigChildCode::Continued.into(),
+                        uid: child_pcb.cred().uid.data() as u32,
+                    };
+                }
+                return Some(Ok(child_pcb.pid().data()));
+            }
+        }
     };
 
     return None;
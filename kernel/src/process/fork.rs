@@ -6,20 +6,27 @@ use log::error;
 use system_error::SystemError;
 
 use crate::{
-    arch::{interrupt::TrapFrame, ipc::signal::Signal},
+    arch::{
+        interrupt::TrapFrame,
+        ipc::signal::{Signal, MAX_SIG_NUM},
+        MMArch,
+    },
     filesystem::procfs::procfs_register_pid,
     ipc::signal::flush_signal_handlers,
     libs::rwlock::RwLock,
-    mm::VirtAddr,
+    mm::{verify_area, MemoryManagementArch, VirtAddr},
     namespaces::{create_new_namespaces, namespace::USER_NS, pid_namespace::PidStrcut},
     process::ProcessFlags,
     sched::{sched_cgroup_fork, sched_fork},
     smp::core::smp_get_processor_id,
-    syscall::user_access::UserBufferWriter,
+    syscall::user_access::{UserBufferReader, UserBufferWriter},
 };
 
 use super::{
+    cred::CAPFlags,
     kthread::{KernelThreadPcbPrivate, WorkerPrivate},
+    pidfd::{pidfd_file, PidFdFlags},
+    resource::{RLimitID, RLIM_INFINITY},
     KernelStack, Pgid, Pid, ProcessControlBlock, ProcessManager, Sid,
 };
 const MAX_PID_NS_LEVEL: usize = 32;
@@ -77,9 +84,38 @@ bitflags! {
         const CLONE_IO = 0x80000000;
         /// 克隆时，将原本被设置为SIG_IGNORE的信号，设置回SIG_DEFAULT
         const CLONE_CLEAR_SIGHAND = 0x100000000;
+        /// 克隆时，将子进程放入`cgroup`指定的cgroup中（仅clone3可用）
+        const CLONE_INTO_CGROUP = 0x200000000;
     }
 }
 
+/// clone的flags中，低8位用于指定子进程退出时发送的信号
+const CSIGNAL: u64 = 0xff;
+
+/// 第一个版本的`clone_args`的大小
+const CLONE_ARGS_SIZE_VER0: usize = 64;
+/// 增加了`cgroup`之后的`clone_args`的大小
+const CLONE_ARGS_SIZE_VER2: usize = 88;
+
+/// clone3系统调用的参数，与Linux的`struct clone_args`相同
+///
+/// 参考 https://code.dragonos.org.cn/xref/linux-6.1.9/include/uapi/linux/sched.h#92
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct PosixCloneArgs {
+    pub flags: u64,
+    pub pidfd: u64,
+    pub child_tid: u64,
+    pub parent_tid: u64,
+    pub exit_signal: u64,
+    pub stack: u64,
+    pub stack_size: u64,
+    pub tls: u64,
+    pub set_tid: u64,
+    pub set_tid_size: u64,
+    pub cgroup: u64,
+}
+
 /// ## clone与clone3系统调用的参数载体
 ///
 /// 因为这两个系统调用的参数很多，所以有这样一个载体更灵活
@@ -137,6 +173,130 @@ impl KernelCloneArgs {
             fn_arg: null_addr,
         }
     }
+
+    /// 从用户空间读取clone3的参数，并转换为KernelCloneArgs
+    ///
+    /// ## 参数
+    ///
+    /// - `uargs` 用户空间的`struct clone_args`的地址
+    /// - `size` 用户空间的`struct clone_args`的大小
+    ///
+    /// 参考 https://code.dragonos.org.cn/xref/linux-6.1.9/kernel/fork.c#2833
+    pub fn copy_from_user(uargs: *const u8, size: usize) -> Result<Self, SystemError> {
+        if size < CLONE_ARGS_SIZE_VER0 {
+            return Err(SystemError::EINVAL);
+        }
+        if size > MMArch::PAGE_SIZE {
+            return Err(SystemError::E2BIG);
+        }
+
+        let reader = UserBufferReader::new(uargs, size, true)?;
+        let buf = reader.read_from_user::<u8>(0)?;
+        let known_size = core::mem::size_of::<PosixCloneArgs>();
+        // 用户程序比内核新时，内核不认识的字段必须全部为0
+        if size > known_size && buf[known_size..].iter().any(|b| *b != 0) {
+            return Err(SystemError::E2BIG);
+        }
+        let mut args = PosixCloneArgs::default();
+        let len = size.min(known_size);
+        unsafe {
+            core::ptr::copy_nonoverlapping(
+                buf.as_ptr(),
+                &mut args as *mut PosixCloneArgs as *mut u8,
+                len,
+            )
+        };
+
+        if args.set_tid_size > MAX_PID_NS_LEVEL as u64
+            || (args.set_tid == 0) != (args.set_tid_size == 0)
+        {
+            return Err(SystemError::EINVAL);
+        }
+        // exit_signal只能是一个合法的信号
+        if args.exit_signal & !CSIGNAL != 0 || args.exit_signal as usize > MAX_SIG_NUM {
+            return Err(SystemError::EINVAL);
+        }
+        let flags = CloneFlags::from_bits(args.flags).ok_or(SystemError::EINVAL)?;
+        if flags.contains(CloneFlags::CLONE_INTO_CGROUP)
+            && (args.cgroup > i32::MAX as u64 || size < CLONE_ARGS_SIZE_VER2)
+        {
+            return Err(SystemError::EINVAL);
+        }
+
+        let mut kargs = Self::new();
+        kargs.flags = flags;
+        kargs.pidfd = VirtAddr::new(args.pidfd as usize);
+        kargs.child_tid = VirtAddr::new(args.child_tid as usize);
+        kargs.parent_tid = VirtAddr::new(args.parent_tid as usize);
+        kargs.exit_signal = Signal::from(args.exit_signal as i32);
+        kargs.stack = args.stack as usize;
+        kargs.stack_size = args.stack_size as usize;
+        kargs.tls = args.tls as usize;
+        kargs.set_tid_size = args.set_tid_size as usize;
+        kargs.cgroup = args.cgroup as i32;
+
+        if kargs.set_tid_size > 0 {
+            let reader = UserBufferReader::new(
+                args.set_tid as *const i32,
+                kargs.set_tid_size * core::mem::size_of::<i32>(),
+                true,
+            )?;
+            let set_tid = reader.read_from_user::<i32>(0)?;
+            // 指定子进程的pid需要CAP_SYS_ADMIN或CAP_CHECKPOINT_RESTORE
+            let cred = ProcessManager::current_pcb().cred();
+            if !cred.has_capability(CAPFlags::CAP_SYS_ADMIN)
+                && !cred.has_capability(CAPFlags::CAP_CHECKPOINT_RESTORE)
+            {
+                return Err(SystemError::EPERM);
+            }
+            if set_tid.iter().any(|tid| *tid < 0) {
+                return Err(SystemError::EINVAL);
+            }
+            kargs.set_tid = set_tid.iter().map(|tid| *tid as usize).collect();
+        }
+
+        kargs.check_clone3()?;
+        return Ok(kargs);
+    }
+
+    /// 检查clone3的参数是否合法
+    ///
+    /// 参考 https://code.dragonos.org.cn/xref/linux-6.1.9/kernel/fork.c#2920
+    fn check_clone3(&mut self) -> Result<(), SystemError> {
+        // 退出信号由clone_args.exit_signal指定，flags的低8位必须为0
+        if self.flags.bits() & CSIGNAL != 0 || self.flags.contains(CloneFlags::CLONE_DETACHED) {
+            return Err(SystemError::EINVAL);
+        }
+        if self
+            .flags
+            .contains(CloneFlags::CLONE_SIGHAND | CloneFlags::CLONE_CLEAR_SIGHAND)
+        {
+            return Err(SystemError::EINVAL);
+        }
+        if self
+            .flags
+            .intersects(CloneFlags::CLONE_THREAD | CloneFlags::CLONE_PARENT)
+            && self.exit_signal != Signal::INVALID
+        {
+            return Err(SystemError::EINVAL);
+        }
+
+        // clone3传入的是栈的起始地址和大小，需要转换为栈顶
+        if self.stack == 0 {
+            if self.stack_size != 0 {
+                return Err(SystemError::EINVAL);
+            }
+        } else {
+            if self.stack_size == 0 {
+                return Err(SystemError::EINVAL);
+            }
+            verify_area(VirtAddr::new(self.stack), self.stack_size)
+                .map_err(|_| SystemError::EINVAL)?;
+            self.stack += self.stack_size;
+        }
+
+        return Ok(());
+    }
 }
 
 impl ProcessManager {
@@ -329,6 +489,44 @@ impl ProcessManager {
         return Ok(());
     }
 
+    /// 检查当前用户的进程数量是否超出RLIMIT_NPROC
+    ///
+    /// 内核线程和拥有CAP_SYS_RESOURCE或CAP_SYS_ADMIN的进程不受此限制
+    fn check_nproc_limit(
+        current_pcb: &Arc<ProcessControlBlock>,
+        pcb: &Arc<ProcessControlBlock>,
+    ) -> Result<(), SystemError> {
+        let limit = current_pcb.get_rlimit(RLimitID::Nproc).rlim_cur;
+        if limit == RLIM_INFINITY || current_pcb.flags().contains(ProcessFlags::KTHREAD) {
+            return Ok(());
+        }
+        let cred = current_pcb.cred();
+        if cred.has_capability(CAPFlags::CAP_SYS_RESOURCE)
+            || cred.has_capability(CAPFlags::CAP_SYS_ADMIN)
+        {
+            return Ok(());
+        }
+
+        let mut count = 0;
+        for pid in ProcessManager::get_all_processes() {
+            if pid == pcb.pid() {
+                continue;
+            }
+            if let Some(p) = ProcessManager::find(pid) {
+                if !p.is_exited()
+                    && !p.flags().contains(ProcessFlags::KTHREAD)
+                    && p.cred().uid == cred.uid
+                {
+                    count += 1;
+                }
+            }
+        }
+        if count >= limit {
+            return Err(SystemError::EAGAIN_OR_EWOULDBLOCK);
+        }
+        return Ok(());
+    }
+
     /// 拷贝进程信息
     ///
     /// ## panic:
@@ -400,6 +598,8 @@ impl ProcessManager {
             return Err(SystemError::EINVAL);
         }
 
+        Self::check_nproc_limit(current_pcb, pcb)?;
+
         // TODO: 克隆前应该锁信号处理，等待克隆完成后再处理
 
         // 克隆架构相关
@@ -434,6 +634,19 @@ impl ProcessManager {
             writer.copy_one_to_user(&(pcb.pid().0 as i32), 0)?;
         }
 
+        // 先为子进程创建pidfd文件，等到子进程拷贝完文件描述符表之后再加入当前进程的文件描述符表，
+        // 避免子进程继承这个pidfd
+        let pidfd = if clone_flags.contains(CloneFlags::CLONE_PIDFD) {
+            let writer = UserBufferWriter::new(
+                clone_args.pidfd.data() as *mut i32,
+                core::mem::size_of::<i32>(),
+                true,
+            )?;
+            Some((pidfd_file(pcb, PidFdFlags::empty())?, writer))
+        } else {
+            None
+        };
+
         sched_fork(pcb).unwrap_or_else(|e| {
             panic!(
                 "fork: Failed to set sched info from current process, current pid: [{:?}], new pid: [{:?}]. Error: {:?}",
@@ -496,8 +709,13 @@ impl ProcessManager {
         // log::debug!("fork: clone_flags: {:?}", clone_flags);
         // 设置线程组id、组长
         if clone_flags.contains(CloneFlags::CLONE_THREAD) {
-            pcb.thread.write_irqsave().group_leader =
-                current_pcb.thread.read_irqsave().group_leader.clone();
+            let leader = current_pcb.thread.read_irqsave().group_leader.clone();
+            if let Some(leader) = leader.upgrade() {
+                let mut threads = leader.thread_group.lock_irqsave();
+                threads.retain(|thread| thread.strong_count() > 0);
+                threads.push(Arc::downgrade(pcb));
+            }
+            pcb.thread.write_irqsave().group_leader = leader;
             unsafe {
                 let ptr = pcb.as_ref() as *const ProcessControlBlock as *mut ProcessControlBlock;
                 (*ptr).tgid = current_pcb.tgid;
@@ -556,6 +774,16 @@ impl ProcessManager {
             )
         });
 
+        // 将pidfd存储在用户态传进的地址中，失败时关闭pidfd
+        if let Some((file, mut writer)) = pidfd {
+            let fd_table = current_pcb.fd_table();
+            let pidfd = fd_table.write().alloc_fd(file, None)?;
+            if let Err(e) = writer.copy_one_to_user(&pidfd, 0) {
+                let _ = fd_table.write().drop_fd(pidfd);
+                return Err(e);
+            }
+        }
+
         sched_cgroup_fork(pcb);
 
         Ok(())
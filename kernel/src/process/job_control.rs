@@ -0,0 +1,261 @@
+//! 作业控制
+//!
+//! 包括SIGSTOP/SIGTSTP/SIGTTIN/SIGTTOU使线程组停止、SIGCONT使线程组继续运行、
+//! 通过SIGCHLD和wait向父进程报告线程组状态的变化，以及孤儿进程组的处理
+//!
+//! 参考 https://code.dragonos.org.cn/xref/linux-6.1.9/kernel/signal.c#2432
+
+use alloc::{sync::Arc, vec::Vec};
+
+use crate::{
+    arch::{
+        ipc::signal::{SigChildCode, SigCode, SigFlags, Signal},
+        CurrentIrqArch,
+    },
+    exception::InterruptArch,
+    ipc::{
+        kill::kill_process_group,
+        signal_types::{SigInfo, SigType, SIG_KERNEL_STOP_MASK},
+    },
+    sched::{schedule, SchedMode},
+};
+
+use super::{process_group::ProcessGroup, ProcessControlBlock, ProcessManager};
+
+/// 线程组的作业控制状态，保存在线程组leader的pcb中
+#[derive(Debug, Default)]
+pub struct JobCtl {
+    /// 线程组是否处于停止状态
+    stopped: bool,
+    /// 使线程组停止的信号，在被wait报告之前为Some
+    stop_signal: Option<Signal>,
+    /// 线程组从停止状态中恢复，并且还没有被wait报告
+    continued: bool,
+}
+
+impl ProcessControlBlock {
+    /// 获取保存作业控制状态的线程组leader，没有leader时返回自身
+    fn job_ctl_leader(&self) -> Arc<ProcessControlBlock> {
+        self.thread
+            .read_irqsave()
+            .group_leader()
+            .unwrap_or_else(|| self.self_ref.upgrade().unwrap())
+    }
+
+    /// 判断线程组是否处于停止状态
+    pub fn is_group_stopped(&self) -> bool {
+        self.job_ctl_leader().job_ctl.lock_irqsave().stopped
+    }
+
+    /// 获取尚未被wait报告的停止信号
+    ///
+    /// ## 参数
+    ///
+    /// - `consume` 是否清除该状态，使其不会被再次报告（WNOWAIT时为false）
+    pub(super) fn wait_task_stopped(&self, consume: bool) -> Option<Signal> {
+        let leader = self.job_ctl_leader();
+        let mut job_ctl = leader.job_ctl.lock_irqsave();
+        if !job_ctl.stopped {
+            return None;
+        }
+        let sig = job_ctl.stop_signal;
+        if consume {
+            job_ctl.stop_signal = None;
+        }
+        sig
+    }
+
+    /// 判断线程组是否从停止状态中恢复，并且尚未被wait报告
+    ///
+    /// ## 参数
+    ///
+    /// - `consume` 是否清除该状态，使其不会被再次报告（WNOWAIT时为false）
+    pub(super) fn wait_task_continued(&self, consume: bool) -> bool {
+        let leader = self.job_ctl_leader();
+        let mut job_ctl = leader.job_ctl.lock_irqsave();
+        let continued = job_ctl.continued;
+        if consume {
+            job_ctl.continued = false;
+        }
+        continued
+    }
+}
+
+/// 停止信号的默认处理函数：停止当前线程组
+///
+/// 第一个处理停止信号的线程负责让线程组中的其他线程也停止，并通知父进程
+///
+/// 参考 https://code.dragonos.org.cn/xref/linux-6.1.9/kernel/signal.c#2432
+pub fn do_signal_stop(sig: Signal) {
+    let current = ProcessManager::current_pcb();
+    let leader = current.job_ctl_leader();
+
+    // 孤儿进程组中的进程不会因为终端产生的停止信号而停止，否则将没有进程能够让它们继续运行
+    if sig != Signal::SIGSTOP
+        && !leader.is_group_stopped()
+        && ProcessManager::is_current_pgrp_orphaned()
+    {
+        return;
+    }
+
+    let initiator = {
+        let mut job_ctl = leader.job_ctl.lock_irqsave();
+        let initiator = !job_ctl.stopped;
+        if initiator {
+            job_ctl.stopped = true;
+            job_ctl.stop_signal = Some(sig);
+            job_ctl.continued = false;
+        }
+        initiator
+    };
+
+    if initiator {
+        current.for_each_thread(|thread| {
+            if !Arc::ptr_eq(thread, &current) {
+                let mut info = SigInfo::new(
+                    Signal::SIGSTOP,
+                    0,
+                    SigCode::Kernel,
+                    SigType::Kill(thread.pid()),
+                );
+                let _ = Signal::SIGSTOP.send_signal_info(Some(&mut info), thread.pid());
+            }
+        });
+        do_notify_parent_cldstop(&leader, SigChildCode::Stopped, sig);
+    }
+
+    let irq_guard = unsafe { CurrentIrqArch::save_and_disable_irq() };
+    let job_ctl = leader.job_ctl.lock_irqsave();
+    // 在此之前收到了SIGCONT，则不再停止
+    if !job_ctl.stopped {
+        return;
+    }
+    ProcessManager::mark_stop().unwrap_or_else(|e| {
+        log::error!(
+            "failed to stop process {:?} with signal {:?}: {:?}",
+            current.pid(),
+            sig,
+            e
+        );
+    });
+    drop(job_ctl);
+    drop(current);
+    drop(leader);
+    drop(irq_guard);
+    schedule(SchedMode::SM_NONE);
+}
+
+/// 向线程组发送SIGCONT时调用：清除所有线程中未处理的停止信号，并唤醒停止的线程
+///
+/// 如果线程组原本处于停止状态，还会通知父进程
+///
+/// 参考 https://code.dragonos.org.cn/xref/linux-6.1.9/kernel/signal.c#912
+pub fn signal_continue(pcb: &Arc<ProcessControlBlock>) {
+    let leader = pcb.job_ctl_leader();
+    let was_stopped = {
+        let mut job_ctl = leader.job_ctl.lock_irqsave();
+        let was_stopped = job_ctl.stopped;
+        if was_stopped {
+            job_ctl.stopped = false;
+            job_ctl.stop_signal = None;
+            job_ctl.continued = true;
+        }
+        was_stopped
+    };
+
+    pcb.for_each_thread(|thread| {
+        let mut siginfo = thread.sig_info_mut();
+        siginfo
+            .sig_pending_mut()
+            .flush_by_mask(&SIG_KERNEL_STOP_MASK);
+        siginfo
+            .sig_shared_pending_mut()
+            .flush_by_mask(&SIG_KERNEL_STOP_MASK);
+        drop(siginfo);
+        let _ = ProcessManager::wakeup_stop(thread);
+    });
+
+    if was_stopped {
+        do_notify_parent_cldstop(&leader, SigChildCode::Continued, Signal::SIGCONT);
+    }
+}
+
+/// 向线程组发送停止信号时调用：清除所有线程中未处理的SIGCONT
+pub fn signal_stop_flush_continue(pcb: &Arc<ProcessControlBlock>) {
+    let flush = Signal::SIGCONT.into_sigset();
+    pcb.for_each_thread(|thread| {
+        let mut siginfo = thread.sig_info_mut();
+        siginfo.sig_pending_mut().flush_by_mask(&flush);
+        siginfo.sig_shared_pending_mut().flush_by_mask(&flush);
+    });
+}
+
+/// 线程组停止或者继续运行时，唤醒在wait中等待的父进程，并向父进程发送SIGCHLD
+///
+/// 父进程的SIGCHLD设置了SA_NOCLDSTOP时，不发送SIGCHLD
+///
+/// 参考 https://code.dragonos.org.cn/xref/linux-6.1.9/kernel/signal.c#2157
+fn do_notify_parent_cldstop(leader: &Arc<ProcessControlBlock>, why: SigChildCode, sig: Signal) {
+    leader.wake_up_parent();
+
+    let parent = match leader.real_parent_pcb.read_irqsave().upgrade() {
+        Some(parent) => parent,
+        None => return,
+    };
+    if parent.sig_struct_irqsave().handlers[Signal::SIGCHLD as usize - 1]
+        .flags()
+        .contains(SigFlags::SA_NOCLDSTOP)
+    {
+        return;
+    }
+
+    let mut info = SigInfo::new(
+        Signal::SIGCHLD,
+        0,
+        SigCode::Kernel,
+        SigType::SigChild {
+            pid: leader.pid(),
+            code: why.into(),
+            status: sig as i32,
+        },
+    );
+    let _ = Signal::SIGCHLD.send_signal_info(Some(&mut info), parent.pid());
+}
+
+/// 进程退出时，如果导致含有停止进程的进程组成为孤儿进程组，则向该进程组发送SIGHUP和SIGCONT
+///
+/// 需要检查的是退出进程所在的进程组，以及子进程所在的进程组
+///
+/// 参考 https://code.dragonos.org.cn/xref/linux-6.1.9/kernel/exit.c#363
+pub(super) fn kill_orphaned_pgrps(exiting: &Arc<ProcessControlBlock>) {
+    let pgid = exiting.pgid();
+    let sid = exiting.sid();
+    let mut pgrps: Vec<Arc<ProcessGroup>> = Vec::new();
+
+    // 退出进程是其进程组与同一会话中其他进程组之间的联系
+    if let Some(pg) = exiting.process_group() {
+        if let Some(parent) = exiting.real_parent_pcb.read_irqsave().upgrade() {
+            if parent.pgid() != pgid && parent.sid() == sid {
+                pgrps.push(pg);
+            }
+        }
+    }
+
+    // 退出进程是子进程所在进程组与同一会话中其他进程组之间的联系
+    let children = exiting.children.read_irqsave().clone();
+    for child in children.into_iter().filter_map(ProcessManager::find) {
+        if let Some(pg) = child.process_group() {
+            if pg.pgid() != pgid && child.sid() == sid && !pgrps.iter().any(|p| Arc::ptr_eq(p, &pg))
+            {
+                pgrps.push(pg);
+            }
+        }
+    }
+
+    for pg in pgrps {
+        if pg.will_become_orphaned(Some(exiting)) && pg.has_stopped_jobs() {
+            let _ = kill_process_group(pg.pgid(), Signal::SIGHUP);
+            let _ = kill_process_group(pg.pgid(), Signal::SIGCONT);
+        }
+    }
+}
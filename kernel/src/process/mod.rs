@@ -4,10 +4,13 @@ use core::{
     hint::spin_loop,
     intrinsics::{likely, unlikely},
     mem::ManuallyDrop,
-    sync::atomic::{compiler_fence, fence, AtomicBool, AtomicUsize, Ordering},
+    sync::atomic::{
+        compiler_fence, fence, AtomicBool, AtomicI32, AtomicU64, AtomicU8, AtomicUsize, Ordering,
+    },
 };
 
 use alloc::{
+    collections::LinkedList,
     ffi::CString,
     string::{String, ToString},
     sync::{Arc, Weak},
@@ -30,6 +33,7 @@ use crate::{
     driver::tty::tty_core::TtyCore,
     exception::InterruptArch,
     filesystem::{
+        epoll::EPollItem,
         procfs::procfs_unregister_pid,
         vfs::{file::FileDescriptorVec, FileType, IndexNode},
     },
@@ -51,6 +55,7 @@ use crate::{
         wait_queue::WaitQueue,
     },
     mm::{
+        mempolicy::MemPolicy,
         percpu::{PerCpu, PerCpuVar},
         set_IDLE_PROCESS_ADDRESS_SPACE,
         ucontext::AddressSpace,
@@ -71,18 +76,33 @@ use crate::{
 };
 use timer::AlarmTimer;
 
-use self::{cred::Cred, kthread::WorkerPrivate};
+use self::{
+    coredump::{coredump_task_exit, CoreState},
+    cred::Cred,
+    exit::find_new_reaper,
+    job_control::{kill_orphaned_pgrps, JobCtl},
+    kthread::WorkerPrivate,
+    prctl::{DEFAULT_TIMER_SLACK_NS, SUID_DUMP_USER},
+    resource::{GroupRUsage, RLimit64, TaskRUsage, INIT_RLIMITS, RLIM_NLIMITS},
+    seccomp::Seccomp,
+};
 
 pub mod abi;
+pub mod capability;
+pub mod coredump;
 pub mod cred;
 pub mod exec;
 pub mod exit;
 pub mod fork;
 pub mod idle;
+pub mod job_control;
 pub mod kthread;
 pub mod pid;
+pub mod pidfd;
+pub mod prctl;
 pub mod process_group;
 pub mod resource;
+pub mod seccomp;
 pub mod session;
 pub mod stdio;
 pub mod syscall;
@@ -232,6 +252,29 @@ impl ProcessManager {
             .insert(pcb.pid(), pcb.clone());
     }
 
+    /// 在持有进程表的锁的情况下遍历所有进程
+    ///
+    /// 本函数不会等待进程表的锁，适用于内存不足等不能阻塞的场景。
+    /// 回调函数中不能再访问进程表。
+    ///
+    /// ## 返回值
+    ///
+    /// 进程表的锁正被持有时，返回`EAGAIN_OR_EWOULDBLOCK`
+    pub fn try_for_each_process<F>(mut f: F) -> Result<(), SystemError>
+    where
+        F: FnMut(&Arc<ProcessControlBlock>),
+    {
+        let guard = ALL_PROCESS.try_lock_irqsave()?;
+        for pcb in guard
+            .as_ref()
+            .ok_or(SystemError::EAGAIN_OR_EWOULDBLOCK)?
+            .values()
+        {
+            f(pcb);
+        }
+        return Ok(());
+    }
+
     /// ### 获取所有进程的pid
     pub fn get_all_processes() -> Vec<Pid> {
         let mut pids = Vec::new();
@@ -374,7 +417,7 @@ impl ProcessManager {
     /// 当子进程退出后向父进程发送通知
     fn exit_notify() {
         let current = ProcessManager::current_pcb();
-        // 让INIT进程收养所有子进程
+        // 让child subreaper或者INIT进程收养所有子进程
         if current.pid() != Pid(1) {
             unsafe {
                 current
@@ -410,17 +453,23 @@ impl ProcessManager {
     ///
     ///  因此注意，传入的`exit_code`应该是已经完成了移位操作的
     pub fn exit(exit_code: usize) -> ! {
+        // 线程组正在生成core dump时，需要等待dump完成
+        coredump_task_exit(&ProcessManager::current_pcb());
         // 关中断
         let _irq_guard = unsafe { CurrentIrqArch::save_and_disable_irq() };
         let pid: Pid;
         {
             let pcb = ProcessManager::current_pcb();
             pid = pcb.pid();
+            // 线程的资源使用情况需要在标记为退出之前累加到线程组中
+            pcb.account_dead_thread();
             pcb.sched_info
                 .inner_lock_write_irqsave()
                 .set_state(ProcessState::Exited(exit_code));
             pcb.wait_queue.mark_dead();
             pcb.wait_queue.wakeup_all(Some(ProcessState::Blocked(true)));
+            pcb.wake_up_parent();
+            pcb.pidfd_notify();
 
             let rq = cpu_rq(smp_get_processor_id().data() as usize);
             let (rq, guard) = rq.self_lock();
@@ -469,6 +518,10 @@ impl ProcessManager {
             }
             pcb.sig_info_mut().set_tty(None);
 
+            // 进程退出可能使含有停止进程的进程组成为孤儿进程组
+            if pcb.pid() == pcb.tgid() {
+                kill_orphaned_pgrps(&pcb);
+            }
             pcb.clear_pg_and_session_reference();
             drop(pcb);
             ProcessManager::exit_notify();
@@ -500,6 +553,16 @@ impl ProcessManager {
             //     panic!()
             // }
 
+            let pcb = pcb.unwrap();
+            if pcb.pid() != pcb.tgid() {
+                if let Some(leader) = pcb.thread.read_irqsave().group_leader() {
+                    leader
+                        .thread_group
+                        .lock_irqsave()
+                        .retain(|thread| thread.as_ptr() != Arc::as_ptr(&pcb));
+                }
+            }
+
             ALL_PROCESS.lock_irqsave().as_mut().unwrap().remove(&pid);
         }
     }
@@ -654,12 +717,19 @@ bitflags! {
         const HAS_PENDING_SIGNAL = 1 << 9;
         /// 进程需要恢复之前保存的信号掩码
         const RESTORE_SIG_MASK = 1 << 10;
+        /// 进程被OOM killer选中，正在退出
+        const OOM_VICTIM = 1 << 11;
+        /// 返回用户态之前有延迟处理的工作（例如时钟中断中产生的资源限制信号）
+        /// 相当于Linux的TIF_NOTIFY_RESUME
+        const NOTIFY_RESUME = 1 << 12;
     }
 }
 
 impl ProcessFlags {
     pub const fn exit_to_user_mode_work(&self) -> Self {
-        Self::from_bits_truncate(self.bits & (Self::HAS_PENDING_SIGNAL.bits))
+        Self::from_bits_truncate(
+            self.bits & (Self::HAS_PENDING_SIGNAL.bits | Self::NOTIFY_RESUME.bits),
+        )
     }
 
     /// 测试并清除标志位
@@ -707,6 +777,12 @@ pub struct ProcessControlBlock {
     sig_struct: SpinLock<SignalStruct>,
     /// 退出信号S
     exit_signal: AtomicSignal,
+    /// 线程组的作业控制状态，只有线程组leader的这个字段有效
+    job_ctl: SpinLock<JobCtl>,
+    /// 线程组正在生成core dump时的状态，只有线程组leader的这个字段有效
+    core_state: SpinLock<Option<Arc<CoreState>>>,
+    /// 线程组中除leader以外的线程，只有线程组leader的这个字段有效
+    thread_group: SpinLock<Vec<Weak<ProcessControlBlock>>>,
 
     /// 父进程指针
     parent_pcb: RwLock<Weak<ProcessControlBlock>>,
@@ -718,6 +794,12 @@ pub struct ProcessControlBlock {
 
     /// 等待队列
     wait_queue: WaitQueue,
+    /// 在wait中等待子进程状态变化的等待队列，只有线程组leader的这个字段有效
+    wait_chldexit: WaitQueue,
+    /// 子进程状态变化的次数，用于避免在检查子进程和睡眠之间错过唤醒
+    wait_chldexit_seq: AtomicUsize,
+    /// 在指向本进程的pidfd上等待的epoll项
+    pidfd_epitems: SpinLock<LinkedList<Arc<EPollItem>>>,
 
     /// 线程信息
     thread: RwLock<ThreadInfo>,
@@ -745,6 +827,43 @@ pub struct ProcessControlBlock {
 
     /// 进程的可执行文件路径
     executable_path: RwLock<String>,
+
+    /// OOM killer选择进程时使用的分数调整值，范围为[-1000, 1000]
+    oom_score_adj: AtomicI32,
+
+    /// 进程的内存策略，为None时在本地节点分配
+    mempolicy: SpinLock<Option<Arc<MemPolicy>>>,
+    /// 处理缺页异常期间，缺页地址所在VMA的内存策略
+    fault_mempolicy: SpinLock<Option<Arc<MemPolicy>>>,
+    /// 上面两个策略中哪些被设置了，分配页帧时无需加锁即可判断是否使用默认策略
+    mempolicy_set: AtomicU8,
+
+    /// 父进程退出时向当前进程发送的信号，为INVALID时不发送
+    pdeath_signal: AtomicSignal,
+    /// 当前进程是否收养后代进程中的孤儿进程
+    child_subreaper: AtomicBool,
+    /// 祖先进程中是否存在child subreaper
+    has_child_subreaper: AtomicBool,
+    /// 为true时，execve不能再赋予进程新的权限
+    no_new_privs: AtomicBool,
+    /// 进程的seccomp模式和过滤器
+    seccomp: SpinLock<Seccomp>,
+    /// seccomp模式的无锁副本，用于在每次系统调用入口处快速判断是否需要进行检查
+    seccomp_mode: AtomicU8,
+    /// 进程的资源限制，同一线程组内的线程保持一致
+    rlimits: SpinLock<[RLimit64; RLIM_NLIMITS]>,
+    /// 当前线程的资源使用统计
+    rusage: TaskRUsage,
+    /// 线程组的资源使用统计，只在线程组的leader中使用
+    group_rusage: SpinLock<GroupRUsage>,
+    /// 时钟中断中检查RLIMIT_CPU时产生的、需要在返回用户态之前发送的信号
+    rlimit_signal: AtomicSignal,
+    /// 进程能否生成core dump，取值为`SUID_DUMP_*`
+    dumpable: AtomicU8,
+    /// 定时器的松弛时间（纳秒）
+    timer_slack_ns: AtomicU64,
+    /// 把定时器的松弛时间设置为0时恢复的值
+    default_timer_slack_ns: AtomicU64,
 }
 
 impl ProcessControlBlock {
@@ -783,17 +902,28 @@ impl ProcessControlBlock {
 
     #[inline(never)]
     fn do_create_pcb(name: String, kstack: KernelStack, is_idle: bool) -> Arc<Self> {
-        let (pid, ppid, cwd, cred, tty) = if is_idle {
+        let (pid, ppid, cwd, cred, tty, oom_score_adj, mempolicy) = if is_idle {
             let cred = INIT_CRED.clone();
-            (Pid(0), Pid(0), "/".to_string(), cred, None)
+            (Pid(0), Pid(0), "/".to_string(), cred, None, 0, None)
         } else {
             let ppid = ProcessManager::current_pcb().pid();
-            let mut cred = ProcessManager::current_pcb().cred();
-            cred.cap_permitted = cred.cap_ambient;
-            cred.cap_effective = cred.cap_ambient;
+            // 子进程继承父进程的凭证，capability在execve时才会重新计算
+            let cred = ProcessManager::current_pcb().cred();
             let cwd = ProcessManager::current_pcb().basic().cwd();
             let tty = ProcessManager::current_pcb().sig_info_irqsave().tty();
-            (Self::generate_pid(), ppid, cwd, cred, tty)
+            // 子进程继承父进程的oom_score_adj
+            let oom_score_adj = ProcessManager::current_pcb().oom_score_adj();
+            // 子进程继承父进程的内存策略
+            let mempolicy = ProcessManager::current_pcb().mempolicy();
+            (
+                Self::generate_pid(),
+                ppid,
+                cwd,
+                cred,
+                tty,
+                oom_score_adj,
+                mempolicy,
+            )
         };
 
         let basic_info = ProcessBasicInfo::new(ppid, name.clone(), cwd, None);
@@ -806,6 +936,36 @@ impl ProcessControlBlock {
         let ppcb: Weak<ProcessControlBlock> = ProcessManager::find(ppid)
             .map(|p| Arc::downgrade(&p))
             .unwrap_or_default();
+
+        // 子进程继承父进程通过prctl设置的属性，但pdeath_signal和child_subreaper除外
+        let (has_child_subreaper, no_new_privs, seccomp, dumpable, timer_slack_ns, rlimits) =
+            if is_idle {
+                (
+                    false,
+                    false,
+                    Seccomp::default(),
+                    SUID_DUMP_USER,
+                    DEFAULT_TIMER_SLACK_NS,
+                    INIT_RLIMITS,
+                )
+            } else {
+                let current = ProcessManager::current_pcb();
+                // 子进程继承父进程的资源限制
+                let rlimits = *current.rlimits.lock_irqsave();
+                (
+                    current.is_child_subreaper() || current.has_child_subreaper(),
+                    current.no_new_privs(),
+                    current.seccomp(),
+                    current.dumpable(),
+                    current.timer_slack_ns(),
+                    rlimits,
+                )
+            };
+        let mempolicy_set = if mempolicy.is_some() {
+            Self::MEMPOLICY_TASK
+        } else {
+            0
+        };
         let mut pcb = Self {
             pid,
             tgid: pid,
@@ -821,10 +981,16 @@ impl ProcessControlBlock {
             sig_info: RwLock::new(ProcessSignalInfo::default()),
             sig_struct: SpinLock::new(SignalStruct::new()),
             exit_signal: AtomicSignal::new(Signal::SIGCHLD),
+            job_ctl: SpinLock::new(JobCtl::default()),
+            core_state: SpinLock::new(None),
+            thread_group: SpinLock::new(Vec::new()),
             parent_pcb: RwLock::new(ppcb.clone()),
             real_parent_pcb: RwLock::new(ppcb),
             children: RwLock::new(Vec::new()),
             wait_queue: WaitQueue::default(),
+            wait_chldexit: WaitQueue::default(),
+            wait_chldexit_seq: AtomicUsize::new(0),
+            pidfd_epitems: SpinLock::new(LinkedList::new()),
             thread: RwLock::new(ThreadInfo::new()),
             fs: RwLock::new(Arc::new(FsStruct::new())),
             alarm_timer: SpinLock::new(None),
@@ -835,6 +1001,23 @@ impl ProcessControlBlock {
             restart_block: SpinLock::new(None),
             process_group: Mutex::new(Weak::new()),
             executable_path: RwLock::new(name),
+            oom_score_adj: AtomicI32::new(oom_score_adj),
+            mempolicy: SpinLock::new(mempolicy),
+            fault_mempolicy: SpinLock::new(None),
+            mempolicy_set: AtomicU8::new(mempolicy_set),
+            pdeath_signal: AtomicSignal::new(Signal::INVALID),
+            child_subreaper: AtomicBool::new(false),
+            has_child_subreaper: AtomicBool::new(has_child_subreaper),
+            no_new_privs: AtomicBool::new(no_new_privs),
+            seccomp_mode: AtomicU8::new(seccomp.mode()),
+            seccomp: SpinLock::new(seccomp),
+            dumpable: AtomicU8::new(dumpable),
+            timer_slack_ns: AtomicU64::new(timer_slack_ns),
+            default_timer_slack_ns: AtomicU64::new(timer_slack_ns),
+            rlimits: SpinLock::new(rlimits),
+            rusage: TaskRUsage::default(),
+            group_rusage: SpinLock::new(GroupRUsage::default()),
+            rlimit_signal: AtomicSignal::new(Signal::INVALID),
         };
 
         pcb.sig_info.write().set_tty(tty);
@@ -947,6 +1130,12 @@ impl ProcessControlBlock {
         return self.basic.read_irqsave();
     }
 
+    /// 尝试获取进程基本信息的读锁，用于不能睡眠等待的上下文（例如内存分配失败的路径）
+    #[inline(always)]
+    pub fn try_basic(&self) -> Option<RwLockReadGuard<ProcessBasicInfo>> {
+        return self.basic.try_read_irqsave();
+    }
+
     #[inline(always)]
     pub fn set_name(&self, name: String) {
         self.basic.write().set_name(name);
@@ -1048,6 +1237,119 @@ impl ProcessControlBlock {
         self.executable_path.read().clone()
     }
 
+    /// 获取进程的oom_score_adj
+    pub fn oom_score_adj(&self) -> i32 {
+        self.oom_score_adj.load(Ordering::Relaxed)
+    }
+
+    /// 设置进程的oom_score_adj，调用者需要保证取值在合法范围内
+    pub fn set_oom_score_adj(&self, adj: i32) {
+        self.oom_score_adj.store(adj, Ordering::Relaxed);
+    }
+
+    /// 获取父进程退出时向当前进程发送的信号
+    pub fn pdeath_signal(&self) -> Signal {
+        self.pdeath_signal.load(Ordering::Relaxed)
+    }
+
+    pub fn set_pdeath_signal(&self, sig: Signal) {
+        self.pdeath_signal.store(sig, Ordering::Relaxed);
+    }
+
+    /// 当前进程是否是child subreaper
+    pub fn is_child_subreaper(&self) -> bool {
+        self.child_subreaper.load(Ordering::Relaxed)
+    }
+
+    /// 设置当前进程是否是child subreaper
+    ///
+    /// 与Linux一致，只有在此之后创建的后代进程才会知道祖先中存在child subreaper
+    pub fn set_child_subreaper(&self, enable: bool) {
+        self.child_subreaper.store(enable, Ordering::Relaxed);
+    }
+
+    /// 祖先进程中是否存在child subreaper
+    pub fn has_child_subreaper(&self) -> bool {
+        self.has_child_subreaper.load(Ordering::Relaxed)
+    }
+
+    pub fn no_new_privs(&self) -> bool {
+        self.no_new_privs.load(Ordering::Relaxed)
+    }
+
+    /// 设置no_new_privs。这个属性一旦设置就不能被清除
+    pub fn set_no_new_privs(&self) {
+        self.no_new_privs.store(true, Ordering::Relaxed);
+    }
+
+    pub fn dumpable(&self) -> u8 {
+        self.dumpable.load(Ordering::Relaxed)
+    }
+
+    pub fn set_dumpable(&self, dumpable: u8) {
+        self.dumpable.store(dumpable, Ordering::Relaxed);
+    }
+
+    pub fn timer_slack_ns(&self) -> u64 {
+        self.timer_slack_ns.load(Ordering::Relaxed)
+    }
+
+    /// 设置定时器的松弛时间，为0时恢复为默认值
+    pub fn set_timer_slack_ns(&self, slack: u64) {
+        let slack = if slack == 0 {
+            self.default_timer_slack_ns.load(Ordering::Relaxed)
+        } else {
+            slack
+        };
+        self.timer_slack_ns.store(slack, Ordering::Relaxed);
+    }
+
+    /// 获取进程的内存策略
+    pub fn mempolicy(&self) -> Option<Arc<MemPolicy>> {
+        self.mempolicy.lock_irqsave().clone()
+    }
+
+    const MEMPOLICY_TASK: u8 = 1 << 0;
+    const MEMPOLICY_FAULT: u8 = 1 << 1;
+
+    fn update_mempolicy_set(&self, bit: u8, set: bool) {
+        if set {
+            self.mempolicy_set.fetch_or(bit, Ordering::Release);
+        } else {
+            self.mempolicy_set.fetch_and(!bit, Ordering::Release);
+        }
+    }
+
+    /// 设置进程的内存策略
+    pub fn set_mempolicy(&self, policy: Option<Arc<MemPolicy>>) {
+        let mut guard = self.mempolicy.lock_irqsave();
+        self.update_mempolicy_set(Self::MEMPOLICY_TASK, policy.is_some());
+        *guard = policy;
+    }
+
+    /// 替换处理缺页异常期间使用的内存策略，返回原来的策略
+    pub fn swap_fault_mempolicy(&self, policy: Option<Arc<MemPolicy>>) -> Option<Arc<MemPolicy>> {
+        let mut guard = self.fault_mempolicy.lock_irqsave();
+        self.update_mempolicy_set(Self::MEMPOLICY_FAULT, policy.is_some());
+        core::mem::replace(&mut *guard, policy)
+    }
+
+    /// 获取分配页帧时使用的内存策略：缺页异常期间优先使用VMA的策略，否则使用进程的策略
+    ///
+    /// 没有设置任何策略时不获取锁，直接返回None
+    pub fn alloc_mempolicy(&self) -> Option<Arc<MemPolicy>> {
+        let set = self.mempolicy_set.load(Ordering::Acquire);
+        if set == 0 {
+            return None;
+        }
+        if set & Self::MEMPOLICY_FAULT != 0 {
+            if let Some(policy) = self.fault_mempolicy.lock_irqsave().clone() {
+                return Some(policy);
+            }
+        }
+        self.mempolicy()
+    }
+
     /// 根据文件描述符序号，获取socket对象的Arc指针
     ///
     /// ## 参数
@@ -1074,21 +1376,35 @@ impl ProcessControlBlock {
         return Some(socket);
     }
 
-    /// 当前进程退出时,让初始进程收养所有子进程
+    /// 当前进程退出时,让child subreaper或者初始进程收养所有子进程
+    ///
+    /// 设置了pdeath_signal的子进程会收到对应的信号
     unsafe fn adopt_childen(&self) -> Result<(), SystemError> {
-        match ProcessManager::find(Pid(1)) {
-            Some(init_pcb) => {
-                let childen_guard = self.children.write();
-                let mut init_childen_guard = init_pcb.children.write();
-
-                childen_guard.iter().for_each(|pid| {
-                    init_childen_guard.push(*pid);
-                });
+        let reaper = find_new_reaper(self).ok_or(SystemError::ECHILD)?;
+        let children: Vec<Pid> = core::mem::take(&mut *self.children.write());
+        reaper.children.write().extend(children.iter().copied());
+
+        let mut has_zombie = false;
+        for pid in children {
+            let Some(child) = ProcessManager::find(pid) else {
+                continue;
+            };
+            *child.parent_pcb.write_irqsave() = Arc::downgrade(&reaper);
+            *child.real_parent_pcb.write_irqsave() = Arc::downgrade(&reaper);
+            has_zombie |= child.is_exited();
 
-                return Ok(());
+            let sig = child.pdeath_signal();
+            if sig != Signal::INVALID {
+                let _ = crate::ipc::kill::kill_process(pid, sig);
             }
-            _ => Err(SystemError::ECHILD),
         }
+
+        // 已经退出的子进程需要通知新的父进程回收
+        if has_zombie {
+            reaper.wake_up_child_waiters();
+            let _ = crate::ipc::kill::kill_process(reaper.pid(), Signal::SIGCHLD);
+        }
+        return Ok(());
     }
 
     /// 生成进程的名字
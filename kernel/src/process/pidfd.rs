@@ -0,0 +1,403 @@
+//! pidfd：指向进程的文件描述符
+//!
+//! pidfd持有的是进程本身的引用，而不是pid，因此即使pid被回收并分配给了新的进程，
+//! 通过pidfd进行的操作也不会作用到错误的进程上。当进程退出后，pidfd变为可读。
+//!
+//! 参考 https://code.dragonos.org.cn/xref/linux-6.1.9/kernel/pid.c#555
+
+use alloc::{
+    string::String,
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+use core::any::Any;
+use system_error::SystemError;
+
+use crate::{
+    arch::ipc::signal::{SigCode, Signal, MAX_SIG_NUM},
+    filesystem::{
+        epoll::{event_poll::EventPoll, EPollEventType, EPollItem},
+        vfs::{
+            file::{File, FileMode},
+            syscall::ModeType,
+            FilePrivateData, FileSystem, FileType, IndexNode, Metadata, PollableInode,
+        },
+    },
+    ipc::{
+        kill::check_kill_permission,
+        signal_types::{SigInfo, SigType},
+    },
+    libs::spinlock::SpinLockGuard,
+    syscall::{user_access::UserBufferReader, Syscall},
+};
+
+use super::{cred::CAPFlags, prctl::SUID_DUMP_USER, Pid, ProcessControlBlock, ProcessManager};
+
+bitflags! {
+    pub struct PidFdFlags: u32 {
+        /// 以非阻塞模式打开pidfd
+        const PIDFD_NONBLOCK = FileMode::O_NONBLOCK.bits();
+    }
+}
+
+/// pidfd对应的inode
+#[derive(Debug)]
+pub struct PidFdInode {
+    pcb: Weak<ProcessControlBlock>,
+}
+
+impl PidFdInode {
+    fn new(pcb: &Arc<ProcessControlBlock>) -> Self {
+        Self {
+            pcb: Arc::downgrade(pcb),
+        }
+    }
+
+    /// 获取pidfd指向的进程，进程已经被回收时返回None
+    pub fn task(&self) -> Option<Arc<ProcessControlBlock>> {
+        self.pcb.upgrade()
+    }
+}
+
+impl PollableInode for PidFdInode {
+    /// 线程组中的所有线程都退出后，pidfd变为可读；进程被回收后，还会设置EPOLLHUP
+    fn poll(&self, _private_data: &FilePrivateData) -> Result<usize, SystemError> {
+        let mut events = EPollEventType::empty();
+        match self.task() {
+            None => {
+                events |=
+                    EPollEventType::EPOLLIN | EPollEventType::EPOLLRDNORM | EPollEventType::EPOLLHUP
+            }
+            Some(pcb) => {
+                if pcb.thread_group_exited() {
+                    events |= EPollEventType::EPOLLIN | EPollEventType::EPOLLRDNORM;
+                }
+            }
+        }
+        return Ok(events.bits() as usize);
+    }
+
+    fn add_epitem(
+        &self,
+        epitem: Arc<EPollItem>,
+        _private_data: &FilePrivateData,
+    ) -> Result<(), SystemError> {
+        // 进程已经被回收时，pidfd的状态不会再发生变化，不需要记录epitem
+        if let Some(pcb) = self.task() {
+            pcb.pidfd_epitems.lock_irqsave().push_back(epitem);
+        }
+        Ok(())
+    }
+
+    fn remove_epitem(
+        &self,
+        epitem: &Arc<EPollItem>,
+        _private_data: &FilePrivateData,
+    ) -> Result<(), SystemError> {
+        if let Some(pcb) = self.task() {
+            pcb.pidfd_epitems
+                .lock_irqsave()
+                .retain(|x| !Arc::ptr_eq(x, epitem));
+        }
+        Ok(())
+    }
+}
+
+impl IndexNode for PidFdInode {
+    fn open(
+        &self,
+        _data: SpinLockGuard<FilePrivateData>,
+        _mode: &FileMode,
+    ) -> Result<(), SystemError> {
+        Ok(())
+    }
+
+    fn close(&self, _data: SpinLockGuard<FilePrivateData>) -> Result<(), SystemError> {
+        Ok(())
+    }
+
+    fn read_at(
+        &self,
+        _offset: usize,
+        _len: usize,
+        _buf: &mut [u8],
+        _data: SpinLockGuard<FilePrivateData>,
+    ) -> Result<usize, SystemError> {
+        Err(SystemError::EINVAL)
+    }
+
+    fn write_at(
+        &self,
+        _offset: usize,
+        _len: usize,
+        _buf: &[u8],
+        _data: SpinLockGuard<FilePrivateData>,
+    ) -> Result<usize, SystemError> {
+        Err(SystemError::EINVAL)
+    }
+
+    fn metadata(&self) -> Result<Metadata, SystemError> {
+        let meta = Metadata {
+            mode: ModeType::from_bits_truncate(0o600),
+            file_type: FileType::File,
+            ..Default::default()
+        };
+        Ok(meta)
+    }
+
+    fn fs(&self) -> Arc<dyn FileSystem> {
+        panic!("PidFd does not have a filesystem")
+    }
+
+    fn as_any_ref(&self) -> &dyn Any {
+        self
+    }
+
+    fn list(&self) -> Result<Vec<String>, SystemError> {
+        Err(SystemError::ENOTDIR)
+    }
+
+    fn as_pollable_inode(&self) -> Result<&dyn PollableInode, SystemError> {
+        Ok(self)
+    }
+}
+
+impl ProcessControlBlock {
+    /// 判断线程组中的所有线程是否都已经退出
+    pub fn thread_group_exited(&self) -> bool {
+        if !self.is_exited() {
+            return false;
+        }
+        let mut exited = true;
+        self.for_each_thread(|thread| exited &= thread.is_exited());
+        exited
+    }
+
+    /// 线程退出时，唤醒在线程组leader的pidfd上等待的进程
+    pub(super) fn pidfd_notify(&self) {
+        let leader = self
+            .thread
+            .read_irqsave()
+            .group_leader()
+            .unwrap_or_else(|| self.self_ref.upgrade().unwrap());
+        let _ = EventPoll::wakeup_epoll(
+            &leader.pidfd_epitems,
+            EPollEventType::EPOLLIN | EPollEventType::EPOLLRDNORM,
+        );
+    }
+}
+
+/// 为进程创建一个pidfd文件，但不加入文件描述符表
+///
+/// ## 参数
+///
+/// - `pcb` pidfd指向的进程，需要是线程组的leader
+/// - `flags` 打开pidfd的标志
+pub fn pidfd_file(pcb: &Arc<ProcessControlBlock>, flags: PidFdFlags) -> Result<File, SystemError> {
+    let inode = Arc::new(PidFdInode::new(pcb));
+    // pidfd总是设置了close-on-exec
+    let mode = FileMode::O_RDWR | FileMode::O_CLOEXEC | FileMode::from_bits_truncate(flags.bits());
+    return File::new(inode, mode);
+}
+
+/// 为进程创建一个pidfd，并加入当前进程的文件描述符表
+///
+/// ## 参数
+///
+/// - `pcb` pidfd指向的进程，需要是线程组的leader
+/// - `flags` 打开pidfd的标志
+///
+/// ## 返回值
+///
+/// 新的文件描述符
+pub fn pidfd_create(pcb: &Arc<ProcessControlBlock>, flags: PidFdFlags) -> Result<i32, SystemError> {
+    let file = pidfd_file(pcb, flags)?;
+    let binding = ProcessManager::current_pcb().fd_table();
+    let mut fd_table_guard = binding.write();
+    return fd_table_guard.alloc_fd(file, None);
+}
+
+/// 查找当前进程的pidfd，返回其指向的进程以及文件的打开模式
+fn pidfd_lookup(fd: i32) -> Result<(Weak<ProcessControlBlock>, FileMode), SystemError> {
+    let file = ProcessManager::current_pcb()
+        .fd_table()
+        .read()
+        .get_file_by_fd(fd)
+        .ok_or(SystemError::EBADF)?;
+    let inode = file.inode();
+    let pidfd = inode
+        .as_any_ref()
+        .downcast_ref::<PidFdInode>()
+        .ok_or(SystemError::EBADF)?;
+    Ok((pidfd.pcb.clone(), file.mode()))
+}
+
+/// 获取pidfd指向的进程
+///
+/// ## 返回值
+///
+/// - `Err(SystemError::EBADF)` fd不是一个pidfd
+/// - `Err(SystemError::ESRCH)` 进程已经被回收
+pub fn pidfd_get_task(fd: i32) -> Result<Arc<ProcessControlBlock>, SystemError> {
+    pidfd_lookup(fd)?.0.upgrade().ok_or(SystemError::ESRCH)
+}
+
+/// 获取waitid(P_PIDFD)要等待的进程
+///
+/// ## 返回值
+///
+/// - `Ok((Pid, bool))` 进程的pid，以及pidfd是否处于非阻塞模式
+/// - `Err(SystemError::ECHILD)` 进程已经被回收
+pub fn pidfd_get_pid(fd: i32) -> Result<(Pid, bool), SystemError> {
+    let (pcb, mode) = pidfd_lookup(fd)?;
+    let pcb = pcb.upgrade().ok_or(SystemError::ECHILD)?;
+    Ok((pcb.pid(), mode.contains(FileMode::O_NONBLOCK)))
+}
+
+/// 从用户态传入的`siginfo_t`中读取的头部
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+struct UserSigInfoHeader {
+    si_signo: i32,
+    si_errno: i32,
+    si_code: i32,
+}
+
+/// 由tgkill、tkill发送的信号
+const SI_TKILL: i32 = -6;
+
+impl Syscall {
+    /// # pidfd_open系统调用
+    ///
+    /// 为线程组leader创建一个pidfd
+    ///
+    /// ## 参数
+    ///
+    /// - `pid` 进程的pid
+    /// - `flags` 0或者`PIDFD_NONBLOCK`
+    pub fn pidfd_open(pid: i32, flags: u32) -> Result<usize, SystemError> {
+        let flags = PidFdFlags::from_bits(flags).ok_or(SystemError::EINVAL)?;
+        if pid <= 0 {
+            return Err(SystemError::EINVAL);
+        }
+        let pcb = ProcessManager::find(Pid::new(pid as usize)).ok_or(SystemError::ESRCH)?;
+        if pcb.tgid() != pcb.pid() {
+            return Err(SystemError::EINVAL);
+        }
+        pidfd_create(&pcb, flags).map(|fd| fd as usize)
+    }
+
+    /// # pidfd_send_signal系统调用
+    ///
+    /// 向pidfd指向的进程发送信号
+    ///
+    /// ## 参数
+    ///
+    /// - `pidfd` 目标进程的pidfd
+    /// - `sig` 信号，为0时只检查权限
+    /// - `info` 用户提供的siginfo，为NULL时与kill(2)的行为相同
+    /// - `flags` 目前必须为0
+    pub fn pidfd_send_signal(
+        pidfd: i32,
+        sig: i32,
+        info: usize,
+        flags: u32,
+    ) -> Result<usize, SystemError> {
+        if flags != 0 {
+            return Err(SystemError::EINVAL);
+        }
+        let pcb = pidfd_get_task(pidfd)?;
+        if pcb.thread_group_exited() {
+            return Err(SystemError::ESRCH);
+        }
+        if sig < 0 || sig as usize > MAX_SIG_NUM {
+            return Err(SystemError::EINVAL);
+        }
+        let sig = Signal::from(sig);
+
+        let current = ProcessManager::current_pcb();
+        let mut siginfo = if info != 0 {
+            let reader = UserBufferReader::new(
+                info as *const UserSigInfoHeader,
+                core::mem::size_of::<UserSigInfoHeader>(),
+                true,
+            )?;
+            let header = *reader.read_one_from_user::<UserSigInfoHeader>(0)?;
+            if header.si_signo != sig as i32 {
+                return Err(SystemError::EINVAL);
+            }
+            // 不允许向其它进程伪装成内核或者kill(2)发送的信号
+            if current.tgid() != pcb.tgid() && (header.si_code >= 0 || header.si_code == SI_TKILL) {
+                return Err(SystemError::EPERM);
+            }
+            SigInfo::new(
+                sig,
+                header.si_errno,
+                SigCode::Queue,
+                SigType::Kill(current.pid()),
+            )
+        } else {
+            SigInfo::new(sig, 0, SigCode::User, SigType::Kill(current.pid()))
+        };
+
+        check_kill_permission(sig, &pcb)?;
+        // 信号为0时只检查权限
+        if sig == Signal::INVALID {
+            return Ok(0);
+        }
+        // 确认pid没有被其它进程复用
+        match ProcessManager::find(pcb.pid()) {
+            Some(p) if Arc::ptr_eq(&p, &pcb) => {}
+            _ => return Err(SystemError::ESRCH),
+        }
+        sig.send_signal_info(Some(&mut siginfo), pcb.pid())
+            .map(|_| 0)
+    }
+
+    /// # pidfd_getfd系统调用
+    ///
+    /// 复制其它进程的文件描述符到当前进程
+    ///
+    /// ## 参数
+    ///
+    /// - `pidfd` 目标进程的pidfd
+    /// - `targetfd` 目标进程中的文件描述符
+    /// - `flags` 目前必须为0
+    pub fn pidfd_getfd(pidfd: i32, targetfd: i32, flags: u32) -> Result<usize, SystemError> {
+        if flags != 0 {
+            return Err(SystemError::EINVAL);
+        }
+        let pcb = pidfd_get_task(pidfd)?;
+        if pcb.is_exited() {
+            return Err(SystemError::ESRCH);
+        }
+
+        // 需要有ptrace目标进程的权限：真实用户ID与目标进程的所有用户ID都相同，或者拥有CAP_SYS_PTRACE
+        let cred = ProcessManager::current_pcb().cred();
+        let tcred = pcb.cred();
+        let same_user = cred.uid == tcred.euid
+            && cred.uid == tcred.suid
+            && cred.uid == tcred.uid
+            && cred.gid == tcred.egid
+            && cred.gid == tcred.sgid
+            && cred.gid == tcred.gid;
+        if !cred.has_capability(CAPFlags::CAP_SYS_PTRACE)
+            && (!same_user || pcb.dumpable() != SUID_DUMP_USER)
+        {
+            return Err(SystemError::EPERM);
+        }
+
+        let file = pcb
+            .fd_table()
+            .read()
+            .get_file_by_fd(targetfd)
+            .ok_or(SystemError::EBADF)?;
+        let new_file = file.try_clone().ok_or(SystemError::EBADF)?;
+        new_file.set_close_on_exec(true);
+        let binding = ProcessManager::current_pcb().fd_table();
+        let mut fd_table_guard = binding.write();
+        fd_table_guard
+            .alloc_fd(new_file, None)
+            .map(|fd| fd as usize)
+    }
+}
@@ -0,0 +1,200 @@
+//! prctl系统调用
+//!
+//! 参考 https://code.dragonos.org.cn/xref/linux-6.1.9/kernel/sys.c#2357
+
+use alloc::string::ToString;
+use system_error::SystemError;
+
+use crate::{
+    arch::ipc::signal::{Signal, MAX_SIG_NUM},
+    syscall::{
+        user_access::{check_and_clone_cstr, UserBufferWriter},
+        Syscall,
+    },
+};
+
+use super::{
+    cred::CAPFlags,
+    seccomp::{prctl_get_seccomp, prctl_set_seccomp},
+    ProcessManager,
+};
+
+const PR_SET_PDEATHSIG: usize = 1;
+const PR_GET_PDEATHSIG: usize = 2;
+const PR_GET_DUMPABLE: usize = 3;
+const PR_SET_DUMPABLE: usize = 4;
+const PR_SET_NAME: usize = 15;
+const PR_GET_NAME: usize = 16;
+const PR_GET_SECCOMP: usize = 21;
+const PR_SET_SECCOMP: usize = 22;
+const PR_CAPBSET_READ: usize = 23;
+const PR_CAPBSET_DROP: usize = 24;
+const PR_SET_TIMERSLACK: usize = 29;
+const PR_GET_TIMERSLACK: usize = 30;
+const PR_SET_CHILD_SUBREAPER: usize = 36;
+const PR_GET_CHILD_SUBREAPER: usize = 37;
+const PR_SET_NO_NEW_PRIVS: usize = 38;
+const PR_GET_NO_NEW_PRIVS: usize = 39;
+const PR_CAP_AMBIENT: usize = 47;
+
+const PR_CAP_AMBIENT_IS_SET: usize = 1;
+const PR_CAP_AMBIENT_RAISE: usize = 2;
+const PR_CAP_AMBIENT_LOWER: usize = 3;
+const PR_CAP_AMBIENT_CLEAR_ALL: usize = 4;
+
+/// 不允许生成core dump
+pub const SUID_DUMP_DISABLE: u8 = 0;
+/// 允许生成core dump
+pub const SUID_DUMP_USER: u8 = 1;
+
+/// 定时器默认的松弛时间（纳秒）
+pub const DEFAULT_TIMER_SLACK_NS: u64 = 50000;
+
+/// 进程名的最大长度（包括结尾的'\0'）
+const TASK_COMM_LEN: usize = 16;
+
+impl Syscall {
+    /// # prctl系统调用
+    ///
+    /// 对当前进程进行各种设置
+    ///
+    /// ## 参数
+    ///
+    /// - `option`: 操作类型，取值为`PR_*`
+    /// - `arg2`..`arg5`: 操作的参数，含义由`option`决定
+    pub fn prctl(
+        option: usize,
+        arg2: usize,
+        arg3: usize,
+        arg4: usize,
+        arg5: usize,
+    ) -> Result<usize, SystemError> {
+        let pcb = ProcessManager::current_pcb();
+        match option {
+            PR_SET_PDEATHSIG => {
+                if arg2 > MAX_SIG_NUM {
+                    return Err(SystemError::EINVAL);
+                }
+                pcb.set_pdeath_signal(Signal::from(arg2));
+                Ok(0)
+            }
+            PR_GET_PDEATHSIG => {
+                let sig = pcb.pdeath_signal() as i32;
+                let mut writer =
+                    UserBufferWriter::new(arg2 as *mut i32, core::mem::size_of::<i32>(), true)?;
+                writer.copy_one_to_user(&sig, 0)?;
+                Ok(0)
+            }
+            PR_GET_DUMPABLE => Ok(pcb.dumpable() as usize),
+            PR_SET_DUMPABLE => {
+                if arg2 != SUID_DUMP_DISABLE as usize && arg2 != SUID_DUMP_USER as usize {
+                    return Err(SystemError::EINVAL);
+                }
+                pcb.set_dumpable(arg2 as u8);
+                Ok(0)
+            }
+            PR_SET_NAME => {
+                let name = check_and_clone_cstr(arg2 as *const u8, Some(TASK_COMM_LEN - 1))?;
+                pcb.set_name(name.to_string_lossy().to_string());
+                Ok(0)
+            }
+            PR_GET_NAME => {
+                let mut comm = [0u8; TASK_COMM_LEN];
+                {
+                    let basic = pcb.basic();
+                    let name = basic.name().as_bytes();
+                    let len = name.len().min(TASK_COMM_LEN - 1);
+                    comm[..len].copy_from_slice(&name[..len]);
+                }
+                let mut writer = UserBufferWriter::new(arg2 as *mut u8, TASK_COMM_LEN, true)?;
+                writer.copy_to_user(&comm, 0)?;
+                Ok(0)
+            }
+            PR_GET_SECCOMP => prctl_get_seccomp(),
+            PR_SET_SECCOMP => prctl_set_seccomp(arg2, arg3),
+            PR_CAPBSET_READ => {
+                let cap = cap_from_arg(arg2)?;
+                Ok(pcb.cred().cap_bset.contains(cap) as usize)
+            }
+            PR_CAPBSET_DROP => {
+                let cap = cap_from_arg(arg2)?;
+                let mut cred = pcb.cred.lock();
+                if !cred.has_capability(CAPFlags::CAP_SETPCAP) {
+                    return Err(SystemError::EPERM);
+                }
+                cred.cap_bset.remove(cap);
+                Ok(0)
+            }
+            PR_SET_TIMERSLACK => {
+                // 与Linux一致，小于等于0的值表示恢复为默认值
+                pcb.set_timer_slack_ns((arg2 as isize).max(0) as u64);
+                Ok(0)
+            }
+            PR_GET_TIMERSLACK => Ok(pcb.timer_slack_ns() as usize),
+            PR_SET_CHILD_SUBREAPER => {
+                pcb.set_child_subreaper(arg2 != 0);
+                Ok(0)
+            }
+            PR_GET_CHILD_SUBREAPER => {
+                let value = pcb.is_child_subreaper() as i32;
+                let mut writer =
+                    UserBufferWriter::new(arg2 as *mut i32, core::mem::size_of::<i32>(), true)?;
+                writer.copy_one_to_user(&value, 0)?;
+                Ok(0)
+            }
+            PR_SET_NO_NEW_PRIVS => {
+                if arg2 != 1 || arg3 != 0 || arg4 != 0 || arg5 != 0 {
+                    return Err(SystemError::EINVAL);
+                }
+                pcb.set_no_new_privs();
+                Ok(0)
+            }
+            PR_GET_NO_NEW_PRIVS => {
+                if arg2 != 0 || arg3 != 0 || arg4 != 0 || arg5 != 0 {
+                    return Err(SystemError::EINVAL);
+                }
+                Ok(pcb.no_new_privs() as usize)
+            }
+            PR_CAP_AMBIENT => {
+                if arg4 != 0 || arg5 != 0 {
+                    return Err(SystemError::EINVAL);
+                }
+                let mut cred = pcb.cred.lock();
+                if arg2 == PR_CAP_AMBIENT_CLEAR_ALL {
+                    if arg3 != 0 {
+                        return Err(SystemError::EINVAL);
+                    }
+                    cred.cap_ambient = CAPFlags::CAP_EMPTY_SET;
+                    return Ok(0);
+                }
+                let cap = cap_from_arg(arg3)?;
+                match arg2 {
+                    PR_CAP_AMBIENT_IS_SET => Ok(cred.cap_ambient.contains(cap) as usize),
+                    PR_CAP_AMBIENT_RAISE => {
+                        // 只能提升同时位于permitted和inheritable集合中的capability
+                        if !cred.cap_permitted.contains(cap) || !cred.cap_inheritable.contains(cap)
+                        {
+                            return Err(SystemError::EPERM);
+                        }
+                        cred.cap_ambient.insert(cap);
+                        Ok(0)
+                    }
+                    PR_CAP_AMBIENT_LOWER => {
+                        cred.cap_ambient.remove(cap);
+                        Ok(0)
+                    }
+                    _ => Err(SystemError::EINVAL),
+                }
+            }
+            _ => Err(SystemError::EINVAL),
+        }
+    }
+}
+
+/// 把用户传入的capability编号转换为对应的位
+fn cap_from_arg(cap: usize) -> Result<CAPFlags, SystemError> {
+    if cap > CAPFlags::CAP_LAST_CAP {
+        return Err(SystemError::EINVAL);
+    }
+    return Ok(CAPFlags::from_bits_truncate(1 << cap));
+}
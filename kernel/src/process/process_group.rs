@@ -6,6 +6,7 @@ use crate::libs::spinlock::SpinLock;
 use alloc::{
     collections::BTreeMap,
     sync::{Arc, Weak},
+    vec::Vec,
 };
 use hashbrown::HashMap;
 use system_error::SystemError;
@@ -84,6 +85,54 @@ impl ProcessGroup {
         return session;
     }
 
+    /// 判断进程组在`exiting`退出之后是否为孤儿进程组
+    ///
+    /// 如果进程组中某个进程的父进程位于同一会话中的另一个进程组，那么该进程组不是孤儿进程组
+    ///
+    /// ## 参数
+    ///
+    /// - `exiting` 正在退出的进程，判断时忽略它，以及以它为父进程的联系
+    ///
+    /// 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/kernel/exit.c#323
+    pub fn will_become_orphaned(&self, exiting: Option<&Arc<ProcessControlBlock>>) -> bool {
+        let is_exiting =
+            |pcb: &Arc<ProcessControlBlock>| exiting.is_some_and(|e| Arc::ptr_eq(e, pcb));
+        let sid = self.sid();
+        // 先释放进程组的锁，再访问父进程所在的进程组，避免同时持有两个进程组的锁
+        let processes = self
+            .process_group_inner
+            .lock()
+            .processes
+            .values()
+            .cloned()
+            .collect::<Vec<_>>();
+        for process in processes {
+            if process.is_exited() || is_exiting(&process) {
+                continue;
+            }
+            if let Some(real_parent) = process.real_parent_pcb.read().clone().upgrade() {
+                //todo 添加判断：父进程是否被忽略
+                if real_parent.pid == Pid(1) || real_parent.is_exited() || is_exiting(&real_parent)
+                {
+                    continue;
+                }
+                if real_parent.pgid() != self.pgid && real_parent.sid() == sid {
+                    return false;
+                }
+            }
+        }
+        true
+    }
+
+    /// 判断进程组中是否有处于停止状态的进程
+    pub fn has_stopped_jobs(&self) -> bool {
+        self.process_group_inner
+            .lock()
+            .processes
+            .values()
+            .any(|pcb| pcb.is_group_stopped())
+    }
+
     pub fn broadcast(&self) {
         unimplemented!("broadcast not supported yet");
     }
@@ -172,25 +221,10 @@ impl ProcessManager {
 
     // 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/kernel/exit.c#345
     pub fn is_current_pgrp_orphaned() -> bool {
-        let current_pcb = ProcessManager::current_pcb();
-        let sid = current_pcb.sid();
-        let process_group = current_pcb.process_group();
-        if let Some(pg) = process_group {
-            for process in pg.process_group_inner.lock().processes.values() {
-                if let Some(real_parent) = process.real_parent_pcb.read().clone().upgrade() {
-                    //todo 添加判断： 1.是否被忽略 2.是否已经退出（线程组是否为空）
-                    if real_parent.pid == Pid(1) || process.is_exited() {
-                        log::debug!("is_current_pgrp_orphaned: real_parent is init or exited");
-                        continue;
-                    }
-                    let real_parent_pg = real_parent.process_group().unwrap();
-                    if real_parent_pg.pgid() != pg.pgid() && real_parent_pg.sid() == sid {
-                        return false;
-                    }
-                }
-            }
+        match ProcessManager::current_pcb().process_group() {
+            Some(pg) => pg.will_become_orphaned(None),
+            None => true,
         }
-        true
     }
 }
 
@@ -1,17 +1,26 @@
+use core::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+
+use alloc::{sync::Arc, vec::Vec};
 use num_traits::FromPrimitive;
 use system_error::SystemError;
 
-use crate::time::PosixTimeSpec;
+use crate::{
+    arch::ipc::signal::{SigCode, Signal},
+    filesystem::vfs::file::FileDescriptorVec,
+    ipc::signal_types::{SigInfo, SigType},
+    mm::ucontext::UserStack,
+    time::{syscall::PosixTimeval, NSEC_PER_SEC},
+};
 
-use super::ProcessControlBlock;
+use super::{ProcessControlBlock, ProcessFlags, ProcessManager};
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[derive(Debug, Clone, Copy, Default)]
 #[repr(C)]
 pub struct RUsage {
     /// User time used
-    pub ru_utime: PosixTimeSpec,
+    pub ru_utime: PosixTimeval,
     /// System time used
-    pub ru_stime: PosixTimeSpec,
+    pub ru_stime: PosixTimeval,
 
     // 以下是linux的rusage结构体扩展
     /// Maximum resident set size
@@ -84,6 +93,64 @@ pub struct RLimit64 {
     pub rlim_max: u64,
 }
 
+/// 资源不受限制
+pub const RLIM_INFINITY: u64 = u64::MAX;
+
+/// 默认可以锁定在内存中的字节数
+pub const MLOCK_LIMIT: u64 = 8 * 1024 * 1024;
+
+/// 资源限制的数量
+pub const RLIM_NLIMITS: usize = RLimitID::Nlimits as usize;
+
+/// init进程的资源限制，其他进程在fork时从父进程继承
+///
+/// 参考 https://code.dragonos.org.cn/xref/linux-6.1.9/include/asm-generic/resource.h#72
+pub const INIT_RLIMITS: [RLimit64; RLIM_NLIMITS] = {
+    const fn rlim(cur: u64, max: u64) -> RLimit64 {
+        RLimit64 {
+            rlim_cur: cur,
+            rlim_max: max,
+        }
+    }
+    [
+        // Cpu
+        rlim(RLIM_INFINITY, RLIM_INFINITY),
+        // Fsize
+        rlim(RLIM_INFINITY, RLIM_INFINITY),
+        // Data
+        rlim(RLIM_INFINITY, RLIM_INFINITY),
+        // Stack
+        rlim(UserStack::DEFAULT_USER_STACK_SIZE as u64, RLIM_INFINITY),
+        // Core
+        rlim(0, RLIM_INFINITY),
+        // Rss
+        rlim(RLIM_INFINITY, RLIM_INFINITY),
+        // Nproc
+        rlim(RLIM_INFINITY, RLIM_INFINITY),
+        // Nofile
+        rlim(
+            FileDescriptorVec::PROCESS_MAX_FD as u64,
+            FileDescriptorVec::PROCESS_MAX_FD as u64,
+        ),
+        // Memlock
+        rlim(MLOCK_LIMIT, MLOCK_LIMIT),
+        // As
+        rlim(RLIM_INFINITY, RLIM_INFINITY),
+        // Locks
+        rlim(RLIM_INFINITY, RLIM_INFINITY),
+        // Sigpending
+        rlim(RLIM_INFINITY, RLIM_INFINITY),
+        // Msgqueue
+        rlim(819200, 819200),
+        // Nice
+        rlim(0, 0),
+        // Rtprio
+        rlim(0, 0),
+        // Rttime
+        rlim(RLIM_INFINITY, RLIM_INFINITY),
+    ]
+};
+
 /// Resource limit IDs
 ///
 /// ## Note
@@ -133,19 +200,297 @@ impl TryFrom<usize> for RLimitID {
     type Error = SystemError;
 
     fn try_from(value: usize) -> Result<Self, Self::Error> {
-        <Self as FromPrimitive>::from_usize(value).ok_or(SystemError::EINVAL)
+        <Self as FromPrimitive>::from_usize(value)
+            .filter(|id| *id != RLimitID::Nlimits)
+            .ok_or(SystemError::EINVAL)
+    }
+}
+
+/// 资源使用统计中的计数
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RUsageStats {
+    /// 用户态CPU时间（纳秒）
+    pub utime_ns: u64,
+    /// 内核态CPU时间（纳秒）
+    pub stime_ns: u64,
+    pub minflt: usize,
+    pub majflt: usize,
+    pub nvcsw: usize,
+    pub nivcsw: usize,
+}
+
+impl RUsageStats {
+    pub fn add(&mut self, other: &RUsageStats) {
+        self.utime_ns += other.utime_ns;
+        self.stime_ns += other.stime_ns;
+        self.minflt += other.minflt;
+        self.majflt += other.majflt;
+        self.nvcsw += other.nvcsw;
+        self.nivcsw += other.nivcsw;
+    }
+}
+
+impl From<RUsageStats> for RUsage {
+    fn from(stats: RUsageStats) -> Self {
+        let timeval = |ns: u64| PosixTimeval {
+            tv_sec: (ns / NSEC_PER_SEC as u64) as i64,
+            tv_usec: ((ns % NSEC_PER_SEC as u64) / 1000) as i32,
+        };
+        RUsage {
+            ru_utime: timeval(stats.utime_ns),
+            ru_stime: timeval(stats.stime_ns),
+            ru_minflt: stats.minflt,
+            ru_majflt: stats.majflt,
+            ru_nvcsw: stats.nvcsw,
+            ru_nivcsw: stats.nivcsw,
+            ..Default::default()
+        }
+    }
+}
+
+/// 线程的资源使用统计，在时钟中断、缺页异常和进程切换时更新
+#[derive(Debug, Default)]
+pub struct TaskRUsage {
+    utime_ns: AtomicU64,
+    stime_ns: AtomicU64,
+    minflt: AtomicUsize,
+    majflt: AtomicUsize,
+    nvcsw: AtomicUsize,
+    nivcsw: AtomicUsize,
+}
+
+impl TaskRUsage {
+    pub fn inc_minflt(&self) {
+        self.minflt.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn inc_majflt(&self) {
+        self.majflt.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// 记录一次进程切换
+    ///
+    /// ## 参数
+    ///
+    /// - `voluntary`: 是否是因为进程主动睡眠而发生的切换
+    pub fn inc_csw(&self, voluntary: bool) {
+        if voluntary {
+            self.nvcsw.fetch_add(1, Ordering::Relaxed);
+        } else {
+            self.nivcsw.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    pub fn stats(&self) -> RUsageStats {
+        RUsageStats {
+            utime_ns: self.utime_ns.load(Ordering::Relaxed),
+            stime_ns: self.stime_ns.load(Ordering::Relaxed),
+            minflt: self.minflt.load(Ordering::Relaxed),
+            majflt: self.majflt.load(Ordering::Relaxed),
+            nvcsw: self.nvcsw.load(Ordering::Relaxed),
+            nivcsw: self.nivcsw.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// 线程组共享的资源使用统计，保存在线程组的leader中
+#[derive(Debug, Default)]
+pub struct GroupRUsage {
+    /// 线程组的CPU时间（纳秒），用于检查RLIMIT_CPU
+    cputime_ns: u64,
+    /// 已经退出的线程的资源使用情况
+    dead_threads: RUsageStats,
+    /// 已经被回收的子进程的资源使用情况
+    children: RUsageStats,
+}
+
+/// 获取当前进程的资源限制的软限制
+///
+/// 进程管理初始化完成之前，返回[`RLIM_INFINITY`]
+pub fn rlimit(resource: RLimitID) -> u64 {
+    if !ProcessManager::initialized() {
+        return RLIM_INFINITY;
+    }
+    return ProcessManager::current_pcb().get_rlimit(resource).rlim_cur;
+}
+
+/// 返回用户态之前，发送时钟中断中因为超出RLIMIT_CPU而产生的信号
+pub fn rlimit_resume_work() {
+    let pcb = ProcessManager::current_pcb();
+    let sig = pcb.rlimit_signal.swap(Signal::INVALID, Ordering::Relaxed);
+    drop(pcb);
+    if sig != Signal::INVALID {
+        send_rlimit_signal(sig);
+    }
+}
+
+/// 向当前线程发送因为超出资源限制而产生的信号（SIGXCPU、SIGXFSZ等）
+pub fn send_rlimit_signal(sig: Signal) {
+    let pid = ProcessManager::current_pid();
+    let mut info = SigInfo::new(sig, 0, SigCode::Kernel, SigType::Kill(pid));
+    if let Err(e) = sig.send_signal_info(Some(&mut info), pid) {
+        log::warn!("failed to send {:?} to {:?}: {:?}", sig, pid, e);
     }
 }
 
 impl ProcessControlBlock {
-    /// 获取进程资源使用情况
+    /// 获取进程的资源限制
+    pub fn get_rlimit(&self, resource: RLimitID) -> RLimit64 {
+        self.rlimits.lock_irqsave()[resource as usize]
+    }
+
+    /// 设置线程组中所有线程的资源限制，调用者需要检查权限以及限制的合法性
+    pub fn set_rlimit(&self, resource: RLimitID, rlimit: RLimit64) {
+        self.for_each_thread(|thread| {
+            thread.rlimits.lock_irqsave()[resource as usize] = rlimit;
+        });
+    }
+
+    /// 对线程组中的每一个线程（包括自身）执行`f`
     ///
-    /// ## TODO
+    /// 线程列表保存在线程组的leader中，执行`f`时不持有列表的锁
+    pub fn for_each_thread<F: FnMut(&Arc<ProcessControlBlock>)>(&self, mut f: F) {
+        let Some(leader) = self.group_leader() else {
+            // 没有leader的进程（例如内核线程）只有自身
+            if let Some(pcb) = self.self_ref.upgrade() {
+                f(&pcb);
+            }
+            return;
+        };
+        let threads: Vec<Arc<ProcessControlBlock>> = leader
+            .thread_group
+            .lock_irqsave()
+            .iter()
+            .filter_map(|thread| thread.upgrade())
+            .collect();
+        f(&leader);
+        for thread in threads.iter() {
+            f(thread);
+        }
+    }
+
+    /// 获取线程组的leader，内核线程等没有leader的进程返回None
+    fn group_leader(&self) -> Option<Arc<ProcessControlBlock>> {
+        self.thread.read_irqsave().group_leader()
+    }
+
+    /// 获取当前线程的资源使用统计
+    pub fn task_rusage(&self) -> &TaskRUsage {
+        &self.rusage
+    }
+
+    /// 记录当前线程在一个时钟tick中使用的CPU时间，并检查线程组是否超出了RLIMIT_CPU
+    ///
+    /// 超出软限制时每秒发送一次SIGXCPU，超出硬限制时发送SIGKILL
     ///
-    /// 当前函数尚未实现，只是返回了一个默认的RUsage结构体
-    pub fn get_rusage(&self, _who: RUsageWho) -> Option<RUsage> {
-        let rusage = RUsage::default();
+    /// 参考 https://code.dragonos.org.cn/xref/linux-6.1.9/kernel/time/posix-cpu-timers.c#870
+    pub fn account_cputime(&self, user: bool, ns: u64) {
+        if user {
+            self.rusage.utime_ns.fetch_add(ns, Ordering::Relaxed);
+        } else {
+            self.rusage.stime_ns.fetch_add(ns, Ordering::Relaxed);
+        }
 
-        Some(rusage)
+        let leader = self.group_leader();
+        // RLIMIT_CPU针对整个线程组，统计和检查都在leader上进行
+        let group_pcb = leader.as_deref().unwrap_or(self);
+        let old = {
+            let mut group = group_pcb.group_rusage.lock_irqsave();
+            let old = group.cputime_ns;
+            group.cputime_ns += ns;
+            old
+        };
+        let old_secs = old / NSEC_PER_SEC as u64;
+        let secs = (old + ns) / NSEC_PER_SEC as u64;
+        if old_secs == secs {
+            return;
+        }
+
+        let mut rlimits = group_pcb.rlimits.lock_irqsave();
+        let limit = &mut rlimits[RLimitID::Cpu as usize];
+        // 软限制为无穷时硬限制仍然有效，因此先检查硬限制
+        let sig = if limit.rlim_max != RLIM_INFINITY && secs >= limit.rlim_max {
+            Signal::SIGKILL
+        } else if limit.rlim_cur != RLIM_INFINITY && secs >= limit.rlim_cur {
+            // 与Linux一致，每次发送SIGXCPU后把软限制增加1秒，以便一秒后再次发送
+            if limit.rlim_cur < limit.rlim_max {
+                limit.rlim_cur = secs + 1;
+            }
+            Signal::SIGXCPU
+        } else {
+            return;
+        };
+        drop(rlimits);
+
+        // 这里处于时钟中断中，信号在返回用户态之前由rlimit_resume_work发送
+        if sig == Signal::SIGKILL || self.rlimit_signal.load(Ordering::Relaxed) != Signal::SIGKILL {
+            self.rlimit_signal.store(sig, Ordering::Relaxed);
+        }
+        self.flags().insert(ProcessFlags::NOTIFY_RESUME);
+    }
+
+    /// 线程退出时，把它的资源使用情况累加到线程组中
+    pub(super) fn account_dead_thread(&self) {
+        if self.pid() == self.tgid() {
+            return;
+        }
+        if let Some(leader) = self.group_leader() {
+            leader
+                .group_rusage
+                .lock_irqsave()
+                .dead_threads
+                .add(&self.rusage.stats());
+        }
+    }
+
+    /// 回收子进程时，把子进程（包括它的子进程）的资源使用情况累加到当前进程中
+    ///
+    /// ## 返回值
+    ///
+    /// 子进程的资源使用情况
+    pub(super) fn account_reaped_child(&self, child: &ProcessControlBlock) -> RUsageStats {
+        let mut stats = child.group_stats();
+        stats.add(&child.children_stats());
+
+        let leader = self.group_leader();
+        let group_pcb = leader.as_deref().unwrap_or(self);
+        group_pcb.group_rusage.lock_irqsave().children.add(&stats);
+        return stats;
+    }
+
+    /// 线程组中所有线程（包括已经退出的线程）的资源使用情况
+    fn group_stats(&self) -> RUsageStats {
+        let leader = self.group_leader();
+        let group_pcb = leader.as_deref().unwrap_or(self);
+        let mut stats = group_pcb.group_rusage.lock_irqsave().dead_threads;
+        self.for_each_thread(|thread| {
+            // 已经退出的线程已被累加到dead_threads中，但leader不会被累加
+            if thread.pid() == thread.tgid() || !thread.is_exited() {
+                stats.add(&thread.rusage.stats());
+            }
+        });
+        return stats;
+    }
+
+    /// 已经被回收的子进程的资源使用情况
+    fn children_stats(&self) -> RUsageStats {
+        let leader = self.group_leader();
+        let group_pcb = leader.as_deref().unwrap_or(self);
+        return group_pcb.group_rusage.lock_irqsave().children;
+    }
+
+    /// 获取进程资源使用情况
+    pub fn get_rusage(&self, who: RUsageWho) -> Option<RUsage> {
+        let stats = match who {
+            RUsageWho::RUsageSelf => self.group_stats(),
+            RUsageWho::RUsageChildren => self.children_stats(),
+            RUsageWho::RUsageBoth => {
+                let mut stats = self.group_stats();
+                stats.add(&self.children_stats());
+                stats
+            }
+            RUsageWho::RusageThread => self.rusage.stats(),
+        };
+        Some(stats.into())
     }
 }
@@ -0,0 +1,657 @@
+//! seccomp系统调用过滤
+//!
+//! 进程可以通过seccomp(2)或者prctl(PR_SET_SECCOMP)进入严格模式或者过滤模式：
+//! - 严格模式下只允许调用read、write、exit和rt_sigreturn，调用其它系统调用会被SIGKILL杀死
+//! - 过滤模式下，每次进入系统调用时都会运行进程安装的classic BPF程序，根据程序的返回值决定如何处理系统调用
+//!
+//! 过滤器在fork时被子进程继承，在execve之后仍然保留。后安装的过滤器不会替换之前的过滤器，
+//! 而是与之前的过滤器一起运行，取优先级最高的结果。
+//!
+//! 参考 https://code.dragonos.org.cn/xref/linux-6.1.9/kernel/seccomp.c
+
+use core::sync::atomic::Ordering;
+
+use alloc::{sync::Arc, vec::Vec};
+use log::{info, warn};
+use system_error::SystemError;
+
+use crate::{
+    arch::{
+        interrupt::TrapFrame,
+        ipc::signal::{SigCode, Signal},
+        syscall::nr::{SYS_EXIT, SYS_READ, SYS_RT_SIGRETURN, SYS_WRITE},
+        CurrentElfArch,
+    },
+    ipc::signal_types::{SigInfo, SigType},
+    libs::elf::ElfArch,
+    syscall::{user_access::UserBufferReader, Syscall},
+};
+
+use super::{
+    capability::capable,
+    coredump::{do_coredump, WCOREFLAG},
+    cred::CAPFlags,
+    ProcessControlBlock, ProcessManager,
+};
+
+/// 未启用seccomp
+pub const SECCOMP_MODE_DISABLED: u8 = 0;
+/// 严格模式
+pub const SECCOMP_MODE_STRICT: u8 = 1;
+/// 过滤模式
+pub const SECCOMP_MODE_FILTER: u8 = 2;
+
+const SECCOMP_SET_MODE_STRICT: u32 = 0;
+const SECCOMP_SET_MODE_FILTER: u32 = 1;
+const SECCOMP_GET_ACTION_AVAIL: u32 = 2;
+
+const SECCOMP_FILTER_FLAG_LOG: u32 = 1 << 1;
+
+const SECCOMP_RET_KILL_PROCESS: u32 = 0x80000000;
+const SECCOMP_RET_KILL_THREAD: u32 = 0x00000000;
+const SECCOMP_RET_TRAP: u32 = 0x00030000;
+const SECCOMP_RET_ERRNO: u32 = 0x00050000;
+const SECCOMP_RET_USER_NOTIF: u32 = 0x7fc00000;
+const SECCOMP_RET_TRACE: u32 = 0x7ff00000;
+const SECCOMP_RET_LOG: u32 = 0x7ffc0000;
+const SECCOMP_RET_ALLOW: u32 = 0x7fff0000;
+
+const SECCOMP_RET_ACTION_FULL: u32 = 0xffff0000;
+const SECCOMP_RET_DATA: u32 = 0x0000ffff;
+
+/// 单个过滤器的最大指令数
+const BPF_MAXINSNS: usize = 4096;
+/// 一个进程所有过滤器的指令数之和的上限（每个过滤器额外计4条指令）
+const MAX_INSNS_PER_PATH: usize = 1 << 15;
+/// BPF程序中暂存区的大小（以u32为单位）
+const BPF_MEMWORDS: u32 = 16;
+
+const __AUDIT_ARCH_64BIT: u32 = 0x80000000;
+const __AUDIT_ARCH_LE: u32 = 0x40000000;
+/// 当前架构的AUDIT_ARCH_*，目前支持的架构均为64位小端
+const AUDIT_ARCH: u32 = CurrentElfArch::ELF_MACHINE as u32 | __AUDIT_ARCH_64BIT | __AUDIT_ARCH_LE;
+
+// classic BPF的指令编码
+const BPF_LD: u16 = 0x00;
+const BPF_LDX: u16 = 0x01;
+const BPF_ST: u16 = 0x02;
+const BPF_STX: u16 = 0x03;
+const BPF_ALU: u16 = 0x04;
+const BPF_JMP: u16 = 0x05;
+const BPF_RET: u16 = 0x06;
+const BPF_MISC: u16 = 0x07;
+
+const BPF_W: u16 = 0x00;
+
+const BPF_IMM: u16 = 0x00;
+const BPF_ABS: u16 = 0x20;
+const BPF_MEM: u16 = 0x60;
+const BPF_LEN: u16 = 0x80;
+
+const BPF_ADD: u16 = 0x00;
+const BPF_SUB: u16 = 0x10;
+const BPF_MUL: u16 = 0x20;
+const BPF_DIV: u16 = 0x30;
+const BPF_OR: u16 = 0x40;
+const BPF_AND: u16 = 0x50;
+const BPF_LSH: u16 = 0x60;
+const BPF_RSH: u16 = 0x70;
+const BPF_NEG: u16 = 0x80;
+const BPF_MOD: u16 = 0x90;
+const BPF_XOR: u16 = 0xa0;
+
+const BPF_JA: u16 = 0x00;
+const BPF_JEQ: u16 = 0x10;
+const BPF_JGT: u16 = 0x20;
+const BPF_JGE: u16 = 0x30;
+const BPF_JSET: u16 = 0x40;
+
+const BPF_K: u16 = 0x00;
+const BPF_X: u16 = 0x08;
+const BPF_A: u16 = 0x10;
+
+const BPF_TAX: u16 = 0x00;
+const BPF_TXA: u16 = 0x80;
+
+/// classic BPF指令，与Linux的`struct sock_filter`一致
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct SockFilter {
+    pub code: u16,
+    pub jt: u8,
+    pub jf: u8,
+    pub k: u32,
+}
+
+/// 用户态传入的BPF程序，与Linux的`struct sock_fprog`一致
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct SockFprog {
+    pub len: u16,
+    pub filter: *const SockFilter,
+}
+
+/// 传递给BPF程序的系统调用信息，与Linux的`struct seccomp_data`一致
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+struct SeccompData {
+    nr: i32,
+    arch: u32,
+    instruction_pointer: u64,
+    args: [u64; 6],
+}
+
+impl SeccompData {
+    /// 以u32为单位读取，`offset`需要事先经过检查
+    fn load_word(&self, offset: u32) -> u32 {
+        let words = unsafe {
+            core::slice::from_raw_parts(
+                self as *const Self as *const u32,
+                core::mem::size_of::<Self>() / 4,
+            )
+        };
+        words[offset as usize / 4]
+    }
+}
+
+const SECCOMP_DATA_SIZE: u32 = core::mem::size_of::<SeccompData>() as u32;
+
+/// 一个已经通过检查的seccomp过滤器
+#[derive(Debug)]
+pub struct SeccompFilter {
+    prog: Vec<SockFilter>,
+    /// 是否记录除ALLOW以外的所有动作
+    log: bool,
+    /// 在此之前安装的过滤器
+    prev: Option<Arc<SeccompFilter>>,
+}
+
+impl SeccompFilter {
+    /// 检查用户传入的BPF程序，并转换为内核能直接执行的形式
+    ///
+    /// 参考 https://code.dragonos.org.cn/xref/linux-6.1.9/net/core/filter.c#1043 和
+    /// https://code.dragonos.org.cn/xref/linux-6.1.9/kernel/seccomp.c#278
+    fn check(mut prog: Vec<SockFilter>) -> Result<Vec<SockFilter>, SystemError> {
+        let len = prog.len();
+        if len == 0 || len > BPF_MAXINSNS {
+            return Err(SystemError::EINVAL);
+        }
+        for (pc, insn) in prog.iter_mut().enumerate() {
+            let code = insn.code;
+            match code {
+                c if c == BPF_LD | BPF_W | BPF_ABS => {
+                    if insn.k >= SECCOMP_DATA_SIZE || insn.k & 3 != 0 {
+                        return Err(SystemError::EINVAL);
+                    }
+                }
+                // seccomp_data的长度是固定的，直接转换为立即数
+                c if c == BPF_LD | BPF_W | BPF_LEN => {
+                    insn.code = BPF_LD | BPF_IMM;
+                    insn.k = SECCOMP_DATA_SIZE;
+                }
+                c if c == BPF_LDX | BPF_W | BPF_LEN => {
+                    insn.code = BPF_LDX | BPF_IMM;
+                    insn.k = SECCOMP_DATA_SIZE;
+                }
+                c if c == BPF_LD | BPF_IMM || c == BPF_LDX | BPF_IMM => {}
+                c if c == BPF_LD | BPF_MEM
+                    || c == BPF_LDX | BPF_MEM
+                    || c == BPF_ST
+                    || c == BPF_STX =>
+                {
+                    if insn.k >= BPF_MEMWORDS {
+                        return Err(SystemError::EINVAL);
+                    }
+                }
+                c if c == BPF_ALU | BPF_NEG => {}
+                c if c & 0x07 == BPF_ALU => {
+                    let op = c & 0xf0;
+                    if !matches!(
+                        op,
+                        BPF_ADD
+                            | BPF_SUB
+                            | BPF_MUL
+                            | BPF_DIV
+                            | BPF_MOD
+                            | BPF_AND
+                            | BPF_OR
+                            | BPF_XOR
+                            | BPF_LSH
+                            | BPF_RSH
+                    ) || c & !0xf8 != BPF_ALU
+                    {
+                        return Err(SystemError::EINVAL);
+                    }
+                    if c & BPF_X == BPF_K {
+                        if (op == BPF_DIV || op == BPF_MOD) && insn.k == 0 {
+                            return Err(SystemError::EINVAL);
+                        }
+                        if (op == BPF_LSH || op == BPF_RSH) && insn.k >= 32 {
+                            return Err(SystemError::EINVAL);
+                        }
+                    }
+                }
+                c if c == BPF_JMP | BPF_JA => {
+                    if insn.k as usize >= len - pc - 1 {
+                        return Err(SystemError::EINVAL);
+                    }
+                }
+                c if c & 0x07 == BPF_JMP => {
+                    if !matches!(c & 0xf0, BPF_JEQ | BPF_JGT | BPF_JGE | BPF_JSET)
+                        || c & !0xf8 != BPF_JMP
+                    {
+                        return Err(SystemError::EINVAL);
+                    }
+                    if pc + insn.jt as usize + 1 >= len || pc + insn.jf as usize + 1 >= len {
+                        return Err(SystemError::EINVAL);
+                    }
+                }
+                c if c == BPF_RET | BPF_K || c == BPF_RET | BPF_A => {}
+                c if c == BPF_MISC | BPF_TAX || c == BPF_MISC | BPF_TXA => {}
+                _ => return Err(SystemError::EINVAL),
+            }
+        }
+        // 程序必须以返回指令结束
+        if prog[len - 1].code & 0x07 != BPF_RET {
+            return Err(SystemError::EINVAL);
+        }
+        return Ok(prog);
+    }
+
+    /// 对系统调用运行BPF程序，返回程序的返回值
+    ///
+    /// 程序在安装时已经检查过，所有的跳转都不会越界，并且一定会执行到返回指令
+    fn run(&self, data: &SeccompData) -> u32 {
+        let mut a: u32 = 0;
+        let mut x: u32 = 0;
+        let mut mem = [0u32; BPF_MEMWORDS as usize];
+        let mut pc = 0;
+        loop {
+            let insn = &self.prog[pc];
+            pc += 1;
+            let code = insn.code;
+            let src = if code & BPF_X == BPF_X { x } else { insn.k };
+            match code & 0x07 {
+                BPF_LD => match code & 0xe0 {
+                    BPF_ABS => a = data.load_word(insn.k),
+                    BPF_MEM => a = mem[insn.k as usize],
+                    _ => a = insn.k,
+                },
+                BPF_LDX => match code & 0xe0 {
+                    BPF_MEM => x = mem[insn.k as usize],
+                    _ => x = insn.k,
+                },
+                BPF_ST => mem[insn.k as usize] = a,
+                BPF_STX => mem[insn.k as usize] = x,
+                BPF_ALU => match code & 0xf0 {
+                    BPF_ADD => a = a.wrapping_add(src),
+                    BPF_SUB => a = a.wrapping_sub(src),
+                    BPF_MUL => a = a.wrapping_mul(src),
+                    BPF_DIV => {
+                        // 除以0时，程序返回0
+                        if src == 0 {
+                            return 0;
+                        }
+                        a /= src;
+                    }
+                    BPF_MOD => {
+                        if src == 0 {
+                            return 0;
+                        }
+                        a %= src;
+                    }
+                    BPF_OR => a |= src,
+                    BPF_AND => a &= src,
+                    BPF_XOR => a ^= src,
+                    BPF_LSH => a = a.wrapping_shl(src),
+                    BPF_RSH => a = a.wrapping_shr(src),
+                    _ => a = a.wrapping_neg(),
+                },
+                BPF_JMP => {
+                    let taken = match code & 0xf0 {
+                        BPF_JA => {
+                            pc += insn.k as usize;
+                            continue;
+                        }
+                        BPF_JEQ => a == src,
+                        BPF_JGT => a > src,
+                        BPF_JGE => a >= src,
+                        _ => a & src != 0,
+                    };
+                    let off = if taken { insn.jt } else { insn.jf };
+                    pc += off as usize;
+                }
+                BPF_RET => {
+                    return if code & BPF_A == BPF_A { a } else { insn.k };
+                }
+                _ => {
+                    if code & 0xf8 == BPF_TXA {
+                        a = x;
+                    } else {
+                        x = a;
+                    }
+                }
+            }
+        }
+    }
+
+    /// 依次运行所有过滤器，返回优先级最高的结果，以及产生该结果的过滤器
+    fn run_all(self: &Arc<Self>, data: &SeccompData) -> (u32, &Arc<SeccompFilter>) {
+        let mut ret = SECCOMP_RET_ALLOW;
+        let mut matched = self;
+        let mut filter = Some(self);
+        while let Some(f) = filter {
+            let cur = f.run(data);
+            // 动作值按有符号数比较，越小的优先级越高
+            if ((cur & SECCOMP_RET_ACTION_FULL) as i32) < ((ret & SECCOMP_RET_ACTION_FULL) as i32) {
+                ret = cur;
+                matched = f;
+            }
+            filter = f.prev.as_ref();
+        }
+        (ret, matched)
+    }
+
+    fn total_insns(&self) -> usize {
+        let mut total = 0;
+        let mut filter = Some(self);
+        while let Some(f) = filter {
+            total += f.prog.len() + 4;
+            filter = f.prev.as_deref();
+        }
+        total
+    }
+}
+
+/// 进程的seccomp状态
+#[derive(Debug, Clone, Default)]
+pub struct Seccomp {
+    mode: u8,
+    filter: Option<Arc<SeccompFilter>>,
+}
+
+impl Seccomp {
+    pub fn mode(&self) -> u8 {
+        self.mode
+    }
+}
+
+impl ProcessControlBlock {
+    /// 获取进程的seccomp状态
+    pub fn seccomp(&self) -> Seccomp {
+        self.seccomp.lock_irqsave().clone()
+    }
+
+    /// 获取进程的seccomp模式，不需要加锁
+    ///
+    /// 模式只会由进程自身从`SECCOMP_MODE_DISABLED`切换为其它模式，并且在过滤器安装完成之后才会更新
+    pub fn seccomp_mode(&self) -> u8 {
+        self.seccomp_mode.load(Ordering::Acquire)
+    }
+}
+
+/// 判断动作值是否受支持
+fn action_available(action: u32) -> bool {
+    matches!(
+        action,
+        SECCOMP_RET_KILL_PROCESS
+            | SECCOMP_RET_KILL_THREAD
+            | SECCOMP_RET_TRAP
+            | SECCOMP_RET_ERRNO
+            | SECCOMP_RET_LOG
+            | SECCOMP_RET_ALLOW
+    )
+}
+
+fn instruction_pointer(frame: &TrapFrame) -> u64 {
+    #[cfg(target_arch = "x86_64")]
+    return frame.rip;
+    #[cfg(target_arch = "riscv64")]
+    return frame.epc as u64;
+    #[cfg(target_arch = "loongarch64")]
+    return frame.csr_era as u64;
+}
+
+/// 以SIGSYS终止当前线程组，并生成core dump
+fn seccomp_kill_process(frame: &TrapFrame) -> ! {
+    let current = ProcessManager::current_pcb();
+    current.for_each_thread(|thread| {
+        if !Arc::ptr_eq(thread, &current) {
+            let _ = Signal::SIGKILL.send_signal_info(None, thread.pid());
+        }
+    });
+    drop(current);
+    let mut exit_code = Signal::SIGSYS as usize;
+    if do_coredump(Signal::SIGSYS, frame) {
+        exit_code |= WCOREFLAG;
+    }
+    ProcessManager::exit(exit_code);
+}
+
+/// 在系统调用入口处进行seccomp检查
+///
+/// ## 参数
+///
+/// - `syscall_num` 系统调用号
+/// - `args` 系统调用参数
+/// - `frame` 用户态进入内核时保存的寄存器
+///
+/// ## 返回值
+///
+/// - `None` 允许执行该系统调用
+/// - `Some(r)` 跳过该系统调用，并以`r`作为它的返回值
+///
+/// 参考 https://code.dragonos.org.cn/xref/linux-6.1.9/kernel/seccomp.c#1185
+pub fn secure_computing(
+    syscall_num: usize,
+    args: &[usize],
+    frame: &TrapFrame,
+) -> Option<Result<usize, SystemError>> {
+    let current = ProcessManager::current_pcb();
+    // 绝大多数进程没有启用seccomp，此时不需要获取锁
+    match current.seccomp_mode() {
+        SECCOMP_MODE_STRICT => {
+            if matches!(
+                syscall_num,
+                SYS_READ | SYS_WRITE | SYS_EXIT | SYS_RT_SIGRETURN
+            ) {
+                return None;
+            }
+            warn!(
+                "seccomp: pid {:?} killed in strict mode, syscall {}",
+                current.pid(),
+                syscall_num
+            );
+            drop(current);
+            ProcessManager::exit(Signal::SIGKILL as usize);
+        }
+        SECCOMP_MODE_FILTER => {}
+        _ => return None,
+    }
+    let filter = current.seccomp.lock_irqsave().filter.clone()?;
+
+    let mut data = SeccompData {
+        nr: syscall_num as i32,
+        arch: AUDIT_ARCH,
+        instruction_pointer: instruction_pointer(frame),
+        args: [0; 6],
+    };
+    for (d, a) in data.args.iter_mut().zip(args.iter()) {
+        *d = *a as u64;
+    }
+
+    let (ret, matched) = filter.run_all(&data);
+    let action = ret & SECCOMP_RET_ACTION_FULL;
+    let errno = (ret & SECCOMP_RET_DATA) as i32;
+    if matched.log && action != SECCOMP_RET_ALLOW && action != SECCOMP_RET_LOG {
+        info!(
+            "seccomp: pid {:?} syscall {} action {:#x}",
+            current.pid(),
+            syscall_num,
+            action
+        );
+    }
+    match action {
+        SECCOMP_RET_ALLOW => None,
+        SECCOMP_RET_LOG => {
+            info!(
+                "seccomp: pid {:?} syscall {} logged",
+                current.pid(),
+                syscall_num
+            );
+            None
+        }
+        SECCOMP_RET_ERRNO => {
+            if errno == 0 {
+                return Some(Ok(0));
+            }
+            // 错误码最大为MAX_ERRNO
+            let errno = errno.min(SystemError::MAXERRNO as i32);
+            Some(Err(
+                SystemError::from_posix_errno(-errno).unwrap_or(SystemError::EPERM)
+            ))
+        }
+        SECCOMP_RET_TRAP => {
+            let pid = current.pid();
+            let mut info = SigInfo::new(Signal::SIGSYS, errno, SigCode::Kernel, SigType::Kill(pid));
+            if let Err(e) = Signal::SIGSYS.send_signal_info(Some(&mut info), pid) {
+                warn!("seccomp: failed to send SIGSYS to {:?}: {:?}", pid, e);
+            }
+            Some(Err(SystemError::ENOSYS))
+        }
+        // 没有实现ptrace和用户态通知，与Linux在没有tracer或listener时的行为一致
+        SECCOMP_RET_TRACE | SECCOMP_RET_USER_NOTIF => Some(Err(SystemError::ENOSYS)),
+        SECCOMP_RET_KILL_THREAD => {
+            warn!(
+                "seccomp: pid {:?} killed, syscall {}",
+                current.pid(),
+                syscall_num
+            );
+            drop(current);
+            ProcessManager::exit(Signal::SIGSYS as usize);
+        }
+        _ => {
+            warn!(
+                "seccomp: process {:?} killed, syscall {}",
+                current.tgid(),
+                syscall_num
+            );
+            drop(current);
+            seccomp_kill_process(frame);
+        }
+    }
+}
+
+/// 让当前进程进入严格模式
+fn seccomp_set_mode_strict() -> Result<usize, SystemError> {
+    let pcb = ProcessManager::current_pcb();
+    let mut seccomp = pcb.seccomp.lock_irqsave();
+    if seccomp.mode != SECCOMP_MODE_DISABLED && seccomp.mode != SECCOMP_MODE_STRICT {
+        return Err(SystemError::EINVAL);
+    }
+    seccomp.mode = SECCOMP_MODE_STRICT;
+    pcb.seccomp_mode
+        .store(SECCOMP_MODE_STRICT, Ordering::Release);
+    return Ok(0);
+}
+
+/// 为当前进程安装一个新的过滤器
+///
+/// ## 参数
+///
+/// - `flags` `SECCOMP_FILTER_FLAG_*`，目前只支持`SECCOMP_FILTER_FLAG_LOG`
+/// - `fprog` 用户态的`struct sock_fprog`
+fn seccomp_set_mode_filter(flags: u32, fprog: *const SockFprog) -> Result<usize, SystemError> {
+    if flags & !SECCOMP_FILTER_FLAG_LOG != 0 {
+        return Err(SystemError::EINVAL);
+    }
+    let pcb = ProcessManager::current_pcb();
+    // 防止没有特权的进程通过过滤器影响set-user-ID程序的行为
+    if !pcb.no_new_privs() && !capable(CAPFlags::CAP_SYS_ADMIN) {
+        return Err(SystemError::EACCES);
+    }
+
+    let reader = UserBufferReader::new(fprog, core::mem::size_of::<SockFprog>(), true)?;
+    let fprog = *reader.read_one_from_user::<SockFprog>(0)?;
+    let len = fprog.len as usize;
+    if len == 0 || len > BPF_MAXINSNS {
+        return Err(SystemError::EINVAL);
+    }
+    let reader =
+        UserBufferReader::new(fprog.filter, len * core::mem::size_of::<SockFilter>(), true)?;
+    let prog = SeccompFilter::check(reader.read_from_user::<SockFilter>(0)?.to_vec())?;
+
+    let mut seccomp = pcb.seccomp.lock_irqsave();
+    if seccomp.mode != SECCOMP_MODE_DISABLED && seccomp.mode != SECCOMP_MODE_FILTER {
+        return Err(SystemError::EINVAL);
+    }
+    let filter = SeccompFilter {
+        prog,
+        log: flags & SECCOMP_FILTER_FLAG_LOG != 0,
+        prev: seccomp.filter.clone(),
+    };
+    if filter.total_insns() > MAX_INSNS_PER_PATH {
+        return Err(SystemError::ENOMEM);
+    }
+    seccomp.filter = Some(Arc::new(filter));
+    seccomp.mode = SECCOMP_MODE_FILTER;
+    pcb.seccomp_mode
+        .store(SECCOMP_MODE_FILTER, Ordering::Release);
+    return Ok(0);
+}
+
+/// prctl(PR_GET_SECCOMP)
+pub fn prctl_get_seccomp() -> Result<usize, SystemError> {
+    Ok(ProcessManager::current_pcb().seccomp_mode() as usize)
+}
+
+/// prctl(PR_SET_SECCOMP)
+///
+/// ## 参数
+///
+/// - `mode` `SECCOMP_MODE_STRICT`或者`SECCOMP_MODE_FILTER`
+/// - `filter` 过滤模式下的`struct sock_fprog`
+pub fn prctl_set_seccomp(mode: usize, filter: usize) -> Result<usize, SystemError> {
+    match mode {
+        m if m == SECCOMP_MODE_STRICT as usize => seccomp_set_mode_strict(),
+        m if m == SECCOMP_MODE_FILTER as usize => {
+            seccomp_set_mode_filter(0, filter as *const SockFprog)
+        }
+        _ => Err(SystemError::EINVAL),
+    }
+}
+
+impl Syscall {
+    /// # seccomp系统调用
+    ///
+    /// ## 参数
+    ///
+    /// - `op` 操作类型，取值为`SECCOMP_SET_MODE_*`或`SECCOMP_GET_ACTION_AVAIL`
+    /// - `flags` 操作的标志
+    /// - `uargs` 操作的参数，含义由`op`决定
+    pub fn seccomp(op: u32, flags: u32, uargs: usize) -> Result<usize, SystemError> {
+        match op {
+            SECCOMP_SET_MODE_STRICT => {
+                if flags != 0 || uargs != 0 {
+                    return Err(SystemError::EINVAL);
+                }
+                seccomp_set_mode_strict()
+            }
+            SECCOMP_SET_MODE_FILTER => seccomp_set_mode_filter(flags, uargs as *const SockFprog),
+            SECCOMP_GET_ACTION_AVAIL => {
+                if flags != 0 {
+                    return Err(SystemError::EINVAL);
+                }
+                let reader =
+                    UserBufferReader::new(uargs as *const u32, core::mem::size_of::<u32>(), true)?;
+                let action = *reader.read_one_from_user::<u32>(0)?;
+                if action_available(action) {
+                    Ok(0)
+                } else {
+                    Err(SystemError::EOPNOTSUPP_OR_ENOTSUP)
+                }
+            }
+            _ => Err(SystemError::EINVAL),
+        }
+    }
+}
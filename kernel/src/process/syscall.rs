@@ -10,30 +10,34 @@ use log::error;
 use system_error::SystemError;
 
 use super::{
-    abi::WaitOption,
-    cred::{Kgid, Kuid},
+    abi::{AtType, WaitOption},
+    capability::exec_update_creds,
+    cred::{CAPFlags, Kgid, Kuid, NGROUPS_MAX},
     exec::{load_binary_file, ExecParam, ExecParamFlags},
-    exit::kernel_wait4,
+    exit::{kernel_wait4, kernel_waitid, WaitIdSigInfo, P_ALL, P_PGID, P_PID, P_PIDFD},
     fork::{CloneFlags, KernelCloneArgs},
+    pidfd::pidfd_get_pid,
+    prctl::{SUID_DUMP_DISABLE, SUID_DUMP_USER},
     resource::{RLimit64, RLimitID, RUsage, RUsageWho},
     KernelStack, Pgid, Pid, ProcessManager,
 };
 use crate::{
-    arch::{interrupt::TrapFrame, CurrentIrqArch, MMArch},
+    arch::{interrupt::TrapFrame, CurrentIrqArch},
     exception::InterruptArch,
     filesystem::{
         procfs::procfs_register_pid,
         vfs::{file::FileDescriptorVec, MAX_PATHLEN},
     },
+    ipc::syscall::sys_kill::PidConverter,
     libs::rand::rand_bytes,
-    mm::{
-        ucontext::{AddressSpace, UserStack},
-        verify_area, MemoryManagementArch, VirtAddr,
-    },
+    mm::{ucontext::AddressSpace, verify_area, VirtAddr},
+    namespaces::namespace::USER_NS,
     process::ProcessControlBlock,
     sched::completion::Completion,
     syscall::{
-        user_access::{check_and_clone_cstr, check_and_clone_cstr_array, UserBufferWriter},
+        user_access::{
+            check_and_clone_cstr, check_and_clone_cstr_array, UserBufferReader, UserBufferWriter,
+        },
         Syscall,
     },
 };
@@ -164,6 +168,9 @@ impl Syscall {
         let address_space = AddressSpace::new(true).expect("Failed to create new address space");
         // debug!("to load binary file");
         let mut param = ExecParam::new(path.as_str(), address_space.clone(), ExecParamFlags::EXEC)?;
+        // 脚本和binfmt_misc加载器会修改参数列表，因此要在加载之前设置
+        param.init_info_mut().args = argv;
+        param.init_info_mut().envs = envp;
         let old_vm = do_execve_switch_user_vm(address_space.clone());
 
         // 加载可执行文件
@@ -174,9 +181,21 @@ impl Syscall {
         })?;
 
         // debug!("load binary file done");
-        // debug!("argv: {:?}, envp: {:?}", argv, envp);
-        param.init_info_mut().args = argv;
-        param.init_info_mut().envs = envp;
+        // 已经无法回退到原来的程序，根据可执行文件计算新的凭证
+        let pcb = ProcessManager::current_pcb();
+        let inode = param.file_mut().inode();
+        let secure = exec_update_creds(&pcb, &inode);
+        // 以特权运行的程序默认不生成core dump
+        pcb.set_dumpable(if secure {
+            SUID_DUMP_DISABLE
+        } else {
+            SUID_DUMP_USER
+        });
+        param
+            .init_info_mut()
+            .auxv
+            .insert(AtType::Secure as u8, secure as usize);
+
         // // 生成16字节随机数
         param.init_info_mut().rand_num = rand_bytes::<16>();
 
@@ -200,7 +219,15 @@ impl Syscall {
                 )
                 .expect("Failed to push proc_init_info to user stack")
         };
-        address_space.write().user_stack = Some(ustack_message);
+        let mut address_space_guard = address_space.write();
+        address_space_guard.user_stack = Some(ustack_message);
+        address_space_guard.saved_auxv = param
+            .init_info()
+            .auxv
+            .iter()
+            .map(|(&k, &v)| (k as usize, v))
+            .collect();
+        drop(address_space_guard);
 
         Self::arch_do_execve(regs, &param, &load_result, user_sp, argv_ptr)
     }
@@ -242,6 +269,92 @@ impl Syscall {
         return Ok(r);
     }
 
+    /// # waitid系统调用
+    ///
+    /// 等待子进程的状态发生变化
+    ///
+    /// ## 参数
+    ///
+    /// - `idtype` 取值为`P_ALL`、`P_PID`、`P_PGID`或`P_PIDFD`
+    /// - `id` 与`idtype`对应的pid、pgid或者pidfd
+    /// - `infop` 用于返回子进程状态的siginfo，可以为NULL
+    /// - `options` 等待选项，至少包含`WEXITED`、`WSTOPPED`、`WCONTINUED`之一
+    /// - `rusage` 用于返回子进程的资源使用情况，可以为NULL
+    pub fn waitid(
+        idtype: u32,
+        id: i32,
+        infop: *mut WaitIdSigInfo,
+        options: i32,
+        rusage: *mut c_void,
+    ) -> Result<usize, SystemError> {
+        let mut options = WaitOption::from_bits(options as u32).ok_or(SystemError::EINVAL)?;
+        if (options & (WaitOption::WEXITED | WaitOption::WSTOPPED | WaitOption::WCONTINUED))
+            .is_empty()
+        {
+            return Err(SystemError::EINVAL);
+        }
+
+        let converter = match idtype {
+            P_ALL => PidConverter::All,
+            P_PID => {
+                if id <= 0 {
+                    return Err(SystemError::EINVAL);
+                }
+                PidConverter::Pid(Pid::new(id as usize))
+            }
+            P_PGID => {
+                if id < 0 {
+                    return Err(SystemError::EINVAL);
+                }
+                if id == 0 {
+                    PidConverter::Pgid(ProcessManager::current_pcb().pgid())
+                } else {
+                    PidConverter::Pgid(Pgid::new(id as usize))
+                }
+            }
+            P_PIDFD => {
+                if id < 0 {
+                    return Err(SystemError::EINVAL);
+                }
+                let (pid, nonblock) = pidfd_get_pid(id)?;
+                // 非阻塞的pidfd相当于设置了WNOHANG
+                if nonblock {
+                    options.insert(WaitOption::WNOHANG);
+                }
+                PidConverter::Pid(pid)
+            }
+            _ => return Err(SystemError::EINVAL),
+        };
+
+        let infop_buf = if infop.is_null() {
+            None
+        } else {
+            Some(UserBufferWriter::new(
+                infop,
+                core::mem::size_of::<WaitIdSigInfo>(),
+                true,
+            )?)
+        };
+
+        let mut tmp_rusage = if rusage.is_null() {
+            None
+        } else {
+            Some(RUsage::default())
+        };
+
+        let r = kernel_waitid(converter, infop_buf, options, tmp_rusage.as_mut())?;
+
+        if !rusage.is_null() {
+            let mut rusage_buf = UserBufferWriter::new::<RUsage>(
+                rusage as *mut RUsage,
+                core::mem::size_of::<RUsage>(),
+                true,
+            )?;
+            rusage_buf.copy_one_to_user(&tmp_rusage.unwrap(), 0)?;
+        }
+        return Ok(r);
+    }
+
     /// # 退出进程
     ///
     /// ## 参数
@@ -347,6 +460,27 @@ impl Syscall {
         return Ok(current_pcb.basic().ppid());
     }
 
+    /// # clone3系统调用
+    ///
+    /// 使用可扩展的`struct clone_args`创建子进程
+    ///
+    /// ## 参数
+    ///
+    /// - `uargs` 用户空间的`struct clone_args`的地址
+    /// - `size` 用户空间的`struct clone_args`的大小
+    pub fn clone3(
+        current_trapframe: &TrapFrame,
+        uargs: *const u8,
+        size: usize,
+    ) -> Result<usize, SystemError> {
+        let clone_args = KernelCloneArgs::copy_from_user(uargs, size)?;
+        // todo: 支持cgroup v2之后，实现CLONE_INTO_CGROUP
+        if clone_args.flags.contains(CloneFlags::CLONE_INTO_CGROUP) {
+            return Err(SystemError::EOPNOTSUPP_OR_ENOTSUP);
+        }
+        return Self::clone(current_trapframe, clone_args);
+    }
+
     pub fn clone(
         current_trapframe: &TrapFrame,
         clone_args: KernelCloneArgs,
@@ -355,12 +489,6 @@ impl Syscall {
 
         let vfork = Arc::new(Completion::new());
 
-        if flags.contains(CloneFlags::CLONE_PIDFD)
-            && flags.contains(CloneFlags::CLONE_PARENT_SETTID)
-        {
-            return Err(SystemError::EINVAL);
-        }
-
         let current_pcb = ProcessManager::current_pcb();
         let new_kstack = KernelStack::new()?;
         let name = current_pcb.basic().name().to_string();
@@ -443,16 +571,20 @@ impl Syscall {
     pub fn setuid(uid: usize) -> Result<usize, SystemError> {
         let pcb = ProcessManager::current_pcb();
         let mut guard = pcb.cred.lock();
+        let old = guard.clone();
 
-        if guard.uid.data() == 0 {
+        if guard.has_capability(CAPFlags::CAP_SETUID) {
             guard.setuid(uid);
             guard.seteuid(uid);
             guard.setsuid(uid);
+            guard.setfsuid(uid);
         } else if uid == guard.uid.data() || uid == guard.suid.data() {
             guard.seteuid(uid);
+            guard.setfsuid(uid);
         } else {
             return Err(SystemError::EPERM);
         }
+        guard.fix_setuid_caps(&old);
 
         return Ok(0);
     }
@@ -461,7 +593,7 @@ impl Syscall {
         let pcb = ProcessManager::current_pcb();
         let mut guard = pcb.cred.lock();
 
-        if guard.egid.data() == 0 {
+        if guard.has_capability(CAPFlags::CAP_SETGID) {
             guard.setgid(gid);
             guard.setegid(gid);
             guard.setsgid(gid);
@@ -479,17 +611,25 @@ impl Syscall {
     pub fn seteuid(euid: usize) -> Result<usize, SystemError> {
         let pcb = ProcessManager::current_pcb();
         let mut guard = pcb.cred.lock();
+        let old = guard.clone();
 
         if euid == usize::MAX || (euid == guard.euid.data() && euid == guard.fsuid.data()) {
             return Ok(0);
         }
 
-        if euid != usize::MAX {
-            guard.seteuid(euid);
+        // 没有CAP_SETUID时，只能把euid设置为uid、euid或suid之一
+        let euid = Kuid::new(euid);
+        if !guard.has_capability(CAPFlags::CAP_SETUID)
+            && euid != guard.uid
+            && euid != guard.euid
+            && euid != guard.suid
+        {
+            return Err(SystemError::EPERM);
         }
 
-        let euid = guard.euid.data();
-        guard.setfsuid(euid);
+        guard.seteuid(euid.data());
+        guard.setfsuid(euid.data());
+        guard.fix_setuid_caps(&old);
 
         return Ok(0);
     }
@@ -502,12 +642,18 @@ impl Syscall {
             return Ok(0);
         }
 
-        if egid != usize::MAX {
-            guard.setegid(egid);
+        // 没有CAP_SETGID时，只能把egid设置为gid、egid或sgid之一
+        let egid = Kgid::new(egid);
+        if !guard.has_capability(CAPFlags::CAP_SETGID)
+            && egid != guard.gid
+            && egid != guard.egid
+            && egid != guard.sgid
+        {
+            return Err(SystemError::EPERM);
         }
 
-        let egid = guard.egid.data();
-        guard.setfsgid(egid);
+        guard.setegid(egid.data());
+        guard.setfsgid(egid.data());
 
         return Ok(0);
     }
@@ -517,13 +663,19 @@ impl Syscall {
 
         let pcb = ProcessManager::current_pcb();
         let mut guard = pcb.cred.lock();
-        let old_fsuid = guard.fsuid;
+        let old = guard.clone();
 
-        if fsuid == guard.uid || fsuid == guard.euid || fsuid == guard.suid {
+        if fsuid == guard.uid
+            || fsuid == guard.euid
+            || fsuid == guard.suid
+            || fsuid == guard.fsuid
+            || guard.has_capability(CAPFlags::CAP_SETUID)
+        {
             guard.setfsuid(fsuid.data());
+            guard.fix_setuid_caps(&old);
         }
 
-        Ok(old_fsuid.data())
+        Ok(old.fsuid.data())
     }
 
     pub fn setfsgid(fsgid: usize) -> Result<usize, SystemError> {
@@ -533,13 +685,94 @@ impl Syscall {
         let mut guard = pcb.cred.lock();
         let old_fsgid = guard.fsgid;
 
-        if fsgid == guard.gid || fsgid == guard.egid || fsgid == guard.sgid {
+        if fsgid == guard.gid
+            || fsgid == guard.egid
+            || fsgid == guard.sgid
+            || fsgid == guard.fsgid
+            || guard.has_capability(CAPFlags::CAP_SETGID)
+        {
             guard.setfsgid(fsgid.data());
         }
 
         Ok(old_fsgid.data())
     }
 
+    /// # getgroups系统调用
+    ///
+    /// 获取当前进程的附加组
+    ///
+    /// ## 参数
+    ///
+    /// - `size` 用户缓冲区能容纳的gid个数，为0时只返回附加组的数量
+    /// - `list` 用于返回附加组的用户缓冲区
+    ///
+    /// ## 返回值
+    ///
+    /// 附加组的数量
+    ///
+    /// 参考 https://code.dragonos.org.cn/xref/linux-6.1.9/kernel/groups.c#158
+    pub fn getgroups(size: i32, list: *mut u32) -> Result<usize, SystemError> {
+        if size < 0 {
+            return Err(SystemError::EINVAL);
+        }
+        let cred = ProcessManager::current_pcb().cred();
+        let gids: Vec<u32> = cred
+            .group_info
+            .as_ref()
+            .map(|info| {
+                info.gids
+                    .iter()
+                    .map(|gid| USER_NS.from_kgid_munged(*gid))
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        if size == 0 {
+            return Ok(gids.len());
+        }
+        if (size as usize) < gids.len() {
+            return Err(SystemError::EINVAL);
+        }
+        if !gids.is_empty() {
+            let mut writer =
+                UserBufferWriter::new(list, gids.len() * core::mem::size_of::<u32>(), true)?;
+            writer.copy_to_user(&gids, 0)?;
+        }
+        Ok(gids.len())
+    }
+
+    /// # setgroups系统调用
+    ///
+    /// 设置当前进程的附加组，需要CAP_SETGID
+    ///
+    /// ## 参数
+    ///
+    /// - `size` gid的个数，不能超过NGROUPS_MAX
+    /// - `list` 保存新的附加组的用户缓冲区
+    ///
+    /// 参考 https://code.dragonos.org.cn/xref/linux-6.1.9/kernel/groups.c#190
+    pub fn setgroups(size: i32, list: *const u32) -> Result<usize, SystemError> {
+        let pcb = ProcessManager::current_pcb();
+        if !pcb.cred().has_capability(CAPFlags::CAP_SETGID) {
+            return Err(SystemError::EPERM);
+        }
+        if size < 0 || size as usize > NGROUPS_MAX {
+            return Err(SystemError::EINVAL);
+        }
+
+        let mut gids = Vec::with_capacity(size as usize);
+        if size > 0 {
+            let reader =
+                UserBufferReader::new(list, size as usize * core::mem::size_of::<u32>(), true)?;
+            for gid in reader.read_from_user::<u32>(0)? {
+                gids.push(USER_NS.make_kgid(*gid).ok_or(SystemError::EINVAL)?);
+            }
+        }
+
+        pcb.cred.lock().set_groups(gids);
+        Ok(0)
+    }
+
     pub fn get_rusage(who: i32, rusage: *mut RUsage) -> Result<usize, SystemError> {
         let who = RUsageWho::try_from(who)?;
         let mut writer = UserBufferWriter::new(rusage, core::mem::size_of::<RUsage>(), true)?;
@@ -552,71 +785,82 @@ impl Syscall {
         return Ok(0);
     }
 
-    /// # 设置资源限制
-    ///
-    /// TODO: 目前暂时不支持设置资源限制，只提供读取默认值的功能
+    /// # 获取或设置进程的资源限制
     ///
     /// ## 参数
     ///
-    /// - pid: 进程号
+    /// - pid: 进程号，为0时表示当前进程
     /// - resource: 资源类型
-    /// - new_limit: 新的资源限制
-    /// - old_limit: 旧的资源限制
+    /// - new_limit: 新的资源限制，为NULL时不修改
+    /// - old_limit: 旧的资源限制，为NULL时不返回
     ///
     /// ## 返回值
     ///
     /// - 成功，0
     /// - 如果old_limit不为NULL，则返回旧的资源限制到old_limit
     ///
+    /// 参考 https://code.dragonos.org.cn/xref/linux-6.1.9/kernel/sys.c#1642
     pub fn prlimit64(
-        _pid: Pid,
+        pid: Pid,
         resource: usize,
-        _new_limit: *const RLimit64,
+        new_limit: *const RLimit64,
         old_limit: *mut RLimit64,
     ) -> Result<usize, SystemError> {
         let resource = RLimitID::try_from(resource)?;
-        let mut writer = None;
+        let current = ProcessManager::current_pcb();
+        let target = if pid.data() == 0 || pid == current.pid() {
+            current.clone()
+        } else {
+            ProcessManager::find(pid).ok_or(SystemError::ESRCH)?
+        };
 
-        if !old_limit.is_null() {
-            writer = Some(UserBufferWriter::new(
-                old_limit,
-                core::mem::size_of::<RLimit64>(),
-                true,
-            )?);
+        // 访问其他进程的资源限制时，调用者的uid和gid需要与目标进程的完全一致，或者拥有CAP_SYS_RESOURCE
+        if !Arc::ptr_eq(&current, &target) {
+            let cred = current.cred();
+            let tcred = target.cred();
+            let same_ids = cred.uid == tcred.uid
+                && cred.uid == tcred.euid
+                && cred.uid == tcred.suid
+                && cred.gid == tcred.gid
+                && cred.gid == tcred.egid
+                && cred.gid == tcred.sgid;
+            if !same_ids && !cred.has_capability(CAPFlags::CAP_SYS_RESOURCE) {
+                return Err(SystemError::EPERM);
+            }
         }
 
-        match resource {
-            RLimitID::Stack => {
-                if let Some(mut writer) = writer {
-                    let mut rlimit = writer.buffer::<RLimit64>(0).unwrap()[0];
-                    rlimit.rlim_cur = UserStack::DEFAULT_USER_STACK_SIZE as u64;
-                    rlimit.rlim_max = UserStack::DEFAULT_USER_STACK_SIZE as u64;
-                }
-                return Ok(0);
-            }
+        let new_limit = if new_limit.is_null() {
+            None
+        } else {
+            let reader = UserBufferReader::new(new_limit, core::mem::size_of::<RLimit64>(), true)?;
+            Some(*reader.read_one_from_user::<RLimit64>(0)?)
+        };
 
-            RLimitID::Nofile => {
-                if let Some(mut writer) = writer {
-                    let mut rlimit = writer.buffer::<RLimit64>(0).unwrap()[0];
-                    rlimit.rlim_cur = FileDescriptorVec::PROCESS_MAX_FD as u64;
-                    rlimit.rlim_max = FileDescriptorVec::PROCESS_MAX_FD as u64;
-                }
-                return Ok(0);
+        let old = target.get_rlimit(resource);
+        if let Some(new) = new_limit {
+            if new.rlim_cur > new.rlim_max {
+                return Err(SystemError::EINVAL);
             }
-
-            RLimitID::As | RLimitID::Rss => {
-                if let Some(mut writer) = writer {
-                    let mut rlimit = writer.buffer::<RLimit64>(0).unwrap()[0];
-                    rlimit.rlim_cur = MMArch::USER_END_VADDR.data() as u64;
-                    rlimit.rlim_max = MMArch::USER_END_VADDR.data() as u64;
-                }
-                return Ok(0);
+            if new.rlim_max > old.rlim_max
+                && !current.cred().has_capability(CAPFlags::CAP_SYS_RESOURCE)
+            {
+                return Err(SystemError::EPERM);
             }
-
-            _ => {
-                return Err(SystemError::ENOSYS);
+            if resource == RLimitID::Nofile
+                && new.rlim_max > FileDescriptorVec::PROCESS_MAX_FD as u64
+            {
+                return Err(SystemError::EPERM);
             }
+            target.set_rlimit(resource, new);
+        }
+
+        if !old_limit.is_null() {
+            let mut writer =
+                UserBufferWriter::new(old_limit, core::mem::size_of::<RLimit64>(), true)?;
+            writer.copy_one_to_user(&old, 0)?;
         }
+
+        return Ok(0);
     }
 
     pub fn uname(name: *mut PosixOldUtsName) -> Result<usize, SystemError> {
@@ -9,7 +9,7 @@ use crate::{
 };
 use alloc::sync::Arc;
 
-use super::{clock::SchedClock, cpu_irq_time};
+use super::{clock::SchedClock, cpu_irq_time, SchedPolicy};
 
 pub fn irq_time_read(cpu: ProcessorId) -> u64 {
     compiler_fence(Ordering::SeqCst);
@@ -75,8 +75,8 @@ impl IrqTime {
 pub struct CpuTimeFunc;
 impl CpuTimeFunc {
     pub fn irqtime_account_process_tick(
-        _pcb: &Arc<ProcessControlBlock>,
-        _user_tick: bool,
+        pcb: &Arc<ProcessControlBlock>,
+        user_tick: bool,
         ticks: u64,
     ) {
         let cputime = TICK_NESC as u64 * ticks;
@@ -87,7 +87,12 @@ impl CpuTimeFunc {
             return;
         }
 
-        // TODO: update process time
+        // idle进程的时间不计入任何进程
+        if pcb.sched_info().policy() == SchedPolicy::IDLE {
+            return;
+        }
+
+        pcb.account_cputime(user_tick, cputime - other);
     }
 
     pub fn account_other_time(max: u64) -> u64 {
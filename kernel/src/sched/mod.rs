@@ -871,10 +871,10 @@ pub fn __schedule(sched_mod: SchedMode) {
     // );
 
     // error!("prev pid {:?} {:?}", prev.pid(), prev.sched_info().policy());
-    if !sched_mod.contains(SchedMode::SM_MASK_PREEMPT)
+    let voluntary = !sched_mod.contains(SchedMode::SM_MASK_PREEMPT)
         && prev.sched_info().policy() != SchedPolicy::IDLE
-        && prev.sched_info().inner_lock_read_irqsave().is_mark_sleep()
-    {
+        && prev.sched_info().inner_lock_read_irqsave().is_mark_sleep();
+    if voluntary {
         // warn!("deactivate_task prev {:?}", prev.pid());
         // TODO: 这里需要处理信号
         // https://code.dragonos.org.cn/xref/linux-6.6.21/kernel/sched/core.c?r=&mo=172979&fi=6578#6630
@@ -905,6 +905,7 @@ pub fn __schedule(sched_mod: SchedMode) {
     prev.flags().remove(ProcessFlags::NEED_SCHEDULE);
     fence(Ordering::SeqCst);
     if likely(!Arc::ptr_eq(&prev, &next)) {
+        prev.task_rusage().inc_csw(voluntary);
         rq.set_current(Arc::downgrade(&next));
         // warn!(
         //     "switch_process prev {:?} next {:?} sched_mode {sched_mod:?}",
@@ -7,7 +7,10 @@ use system_error::SystemError;
 use crate::{
     arch::CurrentSMPArch,
     libs::cpumask::CpuMask,
-    mm::percpu::{PerCpu, PerCpuVar},
+    mm::{
+        mmzone::drain_cpu_pages,
+        percpu::{PerCpu, PerCpuVar},
+    },
     process::{ProcessControlBlock, ProcessManager},
     sched::completion::Completion,
 };
@@ -248,6 +251,9 @@ impl SmpCpuManager {
         if let Err(e) = self.do_cpuhp_kick_ap(hpstate) {
             self.cpuhp_reset_state(hpstate, prev_state);
             self.do_cpuhp_kick_ap(hpstate).ok();
+            if prev_state == CpuHpState::Offline {
+                self.cpuhp_cpu_dead(cpu_id);
+            }
 
             return Err(e);
         }
@@ -292,6 +298,12 @@ impl SmpCpuManager {
         }
     }
 
+    /// CPU回到离线状态之后，释放它持有的每CPU资源
+    fn cpuhp_cpu_dead(&self, cpu_id: ProcessorId) {
+        // 离线CPU缓存的页帧不会再被分配，需要归还给伙伴分配器
+        drain_cpu_pages(cpu_id);
+    }
+
     fn cpuhp_reset_state(&self, st: &mut CpuHpCpuState, prev_state: CpuHpState) {
         let bringup = !st.bringup;
         st.target_state = prev_state;
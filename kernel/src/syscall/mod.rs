@@ -10,9 +10,12 @@ use crate::{
     mm::{page::PAGE_4K_SIZE, syscall::MremapFlags},
     net::syscall::MsgHdr,
     process::{
+        capability::{CapUserData, CapUserHeader},
+        exit::WaitIdSigInfo,
         fork::KernelCloneArgs,
         process_group::Pgid,
         resource::{RLimit64, RUsage},
+        seccomp::secure_computing,
         ProcessFlags, ProcessManager,
     },
     sched::{schedule, SchedMode},
@@ -98,6 +101,12 @@ impl Syscall {
         args: &[usize],
         frame: &mut TrapFrame,
     ) -> Result<usize, SystemError> {
+        if frame.is_from_user() {
+            if let Some(r) = secure_computing(syscall_num, args, frame) {
+                return r;
+            }
+        }
+
         // 首先尝试从syscall_table获取处理函数
         if let Some(handler) = syscall_table().get(syscall_num) {
             // 使用以下代码可以打印系统调用号和参数，方便调试
@@ -277,6 +286,21 @@ impl Syscall {
                 Self::wait4(pid, wstatus, options, rusage)
             }
 
+            SYS_WAITID => {
+                let idtype = args[0] as u32;
+                let id = args[1] as i32;
+                let infop = args[2] as *mut WaitIdSigInfo;
+                let options = args[3] as c_int;
+                let rusage = args[4] as *mut c_void;
+                Self::waitid(idtype, id, infop, options, rusage)
+            }
+
+            SYS_PIDFD_OPEN => Self::pidfd_open(args[0] as i32, args[1] as u32),
+            SYS_PIDFD_SEND_SIGNAL => {
+                Self::pidfd_send_signal(args[0] as i32, args[1] as i32, args[2], args[3] as u32)
+            }
+            SYS_PIDFD_GETFD => Self::pidfd_getfd(args[0] as i32, args[1] as i32, args[2] as u32),
+
             SYS_EXIT => {
                 let exit_code = args[0];
                 Self::exit(exit_code)
@@ -612,7 +636,7 @@ impl Syscall {
                 let fd = args[0] as i32;
                 let cmd: Option<FcntlCommand> =
                     <FcntlCommand as FromPrimitive>::from_u32(args[1] as u32);
-                let arg = args[2] as i32;
+                let arg = args[2];
                 let res = if let Some(cmd) = cmd {
                     Self::fcntl(fd, cmd, arg)
                 } else {
@@ -652,15 +676,25 @@ impl Syscall {
                 verify_area(parent_tid, core::mem::size_of::<i32>())?;
                 verify_area(child_tid, core::mem::size_of::<i32>())?;
 
+                let flags = CloneFlags::from_bits_truncate(args[0] as u64);
+                // clone系统调用通过parent_tid返回pidfd，因此两者不能同时使用
+                if flags.contains(CloneFlags::CLONE_PIDFD | CloneFlags::CLONE_PARENT_SETTID) {
+                    return Err(SystemError::EINVAL);
+                }
+
                 let mut clone_args = KernelCloneArgs::new();
-                clone_args.flags = CloneFlags::from_bits_truncate(args[0] as u64);
+                clone_args.flags = flags;
                 clone_args.stack = args[1];
                 clone_args.parent_tid = parent_tid;
                 clone_args.child_tid = child_tid;
+                // clone系统调用通过parent_tid返回pidfd
+                clone_args.pidfd = parent_tid;
                 clone_args.tls = args[4];
                 Self::clone(frame, clone_args)
             }
 
+            SYS_CLONE3 => Self::clone3(frame, args[0] as *const u8, args[1]),
+
             SYS_FUTEX => {
                 let uaddr = VirtAddr::new(args[0]);
                 let operation = FutexFlag::from_bits(args[1] as u32).ok_or(SystemError::ENOSYS)?;
@@ -811,6 +845,9 @@ impl Syscall {
             SYS_SETFSUID => Self::setfsuid(args[0]),
             SYS_SETFSGID => Self::setfsgid(args[0]),
 
+            SYS_GETGROUPS => Self::getgroups(args[0] as i32, args[1] as *mut u32),
+            SYS_SETGROUPS => Self::setgroups(args[0] as i32, args[1] as *const u32),
+
             SYS_SETSID => Self::setsid(),
             SYS_GETSID => Self::getsid(Pid::new(args[0])),
 
@@ -997,11 +1034,12 @@ impl Syscall {
                 let name = args[0] as *mut PosixOldUtsName;
                 Self::uname(name)
             }
-            SYS_PRCTL => {
-                // todo: 这个系统调用还没有实现
-
-                Err(SystemError::EINVAL)
+            SYS_PRCTL => Self::prctl(args[0], args[1], args[2], args[3], args[4]),
+            SYS_CAPGET => Self::capget(args[0] as *mut CapUserHeader, args[1] as *mut CapUserData),
+            SYS_CAPSET => {
+                Self::capset(args[0] as *mut CapUserHeader, args[1] as *const CapUserData)
             }
+            SYS_SECCOMP => Self::seccomp(args[0] as u32, args[1] as u32, args[2]),
 
             #[cfg(target_arch = "x86_64")]
             SYS_ALARM => {
@@ -1014,6 +1052,33 @@ impl Syscall {
                 let flags = args[2];
                 Self::msync(VirtAddr::new(start), len, flags)
             }
+            SYS_MLOCK => Self::mlock2(VirtAddr::new(args[0]), args[1], 0),
+            SYS_MLOCK2 => Self::mlock2(VirtAddr::new(args[0]), args[1], args[2] as u32),
+            SYS_MUNLOCK => Self::munlock(VirtAddr::new(args[0]), args[1]),
+            SYS_MLOCKALL => Self::mlockall(args[0] as u32),
+            SYS_MUNLOCKALL => Self::munlockall(),
+            SYS_MINCORE => Self::mincore(VirtAddr::new(args[0]), args[1], args[2] as *mut u8),
+            SYS_MBIND => Self::mbind(
+                VirtAddr::new(args[0]),
+                args[1],
+                args[2] as u32,
+                args[3] as *const u64,
+                args[4],
+                args[5] as u32,
+            ),
+            SYS_SET_MEMPOLICY => {
+                Self::set_mempolicy(args[0] as u32, args[1] as *const u64, args[2])
+            }
+            SYS_GET_MEMPOLICY => Self::get_mempolicy(
+                args[0] as *mut i32,
+                args[1] as *mut u64,
+                args[2],
+                args[3],
+                args[4] as u32,
+            ),
+            SYS_MEMFD_CREATE => Self::memfd_create(args[0] as *const u8, args[1] as u32),
+            SYS_SWAPON => Self::swapon(args[0] as *const u8, args[1] as u32),
+            SYS_SWAPOFF => Self::swapoff(args[0] as *const u8),
             SYS_UTIMENSAT => Self::sys_utimensat(
                 args[0] as i32,
                 args[1] as *const u8,
@@ -1058,7 +1123,17 @@ impl Syscall {
                 Self::sys_perf_event_open(attr, pid, cpu, group_fd, flags)
             }
             #[cfg(any(target_arch = "x86_64", target_arch = "riscv64"))]
-            SYS_SETRLIMIT => Ok(0),
+            SYS_SETRLIMIT => {
+                let resource = args[0];
+                let rlimit = args[1] as *const RLimit64;
+
+                Self::prlimit64(
+                    ProcessManager::current_pcb().pid(),
+                    resource,
+                    rlimit,
+                    core::ptr::null_mut::<RLimit64>(),
+                )
+            }
 
             SYS_RT_SIGTIMEDWAIT => {
                 log::warn!("SYS_RT_SIGTIMEDWAIT has not yet been implemented");
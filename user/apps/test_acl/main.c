@@ -0,0 +1,270 @@
+#define _GNU_SOURCE
+#include <assert.h>
+#include <errno.h>
+#include <fcntl.h>
+#include <grp.h>
+#include <stdint.h>
+#include <stdio.h>
+#include <string.h>
+#include <sys/mount.h>
+#include <sys/stat.h>
+#include <sys/wait.h>
+#include <sys/xattr.h>
+#include <unistd.h>
+
+#define MNT "/test_acl_mnt"
+
+#define ACL_ACCESS "system.posix_acl_access"
+#define ACL_DEFAULT "system.posix_acl_default"
+
+#define ACL_USER_OBJ 0x01
+#define ACL_USER 0x02
+#define ACL_GROUP_OBJ 0x04
+#define ACL_GROUP 0x08
+#define ACL_MASK 0x10
+#define ACL_OTHER 0x20
+#define ACL_UNDEFINED_ID ((uint32_t)-1)
+
+struct acl_entry {
+    uint16_t tag;
+    uint16_t perm;
+    uint32_t id;
+};
+
+struct acl {
+    uint32_t version;
+    struct acl_entry entries[8];
+};
+
+// 表项需要按照tag和id递增的顺序给出
+static int set_acl(const char *path, const char *name, const struct acl_entry *entries, int count)
+{
+    struct acl acl;
+    acl.version = 2;
+    memcpy(acl.entries, entries, count * sizeof(struct acl_entry));
+    return setxattr(path, name, &acl, 4 + count * sizeof(struct acl_entry), 0);
+}
+
+// 以指定的uid和gid运行f，返回f的返回值
+static int run_as(uid_t uid, gid_t gid, int (*f)(void))
+{
+    pid_t pid = fork();
+    if (pid == 0) {
+        // 清除附加组，避免附加组与ACL中的组表项匹配
+        setgroups(0, NULL);
+        if (setgid(gid) != 0 || setuid(uid) != 0)
+            _exit(100);
+        _exit(f());
+    }
+    int status;
+    assert(waitpid(pid, &status, 0) == pid);
+    assert(WIFEXITED(status));
+    return WEXITSTATUS(status);
+}
+
+// 返回0表示可以打开，否则返回errno
+static int try_open(const char *path, int flags)
+{
+    int fd = open(path, flags);
+    if (fd < 0)
+        return errno;
+    close(fd);
+    return 0;
+}
+
+static int open_file_read(void)
+{
+    return try_open(MNT "/file", O_RDONLY);
+}
+
+static int open_file_write(void)
+{
+    return try_open(MNT "/file", O_WRONLY);
+}
+
+static void test_named_entries(void)
+{
+    printf("Test named user and group entries\n");
+    int fd = open(MNT "/file", O_CREAT | O_RDWR, 0600);
+    assert(fd >= 0);
+    close(fd);
+
+    struct acl_entry entries[] = {
+        { ACL_USER_OBJ, 6, ACL_UNDEFINED_ID },
+        { ACL_USER, 6, 1000 },
+        { ACL_GROUP_OBJ, 0, ACL_UNDEFINED_ID },
+        { ACL_GROUP, 4, 2000 },
+        { ACL_MASK, 4, ACL_UNDEFINED_ID },
+        { ACL_OTHER, 0, ACL_UNDEFINED_ID },
+    };
+    assert(set_acl(MNT "/file", ACL_ACCESS, entries, 6) == 0);
+    // 权限位中的组权限等于mask
+    struct stat st;
+    assert(stat(MNT "/file", &st) == 0 && (st.st_mode & 0777) == 0640);
+    assert(getxattr(MNT "/file", ACL_ACCESS, NULL, 0) == 4 + 6 * 8);
+
+    // 命名用户的权限受mask的限制
+    assert(run_as(1000, 3000, open_file_read) == 0);
+    assert(run_as(1000, 3000, open_file_write) == EACCES);
+    // 命名组
+    assert(run_as(1001, 2000, open_file_read) == 0);
+    assert(run_as(1001, 2000, open_file_write) == EACCES);
+    // 既不是命名用户也不在命名组中时使用other的权限
+    assert(run_as(1002, 3000, open_file_read) == EACCES);
+
+    // 放宽mask之后命名用户可以写入
+    entries[4].perm = 6;
+    assert(set_acl(MNT "/file", ACL_ACCESS, entries, 6) == 0);
+    assert(stat(MNT "/file", &st) == 0 && (st.st_mode & 0777) == 0660);
+    assert(run_as(1000, 3000, open_file_write) == 0);
+
+    // 删除ACL之后只使用权限位
+    assert(removexattr(MNT "/file", ACL_ACCESS) == 0);
+    assert(chmod(MNT "/file", 0600) == 0);
+    assert(run_as(1000, 3000, open_file_read) == EACCES);
+    printf("named user and group entries passed\n\n");
+}
+
+static void test_invalid_acl(void)
+{
+    printf("Test invalid ACLs\n");
+    // 缺少ACL_USER_OBJ
+    struct acl_entry no_user_obj[] = {
+        { ACL_GROUP_OBJ, 4, ACL_UNDEFINED_ID },
+        { ACL_OTHER, 0, ACL_UNDEFINED_ID },
+    };
+    assert(set_acl(MNT "/file", ACL_ACCESS, no_user_obj, 2) < 0 && errno == EINVAL);
+    // 有命名用户但没有mask
+    struct acl_entry no_mask[] = {
+        { ACL_USER_OBJ, 6, ACL_UNDEFINED_ID },
+        { ACL_USER, 6, 1000 },
+        { ACL_GROUP_OBJ, 4, ACL_UNDEFINED_ID },
+        { ACL_OTHER, 0, ACL_UNDEFINED_ID },
+    };
+    assert(set_acl(MNT "/file", ACL_ACCESS, no_mask, 4) < 0 && errno == EINVAL);
+    // 表项顺序错误
+    struct acl_entry unordered[] = {
+        { ACL_GROUP_OBJ, 4, ACL_UNDEFINED_ID },
+        { ACL_USER_OBJ, 6, ACL_UNDEFINED_ID },
+        { ACL_OTHER, 0, ACL_UNDEFINED_ID },
+    };
+    assert(set_acl(MNT "/file", ACL_ACCESS, unordered, 3) < 0 && errno == EINVAL);
+    // 普通文件不能设置默认ACL
+    struct acl_entry minimal[] = {
+        { ACL_USER_OBJ, 6, ACL_UNDEFINED_ID },
+        { ACL_GROUP_OBJ, 4, ACL_UNDEFINED_ID },
+        { ACL_OTHER, 0, ACL_UNDEFINED_ID },
+    };
+    assert(set_acl(MNT "/file", ACL_DEFAULT, minimal, 3) < 0 && errno == EACCES);
+
+    // 与权限位等价的ACL只修改权限位，不保存为扩展属性
+    assert(set_acl(MNT "/file", ACL_ACCESS, minimal, 3) == 0);
+    struct stat st;
+    assert(stat(MNT "/file", &st) == 0 && (st.st_mode & 0777) == 0640);
+    assert(getxattr(MNT "/file", ACL_ACCESS, NULL, 0) < 0 && errno == ENODATA);
+    printf("invalid ACLs passed\n\n");
+}
+
+static int stat_in_dir(void)
+{
+    struct stat st;
+    return stat(MNT "/dir/inner", &st) == 0 ? 0 : errno;
+}
+
+static int open_dir(void)
+{
+    return try_open(MNT "/dir", O_RDONLY | O_DIRECTORY);
+}
+
+static void test_traversal(void)
+{
+    printf("Test directory traversal\n");
+    assert(mkdir(MNT "/dir", 0755) == 0);
+    int fd = open(MNT "/dir/inner", O_CREAT | O_RDWR, 0644);
+    assert(fd >= 0);
+    close(fd);
+
+    // 命名用户没有搜索权限时不能访问目录中的文件
+    struct acl_entry entries[] = {
+        { ACL_USER_OBJ, 7, ACL_UNDEFINED_ID },
+        { ACL_USER, 0, 1000 },
+        { ACL_GROUP_OBJ, 5, ACL_UNDEFINED_ID },
+        { ACL_MASK, 5, ACL_UNDEFINED_ID },
+        { ACL_OTHER, 5, ACL_UNDEFINED_ID },
+    };
+    assert(set_acl(MNT "/dir", ACL_ACCESS, entries, 5) == 0);
+    assert(run_as(1000, 3000, stat_in_dir) == EACCES);
+    assert(run_as(1001, 3000, stat_in_dir) == 0);
+
+    // 只有搜索权限时可以访问目录中的文件，但不能读取目录
+    entries[1].perm = 1;
+    assert(set_acl(MNT "/dir", ACL_ACCESS, entries, 5) == 0);
+    assert(run_as(1000, 3000, stat_in_dir) == 0);
+    assert(run_as(1000, 3000, open_dir) == EACCES);
+    assert(run_as(1001, 3000, open_dir) == 0);
+    printf("directory traversal passed\n\n");
+}
+
+static int write_inherited(void)
+{
+    return try_open(MNT "/inherit/file", O_WRONLY);
+}
+
+static void test_default_acl(void)
+{
+    printf("Test default ACL inheritance\n");
+    assert(mkdir(MNT "/inherit", 0755) == 0);
+    struct acl_entry entries[] = {
+        { ACL_USER_OBJ, 7, ACL_UNDEFINED_ID },
+        { ACL_USER, 7, 1000 },
+        { ACL_GROUP_OBJ, 5, ACL_UNDEFINED_ID },
+        { ACL_MASK, 7, ACL_UNDEFINED_ID },
+        { ACL_OTHER, 0, ACL_UNDEFINED_ID },
+    };
+    assert(set_acl(MNT "/inherit", ACL_DEFAULT, entries, 5) == 0);
+    assert(getxattr(MNT "/inherit", ACL_DEFAULT, NULL, 0) == 4 + 5 * 8);
+    // 设置默认ACL不影响目录自身的权限
+    assert(getxattr(MNT "/inherit", ACL_ACCESS, NULL, 0) < 0 && errno == ENODATA);
+
+    // 新文件的权限为默认ACL与请求的权限的交集，mask体现在组权限中
+    int fd = open(MNT "/inherit/file", O_CREAT | O_RDWR, 0666);
+    assert(fd >= 0);
+    close(fd);
+    struct stat st;
+    assert(stat(MNT "/inherit/file", &st) == 0);
+    printf("inherited file mode: %o\n", st.st_mode & 0777);
+    assert((st.st_mode & 0777) == 0660);
+    assert(getxattr(MNT "/inherit/file", ACL_ACCESS, NULL, 0) == 4 + 5 * 8);
+    assert(getxattr(MNT "/inherit/file", ACL_DEFAULT, NULL, 0) < 0 && errno == ENODATA);
+    assert(run_as(1000, 3000, write_inherited) == 0);
+
+    // 子目录同时继承默认ACL
+    assert(mkdir(MNT "/inherit/sub", 0777) == 0);
+    assert(stat(MNT "/inherit/sub", &st) == 0 && (st.st_mode & 0777) == 0770);
+    assert(getxattr(MNT "/inherit/sub", ACL_DEFAULT, NULL, 0) == 4 + 5 * 8);
+    assert(getxattr(MNT "/inherit/sub", ACL_ACCESS, NULL, 0) == 4 + 5 * 8);
+    printf("default ACL inheritance passed\n\n");
+}
+
+int main()
+{
+    umask(0);
+    assert(mkdir(MNT, 0755) == 0 || errno == EEXIST);
+    assert(mount("tmpfs", MNT, "tmpfs", 0, "mode=755") == 0);
+
+    test_named_entries();
+    test_invalid_acl();
+    test_traversal();
+    test_default_acl();
+
+    assert(unlink(MNT "/inherit/file") == 0);
+    assert(rmdir(MNT "/inherit/sub") == 0);
+    assert(rmdir(MNT "/inherit") == 0);
+    assert(unlink(MNT "/dir/inner") == 0);
+    assert(rmdir(MNT "/dir") == 0);
+    assert(unlink(MNT "/file") == 0);
+    assert(umount(MNT) == 0);
+    rmdir(MNT);
+    printf("All ACL tests passed\n");
+    return 0;
+}
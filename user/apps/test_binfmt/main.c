@@ -0,0 +1,171 @@
+#include <assert.h>
+#include <errno.h>
+#include <fcntl.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <sys/mount.h>
+#include <sys/stat.h>
+#include <sys/wait.h>
+#include <unistd.h>
+
+#define SELF "/bin/test_binfmt"
+#define SCRIPT "/tmp/test_binfmt.sh"
+#define LOOP_SCRIPT "/tmp/test_binfmt_loop.sh"
+#define EXT_FILE "/tmp/test_binfmt.tbf"
+#define MAGIC_FILE "/tmp/test_binfmt_magic"
+#define BINFMT_DIR "/proc/sys/fs/binfmt_misc"
+
+#define EXIT_SCRIPT 10
+#define EXIT_MISC 20
+
+static void write_file(const char *path, const char *content, mode_t mode)
+{
+    int fd = open(path, O_WRONLY | O_CREAT | O_TRUNC, mode);
+    assert(fd >= 0);
+    assert(write(fd, content, strlen(content)) == (ssize_t)strlen(content));
+    close(fd);
+    assert(chmod(path, mode) == 0);
+}
+
+// 执行文件，返回子进程的退出码；execve失败时返回-errno
+static int run(const char *path)
+{
+    int pipefd[2];
+    assert(pipe(pipefd) == 0);
+    pid_t pid = fork();
+    if (pid == 0) {
+        close(pipefd[0]);
+        char *argv[] = { (char *)path, "a", "b c", NULL };
+        char *envp[] = { NULL };
+        execve(path, argv, envp);
+        int err = errno;
+        write(pipefd[1], &err, sizeof(err));
+        _exit(127);
+    }
+    close(pipefd[1]);
+    int err = 0;
+    ssize_t n = read(pipefd[0], &err, sizeof(err));
+    close(pipefd[0]);
+    int status;
+    assert(waitpid(pid, &status, 0) == pid);
+    if (n == sizeof(err))
+        return -err;
+    assert(WIFEXITED(status));
+    return WEXITSTATUS(status);
+}
+
+// 作为解释器被执行时检查参数列表
+static int interp_main(int argc, char **argv)
+{
+    // 脚本: 解释器 可选参数 脚本路径 原参数[1..]
+    if (argc == 5 && strcmp(argv[1], "--interp") == 0) {
+        if (strcmp(argv[2], SCRIPT) == 0 && strcmp(argv[3], "a") == 0
+            && strcmp(argv[4], "b c") == 0)
+            return EXIT_SCRIPT;
+        return 1;
+    }
+    // binfmt_misc: 解释器 文件路径 原参数[1..]
+    if (argc == 4 && (strcmp(argv[1], EXT_FILE) == 0 || strcmp(argv[1], MAGIC_FILE) == 0)) {
+        if (strcmp(argv[2], "a") == 0 && strcmp(argv[3], "b c") == 0)
+            return EXIT_MISC;
+        return 1;
+    }
+    return -1;
+}
+
+static void test_script(void)
+{
+    printf("Test #! script\n");
+    write_file(SCRIPT, "#!" SELF "   --interp  \nexit 1\n", 0755);
+    assert(run(SCRIPT) == EXIT_SCRIPT);
+
+    // 脚本递归地把自己作为解释器
+    write_file(LOOP_SCRIPT, "#!" LOOP_SCRIPT "\n", 0755);
+    assert(run(LOOP_SCRIPT) == -ELOOP);
+
+    // 解释器不存在
+    write_file(SCRIPT, "#!/nonexistent/interp\n", 0755);
+    assert(run(SCRIPT) == -ENOENT);
+    unlink(SCRIPT);
+    unlink(LOOP_SCRIPT);
+    printf("#! script passed\n\n");
+}
+
+static void write_binfmt(const char *name, const char *cmd)
+{
+    char path[128];
+    snprintf(path, sizeof(path), BINFMT_DIR "/%s", name);
+    int fd = open(path, O_WRONLY);
+    assert(fd >= 0);
+    assert(write(fd, cmd, strlen(cmd)) == (ssize_t)strlen(cmd));
+    close(fd);
+}
+
+static void test_binfmt_misc(void)
+{
+    printf("Test binfmt_misc\n");
+    assert(mount("binfmt_misc", BINFMT_DIR, "binfmt_misc", 0, NULL) == 0);
+    assert(access(BINFMT_DIR "/register", F_OK) == 0);
+
+    write_file(EXT_FILE, "not an elf\n", 0755);
+    write_file(MAGIC_FILE, "\x7fTBFpayload\n", 0755);
+    assert(run(EXT_FILE) == -ENOEXEC);
+
+    // 按照扩展名匹配
+    write_binfmt("register", ":tbf:E::tbf::" SELF ":");
+    assert(run(EXT_FILE) == EXIT_MISC);
+    char buf[256] = { 0 };
+    int fd = open(BINFMT_DIR "/tbf", O_RDONLY);
+    assert(fd >= 0);
+    assert(read(fd, buf, sizeof(buf) - 1) > 0);
+    close(fd);
+    assert(strstr(buf, "enabled") != NULL);
+    assert(strstr(buf, "interpreter " SELF) != NULL);
+    assert(strstr(buf, "extension .tbf") != NULL);
+
+    // 同名的注册项不能重复注册
+    fd = open(BINFMT_DIR "/register", O_WRONLY);
+    const char *dup = ":tbf:E::tbf::" SELF ":";
+    assert(write(fd, dup, strlen(dup)) < 0 && errno == EEXIST);
+    close(fd);
+
+    // 禁用之后不再匹配
+    write_binfmt("tbf", "0");
+    assert(run(EXT_FILE) == -ENOEXEC);
+    write_binfmt("tbf", "1");
+    assert(run(EXT_FILE) == EXIT_MISC);
+
+    // 按照魔数匹配
+    write_binfmt("register", ":tbfmagic:M::\\x7fTBF::" SELF ":");
+    assert(run(MAGIC_FILE) == EXIT_MISC);
+
+    // 禁用整个binfmt_misc
+    write_binfmt("status", "0");
+    assert(run(MAGIC_FILE) == -ENOEXEC);
+    write_binfmt("status", "1");
+    assert(run(MAGIC_FILE) == EXIT_MISC);
+
+    // 删除注册项
+    write_binfmt("tbf", "-1");
+    write_binfmt("tbfmagic", "-1");
+    assert(access(BINFMT_DIR "/tbf", F_OK) < 0 && errno == ENOENT);
+    assert(run(EXT_FILE) == -ENOEXEC);
+
+    unlink(EXT_FILE);
+    unlink(MAGIC_FILE);
+    assert(umount(BINFMT_DIR) == 0);
+    printf("binfmt_misc passed\n\n");
+}
+
+int main(int argc, char **argv)
+{
+    int r = interp_main(argc, argv);
+    if (r >= 0)
+        return r;
+
+    test_script();
+    test_binfmt_misc();
+    printf("All binfmt tests passed\n");
+    return 0;
+}
@@ -0,0 +1,230 @@
+#define _GNU_SOURCE
+#include <assert.h>
+#include <errno.h>
+#include <fcntl.h>
+#include <linux/capability.h>
+#include <stdio.h>
+#include <string.h>
+#include <sys/prctl.h>
+#include <sys/stat.h>
+#include <sys/syscall.h>
+#include <sys/wait.h>
+#include <unistd.h>
+
+#define SELF "/bin/test_capability"
+#define TEST_FILE "/tmp/test_capability_file"
+
+#define CAP_BIT(cap) (1u << ((cap) & 31))
+
+struct caps {
+    struct __user_cap_header_struct hdr;
+    struct __user_cap_data_struct data[2];
+};
+
+static int get_caps(pid_t pid, struct caps *c)
+{
+    memset(c, 0, sizeof(*c));
+    c->hdr.version = _LINUX_CAPABILITY_VERSION_3;
+    c->hdr.pid = pid;
+    return syscall(SYS_capget, &c->hdr, c->data);
+}
+
+static int set_caps(struct caps *c)
+{
+    c->hdr.version = _LINUX_CAPABILITY_VERSION_3;
+    c->hdr.pid = 0;
+    return syscall(SYS_capset, &c->hdr, c->data);
+}
+
+// 在子进程中运行f，返回它的退出码
+static int run_child(int (*f)(void))
+{
+    pid_t pid = fork();
+    if (pid == 0)
+        _exit(f());
+    int status;
+    assert(waitpid(pid, &status, 0) == pid);
+    assert(WIFEXITED(status));
+    return WEXITSTATUS(status);
+}
+
+static void test_capget(void)
+{
+    printf("Test capget\n");
+    // 不支持的版本号会被改写为内核推荐的版本
+    struct __user_cap_header_struct hdr = { 0x12345678, 0 };
+    assert(syscall(SYS_capget, &hdr, NULL) == 0);
+    assert(hdr.version == _LINUX_CAPABILITY_VERSION_3);
+    struct __user_cap_data_struct data[2];
+    hdr.version = 0x12345678;
+    assert(syscall(SYS_capget, &hdr, data) < 0 && errno == EINVAL);
+
+    // root拥有全部的capability
+    struct caps c;
+    assert(get_caps(0, &c) == 0);
+    assert(c.data[0].effective & CAP_BIT(CAP_CHOWN));
+    assert(c.data[0].permitted & CAP_BIT(CAP_KILL));
+    assert(c.data[1].permitted & CAP_BIT(CAP_SYSLOG));
+    assert(get_caps(getpid(), &c) == 0);
+    assert(get_caps(-1, &c) < 0 && errno == EINVAL);
+    printf("capget passed\n\n");
+}
+
+static int chown_test_file(void)
+{
+    return chown(TEST_FILE, 1000, 1000) == 0 ? 0 : errno;
+}
+
+static int capset_child(void)
+{
+    struct caps c;
+    if (get_caps(0, &c) != 0)
+        return 1;
+
+    // 从effective集合中去掉CAP_CHOWN之后不能修改文件所有者
+    c.data[0].effective &= ~CAP_BIT(CAP_CHOWN);
+    if (set_caps(&c) != 0)
+        return 2;
+    if (chown_test_file() != EPERM)
+        return 3;
+
+    // permitted集合中仍然有CAP_CHOWN，可以重新启用
+    c.data[0].effective |= CAP_BIT(CAP_CHOWN);
+    if (set_caps(&c) != 0 || chown_test_file() != 0)
+        return 4;
+
+    // 从permitted集合中去掉之后不能再启用
+    c.data[0].effective &= ~CAP_BIT(CAP_CHOWN);
+    c.data[0].permitted &= ~CAP_BIT(CAP_CHOWN);
+    if (set_caps(&c) != 0)
+        return 5;
+    c.data[0].effective |= CAP_BIT(CAP_CHOWN);
+    if (set_caps(&c) == 0 || errno != EPERM)
+        return 6;
+    c.data[0].effective &= ~CAP_BIT(CAP_CHOWN);
+    c.data[0].permitted |= CAP_BIT(CAP_CHOWN);
+    if (set_caps(&c) == 0 || errno != EPERM)
+        return 7;
+
+    // 不能设置其他进程的capability
+    c.hdr.version = _LINUX_CAPABILITY_VERSION_3;
+    c.hdr.pid = getppid();
+    if (syscall(SYS_capset, &c.hdr, c.data) == 0 || errno != EPERM)
+        return 8;
+    return 0;
+}
+
+static void test_capset(void)
+{
+    printf("Test capset\n");
+    int fd = open(TEST_FILE, O_CREAT | O_WRONLY, 0644);
+    assert(fd >= 0);
+    close(fd);
+    assert(run_child(capset_child) == 0);
+    unlink(TEST_FILE);
+    printf("capset passed\n\n");
+}
+
+static int setuid_child(void)
+{
+    // 所有uid都不再是root之后，capability被清除
+    if (setuid(65534) != 0)
+        return 1;
+    struct caps c;
+    if (get_caps(0, &c) != 0)
+        return 2;
+    if (c.data[0].effective || c.data[0].permitted || c.data[1].effective || c.data[1].permitted)
+        return 3;
+    if (prctl(PR_CAP_AMBIENT, PR_CAP_AMBIENT_RAISE, CAP_CHOWN, 0, 0) == 0 || errno != EPERM)
+        return 4;
+    if (prctl(PR_CAPBSET_DROP, CAP_CHOWN) == 0 || errno != EPERM)
+        return 5;
+    return 0;
+}
+
+static int bset_child(void)
+{
+    if (prctl(PR_CAPBSET_READ, CAP_NET_RAW) != 1)
+        return 1;
+    if (prctl(PR_CAPBSET_DROP, CAP_NET_RAW) != 0)
+        return 2;
+    if (prctl(PR_CAPBSET_READ, CAP_NET_RAW) != 0)
+        return 3;
+
+    // 清空inheritable集合，execve之后新程序不再拥有CAP_NET_RAW
+    struct caps c;
+    if (get_caps(0, &c) != 0)
+        return 4;
+    c.data[0].inheritable = c.data[1].inheritable = 0;
+    if (set_caps(&c) != 0)
+        return 5;
+    char *argv[] = { SELF, "--check-bset", NULL };
+    execv(SELF, argv);
+    return 6;
+}
+
+// bset_child执行execve之后，在新程序中检查capability
+static int check_bset(void)
+{
+    struct caps c;
+    if (get_caps(0, &c) != 0)
+        return 10;
+    if (c.data[0].permitted & CAP_BIT(CAP_NET_RAW))
+        return 11;
+    // 其他capability不受影响
+    if (!(c.data[0].permitted & CAP_BIT(CAP_CHOWN)) || !(c.data[0].effective & CAP_BIT(CAP_CHOWN)))
+        return 12;
+    if (prctl(PR_CAPBSET_READ, CAP_NET_RAW) != 0)
+        return 13;
+    return 0;
+}
+
+static int ambient_child(void)
+{
+    struct caps c;
+    if (get_caps(0, &c) != 0)
+        return 1;
+    c.data[0].inheritable &= ~CAP_BIT(CAP_CHOWN);
+    if (set_caps(&c) != 0)
+        return 2;
+
+    // ambient集合必须是permitted和inheritable的子集
+    if (prctl(PR_CAP_AMBIENT, PR_CAP_AMBIENT_RAISE, CAP_CHOWN, 0, 0) == 0 || errno != EPERM)
+        return 3;
+    c.data[0].inheritable |= CAP_BIT(CAP_CHOWN);
+    if (set_caps(&c) != 0)
+        return 4;
+    if (prctl(PR_CAP_AMBIENT, PR_CAP_AMBIENT_RAISE, CAP_CHOWN, 0, 0) != 0)
+        return 5;
+    if (prctl(PR_CAP_AMBIENT, PR_CAP_AMBIENT_IS_SET, CAP_CHOWN, 0, 0) != 1)
+        return 6;
+
+    // 从inheritable集合中去掉之后，ambient集合中也随之去掉
+    c.data[0].inheritable &= ~CAP_BIT(CAP_CHOWN);
+    if (set_caps(&c) != 0)
+        return 7;
+    if (prctl(PR_CAP_AMBIENT, PR_CAP_AMBIENT_IS_SET, CAP_CHOWN, 0, 0) != 0)
+        return 8;
+    return 0;
+}
+
+static void test_sets(void)
+{
+    printf("Test bounding and ambient sets\n");
+    assert(run_child(setuid_child) == 0);
+    assert(run_child(bset_child) == 0);
+    assert(run_child(ambient_child) == 0);
+    printf("bounding and ambient sets passed\n\n");
+}
+
+int main(int argc, char **argv)
+{
+    if (argc == 2 && strcmp(argv[1], "--check-bset") == 0)
+        return check_bset();
+
+    test_capget();
+    test_capset();
+    test_sets();
+    printf("All capability tests passed\n");
+    return 0;
+}
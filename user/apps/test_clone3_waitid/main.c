@@ -0,0 +1,230 @@
+#define _GNU_SOURCE
+#include <assert.h>
+#include <errno.h>
+#include <linux/sched.h>
+#include <signal.h>
+#include <stdint.h>
+#include <stdio.h>
+#include <string.h>
+#include <sys/syscall.h>
+#include <sys/time.h>
+#include <sys/wait.h>
+#include <unistd.h>
+
+#ifndef SYS_clone3
+#define SYS_clone3 435
+#endif
+#ifndef CLONE_ARGS_SIZE_VER0
+#define CLONE_ARGS_SIZE_VER0 64
+#endif
+
+static pid_t clone3(struct clone_args *args, size_t size)
+{
+    return syscall(SYS_clone3, args, size);
+}
+
+static pid_t clone3_simple(void)
+{
+    struct clone_args args;
+    memset(&args, 0, sizeof(args));
+    args.exit_signal = SIGCHLD;
+    return clone3(&args, sizeof(args));
+}
+
+static long now_ms(void)
+{
+    struct timeval tv;
+    gettimeofday(&tv, NULL);
+    return tv.tv_sec * 1000L + tv.tv_usec / 1000;
+}
+
+static void test_clone3(void)
+{
+    printf("Test clone3\n");
+    pid_t pid = clone3_simple();
+    assert(pid >= 0);
+    if (pid == 0)
+        _exit(7);
+    siginfo_t info;
+    memset(&info, 0, sizeof(info));
+    assert(waitid(P_PID, pid, &info, WEXITED) == 0);
+    assert(info.si_signo == SIGCHLD && info.si_pid == pid);
+    assert(info.si_code == CLD_EXITED && info.si_status == 7);
+
+    // 参数检查
+    struct clone_args args;
+    memset(&args, 0, sizeof(args));
+    args.exit_signal = SIGCHLD;
+    assert(clone3(&args, CLONE_ARGS_SIZE_VER0 - 8) < 0 && errno == EINVAL);
+    args.exit_signal = 0x100;
+    assert(clone3(&args, sizeof(args)) < 0 && errno == EINVAL);
+    args.exit_signal = SIGCHLD;
+    pid_t tid = 1;
+    args.set_tid = (uint64_t)(uintptr_t)&tid;
+    assert(clone3(&args, sizeof(args)) < 0 && errno == EINVAL);
+    args.set_tid = 0;
+
+    // 比内核的clone_args更大的结构体中，多出的部分必须为0
+    struct {
+        struct clone_args args;
+        uint64_t extra;
+    } big;
+    memset(&big, 0, sizeof(big));
+    big.args.exit_signal = SIGCHLD;
+    big.extra = 1;
+    assert(clone3(&big.args, sizeof(big)) < 0 && errno == E2BIG);
+
+    // 指定子进程的pid
+    pid = clone3_simple();
+    if (pid == 0)
+        _exit(0);
+    assert(waitpid(pid, NULL, 0) == pid);
+    tid = pid;
+    args.set_tid = (uint64_t)(uintptr_t)&tid;
+    args.set_tid_size = 1;
+    pid = clone3(&args, sizeof(args));
+    if (pid < 0) {
+        // pid可能已经被其它进程使用
+        assert(errno == EEXIST);
+    } else {
+        if (pid == 0)
+            _exit(getpid() == tid ? 0 : 1);
+        int status;
+        assert(pid == tid);
+        assert(waitpid(pid, &status, 0) == pid);
+        assert(WIFEXITED(status) && WEXITSTATUS(status) == 0);
+    }
+    printf("clone3 passed\n\n");
+}
+
+static void test_waitid_exited(void)
+{
+    printf("Test waitid WEXITED/WNOWAIT/WNOHANG\n");
+    siginfo_t info;
+    int pipefd[2];
+    assert(pipe(pipefd) == 0);
+    pid_t pid = fork();
+    if (pid == 0) {
+        char c;
+        close(pipefd[1]);
+        read(pipefd[0], &c, 1);
+        _exit(3);
+    }
+    close(pipefd[0]);
+
+    // 子进程还在运行时，WNOHANG立即返回并清零siginfo
+    memset(&info, 0xff, sizeof(info));
+    assert(waitid(P_ALL, 0, &info, WEXITED | WNOHANG) == 0);
+    assert(info.si_pid == 0);
+
+    close(pipefd[1]);
+    // WNOWAIT只报告状态，子进程仍然可以被等待
+    memset(&info, 0, sizeof(info));
+    assert(waitid(P_PID, pid, &info, WEXITED | WNOWAIT) == 0);
+    assert(info.si_pid == pid && info.si_code == CLD_EXITED && info.si_status == 3);
+    memset(&info, 0, sizeof(info));
+    assert(waitid(P_ALL, 0, &info, WEXITED) == 0);
+    assert(info.si_pid == pid && info.si_status == 3);
+    assert(waitid(P_ALL, 0, &info, WEXITED) < 0 && errno == ECHILD);
+
+    // 被信号杀死的子进程
+    pid = fork();
+    if (pid == 0) {
+        for (;;)
+            pause();
+    }
+    kill(pid, SIGKILL);
+    memset(&info, 0, sizeof(info));
+    assert(waitid(P_PID, pid, &info, WEXITED) == 0);
+    assert(info.si_code == CLD_KILLED && info.si_status == SIGKILL);
+
+    assert(waitid(P_ALL, 0, &info, 0) < 0 && errno == EINVAL);
+    assert(waitid(P_PID, 0, &info, WEXITED) < 0 && errno == EINVAL);
+    assert(waitid(5, 0, &info, WEXITED) < 0 && errno == EINVAL);
+    printf("waitid WEXITED/WNOWAIT/WNOHANG passed\n\n");
+}
+
+static void test_waitid_stopped(void)
+{
+    printf("Test waitid WSTOPPED/WCONTINUED\n");
+    siginfo_t info;
+    pid_t pid = fork();
+    if (pid == 0) {
+        for (;;)
+            pause();
+    }
+    assert(kill(pid, SIGSTOP) == 0);
+    memset(&info, 0, sizeof(info));
+    assert(waitid(P_PID, pid, &info, WSTOPPED) == 0);
+    assert(info.si_pid == pid && info.si_code == CLD_STOPPED && info.si_status == SIGSTOP);
+
+    assert(kill(pid, SIGCONT) == 0);
+    memset(&info, 0, sizeof(info));
+    assert(waitid(P_PID, pid, &info, WCONTINUED) == 0);
+    assert(info.si_pid == pid && info.si_code == CLD_CONTINUED && info.si_status == SIGCONT);
+
+    kill(pid, SIGKILL);
+    assert(waitid(P_PID, pid, &info, WEXITED) == 0);
+    printf("waitid WSTOPPED/WCONTINUED passed\n\n");
+}
+
+static void test_waitid_pgid(void)
+{
+    printf("Test waitid P_PGID and blocking wait\n");
+    siginfo_t info;
+    pid_t other = fork();
+    if (other == 0) {
+        usleep(300000);
+        _exit(1);
+    }
+    pid_t pid = fork();
+    if (pid == 0) {
+        setpgid(0, 0);
+        usleep(100000);
+        _exit(2);
+    }
+    setpgid(pid, pid);
+
+    // 只等待指定进程组中的子进程，子进程退出时应当被及时唤醒
+    long start = now_ms();
+    memset(&info, 0, sizeof(info));
+    assert(waitid(P_PGID, pid, &info, WEXITED) == 0);
+    assert(info.si_pid == pid && info.si_status == 2);
+    memset(&info, 0, sizeof(info));
+    assert(waitid(P_ALL, 0, &info, WEXITED) == 0);
+    assert(info.si_pid == other && info.si_status == 1);
+    long elapsed = now_ms() - start;
+    printf("elapsed: %ld ms\n", elapsed);
+    assert(elapsed < 1000);
+    printf("waitid P_PGID and blocking wait passed\n\n");
+}
+
+static void test_waitid_zombie_without_wexited(void)
+{
+    printf("Test waitid without WEXITED on a zombie\n");
+    pid_t pid = fork();
+    if (pid == 0)
+        _exit(0);
+    usleep(100000);
+
+    // 已经退出的子进程不会再停止或继续运行，没有WEXITED时不能等待它
+    siginfo_t info;
+    assert(waitid(P_PID, pid, &info, WSTOPPED) < 0 && errno == ECHILD);
+    assert(waitid(P_ALL, 0, &info, WSTOPPED | WCONTINUED) < 0 && errno == ECHILD);
+
+    memset(&info, 0, sizeof(info));
+    assert(waitid(P_PID, pid, &info, WEXITED) == 0);
+    assert(info.si_pid == pid && info.si_code == CLD_EXITED);
+    printf("waitid without WEXITED on a zombie passed\n\n");
+}
+
+int main()
+{
+    test_clone3();
+    test_waitid_exited();
+    test_waitid_stopped();
+    test_waitid_pgid();
+    test_waitid_zombie_without_wexited();
+    printf("All clone3 and waitid tests passed\n");
+    return 0;
+}
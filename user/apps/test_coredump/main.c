@@ -0,0 +1,148 @@
+#include <assert.h>
+#include <elf.h>
+#include <fcntl.h>
+#include <pthread.h>
+#include <signal.h>
+#include <stdio.h>
+#include <string.h>
+#include <sys/prctl.h>
+#include <sys/resource.h>
+#include <sys/stat.h>
+#include <sys/wait.h>
+#include <unistd.h>
+
+#define CORE_PATTERN_FILE "/proc/sys/kernel/core_pattern"
+#define CORE_DIR "/tmp"
+#define NR_THREADS 3
+
+static volatile unsigned long counter;
+
+static void set_core_pattern(const char *pattern)
+{
+    int fd = open(CORE_PATTERN_FILE, O_WRONLY | O_TRUNC);
+    assert(fd >= 0);
+    assert(write(fd, pattern, strlen(pattern)) == (ssize_t)strlen(pattern));
+    close(fd);
+}
+
+static void *spin_thread(void *arg)
+{
+    (void)arg;
+    // 不断修改内存，core dump期间必须已经停下来
+    for (;;)
+        counter++;
+    return NULL;
+}
+
+// 在子进程中以SIGSEGV终止，nr_threads为线程组中的线程数
+static pid_t crash_child(int nr_threads, rlim_t core_limit, int dumpable)
+{
+    pid_t pid = fork();
+    if (pid == 0) {
+        struct rlimit rl = { core_limit, core_limit };
+        if (setrlimit(RLIMIT_CORE, &rl) != 0)
+            _exit(1);
+        if (prctl(PR_SET_DUMPABLE, dumpable) != 0)
+            _exit(2);
+        for (int i = 1; i < nr_threads; i++) {
+            pthread_t tid;
+            if (pthread_create(&tid, NULL, spin_thread, NULL) != 0)
+                _exit(3);
+        }
+        usleep(100000);
+        *(volatile int *)0 = 1;
+        _exit(4);
+    }
+    return pid;
+}
+
+// 统计core文件中NT_PRSTATUS note的数量，即被保存的线程数
+static int count_prstatus(const char *path)
+{
+    int fd = open(path, O_RDONLY);
+    assert(fd >= 0);
+    Elf64_Ehdr ehdr;
+    assert(read(fd, &ehdr, sizeof(ehdr)) == sizeof(ehdr));
+    assert(memcmp(ehdr.e_ident, ELFMAG, SELFMAG) == 0);
+    assert(ehdr.e_type == ET_CORE);
+
+    int count = 0;
+    for (int i = 0; i < ehdr.e_phnum; i++) {
+        Elf64_Phdr phdr;
+        assert(pread(fd, &phdr, sizeof(phdr), ehdr.e_phoff + i * ehdr.e_phentsize) == sizeof(phdr));
+        if (phdr.p_type != PT_NOTE)
+            continue;
+        static char notes[65536];
+        assert(phdr.p_filesz <= sizeof(notes));
+        assert(pread(fd, notes, phdr.p_filesz, phdr.p_offset) == (ssize_t)phdr.p_filesz);
+        size_t off = 0;
+        while (off + sizeof(Elf64_Nhdr) <= phdr.p_filesz) {
+            Elf64_Nhdr *nhdr = (Elf64_Nhdr *)(notes + off);
+            if (nhdr->n_type == NT_PRSTATUS)
+                count++;
+            off += sizeof(Elf64_Nhdr) + ((nhdr->n_namesz + 3) & ~3) + ((nhdr->n_descsz + 3) & ~3);
+        }
+    }
+    close(fd);
+    return count;
+}
+
+static void test_coredump(void)
+{
+    printf("Test multithreaded core dump\n");
+    pid_t pid = crash_child(NR_THREADS, RLIM_INFINITY, 1);
+    int status;
+    assert(waitpid(pid, &status, 0) == pid);
+    assert(WIFSIGNALED(status) && WTERMSIG(status) == SIGSEGV);
+    assert(WCOREDUMP(status));
+
+    char path[64];
+    snprintf(path, sizeof(path), CORE_DIR "/core.%d", pid);
+    int n = count_prstatus(path);
+    printf("threads in core file: %d\n", n);
+    assert(n == NR_THREADS);
+    unlink(path);
+    printf("multithreaded core dump passed\n\n");
+}
+
+static void test_no_coredump(void)
+{
+    printf("Test core dump disabled\n");
+    struct stat st;
+    char path[64];
+    int status;
+
+    // RLIMIT_CORE为0时不生成core文件
+    pid_t pid = crash_child(1, 0, 1);
+    assert(waitpid(pid, &status, 0) == pid);
+    assert(WIFSIGNALED(status) && !WCOREDUMP(status));
+    snprintf(path, sizeof(path), CORE_DIR "/core.%d", pid);
+    assert(stat(path, &st) < 0);
+
+    // 不可dump的进程不生成core文件
+    pid = crash_child(1, RLIM_INFINITY, 0);
+    assert(waitpid(pid, &status, 0) == pid);
+    assert(WIFSIGNALED(status) && !WCOREDUMP(status));
+    snprintf(path, sizeof(path), CORE_DIR "/core.%d", pid);
+    assert(stat(path, &st) < 0);
+    printf("core dump disabled passed\n\n");
+}
+
+int main()
+{
+    char old_pattern[128] = { 0 };
+    int fd = open(CORE_PATTERN_FILE, O_RDONLY);
+    assert(fd >= 0);
+    ssize_t len = read(fd, old_pattern, sizeof(old_pattern) - 1);
+    assert(len >= 0);
+    close(fd);
+    if (len > 0 && old_pattern[len - 1] == '\n')
+        old_pattern[len - 1] = 0;
+
+    set_core_pattern(CORE_DIR "/core.%p");
+    test_coredump();
+    test_no_coredump();
+    set_core_pattern(old_pattern);
+    printf("All coredump tests passed\n");
+    return 0;
+}
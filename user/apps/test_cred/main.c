@@ -1,5 +1,6 @@
 #include <sys/types.h>
 #include <unistd.h>
+#include <grp.h>
 #include <stdio.h>
 #include <assert.h>
 
@@ -7,6 +8,17 @@ int main()
 {
     printf("Current uid: %d, euid: %d, gid: %d, egid: %d\n\n", getuid(), geteuid(), getgid(), getegid());
 
+    // 测试附加组
+    printf("Set supplementary groups 1001, 1002\n");
+    gid_t groups[2] = {1002, 1001};
+    assert(setgroups(2, groups) == 0);
+    assert(getgroups(0, NULL) == 2);
+    gid_t got[2];
+    assert(getgroups(1, got) < 0); // 缓冲区太小
+    assert(getgroups(2, got) == 2);
+    assert((got[0] == 1001 && got[1] == 1002) || (got[0] == 1002 && got[1] == 1001));
+    printf("Current groups: %d, %d\n\n", got[0], got[1]);
+
     // 测试uid
     printf("Set uid 1000\n");
     setuid(1000);
@@ -38,5 +50,6 @@ int main()
     // 测试uid在非root用户下无法修改
     printf("Try to setuid for non_root.\n");
     assert(setuid(0) < 0); // 非root用户无法修改uid
+    assert(setgroups(0, NULL) < 0); // 非root用户无法修改附加组
     printf("Current uid: %d, euid: %d, gid: %d, egid: %d\n", getuid(), geteuid(), getgid(), getegid());
 }
\ No newline at end of file
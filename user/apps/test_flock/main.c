@@ -0,0 +1,113 @@
+#define _GNU_SOURCE
+#include <assert.h>
+#include <errno.h>
+#include <fcntl.h>
+#include <stdio.h>
+#include <sys/file.h>
+#include <sys/wait.h>
+#include <unistd.h>
+
+#define TEST_FILE "/tmp/test_flock.txt"
+
+static int set_lock(int fd, int cmd, short type, off_t start, off_t len)
+{
+    struct flock fl = {
+        .l_type = type,
+        .l_whence = SEEK_SET,
+        .l_start = start,
+        .l_len = len,
+        .l_pid = 0,
+    };
+    return fcntl(fd, cmd, &fl);
+}
+
+static void test_flock(void)
+{
+    printf("Test flock\n");
+    int fd1 = open(TEST_FILE, O_RDWR | O_CREAT, 0644);
+    int fd2 = open(TEST_FILE, O_RDWR);
+    assert(fd1 >= 0 && fd2 >= 0);
+
+    // 两个独立的打开文件描述，排他锁互斥
+    assert(flock(fd1, LOCK_EX) == 0);
+    assert(flock(fd2, LOCK_EX | LOCK_NB) < 0 && errno == EWOULDBLOCK);
+    assert(flock(fd2, LOCK_SH | LOCK_NB) < 0 && errno == EWOULDBLOCK);
+
+    // 降级为共享锁后，另一个描述可以加共享锁
+    assert(flock(fd1, LOCK_SH) == 0);
+    assert(flock(fd2, LOCK_SH | LOCK_NB) == 0);
+    assert(flock(fd2, LOCK_UN) == 0);
+
+    // dup出来的fd共享同一把锁，关闭最后一个引用才释放
+    int fd3 = dup(fd1);
+    assert(close(fd1) == 0);
+    assert(flock(fd2, LOCK_EX | LOCK_NB) < 0 && errno == EWOULDBLOCK);
+    assert(close(fd3) == 0);
+    assert(flock(fd2, LOCK_EX | LOCK_NB) == 0);
+    assert(close(fd2) == 0);
+    printf("flock passed\n\n");
+}
+
+static void test_posix_lock(void)
+{
+    printf("Test fcntl POSIX locks\n");
+    int fd = open(TEST_FILE, O_RDWR | O_CREAT, 0644);
+    assert(fd >= 0);
+    assert(set_lock(fd, F_SETLK, F_WRLCK, 0, 100) == 0);
+
+    pid_t pid = fork();
+    if (pid == 0) {
+        int cfd = open(TEST_FILE, O_RDWR);
+        // 冲突区间加锁失败
+        if (set_lock(cfd, F_SETLK, F_WRLCK, 50, 10) == 0 || (errno != EAGAIN && errno != EACCES))
+            _exit(1);
+        // F_GETLK应返回父进程持有的锁
+        struct flock fl = { .l_type = F_WRLCK, .l_whence = SEEK_SET, .l_start = 0, .l_len = 10 };
+        if (fcntl(cfd, F_GETLK, &fl) != 0 || fl.l_type != F_WRLCK || fl.l_pid != getppid())
+            _exit(2);
+        // 不冲突的区间可以加锁
+        if (set_lock(cfd, F_SETLK, F_WRLCK, 100, 10) != 0)
+            _exit(3);
+        _exit(0);
+    }
+    int status;
+    assert(waitpid(pid, &status, 0) == pid);
+    assert(WIFEXITED(status) && WEXITSTATUS(status) == 0);
+
+    // 子进程退出后它的锁被释放，同一进程的锁不会和自己冲突
+    assert(set_lock(fd, F_SETLK, F_WRLCK, 100, 10) == 0);
+    assert(set_lock(fd, F_SETLK, F_UNLCK, 0, 0) == 0);
+    assert(close(fd) == 0);
+    printf("POSIX locks passed\n\n");
+}
+
+static void test_ofd_lock(void)
+{
+    printf("Test fcntl OFD locks\n");
+    int fd1 = open(TEST_FILE, O_RDWR | O_CREAT, 0644);
+    int fd2 = open(TEST_FILE, O_RDWR);
+    assert(fd1 >= 0 && fd2 >= 0);
+
+    // OFD锁属于打开文件描述，同一进程的两个描述之间也会冲突
+    assert(set_lock(fd1, F_OFD_SETLK, F_WRLCK, 0, 10) == 0);
+    assert(set_lock(fd2, F_OFD_SETLK, F_RDLCK, 5, 10) < 0 && errno == EAGAIN);
+
+    struct flock fl = { .l_type = F_RDLCK, .l_whence = SEEK_SET, .l_start = 0, .l_len = 1 };
+    assert(fcntl(fd2, F_OFD_GETLK, &fl) == 0);
+    assert(fl.l_type == F_WRLCK && fl.l_pid == -1);
+
+    assert(close(fd1) == 0);
+    assert(set_lock(fd2, F_OFD_SETLK, F_RDLCK, 5, 10) == 0);
+    assert(close(fd2) == 0);
+    printf("OFD locks passed\n\n");
+}
+
+int main()
+{
+    test_flock();
+    test_posix_lock();
+    test_ofd_lock();
+    unlink(TEST_FILE);
+    printf("All file lock tests passed\n");
+    return 0;
+}
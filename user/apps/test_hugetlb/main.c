@@ -0,0 +1,105 @@
+#define _GNU_SOURCE
+#include <assert.h>
+#include <errno.h>
+#include <fcntl.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <sys/mman.h>
+#include <sys/mount.h>
+#include <sys/stat.h>
+#include <unistd.h>
+
+#define HPAGE_SIZE (2UL << 20)
+#define HPAGE_DIR "/sys/kernel/mm/hugepages/hugepages-2048kB/"
+#define MOUNT_POINT "/tmp/test_hugetlbfs"
+
+static long read_attr(const char *name)
+{
+    char path[128], buf[32] = {0};
+    snprintf(path, sizeof(path), HPAGE_DIR "%s", name);
+    int fd = open(path, O_RDONLY);
+    assert(fd >= 0);
+    assert(read(fd, buf, sizeof(buf) - 1) > 0);
+    close(fd);
+    return atol(buf);
+}
+
+static void set_nr_hugepages(long nr)
+{
+    char buf[32];
+    int len = snprintf(buf, sizeof(buf), "%ld\n", nr);
+    int fd = open(HPAGE_DIR "nr_hugepages", O_WRONLY);
+    assert(fd >= 0);
+    assert(write(fd, buf, len) == len);
+    close(fd);
+}
+
+static void test_map_hugetlb(void)
+{
+    printf("Test MAP_HUGETLB\n");
+    long free_before = read_attr("free_hugepages");
+    size_t len = 2 * HPAGE_SIZE;
+    char *p = mmap(NULL, len, PROT_READ | PROT_WRITE, MAP_PRIVATE | MAP_ANONYMOUS | MAP_HUGETLB, -1, 0);
+    assert(p != MAP_FAILED);
+    assert(((unsigned long)p & (HPAGE_SIZE - 1)) == 0);
+
+    memset(p, 0x5a, len);
+    assert(p[0] == 0x5a && p[len - 1] == 0x5a);
+    assert(read_attr("free_hugepages") == free_before - 2);
+
+    // 不按大页大小对齐的munmap会失败
+    assert(munmap(p + 4096, 4096) < 0 && errno == EINVAL);
+    assert(munmap(p, len) == 0);
+    assert(read_attr("free_hugepages") == free_before);
+
+    // 池中的大页不够时映射失败
+    p = mmap(NULL, (free_before + 1) * HPAGE_SIZE, PROT_READ | PROT_WRITE,
+             MAP_PRIVATE | MAP_ANONYMOUS | MAP_HUGETLB | MAP_POPULATE, -1, 0);
+    assert(p == MAP_FAILED && errno == ENOMEM);
+    printf("MAP_HUGETLB passed\n\n");
+}
+
+static void test_hugetlbfs(void)
+{
+    printf("Test hugetlbfs\n");
+    mkdir(MOUNT_POINT, 0755);
+    assert(mount("none", MOUNT_POINT, "hugetlbfs", 0, NULL) == 0);
+
+    int fd = open(MOUNT_POINT "/file", O_RDWR | O_CREAT, 0644);
+    assert(fd >= 0);
+    assert(ftruncate(fd, HPAGE_SIZE) == 0);
+    char *p = mmap(NULL, HPAGE_SIZE, PROT_READ | PROT_WRITE, MAP_SHARED, fd, 0);
+    assert(p != MAP_FAILED);
+    strcpy(p, "hello hugetlbfs");
+    assert(munmap(p, HPAGE_SIZE) == 0);
+
+    // 共享映射的内容保存在文件中
+    p = mmap(NULL, HPAGE_SIZE, PROT_READ, MAP_SHARED, fd, 0);
+    assert(p != MAP_FAILED);
+    assert(strcmp(p, "hello hugetlbfs") == 0);
+    assert(munmap(p, HPAGE_SIZE) == 0);
+
+    // hugetlbfs的文件不支持write
+    assert(write(fd, "x", 1) < 0);
+    close(fd);
+    assert(unlink(MOUNT_POINT "/file") == 0);
+    assert(umount(MOUNT_POINT) == 0);
+    rmdir(MOUNT_POINT);
+    printf("hugetlbfs passed\n\n");
+}
+
+int main()
+{
+    set_nr_hugepages(4);
+    assert(read_attr("nr_hugepages") == 4);
+    assert(read_attr("free_hugepages") == 4);
+
+    test_map_hugetlb();
+    test_hugetlbfs();
+
+    set_nr_hugepages(0);
+    assert(read_attr("nr_hugepages") == 0);
+    printf("All hugetlb tests passed\n");
+    return 0;
+}
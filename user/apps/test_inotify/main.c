@@ -0,0 +1,97 @@
+#include <assert.h>
+#include <errno.h>
+#include <fcntl.h>
+#include <limits.h>
+#include <stdio.h>
+#include <string.h>
+#include <sys/inotify.h>
+#include <sys/ioctl.h>
+#include <sys/stat.h>
+#include <unistd.h>
+
+#define TEST_DIR "/tmp/test_inotify"
+#define EVENT_BUF_LEN (16 * (sizeof(struct inotify_event) + NAME_MAX + 1))
+
+static char buf[EVENT_BUF_LEN] __attribute__((aligned(__alignof__(struct inotify_event))));
+
+// 读取一批事件，检查其中是否有指定的事件
+static int has_event(int fd, int wd, uint32_t mask, const char *name)
+{
+    ssize_t len = read(fd, buf, sizeof(buf));
+    assert(len > 0);
+    for (char *p = buf; p < buf + len;) {
+        struct inotify_event *ev = (struct inotify_event *)p;
+        if (ev->wd == wd && (ev->mask & mask) && (name == NULL || (ev->len && strcmp(ev->name, name) == 0)))
+            return 1;
+        p += sizeof(struct inotify_event) + ev->len;
+    }
+    return 0;
+}
+
+int main()
+{
+    mkdir(TEST_DIR, 0755);
+
+    // inotify_init1(IN_NONBLOCK)：队列为空时读取返回EAGAIN
+    int fd = inotify_init1(IN_NONBLOCK);
+    assert(fd >= 0);
+    int wd = inotify_add_watch(fd, TEST_DIR, IN_CREATE | IN_DELETE | IN_MODIFY);
+    assert(wd >= 0);
+    assert(read(fd, buf, sizeof(buf)) < 0 && errno == EAGAIN);
+
+    // 创建文件产生IN_CREATE
+    int file = open(TEST_DIR "/a.txt", O_RDWR | O_CREAT, 0644);
+    assert(file >= 0);
+    int avail = 0;
+    assert(ioctl(fd, FIONREAD, &avail) == 0 && avail > 0);
+    assert(has_event(fd, wd, IN_CREATE, "a.txt"));
+    printf("IN_CREATE ok\n");
+
+    // 写文件产生IN_MODIFY
+    assert(write(file, "hello", 5) == 5);
+    assert(has_event(fd, wd, IN_MODIFY, "a.txt"));
+    close(file);
+    printf("IN_MODIFY ok\n");
+
+    // 缓冲区太小时返回EINVAL
+    unlink(TEST_DIR "/a.txt");
+    assert(read(fd, buf, 1) < 0 && errno == EINVAL);
+    assert(has_event(fd, wd, IN_DELETE, "a.txt"));
+    printf("IN_DELETE ok\n");
+
+    // 移除监视产生IN_IGNORED
+    assert(inotify_rm_watch(fd, wd) == 0);
+    assert(has_event(fd, wd, IN_IGNORED, NULL));
+    assert(inotify_rm_watch(fd, wd) < 0 && errno == EINVAL);
+    close(fd);
+    printf("IN_IGNORED ok\n");
+
+    // 非阻塞标志以文件当前的标志为准：F_SETFL打开O_NONBLOCK后，read不再阻塞
+    fd = inotify_init1(0);
+    assert(fd >= 0);
+    assert(inotify_add_watch(fd, TEST_DIR, IN_CREATE) >= 0);
+    assert(fcntl(fd, F_SETFL, fcntl(fd, F_GETFL) | O_NONBLOCK) == 0);
+    assert(read(fd, buf, sizeof(buf)) < 0 && errno == EAGAIN);
+    close(fd);
+    printf("F_SETFL O_NONBLOCK ok\n");
+
+    // 反过来，F_SETFL清除O_NONBLOCK后，read等待事件到来
+    fd = inotify_init1(IN_NONBLOCK);
+    assert(fd >= 0);
+    wd = inotify_add_watch(fd, TEST_DIR, IN_CREATE);
+    assert(wd >= 0);
+    assert(fcntl(fd, F_SETFL, fcntl(fd, F_GETFL) & ~O_NONBLOCK) == 0);
+    if (fork() == 0) {
+        usleep(100000);
+        close(open(TEST_DIR "/b.txt", O_RDWR | O_CREAT, 0644));
+        _exit(0);
+    }
+    assert(has_event(fd, wd, IN_CREATE, "b.txt"));
+    close(fd);
+    unlink(TEST_DIR "/b.txt");
+    printf("blocking read ok\n");
+
+    rmdir(TEST_DIR);
+    printf("All inotify tests passed\n");
+    return 0;
+}
@@ -0,0 +1,220 @@
+#define _GNU_SOURCE
+#include <assert.h>
+#include <errno.h>
+#include <poll.h>
+#include <pthread.h>
+#include <signal.h>
+#include <stdio.h>
+#include <string.h>
+#include <sys/mman.h>
+#include <sys/wait.h>
+#include <unistd.h>
+
+static volatile sig_atomic_t chld_code;
+static volatile sig_atomic_t chld_count;
+
+static void sigchld_handler(int sig, siginfo_t *info, void *ctx)
+{
+    (void)sig;
+    (void)ctx;
+    chld_code = info->si_code;
+    chld_count++;
+}
+
+static void set_sigchld(int flags)
+{
+    struct sigaction sa;
+    memset(&sa, 0, sizeof(sa));
+    sa.sa_sigaction = sigchld_handler;
+    sa.sa_flags = SA_SIGINFO | SA_RESTART | flags;
+    assert(sigaction(SIGCHLD, &sa, NULL) == 0);
+    chld_code = 0;
+    chld_count = 0;
+}
+
+// 子进程位于单独的进程组中，父进程与它在同一会话的不同进程组中，因此它的进程组不是孤儿进程组
+static pid_t spawn_sleeper(void)
+{
+    pid_t pid = fork();
+    if (pid == 0) {
+        setpgid(0, 0);
+        for (;;)
+            pause();
+    }
+    setpgid(pid, pid);
+    return pid;
+}
+
+// 等待SIGCHLD处理函数被调用，返回是否在超时之前收到
+static int wait_sigchld(int count)
+{
+    for (int i = 0; i < 100 && chld_count < count; i++)
+        usleep(10000);
+    return chld_count >= count;
+}
+
+static void test_wuntraced(void)
+{
+    printf("Test WUNTRACED and WCONTINUED\n");
+    set_sigchld(0);
+    pid_t pid = spawn_sleeper();
+    int status;
+
+    assert(kill(pid, SIGSTOP) == 0);
+    assert(waitpid(pid, &status, WUNTRACED) == pid);
+    assert(WIFSTOPPED(status) && WSTOPSIG(status) == SIGSTOP);
+    assert(wait_sigchld(1) && chld_code == CLD_STOPPED);
+    // 停止状态只报告一次
+    assert(waitpid(pid, &status, WUNTRACED | WNOHANG) == 0);
+
+    assert(kill(pid, SIGCONT) == 0);
+    assert(waitpid(pid, &status, WCONTINUED) == pid);
+    assert(WIFCONTINUED(status));
+    assert(wait_sigchld(2) && chld_code == CLD_CONTINUED);
+    assert(waitpid(pid, &status, WCONTINUED | WNOHANG) == 0);
+
+    // 没有WUNTRACED时不报告停止状态
+    assert(kill(pid, SIGTSTP) == 0);
+    usleep(100000);
+    assert(waitpid(pid, &status, WNOHANG) == 0);
+    assert(waitpid(pid, &status, WUNTRACED) == pid);
+    assert(WIFSTOPPED(status) && WSTOPSIG(status) == SIGTSTP);
+
+    // 停止的进程可以被SIGKILL杀死
+    assert(kill(pid, SIGKILL) == 0);
+    assert(waitpid(pid, &status, 0) == pid);
+    assert(WIFSIGNALED(status) && WTERMSIG(status) == SIGKILL);
+    printf("WUNTRACED and WCONTINUED passed\n\n");
+}
+
+static void test_nocldstop(void)
+{
+    printf("Test SA_NOCLDSTOP\n");
+    set_sigchld(SA_NOCLDSTOP);
+    pid_t pid = spawn_sleeper();
+    int status;
+    assert(kill(pid, SIGSTOP) == 0);
+    assert(waitpid(pid, &status, WUNTRACED) == pid);
+    assert(kill(pid, SIGCONT) == 0);
+    assert(waitpid(pid, &status, WCONTINUED) == pid);
+    usleep(100000);
+    assert(chld_count == 0);
+
+    // 子进程退出时仍然发送SIGCHLD
+    assert(kill(pid, SIGKILL) == 0);
+    assert(waitpid(pid, &status, 0) == pid);
+    assert(wait_sigchld(1) && chld_code == CLD_KILLED);
+    signal(SIGCHLD, SIG_DFL);
+    printf("SA_NOCLDSTOP passed\n\n");
+}
+
+static volatile unsigned long *counter;
+
+static void *count_thread(void *arg)
+{
+    (void)arg;
+    for (;;)
+        (*counter)++;
+    return NULL;
+}
+
+static void test_group_stop(void)
+{
+    printf("Test stopping all threads in a thread group\n");
+    counter = mmap(NULL, sizeof(*counter), PROT_READ | PROT_WRITE, MAP_SHARED | MAP_ANONYMOUS, -1, 0);
+    assert(counter != MAP_FAILED);
+    *counter = 0;
+
+    pid_t pid = fork();
+    if (pid == 0) {
+        pthread_t tid;
+        pthread_create(&tid, NULL, count_thread, NULL);
+        for (;;)
+            pause();
+    }
+    usleep(100000);
+    int status;
+    assert(kill(pid, SIGSTOP) == 0);
+    assert(waitpid(pid, &status, WUNTRACED) == pid && WIFSTOPPED(status));
+
+    // 线程组停止之后，其它线程也不再运行
+    usleep(50000);
+    unsigned long before = *counter;
+    usleep(200000);
+    assert(*counter == before);
+
+    assert(kill(pid, SIGCONT) == 0);
+    usleep(200000);
+    assert(*counter != before);
+
+    kill(pid, SIGKILL);
+    assert(waitpid(pid, &status, 0) == pid);
+    munmap((void *)counter, sizeof(*counter));
+    printf("stopping all threads in a thread group passed\n\n");
+}
+
+static int report_fd;
+
+static void sighup_handler(int sig)
+{
+    (void)sig;
+    write(report_fd, "H", 1);
+}
+
+static void test_orphaned_pgrp(void)
+{
+    printf("Test orphaned process group\n");
+    int fds[2];
+    assert(pipe(fds) == 0);
+    pid_t pid = fork();
+    if (pid == 0) {
+        close(fds[0]);
+        pid_t grandchild = fork();
+        if (grandchild == 0) {
+            // 在同一会话中创建新的进程组，它与外界的联系只有父进程
+            report_fd = fds[1];
+            signal(SIGHUP, sighup_handler);
+            setpgid(0, 0);
+            raise(SIGSTOP);
+            // 父进程退出后，进程组成为孤儿进程组，收到SIGHUP和SIGCONT之后继续运行
+            write(fds[1], "C", 1);
+            // 孤儿进程组中的进程不会因为SIGTSTP而停止
+            raise(SIGTSTP);
+            write(fds[1], "T", 1);
+            _exit(0);
+        }
+        setpgid(grandchild, grandchild);
+        int status;
+        if (waitpid(grandchild, &status, WUNTRACED) != grandchild || !WIFSTOPPED(status))
+            _exit(1);
+        _exit(0);
+    }
+    close(fds[1]);
+    int status;
+    assert(waitpid(pid, &status, 0) == pid);
+    assert(WIFEXITED(status) && WEXITSTATUS(status) == 0);
+
+    char buf[4] = { 0 };
+    int n = 0;
+    while (n < 3) {
+        struct pollfd pfd = { fds[0], POLLIN, 0 };
+        assert(poll(&pfd, 1, 5000) == 1);
+        ssize_t r = read(fds[0], buf + n, sizeof(buf) - 1 - n);
+        assert(r > 0);
+        n += r;
+    }
+    printf("grandchild reported: %s\n", buf);
+    assert(strcmp(buf, "HCT") == 0);
+    close(fds[0]);
+    printf("orphaned process group passed\n\n");
+}
+
+int main()
+{
+    test_wuntraced();
+    test_nocldstop();
+    test_group_stop();
+    test_orphaned_pgrp();
+    printf("All job control tests passed\n");
+    return 0;
+}
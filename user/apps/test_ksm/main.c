@@ -0,0 +1,151 @@
+#define _GNU_SOURCE
+#include <assert.h>
+#include <errno.h>
+#include <fcntl.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <sys/mman.h>
+#include <sys/wait.h>
+#include <unistd.h>
+
+#define KSM "/sys/kernel/mm/ksm/"
+#define PAGE 4096
+#define NR_PAGES 32
+
+static long read_ksm(const char *name)
+{
+    char path[128], buf[32];
+    snprintf(path, sizeof(path), KSM "%s", name);
+    int fd = open(path, O_RDONLY);
+    assert(fd >= 0);
+    ssize_t n = read(fd, buf, sizeof(buf) - 1);
+    assert(n > 0);
+    buf[n] = 0;
+    close(fd);
+    return atol(buf);
+}
+
+static void write_ksm(const char *name, long value)
+{
+    char path[128], buf[32];
+    snprintf(path, sizeof(path), KSM "%s", name);
+    int fd = open(path, O_WRONLY);
+    assert(fd >= 0);
+    int len = snprintf(buf, sizeof(buf), "%ld\n", value);
+    assert(write(fd, buf, len) == len);
+    close(fd);
+}
+
+// 每个页面的内容互不相同，但两个进程中相同下标的页面内容相同
+static void fill(char *p)
+{
+    for (int i = 0; i < NR_PAGES; i++)
+        memset(p + i * PAGE, 'A' + i, PAGE);
+}
+
+static int check(const char *p, int first_page_value)
+{
+    for (int i = 0; i < NR_PAGES; i++) {
+        char expect = i == 0 ? first_page_value : 'A' + i;
+        for (int j = 0; j < PAGE; j++) {
+            if (p[i * PAGE + j] != expect)
+                return 0;
+        }
+    }
+    return 1;
+}
+
+// 子进程：映射并填充可合并的内存，等待父进程的命令
+// 命令'w'表示写入第一个页面，命令'c'表示只检查内容
+static pid_t spawn_child(int ready_fd, int *cmd_fd)
+{
+    int cmd[2];
+    assert(pipe(cmd) == 0);
+    pid_t pid = fork();
+    if (pid == 0) {
+        close(cmd[1]);
+        char *p = mmap(NULL, NR_PAGES * PAGE, PROT_READ | PROT_WRITE, MAP_PRIVATE | MAP_ANONYMOUS, -1, 0);
+        if (p == MAP_FAILED)
+            _exit(1);
+        fill(p);
+        if (madvise(p, NR_PAGES * PAGE, MADV_MERGEABLE) != 0)
+            _exit(2);
+        write(ready_fd, "r", 1);
+
+        char c;
+        if (read(cmd[0], &c, 1) != 1)
+            _exit(3);
+        if (!check(p, 'A'))
+            _exit(4);
+        // 写入合并后的页面时进行写时复制
+        if (c == 'w') {
+            memset(p, 'z', PAGE);
+            if (!check(p, 'z'))
+                _exit(5);
+        }
+        write(ready_fd, "d", 1);
+        if (read(cmd[0], &c, 1) != 1)
+            _exit(6);
+        _exit(check(p, c == 'w' ? 'z' : 'A') ? 0 : 7);
+    }
+    close(cmd[0]);
+    *cmd_fd = cmd[1];
+    return pid;
+}
+
+int main()
+{
+    long old_run = read_ksm("run");
+    long old_pages_to_scan = read_ksm("pages_to_scan");
+    long old_sleep = read_ksm("sleep_millisecs");
+    long base = read_ksm("pages_sharing");
+
+    int ready[2];
+    assert(pipe(ready) == 0);
+    int cmd_a, cmd_b;
+    pid_t a = spawn_child(ready[1], &cmd_a);
+    pid_t b = spawn_child(ready[1], &cmd_b);
+    char c;
+    assert(read(ready[0], &c, 1) == 1 && read(ready[0], &c, 1) == 1);
+
+    printf("Test ksmd merging pages across processes\n");
+    write_ksm("pages_to_scan", 1000);
+    write_ksm("sleep_millisecs", 10);
+    write_ksm("run", 1);
+
+    // 等待两个进程中相同的页面被合并
+    long sharing = 0;
+    for (int i = 0; i < 1000; i++) {
+        sharing = read_ksm("pages_sharing") - base;
+        if (sharing >= NR_PAGES)
+            break;
+        usleep(10000);
+    }
+    printf("pages_shared: %ld, pages_sharing: %ld, full_scans: %ld\n", read_ksm("pages_shared"),
+           read_ksm("pages_sharing"), read_ksm("full_scans"));
+    assert(sharing >= NR_PAGES);
+
+    // 一个进程写入合并的页面后，另一个进程看到的内容不变
+    write_ksm("run", 0);
+    assert(write(cmd_a, "w", 1) == 1);
+    assert(read(ready[0], &c, 1) == 1);
+    assert(write(cmd_b, "c", 1) == 1);
+    assert(read(ready[0], &c, 1) == 1);
+
+    // 拆分所有合并的页面
+    write_ksm("run", 2);
+    assert(read_ksm("pages_sharing") == 0 && read_ksm("pages_shared") == 0);
+    assert(write(cmd_a, "w", 1) == 1);
+    assert(write(cmd_b, "c", 1) == 1);
+    int status;
+    assert(waitpid(a, &status, 0) == a && WIFEXITED(status) && WEXITSTATUS(status) == 0);
+    assert(waitpid(b, &status, 0) == b && WIFEXITED(status) && WEXITSTATUS(status) == 0);
+    printf("ksmd merging pages across processes passed\n\n");
+
+    write_ksm("run", old_run);
+    write_ksm("pages_to_scan", old_pages_to_scan);
+    write_ksm("sleep_millisecs", old_sleep);
+    printf("All KSM tests passed\n");
+    return 0;
+}
@@ -0,0 +1,174 @@
+#define _GNU_SOURCE
+#include <assert.h>
+#include <errno.h>
+#include <fcntl.h>
+#include <stdio.h>
+#include <string.h>
+#include <sys/mman.h>
+#include <sys/mount.h>
+#include <sys/stat.h>
+#include <unistd.h>
+
+#ifndef MADV_COLD
+#define MADV_COLD 20
+#endif
+#ifndef MADV_PAGEOUT
+#define MADV_PAGEOUT 21
+#endif
+#ifndef MADV_POPULATE_READ
+#define MADV_POPULATE_READ 22
+#endif
+#ifndef MADV_POPULATE_WRITE
+#define MADV_POPULATE_WRITE 23
+#endif
+
+#define MNT "/test_madvise_mnt"
+#define PAGE 4096
+
+static void check_fill(const char *p, char c, size_t len)
+{
+    for (size_t i = 0; i < len; i++)
+        assert(p[i] == c);
+}
+
+// 在tmpfs上创建一个文件，写入len字节的c
+static int create_file(const char *path, char c, size_t len)
+{
+    static char buf[16 * PAGE];
+    int fd = open(path, O_CREAT | O_RDWR | O_TRUNC, 0644);
+    assert(fd >= 0);
+    memset(buf, c, len);
+    assert(write(fd, buf, len) == (ssize_t)len);
+    return fd;
+}
+
+static void test_dontneed(void)
+{
+    printf("Test MADV_DONTNEED\n");
+    // 私有匿名映射的页面被释放，再次访问时为0
+    char *p = mmap(NULL, 4 * PAGE, PROT_READ | PROT_WRITE, MAP_PRIVATE | MAP_ANONYMOUS, -1, 0);
+    assert(p != MAP_FAILED);
+    memset(p, 'a', 4 * PAGE);
+    assert(madvise(p + PAGE, 2 * PAGE, MADV_DONTNEED) == 0);
+    check_fill(p, 'a', PAGE);
+    check_fill(p + PAGE, 0, 2 * PAGE);
+    check_fill(p + 3 * PAGE, 'a', PAGE);
+    munmap(p, 4 * PAGE);
+
+    // 私有文件映射丢弃写时复制的页面，再次访问时重新读取文件内容
+    int fd = create_file(MNT "/private", 'f', 2 * PAGE);
+    p = mmap(NULL, 2 * PAGE, PROT_READ | PROT_WRITE, MAP_PRIVATE, fd, 0);
+    assert(p != MAP_FAILED);
+    memset(p, 'p', 2 * PAGE);
+    assert(madvise(p, 2 * PAGE, MADV_DONTNEED) == 0);
+    check_fill(p, 'f', 2 * PAGE);
+    munmap(p, 2 * PAGE);
+    close(fd);
+    assert(unlink(MNT "/private") == 0);
+    printf("MADV_DONTNEED passed\n\n");
+}
+
+static void test_remove(void)
+{
+    printf("Test MADV_REMOVE\n");
+    int fd = create_file(MNT "/remove", 'r', 16 * PAGE);
+    struct stat st;
+    assert(fstat(fd, &st) == 0 && st.st_blocks == 16 * PAGE / 512);
+
+    // 私有映射不能在文件中打洞
+    char *p = mmap(NULL, 16 * PAGE, PROT_READ | PROT_WRITE, MAP_PRIVATE, fd, 0);
+    assert(p != MAP_FAILED);
+    assert(madvise(p, PAGE, MADV_REMOVE) < 0 && errno == EACCES);
+    munmap(p, 16 * PAGE);
+
+    // 共享映射打洞之后，映射和文件中的对应部分都为0，并且释放了空间
+    p = mmap(NULL, 16 * PAGE, PROT_READ | PROT_WRITE, MAP_SHARED, fd, 0);
+    assert(p != MAP_FAILED);
+    assert(madvise(p + 4 * PAGE, 8 * PAGE, MADV_REMOVE) == 0);
+    check_fill(p, 'r', 4 * PAGE);
+    check_fill(p + 4 * PAGE, 0, 8 * PAGE);
+    check_fill(p + 12 * PAGE, 'r', 4 * PAGE);
+    char buf[PAGE];
+    assert(pread(fd, buf, PAGE, 4 * PAGE) == PAGE);
+    check_fill(buf, 0, PAGE);
+    assert(fstat(fd, &st) == 0);
+    printf("size: %ld, blocks: %ld\n", (long)st.st_size, (long)st.st_blocks);
+    assert(st.st_size == 16 * PAGE && st.st_blocks == 8 * PAGE / 512);
+    munmap(p, 16 * PAGE);
+    close(fd);
+    assert(unlink(MNT "/remove") == 0);
+    printf("MADV_REMOVE passed\n\n");
+}
+
+static void test_populate(void)
+{
+    printf("Test MADV_POPULATE_WRITE\n");
+    int fd = open(MNT "/populate", O_CREAT | O_RDWR | O_TRUNC, 0644);
+    assert(fd >= 0);
+    assert(ftruncate(fd, 8 * PAGE) == 0);
+    struct stat st;
+    assert(fstat(fd, &st) == 0 && st.st_blocks == 0);
+
+    // 预先处理写缺页，为文件中的空洞分配页面
+    char *p = mmap(NULL, 8 * PAGE, PROT_READ | PROT_WRITE, MAP_SHARED, fd, 0);
+    assert(p != MAP_FAILED);
+    assert(madvise(p, 8 * PAGE, MADV_POPULATE_WRITE) == 0);
+    assert(fstat(fd, &st) == 0);
+    printf("blocks after populate: %ld\n", (long)st.st_blocks);
+    assert(st.st_blocks == 8 * PAGE / 512);
+    check_fill(p, 0, 8 * PAGE);
+    munmap(p, 8 * PAGE);
+    close(fd);
+    assert(unlink(MNT "/populate") == 0);
+
+    // 匿名映射
+    p = mmap(NULL, 4 * PAGE, PROT_READ | PROT_WRITE, MAP_PRIVATE | MAP_ANONYMOUS, -1, 0);
+    assert(p != MAP_FAILED);
+    assert(madvise(p, 4 * PAGE, MADV_POPULATE_READ) == 0);
+    assert(madvise(p, 4 * PAGE, MADV_POPULATE_WRITE) == 0);
+    check_fill(p, 0, 4 * PAGE);
+    munmap(p, 4 * PAGE);
+    printf("MADV_POPULATE_WRITE passed\n\n");
+}
+
+static void test_hints(void)
+{
+    printf("Test MADV_WILLNEED, MADV_COLD, MADV_PAGEOUT and MADV_FREE\n");
+    char *p = mmap(NULL, 4 * PAGE, PROT_READ | PROT_WRITE, MAP_PRIVATE | MAP_ANONYMOUS, -1, 0);
+    assert(p != MAP_FAILED);
+    memset(p, 'h', 4 * PAGE);
+    // 这些操作只是提示，不改变内存中的数据
+    assert(madvise(p, 4 * PAGE, MADV_WILLNEED) == 0);
+    assert(madvise(p, 4 * PAGE, MADV_COLD) == 0);
+    check_fill(p, 'h', 4 * PAGE);
+    assert(madvise(p, 4 * PAGE, MADV_PAGEOUT) == 0);
+    check_fill(p, 'h', 4 * PAGE);
+
+    // MADV_FREE之后再次写入的页面不会被释放
+    assert(madvise(p, 4 * PAGE, MADV_FREE) == 0);
+    memset(p, 'w', 4 * PAGE);
+    check_fill(p, 'w', 4 * PAGE);
+    munmap(p, 4 * PAGE);
+
+    // 参数检查
+    assert(madvise(p + 1, PAGE, MADV_DONTNEED) < 0 && errno == EINVAL);
+    assert(madvise(p, PAGE, 12345) < 0 && errno == EINVAL);
+    assert(madvise(p, PAGE, MADV_WILLNEED) < 0 && errno == ENOMEM);
+    printf("MADV_WILLNEED, MADV_COLD, MADV_PAGEOUT and MADV_FREE passed\n\n");
+}
+
+int main()
+{
+    assert(mkdir(MNT, 0755) == 0 || errno == EEXIST);
+    assert(mount("tmpfs", MNT, "tmpfs", 0, NULL) == 0);
+
+    test_dontneed();
+    test_remove();
+    test_populate();
+    test_hints();
+
+    assert(umount(MNT) == 0);
+    rmdir(MNT);
+    printf("All madvise tests passed\n");
+    return 0;
+}
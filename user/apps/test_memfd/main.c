@@ -0,0 +1,128 @@
+#define _GNU_SOURCE
+#include <assert.h>
+#include <errno.h>
+#include <fcntl.h>
+#include <stdio.h>
+#include <string.h>
+#include <sys/mman.h>
+#include <sys/syscall.h>
+#include <sys/wait.h>
+#include <unistd.h>
+
+#ifndef F_SEAL_FUTURE_WRITE
+#define F_SEAL_FUTURE_WRITE 0x0010
+#endif
+
+static long page_size;
+
+static int create_memfd(const char *name, unsigned int flags)
+{
+    int fd = syscall(SYS_memfd_create, name, flags);
+    assert(fd >= 0);
+    assert(ftruncate(fd, page_size) == 0);
+    return fd;
+}
+
+static void test_seals(void)
+{
+    printf("Test memfd seals\n");
+    // 没有MFD_ALLOW_SEALING的memfd一开始就带有F_SEAL_SEAL
+    int fd = create_memfd("noseal", 0);
+    assert(fcntl(fd, F_GET_SEALS) == F_SEAL_SEAL);
+    assert(fcntl(fd, F_ADD_SEALS, F_SEAL_WRITE) < 0 && errno == EPERM);
+    close(fd);
+
+    fd = create_memfd("seals", MFD_ALLOW_SEALING);
+    assert(fcntl(fd, F_GET_SEALS) == 0);
+
+    // F_SEAL_SHRINK / F_SEAL_GROW 限制文件大小的变化
+    assert(fcntl(fd, F_ADD_SEALS, F_SEAL_SHRINK) == 0);
+    assert(ftruncate(fd, page_size / 2) < 0 && errno == EPERM);
+    assert(ftruncate(fd, 2 * page_size) == 0);
+    assert(fcntl(fd, F_ADD_SEALS, F_SEAL_GROW) == 0);
+    assert(ftruncate(fd, 3 * page_size) < 0 && errno == EPERM);
+
+    // F_SEAL_SEAL之后不能再添加seal
+    assert(fcntl(fd, F_ADD_SEALS, F_SEAL_SEAL) == 0);
+    assert(fcntl(fd, F_ADD_SEALS, F_SEAL_WRITE) < 0 && errno == EPERM);
+    assert(fcntl(fd, F_GET_SEALS) == (F_SEAL_SHRINK | F_SEAL_GROW | F_SEAL_SEAL));
+    close(fd);
+    printf("memfd seals passed\n\n");
+}
+
+static void test_seal_write(void)
+{
+    printf("Test F_SEAL_WRITE\n");
+    int fd = create_memfd("write", MFD_ALLOW_SEALING);
+
+    // 存在可写的共享映射时不能添加F_SEAL_WRITE
+    char *p = mmap(NULL, page_size, PROT_READ | PROT_WRITE, MAP_SHARED, fd, 0);
+    assert(p != MAP_FAILED);
+    strcpy(p, "hello");
+    assert(fcntl(fd, F_ADD_SEALS, F_SEAL_WRITE) < 0 && errno == EBUSY);
+
+    // fork出的子进程同样持有这个可写映射
+    int pipefd[2];
+    assert(pipe(pipefd) == 0);
+    pid_t pid = fork();
+    if (pid == 0) {
+        char c;
+        close(pipefd[1]);
+        read(pipefd[0], &c, 1);
+        _exit(0);
+    }
+    close(pipefd[0]);
+    assert(munmap(p, page_size) == 0);
+    assert(fcntl(fd, F_ADD_SEALS, F_SEAL_WRITE) < 0 && errno == EBUSY);
+    close(pipefd[1]);
+    int status;
+    assert(waitpid(pid, &status, 0) == pid);
+
+    // 所有可写映射都解除之后可以添加F_SEAL_WRITE
+    assert(fcntl(fd, F_ADD_SEALS, F_SEAL_WRITE) == 0);
+    assert(write(fd, "x", 1) < 0 && errno == EPERM);
+    assert(mmap(NULL, page_size, PROT_READ | PROT_WRITE, MAP_SHARED, fd, 0) == MAP_FAILED
+           && errno == EPERM);
+
+    // 只读的共享映射不能通过mprotect变为可写
+    p = mmap(NULL, page_size, PROT_READ, MAP_SHARED, fd, 0);
+    assert(p != MAP_FAILED);
+    assert(strcmp(p, "hello") == 0);
+    assert(mprotect(p, page_size, PROT_READ | PROT_WRITE) < 0 && errno == EACCES);
+    munmap(p, page_size);
+
+    // 私有映射不受影响
+    p = mmap(NULL, page_size, PROT_READ | PROT_WRITE, MAP_PRIVATE, fd, 0);
+    assert(p != MAP_FAILED);
+    p[0] = 'H';
+    munmap(p, page_size);
+    close(fd);
+    printf("F_SEAL_WRITE passed\n\n");
+}
+
+static void test_seal_future_write(void)
+{
+    printf("Test F_SEAL_FUTURE_WRITE\n");
+    int fd = create_memfd("future", MFD_ALLOW_SEALING);
+    // 已有的可写映射仍然可以写入，但不能再建立新的可写映射
+    char *p = mmap(NULL, page_size, PROT_READ | PROT_WRITE, MAP_SHARED, fd, 0);
+    assert(p != MAP_FAILED);
+    assert(fcntl(fd, F_ADD_SEALS, F_SEAL_FUTURE_WRITE) == 0);
+    p[0] = 'a';
+    assert(mmap(NULL, page_size, PROT_READ | PROT_WRITE, MAP_SHARED, fd, 0) == MAP_FAILED
+           && errno == EPERM);
+    assert(write(fd, "x", 1) < 0 && errno == EPERM);
+    munmap(p, page_size);
+    close(fd);
+    printf("F_SEAL_FUTURE_WRITE passed\n\n");
+}
+
+int main()
+{
+    page_size = sysconf(_SC_PAGESIZE);
+    test_seals();
+    test_seal_write();
+    test_seal_future_write();
+    printf("All memfd tests passed\n");
+    return 0;
+}
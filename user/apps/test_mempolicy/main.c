@@ -0,0 +1,161 @@
+#define _GNU_SOURCE
+#include <assert.h>
+#include <errno.h>
+#include <stdio.h>
+#include <string.h>
+#include <sys/mman.h>
+#include <sys/syscall.h>
+#include <unistd.h>
+
+#define MPOL_DEFAULT 0
+#define MPOL_PREFERRED 1
+#define MPOL_BIND 2
+#define MPOL_INTERLEAVE 3
+#define MPOL_LOCAL 4
+
+#define MPOL_F_NODE (1 << 0)
+#define MPOL_F_ADDR (1 << 1)
+#define MPOL_F_MEMS_ALLOWED (1 << 2)
+
+#define MPOL_MF_STRICT (1 << 0)
+
+#define MAXNODE 64
+#define PAGE 4096
+
+static long set_mempolicy(int mode, const unsigned long *nmask, unsigned long maxnode)
+{
+    return syscall(SYS_set_mempolicy, mode, nmask, maxnode);
+}
+
+static long get_mempolicy(int *mode, unsigned long *nmask, unsigned long maxnode, void *addr,
+                          unsigned long flags)
+{
+    return syscall(SYS_get_mempolicy, mode, nmask, maxnode, addr, flags);
+}
+
+static long mbind(void *addr, unsigned long len, int mode, const unsigned long *nmask,
+                  unsigned long maxnode, unsigned flags)
+{
+    return syscall(SYS_mbind, addr, len, mode, nmask, maxnode, flags);
+}
+
+static void expect_einval(long ret, const char *what)
+{
+    if (ret != -1 || errno != EINVAL) {
+        printf("%s: expected EINVAL, got ret=%ld errno=%d\n", what, ret, errno);
+        assert(0);
+    }
+}
+
+static void test_invalid_arguments()
+{
+    printf("Test invalid modes and nodemasks\n");
+    unsigned long node0 = 1;
+    unsigned long empty = 0;
+    unsigned long far_node = 1UL << 63;
+
+    expect_einval(set_mempolicy(5, NULL, 0), "unknown mode");
+    expect_einval(set_mempolicy(MPOL_DEFAULT, &node0, MAXNODE), "MPOL_DEFAULT with nodes");
+    expect_einval(set_mempolicy(MPOL_BIND, &empty, MAXNODE), "MPOL_BIND with empty nodemask");
+    expect_einval(set_mempolicy(MPOL_INTERLEAVE, NULL, 0), "MPOL_INTERLEAVE without nodemask");
+    expect_einval(set_mempolicy(MPOL_LOCAL, &node0, MAXNODE), "MPOL_LOCAL with nodes");
+    expect_einval(set_mempolicy(MPOL_BIND, &far_node, MAXNODE), "MPOL_BIND with offline node");
+    expect_einval(get_mempolicy(NULL, NULL, 0, NULL, 1 << 3), "get_mempolicy unknown flag");
+    expect_einval(get_mempolicy(NULL, NULL, 0, (void *)PAGE, 0), "get_mempolicy addr without MPOL_F_ADDR");
+
+    // 失败的调用不能改变当前的策略
+    int mode = -1;
+    assert(get_mempolicy(&mode, NULL, 0, NULL, 0) == 0);
+    assert(mode == MPOL_DEFAULT);
+    printf("invalid arguments passed\n\n");
+}
+
+static void test_task_policy()
+{
+    printf("Test set_mempolicy/get_mempolicy round trip\n");
+    unsigned long node0 = 1;
+    unsigned long mask = 0;
+    int mode = -1;
+
+    assert(set_mempolicy(MPOL_BIND, &node0, MAXNODE) == 0);
+    assert(get_mempolicy(&mode, &mask, MAXNODE, NULL, 0) == 0);
+    assert(mode == MPOL_BIND);
+    assert(mask == node0);
+
+    assert(set_mempolicy(MPOL_INTERLEAVE, &node0, MAXNODE) == 0);
+    mask = 0;
+    assert(get_mempolicy(&mode, &mask, MAXNODE, NULL, 0) == 0);
+    assert(mode == MPOL_INTERLEAVE);
+    assert(mask == node0);
+    // MPOL_INTERLEAVE下MPOL_F_NODE返回下一次分配将使用的节点
+    assert(get_mempolicy(&mode, NULL, 0, NULL, MPOL_F_NODE) == 0);
+    assert(mode == 0);
+
+    // 策略下分配内存仍然正常
+    char *p = mmap(NULL, 16 * PAGE, PROT_READ | PROT_WRITE, MAP_PRIVATE | MAP_ANONYMOUS, -1, 0);
+    assert(p != MAP_FAILED);
+    memset(p, 0x5a, 16 * PAGE);
+    assert(p[15 * PAGE] == 0x5a);
+    assert(munmap(p, 16 * PAGE) == 0);
+
+    assert(set_mempolicy(MPOL_DEFAULT, NULL, 0) == 0);
+    mask = ~0UL;
+    assert(get_mempolicy(&mode, &mask, MAXNODE, NULL, 0) == 0);
+    assert(mode == MPOL_DEFAULT);
+    assert(mask == 0);
+
+    mask = 0;
+    assert(get_mempolicy(NULL, &mask, MAXNODE, NULL, MPOL_F_MEMS_ALLOWED) == 0);
+    assert(mask & node0);
+    printf("task policy passed\n\n");
+}
+
+static void test_mbind()
+{
+    printf("Test mbind\n");
+    unsigned long node0 = 1;
+    unsigned long mask = 0;
+    int mode = -1;
+    char *p = mmap(NULL, 4 * PAGE, PROT_READ | PROT_WRITE, MAP_PRIVATE | MAP_ANONYMOUS, -1, 0);
+    assert(p != MAP_FAILED);
+
+    expect_einval(mbind(p + 1, PAGE, MPOL_BIND, &node0, MAXNODE, 0), "mbind unaligned address");
+    expect_einval(mbind(p, PAGE, 7, &node0, MAXNODE, 0), "mbind unknown mode");
+    expect_einval(mbind(p, PAGE, MPOL_BIND, &node0, MAXNODE, 1 << 5), "mbind unknown flag");
+
+    // 只对中间的两页设置策略
+    assert(mbind(p + PAGE, 2 * PAGE, MPOL_BIND, &node0, MAXNODE, 0) == 0);
+    assert(get_mempolicy(&mode, &mask, MAXNODE, p + PAGE, MPOL_F_ADDR) == 0);
+    assert(mode == MPOL_BIND);
+    assert(mask == node0);
+    mask = 0;
+    assert(get_mempolicy(&mode, &mask, MAXNODE, p, MPOL_F_ADDR) == 0);
+    assert(mode == MPOL_DEFAULT);
+    assert(mask == 0);
+
+    p[PAGE] = 1;
+    assert(get_mempolicy(&mode, NULL, 0, p + PAGE, MPOL_F_ADDR | MPOL_F_NODE) == 0);
+    assert(mode == 0);
+    // 已有的页面都在节点0上，MPOL_MF_STRICT检查通过
+    assert(mbind(p + PAGE, 2 * PAGE, MPOL_BIND, &node0, MAXNODE, MPOL_MF_STRICT) == 0);
+
+    assert(mbind(p + PAGE, 2 * PAGE, MPOL_DEFAULT, NULL, 0, 0) == 0);
+    assert(get_mempolicy(&mode, NULL, 0, p + PAGE, MPOL_F_ADDR) == 0);
+    assert(mode == MPOL_DEFAULT);
+
+    assert(munmap(p, 4 * PAGE) == 0);
+    errno = 0;
+    assert(mbind(p, PAGE, MPOL_BIND, &node0, MAXNODE, 0) == -1 && errno == EFAULT);
+    errno = 0;
+    assert(get_mempolicy(&mode, NULL, 0, p, MPOL_F_ADDR) == -1 && errno == EFAULT);
+    printf("mbind passed\n\n");
+}
+
+int main()
+{
+    test_invalid_arguments();
+    test_task_policy();
+    test_mbind();
+    printf("All mempolicy tests passed\n");
+    return 0;
+}
@@ -0,0 +1,122 @@
+#define _GNU_SOURCE
+#include <assert.h>
+#include <errno.h>
+#include <stdio.h>
+#include <sys/mman.h>
+#include <sys/resource.h>
+#include <sys/syscall.h>
+#include <sys/wait.h>
+#include <unistd.h>
+
+#define NR_PAGES 8
+
+#ifndef MLOCK_ONFAULT
+#define MLOCK_ONFAULT 0x01
+#endif
+
+static long page_size;
+
+static int do_mlock2(void *addr, size_t len, unsigned int flags)
+{
+    return syscall(SYS_mlock2, addr, len, flags);
+}
+
+static int resident_pages(char *buf, int nr)
+{
+    unsigned char vec[NR_PAGES];
+    assert(mincore(buf, nr * page_size, vec) == 0);
+    int n = 0;
+    for (int i = 0; i < nr; i++)
+        n += vec[i] & 1;
+    return n;
+}
+
+static char *map_pages(int nr)
+{
+    char *p = mmap(NULL, nr * page_size, PROT_READ | PROT_WRITE, MAP_PRIVATE | MAP_ANONYMOUS, -1, 0);
+    assert(p != MAP_FAILED);
+    return p;
+}
+
+static void test_mincore(void)
+{
+    printf("Test mincore\n");
+    char *p = map_pages(NR_PAGES);
+    int before = resident_pages(p, NR_PAGES);
+    p[0] = 1;
+    p[3 * page_size] = 1;
+    assert(resident_pages(p, NR_PAGES) >= 2);
+    printf("resident pages: %d -> %d\n", before, resident_pages(p, NR_PAGES));
+
+    unsigned char vec[NR_PAGES];
+    // 地址没有按页对齐
+    assert(mincore(p + 1, page_size, vec) < 0 && errno == EINVAL);
+    munmap(p, NR_PAGES * page_size);
+    // 区域没有被映射
+    assert(mincore(p, page_size, vec) < 0 && errno == ENOMEM);
+    printf("mincore passed\n\n");
+}
+
+static void test_mlock(void)
+{
+    printf("Test mlock\n");
+    // mlock之后所有页面都已经建立映射
+    char *p = map_pages(NR_PAGES);
+    assert(mlock(p, NR_PAGES * page_size) == 0);
+    assert(resident_pages(p, NR_PAGES) == NR_PAGES);
+    assert(munlock(p, NR_PAGES * page_size) == 0);
+    munmap(p, NR_PAGES * page_size);
+
+    // MLOCK_ONFAULT只锁定之后被访问的页面
+    p = map_pages(NR_PAGES);
+    assert(do_mlock2(p, NR_PAGES * page_size, MLOCK_ONFAULT) == 0);
+    assert(resident_pages(p, NR_PAGES) == 0);
+    p[page_size] = 1;
+    assert(resident_pages(p, NR_PAGES) == 1);
+    assert(do_mlock2(p, page_size, 0x100) < 0 && errno == EINVAL);
+    munmap(p, NR_PAGES * page_size);
+
+    // mlockall(MCL_CURRENT | MCL_FUTURE)：已有以及之后的映射都会被锁定
+    assert(mlockall(0) < 0 && errno == EINVAL);
+    assert(mlockall(MCL_CURRENT | MCL_FUTURE) == 0);
+    p = map_pages(NR_PAGES);
+    assert(resident_pages(p, NR_PAGES) == NR_PAGES);
+    assert(munlockall() == 0);
+    munmap(p, NR_PAGES * page_size);
+    printf("mlock passed\n\n");
+}
+
+static void test_memlock_rlimit(void)
+{
+    printf("Test RLIMIT_MEMLOCK\n");
+    pid_t pid = fork();
+    if (pid == 0) {
+        // 放弃CAP_IPC_LOCK之后受到RLIMIT_MEMLOCK的限制
+        struct rlimit rl = { 2 * page_size, 2 * page_size };
+        if (setrlimit(RLIMIT_MEMLOCK, &rl) != 0 || setuid(1000) != 0)
+            _exit(1);
+        char *p = map_pages(NR_PAGES);
+        if (mlock(p, 2 * page_size) != 0)
+            _exit(2);
+        if (mlock(p, NR_PAGES * page_size) == 0 || errno != ENOMEM)
+            _exit(3);
+        // 已经锁定的部分不会被重复计算
+        if (mlock(p, page_size) != 0)
+            _exit(4);
+        _exit(0);
+    }
+    int status;
+    assert(waitpid(pid, &status, 0) == pid);
+    assert(WIFEXITED(status) && WEXITSTATUS(status) == 0);
+    printf("RLIMIT_MEMLOCK passed\n\n");
+}
+
+int main()
+{
+    page_size = sysconf(_SC_PAGESIZE);
+    test_mincore();
+    test_mlock();
+    test_memlock_rlimit();
+    printf("All mlock tests passed\n");
+    return 0;
+}
@@ -0,0 +1,132 @@
+#define _GNU_SOURCE
+#include <assert.h>
+#include <errno.h>
+#include <fcntl.h>
+#include <signal.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <sys/mman.h>
+#include <sys/wait.h>
+#include <unistd.h>
+
+#define CHUNK (1 << 20)
+
+static int read_proc(pid_t pid, const char *name)
+{
+    char path[64], buf[32];
+    snprintf(path, sizeof(path), "/proc/%d/%s", pid, name);
+    int fd = open(path, O_RDONLY);
+    assert(fd >= 0);
+    ssize_t n = read(fd, buf, sizeof(buf) - 1);
+    assert(n > 0);
+    buf[n] = 0;
+    close(fd);
+    return atoi(buf);
+}
+
+// 写入成功返回0，否则返回errno
+static int write_adj(pid_t pid, int adj)
+{
+    char path[64], buf[32];
+    snprintf(path, sizeof(path), "/proc/%d/oom_score_adj", pid);
+    int fd = open(path, O_WRONLY);
+    assert(fd >= 0);
+    int len = snprintf(buf, sizeof(buf), "%d\n", adj);
+    int ret = write(fd, buf, len) == len ? 0 : errno;
+    close(fd);
+    return ret;
+}
+
+static void test_score_adj(void)
+{
+    printf("Test oom_score_adj and oom_score\n");
+    pid_t self = getpid();
+    assert(read_proc(self, "oom_score_adj") == 0);
+    int score = read_proc(self, "oom_score");
+    printf("oom_score: %d\n", score);
+    assert(score >= 0 && score <= 2000);
+
+    assert(write_adj(self, 500) == 0);
+    assert(read_proc(self, "oom_score_adj") == 500);
+    assert(read_proc(self, "oom_score") > score);
+    assert(write_adj(self, 1001) == EINVAL);
+    assert(write_adj(self, -1001) == EINVAL);
+
+    // oom_score_adj为-1000的进程不会被杀死，分数为0
+    assert(write_adj(self, -1000) == 0);
+    assert(read_proc(self, "oom_score") == 0);
+
+    // 没有CAP_SYS_RESOURCE时不能降低oom_score_adj
+    pid_t pid = fork();
+    if (pid == 0) {
+        if (write_adj(getpid(), 100) != 0 || setuid(65534) != 0)
+            _exit(1);
+        if (write_adj(getpid(), 200) != 0)
+            _exit(2);
+        if (write_adj(getpid(), 0) != EACCES)
+            _exit(3);
+        _exit(read_proc(getpid(), "oom_score_adj") == 200 ? 0 : 4);
+    }
+    int status;
+    assert(waitpid(pid, &status, 0) == pid);
+    assert(WIFEXITED(status) && WEXITSTATUS(status) == 0);
+    printf("oom_score_adj and oom_score passed\n\n");
+}
+
+static void test_oom_kill(void)
+{
+    printf("Test OOM victim selection\n");
+    // 当前进程的oom_score_adj已经为-1000，不会被杀死
+    assert(read_proc(getpid(), "oom_score_adj") == -1000);
+
+    // 另一个被保护的进程，内存占用较多但不会被选中
+    int ready[2];
+    assert(pipe(ready) == 0);
+    pid_t bystander = fork();
+    if (bystander == 0) {
+        char *p = mmap(NULL, 16 * CHUNK, PROT_READ | PROT_WRITE, MAP_PRIVATE | MAP_ANONYMOUS, -1, 0);
+        if (p == MAP_FAILED)
+            _exit(1);
+        memset(p, 1, 16 * CHUNK);
+        write(ready[1], "x", 1);
+        for (;;)
+            pause();
+    }
+    char c;
+    assert(read(ready[0], &c, 1) == 1);
+
+    // 不断申请内存直到被OOM killer杀死
+    pid_t hog = fork();
+    if (hog == 0) {
+        if (write_adj(getpid(), 1000) != 0)
+            _exit(1);
+        for (;;) {
+            char *p = mmap(NULL, CHUNK, PROT_READ | PROT_WRITE, MAP_PRIVATE | MAP_ANONYMOUS, -1, 0);
+            if (p == MAP_FAILED)
+                continue;
+            memset(p, 1, CHUNK);
+        }
+    }
+    int status;
+    assert(waitpid(hog, &status, 0) == hog);
+    printf("hog status: %#x\n", status);
+    assert(WIFSIGNALED(status) && WTERMSIG(status) == SIGKILL);
+
+    // 被保护的进程仍然存活
+    assert(waitpid(bystander, &status, WNOHANG) == 0);
+    assert(kill(bystander, SIGKILL) == 0);
+    assert(waitpid(bystander, &status, 0) == bystander);
+    assert(WIFSIGNALED(status) && WTERMSIG(status) == SIGKILL);
+    close(ready[0]);
+    close(ready[1]);
+    printf("OOM victim selection passed\n\n");
+}
+
+int main()
+{
+    test_score_adj();
+    test_oom_kill();
+    printf("All OOM tests passed\n");
+    return 0;
+}
@@ -0,0 +1,113 @@
+#define _GNU_SOURCE
+#include <assert.h>
+#include <fcntl.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <sys/mman.h>
+#include <unistd.h>
+
+#define PAGE 4096
+#define NR_PAGES 1024
+
+// /proc/zoneinfo中所有CPU的页帧缓存统计信息之和
+struct pcp_stats {
+    int nr_cpus;
+    long count;
+    long high;
+    long batch;
+    long alloc_hit;
+    long refill;
+    long free;
+    long drained;
+};
+
+static char buf[64 * 1024];
+
+static void read_pcp_stats(struct pcp_stats *stats)
+{
+    int fd = open("/proc/zoneinfo", O_RDONLY);
+    assert(fd >= 0);
+    ssize_t len = 0, n;
+    while ((n = read(fd, buf + len, sizeof(buf) - 1 - len)) > 0)
+        len += n;
+    assert(len > 0);
+    buf[len] = 0;
+    close(fd);
+
+    memset(stats, 0, sizeof(*stats));
+    for (char *line = strtok(buf, "\n"); line != NULL; line = strtok(NULL, "\n")) {
+        while (*line == ' ')
+            line++;
+        long value;
+        if (sscanf(line, "cpu: %ld", &value) == 1) {
+            stats->nr_cpus++;
+        } else if (sscanf(line, "count: %ld", &value) == 1) {
+            stats->count += value;
+        } else if (sscanf(line, "high: %ld", &value) == 1) {
+            stats->high = value;
+        } else if (sscanf(line, "batch: %ld", &value) == 1) {
+            stats->batch = value;
+        } else if (sscanf(line, "alloc_hit: %ld", &value) == 1) {
+            stats->alloc_hit += value;
+        } else if (sscanf(line, "refill: %ld", &value) == 1) {
+            stats->refill += value;
+        } else if (sscanf(line, "free: %ld", &value) == 1) {
+            stats->free += value;
+        } else if (sscanf(line, "drained: %ld", &value) == 1) {
+            stats->drained += value;
+        }
+    }
+}
+
+static long meminfo(const char *key)
+{
+    int fd = open("/proc/meminfo", O_RDONLY);
+    assert(fd >= 0);
+    ssize_t n = read(fd, buf, sizeof(buf) - 1);
+    assert(n > 0);
+    buf[n] = 0;
+    close(fd);
+    char *p = strstr(buf, key);
+    assert(p != NULL);
+    return strtol(p + strlen(key), NULL, 10);
+}
+
+int main()
+{
+    printf("Test per-CPU page cache statistics\n");
+    struct pcp_stats before, after;
+    read_pcp_stats(&before);
+    printf("cpus: %d, count: %ld, high: %ld, batch: %ld\n", before.nr_cpus, before.count, before.high,
+           before.batch);
+    assert(before.nr_cpus > 0);
+    assert(before.batch > 0 && before.high >= before.batch);
+    assert(before.count <= before.high * before.nr_cpus);
+
+    // 单个页帧的分配和释放经过每CPU缓存
+    char *p = mmap(NULL, NR_PAGES * PAGE, PROT_READ | PROT_WRITE, MAP_PRIVATE | MAP_ANONYMOUS, -1, 0);
+    assert(p != MAP_FAILED);
+    // 避免使用透明大页，保证每个页帧都单独分配和释放
+    assert(madvise(p, NR_PAGES * PAGE, MADV_NOHUGEPAGE) == 0);
+    for (int i = 0; i < NR_PAGES; i++)
+        p[i * PAGE] = 1;
+    long free_kb = meminfo("MemFree:");
+    assert(munmap(p, NR_PAGES * PAGE) == 0);
+    // 缓存中的页帧算作空闲内存
+    long free_kb_after = meminfo("MemFree:");
+    printf("MemFree: %ld kB -> %ld kB\n", free_kb, free_kb_after);
+    assert(free_kb_after >= free_kb + NR_PAGES * PAGE / 1024 / 2);
+
+    read_pcp_stats(&after);
+    printf("alloc_hit: +%ld, refill: +%ld, free: +%ld, drained: +%ld\n",
+           after.alloc_hit - before.alloc_hit, after.refill - before.refill, after.free - before.free,
+           after.drained - before.drained);
+    assert(after.alloc_hit - before.alloc_hit + (after.refill - before.refill) * after.batch >= NR_PAGES);
+    assert(after.free - before.free >= NR_PAGES);
+    // 缓存超过上限时归还给伙伴分配器
+    assert(after.drained - before.drained >= NR_PAGES - after.high * after.nr_cpus);
+    assert(after.count <= after.high * after.nr_cpus);
+    printf("per-CPU page cache statistics passed\n\n");
+    printf("All pcp tests passed\n");
+    return 0;
+}
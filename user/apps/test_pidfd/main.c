@@ -0,0 +1,196 @@
+#define _GNU_SOURCE
+#include <assert.h>
+#include <errno.h>
+#include <fcntl.h>
+#include <linux/sched.h>
+#include <poll.h>
+#include <signal.h>
+#include <stdint.h>
+#include <stdio.h>
+#include <string.h>
+#include <sys/syscall.h>
+#include <sys/wait.h>
+#include <unistd.h>
+
+#ifndef SYS_pidfd_open
+#define SYS_pidfd_open 434
+#endif
+#ifndef SYS_clone3
+#define SYS_clone3 435
+#endif
+#ifndef SYS_pidfd_send_signal
+#define SYS_pidfd_send_signal 424
+#endif
+#ifndef SYS_pidfd_getfd
+#define SYS_pidfd_getfd 438
+#endif
+#ifndef P_PIDFD
+#define P_PIDFD 3
+#endif
+
+#define TARGET_FD 50
+
+static int pidfd_open(pid_t pid, unsigned int flags)
+{
+    return syscall(SYS_pidfd_open, pid, flags);
+}
+
+static int pidfd_send_signal(int pidfd, int sig)
+{
+    return syscall(SYS_pidfd_send_signal, pidfd, sig, NULL, 0);
+}
+
+// 检查pidfd是否可读，即进程是否已经退出
+static int pidfd_readable(int pidfd, int timeout)
+{
+    struct pollfd pfd = { pidfd, POLLIN, 0 };
+    int r = poll(&pfd, 1, timeout);
+    assert(r >= 0);
+    return r == 1 && (pfd.revents & POLLIN);
+}
+
+static void test_pidfd_open(void)
+{
+    printf("Test pidfd_open and pidfd_send_signal\n");
+    pid_t pid = fork();
+    if (pid == 0) {
+        for (;;)
+            pause();
+    }
+    int pidfd = pidfd_open(pid, 0);
+    assert(pidfd >= 0);
+    assert(fcntl(pidfd, F_GETFD) & FD_CLOEXEC);
+    assert(!pidfd_readable(pidfd, 100));
+
+    // 子进程退出之后pidfd变为可读，并且可以通过waitid(P_PIDFD)回收
+    assert(pidfd_send_signal(pidfd, SIGTERM) == 0);
+    assert(pidfd_readable(pidfd, 5000));
+    siginfo_t info;
+    memset(&info, 0, sizeof(info));
+    assert(waitid(P_PIDFD, pidfd, &info, WEXITED) == 0);
+    assert(info.si_pid == pid && info.si_code == CLD_KILLED && info.si_status == SIGTERM);
+
+    // 进程已经被回收
+    assert(pidfd_send_signal(pidfd, SIGTERM) < 0 && errno == ESRCH);
+    assert(waitid(P_PIDFD, pidfd, &info, WEXITED) < 0 && errno == ECHILD);
+    close(pidfd);
+
+    assert(pidfd_open(pid, 0) < 0 && errno == ESRCH);
+    assert(pidfd_open(getpid(), 0x1234) < 0 && errno == EINVAL);
+    assert(pidfd_send_signal(0, SIGTERM) < 0 && errno == EBADF);
+    printf("pidfd_open and pidfd_send_signal passed\n\n");
+}
+
+static void test_clone_pidfd(void)
+{
+    printf("Test CLONE_PIDFD\n");
+    int pidfd = -1;
+    struct clone_args args;
+    memset(&args, 0, sizeof(args));
+    args.flags = CLONE_PIDFD;
+    args.pidfd = (uint64_t)(uintptr_t)&pidfd;
+    args.exit_signal = SIGCHLD;
+    pid_t pid = syscall(SYS_clone3, &args, sizeof(args));
+    assert(pid >= 0);
+    if (pid == 0) {
+        // 子进程不会继承为它自己创建的pidfd
+        _exit(fcntl(pidfd, F_GETFD) < 0 && errno == EBADF ? 0 : 1);
+    }
+    assert(pidfd >= 0);
+    assert(fcntl(pidfd, F_GETFD) & FD_CLOEXEC);
+    siginfo_t info;
+    memset(&info, 0, sizeof(info));
+    assert(waitid(P_PIDFD, pidfd, &info, WEXITED) == 0);
+    assert(info.si_pid == pid && info.si_code == CLD_EXITED && info.si_status == 0);
+    close(pidfd);
+
+    // pidfd的地址无效时clone失败，父进程中不会留下pidfd
+    int fd = dup(0);
+    assert(fd >= 0);
+    close(fd);
+    args.pidfd = 8;
+    assert(syscall(SYS_clone3, &args, sizeof(args)) < 0 && errno == EFAULT);
+    assert(fcntl(fd, F_GETFD) < 0 && errno == EBADF);
+    printf("CLONE_PIDFD passed\n\n");
+}
+
+static void test_pidfd_getfd(void)
+{
+    printf("Test pidfd_getfd\n");
+    int data[2], ready[2];
+    assert(pipe(data) == 0 && pipe(ready) == 0);
+    pid_t pid = fork();
+    if (pid == 0) {
+        // 把管道的读端移动到TARGET_FD，然后等待被杀死
+        dup2(data[0], TARGET_FD);
+        close(data[0]);
+        close(data[1]);
+        close(ready[0]);
+        write(ready[1], "x", 1);
+        for (;;)
+            pause();
+    }
+    close(data[0]);
+    close(ready[1]);
+    char c;
+    assert(read(ready[0], &c, 1) == 1);
+
+    int pidfd = pidfd_open(pid, 0);
+    assert(pidfd >= 0);
+    int fd = syscall(SYS_pidfd_getfd, pidfd, TARGET_FD, 0);
+    assert(fd >= 0);
+    assert(fcntl(fd, F_GETFD) & FD_CLOEXEC);
+    assert(write(data[1], "hi", 2) == 2);
+    char buf[2];
+    assert(read(fd, buf, 2) == 2 && memcmp(buf, "hi", 2) == 0);
+    assert(syscall(SYS_pidfd_getfd, pidfd, TARGET_FD + 1, 0) < 0 && errno == EBADF);
+    assert(syscall(SYS_pidfd_getfd, pidfd, TARGET_FD, 1) < 0 && errno == EINVAL);
+
+    assert(pidfd_send_signal(pidfd, SIGKILL) == 0);
+    assert(waitpid(pid, NULL, 0) == pid);
+    close(fd);
+    close(pidfd);
+    close(data[1]);
+    close(ready[0]);
+    printf("pidfd_getfd passed\n\n");
+}
+
+static void test_wait_not_child(void)
+{
+    printf("Test waiting for a process that is not a child\n");
+    int status;
+    assert(waitpid(getppid(), &status, WNOHANG) < 0 && errno == ECHILD);
+    assert(waitpid(1, &status, 0) < 0 && errno == ECHILD);
+
+    // 孙进程不是当前进程的子进程
+    int fds[2];
+    assert(pipe(fds) == 0);
+    pid_t pid = fork();
+    if (pid == 0) {
+        pid_t grandchild = fork();
+        if (grandchild == 0) {
+            usleep(200000);
+            _exit(0);
+        }
+        write(fds[1], &grandchild, sizeof(grandchild));
+        waitpid(grandchild, NULL, 0);
+        _exit(0);
+    }
+    pid_t grandchild;
+    assert(read(fds[0], &grandchild, sizeof(grandchild)) == sizeof(grandchild));
+    assert(waitpid(grandchild, &status, 0) < 0 && errno == ECHILD);
+    assert(waitpid(pid, &status, 0) == pid);
+    close(fds[0]);
+    close(fds[1]);
+    printf("waiting for a process that is not a child passed\n\n");
+}
+
+int main()
+{
+    test_pidfd_open();
+    test_clone_pidfd();
+    test_pidfd_getfd();
+    test_wait_not_child();
+    printf("All pidfd tests passed\n");
+    return 0;
+}
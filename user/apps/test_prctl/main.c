@@ -0,0 +1,173 @@
+#define _GNU_SOURCE
+#include <assert.h>
+#include <errno.h>
+#include <signal.h>
+#include <stdio.h>
+#include <string.h>
+#include <sys/prctl.h>
+#include <sys/wait.h>
+#include <unistd.h>
+
+#ifndef PR_SET_CHILD_SUBREAPER
+#define PR_SET_CHILD_SUBREAPER 36
+#define PR_GET_CHILD_SUBREAPER 37
+#endif
+#ifndef PR_SET_NO_NEW_PRIVS
+#define PR_SET_NO_NEW_PRIVS 38
+#define PR_GET_NO_NEW_PRIVS 39
+#endif
+
+#define CAP_NET_RAW 13
+#define CAP_LAST_CAP 40
+
+static void test_name()
+{
+    printf("Test PR_SET_NAME/PR_GET_NAME\n");
+    char name[32];
+
+    assert(prctl(PR_SET_NAME, "prctl-test", 0, 0, 0) == 0);
+    memset(name, 'x', sizeof(name));
+    assert(prctl(PR_GET_NAME, name, 0, 0, 0) == 0);
+    assert(strcmp(name, "prctl-test") == 0);
+
+    // 名字最长15字节，超出的部分被截断
+    assert(prctl(PR_SET_NAME, "0123456789abcdefghij", 0, 0, 0) == 0);
+    memset(name, 'x', sizeof(name));
+    assert(prctl(PR_GET_NAME, name, 0, 0, 0) == 0);
+    assert(strcmp(name, "0123456789abcde") == 0);
+    printf("name passed\n\n");
+}
+
+static volatile sig_atomic_t got_pdeathsig = 0;
+
+static void pdeathsig_handler(int sig)
+{
+    got_pdeathsig = sig;
+}
+
+// 子进程A创建孙进程B后退出，B应当收到父进程退出信号，并被设置了child subreaper的当前进程收养
+static void test_pdeathsig_and_subreaper()
+{
+    printf("Test PR_SET_PDEATHSIG and PR_SET_CHILD_SUBREAPER\n");
+    int value = -1;
+    assert(prctl(PR_SET_CHILD_SUBREAPER, 1, 0, 0, 0) == 0);
+    assert(prctl(PR_GET_CHILD_SUBREAPER, &value, 0, 0, 0) == 0);
+    assert(value == 1);
+
+    pid_t reaper = getpid();
+    int ready[2], result[2];
+    assert(pipe(ready) == 0 && pipe(result) == 0);
+
+    pid_t a = fork();
+    assert(a >= 0);
+    if (a == 0) {
+        pid_t b = fork();
+        assert(b >= 0);
+        if (b == 0) {
+            signal(SIGUSR1, pdeathsig_handler);
+            assert(prctl(PR_SET_PDEATHSIG, SIGUSR1, 0, 0, 0) == 0);
+            int sig = 0;
+            assert(prctl(PR_GET_PDEATHSIG, &sig, 0, 0, 0) == 0);
+            assert(sig == SIGUSR1);
+            assert(write(ready[1], "r", 1) == 1);
+
+            for (int i = 0; i < 500 && !got_pdeathsig; i++)
+                usleep(10000);
+            int res[2] = {got_pdeathsig, getppid()};
+            assert(write(result[1], res, sizeof(res)) == sizeof(res));
+            _exit(0);
+        }
+        char c;
+        assert(read(ready[0], &c, 1) == 1);
+        _exit(0);
+    }
+
+    int status;
+    assert(waitpid(a, &status, 0) == a);
+    assert(WIFEXITED(status) && WEXITSTATUS(status) == 0);
+
+    int res[2];
+    assert(read(result[0], res, sizeof(res)) == sizeof(res));
+    printf("grandchild got signal %d, new parent %d (reaper %d)\n", res[0], res[1], reaper);
+    assert(res[0] == SIGUSR1);
+    assert(res[1] == reaper);
+    // 孙进程被收养后由当前进程回收
+    pid_t b = wait(&status);
+    assert(b > 0);
+    assert(WIFEXITED(status) && WEXITSTATUS(status) == 0);
+
+    assert(prctl(PR_SET_CHILD_SUBREAPER, 0, 0, 0, 0) == 0);
+    assert(prctl(PR_GET_CHILD_SUBREAPER, &value, 0, 0, 0) == 0);
+    assert(value == 0);
+    close(ready[0]);
+    close(ready[1]);
+    close(result[0]);
+    close(result[1]);
+    printf("pdeathsig and subreaper passed\n\n");
+}
+
+static void test_no_new_privs()
+{
+    printf("Test PR_SET_NO_NEW_PRIVS\n");
+    pid_t pid = fork();
+    assert(pid >= 0);
+    if (pid == 0) {
+        assert(prctl(PR_GET_NO_NEW_PRIVS, 0, 0, 0, 0) == 0);
+        errno = 0;
+        assert(prctl(PR_SET_NO_NEW_PRIVS, 0, 0, 0, 0) == -1 && errno == EINVAL);
+        assert(prctl(PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) == 0);
+        assert(prctl(PR_GET_NO_NEW_PRIVS, 0, 0, 0, 0) == 1);
+        // 设置之后不能再清除
+        errno = 0;
+        assert(prctl(PR_SET_NO_NEW_PRIVS, 0, 0, 0, 0) == -1 && errno == EINVAL);
+        assert(prctl(PR_GET_NO_NEW_PRIVS, 0, 0, 0, 0) == 1);
+
+        // 子进程继承该标志
+        pid_t child = fork();
+        assert(child >= 0);
+        if (child == 0)
+            _exit(prctl(PR_GET_NO_NEW_PRIVS, 0, 0, 0, 0) == 1 ? 0 : 1);
+        int status;
+        assert(waitpid(child, &status, 0) == child);
+        _exit(WIFEXITED(status) && WEXITSTATUS(status) == 0 ? 0 : 1);
+    }
+    int status;
+    assert(waitpid(pid, &status, 0) == pid);
+    assert(WIFEXITED(status) && WEXITSTATUS(status) == 0);
+    assert(prctl(PR_GET_NO_NEW_PRIVS, 0, 0, 0, 0) == 0);
+    printf("no_new_privs passed\n\n");
+}
+
+static void test_capbset()
+{
+    printf("Test PR_CAPBSET_DROP/PR_CAPBSET_READ\n");
+    errno = 0;
+    assert(prctl(PR_CAPBSET_READ, CAP_LAST_CAP + 100, 0, 0, 0) == -1 && errno == EINVAL);
+
+    // 在子进程中修改，避免影响后续的测试
+    pid_t pid = fork();
+    assert(pid >= 0);
+    if (pid == 0) {
+        assert(prctl(PR_CAPBSET_READ, CAP_NET_RAW, 0, 0, 0) == 1);
+        assert(prctl(PR_CAPBSET_DROP, CAP_NET_RAW, 0, 0, 0) == 0);
+        assert(prctl(PR_CAPBSET_READ, CAP_NET_RAW, 0, 0, 0) == 0);
+        // 其他capability不受影响
+        assert(prctl(PR_CAPBSET_READ, 0, 0, 0, 0) == 1);
+        _exit(0);
+    }
+    int status;
+    assert(waitpid(pid, &status, 0) == pid);
+    assert(WIFEXITED(status) && WEXITSTATUS(status) == 0);
+    assert(prctl(PR_CAPBSET_READ, CAP_NET_RAW, 0, 0, 0) == 1);
+    printf("capbset passed\n\n");
+}
+
+int main()
+{
+    test_name();
+    test_pdeathsig_and_subreaper();
+    test_no_new_privs();
+    test_capbset();
+    printf("All prctl tests passed\n");
+    return 0;
+}
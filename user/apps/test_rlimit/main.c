@@ -0,0 +1,229 @@
+#define _GNU_SOURCE
+#include <assert.h>
+#include <errno.h>
+#include <fcntl.h>
+#include <signal.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <sys/mman.h>
+#include <sys/resource.h>
+#include <sys/time.h>
+#include <sys/wait.h>
+#include <unistd.h>
+
+#define FSIZE_FILE "/tmp/test_rlimit_fsize"
+
+static volatile sig_atomic_t got_signal;
+
+static void handler(int sig)
+{
+    got_signal = sig;
+}
+
+static void burn_cpu(void)
+{
+    for (volatile unsigned long i = 0;; i++)
+        ;
+}
+
+static long tv_us(struct timeval tv)
+{
+    return tv.tv_sec * 1000000L + tv.tv_usec;
+}
+
+static void test_get_set(void)
+{
+    printf("Test getrlimit/setrlimit/prlimit\n");
+    struct rlimit old, rl;
+    assert(getrlimit(RLIMIT_NOFILE, &old) == 0);
+
+    // 软限制不能大于硬限制
+    rl.rlim_cur = old.rlim_max + 1;
+    rl.rlim_max = old.rlim_max;
+    if (old.rlim_max != RLIM_INFINITY)
+        assert(setrlimit(RLIMIT_NOFILE, &rl) < 0 && errno == EINVAL);
+
+    // 通过prlimit设置并读回旧值
+    struct rlimit new = { 64, old.rlim_max }, prev;
+    assert(prlimit(0, RLIMIT_NOFILE, &new, &prev) == 0);
+    assert(prev.rlim_cur == old.rlim_cur && prev.rlim_max == old.rlim_max);
+    assert(getrlimit(RLIMIT_NOFILE, &rl) == 0 && rl.rlim_cur == 64);
+
+    // 限制被子进程继承，非特权进程不能提高硬限制
+    pid_t pid = fork();
+    if (pid == 0) {
+        if (getrlimit(RLIMIT_NOFILE, &rl) != 0 || rl.rlim_cur != 64)
+            _exit(1);
+        rl.rlim_cur = rl.rlim_max = 32;
+        if (setrlimit(RLIMIT_NOFILE, &rl) != 0)
+            _exit(2);
+        if (setuid(65534) != 0)
+            _exit(3);
+        rl.rlim_max = 33;
+        if (setrlimit(RLIMIT_NOFILE, &rl) == 0 || errno != EPERM)
+            _exit(4);
+        _exit(0);
+    }
+    int status;
+    assert(waitpid(pid, &status, 0) == pid);
+    assert(WIFEXITED(status) && WEXITSTATUS(status) == 0);
+    assert(setrlimit(RLIMIT_NOFILE, &old) == 0);
+    printf("getrlimit/setrlimit/prlimit passed\n\n");
+}
+
+static void test_nofile(void)
+{
+    printf("Test RLIMIT_NOFILE\n");
+    struct rlimit old, rl;
+    assert(getrlimit(RLIMIT_NOFILE, &old) == 0);
+    rl.rlim_cur = 8;
+    rl.rlim_max = old.rlim_max;
+    assert(setrlimit(RLIMIT_NOFILE, &rl) == 0);
+
+    int fds[8], n = 0;
+    for (;;) {
+        int fd = open("/dev/null", O_RDONLY);
+        if (fd < 0) {
+            assert(errno == EMFILE);
+            break;
+        }
+        assert(fd < 8 && n < 8);
+        fds[n++] = fd;
+    }
+    assert(dup(0) < 0 && errno == EMFILE);
+    assert(fcntl(0, F_DUPFD, 0) < 0 && errno == EMFILE);
+    for (int i = 0; i < n; i++)
+        close(fds[i]);
+    assert(setrlimit(RLIMIT_NOFILE, &old) == 0);
+    printf("RLIMIT_NOFILE passed\n\n");
+}
+
+static void test_fsize(void)
+{
+    printf("Test RLIMIT_FSIZE\n");
+    pid_t pid = fork();
+    if (pid == 0) {
+        signal(SIGXFSZ, handler);
+        struct rlimit rl = { 10, 10 };
+        if (setrlimit(RLIMIT_FSIZE, &rl) != 0)
+            _exit(1);
+        int fd = open(FSIZE_FILE, O_WRONLY | O_CREAT | O_TRUNC, 0644);
+        if (fd < 0)
+            _exit(2);
+        // 写入被截断到限制以内
+        if (write(fd, "0123456789abcdef", 16) != 10)
+            _exit(3);
+        if (got_signal)
+            _exit(4);
+        // 起始位置已经达到限制时返回EFBIG并发送SIGXFSZ
+        if (write(fd, "x", 1) >= 0 || errno != EFBIG)
+            _exit(5);
+        if (got_signal != SIGXFSZ)
+            _exit(6);
+        close(fd);
+        _exit(0);
+    }
+    int status;
+    assert(waitpid(pid, &status, 0) == pid);
+    assert(WIFEXITED(status) && WEXITSTATUS(status) == 0);
+    unlink(FSIZE_FILE);
+
+    // 没有处理SIGXFSZ时进程被终止
+    pid = fork();
+    if (pid == 0) {
+        struct rlimit rl = { 0, 0 };
+        setrlimit(RLIMIT_FSIZE, &rl);
+        int fd = open(FSIZE_FILE, O_WRONLY | O_CREAT | O_TRUNC, 0644);
+        write(fd, "x", 1);
+        _exit(0);
+    }
+    assert(waitpid(pid, &status, 0) == pid);
+    assert(WIFSIGNALED(status) && WTERMSIG(status) == SIGXFSZ);
+    unlink(FSIZE_FILE);
+    printf("RLIMIT_FSIZE passed\n\n");
+}
+
+static void test_cpu(void)
+{
+    printf("Test RLIMIT_CPU\n");
+    int status;
+
+    // 超出软限制时收到SIGXCPU
+    pid_t pid = fork();
+    if (pid == 0) {
+        signal(SIGXCPU, handler);
+        struct rlimit rl = { 1, 3 };
+        if (setrlimit(RLIMIT_CPU, &rl) != 0)
+            _exit(1);
+        while (!got_signal)
+            ;
+        _exit(got_signal == SIGXCPU ? 0 : 2);
+    }
+    assert(waitpid(pid, &status, 0) == pid);
+    assert(WIFEXITED(status) && WEXITSTATUS(status) == 0);
+
+    // 忽略SIGXCPU时，超出硬限制后被SIGKILL杀死
+    pid = fork();
+    if (pid == 0) {
+        signal(SIGXCPU, SIG_IGN);
+        struct rlimit rl = { 1, 2 };
+        if (setrlimit(RLIMIT_CPU, &rl) != 0)
+            _exit(1);
+        burn_cpu();
+    }
+    assert(waitpid(pid, &status, 0) == pid);
+    assert(WIFSIGNALED(status) && WTERMSIG(status) == SIGKILL);
+    printf("RLIMIT_CPU passed\n\n");
+}
+
+static void test_getrusage(void)
+{
+    printf("Test getrusage\n");
+    struct rusage ru;
+    assert(getrusage(RUSAGE_SELF, &ru) == 0);
+    long utime = tv_us(ru.ru_utime);
+    long minflt = ru.ru_minflt;
+
+    // 用户态计算使得utime增加，访问新页面使得minflt增加
+    for (volatile unsigned long i = 0; i < 200000000UL; i++)
+        ;
+    long page_size = sysconf(_SC_PAGESIZE);
+    char *p = mmap(NULL, 16 * page_size, PROT_READ | PROT_WRITE, MAP_PRIVATE | MAP_ANONYMOUS, -1, 0);
+    assert(p != MAP_FAILED);
+    for (int i = 0; i < 16; i++)
+        p[i * page_size] = 1;
+    munmap(p, 16 * page_size);
+    assert(getrusage(RUSAGE_SELF, &ru) == 0);
+    printf("utime: %ld us, minflt: %ld\n", tv_us(ru.ru_utime), ru.ru_minflt);
+    assert(tv_us(ru.ru_utime) > utime);
+    assert(ru.ru_minflt >= minflt + 16);
+    assert(getrusage(RUSAGE_THREAD, &ru) == 0);
+
+    // 子进程被回收之后计入RUSAGE_CHILDREN
+    struct rusage before;
+    assert(getrusage(RUSAGE_CHILDREN, &before) == 0);
+    pid_t pid = fork();
+    if (pid == 0) {
+        for (volatile unsigned long i = 0; i < 200000000UL; i++)
+            ;
+        _exit(0);
+    }
+    int status;
+    assert(waitpid(pid, &status, 0) == pid);
+    assert(getrusage(RUSAGE_CHILDREN, &ru) == 0);
+    assert(tv_us(ru.ru_utime) + tv_us(ru.ru_stime) > tv_us(before.ru_utime) + tv_us(before.ru_stime));
+    assert(getrusage(-3, &ru) < 0 && errno == EINVAL);
+    printf("getrusage passed\n\n");
+}
+
+int main()
+{
+    test_get_set();
+    test_nofile();
+    test_fsize();
+    test_cpu();
+    test_getrusage();
+    printf("All rlimit tests passed\n");
+    return 0;
+}
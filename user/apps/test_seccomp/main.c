@@ -0,0 +1,258 @@
+#define _GNU_SOURCE
+#include <assert.h>
+#include <errno.h>
+#include <linux/audit.h>
+#include <linux/filter.h>
+#include <linux/seccomp.h>
+#include <signal.h>
+#include <stddef.h>
+#include <stdio.h>
+#include <string.h>
+#include <sys/prctl.h>
+#include <sys/syscall.h>
+#include <sys/wait.h>
+#include <unistd.h>
+
+#define SELF "/bin/test_seccomp"
+
+#if defined(__x86_64__)
+#define AUDIT_ARCH_CURRENT AUDIT_ARCH_X86_64
+#elif defined(__riscv) && __riscv_xlen == 64
+#define AUDIT_ARCH_CURRENT AUDIT_ARCH_RISCV64
+#endif
+
+#define LOAD_ARCH BPF_STMT(BPF_LD | BPF_W | BPF_ABS, offsetof(struct seccomp_data, arch))
+#define LOAD_NR BPF_STMT(BPF_LD | BPF_W | BPF_ABS, offsetof(struct seccomp_data, nr))
+#define LOAD_ARG0 BPF_STMT(BPF_LD | BPF_W | BPF_ABS, offsetof(struct seccomp_data, args[0]))
+#define RET(action) BPF_STMT(BPF_RET | BPF_K, (action))
+
+static int install(struct sock_filter *insns, unsigned short len)
+{
+    struct sock_fprog prog = { len, insns };
+    return syscall(SYS_seccomp, SECCOMP_SET_MODE_FILTER, 0, &prog);
+}
+
+// 对系统调用nr返回action，其他系统调用允许执行
+static int install_action(int nr, unsigned int action)
+{
+    struct sock_filter insns[] = {
+        LOAD_ARCH,
+        BPF_JUMP(BPF_JMP | BPF_JEQ | BPF_K, AUDIT_ARCH_CURRENT, 1, 0),
+        RET(SECCOMP_RET_KILL_PROCESS),
+        LOAD_NR,
+        BPF_JUMP(BPF_JMP | BPF_JEQ | BPF_K, nr, 0, 1),
+        RET(action),
+        RET(SECCOMP_RET_ALLOW),
+    };
+    return install(insns, sizeof(insns) / sizeof(insns[0]));
+}
+
+// 在子进程中运行f，返回waitpid得到的状态
+static int run_child(int (*f)(void))
+{
+    pid_t pid = fork();
+    if (pid == 0)
+        _exit(f());
+    int status;
+    assert(waitpid(pid, &status, 0) == pid);
+    return status;
+}
+
+static int strict_child(void)
+{
+    if (prctl(PR_SET_SECCOMP, SECCOMP_MODE_STRICT) != 0)
+        return 1;
+    // 严格模式下允许write，其它系统调用会使进程被杀死
+    if (syscall(SYS_write, 1, "strict write ok\n", 16) != 16)
+        syscall(SYS_exit, 2);
+    syscall(SYS_getpid);
+    syscall(SYS_exit, 3);
+    return 4;
+}
+
+static void test_strict(void)
+{
+    printf("Test strict mode\n");
+    int status = run_child(strict_child);
+    assert(WIFSIGNALED(status) && WTERMSIG(status) == SIGKILL);
+    printf("strict mode passed\n\n");
+}
+
+static int errno_child(void)
+{
+    if (prctl(PR_GET_SECCOMP) != 0)
+        return 1;
+    if (install_action(SYS_getppid, SECCOMP_RET_ERRNO | EPERM) != 0)
+        return 2;
+    if (prctl(PR_GET_SECCOMP) != SECCOMP_MODE_FILTER)
+        return 3;
+    if (syscall(SYS_getppid) >= 0 || errno != EPERM)
+        return 4;
+    if (getpid() <= 0)
+        return 5;
+
+    // 后安装的过滤器与之前的一起运行，取优先级最高的结果
+    if (install_action(SYS_getppid, SECCOMP_RET_ALLOW) != 0)
+        return 6;
+    if (syscall(SYS_getppid) >= 0 || errno != EPERM)
+        return 7;
+    if (install_action(SYS_getppid, SECCOMP_RET_ERRNO | EACCES) != 0)
+        return 8;
+    if (syscall(SYS_getppid) >= 0 || errno != EACCES)
+        return 9;
+
+    // 过滤器在fork和execve之后仍然有效
+    pid_t pid = fork();
+    if (pid == 0)
+        _exit(syscall(SYS_getppid) < 0 && errno == EACCES ? 0 : 1);
+    int status;
+    if (waitpid(pid, &status, 0) != pid || !WIFEXITED(status) || WEXITSTATUS(status) != 0)
+        return 10;
+    char *argv[] = { SELF, "--check-inherit", NULL };
+    execv(SELF, argv);
+    return 11;
+}
+
+static int check_inherit(void)
+{
+    if (prctl(PR_GET_SECCOMP) != SECCOMP_MODE_FILTER)
+        return 20;
+    if (syscall(SYS_getppid) >= 0 || errno != EACCES)
+        return 21;
+    return 0;
+}
+
+static void test_errno(void)
+{
+    printf("Test SECCOMP_RET_ERRNO and stacked filters\n");
+    int status = run_child(errno_child);
+    assert(WIFEXITED(status) && WEXITSTATUS(status) == 0);
+    printf("SECCOMP_RET_ERRNO and stacked filters passed\n\n");
+}
+
+static volatile sig_atomic_t sigsys_count;
+
+static void sigsys_handler(int sig)
+{
+    (void)sig;
+    sigsys_count++;
+}
+
+static int trap_child(void)
+{
+    signal(SIGSYS, sigsys_handler);
+    if (install_action(SYS_getppid, SECCOMP_RET_TRAP) != 0)
+        return 1;
+    syscall(SYS_getppid);
+    return sigsys_count == 1 ? 0 : 2;
+}
+
+static int kill_child(void)
+{
+    if (install_action(SYS_getppid, SECCOMP_RET_KILL_PROCESS) != 0)
+        return 1;
+    syscall(SYS_getppid);
+    return 2;
+}
+
+static void test_trap_kill(void)
+{
+    printf("Test SECCOMP_RET_TRAP and SECCOMP_RET_KILL_PROCESS\n");
+    int status = run_child(trap_child);
+    assert(WIFEXITED(status) && WEXITSTATUS(status) == 0);
+    status = run_child(kill_child);
+    assert(WIFSIGNALED(status) && WTERMSIG(status) == SIGSYS);
+    printf("SECCOMP_RET_TRAP and SECCOMP_RET_KILL_PROCESS passed\n\n");
+}
+
+static int mod_child(void)
+{
+    // dup的参数模3余1时返回EACCES
+    struct sock_filter insns[] = {
+        LOAD_NR,
+        BPF_JUMP(BPF_JMP | BPF_JEQ | BPF_K, SYS_dup, 0, 4),
+        LOAD_ARG0,
+        BPF_STMT(BPF_ALU | BPF_MOD | BPF_K, 3),
+        BPF_JUMP(BPF_JMP | BPF_JEQ | BPF_K, 1, 0, 1),
+        RET(SECCOMP_RET_ERRNO | EACCES),
+        RET(SECCOMP_RET_ALLOW),
+    };
+    if (install(insns, sizeof(insns) / sizeof(insns[0])) != 0)
+        return 1;
+    if (dup(100) >= 0 || errno != EACCES)
+        return 2;
+    if (dup(99) >= 0 || errno != EBADF)
+        return 3;
+    int fd = dup(0);
+    if (fd < 0)
+        return 4;
+    close(fd);
+    return 0;
+}
+
+static void test_bpf(void)
+{
+    printf("Test BPF program checking\n");
+    // 除数为常数0
+    struct sock_filter div0[] = {
+        BPF_STMT(BPF_ALU | BPF_MOD | BPF_K, 0),
+        RET(SECCOMP_RET_ALLOW),
+    };
+    assert(install(div0, 2) < 0 && errno == EINVAL);
+    // 越界读取seccomp_data
+    struct sock_filter oob[] = {
+        BPF_STMT(BPF_LD | BPF_W | BPF_ABS, sizeof(struct seccomp_data)),
+        RET(SECCOMP_RET_ALLOW),
+    };
+    assert(install(oob, 2) < 0 && errno == EINVAL);
+    // 最后一条指令不是返回指令
+    struct sock_filter noret[] = { LOAD_NR };
+    assert(install(noret, 1) < 0 && errno == EINVAL);
+    assert(install(noret, 0) < 0 && errno == EINVAL);
+    assert(prctl(PR_GET_SECCOMP) == 0);
+
+    int status = run_child(mod_child);
+    assert(WIFEXITED(status) && WEXITSTATUS(status) == 0);
+
+    unsigned int action = SECCOMP_RET_ERRNO;
+    assert(syscall(SYS_seccomp, SECCOMP_GET_ACTION_AVAIL, 0, &action) == 0);
+    action = 0x12340000;
+    assert(syscall(SYS_seccomp, SECCOMP_GET_ACTION_AVAIL, 0, &action) < 0 && errno == EOPNOTSUPP);
+    printf("BPF program checking passed\n\n");
+}
+
+static int nopriv_child(void)
+{
+    if (setuid(65534) != 0)
+        return 1;
+    // 没有特权时需要先设置no_new_privs
+    if (install_action(SYS_getppid, SECCOMP_RET_ERRNO | EPERM) == 0 || errno != EACCES)
+        return 2;
+    if (prctl(PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) != 0)
+        return 3;
+    if (install_action(SYS_getppid, SECCOMP_RET_ERRNO | EPERM) != 0)
+        return 4;
+    return 0;
+}
+
+static void test_no_new_privs(void)
+{
+    printf("Test unprivileged filter\n");
+    int status = run_child(nopriv_child);
+    assert(WIFEXITED(status) && WEXITSTATUS(status) == 0);
+    printf("unprivileged filter passed\n\n");
+}
+
+int main(int argc, char **argv)
+{
+    if (argc == 2 && strcmp(argv[1], "--check-inherit") == 0)
+        return check_inherit();
+
+    test_strict();
+    test_errno();
+    test_trap_kill();
+    test_bpf();
+    test_no_new_privs();
+    printf("All seccomp tests passed\n");
+    return 0;
+}
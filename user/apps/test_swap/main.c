@@ -0,0 +1,109 @@
+#define _GNU_SOURCE
+#include <assert.h>
+#include <errno.h>
+#include <fcntl.h>
+#include <stdint.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <sys/mman.h>
+#include <sys/swap.h>
+#include <unistd.h>
+
+// 交换文件需要放在支持直接读写的文件系统上
+#define SWAP_FILE "/test_swapfile"
+#define SWAP_PAGES 256
+#define NR_PAGES 32
+
+static long page_size;
+
+// 生成mkswap格式的交换文件
+static void make_swap_file(void)
+{
+    int fd = open(SWAP_FILE, O_RDWR | O_CREAT | O_TRUNC, 0600);
+    assert(fd >= 0);
+    char *page = calloc(1, page_size);
+    for (int i = 0; i < SWAP_PAGES; i++)
+        assert(write(fd, page, page_size) == page_size);
+
+    uint32_t *info = (uint32_t *)(page + 1024);
+    info[0] = 1;              // version
+    info[1] = SWAP_PAGES - 1; // last_page
+    info[2] = 0;              // nr_badpages
+    memcpy(page + page_size - 10, "SWAPSPACE2", 10);
+    assert(pwrite(fd, page, page_size, 0) == page_size);
+    free(page);
+    close(fd);
+}
+
+static void fill(char *buf, int seed)
+{
+    for (int i = 0; i < NR_PAGES; i++)
+        memset(buf + i * page_size, seed + i, page_size);
+}
+
+static void check(char *buf, int seed)
+{
+    for (int i = 0; i < NR_PAGES; i++)
+        for (long j = 0; j < page_size; j += 512)
+            assert(buf[i * page_size + j] == (char)(seed + i));
+}
+
+static int resident_pages(char *buf)
+{
+    unsigned char vec[NR_PAGES];
+    assert(mincore(buf, NR_PAGES * page_size, vec) == 0);
+    int n = 0;
+    for (int i = 0; i < NR_PAGES; i++)
+        n += vec[i] & 1;
+    return n;
+}
+
+int main()
+{
+    page_size = sysconf(_SC_PAGESIZE);
+    make_swap_file();
+
+    // 非法的交换区头部
+    int fd = open("/test_badswap", O_RDWR | O_CREAT | O_TRUNC, 0600);
+    assert(fd >= 0 && ftruncate(fd, 16 * page_size) == 0);
+    close(fd);
+    assert(swapon("/test_badswap", 0) < 0 && errno == EINVAL);
+    unlink("/test_badswap");
+
+    assert(swapon(SWAP_FILE, 0) == 0);
+    assert(swapon(SWAP_FILE, 0) < 0 && errno == EBUSY);
+    printf("swapon ok\n");
+
+    // 换出再换入，内容保持不变
+    size_t len = NR_PAGES * page_size;
+    char *buf = mmap(NULL, len, PROT_READ | PROT_WRITE, MAP_PRIVATE | MAP_ANONYMOUS, -1, 0);
+    assert(buf != MAP_FAILED);
+    fill(buf, 1);
+    assert(madvise(buf, len, MADV_PAGEOUT) == 0);
+    printf("resident after pageout: %d/%d\n", resident_pages(buf), NR_PAGES);
+    assert(resident_pages(buf) < NR_PAGES);
+    check(buf, 1);
+    printf("swap out/in ok\n");
+
+    // mremap搬动之后，页面仍然可以在新的地址被换出和换入
+    char *target = mmap(NULL, 2 * len, PROT_NONE, MAP_PRIVATE | MAP_ANONYMOUS, -1, 0);
+    assert(target != MAP_FAILED);
+    assert(madvise(buf, len, MADV_PAGEOUT) == 0);
+    char *moved = mremap(buf, len, 2 * len, MREMAP_MAYMOVE | MREMAP_FIXED, target);
+    assert(moved == target);
+    check(moved, 1);
+    fill(moved, 7);
+    assert(madvise(moved, len, MADV_PAGEOUT) == 0);
+    assert(resident_pages(moved) < NR_PAGES);
+    check(moved, 7);
+    assert(moved[2 * len - 1] == 0);
+    munmap(moved, 2 * len);
+    printf("swap after mremap ok\n");
+
+    assert(swapoff(SWAP_FILE) == 0);
+    assert(swapoff(SWAP_FILE) < 0 && errno == EINVAL);
+    unlink(SWAP_FILE);
+    printf("All swap tests passed\n");
+    return 0;
+}
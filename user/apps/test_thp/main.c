@@ -0,0 +1,137 @@
+#define _GNU_SOURCE
+#include <assert.h>
+#include <errno.h>
+#include <fcntl.h>
+#include <stdint.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <sys/mman.h>
+#include <unistd.h>
+
+#ifndef MADV_COLLAPSE
+#define MADV_COLLAPSE 25
+#endif
+
+#define PAGE 4096
+#define HPAGE (2UL << 20)
+
+// 读取/proc/meminfo中的AnonHugePages，单位为kB
+static long anon_huge_kb(void)
+{
+    char buf[4096];
+    int fd = open("/proc/meminfo", O_RDONLY);
+    assert(fd >= 0);
+    ssize_t n = read(fd, buf, sizeof(buf) - 1);
+    assert(n > 0);
+    buf[n] = 0;
+    close(fd);
+    char *p = strstr(buf, "AnonHugePages:");
+    assert(p != NULL);
+    return strtol(p + strlen("AnonHugePages:"), NULL, 10);
+}
+
+// 映射一段按2M对齐的私有匿名内存
+static char *map_aligned(size_t size)
+{
+    char *p = mmap(NULL, size + HPAGE, PROT_READ | PROT_WRITE, MAP_PRIVATE | MAP_ANONYMOUS, -1, 0);
+    assert(p != MAP_FAILED);
+    char *aligned = (char *)(((uintptr_t)p + HPAGE - 1) & ~(HPAGE - 1));
+    if (aligned != p)
+        munmap(p, aligned - p);
+    munmap(aligned + size, p + HPAGE - aligned);
+    return aligned;
+}
+
+static void fill(char *p, size_t size)
+{
+    for (size_t i = 0; i < size; i += PAGE)
+        p[i] = (char)(i / PAGE + 1);
+}
+
+static void check(char *p, size_t start, size_t end)
+{
+    for (size_t i = start; i < end; i += PAGE)
+        assert(p[i] == (char)(i / PAGE + 1));
+}
+
+static void test_fault(void)
+{
+    printf("Test THP fault and split on mprotect\n");
+    char buf[64];
+    int fd = open("/sys/kernel/mm/transparent_hugepage/enabled", O_RDONLY);
+    assert(fd >= 0);
+    ssize_t n = read(fd, buf, sizeof(buf) - 1);
+    assert(n > 0);
+    buf[n] = 0;
+    close(fd);
+    printf("enabled: %s", buf);
+    assert(strchr(buf, '[') != NULL);
+
+    long before = anon_huge_kb();
+    char *p = map_aligned(HPAGE);
+    assert(madvise(p, HPAGE, MADV_HUGEPAGE) == 0);
+    // 缺页时直接映射2M的大页
+    fill(p, HPAGE);
+    long after = anon_huge_kb();
+    printf("AnonHugePages: %ld kB -> %ld kB\n", before, after);
+    assert(after >= before + 2048);
+
+    // 只修改大页中一个页面的权限时，大页被拆分，数据保持不变
+    assert(mprotect(p + PAGE, PAGE, PROT_READ) == 0);
+    assert(anon_huge_kb() <= after - 2048);
+    check(p, 0, HPAGE);
+    p[0] = 100;
+    p[2 * PAGE] = 101;
+    assert(p[0] == 100 && p[2 * PAGE] == 101);
+    assert(munmap(p, HPAGE) == 0);
+    printf("THP fault and split on mprotect passed\n\n");
+}
+
+static void test_munmap_split(void)
+{
+    printf("Test THP split on partial munmap\n");
+    long before = anon_huge_kb();
+    char *p = map_aligned(HPAGE);
+    assert(madvise(p, HPAGE, MADV_HUGEPAGE) == 0);
+    fill(p, HPAGE);
+    assert(anon_huge_kb() >= before + 2048);
+
+    // 释放大页中间的一个页面，其余部分的数据保持不变
+    assert(munmap(p + HPAGE / 2, PAGE) == 0);
+    assert(anon_huge_kb() <= before);
+    check(p, 0, HPAGE / 2);
+    check(p, HPAGE / 2 + PAGE, HPAGE);
+    assert(munmap(p, HPAGE) == 0);
+    printf("THP split on partial munmap passed\n\n");
+}
+
+static void test_collapse(void)
+{
+    printf("Test MADV_COLLAPSE\n");
+    long before = anon_huge_kb();
+    char *p = map_aligned(HPAGE);
+    // 使用普通页面填充之后合并为大页
+    assert(madvise(p, HPAGE, MADV_NOHUGEPAGE) == 0);
+    fill(p, HPAGE);
+    assert(anon_huge_kb() == before);
+    assert(madvise(p, HPAGE, MADV_COLLAPSE) < 0 && errno == EINVAL);
+
+    assert(madvise(p, HPAGE, MADV_HUGEPAGE) == 0);
+    assert(madvise(p, HPAGE, MADV_COLLAPSE) == 0);
+    long after = anon_huge_kb();
+    printf("AnonHugePages: %ld kB -> %ld kB\n", before, after);
+    assert(after >= before + 2048);
+    check(p, 0, HPAGE);
+    assert(munmap(p, HPAGE) == 0);
+    printf("MADV_COLLAPSE passed\n\n");
+}
+
+int main()
+{
+    test_fault();
+    test_munmap_split();
+    test_collapse();
+    printf("All THP tests passed\n");
+    return 0;
+}
@@ -0,0 +1,160 @@
+#define _GNU_SOURCE
+#include <assert.h>
+#include <errno.h>
+#include <fcntl.h>
+#include <stdio.h>
+#include <string.h>
+#include <sys/mount.h>
+#include <sys/stat.h>
+#include <sys/statfs.h>
+#include <unistd.h>
+
+#define MNT "/test_tmpfs_mnt"
+#define PAGE 4096
+
+static char buf[32 * PAGE];
+
+static void mount_tmpfs(const char *options)
+{
+    assert(mkdir(MNT, 0755) == 0 || errno == EEXIST);
+    assert(mount("tmpfs", MNT, "tmpfs", 0, options) == 0);
+}
+
+static void test_size_limit(void)
+{
+    printf("Test size limit\n");
+    mount_tmpfs("size=64k");
+    struct statfs sfs;
+    assert(statfs(MNT, &sfs) == 0);
+    assert(sfs.f_blocks * sfs.f_bsize == 64 * 1024);
+    assert(sfs.f_bfree == sfs.f_blocks);
+
+    int fd = open(MNT "/big", O_CREAT | O_RDWR, 0644);
+    assert(fd >= 0);
+    memset(buf, 'a', sizeof(buf));
+    // 超出容量时只写入能够容纳的部分，之后的写入返回ENOSPC
+    ssize_t n = write(fd, buf, 32 * PAGE);
+    printf("written: %zd\n", n);
+    assert(n == 16 * PAGE);
+    assert(write(fd, buf, 1) < 0 && errno == ENOSPC);
+    struct stat st;
+    assert(fstat(fd, &st) == 0 && st.st_size == 16 * PAGE);
+    assert(statfs(MNT, &sfs) == 0 && sfs.f_bfree == 0);
+
+    // 文件系统已满时仍然可以读取文件，包括读取文件空洞
+    int sparse = open(MNT "/sparse", O_CREAT | O_RDWR, 0644);
+    assert(sparse >= 0);
+    assert(ftruncate(sparse, 4 * PAGE) == 0);
+    memset(buf, 'x', PAGE);
+    assert(pread(sparse, buf, PAGE, PAGE) == PAGE);
+    for (int i = 0; i < PAGE; i++)
+        assert(buf[i] == 0);
+    assert(pread(fd, buf, PAGE, 0) == PAGE && buf[0] == 'a');
+    close(sparse);
+
+    // 截断文件后归还容量
+    assert(ftruncate(fd, 0) == 0);
+    assert(statfs(MNT, &sfs) == 0 && sfs.f_bfree == sfs.f_blocks);
+    assert(write(fd, buf, PAGE) == PAGE);
+    close(fd);
+
+    assert(unlink(MNT "/big") == 0);
+    assert(unlink(MNT "/sparse") == 0);
+    assert(umount(MNT) == 0);
+    printf("size limit passed\n\n");
+}
+
+static void test_sparse_blocks(void)
+{
+    printf("Test sparse file blocks\n");
+    mount_tmpfs("size=1m");
+    int fd = open(MNT "/sparse", O_CREAT | O_RDWR, 0644);
+    assert(fd >= 0);
+    struct stat st;
+
+    // 文件空洞不占用空间
+    assert(ftruncate(fd, 64 * PAGE) == 0);
+    assert(fstat(fd, &st) == 0 && st.st_size == 64 * PAGE && st.st_blocks == 0);
+    assert(pwrite(fd, "x", 1, 10 * PAGE) == 1);
+    assert(fstat(fd, &st) == 0 && st.st_blocks == PAGE / 512);
+
+    // 读取空洞不会分配页面
+    memset(buf, 'y', sizeof(buf));
+    assert(pread(fd, buf, 32 * PAGE, 0) == 32 * PAGE);
+    for (int i = 0; i < 32 * PAGE; i++)
+        assert(buf[i] == (i == 10 * PAGE ? 'x' : 0));
+    assert(fstat(fd, &st) == 0 && st.st_blocks == PAGE / 512);
+    struct statfs sfs;
+    assert(statfs(MNT, &sfs) == 0 && sfs.f_blocks - sfs.f_bfree == 1);
+    close(fd);
+
+    assert(unlink(MNT "/sparse") == 0);
+    assert(umount(MNT) == 0);
+    printf("sparse file blocks passed\n\n");
+}
+
+static void test_nr_inodes(void)
+{
+    printf("Test nr_inodes limit\n");
+    // 根目录占用一个inode
+    mount_tmpfs("nr_inodes=3");
+    int fd = open(MNT "/a", O_CREAT | O_RDWR, 0644);
+    assert(fd >= 0);
+    close(fd);
+    assert(mkdir(MNT "/d", 0755) == 0);
+    assert(open(MNT "/b", O_CREAT | O_RDWR, 0644) < 0 && errno == ENOSPC);
+    assert(unlink(MNT "/a") == 0);
+    fd = open(MNT "/b", O_CREAT | O_RDWR, 0644);
+    assert(fd >= 0);
+    close(fd);
+
+    assert(unlink(MNT "/b") == 0);
+    assert(rmdir(MNT "/d") == 0);
+    assert(umount(MNT) == 0);
+    printf("nr_inodes limit passed\n\n");
+}
+
+static void test_rename(void)
+{
+    printf("Test rename targets\n");
+    mount_tmpfs("size=1m");
+    assert(mkdir(MNT "/dir", 0755) == 0);
+    assert(mkdir(MNT "/full", 0755) == 0);
+    assert(mkdir(MNT "/empty", 0755) == 0);
+    int fd = open(MNT "/full/f", O_CREAT | O_RDWR, 0644);
+    assert(fd >= 0);
+    close(fd);
+    fd = open(MNT "/file", O_CREAT | O_RDWR, 0644);
+    assert(fd >= 0);
+    close(fd);
+
+    // 目录不能覆盖文件，文件不能覆盖目录，目录只能覆盖空目录
+    assert(rename(MNT "/dir", MNT "/file") < 0 && errno == ENOTDIR);
+    assert(rename(MNT "/file", MNT "/dir") < 0 && errno == EISDIR);
+    assert(rename(MNT "/dir", MNT "/full") < 0 && (errno == ENOTEMPTY || errno == EEXIST));
+    assert(rename(MNT "/dir", MNT "/empty") == 0);
+    struct stat st;
+    assert(stat(MNT "/dir", &st) < 0 && errno == ENOENT);
+    assert(stat(MNT "/empty", &st) == 0 && S_ISDIR(st.st_mode));
+
+    // 文件覆盖另一个文件
+    assert(rename(MNT "/file", MNT "/full/f") == 0);
+    assert(stat(MNT "/file", &st) < 0 && errno == ENOENT);
+
+    assert(unlink(MNT "/full/f") == 0);
+    assert(rmdir(MNT "/full") == 0);
+    assert(rmdir(MNT "/empty") == 0);
+    assert(umount(MNT) == 0);
+    printf("rename targets passed\n\n");
+}
+
+int main()
+{
+    test_size_limit();
+    test_sparse_blocks();
+    test_nr_inodes();
+    test_rename();
+    rmdir(MNT);
+    printf("All tmpfs tests passed\n");
+    return 0;
+}
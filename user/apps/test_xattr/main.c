@@ -0,0 +1,160 @@
+#define _GNU_SOURCE
+#include <assert.h>
+#include <errno.h>
+#include <fcntl.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <sys/mount.h>
+#include <sys/stat.h>
+#include <sys/wait.h>
+#include <sys/xattr.h>
+#include <unistd.h>
+
+#define MNT "/test_xattr_mnt"
+#define FILE_PATH MNT "/file"
+#define LINK_PATH MNT "/link"
+
+// 检查listxattr返回的属性名列表中是否包含name
+static int list_contains(const char *list, ssize_t len, const char *name)
+{
+    for (ssize_t i = 0; i < len; i += strlen(list + i) + 1) {
+        if (strcmp(list + i, name) == 0)
+            return 1;
+    }
+    return 0;
+}
+
+static void test_set_get(void)
+{
+    printf("Test setxattr and getxattr\n");
+    char buf[64];
+    assert(setxattr(FILE_PATH, "user.a", "hello", 5, 0) == 0);
+    assert(getxattr(FILE_PATH, "user.a", NULL, 0) == 5);
+    memset(buf, 0, sizeof(buf));
+    assert(getxattr(FILE_PATH, "user.a", buf, sizeof(buf)) == 5);
+    assert(memcmp(buf, "hello", 5) == 0);
+    // 缓冲区太小
+    assert(getxattr(FILE_PATH, "user.a", buf, 2) < 0 && errno == ERANGE);
+
+    // XATTR_CREATE要求属性不存在，XATTR_REPLACE要求属性已存在
+    assert(setxattr(FILE_PATH, "user.a", "x", 1, XATTR_CREATE) < 0 && errno == EEXIST);
+    assert(setxattr(FILE_PATH, "user.b", "x", 1, XATTR_REPLACE) < 0 && errno == ENODATA);
+    assert(setxattr(FILE_PATH, "user.a", "world!", 6, XATTR_REPLACE) == 0);
+    assert(getxattr(FILE_PATH, "user.a", buf, sizeof(buf)) == 6);
+    assert(memcmp(buf, "world!", 6) == 0);
+    assert(setxattr(FILE_PATH, "user.b", "", 0, XATTR_CREATE) == 0);
+    assert(getxattr(FILE_PATH, "user.b", buf, sizeof(buf)) == 0);
+
+    // 通过文件描述符访问
+    int fd = open(FILE_PATH, O_RDWR);
+    assert(fd >= 0);
+    assert(fsetxattr(fd, "user.c", "fd", 2, 0) == 0);
+    assert(fgetxattr(fd, "user.c", buf, sizeof(buf)) == 2);
+    assert(memcmp(buf, "fd", 2) == 0);
+    close(fd);
+
+    // 不存在的属性、不支持的命名空间以及过大的属性值
+    assert(getxattr(FILE_PATH, "user.none", buf, sizeof(buf)) < 0 && errno == ENODATA);
+    assert(setxattr(FILE_PATH, "foo.bar", "x", 1, 0) < 0 && errno == EOPNOTSUPP);
+    char *big = calloc(1, 65537);
+    assert(big != NULL);
+    assert(setxattr(FILE_PATH, "user.big", big, 65537, 0) < 0 && errno == E2BIG);
+    free(big);
+    assert(setxattr(FILE_PATH, "user.a", "x", 1, 4) < 0 && errno == EINVAL);
+
+    // 符号链接上不能设置user命名空间的属性
+    assert(lsetxattr(LINK_PATH, "user.a", "x", 1, 0) < 0 && errno == EPERM);
+    assert(lgetxattr(LINK_PATH, "user.a", buf, sizeof(buf)) < 0 && errno == ENODATA);
+    // 不带l前缀的系统调用会跟随符号链接
+    assert(getxattr(LINK_PATH, "user.c", buf, sizeof(buf)) == 2);
+    printf("setxattr and getxattr passed\n\n");
+}
+
+static void test_list_remove(void)
+{
+    printf("Test listxattr and removexattr\n");
+    char list[256];
+    ssize_t size = listxattr(FILE_PATH, NULL, 0);
+    assert(size == (ssize_t)(strlen("user.a") + strlen("user.b") + strlen("user.c") + 3));
+    assert(listxattr(FILE_PATH, list, size - 1) < 0 && errno == ERANGE);
+    assert(listxattr(FILE_PATH, list, sizeof(list)) == size);
+    assert(list_contains(list, size, "user.a"));
+    assert(list_contains(list, size, "user.b"));
+    assert(list_contains(list, size, "user.c"));
+
+    assert(removexattr(FILE_PATH, "user.a") == 0);
+    assert(removexattr(FILE_PATH, "user.a") < 0 && errno == ENODATA);
+    assert(getxattr(FILE_PATH, "user.a", NULL, 0) < 0 && errno == ENODATA);
+    int fd = open(FILE_PATH, O_RDONLY);
+    assert(fd >= 0);
+    assert(fremovexattr(fd, "user.c") == 0);
+    close(fd);
+    size = listxattr(FILE_PATH, list, sizeof(list));
+    assert(size == (ssize_t)strlen("user.b") + 1);
+    assert(list_contains(list, size, "user.b"));
+    assert(removexattr(FILE_PATH, "user.b") == 0);
+    assert(listxattr(FILE_PATH, list, sizeof(list)) == 0);
+    printf("listxattr and removexattr passed\n\n");
+}
+
+static int unprivileged_child(void)
+{
+    char list[256], buf[16];
+    if (setuid(65534) != 0)
+        return 1;
+    // 没有CAP_SYS_ADMIN时看不到trusted命名空间的属性
+    ssize_t size = listxattr(FILE_PATH, list, sizeof(list));
+    if (size < 0 || list_contains(list, size, "trusted.t") || !list_contains(list, size, "user.u"))
+        return 2;
+    if (getxattr(FILE_PATH, "trusted.t", buf, sizeof(buf)) >= 0 || errno != ENODATA)
+        return 3;
+    if (setxattr(FILE_PATH, "trusted.t", "y", 1, 0) >= 0 || errno != EPERM)
+        return 4;
+    // user命名空间的属性遵循文件的访问权限
+    if (getxattr(FILE_PATH, "user.u", buf, sizeof(buf)) != 1)
+        return 5;
+    if (setxattr(FILE_PATH, "user.u", "y", 1, 0) >= 0 || errno != EACCES)
+        return 6;
+    return 0;
+}
+
+static void test_trusted(void)
+{
+    printf("Test trusted namespace\n");
+    char list[256];
+    assert(setxattr(FILE_PATH, "trusted.t", "x", 1, 0) == 0);
+    assert(setxattr(FILE_PATH, "user.u", "x", 1, 0) == 0);
+    ssize_t size = listxattr(FILE_PATH, list, sizeof(list));
+    assert(size > 0 && list_contains(list, size, "trusted.t"));
+
+    pid_t pid = fork();
+    if (pid == 0)
+        _exit(unprivileged_child());
+    int status;
+    assert(waitpid(pid, &status, 0) == pid);
+    printf("child exit status: %d\n", WEXITSTATUS(status));
+    assert(WIFEXITED(status) && WEXITSTATUS(status) == 0);
+    printf("trusted namespace passed\n\n");
+}
+
+int main()
+{
+    assert(mkdir(MNT, 0755) == 0 || errno == EEXIST);
+    assert(mount("tmpfs", MNT, "tmpfs", 0, NULL) == 0);
+    int fd = open(FILE_PATH, O_CREAT | O_RDWR, 0644);
+    assert(fd >= 0);
+    close(fd);
+    assert(symlink(FILE_PATH, LINK_PATH) == 0);
+
+    test_set_get();
+    test_list_remove();
+    test_trusted();
+
+    assert(unlink(LINK_PATH) == 0);
+    assert(unlink(FILE_PATH) == 0);
+    assert(umount(MNT) == 0);
+    rmdir(MNT);
+    printf("All xattr tests passed\n");
+    return 0;
+}
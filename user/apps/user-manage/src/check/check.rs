@@ -38,7 +38,10 @@ impl UAddCheck {
                     info.uid = arg.clone();
                 }
                 CmdOption::Group => {
-                    info.group = arg.clone();
+                    // 第一个组作为用户所在的组，其余的作为附加组
+                    let mut groups = arg.split(",").map(|s| s.to_string());
+                    info.group = groups.next().unwrap_or_default();
+                    info.groups = groups.filter(|s| !s.is_empty()).collect();
                 }
                 CmdOption::Gid => {
                     info.gid = arg.clone();
@@ -91,6 +94,17 @@ impl UAddCheck {
         // 判断group和gid是否有效
         Self::check_group_gid(&mut info);
 
+        // 校验附加组是否存在
+        if !info.groups.is_empty() {
+            scan_group(
+                GroupField {
+                    groups: Some(info.groups.clone()),
+                    gid: None,
+                },
+                true,
+            );
+        }
+
         info
     }
 
@@ -315,6 +329,10 @@ impl UModCheck {
                 CmdOption::Append => {
                     info.groups = Some(arg.split(",").map(|s| s.to_string()).collect());
                 }
+                CmdOption::Group => {
+                    info.groups = Some(arg.split(",").map(|s| s.to_string()).collect());
+                    info.replace_groups = true;
+                }
                 CmdOption::Comment => {
                     info.new_comment = Some(arg.clone());
                 }
@@ -7,6 +7,8 @@ pub struct UAddInfo {
     pub gid: String,
     /// 所在组的组名
     pub group: String,
+    /// 附加组的组名
+    pub groups: Vec<String>,
     /// 用户描述信息
     pub comment: String,
     /// 主目录
@@ -36,6 +38,8 @@ pub struct UDelInfo {
 pub struct UModInfo {
     pub username: String,
     pub groups: Option<Vec<String>>,
+    /// 是否用groups替换用户原有的附加组（-G），否则追加（-a -G）
+    pub replace_groups: bool,
     pub new_comment: Option<String>,
     pub new_home: Option<String>,
     pub new_gid: Option<String>,
@@ -96,10 +96,6 @@ impl UAddExecutor {
 
     /// 写入/etc/group文件：将用户添加到对应用户组中
     fn write_group_file(info: &UAddInfo) {
-        if info.group == info.username {
-            return;
-        }
-
         let mut guard = GLOBAL_FILE.lock().unwrap();
         let content = read_to_string(&guard.group_file);
         let mut new_content = String::new();
@@ -110,7 +106,7 @@ impl UAddExecutor {
                 .into_iter()
                 .filter(|username| !username.is_empty())
                 .collect::<Vec<&str>>();
-            if field[0].eq(info.group.as_str()) && !users.contains(&info.username.as_str()) {
+            if Self::is_member(info, field[0]) && !users.contains(&info.username.as_str()) {
                 users.push(info.username.as_str());
             }
 
@@ -125,6 +121,12 @@ impl UAddExecutor {
         guard.group_file.flush().unwrap();
     }
 
+    /// 判断用户是否应该被添加到组的成员列表中：用户所在的组（与用户同名的组除外）以及附加组
+    fn is_member(info: &UAddInfo, groupname: &str) -> bool {
+        (info.group == groupname && info.group != info.username)
+            || info.groups.iter().any(|g| g == groupname)
+    }
+
     /// 写入/etc/shadow文件：添加用户口令相关信息
     fn write_shadow_file(info: &UAddInfo) {
         let data = format!("{}::::::::\n", info.username,);
@@ -138,10 +140,6 @@ impl UAddExecutor {
 
     /// 写入/etc/gshadow文件：将用户添加到对应用户组中
     fn write_gshadow_file(info: &UAddInfo) {
-        if info.group == info.username {
-            return;
-        }
-
         let mut guard = GLOBAL_FILE.lock().unwrap();
         let content = read_to_string(&guard.gshadow_file);
         let mut new_content = String::new();
@@ -152,7 +150,7 @@ impl UAddExecutor {
                 .into_iter()
                 .filter(|username| !username.is_empty())
                 .collect::<Vec<&str>>();
-            if field[0].eq(info.group.as_str()) && !users.contains(&info.username.as_str()) {
+            if Self::is_member(info, field[0]) && !users.contains(&info.username.as_str()) {
                 users.push(info.username.as_str());
             }
 
@@ -392,8 +390,13 @@ impl UModExecutor {
             }
 
             if let Some(groups) = &info.groups {
-                if groups.contains(&fields[0].to_string()) && !users.contains(&name.as_str()) {
-                    users.push(&name);
+                if groups.contains(&fields[0].to_string()) {
+                    if !users.contains(&name.as_str()) {
+                        users.push(&name);
+                    }
+                } else if info.replace_groups {
+                    // 替换附加组，将用户从不在列表中的组删去
+                    users.retain(|&u| u != name);
                 }
             }
 
@@ -459,14 +462,14 @@ impl UModExecutor {
                 }
             }
 
-            let tmp = format!(",{}", name);
             if let Some(groups) = &info.groups {
-                if groups.contains(&fields[0].to_string()) && !users.contains(&name.as_str()) {
-                    if users.is_empty() {
+                if groups.contains(&fields[0].to_string()) {
+                    if !users.contains(&name.as_str()) {
                         users.push(&name);
-                    } else {
-                        users.push(tmp.as_str());
                     }
+                } else if info.replace_groups {
+                    // 替换附加组，将用户从不在列表中的组删去
+                    users.retain(|&u| u != name);
                 }
             }
 